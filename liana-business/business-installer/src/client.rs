@@ -19,6 +19,8 @@ use liana_gui::{
 use miniscript::bitcoin::Network;
 use std::{
     collections::BTreeMap,
+    io::{Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Mutex,
@@ -75,7 +77,14 @@ fn get_service_config_blocking(network: Network) -> Result<ServiceConfig, reqwes
     use tracing::debug;
 
     let api_url = auth_api_url(network);
-    let client = reqwest::blocking::Client::new();
+    let mut builder = reqwest::blocking::Client::builder();
+    if let Some(proxy) = liana_gui::services::http::proxy() {
+        builder = builder.proxy(
+            reqwest::Proxy::all(format!("socks5h://{}", proxy.addr))?
+                .no_proxy(reqwest::NoProxy::from_string("localhost,127.0.0.1,::1")),
+        );
+    }
+    let client = builder.build()?;
     let url = format!("{api_url}/v1/desktop");
 
     debug!("get_service_config_blocking: fetching from {}", url);
@@ -848,6 +857,96 @@ fn request_timeout_checker_thread(
         .expect("failed to spawn request timeout thread");
 }
 
+// Establish a connection to `host`:`port` through the SOCKS5 proxy at `proxy` (see RFC 1928).
+// The host name is resolved by the proxy. Only proxies without authentication are supported.
+fn socks5_connect(proxy: SocketAddr, host: &str, port: u16) -> std::io::Result<TcpStream> {
+    let protocol_error = |msg: String| std::io::Error::new(std::io::ErrorKind::Other, msg);
+    let mut stream = TcpStream::connect_timeout(&proxy, Duration::from_secs(30))?;
+    stream.set_read_timeout(Some(Duration::from_secs(60)))?;
+
+    // Version 5, one authentication method: none.
+    stream.write_all(&[0x05, 0x01, 0x00])?;
+    let mut resp = [0; 2];
+    stream.read_exact(&mut resp)?;
+    if resp != [0x05, 0x00] {
+        return Err(protocol_error(
+            "SOCKS5 proxy requires an unsupported authentication method".to_string(),
+        ));
+    }
+
+    // Version 5, command CONNECT, reserved byte, then the address of the target.
+    let mut req = vec![0x05, 0x01, 0x00];
+    match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(IpAddr::V4(ip)) => {
+            req.push(0x01);
+            req.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            req.push(0x04);
+            req.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let len = u8::try_from(host.len())
+                .map_err(|_| protocol_error(format!("Host name too long: {host}")))?;
+            req.push(0x03);
+            req.push(len);
+            req.extend_from_slice(host.as_bytes());
+        }
+    }
+    req.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&req)?;
+
+    // The reply is the version, the status, a reserved byte and the address bound by the proxy.
+    let mut resp = [0; 4];
+    stream.read_exact(&mut resp)?;
+    if resp[0] != 0x05 || resp[1] != 0x00 {
+        return Err(protocol_error(format!(
+            "SOCKS5 proxy failed to connect to {host}, status {}",
+            resp[1]
+        )));
+    }
+    let addr_len = match resp[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => {
+            let mut len = [0; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        t => {
+            return Err(protocol_error(format!(
+                "SOCKS5 proxy replied with unknown address type {t}"
+            )))
+        }
+    };
+    // Skip the bound address and port.
+    let mut bound = vec![0; addr_len + 2];
+    stream.read_exact(&mut bound)?;
+    stream.set_read_timeout(None)?;
+
+    Ok(stream)
+}
+
+// Open the WebSocket connection, through the proxy set in the GUI settings if any.
+fn ws_connect(
+    url: &str,
+) -> Result<tungstenite::WebSocket<tungstenite::stream::MaybeTlsStream<TcpStream>>, String> {
+    let parsed = url::Url::parse(url).map_err(|e| e.to_string())?;
+    let host = parsed.host_str().ok_or("URL without host")?;
+    match liana_gui::services::http::proxy().filter(|p| p.applies_to(host)) {
+        Some(proxy) => {
+            let port = parsed.port_or_known_default().ok_or("URL without port")?;
+            let stream = socks5_connect(proxy.addr, host, port).map_err(|e| e.to_string())?;
+            tungstenite::client_tls(url, stream)
+                .map(|(ws, _)| ws)
+                .map_err(|e| e.to_string())
+        }
+        None => tungstenite::connect(url)
+            .map(|(ws, _)| ws)
+            .map_err(|e| e.to_string()),
+    }
+}
+
 // WSS thread function
 #[allow(clippy::too_many_arguments)]
 fn wss_thread(
@@ -894,13 +993,13 @@ fn wss_thread(
     // Install ring crypto provider for rustls (required by tungstenite TLS)
     let _ = rustls::crypto::ring::default_provider().install_default();
 
-    let (mut ws_stream, _) = match tungstenite::connect(&url) {
+    let mut ws_stream = match ws_connect(&url) {
         Ok(stream) => {
             tracing::debug!("wss_thread: WebSocket connection established");
             stream
         }
         Err(e) => {
-            tracing::error!("wss_thread: WebSocket connection failed: {:?}", e);
+            tracing::error!("wss_thread: WebSocket connection failed: {}", e);
             Client::send_notif(
                 &notif_sender,
                 &notif_waker,
@@ -76,6 +76,12 @@ impl Client {
         }
     }
 
+    /// Use this HTTP client for the requests, for instance one configured with a proxy.
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
     async fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
         request(&self.http, method, url, &self.user_agent)
     }
@@ -52,6 +52,8 @@ pub enum Menu {
     Settings,
     SettingsPreSelected(SettingsOption),
     Coins,
+    Privacy,
+    Reserves,
     CreateSpendTx,
     Recovery,
     RefreshCoins(Vec<OutPoint>),
@@ -105,6 +107,8 @@ impl Menu {
             Menu::Transactions => "Transactions",
             Menu::Settings => "Settings",
             Menu::Coins => "Coins/UTXOs",
+            Menu::Privacy => "Privacy",
+            Menu::Reserves => "Reserves",
             Menu::CreateSpendTx => "Send",
             Menu::Recovery => "Recovery",
             Menu::RefreshCoins(_)
@@ -122,6 +126,8 @@ impl Menu {
             Menu::Transactions => icon::collection_icon(),
             Menu::Settings => icon::settings_icon(),
             Menu::Coins => icon::coins_icon(),
+            Menu::Privacy => icon::shield_icon(),
+            Menu::Reserves => icon::scale_icon(),
             Menu::CreateSpendTx => icon::send_icon(),
             Menu::Recovery => icon::recovery_icon(),
             Menu::RefreshCoins(_)
@@ -138,6 +144,8 @@ impl Menu {
             | Menu::PSBTs
             | Menu::Transactions
             | Menu::Coins
+            | Menu::Privacy
+            | Menu::Reserves
             | Menu::CreateSpendTx
             | Menu::Recovery => true,
             Menu::Settings
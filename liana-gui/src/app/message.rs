@@ -44,10 +44,16 @@ pub enum Message {
         Option<ChildNumber>, // start_index
     ),
     Coins(Result<Vec<Coin>, Error>),
+    Balance(Result<GetBalanceResult, Error>),
     /// When we want both coins and tip height together.
     CoinsTipHeight(Result<Vec<Coin>, Error>, Result<i32, Error>),
     Labels(Result<HashMap<String, String>, Error>),
     SpendTxs(Result<Vec<SpendTx>, Error>),
+    ScheduledBroadcasts(Result<Vec<ScheduledBroadcastEntry>, Error>),
+    PrivacyReport(Result<Vec<PrivacyFinding>, Error>),
+    /// The PSBT of a proof of reserves, to be signed.
+    ReservesProof(Result<Psbt, Error>),
+    ReservesProofFinalized(Result<ReservesProofResult, Error>),
     Psbt(Result<(Psbt, Vec<String>), Error>),
     RbfPsbt(Result<Txid, Error>),
     Recovery(Result<SpendTx, Error>),
@@ -56,6 +62,8 @@ pub enum Message {
     Updated(Result<(), Error>),
     Saved(Result<(), Error>),
     Verified(Fingerprint, Result<(), Error>),
+    /// The signature of a message (BIP322), encoded in base64.
+    MessageSigned(Fingerprint, Result<String, Error>),
     StartRescan(Result<(), Error>),
     HardwareWallets(HardwareWalletMessage),
     HistoryTransactionsExtension(Result<Vec<HistoryTransaction>, Error>),
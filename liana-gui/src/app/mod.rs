@@ -31,7 +31,8 @@ pub use config::Config;
 pub use message::Message;
 
 use state::{
-    CoinsPanel, CreateSpendPanel, Home, PsbtsPanel, ReceivePanel, State, TransactionsPanel,
+    CoinsPanel, CreateSpendPanel, Home, PrivacyPanel, PsbtsPanel, ReceivePanel, ReservesPanel,
+    State, TransactionsPanel,
 };
 use wallet::{sync_status, SyncStatus};
 
@@ -53,6 +54,8 @@ struct Panels<S: SettingsTrait> {
     current: Menu,
     home: Home,
     coins: CoinsPanel,
+    privacy: PrivacyPanel,
+    reserves: ReservesPanel,
     transactions: TransactionsPanel,
     psbts: PsbtsPanel,
     recovery: CreateSpendPanel,
@@ -106,6 +109,8 @@ impl<S: SettingsTrait> Panels<S> {
                 show_rescan_warning,
             ),
             coins: CoinsPanel::new(cache.coins(), wallet.main_descriptor.first_timelock_value()),
+            privacy: PrivacyPanel::default(),
+            reserves: ReservesPanel::new(data_dir.clone(), wallet.clone(), cache.network),
             transactions: TransactionsPanel::new(wallet.clone()),
             psbts: PsbtsPanel::new(wallet.clone()),
             recovery: new_recovery_panel(wallet.clone(), cache),
@@ -131,6 +136,8 @@ impl<S: SettingsTrait> Panels<S> {
             Menu::TransactionPreSelected(_) => &self.transactions,
             Menu::Settings | Menu::SettingsPreSelected(_) => &self.settings,
             Menu::Coins => &self.coins,
+            Menu::Privacy => &self.privacy,
+            Menu::Reserves => &self.reserves,
             Menu::CreateSpendTx => &self.create_spend,
             Menu::Recovery => &self.recovery,
             Menu::RefreshCoins(_) => &self.create_spend,
@@ -147,6 +154,8 @@ impl<S: SettingsTrait> Panels<S> {
             Menu::TransactionPreSelected(_) => &mut self.transactions,
             Menu::Settings | Menu::SettingsPreSelected(_) => &mut self.settings,
             Menu::Coins => &mut self.coins,
+            Menu::Privacy => &mut self.privacy,
+            Menu::Reserves => &mut self.reserves,
             Menu::CreateSpendTx => &mut self.create_spend,
             Menu::Recovery => &mut self.recovery,
             Menu::RefreshCoins(_) => &mut self.create_spend,
@@ -571,6 +571,7 @@ pub mod global {
     use crate::dir::LianaDirectory;
     use async_hwi::bitbox::{ConfigError, NoiseConfig, NoiseConfigData};
     use fs2::FileExt;
+    use lianad::config::ProxyConfig;
     use serde::{Deserialize, Serialize};
     use std::fs::OpenOptions;
     use std::io::{Read, Seek, SeekFrom, Write};
@@ -588,6 +589,10 @@ pub mod global {
     pub struct GlobalSettings {
         pub bitbox: Option<BitboxSettings>,
         pub window_config: Option<WindowConfig>,
+        /// A SOCKS5 proxy for all the connections to remote hosts, such as a Tor daemon. It is
+        /// set by editing the global settings file and applies from the next start.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub proxy: Option<ProxyConfig>,
     }
 
     impl GlobalSettings {
@@ -614,6 +619,12 @@ pub mod global {
             )
         }
 
+        pub fn load_proxy(path: &PathBuf) -> Result<Option<ProxyConfig>, String> {
+            let mut ret = None;
+            Self::update(path, |s| ret = s.proxy.clone(), false)?;
+            Ok(ret)
+        }
+
         pub fn load_bitbox_settings(path: &PathBuf) -> Result<Option<BitboxSettings>, String> {
             let mut ret = None;
             Self::update(path, |s| ret = s.bitbox.clone(), false)?;
@@ -666,6 +677,7 @@ pub mod global {
             if !exists
                 && global_settings.bitbox.is_none()
                 && global_settings.window_config.is_none()
+                && global_settings.proxy.is_none()
             {
                 write = false;
             }
@@ -840,7 +852,16 @@ mod test {
 
     #[test]
     fn test_parse_global_config() {
-        let _ = serde_json::from_str::<GlobalSettings>(RAW_GLOBAL_SETTINGS).unwrap();
+        let settings = serde_json::from_str::<GlobalSettings>(RAW_GLOBAL_SETTINGS).unwrap();
+        assert!(settings.proxy.is_none());
+
+        let settings =
+            serde_json::from_str::<GlobalSettings>(r#"{ "proxy": { "addr": "127.0.0.1:9050" } }"#)
+                .unwrap();
+        assert_eq!(
+            settings.proxy.unwrap().addr,
+            "127.0.0.1:9050".parse().unwrap()
+        );
     }
 
     #[test]
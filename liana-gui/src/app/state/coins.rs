@@ -141,6 +141,33 @@ impl State for CoinsPanel {
                     }
                 }
             }
+            Message::View(view::Message::ReleaseCoin(outpoint)) => {
+                return Task::perform(
+                    async move {
+                        daemon
+                            .release_coins(&[outpoint])
+                            .await
+                            .map_err(|e| e.into())
+                    },
+                    Message::Updated,
+                );
+            }
+            Message::Updated(res) => match res {
+                Err(e) => self.warning = Some(e),
+                Ok(()) => {
+                    self.warning = None;
+                    return Task::perform(
+                        async move {
+                            daemon
+                                .list_coins(&[CoinStatus::Unconfirmed, CoinStatus::Confirmed], &[])
+                                .await
+                                .map(|res| res.coins)
+                                .map_err(|e| e.into())
+                        },
+                        Message::Coins,
+                    );
+                }
+            },
             Message::View(view::Message::Select(i)) => {
                 if let Some(position) = self.selected.iter().position(|j| *j == i) {
                     self.selected.remove(position);
@@ -228,6 +255,7 @@ mod tests {
                 derivation_index: 0.into(),
                 is_change: false,
                 is_from_self: false,
+                is_quarantined: false,
             },
             Coin {
                 outpoint: bitcoin::OutPoint { txid, vout: 3 },
@@ -239,6 +267,7 @@ mod tests {
                 derivation_index: 1.into(),
                 is_change: false,
                 is_from_self: false,
+                is_quarantined: false,
             },
             Coin {
                 outpoint: bitcoin::OutPoint { txid, vout: 0 },
@@ -250,6 +279,7 @@ mod tests {
                 derivation_index: 2.into(),
                 is_change: false,
                 is_from_self: false,
+                is_quarantined: false,
             },
             Coin {
                 outpoint: bitcoin::OutPoint { txid, vout: 1 },
@@ -261,6 +291,7 @@ mod tests {
                 derivation_index: 3.into(),
                 is_change: false,
                 is_from_self: false,
+                is_quarantined: false,
             },
         ]);
 
@@ -82,6 +82,8 @@ impl ExportModal {
             ImportExportType::ExportLabels => "Export Labels",
             ImportExportType::ImportPsbt(_) => "Import PSBT",
             ImportExportType::ImportDescriptor => "Import Descriptor",
+            ImportExportType::ExportBsmsDescriptorRecord(_) => "Export BSMS Descriptor Record",
+            ImportExportType::ExportReservesProof(_) => "Export Proof of Reserves",
             ImportExportType::ImportBackup { .. } => "Restore Backup",
             ImportExportType::FromBackup => "Import existing wallet from backup",
         }
@@ -107,6 +109,10 @@ impl ExportModal {
             ImportExportType::ExportEncryptedDescriptor(_) => "liana.bed".into(),
             ImportExportType::ImportPsbt(_) => "psbt.psbt".into(),
             ImportExportType::ImportDescriptor => "descriptor.txt".into(),
+            ImportExportType::ExportBsmsDescriptorRecord(_) => "liana.bsms".into(),
+            ImportExportType::ExportReservesProof(_) => {
+                format!("liana-reserves-proof-{date}.json")
+            }
             ImportExportType::ExportLabels => format!("liana-labels-{date}.jsonl"),
             ImportExportType::ExportProcessBackup(..) => {
                 format!("liana-backup-{date}.json")
@@ -1,9 +1,11 @@
 mod coins;
 pub mod export;
 mod label;
+mod privacy;
 mod psbt;
 mod psbts;
 mod receive;
+mod reserves;
 pub mod settings;
 mod spend;
 mod transactions;
@@ -38,10 +40,12 @@ use crate::daemon::{
 use crate::utils::now;
 pub use coins::CoinsPanel;
 use label::LabelsEdited;
+pub use privacy::PrivacyPanel;
 pub use psbts::PsbtsPanel;
 pub use receive::ReceivePanel;
+pub use reserves::ReservesPanel;
 pub use settings::{LianaSettingsUI, SettingsState};
-pub use spend::{CreateSpendPanel, FeeMode, Recipient};
+pub use spend::{AdvancedOptions, CreateSpendPanel, FeeMode, Recipient};
 pub use transactions::TransactionsPanel;
 
 pub trait State {
@@ -86,29 +90,20 @@ pub fn fiat_converter_for_wallet(
         .and_then(|p| p.try_into().ok())
 }
 
-/// Returns the confirmed and unconfirmed balances from `coins`, as well
-/// as:
+/// Returns, among the unspent coins in `coins`:
 /// - the `OutPoint`s of those coins, if any, for which the current
 ///   `tip_height` is within 10% of the `timelock` expiring.
 /// - the smallest number of blocks until the expiry of `timelock` among
 ///   all confirmed coins, if any.
 ///
-/// The confirmed balance includes the values of any unconfirmed coins
-/// from self.
-fn coins_summary(
-    coins: &[Coin],
-    tip_height: u32,
-    timelock: u16,
-) -> (Amount, Amount, Vec<OutPoint>, Option<u32>) {
-    let mut balance = Amount::from_sat(0);
-    let mut unconfirmed_balance = Amount::from_sat(0);
+/// The balances are not computed from the coins but queried using
+/// `getbalance`, so as to use the same definitions as the daemon.
+fn coins_summary(coins: &[Coin], tip_height: u32, timelock: u16) -> (Vec<OutPoint>, Option<u32>) {
     let mut expiring_coins = Vec::new();
     let mut remaining_seq = None;
     for coin in coins {
         if coin.spend_info.is_none() {
-            // Include unconfirmed coins from self in confirmed balance.
             if coin_is_owned(coin) {
-                balance += coin.amount;
                 // Only consider confirmed coins for remaining seq
                 // (they would not be considered as expiring so we can also skip that part)
                 if coin.block_height.is_none() {
@@ -127,12 +122,10 @@ fn coins_summary(
                 } else {
                     remaining_seq = Some(seq);
                 }
-            } else {
-                unconfirmed_balance += coin.amount;
             }
         }
     }
-    (balance, unconfirmed_balance, expiring_coins, remaining_seq)
+    (expiring_coins, remaining_seq)
 }
 
 #[derive(Default)]
@@ -168,17 +161,18 @@ impl Home {
         tip_height: i32,
         show_rescan_warning: bool,
     ) -> Self {
-        let (balance, unconfirmed_balance, expiring_coins, remaining_seq) = coins_summary(
+        let (expiring_coins, remaining_seq) = coins_summary(
             coins,
             tip_height as u32,
             wallet.main_descriptor.first_timelock_value(),
         );
 
+        // The balance is set once loaded from the daemon.
         Self {
             wallet,
             sync_status,
-            balance,
-            unconfirmed_balance,
+            balance: Amount::ZERO,
+            unconfirmed_balance: Amount::ZERO,
             remaining_sequence: remaining_seq,
             expiring_coins,
             selected_event: None,
@@ -246,18 +240,21 @@ impl State for Home {
                 Err(e) => self.warning = Some(e),
                 Ok(coins) => {
                     self.warning = None;
-                    (
-                        self.balance,
-                        self.unconfirmed_balance,
-                        self.expiring_coins,
-                        self.remaining_sequence,
-                    ) = coins_summary(
+                    (self.expiring_coins, self.remaining_sequence) = coins_summary(
                         &coins,
                         cache.blockheight() as u32,
                         self.wallet.main_descriptor.first_timelock_value(),
                     );
                 }
             },
+            Message::Balance(res) => match res {
+                Err(e) => self.warning = Some(e),
+                Ok(balance) => {
+                    self.warning = None;
+                    self.balance = balance.confirmed;
+                    self.unconfirmed_balance = balance.unconfirmed;
+                }
+            },
             Message::Payments(res) => match res {
                 Err(e) => self.warning = Some(e),
                 Ok(events) => {
@@ -432,6 +429,7 @@ impl State for Home {
         self.wallet = wallet;
         self.payments.loaded_page_count = 0;
         let daemon2 = daemon.clone();
+        let daemon3 = daemon.clone();
         let now: u32 = now().as_secs().try_into().unwrap();
         self.last_reload = Instant::now();
         Task::batch(vec![
@@ -458,6 +456,10 @@ impl State for Home {
                 },
                 Message::Coins,
             ),
+            Task::perform(
+                async move { daemon3.get_balance(0).await.map_err(|e| e.into()) },
+                Message::Balance,
+            ),
         ])
     }
 }
@@ -494,7 +496,7 @@ mod tests {
         // Without coins, all values are 0 / empty / None:
         assert_eq!(
             coins_summary(&coins, tip_height, timelock),
-            (Amount::from_sat(0), Amount::from_sat(0), Vec::new(), None)
+            (Vec::new(), None)
         );
         // Add a spending coin.
         coins.push(Coin {
@@ -506,6 +508,7 @@ mod tests {
             is_immature: false,
             is_change: false,
             is_from_self: false,
+            is_quarantined: false,
             spend_info: Some(LCSpendInfo {
                 txid: dummy_txid,
                 height: None,
@@ -514,7 +517,7 @@ mod tests {
         // Spending coin is ignored.
         assert_eq!(
             coins_summary(&coins, tip_height, timelock),
-            (Amount::from_sat(0), Amount::from_sat(0), Vec::new(), None)
+            (Vec::new(), None)
         );
         // Add unconfirmed change coin not from self.
         coins.push(Coin {
@@ -526,12 +529,13 @@ mod tests {
             is_immature: false,
             is_change: true,
             is_from_self: false,
+            is_quarantined: false,
             spend_info: None,
         });
-        // Included in unconfirmed balance. Other values remain the same.
+        // Not confirmed, values remain the same.
         assert_eq!(
             coins_summary(&coins, tip_height, timelock),
-            (Amount::from_sat(0), Amount::from_sat(109), Vec::new(), None)
+            (Vec::new(), None)
         );
         // Add unconfirmed coin from self.
         coins.push(Coin {
@@ -543,17 +547,13 @@ mod tests {
             is_immature: false,
             is_change: false,
             is_from_self: true,
+            is_quarantined: false,
             spend_info: None,
         });
-        // Included in confirmed balance. Other values remain the same.
+        // Not confirmed, values remain the same.
         assert_eq!(
             coins_summary(&coins, tip_height, timelock),
-            (
-                Amount::from_sat(111),
-                Amount::from_sat(109),
-                Vec::new(),
-                None
-            )
+            (Vec::new(), None)
         );
         // Add a confirmed coin 1 more than 10% from expiry:
         coins.push(Coin {
@@ -565,17 +565,13 @@ mod tests {
             is_immature: false,
             is_change: false,
             is_from_self: false,
+            is_quarantined: false,
             spend_info: None,
         });
-        // Coin is added to confirmed balance. Not expiring, but remaining seq is set.
+        // Not expiring, but remaining seq is set.
         assert_eq!(
             coins_summary(&coins, tip_height, timelock),
-            (
-                Amount::from_sat(212),
-                Amount::from_sat(109),
-                Vec::new(),
-                Some(1_001)
-            )
+            (Vec::new(), Some(1_001))
         );
         // Now decrease the last coin's confirmation height by 1 so that
         // it is within 10% of expiry:
@@ -583,12 +579,7 @@ mod tests {
         // Its outpoint has been added to expiring coins and remaining seq is lower.
         assert_eq!(
             coins_summary(&coins, tip_height, timelock),
-            (
-                Amount::from_sat(212),
-                Amount::from_sat(109),
-                vec![OutPoint::new(dummy_txid, 3)],
-                Some(1_000)
-            )
+            (vec![OutPoint::new(dummy_txid, 3)], Some(1_000))
         );
         // Now add a confirmed coin that is not yet expiring.
         coins.push(Coin {
@@ -600,17 +591,13 @@ mod tests {
             is_immature: false,
             is_change: false,
             is_from_self: false,
+            is_quarantined: false,
             spend_info: None,
         });
-        // Only confirmed balance has changed.
+        // Not expiring and farther from expiry, values remain the same.
         assert_eq!(
             coins_summary(&coins, tip_height, timelock),
-            (
-                Amount::from_sat(317),
-                Amount::from_sat(109),
-                vec![OutPoint::new(dummy_txid, 3)],
-                Some(1_000)
-            )
+            (vec![OutPoint::new(dummy_txid, 3)], Some(1_000))
         );
         // Now add another confirmed coin that is expiring.
         coins.push(Coin {
@@ -622,14 +609,13 @@ mod tests {
             is_immature: false,
             is_change: false,
             is_from_self: false,
+            is_quarantined: false,
             spend_info: None,
         });
-        // Confirmed balance updated, as well as expiring coins and the remaining seq.
+        // Expiring coins and the remaining seq are updated.
         assert_eq!(
             coins_summary(&coins, tip_height, timelock),
             (
-                Amount::from_sat(425),
-                Amount::from_sat(109),
                 vec![OutPoint::new(dummy_txid, 3), OutPoint::new(dummy_txid, 5)],
                 Some(500)
             )
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use iced::Task;
+
+use liana_ui::widget::Element;
+
+use crate::{
+    app::{cache::Cache, error::Error, menu::Menu, message::Message, view, wallet::Wallet},
+    daemon::{model::PrivacyFinding, Daemon},
+};
+
+use super::State;
+
+#[derive(Default)]
+pub struct PrivacyPanel {
+    findings: Option<Vec<PrivacyFinding>>,
+    warning: Option<Error>,
+}
+
+impl State for PrivacyPanel {
+    fn view<'a>(&'a self, cache: &'a Cache) -> Element<'a, view::Message> {
+        view::dashboard(
+            &Menu::Privacy,
+            cache,
+            self.warning.as_ref(),
+            view::privacy::privacy_view(self.findings.as_deref()),
+        )
+    }
+
+    fn update(
+        &mut self,
+        _daemon: Arc<dyn Daemon + Sync + Send>,
+        _cache: &Cache,
+        message: Message,
+    ) -> Task<Message> {
+        if let Message::PrivacyReport(res) = message {
+            match res {
+                Err(e) => self.warning = Some(e),
+                Ok(findings) => {
+                    self.warning = None;
+                    self.findings = Some(findings);
+                }
+            }
+        }
+        Task::none()
+    }
+
+    fn reload(
+        &mut self,
+        daemon: Arc<dyn Daemon + Sync + Send>,
+        _wallet: Arc<Wallet>,
+    ) -> Task<Message> {
+        Task::perform(
+            async move {
+                daemon
+                    .privacy_report()
+                    .await
+                    .map(|res| res.findings)
+                    .map_err(|e| e.into())
+            },
+            Message::PrivacyReport,
+        )
+    }
+}
+
+impl From<PrivacyPanel> for Box<dyn State> {
+    fn from(s: PrivacyPanel) -> Box<dyn State> {
+        Box::new(s)
+    }
+}
@@ -7,6 +7,7 @@ use iced::Task;
 use liana::{
     descriptors::LianaPolicy,
     miniscript::bitcoin::{bip32::Fingerprint, psbt::Psbt, Network, Txid},
+    silent_payments::{self, SilentPaymentError},
 };
 use lianad::commands::CoinStatus;
 
@@ -537,10 +538,37 @@ impl Modal for SignModal {
                         self.error = None;
                         self.signed.insert(fingerprint);
                         let daemon = daemon.clone();
-                        merge_signatures(&mut tx.psbt, &psbt);
+                        // The outputs paying to silent payment addresses are derived by the signer,
+                        // in which case the signed transaction replaces the previous one.
+                        let prev_txid = tx.psbt.unsigned_tx.compute_txid();
+                        let replaced_txid =
+                            (psbt.unsigned_tx.compute_txid() != prev_txid).then_some(prev_txid);
+                        if let Some(prev_txid) = replaced_txid {
+                            tx.psbt = psbt.clone();
+                            // The labels of the transaction and of its outputs carry over to the
+                            // one replacing it. The daemon does the same for the stored ones.
+                            let prev_txid = prev_txid.to_string();
+                            let txid = psbt.unsigned_tx.compute_txid().to_string();
+                            let labels = std::mem::take(tx.labels());
+                            *tx.labels() = labels
+                                .into_iter()
+                                .map(|(item, label)| match item.strip_prefix(&prev_txid) {
+                                    Some(vout) => (format!("{txid}{vout}"), label),
+                                    None => (item, label),
+                                })
+                                .collect();
+                        } else {
+                            merge_signatures(&mut tx.psbt, &psbt);
+                        }
                         if self.is_saved {
                             return Task::perform(
-                                async move { daemon.update_spend_tx(&psbt).await.map_err(|e| e.into()) },
+                                async move {
+                                    daemon.update_spend_tx(&psbt).await?;
+                                    if let Some(txid) = replaced_txid {
+                                        daemon.delete_spend_tx(&txid).await?;
+                                    }
+                                    Ok::<_, Error>(())
+                                },
                                 Message::Updated,
                             );
                         // If the spend transaction was never saved before, then both the psbt and
@@ -614,7 +642,7 @@ impl Modal for SignModal {
     }
 }
 
-fn merge_signatures(psbt: &mut Psbt, signed_psbt: &Psbt) {
+pub(crate) fn merge_signatures(psbt: &mut Psbt, signed_psbt: &Psbt) {
     for i in 0..signed_psbt.inputs.len() {
         let psbtin = match psbt.inputs.get_mut(i) {
             Some(psbtin) => psbtin,
@@ -636,7 +664,7 @@ fn merge_signatures(psbt: &mut Psbt, signed_psbt: &Psbt) {
     }
 }
 
-async fn sign_psbt_with_hot_signer(
+pub(crate) async fn sign_psbt_with_hot_signer(
     wallet: Arc<Wallet>,
     psbt: Psbt,
 ) -> (Fingerprint, Result<Psbt, Error>) {
@@ -654,11 +682,17 @@ async fn sign_psbt_with_hot_signer(
     }
 }
 
-async fn sign_psbt(
+pub(crate) async fn sign_psbt(
     wallet: Arc<Wallet>,
     hw: std::sync::Arc<dyn async_hwi::HWI + Send + Sync>,
     mut psbt: Psbt,
 ) -> Result<Psbt, Error> {
+    // Signing devices can't derive the outputs paying to silent payment addresses.
+    if silent_payments::has_underived_outputs(&psbt) {
+        return Err(Error::Unexpected(
+            SilentPaymentError::UnsupportedSigning.to_string(),
+        ));
+    }
     // The BitBox02 is only going to produce a signature for a single key in the Script. In order
     // to make sure it doesn't sign for a public key from another spending path we remove the BIP32
     // derivation for the other paths.
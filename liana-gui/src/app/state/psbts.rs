@@ -7,7 +7,10 @@ use liana_ui::widget::Element;
 use super::{export::ExportModal, psbt, State};
 use crate::{
     app::{cache::Cache, error::Error, menu::Menu, message::Message, view, wallet::Wallet},
-    daemon::{model::SpendTx, Daemon},
+    daemon::{
+        model::{ScheduledBroadcastEntry, SpendTx},
+        Daemon,
+    },
     export::{ImportExportMessage, ImportExportType},
 };
 
@@ -15,6 +18,7 @@ pub struct PsbtsPanel {
     wallet: Arc<Wallet>,
     selected_tx: Option<psbt::PsbtState>,
     spend_txs: Vec<SpendTx>,
+    scheduled_broadcasts: Vec<ScheduledBroadcastEntry>,
     warning: Option<Error>,
     modal: Option<ExportModal>,
 }
@@ -24,6 +28,7 @@ impl PsbtsPanel {
         Self {
             wallet,
             spend_txs: Vec::new(),
+            scheduled_broadcasts: Vec::new(),
             warning: None,
             selected_tx: None,
             modal: None,
@@ -47,7 +52,7 @@ impl State for PsbtsPanel {
                 &Menu::PSBTs,
                 cache,
                 self.warning.as_ref(),
-                view::psbts::psbts_view(&self.spend_txs),
+                view::psbts::psbts_view(&self.spend_txs, &self.scheduled_broadcasts),
             );
             if let Some(modal) = &self.modal {
                 modal.view(list_view)
@@ -89,6 +94,11 @@ impl State for PsbtsPanel {
                     }
                 }
             },
+            // Not every backend supports scheduling broadcasts, in which case there is just
+            // nothing to display.
+            Message::ScheduledBroadcasts(res) => {
+                self.scheduled_broadcasts = res.unwrap_or_default();
+            }
             Message::View(view::Message::ImportPsbt) => {
                 if let Some(tx) = &mut self.selected_tx {
                     return tx.update(daemon, cache, message);
@@ -161,16 +171,28 @@ impl State for PsbtsPanel {
         self.wallet = wallet;
         self.selected_tx = None;
         self.modal = None;
-        let daemon = daemon.clone();
-        Task::perform(
-            async move {
-                daemon
-                    .list_spend_transactions(None)
-                    .await
-                    .map_err(|e| e.into())
-            },
-            Message::SpendTxs,
-        )
+        let daemon1 = daemon.clone();
+        Task::batch(vec![
+            Task::perform(
+                async move {
+                    daemon1
+                        .list_spend_transactions(None)
+                        .await
+                        .map_err(|e| e.into())
+                },
+                Message::SpendTxs,
+            ),
+            Task::perform(
+                async move {
+                    daemon
+                        .list_scheduled_broadcasts()
+                        .await
+                        .map(|res| res.transactions)
+                        .map_err(|e| e.into())
+                },
+                Message::ScheduledBroadcasts,
+            ),
+        ])
     }
 }
 
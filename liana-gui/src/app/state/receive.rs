@@ -2,9 +2,14 @@ use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use iced::{widget::qr_code, Length, Subscription, Task};
-use liana::miniscript::bitcoin::{
-    bip32::{ChildNumber, Fingerprint},
-    Address, Network,
+use liana::{
+    bip21::PaymentUri,
+    bip322::{self, MessageSignature},
+    miniscript::bitcoin::{
+        bip32::{ChildNumber, Fingerprint},
+        psbt::Psbt,
+        secp256k1, Address, Amount, Denomination, Network, Script,
+    },
 };
 use liana_ui::{component::form, widget::modal, widget::*};
 
@@ -16,7 +21,11 @@ use crate::{
         error::Error,
         menu::Menu,
         message::Message,
-        state::{label::LabelsEdited, State},
+        state::{
+            label::LabelsEdited,
+            psbt::{sign_psbt, sign_psbt_with_hot_signer},
+            State,
+        },
         view,
         wallet::Wallet,
     },
@@ -36,6 +45,7 @@ pub enum Modal {
     ShowQrCode(ShowQrCodeModal),
     EditLabel(String),
     NewAddress(NewAddressModal),
+    SignMessage(SignMessageModal),
     None,
 }
 
@@ -50,6 +60,11 @@ impl Addresses {
     pub fn is_empty(&self) -> bool {
         self.list.is_empty() && self.derivation_indexes.is_empty() && self.labels.is_empty()
     }
+
+    fn label(&self, address: &Address) -> Option<&String> {
+        self.labels
+            .get(&LabelItem::Address(address.clone()).to_string())
+    }
 }
 
 impl Labelled for Addresses {
@@ -136,6 +151,9 @@ impl State for ReceivePanel {
                     )))
                     .into()
             }
+            Modal::SignMessage(m) => modal::Modal::new(content, m.view())
+                .on_blur(Some(view::Message::Close))
+                .into(),
             Modal::NewAddress(m) => {
                 // No blur-to-close while the label is saving and the address is revealing.
                 let on_blur = (!m.is_processing())
@@ -149,6 +167,7 @@ impl State for ReceivePanel {
     fn subscription(&self) -> Subscription<Message> {
         match &self.modal {
             Modal::VerifyAddress(modal) => modal.subscription(),
+            Modal::SignMessage(modal) => modal.subscription(),
             Modal::NewAddress(m) => m
                 .verify_sub()
                 .map(VerifyAddressModal::subscription)
@@ -287,8 +306,9 @@ impl State for ReceivePanel {
                 }
                 view::NewAddressMessage::ShowQr => {
                     let qr = if let Modal::NewAddress(m) = &self.modal {
-                        m.shown()
-                            .and_then(|(address, _)| ShowQrCodeModal::new(address, None))
+                        m.shown().and_then(|(address, _)| {
+                            ShowQrCodeModal::new(address, self.prev_addresses.label(address))
+                        })
                     } else {
                         None
                     };
@@ -326,6 +346,18 @@ impl State for ReceivePanel {
                 ));
                 Task::none()
             }
+            Message::View(view::Message::SignMessage(view::SignMessageMessage::Open(i))) => {
+                if let (Some(address), Some(index)) = (self.address(i), self.derivation_index(i)) {
+                    self.modal = Modal::SignMessage(SignMessageModal::new(
+                        self.data_dir.clone(),
+                        self.wallet.clone(),
+                        cache.network,
+                        address.clone(),
+                        *index,
+                    ));
+                }
+                Task::none()
+            }
             Message::View(view::Message::NextReceiveAddress) => {
                 self.modal = Modal::NewAddress(NewAddressModal::new());
                 Task::none()
@@ -396,7 +428,9 @@ impl State for ReceivePanel {
             Message::View(view::Message::ShowAddressQrCode(view::AddressQrSource::Row(i))) => {
                 // The address QR code does not encode the derivation index.
                 if let Some(address) = self.address(i) {
-                    if let Some(modal) = ShowQrCodeModal::new(address, None) {
+                    if let Some(modal) =
+                        ShowQrCodeModal::new(address, self.prev_addresses.label(address))
+                    {
                         self.modal = Modal::ShowQrCode(modal);
                     }
                 }
@@ -407,7 +441,7 @@ impl State for ReceivePanel {
                 i,
             ))) => {
                 // Specter DIY devices need the derivation index in the QR code.
-                if let Some(qr) = ShowQrCodeModal::new(&address, Some(i)) {
+                if let Some(qr) = ShowQrCodeModal::with_index(&address, i) {
                     // From the generate flow's verify sub-modal, stack the QR so closing it
                     // returns to the show-address step. From a standalone verify modal, replace.
                     if let Modal::NewAddress(m) = &mut self.modal {
@@ -418,6 +452,18 @@ impl State for ReceivePanel {
                 }
                 Task::none()
             }
+            Message::View(view::Message::QrAmountEdited(value)) => {
+                // The QR code modal can be standalone or stacked on the new-address modal.
+                let modal = match &mut self.modal {
+                    Modal::ShowQrCode(m) => Some(m),
+                    Modal::NewAddress(m) => m.qr_sub_mut(),
+                    _ => None,
+                };
+                if let Some(modal) = modal {
+                    modal.edit_amount(value);
+                }
+                Task::none()
+            }
             Message::View(view::Message::ShowQrOptSection(open)) => {
                 // The verify modal can be standalone or stacked on the new-address modal.
                 let modal = match &mut self.modal {
@@ -432,6 +478,7 @@ impl State for ReceivePanel {
             }
             _ => match &mut self.modal {
                 Modal::VerifyAddress(m) => m.update(daemon, cache, message),
+                Modal::SignMessage(m) => m.update(message),
                 Modal::NewAddress(m) => m
                     .verify_sub_mut()
                     .map(|v| v.update(daemon, cache, message))
@@ -555,24 +602,225 @@ impl VerifyAddressModal {
     }
 }
 
+pub struct SignMessageModal {
+    warning: Option<Error>,
+    wallet: Arc<Wallet>,
+    hws: HardwareWallets,
+    address: Address,
+    derivation_index: ChildNumber,
+    message: form::Value<String>,
+    signing: HashSet<Fingerprint>,
+    /// The signature of the message, once signed.
+    signature: Option<String>,
+}
+
+impl SignMessageModal {
+    pub fn new(
+        data_dir: LianaDirectory,
+        wallet: Arc<Wallet>,
+        network: Network,
+        address: Address,
+        derivation_index: ChildNumber,
+    ) -> Self {
+        Self {
+            warning: None,
+            hws: HardwareWallets::new(data_dir, network).with_wallet(wallet.clone()),
+            wallet,
+            address,
+            derivation_index,
+            message: form::Value::default(),
+            signing: HashSet::new(),
+            signature: None,
+        }
+    }
+
+    fn view(&self) -> Element<'_, view::Message> {
+        view::receive::sign_message_modal(
+            self.warning.as_ref(),
+            &self.address,
+            &self.message,
+            &self.hws.list,
+            &self.wallet.main_descriptor,
+            self.wallet.signer.as_ref().map(|s| s.fingerprint()),
+            self.wallet
+                .signer
+                .as_ref()
+                .and_then(|signer| self.wallet.keys_aliases.get(&signer.fingerprint)),
+            &self.signing,
+            self.signature.as_ref(),
+        )
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        self.hws.refresh().map(Message::HardwareWallets)
+    }
+
+    /// The PSBT to sign in order to sign the message with this address. Only the addresses
+    /// from the receive descriptor are listed in this panel.
+    fn message_psbt(&mut self) -> Option<Psbt> {
+        if self.message.value.is_empty() {
+            self.message.valid = false;
+            return None;
+        }
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc = &self.wallet.main_descriptor;
+        let der_desc = desc
+            .receive_descriptor()
+            .derive(self.derivation_index, &secp);
+        Some(bip322::message_psbt(
+            &der_desc,
+            desc.is_taproot(),
+            self.message.value.as_bytes(),
+        ))
+    }
+
+    fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::HardwareWallets(msg) => match self.hws.update(msg) {
+                Ok(cmd) => cmd.map(Message::HardwareWallets),
+                Err(e) => {
+                    self.warning = Some(e.into());
+                    Task::none()
+                }
+            },
+            Message::View(view::Message::SignMessage(view::SignMessageMessage::MessageEdited(
+                value,
+            ))) => {
+                // A signature is only valid for the message it was made for.
+                if self.signing.is_empty() {
+                    self.message.value = value;
+                    self.message.valid = true;
+                    self.signature = None;
+                }
+                Task::none()
+            }
+            Message::View(view::Message::SelectHardwareWallet(i)) => {
+                let Some(HardwareWallet::Supported {
+                    device,
+                    fingerprint,
+                    ..
+                }) = self.hws.list.get(i)
+                else {
+                    return Task::none();
+                };
+                let (device, fg) = (device.clone(), *fingerprint);
+                let Some(psbt) = self.message_psbt() else {
+                    return Task::none();
+                };
+                self.warning = None;
+                self.signing.insert(fg);
+                let (spk, msg) = (self.address.script_pubkey(), self.message.value.clone());
+                Task::perform(sign_psbt(self.wallet.clone(), device, psbt), move |res| {
+                    Message::MessageSigned(fg, res.and_then(|psbt| signature(&psbt, &spk, &msg)))
+                })
+            }
+            Message::View(view::Message::SignMessage(
+                view::SignMessageMessage::SelectHotSigner,
+            )) => {
+                let Some(psbt) = self.message_psbt() else {
+                    return Task::none();
+                };
+                self.warning = None;
+                if let Some(signer) = &self.wallet.signer {
+                    self.signing.insert(signer.fingerprint());
+                }
+                let (spk, msg) = (self.address.script_pubkey(), self.message.value.clone());
+                Task::perform(
+                    sign_psbt_with_hot_signer(self.wallet.clone(), psbt),
+                    move |(fg, res)| {
+                        Message::MessageSigned(
+                            fg,
+                            res.and_then(|psbt| signature(&psbt, &spk, &msg)),
+                        )
+                    },
+                )
+            }
+            Message::MessageSigned(fg, res) => {
+                self.signing.remove(&fg);
+                match res {
+                    Ok(sig) => self.signature = Some(sig),
+                    Err(e) => {
+                        if !matches!(e, Error::HardwareWallet(async_hwi::Error::UserRefused)) {
+                            self.warning = Some(e);
+                        }
+                    }
+                }
+                Task::none()
+            }
+            _ => Task::none(),
+        }
+    }
+}
+
+/// The signature of the message from its signed PSBT.
+fn signature(psbt: &Psbt, script_pubkey: &Script, message: &str) -> Result<String, Error> {
+    let secp = secp256k1::Secp256k1::verification_only();
+    MessageSignature::from_psbt(&secp, psbt, script_pubkey, message.as_bytes())
+        .map(|sig| sig.to_string())
+        .map_err(|e| Error::Unexpected(e.to_string()))
+}
+
 pub struct ShowQrCodeModal {
     qr_code: qr_code::Data,
     address: String,
+    /// The payment URI encoded in the QR code and the amount it requests, if it can be edited.
+    uri: Option<(PaymentUri, form::Value<String>)>,
 }
 
 impl ShowQrCodeModal {
-    pub fn new(address: &Address, index: Option<ChildNumber>) -> Option<Self> {
-        let index = index.map(|i| format!("?index={i}")).unwrap_or_default();
-        qr_code::Data::new(format!("bitcoin:{address}{index}"))
+    /// A QR code encoding a payment URI for this address, with its label if any. The user may
+    /// then set an amount to request.
+    pub fn new(address: &Address, label: Option<&String>) -> Option<Self> {
+        let mut uri = PaymentUri::new(address.as_unchecked().clone());
+        uri.label = label.cloned();
+        qr_code::Data::new(uri.to_string())
             .ok()
             .map(|qr_code| Self {
                 qr_code,
                 address: address.to_string(),
+                uri: Some((uri, form::Value::default())),
             })
     }
 
+    /// A QR code encoding the derivation index of this address, as needed by Specter DIY devices.
+    pub fn with_index(address: &Address, index: ChildNumber) -> Option<Self> {
+        let uri =
+            PaymentUri::new(address.as_unchecked().clone()).with_param("index", index.to_string());
+        qr_code::Data::new(uri.to_string())
+            .ok()
+            .map(|qr_code| Self {
+                qr_code,
+                address: address.to_string(),
+                uri: None,
+            })
+    }
+
+    fn edit_amount(&mut self, value: String) {
+        let Some((uri, amount)) = &mut self.uri else {
+            return;
+        };
+        uri.amount = if value.is_empty() {
+            None
+        } else {
+            Amount::from_str_in(&value, Denomination::Bitcoin)
+                .ok()
+                .filter(|a| *a > Amount::ZERO)
+        };
+        amount.valid = value.is_empty() || uri.amount.is_some();
+        amount.value = value;
+        if amount.valid {
+            if let Ok(qr_code) = qr_code::Data::new(uri.to_string()) {
+                self.qr_code = qr_code;
+            }
+        }
+    }
+
     fn view(&self) -> Element<'_, view::Message> {
-        view::receive::qr_modal(&self.qr_code, &self.address)
+        view::receive::qr_modal(
+            &self.qr_code,
+            &self.address,
+            self.uri.as_ref().map(|(_, amount)| amount),
+        )
     }
 }
 
@@ -716,6 +964,18 @@ impl NewAddressModal {
         }
     }
 
+    fn qr_sub_mut(&mut self) -> Option<&mut ShowQrCodeModal> {
+        if let Step::Show {
+            sub: Some(NewAddressSubModal::Qr(m)),
+            ..
+        } = &mut self.step
+        {
+            Some(m)
+        } else {
+            None
+        }
+    }
+
     fn is_processing(&self) -> bool {
         matches!(self.step, Step::Processing { .. })
     }
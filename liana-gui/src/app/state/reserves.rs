@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use iced::{Subscription, Task};
+use liana::{
+    miniscript::bitcoin::{bip32::Fingerprint, psbt::Psbt, Network},
+    reserves,
+};
+use liana_ui::{
+    component::{form, toast},
+    widget::Element,
+};
+
+use crate::{
+    app::{cache::Cache, error::Error, menu::Menu, message::Message, view, wallet::Wallet},
+    daemon::Daemon,
+    dir::LianaDirectory,
+    export::{ImportExportMessage, ImportExportType},
+    hw::{HardwareWallet, HardwareWallets},
+};
+
+use super::{
+    export::ExportModal,
+    psbt::{merge_signatures, sign_psbt, sign_psbt_with_hot_signer},
+    State,
+};
+
+/// Create a proof of reserves (BIP127) for the confirmed coins of the wallet, have it signed and
+/// export it to a file. Its PSBT is not stored by the daemon, so it is lost when starting over.
+pub struct ReservesPanel {
+    wallet: Arc<Wallet>,
+    hws: HardwareWallets,
+    message: form::Value<String>,
+    /// The PSBT of the proof, once created.
+    psbt: Option<Psbt>,
+    signed: HashSet<Fingerprint>,
+    signing: HashSet<Fingerprint>,
+    /// Whether the proof is being created or finalized.
+    processing: bool,
+    export: Option<ExportModal>,
+    warning: Option<Error>,
+}
+
+impl ReservesPanel {
+    pub fn new(data_dir: LianaDirectory, wallet: Arc<Wallet>, network: Network) -> Self {
+        Self {
+            hws: HardwareWallets::new(data_dir, network).with_wallet(wallet.clone()),
+            wallet,
+            message: form::Value::default(),
+            psbt: None,
+            signed: HashSet::new(),
+            signing: HashSet::new(),
+            processing: false,
+            export: None,
+            warning: None,
+        }
+    }
+
+    /// The number of signatures of the proof for the primary path, and the number required.
+    fn sigs(&self) -> Option<(usize, usize)> {
+        let psbt = self.psbt.as_ref()?;
+        reserves::proof_spend_info(&self.wallet.main_descriptor, psbt)
+            .ok()
+            .map(|info| {
+                (
+                    info.primary_path().sigs_count,
+                    info.primary_path().threshold,
+                )
+            })
+    }
+}
+
+impl State for ReservesPanel {
+    fn view<'a>(&'a self, cache: &'a Cache) -> Element<'a, view::Message> {
+        let content = view::dashboard(
+            &Menu::Reserves,
+            cache,
+            self.warning.as_ref(),
+            view::reserves::reserves_view(
+                &self.message,
+                self.psbt.as_ref(),
+                self.sigs(),
+                &self.hws.list,
+                &self.wallet.main_descriptor,
+                self.wallet.signer.as_ref().map(|s| s.fingerprint()),
+                self.wallet
+                    .signer
+                    .as_ref()
+                    .and_then(|signer| self.wallet.keys_aliases.get(&signer.fingerprint)),
+                &self.signed,
+                &self.signing,
+                self.processing,
+            ),
+        );
+        let content = toast::Manager::new(
+            content,
+            view::psbt::sign_action_toasts(None, &self.hws.list, &self.signing),
+        )
+        .into();
+        if let Some(export) = &self.export {
+            export.view(content)
+        } else {
+            content
+        }
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        let export = self
+            .export
+            .as_ref()
+            .and_then(|e| e.subscription())
+            .map(|s| {
+                s.map(|m| {
+                    Message::View(view::Message::ImportExport(ImportExportMessage::Progress(
+                        m,
+                    )))
+                })
+            });
+        Subscription::batch(vec![
+            self.hws.refresh().map(Message::HardwareWallets),
+            export.unwrap_or_else(Subscription::none),
+        ])
+    }
+
+    fn update(
+        &mut self,
+        daemon: Arc<dyn Daemon + Sync + Send>,
+        _cache: &Cache,
+        message: Message,
+    ) -> Task<Message> {
+        match message {
+            Message::HardwareWallets(msg) => match self.hws.update(msg) {
+                Ok(cmd) => return cmd.map(Message::HardwareWallets),
+                Err(e) => self.warning = Some(e.into()),
+            },
+            Message::View(view::Message::Reserves(view::ReservesMessage::MessageEdited(value))) => {
+                // The proof commits to the message, which can't be changed once it's created.
+                if self.psbt.is_none() {
+                    self.message.value = value;
+                    self.message.valid = !self.message.value.is_empty();
+                }
+            }
+            Message::View(view::Message::Reserves(view::ReservesMessage::Create)) => {
+                if self.message.value.is_empty() {
+                    self.message.valid = false;
+                    return Task::none();
+                }
+                self.processing = true;
+                self.warning = None;
+                let message = self.message.value.clone();
+                return Task::perform(
+                    async move {
+                        daemon
+                            .create_reserves_proof(&message, &[])
+                            .await
+                            .map(|res| res.psbt)
+                            .map_err(|e| e.into())
+                    },
+                    Message::ReservesProof,
+                );
+            }
+            Message::ReservesProof(res) => {
+                self.processing = false;
+                match res {
+                    Ok(psbt) => self.psbt = Some(psbt),
+                    Err(e) => self.warning = Some(e),
+                }
+            }
+            Message::View(view::Message::SelectHardwareWallet(i)) => {
+                if let (
+                    Some(psbt),
+                    Some(HardwareWallet::Supported {
+                        device,
+                        fingerprint,
+                        ..
+                    }),
+                ) = (&self.psbt, self.hws.list.get(i))
+                {
+                    let fingerprint = *fingerprint;
+                    self.warning = None;
+                    self.signing.insert(fingerprint);
+                    return Task::perform(
+                        sign_psbt(self.wallet.clone(), device.clone(), psbt.clone()),
+                        move |res| Message::Signed(fingerprint, res),
+                    );
+                }
+            }
+            Message::View(view::Message::Reserves(view::ReservesMessage::SelectHotSigner)) => {
+                if let Some(psbt) = &self.psbt {
+                    self.warning = None;
+                    if let Some(signer) = &self.wallet.signer {
+                        self.signing.insert(signer.fingerprint());
+                    }
+                    return Task::perform(
+                        sign_psbt_with_hot_signer(self.wallet.clone(), psbt.clone()),
+                        |(fg, res)| Message::Signed(fg, res),
+                    );
+                }
+            }
+            Message::Signed(fingerprint, res) => {
+                self.signing.remove(&fingerprint);
+                match res {
+                    Ok(signed_psbt) => {
+                        if let Some(psbt) = self.psbt.as_mut() {
+                            merge_signatures(psbt, &signed_psbt);
+                            self.signed.insert(fingerprint);
+                        }
+                    }
+                    Err(e) => {
+                        if !matches!(e, Error::HardwareWallet(async_hwi::Error::UserRefused)) {
+                            self.warning = Some(e);
+                        }
+                    }
+                }
+            }
+            Message::View(view::Message::Reserves(view::ReservesMessage::Export)) => {
+                if let Some(psbt) = self.psbt.clone() {
+                    self.processing = true;
+                    self.warning = None;
+                    let message = self.message.value.clone();
+                    return Task::perform(
+                        async move {
+                            daemon
+                                .finalize_reserves_proof(&psbt, &message)
+                                .await
+                                .map_err(|e| e.into())
+                        },
+                        Message::ReservesProofFinalized,
+                    );
+                }
+            }
+            Message::ReservesProofFinalized(res) => {
+                self.processing = false;
+                match res.and_then(|proof| {
+                    serde_json::to_string_pretty(&proof)
+                        .map_err(|e| Error::Unexpected(e.to_string()))
+                }) {
+                    Ok(proof) => {
+                        let modal =
+                            ExportModal::new(None, ImportExportType::ExportReservesProof(proof));
+                        let launch = modal.launch(true);
+                        self.export = Some(modal);
+                        return launch;
+                    }
+                    Err(e) => self.warning = Some(e),
+                }
+            }
+            Message::View(view::Message::Reserves(view::ReservesMessage::Reset)) => {
+                if self.signing.is_empty() {
+                    self.psbt = None;
+                    self.signed.clear();
+                    self.warning = None;
+                }
+            }
+            Message::View(view::Message::ImportExport(ImportExportMessage::Close)) => {
+                self.export = None;
+            }
+            Message::View(view::Message::ImportExport(m)) => {
+                if let Some(export) = self.export.as_mut() {
+                    return export.update(m);
+                }
+            }
+            _ => {}
+        }
+        Task::none()
+    }
+}
+
+impl From<ReservesPanel> for Box<dyn State> {
+    fn from(s: ReservesPanel) -> Box<dyn State> {
+        Box::new(s)
+    }
+}
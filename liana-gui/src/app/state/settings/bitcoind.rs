@@ -367,6 +367,7 @@ impl BitcoindSettings {
                         Some(lianad::config::BitcoinBackend::Bitcoind(BitcoindConfig {
                             rpc_auth,
                             addr: new_addr.unwrap(),
+                            ..self.bitcoind_config.clone()
                         }));
                     self.processing = true;
                     return Task::perform(async move { daemon_config }, |cfg| {
@@ -477,6 +478,7 @@ impl ElectrumSettings {
                         Some(lianad::config::BitcoinBackend::Electrum(ElectrumConfig {
                             addr: self.addr.value.clone(),
                             validate_domain: self.electrum_config.validate_domain,
+                            ..self.electrum_config.clone()
                         }));
                     self.processing = true;
                     return Task::perform(async move { daemon_config }, |cfg| {
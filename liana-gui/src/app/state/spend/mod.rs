@@ -1,6 +1,6 @@
 mod step;
 
-pub use step::{FeeMode, Recipient};
+pub use step::{AdvancedOptions, FeeMode, Recipient};
 
 use std::collections::HashSet;
 use std::convert::TryInto;
@@ -11,13 +11,17 @@ use std::{
 
 use iced::{Subscription, Task};
 use liana::{
+    bip21::PaymentUri,
     miniscript::bitcoin::{
-        address,
+        absolute, address,
         bip32::{DerivationPath, Fingerprint},
         psbt::Psbt,
         secp256k1, Address, Amount, Denomination, Network, OutPoint,
     },
-    spend::{SpendCreationError, DUST_OUTPUT_SATS, MAX_FEERATE},
+    silent_payments::SilentPaymentAddress,
+    spend::{
+        SpendCreationError, SpendOptions, DUST_OUTPUT_SATS, MAX_FEERATE, MAX_OP_RETURN_DATA_SIZE,
+    },
 };
 use lianad::commands::ListCoinsEntry;
 
@@ -36,7 +40,9 @@ use crate::{
         wallet::Wallet,
     },
     daemon::{
-        model::{coin_is_owned, remaining_sequence, Coin, CreateSpendResult, SpendTx},
+        model::{
+            coin_is_owned, remaining_sequence, Coin, CreateSpendOptions, CreateSpendResult, SpendTx,
+        },
         Daemon,
     },
 };
@@ -144,6 +150,82 @@ pub enum FeeMode {
     Smart(FeeLevel),
 }
 
+/// Optional settings of a primary path spend.
+#[derive(Debug, Clone)]
+pub struct AdvancedOptions {
+    /// Whether the advanced options are displayed.
+    pub expanded: bool,
+    /// Text to embed in an OP_RETURN output.
+    pub op_return: form::Value<String>,
+    /// Block height to use as nLockTime. If empty, the daemon's default is used.
+    pub locktime: form::Value<String>,
+    pub signal_rbf: bool,
+    /// Whether to create a TRUC (v3) transaction.
+    pub truc: bool,
+}
+
+impl Default for AdvancedOptions {
+    fn default() -> Self {
+        Self {
+            expanded: false,
+            op_return: form::Value::default(),
+            locktime: form::Value::default(),
+            signal_rbf: true,
+            truc: false,
+        }
+    }
+}
+
+impl AdvancedOptions {
+    fn valid(&self) -> bool {
+        self.op_return.valid && self.locktime.valid
+    }
+
+    fn update(&mut self, message: view::CreateSpendMessage) {
+        match message {
+            view::CreateSpendMessage::ToggleAdvancedOptions => {
+                self.expanded = !self.expanded;
+            }
+            view::CreateSpendMessage::OpReturnEdited(data) => {
+                self.op_return.valid = data.len() <= MAX_OP_RETURN_DATA_SIZE;
+                self.op_return.value = data;
+            }
+            view::CreateSpendMessage::LocktimeEdited(locktime) => {
+                self.locktime.valid = locktime.is_empty()
+                    || locktime
+                        .parse::<u32>()
+                        .is_ok_and(|height| absolute::Height::from_consensus(height).is_ok());
+                self.locktime.value = locktime;
+            }
+            view::CreateSpendMessage::ToggleRbf => {
+                self.signal_rbf = !self.signal_rbf;
+            }
+            view::CreateSpendMessage::ToggleTruc => {
+                self.truc = !self.truc;
+            }
+            _ => {}
+        }
+    }
+
+    /// The options to pass to the daemon. Must only be called if the values are valid.
+    fn to_create_spend_options(&self) -> CreateSpendOptions {
+        CreateSpendOptions {
+            locktime: (!self.locktime.value.is_empty()).then(|| {
+                absolute::LockTime::from_consensus(
+                    self.locktime.value.parse().expect("Checked before"),
+                )
+            }),
+            spend: SpendOptions {
+                op_return_data: (!self.op_return.value.is_empty())
+                    .then(|| self.op_return.value.as_bytes().to_vec()),
+                signal_rbf: self.signal_rbf,
+                truc: self.truc,
+            },
+            ..Default::default()
+        }
+    }
+}
+
 pub struct DefineSpend {
     recipients: Vec<Recipient>,
     /// If set, this is the index of a recipient that should
@@ -172,6 +254,7 @@ pub struct DefineSpend {
     feerate: form::Value<String>,
     fee_mode: FeeMode,
     fee_amount: Option<Amount>,
+    advanced_options: AdvancedOptions,
     generated: Option<(Psbt, Vec<String>)>,
     warning: Option<Error>,
     /// Whether this is the first step of the spend creation.
@@ -214,6 +297,7 @@ impl DefineSpend {
             feerate: form::Value::default(),
             fee_mode: FeeMode::Smart(FeeLevel::Low),
             fee_amount: None,
+            advanced_options: AdvancedOptions::default(),
             amount_left_to_select: None,
             warning: None,
             is_first_step,
@@ -271,6 +355,7 @@ impl DefineSpend {
         self.feerate.valid
             && !self.feerate.value.is_empty()
             && (self.batch_label.valid || self.recipients.len() < 2)
+            && self.advanced_options.valid()
             // Recipients will be empty for self-send.
             && self.recipients.iter().enumerate().all(|(i, r)|
             r.valid() || (is_redraft && self.send_max_to_recipient == Some(i) && r.address_valid()))
@@ -342,6 +427,18 @@ impl DefineSpend {
                 }
             })
             .collect();
+        // The max recipient never has the fee deducted as it already accounts for it.
+        let subtract_fee_from: Vec<Address<address::NetworkUnchecked>> = self
+            .recipients
+            .iter()
+            .enumerate()
+            .filter(|(i, recipient)| {
+                recipient.subtract_fee && self.send_max_to_recipient != Some(*i)
+            })
+            .map(|(_, recipient)| {
+                Address::from_str(&recipient.address.value).expect("Checked before")
+            })
+            .collect();
 
         // we drop dust warning
         self.recipients.iter_mut().for_each(|r| {
@@ -400,10 +497,11 @@ impl DefineSpend {
             }
             outpoints
         } else if !self.is_user_coin_selection && self.send_max_to_recipient.is_some() {
-            // If user has not selected coins, send the max available from all owned coins.
+            // If user has not selected coins, send the max available from all owned coins, except
+            // those in quarantine which are never selected automatically.
             self.coins
                 .iter()
-                .filter_map(|(c, _)| coin_is_owned(c).then_some(c.outpoint))
+                .filter_map(|(c, _)| (coin_is_owned(c) && !c.is_quarantined).then_some(c.outpoint))
                 .collect()
         } else {
             Vec::new() // pass empty list for auto-selection
@@ -430,6 +528,7 @@ impl DefineSpend {
 
         let feerate_vb = self.feerate.value.parse::<u64>().expect("Checked before");
         let recovery_timelock = self.recovery_timelock;
+        let options = self.advanced_options.to_create_spend_options();
         match tokio::runtime::Handle::current().block_on(async {
             // If recovery timelock is set, create a recovery transaction. Otherwise, a regular spend.
             if let Some(reco_tl) = recovery_timelock {
@@ -448,6 +547,8 @@ impl DefineSpend {
                         &destinations,
                         feerate_vb,
                         Some(max_address.clone()),
+                        &subtract_fee_from,
+                        &options,
                     )
                     .await
             }
@@ -528,9 +629,9 @@ fn handle_max_under_dust(
     // can't currently reach this part of code.
     if !is_user_coin_selection && recovery_timelock_none {
         // The missing amount is based on all candidates for coin selection
-        // being used, which are all owned coins.
+        // being used, which are all owned coins not in quarantine.
         coins.iter_mut().for_each(|(coin, selected)| {
-            *selected = coin_is_owned(coin);
+            *selected = coin_is_owned(coin) && !coin.is_quarantined;
             if *selected {
                 selected_coins += 1;
             }
@@ -743,16 +844,28 @@ impl Step for DefineSpend {
                                 Message::Psbt,
                             );
                         } else {
+                            let mut subtract_fee_from = Vec::new();
                             for recipient in &self.recipients {
                                 let address = Address::from_str(&recipient.address.value)
                                     .expect("Checked before");
+                                if recipient.subtract_fee {
+                                    subtract_fee_from.push(address.clone());
+                                }
                                 outputs
                                     .insert(address, recipient.amount().expect("Checked before"));
                             }
+                            let options = self.advanced_options.to_create_spend_options();
                             return Task::perform(
                                 async move {
                                     daemon
-                                        .create_spend_tx(&inputs, &outputs, feerate_vb, None)
+                                        .create_spend_tx(
+                                            &inputs,
+                                            &outputs,
+                                            feerate_vb,
+                                            None,
+                                            &subtract_fee_from,
+                                            &options,
+                                        )
                                         .await
                                         .map_err(|e| e.into())
                                         .and_then(|res| match res {
@@ -788,9 +901,24 @@ impl Step for DefineSpend {
                             } else {
                                 // Either it's set to some other recipient or not at all.
                                 self.send_max_to_recipient = Some(i);
+                                // The max already accounts for the fee.
+                                recipient.subtract_fee = false;
                             };
                         }
                     }
+                    view::CreateSpendMessage::SubtractFeeFromRecipient(i) => {
+                        if let Some(recipient) = self.recipients.get_mut(i) {
+                            recipient.subtract_fee = !recipient.subtract_fee;
+                        }
+                    }
+                    view::CreateSpendMessage::ToggleAdvancedOptions
+                    | view::CreateSpendMessage::OpReturnEdited(_)
+                    | view::CreateSpendMessage::LocktimeEdited(_)
+                    | view::CreateSpendMessage::ToggleRbf
+                    | view::CreateSpendMessage::ToggleTruc => {
+                        self.advanced_options.update(msg);
+                        self.warning = None;
+                    }
                     _ => {}
                 }
 
@@ -875,7 +1003,9 @@ impl Step for DefineSpend {
                                 .unwrap()
                                 .assume_checked()
                                 .matches_script_pubkey(&output.script_pubkey)
-                            && output.value.to_sat() == recipient.amount().unwrap()
+                            // The fee may have been deducted from the recipient's amount.
+                            && (recipient.subtract_fee
+                                || output.value.to_sat() == recipient.amount().unwrap())
                     })
                     .map(|recipient| recipient.label.value.to_string())
                 {
@@ -918,6 +1048,7 @@ impl Step for DefineSpend {
             &self.feerate,
             self.fee_mode,
             self.fee_amount.as_ref(),
+            &self.advanced_options,
             self.warning.as_ref(),
             self.is_first_step,
             max_under_dust,
@@ -940,6 +1071,8 @@ pub struct Recipient {
     pub fiat_converter: Option<view::FiatAmountConverter>,
     pub is_recovery: bool,
     pub dust_warning: Option<String>,
+    /// Whether the fee should be deducted from this recipient's amount.
+    pub subtract_fee: bool,
 }
 
 impl Recipient {
@@ -991,8 +1124,35 @@ impl Recipient {
 
     fn update(&mut self, network: Network, message: view::CreateSpendMessage) {
         match message {
-            view::CreateSpendMessage::RecipientEdited(_, "address", address) => {
+            view::CreateSpendMessage::RecipientEdited(i, "address", mut address) => {
+                // A payment URI fills in the amount and description it specifies, if any.
+                if let Ok(uri) = PaymentUri::from_str(&address) {
+                    if let Some(amount) = uri.amount {
+                        self.update(
+                            network,
+                            view::CreateSpendMessage::RecipientEdited(
+                                i,
+                                "amount",
+                                amount.to_btc().to_string(),
+                            ),
+                        );
+                    }
+                    if let Some(description) =
+                        uri.description().filter(|_| self.label.value.is_empty())
+                    {
+                        self.update(
+                            network,
+                            view::CreateSpendMessage::RecipientEdited(
+                                i,
+                                "label",
+                                description.to_string(),
+                            ),
+                        );
+                    }
+                    address = uri.address.assume_checked().to_string();
+                }
                 self.address.value = address;
+                self.address.warning = None;
                 if let Ok(address) = Address::from_str(&self.address.value) {
                     self.address.valid = address.is_valid_for_network(network);
                     if !self.amount.value.is_empty() {
@@ -1001,6 +1161,12 @@ impl Recipient {
                 } else if self.address.value.is_empty() {
                     // Make the error disappear if we deleted the invalid address
                     self.address.valid = true;
+                } else if SilentPaymentAddress::from_str(&self.address.value).is_ok() {
+                    // Only the daemon's createspend command can pay to a silent payment address,
+                    // and only for Taproot wallets with a single key in the primary path.
+                    self.address.valid = false;
+                    self.address.warning =
+                        Some("Silent payment addresses are not supported by this form yet");
                 } else {
                     self.address.valid = false;
                 }
@@ -1122,6 +1288,7 @@ impl Recipient {
             fiat_converter,
             &self.label,
             is_max_selected,
+            self.subtract_fee,
             self.is_recovery,
             can_delete,
             &self.dust_warning,
@@ -116,6 +116,7 @@ fn coin_list_view<'a>(
                                     Container::new(Space::with_width(Length::Fill))
                                         .width(Length::Fill)
                                 })
+                                .push_maybe(coin.is_quarantined.then(pill::quarantined))
                                 .push(if coin.spend_info.is_some() {
                                     pill::spent()
                                 } else if coin.block_height.is_none() {
@@ -152,6 +153,15 @@ fn coin_list_view<'a>(
                             })
                             .width(Length::Fill),
                         )
+                        .push_maybe(coin.is_quarantined.then(|| {
+                            Container::new(
+                                p1_bold(
+                                    "This coin looks like a dust attack. It won't be selected \
+                                     automatically to be spent until it is released.",
+                                )
+                                .style(theme::text::warning),
+                            )
+                        }))
                         .push_maybe(if coin.spend_info.is_none() {
                             if let Some(b) = coin.block_height {
                                 if blockheight > b as u32 + timelock as u32 {
@@ -287,18 +297,27 @@ fn coin_list_view<'a>(
                                 .spacing(5)
                         } else {
                             Column::new().push(
-                                Row::new().push(Space::with_width(Length::Fill)).push({
-                                    let (icon, label) =
-                                        (Some(icon::arrow_repeat()), "Refresh coin");
-                                    let refresh_btn = if seq == 0 {
-                                        button::primary(icon, label)
+                                Row::new().push(Space::with_width(Length::Fill)).push(
+                                    if coin.is_quarantined {
+                                        // A quarantined coin can't be spent until it is released.
+                                        button::secondary(
+                                            Some(icon::shield_icon()),
+                                            "Release from quarantine",
+                                        )
+                                        .on_press(Message::ReleaseCoin(coin.outpoint))
                                     } else {
-                                        button::secondary(icon, label)
-                                    };
-                                    refresh_btn.on_press(Message::Menu(Menu::RefreshCoins(vec![
-                                        coin.outpoint,
-                                    ])))
-                                }),
+                                        let (icon, label) =
+                                            (Some(icon::arrow_repeat()), "Refresh coin");
+                                        let refresh_btn = if seq == 0 {
+                                            button::primary(icon, label)
+                                        } else {
+                                            button::secondary(icon, label)
+                                        };
+                                        refresh_btn.on_press(Message::Menu(Menu::RefreshCoins(
+                                            vec![coin.outpoint],
+                                        )))
+                                    },
+                                ),
                             )
                         }),
                 )
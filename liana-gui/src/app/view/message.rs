@@ -25,9 +25,12 @@ pub enum Message {
     Close,
     Select(usize),
     SelectPayment(OutPoint),
+    ReleaseCoin(OutPoint),
     Label(Vec<String>, LabelMessage),
     NextReceiveAddress,
     NewAddress(NewAddressMessage),
+    SignMessage(SignMessageMessage),
+    Reserves(ReservesMessage),
     ToggleShowPreviousAddresses,
     Settings(SettingsMessage),
     CreateSpend(CreateSpendMessage),
@@ -39,6 +42,7 @@ pub enum Message {
     CreateRbf(CreateRbfMessage),
     ShowAddressQrCode(AddressQrSource),
     ShowQrOptSection(bool),
+    QrAmountEdited(String),
     ImportExport(ImportExportMessage),
     HideRescanWarning,
     ExportPsbt,
@@ -75,6 +79,24 @@ pub enum NewAddressMessage {
     Close,
 }
 
+#[derive(Debug, Clone)]
+pub enum SignMessageMessage {
+    /// Open the modal to sign a message with the address at this row.
+    Open(usize),
+    MessageEdited(String),
+    SelectHotSigner,
+}
+
+#[derive(Debug, Clone)]
+pub enum ReservesMessage {
+    MessageEdited(String),
+    Create,
+    SelectHotSigner,
+    Export,
+    /// Discard the proof being signed, to create another one.
+    Reset,
+}
+
 #[derive(Debug, Clone)]
 pub enum CreateSpendMessage {
     AddRecipient,
@@ -91,6 +113,12 @@ pub enum CreateSpendMessage {
     SelectPath(usize),
     Generate,
     SendMaxToRecipient(usize),
+    SubtractFeeFromRecipient(usize),
+    ToggleAdvancedOptions,
+    OpReturnEdited(String),
+    LocktimeEdited(String),
+    ToggleRbf,
+    ToggleTruc,
     Clear,
 }
 
@@ -7,10 +7,12 @@ pub mod export;
 pub mod fiat;
 pub mod home;
 pub mod hw;
+pub mod privacy;
 pub mod psbt;
 pub mod psbts;
 pub mod receive;
 pub mod recovery;
+pub mod reserves;
 pub mod settings;
 pub mod spend;
 pub mod transactions;
@@ -77,6 +79,8 @@ pub fn sidebar<'a>(
             .push(Menu::Recovery.entry(active, menu_width))
             .push(Menu::Transactions.entry(active, menu_width))
             .push(Menu::Coins.entry(active, menu_width))
+            .push(Menu::Privacy.entry(active, menu_width))
+            .push(Menu::Reserves.entry(active, menu_width))
             .push(Menu::Settings.entry(active, menu_width))
             .push(Space::with_height(10)),
     )
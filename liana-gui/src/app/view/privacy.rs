@@ -0,0 +1,91 @@
+use iced::{Alignment, Length};
+
+use liana_ui::{
+    component::{card, text::*},
+    theme,
+    widget::*,
+};
+
+use crate::{
+    app::menu::Menu,
+    daemon::model::{PrivacyFinding, PrivacyFindingKind},
+};
+
+use super::message::Message;
+
+// The kinds of findings in the order they are displayed, with their section title.
+const SECTIONS: [(PrivacyFindingKind, &str); 4] = [
+    (PrivacyFindingKind::AddressReuse, "Reused addresses"),
+    (
+        PrivacyFindingKind::MergedLabels,
+        "Coins from different sources spent together",
+    ),
+    (
+        PrivacyFindingKind::IdentifiableChange,
+        "Identifiable change",
+    ),
+    (
+        PrivacyFindingKind::KycLinkedCoin,
+        "Coins linked to a KYC source",
+    ),
+];
+
+/// The privacy report of the wallet. `findings` is `None` while it is being computed.
+pub fn privacy_view(findings: Option<&[PrivacyFinding]>) -> Element<'_, Message> {
+    let content: Element<'_, Message> = match findings {
+        None => p1_regular("Analyzing the wallet...")
+            .style(theme::text::secondary)
+            .into(),
+        Some([]) => card::simple(p1_regular(
+            "No privacy leak was found in the history of this wallet.",
+        ))
+        .width(Length::Fill)
+        .into(),
+        Some(findings) => SECTIONS
+            .iter()
+            .filter_map(|(kind, title)| {
+                let mut section = findings.iter().filter(|f| f.kind == *kind).peekable();
+                section.peek()?;
+                Some(
+                    section.fold(
+                        Column::new()
+                            .spacing(10)
+                            .push(Container::new(h4_bold(*title)).width(Length::Fill)),
+                        |col, finding| col.push(finding_view(finding)),
+                    ),
+                )
+            })
+            .fold(Column::new().spacing(25), |col, section| col.push(section))
+            .into(),
+    };
+    Column::new()
+        .push(Container::new(panel_title(Menu::Privacy.title())).width(Length::Fill))
+        .push(
+            p2_regular(
+                "Information the transactions of this wallet reveal to the payees and to \
+                 anyone watching the block chain, along with suggestions to limit it. Coins \
+                 are considered linked to a KYC source when they, or the coins they come \
+                 from, have one of the labels listed as KYC sources in the privacy section of \
+                 the daemon configuration.",
+            )
+            .style(theme::text::secondary),
+        )
+        .push(content)
+        .align_x(Alignment::Center)
+        .spacing(25)
+        .into()
+}
+
+fn finding_view(finding: &PrivacyFinding) -> Element<'_, Message> {
+    card::simple(
+        Column::new()
+            .spacing(5)
+            .push(p1_regular(&finding.description))
+            .push(
+                p2_regular(format!("Suggestion: {}", finding.remediation))
+                    .style(theme::text::secondary),
+            ),
+    )
+    .width(Length::Fill)
+    .into()
+}
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Local, Utc};
 use iced::{widget::Space, Alignment, Length};
 
 use liana_ui::{
@@ -14,7 +15,7 @@ use liana_ui::{
 
 use crate::{
     app::{error::Error, menu::Menu},
-    daemon::model::{SpendStatus, SpendTx},
+    daemon::model::{ScheduledBroadcastEntry, ScheduledBroadcastStatus, SpendStatus, SpendTx},
 };
 
 use super::{message::*, warning::warn};
@@ -65,7 +66,10 @@ pub fn import_psbt_success_view<'a>() -> Element<'a, Message> {
         .into()
 }
 
-pub fn psbts_view(spend_txs: &[SpendTx]) -> Element<'_, Message> {
+pub fn psbts_view<'a>(
+    spend_txs: &'a [SpendTx],
+    scheduled_broadcasts: &'a [ScheduledBroadcastEntry],
+) -> Element<'a, Message> {
     Column::new()
         .push(
             Row::new()
@@ -85,6 +89,18 @@ pub fn psbts_view(spend_txs: &[SpendTx]) -> Element<'_, Message> {
                     }),
             ),
         )
+        .push_maybe(if scheduled_broadcasts.is_empty() {
+            None
+        } else {
+            Some(
+                scheduled_broadcasts.iter().fold(
+                    Column::new()
+                        .spacing(10)
+                        .push(Container::new(h4_bold("Scheduled broadcasts")).width(Length::Fill)),
+                    |col, entry| col.push(scheduled_broadcast_view(entry)),
+                ),
+            )
+        })
         .align_x(Alignment::Center)
         .spacing(25)
         .into()
@@ -168,3 +184,47 @@ fn spend_tx_list_view(i: usize, tx: &SpendTx) -> Element<'_, Message> {
     .style(theme::card::button_simple)
     .into()
 }
+
+fn scheduled_broadcast_view(entry: &ScheduledBroadcastEntry) -> Element<'_, Message> {
+    let status = match entry.status {
+        ScheduledBroadcastStatus::WaitingConfirmation => {
+            "Waiting for the coins it spends to confirm".to_string()
+        }
+        ScheduledBroadcastStatus::Pending => {
+            let mut status = "Pending".to_string();
+            if let Some(height) = entry.valid_at_height {
+                status.push_str(&format!(", valid at block {}", height));
+            }
+            if let Some(time) = entry.valid_at_time {
+                let date = DateTime::<Utc>::from_timestamp(time as i64, 0)
+                    .expect("Correct unix timestamp")
+                    .with_timezone(&Local)
+                    .format("%b. %d, %Y - %T");
+                status.push_str(&format!(", valid after {}", date));
+            }
+            status
+        }
+        ScheduledBroadcastStatus::Ready => "Ready to be broadcast".to_string(),
+        ScheduledBroadcastStatus::Broadcast => "Broadcast, waiting for confirmation".to_string(),
+        ScheduledBroadcastStatus::Confirmed => "Confirmed".to_string(),
+        ScheduledBroadcastStatus::Conflicted => {
+            "Conflicted: a coin it spends was spent by another transaction".to_string()
+        }
+    };
+    card::simple(
+        Column::new()
+            .spacing(5)
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .align_y(Alignment::Center)
+                    .push(Container::new(p2_regular(entry.txid.to_string())).width(Length::Fill))
+                    .push(p2_regular(status).style(theme::text::secondary)),
+            )
+            .push_maybe(entry.last_error.as_ref().map(|e| {
+                p2_regular(format!("Last broadcast attempt failed: {}", e))
+                    .style(theme::text::error)
+            })),
+    )
+    .into()
+}
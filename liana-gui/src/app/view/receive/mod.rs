@@ -1,7 +1,7 @@
 mod modals;
 pub use modals::{
     edit_label_modal, new_address_label_modal, new_address_processing_modal,
-    new_address_show_modal, qr_modal, verify_address_modal,
+    new_address_show_modal, qr_modal, sign_message_modal, verify_address_modal,
 };
 
 use std::collections::HashMap;
@@ -40,6 +40,7 @@ fn address_card<'a>(
         Message::Label(vec![addr.clone()], super::LabelMessage::Edit),
         Message::Clipboard(addr),
         Message::Select(row_index),
+        Message::SignMessage(super::SignMessageMessage::Open(row_index)),
         Message::ShowAddressQrCode(super::AddressQrSource::Row(row_index)),
     )
 }
@@ -2,21 +2,26 @@ use std::collections::HashSet;
 
 use iced::{
     widget::{qr_code, row, Space},
-    Length,
+    Alignment, Length,
 };
 
-use liana::miniscript::bitcoin::{
-    bip32::{ChildNumber, Fingerprint},
-    Address,
+use liana::{
+    descriptors::LianaDescriptor,
+    miniscript::bitcoin::{
+        bip32::{ChildNumber, Fingerprint},
+        Address,
+    },
 };
 
 use liana_ui::{
     component::{
-        button::btn_show_qr_section,
+        address::copyable_address,
+        button::{btn_copy, btn_show_qr_section},
         form, label,
+        list::DeviceStatus,
         modal::{self, modal_no_devices_placeholder, optional_section},
         panels::receive,
-        text::text,
+        text::{p1_bold, p2_regular, text, P1_SIZE},
     },
     widget::*,
 };
@@ -27,9 +32,12 @@ use crate::{
         view::{hw, warning::warn},
     },
     hw::HardwareWallet,
+    view::hw::{device_list_entry, HwRowMode},
 };
 
-use crate::app::view::message::{AddressQrSource, LabelMessage, Message, NewAddressMessage};
+use crate::app::view::message::{
+    AddressQrSource, LabelMessage, Message, NewAddressMessage, SignMessageMessage,
+};
 
 pub fn verify_address_modal<'a>(
     warning: Option<&Error>,
@@ -95,13 +103,129 @@ pub fn verify_address_modal<'a>(
     )
 }
 
-pub fn qr_modal<'a>(qr: &'a qr_code::Data, address: &'a str) -> Element<'a, Message> {
+/// Sign a message (BIP322) with an address, using any of the signers of the primary path.
+#[allow(clippy::too_many_arguments)]
+pub fn sign_message_modal<'a>(
+    warning: Option<&Error>,
+    address: &'a Address,
+    message: &'a form::Value<String>,
+    hws: &'a [HardwareWallet],
+    descriptor: &LianaDescriptor,
+    hot_signer: Option<Fingerprint>,
+    hot_signer_alias: Option<&'a String>,
+    signing: &HashSet<Fingerprint>,
+    signature: Option<&'a String>,
+) -> Element<'a, Message> {
+    let mut devices = Column::new().spacing(10);
+    if hws.is_empty() && hot_signer.is_none() {
+        devices = devices.push(row![
+            Space::fill_width(),
+            modal_no_devices_placeholder(),
+            Space::fill_width()
+        ]);
+    }
+    for (i, hw) in hws.iter().enumerate() {
+        let (signing, can_sign) = hw.fingerprint().map_or((false, false), |f| {
+            (
+                signing.contains(&f),
+                descriptor.contains_fingerprint_in_path(f, None),
+            )
+        });
+        devices = devices.push(device_list_entry(
+            hw,
+            HwRowMode::Signing {
+                signed: false,
+                signing,
+                can_sign,
+            },
+            move || Message::SelectHardwareWallet(i),
+        ));
+    }
+    if let Some(fingerprint) = hot_signer {
+        let status = if !descriptor.contains_fingerprint_in_path(fingerprint, None) {
+            DeviceStatus::NotInPath
+        } else if signing.contains(&fingerprint) {
+            DeviceStatus::Processing
+        } else {
+            DeviceStatus::None
+        };
+        let select_msg = matches!(status, DeviceStatus::None)
+            .then_some(Message::SignMessage(SignMessageMessage::SelectHotSigner));
+        devices = devices.push(modal::device_entry(
+            Some(format!("#{fingerprint}")),
+            None::<&str>,
+            hot_signer_alias,
+            status,
+            select_msg,
+        ));
+    }
+
+    let signature = signature.map(|sig| {
+        Column::new()
+            .push(p1_bold("Signature:"))
+            .push(
+                row![
+                    Container::new(p2_regular(sig)).width(Length::Fill),
+                    btn_copy(Some(Message::Clipboard(sig.clone())))
+                ]
+                .align_y(Alignment::Center)
+                .spacing(12),
+            )
+            .spacing(5)
+    });
+
+    let content = Column::new()
+        .push_maybe(warning.map(|w| warn(Some(w))))
+        .push(copyable_address(
+            address,
+            Message::Clipboard(address.to_string()),
+        ))
+        .push(
+            form::Form::new("Message to sign", message, |s| {
+                Message::SignMessage(SignMessageMessage::MessageEdited(s))
+            })
+            .warning("The message must not be empty.")
+            .size(P1_SIZE)
+            .padding(10),
+        )
+        .push_maybe(signature)
+        .push(text("Select device to sign the message with:").width(Length::Fill))
+        .push(devices)
+        .spacing(20)
+        .width(Length::Fill);
+    modal::modal_view(
+        Some("Sign message"),
+        None,
+        Some(Message::Close),
+        modal::ModalWidth::XL,
+        content,
+    )
+}
+
+/// The QR code of an address. If an `amount` form is given, the QR code encodes a payment URI
+/// requesting this amount.
+pub fn qr_modal<'a>(
+    qr: &'a qr_code::Data,
+    address: &'a str,
+    amount: Option<&'a form::Value<String>>,
+) -> Element<'a, Message> {
+    let amount = amount.map(|amount| {
+        form::Form::new_amount_btc("Amount to request (optional, in BTC)", amount, |s| {
+            Message::QrAmountEdited(s)
+        })
+        .warning("Invalid amount.")
+        .size(P1_SIZE)
+        .padding(10)
+    });
     modal::modal_view(
         Some("Address"),
         None,
         Some(Message::Close),
         modal::ModalWidth::L,
-        receive::modal::qr_display(qr, address),
+        Column::new()
+            .push(receive::modal::qr_display(qr, address))
+            .push(amount)
+            .spacing(15),
     )
 }
 
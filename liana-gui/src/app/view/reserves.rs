@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+
+use iced::{widget::Space, Alignment, Length};
+
+use liana::{
+    descriptors::LianaDescriptor,
+    miniscript::bitcoin::{bip32::Fingerprint, psbt::Psbt},
+};
+
+use liana_ui::{
+    component::{
+        amount::amount,
+        button, card, form,
+        list::DeviceStatus,
+        modal::{self, modal_no_devices_placeholder},
+        text::*,
+    },
+    theme,
+    widget::*,
+};
+
+use crate::{
+    app::menu::Menu,
+    hw::HardwareWallet,
+    view::hw::{device_list_entry, HwRowMode},
+};
+
+use super::message::{Message, ReservesMessage};
+
+/// The proof of reserves panel. Until its PSBT is created, only the challenge message can be
+/// edited. It is then signed with the signers of the primary path before being exported.
+#[allow(clippy::too_many_arguments)]
+pub fn reserves_view<'a>(
+    message: &'a form::Value<String>,
+    psbt: Option<&'a Psbt>,
+    sigs: Option<(usize, usize)>,
+    hws: &'a [HardwareWallet],
+    descriptor: &LianaDescriptor,
+    hot_signer: Option<Fingerprint>,
+    hot_signer_alias: Option<&'a String>,
+    signed: &HashSet<Fingerprint>,
+    signing: &HashSet<Fingerprint>,
+    processing: bool,
+) -> Element<'a, Message> {
+    let content: Element<'a, Message> = match psbt {
+        None => card::simple(
+            Column::new()
+                .spacing(20)
+                .push(
+                    form::Form::new("Challenge message", message, |s| {
+                        Message::Reserves(ReservesMessage::MessageEdited(s))
+                    })
+                    .warning("The message must not be empty.")
+                    .size(P1_SIZE)
+                    .padding(10),
+                )
+                .push(
+                    Row::new().push(Space::with_width(Length::Fill)).push(
+                        button::primary(None, "Create proof").on_press_maybe(
+                            (!processing && !message.value.is_empty())
+                                .then_some(Message::Reserves(ReservesMessage::Create)),
+                        ),
+                    ),
+                ),
+        )
+        .width(Length::Fill)
+        .into(),
+        Some(psbt) => {
+            let (sigs_count, threshold) = sigs.unwrap_or_default();
+            let summary = Column::new()
+                .spacing(10)
+                .push(p1_regular(format!("Challenge message: {}", message.value)))
+                .push(
+                    Row::new()
+                        .spacing(5)
+                        .align_y(Alignment::Center)
+                        .push(p1_regular(format!(
+                            "{} coins worth",
+                            psbt.unsigned_tx.input.len().saturating_sub(1)
+                        )))
+                        .push(amount(&psbt.unsigned_tx.output[0].value)),
+                )
+                .push(
+                    p1_regular(format!("{sigs_count} out of {threshold} signatures"))
+                        .style(theme::text::secondary),
+                );
+            let devices = signers(
+                hws,
+                descriptor,
+                hot_signer,
+                hot_signer_alias,
+                signed,
+                signing,
+            );
+            let buttons = Row::new()
+                .spacing(10)
+                .push(Space::with_width(Length::Fill))
+                .push(
+                    button::secondary(None, "Start over").on_press_maybe(
+                        signing
+                            .is_empty()
+                            .then_some(Message::Reserves(ReservesMessage::Reset)),
+                    ),
+                )
+                .push(
+                    button::primary(None, "Export proof").on_press_maybe(
+                        (!processing && sigs_count >= threshold)
+                            .then_some(Message::Reserves(ReservesMessage::Export)),
+                    ),
+                );
+            card::simple(
+                Column::new()
+                    .spacing(20)
+                    .push(summary)
+                    .push(text("Select device to sign the proof with:").width(Length::Fill))
+                    .push(devices)
+                    .push(buttons),
+            )
+            .width(Length::Fill)
+            .into()
+        }
+    };
+    Column::new()
+        .push(Container::new(panel_title(Menu::Reserves.title())).width(Length::Fill))
+        .push(
+            p2_regular(
+                "Prove the ownership of the confirmed coins of this wallet at the time a \
+                 challenge message was chosen (BIP127). The proof is a transaction spending \
+                 the coins which can never be valid: signing it doesn't put the coins at risk. \
+                 It is signed like a payment, with the keys of the primary spending path, and \
+                 exported to a file to share with the verifier.",
+            )
+            .style(theme::text::secondary),
+        )
+        .push(content)
+        .align_x(Alignment::Center)
+        .spacing(25)
+        .into()
+}
+
+fn signers<'a>(
+    hws: &'a [HardwareWallet],
+    descriptor: &LianaDescriptor,
+    hot_signer: Option<Fingerprint>,
+    hot_signer_alias: Option<&'a String>,
+    signed: &HashSet<Fingerprint>,
+    signing: &HashSet<Fingerprint>,
+) -> Column<'a, Message> {
+    let mut devices = Column::new().spacing(10);
+    if hws.is_empty() && hot_signer.is_none() {
+        devices = devices.push(modal_no_devices_placeholder());
+    }
+    for (i, hw) in hws.iter().enumerate() {
+        let (signed, signing, can_sign) = hw.fingerprint().map_or((false, false, false), |f| {
+            (
+                signed.contains(&f),
+                signing.contains(&f),
+                descriptor.contains_fingerprint_in_path(f, None),
+            )
+        });
+        devices = devices.push(device_list_entry(
+            hw,
+            HwRowMode::Signing {
+                signed,
+                signing,
+                can_sign,
+            },
+            move || Message::SelectHardwareWallet(i),
+        ));
+    }
+    if let Some(fingerprint) = hot_signer {
+        let status = if !descriptor.contains_fingerprint_in_path(fingerprint, None) {
+            DeviceStatus::NotInPath
+        } else if signing.contains(&fingerprint) {
+            DeviceStatus::Processing
+        } else if signed.contains(&fingerprint) {
+            DeviceStatus::Signed
+        } else {
+            DeviceStatus::None
+        };
+        let select_msg = matches!(status, DeviceStatus::None)
+            .then_some(Message::Reserves(ReservesMessage::SelectHotSigner));
+        devices = devices.push(modal::device_entry(
+            Some(format!("#{fingerprint}")),
+            None::<&str>,
+            hot_signer_alias,
+            status,
+            select_msg,
+        ));
+    }
+    devices
+}
@@ -21,7 +21,7 @@ use crate::{
         cache::Cache,
         error::Error,
         menu::Menu,
-        state::{FeeMode, Recipient},
+        state::{AdvancedOptions, FeeMode, Recipient},
         view::{dashboard, message::*, psbt, FiatAmountConverter},
     },
     daemon::model::{remaining_sequence, Coin, SpendTx},
@@ -129,6 +129,7 @@ pub fn create_spend_tx<'a>(
     feerate: &form::Value<String>,
     fee_mode: FeeMode,
     fee_amount: Option<&Amount>,
+    advanced_options: &'a AdvancedOptions,
     error: Option<&'a Error>,
     is_first_step: bool,
     max_under_dust: bool,
@@ -227,6 +228,22 @@ pub fn create_spend_tx<'a>(
         .collect();
     let coin_selection = spend::coin_selection(coin_rows);
 
+    // A recovery transaction is always created with the default options.
+    let advanced = recovery_timelock.is_none().then(|| {
+        spend::advanced_options(
+            advanced_options.expanded,
+            &advanced_options.op_return,
+            &advanced_options.locktime,
+            advanced_options.signal_rbf,
+            advanced_options.truc,
+            Message::CreateSpend(CreateSpendMessage::ToggleAdvancedOptions),
+            |s| Message::CreateSpend(CreateSpendMessage::OpReturnEdited(s)),
+            |s| Message::CreateSpend(CreateSpendMessage::LocktimeEdited(s)),
+            Message::CreateSpend(CreateSpendMessage::ToggleRbf),
+            Message::CreateSpend(CreateSpendMessage::ToggleTruc),
+        )
+    });
+
     let previous = (!is_first_step).then_some(button::btn_previous(Some(Message::Previous)));
     let clear = button::btn_clear(Some(Message::CreateSpend(CreateSpendMessage::Clear)));
     // Single source of truth for whether the spend can proceed: the same blocker
@@ -236,6 +253,7 @@ pub fn create_spend_tx<'a>(
         recipients,
         batch_label,
         feerate,
+        advanced_options,
         amount_left,
         coins.iter().any(|(_, selected)| *selected),
         max_under_dust,
@@ -277,6 +295,7 @@ pub fn create_spend_tx<'a>(
         add_payment_row,
         fee_rate_row,
         coin_selection,
+        advanced,
         bottom_row,
         next_reason,
         Space::with_height(Length::Fixed(20.0)),
@@ -305,6 +324,7 @@ fn next_disabled_reason(
     recipients: &[Recipient],
     batch_label: &form::Value<String>,
     feerate: &form::Value<String>,
+    advanced_options: &AdvancedOptions,
     amount_left: Option<&Amount>,
     any_coin_selected: bool,
     max_under_dust: bool,
@@ -330,6 +350,8 @@ fn next_disabled_reason(
         }))
     } else if empty_or_invalid(feerate) {
         Some(NextBlocker::Reason("The feerate is missing or invalid"))
+    } else if !advanced_options.op_return.valid || !advanced_options.locktime.valid {
+        Some(NextBlocker::Reason("An advanced option is invalid"))
     } else if !any_coin_selected {
         Some(NextBlocker::Reason("Select at least one coin"))
     } else if !is_self_send
@@ -351,6 +373,7 @@ pub fn recipient_view<'a>(
     fiat_converter: Option<&FiatAmountConverter>,
     label: &'a form::Value<String>,
     is_max_selected: bool,
+    is_subtract_fee_selected: bool,
     is_recovery: bool,
     can_delete: bool,
     dust_warning: &'a Option<String>,
@@ -370,6 +393,8 @@ pub fn recipient_view<'a>(
     });
 
     let on_max = (!is_recovery).then_some(CreateSpendMessage::SendMaxToRecipient(index));
+    let on_subtract_fee = (!is_recovery && !is_max_selected)
+        .then_some(CreateSpendMessage::SubtractFeeFromRecipient(index));
     let on_delete =
         (can_delete && !is_recovery).then_some(CreateSpendMessage::DeleteRecipient(index));
 
@@ -379,12 +404,14 @@ pub fn recipient_view<'a>(
         amount,
         fiat,
         is_max_selected,
+        is_subtract_fee_selected,
         dust_warning.as_deref(),
         max_estimated_amount,
         move |msg| CreateSpendMessage::RecipientEdited(index, "address", msg.trim().to_string()),
         move |msg| CreateSpendMessage::RecipientEdited(index, "label", msg),
         move |msg| CreateSpendMessage::RecipientEdited(index, "amount", msg),
         on_max,
+        on_subtract_fee,
         on_delete,
     )
 }
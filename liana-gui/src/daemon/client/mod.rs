@@ -14,7 +14,7 @@ pub mod error;
 pub mod jsonrpc;
 
 use liana::miniscript::bitcoin::{
-    address, bip32::ChildNumber, psbt::Psbt, Address, Network, OutPoint, Txid,
+    address, bip32::ChildNumber, hex::DisplayHex, psbt::Psbt, Address, Network, OutPoint, Txid,
 };
 use lianad::{
     commands::{CoinStatus, CreateRecoveryResult, LabelItem},
@@ -133,19 +133,77 @@ impl<C: Client + Send + Sync + Debug> Daemon for Lianad<C> {
         self.call("listspendtxs", Option::<Request>::None)
     }
 
+    async fn get_balance(&self, window: u32) -> Result<GetBalanceResult, DaemonError> {
+        self.call("getbalance", Some(vec![json!(window)]))
+    }
+
+    async fn list_scheduled_broadcasts(
+        &self,
+    ) -> Result<ListScheduledBroadcastsResult, DaemonError> {
+        self.call("listscheduledbroadcasts", Option::<Request>::None)
+    }
+
+    async fn privacy_report(&self) -> Result<PrivacyReportResult, DaemonError> {
+        self.call("privacyreport", Option::<Request>::None)
+    }
+
+    async fn release_coins(&self, outpoints: &[OutPoint]) -> Result<(), DaemonError> {
+        let _res: serde_json::value::Value =
+            self.call("releasecoins", Some(vec![json!(outpoints)]))?;
+        Ok(())
+    }
+
+    async fn create_reserves_proof(
+        &self,
+        message: &str,
+        outpoints: &[OutPoint],
+    ) -> Result<CreateReservesProofResult, DaemonError> {
+        self.call(
+            "createreservesproof",
+            Some(vec![json!(message), json!(outpoints)]),
+        )
+    }
+
+    async fn finalize_reserves_proof(
+        &self,
+        psbt: &Psbt,
+        message: &str,
+    ) -> Result<ReservesProofResult, DaemonError> {
+        self.call(
+            "finalizereservesproof",
+            Some(vec![psbt.to_string(), message.to_string()]),
+        )
+    }
+
     async fn create_spend_tx(
         &self,
         coins_outpoints: &[OutPoint],
         destinations: &HashMap<Address<address::NetworkUnchecked>, u64>,
         feerate_vb: u64,
         change_address: Option<Address<address::NetworkUnchecked>>,
+        subtract_fee_from: &[Address<address::NetworkUnchecked>],
+        options: &CreateSpendOptions,
     ) -> Result<CreateSpendResult, DaemonError> {
         let mut input = vec![
             json!(destinations),
             json!(coins_outpoints),
             json!(feerate_vb),
         ];
-        if let Some(change_address) = change_address {
+        if *options != CreateSpendOptions::default() {
+            input.push(json!(change_address));
+            input.push(json!(subtract_fee_from));
+            input.push(json!(options
+                .spend
+                .op_return_data
+                .as_ref()
+                .map(|data| data.to_lower_hex_string())));
+            input.push(json!(options.locktime.map(|lt| lt.to_consensus_u32())));
+            input.push(json!(options.spend.signal_rbf));
+            input.push(json!(options.spend.truc));
+        } else if !subtract_fee_from.is_empty() {
+            input.push(json!(change_address));
+            input.push(json!(subtract_fee_from));
+        } else if let Some(change_address) = change_address {
             input.push(json!(change_address));
         }
         self.call("createspend", Some(input))
@@ -21,7 +21,12 @@ pub struct EmbeddedDaemon {
 }
 
 impl EmbeddedDaemon {
-    pub fn start(config: Config) -> Result<EmbeddedDaemon, DaemonError> {
+    pub fn start(mut config: Config) -> Result<EmbeddedDaemon, DaemonError> {
+        // The proxy set in the GUI settings applies to the daemon too, unless its own
+        // configuration already sets one.
+        if config.proxy.is_none() {
+            config.proxy = crate::services::http::proxy();
+        }
         let handle =
             DaemonHandle::start_default(config.clone(), false).map_err(DaemonError::Start)?;
         Ok(Self {
@@ -151,6 +156,56 @@ impl Daemon for EmbeddedDaemon {
         .await
     }
 
+    async fn get_balance(&self, window: u32) -> Result<GetBalanceResult, DaemonError> {
+        self.command(|daemon| Ok(daemon.get_balance(window))).await
+    }
+
+    async fn list_scheduled_broadcasts(
+        &self,
+    ) -> Result<ListScheduledBroadcastsResult, DaemonError> {
+        self.command(|daemon| Ok(daemon.list_scheduled_broadcasts()))
+            .await
+    }
+
+    async fn privacy_report(&self) -> Result<PrivacyReportResult, DaemonError> {
+        self.command(|daemon| Ok(daemon.privacy_report())).await
+    }
+
+    async fn release_coins(&self, outpoints: &[OutPoint]) -> Result<(), DaemonError> {
+        self.command(|daemon| {
+            daemon
+                .release_coins(outpoints)
+                .map_err(|e| DaemonError::Unexpected(e.to_string()))
+        })
+        .await
+    }
+
+    async fn create_reserves_proof(
+        &self,
+        message: &str,
+        outpoints: &[OutPoint],
+    ) -> Result<CreateReservesProofResult, DaemonError> {
+        self.command(|daemon| {
+            daemon
+                .create_reserves_proof(message, outpoints)
+                .map_err(|e| DaemonError::Unexpected(e.to_string()))
+        })
+        .await
+    }
+
+    async fn finalize_reserves_proof(
+        &self,
+        psbt: &Psbt,
+        message: &str,
+    ) -> Result<ReservesProofResult, DaemonError> {
+        self.command(|daemon| {
+            daemon
+                .finalize_reserves_proof(psbt.clone(), message)
+                .map_err(|e| DaemonError::Unexpected(e.to_string()))
+        })
+        .await
+    }
+
     async fn list_confirmed_txs(
         &self,
         start: u32,
@@ -172,10 +227,20 @@ impl Daemon for EmbeddedDaemon {
         destinations: &HashMap<Address<address::NetworkUnchecked>, u64>,
         feerate_vb: u64,
         change_address: Option<Address<address::NetworkUnchecked>>,
+        subtract_fee_from: &[Address<address::NetworkUnchecked>],
+        options: &CreateSpendOptions,
     ) -> Result<CreateSpendResult, DaemonError> {
         self.command(|daemon| {
             daemon
-                .create_spend(destinations, coins_outpoints, feerate_vb, change_address)
+                .create_spend_with_options(
+                    destinations,
+                    coins_outpoints,
+                    feerate_vb,
+                    change_address,
+                    None,
+                    subtract_fee_from,
+                    options,
+                )
                 .map_err(|e| DaemonError::Unexpected(e.to_string()))
         })
         .await
@@ -14,7 +14,7 @@ use liana::miniscript::bitcoin::{
     address,
     bip32::{ChildNumber, Fingerprint},
     psbt::Psbt,
-    secp256k1, Address, Network, OutPoint, Txid,
+    secp256k1, Address, Amount, Network, OutPoint, Txid,
 };
 use lianad::bip329::Labels;
 use lianad::commands::UpdateDerivIndexesResult;
@@ -159,6 +159,8 @@ pub trait Daemon: Debug {
         destinations: &HashMap<Address<address::NetworkUnchecked>, u64>,
         feerate_vb: u64,
         change_address: Option<Address<address::NetworkUnchecked>>,
+        subtract_fee_from: &[Address<address::NetworkUnchecked>],
+        options: &CreateSpendOptions,
     ) -> Result<model::CreateSpendResult, DaemonError>;
     async fn rbf_psbt(
         &self,
@@ -214,6 +216,87 @@ pub trait Daemon: Debug {
         Err(DaemonError::NotImplemented)
     }
 
+    /// Get the balance of the wallet, by status. Backends without a `getbalance` command compute
+    /// it from the coins and the stored Spend transactions with the same definitions, but don't
+    /// report the balance of the recovery paths.
+    async fn get_balance(&self, window: u32) -> Result<model::GetBalanceResult, DaemonError> {
+        let coins = self
+            .list_coins(
+                &[
+                    CoinStatus::Unconfirmed,
+                    CoinStatus::Confirmed,
+                    CoinStatus::Spending,
+                ],
+                &[],
+            )
+            .await?
+            .coins;
+        let drafts_inputs: HashSet<OutPoint> = self
+            .list_spend_txs()
+            .await?
+            .spend_txs
+            .into_iter()
+            .flat_map(|entry| entry.psbt.unsigned_tx.input.into_iter())
+            .map(|txin| txin.previous_output)
+            .collect();
+        let mut res = model::GetBalanceResult {
+            confirmed: Amount::ZERO,
+            unconfirmed: Amount::ZERO,
+            immature: Amount::ZERO,
+            spending: Amount::ZERO,
+            window,
+            recovery_paths: Vec::new(),
+        };
+        for coin in coins {
+            if coin.spend_info.is_some() || drafts_inputs.contains(&coin.outpoint) {
+                res.spending += coin.amount;
+            } else if coin.is_immature {
+                res.immature += coin.amount;
+            } else if coin.block_height.is_some() {
+                res.confirmed += coin.amount;
+            } else {
+                res.unconfirmed += coin.amount;
+            }
+        }
+        Ok(res)
+    }
+
+    /// List the transactions scheduled for broadcast once they become valid.
+    async fn list_scheduled_broadcasts(
+        &self,
+    ) -> Result<model::ListScheduledBroadcastsResult, DaemonError> {
+        Err(DaemonError::NotImplemented)
+    }
+
+    /// Analyze the coins and transactions of the wallet for privacy leaks.
+    async fn privacy_report(&self) -> Result<model::PrivacyReportResult, DaemonError> {
+        Err(DaemonError::NotImplemented)
+    }
+
+    /// Release coins quarantined as a likely dust attack.
+    async fn release_coins(&self, _outpoints: &[OutPoint]) -> Result<(), DaemonError> {
+        Err(DaemonError::NotImplemented)
+    }
+
+    /// Create the PSBT of a proof of reserves committing to this message, for the given coins or
+    /// all the confirmed coins if none is given.
+    async fn create_reserves_proof(
+        &self,
+        _message: &str,
+        _outpoints: &[OutPoint],
+    ) -> Result<model::CreateReservesProofResult, DaemonError> {
+        Err(DaemonError::NotImplemented)
+    }
+
+    /// Finalize the signed PSBT of a proof of reserves into the proof to be shared.
+    async fn finalize_reserves_proof(
+        &self,
+        _psbt: &Psbt,
+        _message: &str,
+    ) -> Result<model::ReservesProofResult, DaemonError> {
+        Err(DaemonError::NotImplemented)
+    }
+
     // List spend transactions, optionally filtered to the specified `txids`.
     // Set `txids` to `None` for no filter (passing an empty slice returns no transactions).
     async fn list_spend_transactions(
@@ -12,9 +12,12 @@ pub use liana::{
 };
 use liana_ui::component::panels::home::payment::PaymentKind;
 pub use lianad::commands::{
-    CreateSpendResult, GetAddressResult, GetInfoResult, GetLabelsResult, LabelItem, ListCoinsEntry,
-    ListCoinsResult, ListRevealedAddressesEntry, ListRevealedAddressesResult, ListSpendEntry,
-    ListSpendResult, ListTransactionsResult, TransactionInfo,
+    CreateReservesProofResult, CreateSpendOptions, CreateSpendResult, GetAddressResult,
+    GetBalanceResult, GetInfoResult, GetLabelsResult, LabelItem, ListCoinsEntry, ListCoinsResult,
+    ListRevealedAddressesEntry, ListRevealedAddressesResult, ListScheduledBroadcastsResult,
+    ListSpendEntry, ListSpendResult, ListTransactionsResult, PrivacyFinding, PrivacyFindingKind,
+    PrivacyReportResult, ReservesProofResult, ScheduledBroadcastEntry, ScheduledBroadcastStatus,
+    TransactionInfo,
 };
 
 pub type Coin = ListCoinsEntry;
@@ -269,9 +272,10 @@ impl Labelled for SpendTx {
                 txid,
                 vout: vout as u32,
             }));
-            items.push(LabelItem::Address(
-                Address::from_script(&output.script_pubkey, self.network).unwrap(),
-            ));
+            // A data output has no address.
+            if let Ok(address) = Address::from_script(&output.script_pubkey, self.network) {
+                items.push(LabelItem::Address(address));
+            }
         }
         items
     }
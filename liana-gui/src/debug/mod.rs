@@ -170,6 +170,8 @@ pub static PSBTS_MENU: Menu = Menu::PSBTs;
 pub static RECOVERY_MENU: Menu = Menu::Recovery;
 pub static TRANSACTIONS_MENU: Menu = Menu::Transactions;
 pub static COINS_MENU: Menu = Menu::Coins;
+pub static PRIVACY_MENU: Menu = Menu::Privacy;
+pub static RESERVES_MENU: Menu = Menu::Reserves;
 pub static SETTINGS_MENU: Menu = Menu::Settings;
 
 /// `Cache` contains a `Cell<Size>` (mutated by `dashboard`'s responsive
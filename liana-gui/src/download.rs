@@ -22,7 +22,7 @@ fn download(url: String) -> impl Stream<Item = Result<Progress, DownloadError>>
     try_channel(
         100,
         move |mut output: iced::futures::channel::mpsc::Sender<Progress>| async move {
-            let response = reqwest::get(&url).await?;
+            let response = crate::services::http::client().get(&url).send().await?;
             let total = response.content_length();
 
             let _ = output.send(Progress::Downloading(0.0)).await;
@@ -15,9 +15,14 @@ use tokio::sync::mpsc::{channel, unbounded_channel, Sender, UnboundedReceiver, U
 use async_hwi::bitbox::api::btc::Fingerprint;
 use chrono::{DateTime, Duration, Utc};
 use liana::{
-    descriptors::{bip341_nums, LianaDescriptor},
+    bsms,
+    descriptors::{self, bip341_nums, LianaDescriptor},
     miniscript::{
-        bitcoin::{Amount, Network, Psbt, Txid},
+        bitcoin::{
+            bip32::{DerivationPath, Xpub},
+            secp256k1, Amount, Network, Psbt, Txid,
+        },
+        descriptor::{DescriptorXKey, Wildcard},
         DescriptorPublicKey,
     },
 };
@@ -120,7 +125,7 @@ pub enum Error {
     TxTimeMissing,
     DaemonMissing,
     ParsePsbt,
-    ParseDescriptor,
+    ParseDescriptor(String),
     Bip329Export(String),
     BackupImport(String),
     Backup(backup::Error),
@@ -132,6 +137,7 @@ pub enum Error {
     InsanePsbt,
     OutpointNotOwned,
     UnknownFormat,
+    Bsms(String),
 }
 
 impl Display for Error {
@@ -147,7 +153,7 @@ impl Display for Error {
             Error::TxTimeMissing => write!(f, "ImportExport: transaction block height missing"),
             Error::DaemonMissing => write!(f, "ImportExport: the daemon is missing"),
             Error::ParsePsbt => write!(f, "ImportExport: fail to parse PSBT"),
-            Error::ParseDescriptor => write!(f, "ImportExport: fail to parse descriptor"),
+            Error::ParseDescriptor(e) => write!(f, "ImportExport: fail to parse descriptor: {e}"),
             Error::Bip329Export(e) => write!(f, "Bip329Export: {e}"),
             Error::BackupImport(e) => write!(f, "BackupImport: {e}"),
             Error::Backup(e) => write!(f, "Backup: {e}"),
@@ -161,6 +167,7 @@ impl Display for Error {
             ),
             Error::EncryptedBackup(e) => write!(f, "Failed to encrypt backup: {e:?}"),
             Error::UnknownFormat => write!(f, "Format of the file unknown"),
+            Error::Bsms(e) => write!(f, "BSMS: {e}"),
             Error::EncryptionFailed => write!(f, "Encryption failed, please contact Wizarsardine team.")
         }
     }
@@ -186,6 +193,9 @@ pub enum ImportExportType {
     ImportPsbt(Option<Txid>),
     ImportXpub(Network),
     ImportDescriptor,
+    ExportBsmsDescriptorRecord(String),
+    /// A proof of reserves, as the JSON object returned by the daemon.
+    ExportReservesProof(String),
 }
 
 impl ImportExportType {
@@ -197,6 +207,8 @@ impl ImportExportType {
             | ImportExportType::ExportProcessBackup(..)
             | ImportExportType::ExportXpub(_)
             | ImportExportType::ExportEncryptedDescriptor(_)
+            | ImportExportType::ExportBsmsDescriptorRecord(_)
+            | ImportExportType::ExportReservesProof(_)
             | ImportExportType::ExportLabels => "Export successful!",
             ImportExportType::ImportBackup { .. }
             | ImportExportType::ImportPsbt(_)
@@ -315,6 +327,12 @@ impl Export {
                 export_encrypted_descriptor(&sender, path, *descr).await
             }
             ImportExportType::ExportXpub(xpub_str) => export_string(&sender, path, xpub_str).await,
+            ImportExportType::ExportBsmsDescriptorRecord(record) => {
+                export_string(&sender, path, record).await
+            }
+            ImportExportType::ExportReservesProof(proof) => {
+                export_string(&sender, path, proof).await
+            }
             ImportExportType::ExportProcessBackup(datadir, network, config, wallet) => {
                 app_backup_export(
                     datadir,
@@ -718,7 +736,13 @@ pub async fn import_descriptor(
             }
         })
     };
-    let descriptor = descriptor.ok_or(Error::ParseDescriptor)?;
+    // Also accept the wallet configurations of other coordinators, including BSMS descriptor
+    // records.
+    let descriptor = match descriptor {
+        Some(descriptor) => descriptor,
+        None => descriptors::import_descriptor(descr_str)
+            .map_err(|e| Error::ParseDescriptor(e.to_string()))?,
+    };
 
     send_progress!(sender, Progress(100.0));
     send_progress!(sender, Descriptor(descriptor));
@@ -736,16 +760,18 @@ pub async fn import_xpub(
     file.read_to_string(&mut xpub_str)?;
     let xpub_str = xpub_str.trim().to_string();
 
-    let (descriptor_pubkey, key) =
-        if let Some(DescriptorPublicKey::XPub(key)) = parse_raw_xpub(&xpub_str) {
-            (DescriptorPublicKey::XPub(key.clone()), key)
-        } else if let Some(DescriptorPublicKey::XPub(key)) = parse_coldcard_xpub_json(&xpub_str) {
-            (DescriptorPublicKey::XPub(key.clone()), key)
-        } else if let Some(DescriptorPublicKey::XPub(key)) = parse_coldcard_xpub_ccxp(&xpub_str) {
-            (DescriptorPublicKey::XPub(key.clone()), key)
-        } else {
-            return Err(Error::ParseXpub);
-        };
+    let (descriptor_pubkey, key) = if xpub_str.starts_with(bsms::BSMS_VERSION) {
+        let key = parse_bsms_key_record(&xpub_str)?;
+        (DescriptorPublicKey::XPub(key.clone()), key)
+    } else if let Some(DescriptorPublicKey::XPub(key)) = parse_raw_xpub(&xpub_str) {
+        (DescriptorPublicKey::XPub(key.clone()), key)
+    } else if let Some(DescriptorPublicKey::XPub(key)) = parse_coldcard_xpub_json(&xpub_str) {
+        (DescriptorPublicKey::XPub(key.clone()), key)
+    } else if let Some(DescriptorPublicKey::XPub(key)) = parse_coldcard_xpub_ccxp(&xpub_str) {
+        (DescriptorPublicKey::XPub(key.clone()), key)
+    } else {
+        return Err(Error::ParseXpub);
+    };
     let xpub_str = descriptor_pubkey.to_string();
 
     let valid = if network == Network::Bitcoin {
@@ -767,6 +793,22 @@ pub fn parse_raw_xpub(raw_xpub: &str) -> Option<DescriptorPublicKey> {
     DescriptorPublicKey::from_str(raw_xpub).ok()
 }
 
+// Parse a BIP129 key record, as produced by a signer taking part in a BSMS setup, and check it was
+// signed by the key it contains. We don't hold the session token here, so records are accepted
+// whatever session they were created for.
+pub fn parse_bsms_key_record(record: &str) -> Result<DescriptorXKey<Xpub>, Error> {
+    let record = bsms::KeyRecord::from_str(record).map_err(|e| Error::Bsms(e.to_string()))?;
+    record
+        .verify_signature(&secp256k1::Secp256k1::verification_only())
+        .map_err(|e| Error::Bsms(e.to_string()))?;
+    Ok(DescriptorXKey {
+        origin: Some((record.fingerprint, record.derivation_path)),
+        xkey: record.xpub,
+        derivation_path: DerivationPath::master(),
+        wildcard: Wildcard::None,
+    })
+}
+
 // NOTE: this function is intended to import xpub that have been exported from a coldcard device
 // via this menu: Advanced/Tools => Export Wallet => Generic JSON (Any Edge firmware)
 pub fn parse_coldcard_xpub_json(coldcard_xpub: &str) -> Option<DescriptorPublicKey> {
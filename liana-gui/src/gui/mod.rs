@@ -171,6 +171,16 @@ where
         if let Err(e) = setup_logger(log_level, config.liana_directory.clone()) {
             tracing::warn!("Error while setting error: {}", e);
         }
+        // Set the proxy before any connection is made.
+        match GlobalSettings::load_proxy(&GlobalSettings::path(&config.liana_directory)) {
+            Ok(proxy) => {
+                if let Some(proxy) = proxy.as_ref() {
+                    info!("Using proxy at {} for all remote connections", proxy.addr);
+                }
+                crate::services::http::set_proxy(proxy);
+            }
+            Err(e) => error!("Failed to load proxy settings: {e}"),
+        }
         let mut cmds = vec![
             window::oldest().map(Message::Window),
             Task::perform(ctrl_c(), |_| Message::CtrlC),
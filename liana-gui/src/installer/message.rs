@@ -67,6 +67,7 @@ pub enum Message {
     BackupDescriptor,
     ShowBackupDescriptorHelp(bool),
     ExportEncryptedDescriptor(Result<Box<LianaDescriptor>, encrypted_backup::Error>),
+    ExportBsmsDescriptorRecord,
     ExportXpub(String),
     ImportExport(ImportExportMessage),
     ImportBackup,
@@ -967,10 +967,12 @@ pub fn extract_daemon_config(ctx: &Context, settings: &WalletSettings) -> Result
         .to_path_buf()
         .canonicalize()
         .map_err(|e| Error::Unexpected(format!("Failed to canonicalize datadir path: {e}")))?;
-    let bitcoin_backend = if let Some(BitcoinBackend::Bitcoind(BitcoindConfig {
-        rpc_auth: BitcoindRpcAuth::CookieFile(cookie_path),
-        addr,
-    })) = &ctx.bitcoin_backend
+    let bitcoin_backend = if let Some(BitcoinBackend::Bitcoind(
+        bitcoind_config @ BitcoindConfig {
+            rpc_auth: BitcoindRpcAuth::CookieFile(cookie_path),
+            ..
+        },
+    )) = &ctx.bitcoin_backend
     {
         // The cookie path must exist for this canonicalization to succeed, which means bitcoind must be running.
         // We already checked in the installer that bitcoind is running.
@@ -979,12 +981,12 @@ pub fn extract_daemon_config(ctx: &Context, settings: &WalletSettings) -> Result
             .map_err(|e| Error::Unexpected(format!("Failed to canonicalize cookie path: {e}")))?;
         Some(BitcoinBackend::Bitcoind(BitcoindConfig {
             rpc_auth: BitcoindRpcAuth::CookieFile(cookie_path),
-            addr: *addr,
+            ..bitcoind_config.clone()
         }))
     } else {
         ctx.bitcoin_backend.clone()
     };
-    Ok(Config::new(
+    let mut config = Config::new(
         ctx.bitcoin_config.clone(),
         bitcoin_backend,
         log::LevelFilter::Info,
@@ -992,7 +994,9 @@ pub fn extract_daemon_config(ctx: &Context, settings: &WalletSettings) -> Result
             .clone()
             .expect("Context must have a descriptor at this point"),
         lianad::datadir::DataDirectory::new(data_directory),
-    ))
+    );
+    config.proxy = crate::services::http::proxy();
+    Ok(config)
 }
 
 #[derive(Debug, Clone)]
@@ -446,7 +446,8 @@ impl SelectKeySource {
         let url = (self.network != Network::Bitcoin)
             .then_some(std::env::var("LIANA_KEYS_SIGNET_API_URL").ok())
             .flatten();
-        let client = keys::Client::new_with_optional_url(url.as_deref(), &ua);
+        let client = keys::Client::new_with_optional_url(url.as_deref(), &ua)
+            .with_http_client(crate::services::http::client());
         Task::perform(
             async move { (token.clone(), client.get_key_by_token(token).await) },
             |(token, res)| {
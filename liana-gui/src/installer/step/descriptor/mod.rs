@@ -1,14 +1,12 @@
 pub mod editor;
 
-use std::{
-    collections::{HashMap, HashSet},
-    str::FromStr,
-};
+use std::collections::{HashMap, HashSet};
 
 use iced::{Subscription, Task};
 use liana::{
-    descriptors::LianaDescriptor,
-    miniscript::bitcoin::{bip32::Fingerprint, Network},
+    bsms,
+    descriptors::{self, LianaDescriptor},
+    miniscript::bitcoin::{bip32::Fingerprint, secp256k1, Network},
 };
 
 use liana_ui::{component::form, widget::Element};
@@ -33,6 +31,8 @@ pub struct ImportDescriptor {
     network: Network,
     wrong_network: bool,
     error: Option<String>,
+    /// Why the imported descriptor couldn't be converted to a Liana descriptor.
+    import_error: Option<String>,
     modal: ImportDescriptorModal,
     imported_descriptor: form::Value<String>,
     imported_backup: Option<Backup>,
@@ -47,6 +47,7 @@ impl ImportDescriptor {
             imported_descriptor: form::Value::default(),
             wrong_network: false,
             error: None,
+            import_error: None,
             modal: ImportDescriptorModal::None,
             imported_backup: None,
             imported_aliases: None,
@@ -55,8 +56,11 @@ impl ImportDescriptor {
     }
 
     fn check_descriptor(&mut self, network: Network) -> Option<LianaDescriptor> {
+        self.import_error = None;
         if !self.imported_descriptor.value.is_empty() {
-            if let Ok(desc) = LianaDescriptor::from_str(&self.imported_descriptor.value) {
+            // Also accept wallet configurations exported by other coordinators.
+            let imported = descriptors::import_descriptor(&self.imported_descriptor.value);
+            if let Ok(desc) = imported {
                 if network == Network::Bitcoin {
                     self.imported_descriptor.valid = desc.all_xpubs_net_is(network);
                 } else {
@@ -72,6 +76,7 @@ impl ImportDescriptor {
             } else {
                 self.imported_descriptor.valid = false;
                 self.wrong_network = false;
+                self.import_error = imported.err().map(|e| e.to_string());
                 None
             }
         } else {
@@ -244,7 +249,7 @@ impl Step for ImportDescriptor {
             &self.imported_descriptor,
             self.imported_backup.is_some(),
             self.wrong_network,
-            self.error.as_ref(),
+            self.error.as_ref().or(self.import_error.as_ref()),
             self.paste_descriptor_expanded,
         );
         self.modal.view(content)
@@ -490,6 +495,24 @@ impl Step for BackupDescriptor {
                     return launch;
                 }
             }
+            Message::ExportBsmsDescriptorRecord => {
+                if let (None, Some(ctx)) = (&self.modal, self.context.as_ref()) {
+                    if let Some(descriptor) = &ctx.descriptor {
+                        let record = bsms::descriptor_record(
+                            descriptor,
+                            ctx.network,
+                            &secp256k1::Secp256k1::verification_only(),
+                        );
+                        let modal = ExportModal::new(
+                            None,
+                            ImportExportType::ExportBsmsDescriptorRecord(record),
+                        );
+                        let launch = modal.launch(true);
+                        self.modal = Some(modal);
+                        return launch;
+                    }
+                }
+            }
             Message::UserActionDone(done) => {
                 self.done = done;
             }
@@ -126,7 +126,8 @@ impl Step for Final {
                         .then_some(std::env::var("LIANA_KEYS_SIGNET_API_URL").ok())
                         .flatten();
                     let client =
-                        liana_connect::keys::Client::new_with_optional_url(url.as_deref(), &ua);
+                        liana_connect::keys::Client::new_with_optional_url(url.as_deref(), &ua)
+                            .with_http_client(crate::services::http::client());
                     let pk = pk.clone();
                     return Task::perform(
                         async move { (pk.clone(), client.redeem_key(pk.uuid, pk.token).await) },
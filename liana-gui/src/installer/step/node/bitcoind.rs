@@ -360,6 +360,14 @@ impl DefineBitcoind {
     }
 
     pub fn ping(&self) -> Result<(), Error> {
+        // Like the daemon, never connect directly to a remote bitcoind if a proxy is set.
+        if crate::services::http::proxy().is_some_and(|p| p.applies_to_url(&self.address.value)) {
+            return Err(Error::Bitcoind(
+                "A proxy is set but bitcoind's RPC can't go through it. Use a bitcoind running \
+                 on this machine."
+                    .to_string(),
+            ));
+        }
         let rpc_auth_vals = self.rpc_auth_vals.clone();
         let builder = match self.selected_auth_type {
             RpcAuthType::CookieFile => {
@@ -476,6 +484,8 @@ impl DefineBitcoind {
                     Some(lianad::config::BitcoinBackend::Bitcoind(BitcoindConfig {
                         rpc_auth,
                         addr,
+                        zmqpubrawblock: None,
+                        zmqpubrawtx: None,
                     }));
                 true
             }
@@ -625,6 +635,8 @@ impl Step for InternalBitcoindStep {
                     let bitcoind_config = BitcoindConfig {
                         rpc_auth: cookie_file_auth,
                         addr: internal_bitcoind_address(rpc_port),
+                        zmqpubrawblock: None,
+                        zmqpubrawtx: None,
                     };
                     // Use existing network conf if it exists as it may have rpc_auth field set.
                     // This ensures an existing wallet using username/password authentication will continue to work.
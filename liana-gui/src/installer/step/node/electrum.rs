@@ -59,6 +59,8 @@ impl DefineElectrum {
             ctx.bitcoin_backend = Some(lianad::config::BitcoinBackend::Electrum(ElectrumConfig {
                 addr: self.address.value.clone(),
                 validate_domain: self.validate_domain,
+                fallback_addrs: Vec::new(),
+                cross_check: false,
             }));
             return true;
         }
@@ -70,10 +72,15 @@ impl DefineElectrum {
     }
 
     pub fn ping(&self) -> Result<(), Error> {
+        let socks5 = crate::services::http::proxy()
+            .filter(|proxy| proxy.applies_to_url(&self.address.value))
+            .map(|proxy| electrum_client::Socks5Config::new(proxy.addr));
         let builder = electrum_client::Config::builder();
         let config = builder
-            .timeout(Some(3))
+            // Going through the proxy is much slower to establish a connection (Tor).
+            .timeout(Some(if socks5.is_some() { 30 } else { 3 }))
             .validate_domain(self.validate_domain)
+            .socks5(socks5)
             .build();
         let client = electrum_client::Client::from_config(&self.address.value, config)
             .map_err(|e| Error::Electrum(e.to_string()))?;
@@ -30,6 +30,9 @@ impl NodeDefinition {
         match node_type {
             NodeType::Bitcoind => NodeDefinition::Bitcoind(DefineBitcoind::new()),
             NodeType::Electrum => NodeDefinition::Electrum(DefineElectrum::new()),
+            NodeType::Esplora | NodeType::Cbf => {
+                unreachable!("This backend can only be set up by editing the configuration file.")
+            }
         }
     }
 
@@ -27,8 +27,9 @@ use liana_ui::{
         badge::Tile,
         button::{
             self, btn_accept, btn_backend_options_help, btn_backup_descriptor, btn_change_email,
-            btn_check_connection, btn_connect_another_email, btn_mnemonic_word, btn_next,
-            btn_resend_token, btn_select, btn_send_token, btn_skip, EntryWidth,
+            btn_check_connection, btn_connect_another_email, btn_export_bsms_record,
+            btn_mnemonic_word, btn_next, btn_resend_token, btn_select, btn_send_token, btn_skip,
+            EntryWidth,
         },
         card, form, installer as installer_layout,
         list::{self, DeviceStatus, EntryAccent},
@@ -693,7 +694,9 @@ pub fn backup_descriptor<'a>(
     ];
     let descriptor_scroll =
         scrollable::horizontal_thin(text::new::caption(descriptor_str)).width(Length::Fill);
-    let descriptor_actions = row![Space::fill_width(), backup_button];
+    // Signers taking part in a BIP129 setup verify and register the wallet from this record.
+    let bsms_button = btn_export_bsms_record(Some(Message::ExportBsmsDescriptorRecord));
+    let descriptor_actions = row![Space::fill_width(), bsms_button, backup_button].spacing(10);
     let descriptor_header = row![descriptor_scroll, copy_button]
         .align_y(Alignment::Center)
         .spacing(10);
@@ -927,6 +930,8 @@ pub fn define_bitcoin_node<'a>(
                 match node_type {
                     NodeType::Bitcoind => "Bitcoin Core",
                     NodeType::Electrum => "Electrum",
+                    NodeType::Esplora => "Esplora",
+                    NodeType::Cbf => "Compact block filters",
                 },
                 node_type,
                 Some(selected_node_type),
@@ -7,6 +7,8 @@ pub mod electrum;
 pub enum NodeType {
     Bitcoind,
     Electrum,
+    Esplora,
+    Cbf,
 }
 
 impl From<&BitcoinBackend> for NodeType {
@@ -14,6 +16,8 @@ impl From<&BitcoinBackend> for NodeType {
         match bitcoin_backend {
             BitcoinBackend::Bitcoind(_) => Self::Bitcoind,
             BitcoinBackend::Electrum(_) => Self::Electrum,
+            BitcoinBackend::Esplora(_) => Self::Esplora,
+            BitcoinBackend::Cbf(_) => Self::Cbf,
         }
     }
 }
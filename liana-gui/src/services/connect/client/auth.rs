@@ -82,7 +82,7 @@ impl From<NotSuccessResponseInfo> for AuthError {
 impl AuthClient {
     pub fn new(url: String, api_public_key: String, email: String, user_agent: String) -> Self {
         AuthClient {
-            http: reqwest::Client::new(),
+            http: crate::services::http::client(),
             url,
             api_public_key,
             email,
@@ -90,7 +90,7 @@ impl BackendClient {
         credentials: auth::AccessTokenResponse,
         network: Network,
     ) -> Result<Self, DaemonError> {
-        let http = reqwest::Client::new();
+        let http = crate::services::http::client();
         let response = request(
             &http,
             Method::GET,
@@ -618,6 +618,8 @@ impl Daemon for BackendWalletClient {
             last_poll_timestamp: None,
             receive_index: wallet.deposit_derivation_index,
             change_index: wallet.change_derivation_index,
+            retired_timestamp: None,
+            electrum_servers: Vec::new(),
         })
     }
 
@@ -714,6 +716,7 @@ impl Daemon for BackendWalletClient {
                         height: info.height,
                     }),
                     is_from_self: c.is_from_self,
+                    is_quarantined: false,
                 })
                 .collect(),
         })
@@ -778,7 +781,12 @@ impl Daemon for BackendWalletClient {
         destinations: &HashMap<Address<address::NetworkUnchecked>, u64>,
         feerate_vb: u64,
         change_address: Option<Address<address::NetworkUnchecked>>,
+        subtract_fee_from: &[Address<address::NetworkUnchecked>],
+        options: &CreateSpendOptions,
     ) -> Result<CreateSpendResult, DaemonError> {
+        if !subtract_fee_from.is_empty() || *options != CreateSpendOptions::default() {
+            return Err(DaemonError::NotImplemented);
+        }
         let mut recipients: Vec<api::payload::Recipient> = destinations
             .iter()
             .map(|(addr, amt)| api::payload::Recipient {
@@ -1186,6 +1194,7 @@ fn history_tx_from_api(value: api::Transaction, network: Network) -> HistoryTran
                         height: info.height,
                     }),
                     is_from_self: c.is_from_self,
+                    is_quarantined: false,
                 });
             }
         }
@@ -1242,6 +1251,7 @@ fn spend_tx_from_api(
                         height: info.height,
                     }),
                     is_from_self: c.is_from_self,
+                    is_quarantined: false,
                 });
             }
         }
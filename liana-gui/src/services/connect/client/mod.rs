@@ -52,7 +52,7 @@ pub async fn get_service_config(
         (_, BackendType::LianaBusiness(_)) => std::env::var("LIANA_BUSINESS_SIGNET_API_URL")
             .unwrap_or_else(|_| BUSINESS_SIGNET_API_URL.to_string()),
     };
-    let client = reqwest::Client::new();
+    let client = crate::services::http::client();
     let res: ServiceConfigResource = client
         .get(format!("{backend_api_url}/v1/desktop"))
         .header("User-Agent", backend.user_agent())
@@ -4,7 +4,7 @@ use super::Currency;
 
 use async_trait::async_trait;
 
-use crate::services::http::ResponseExt;
+use crate::services::http::{self, ResponseExt};
 
 pub struct PriceClient<C> {
     inner: C,
@@ -17,9 +17,10 @@ impl<C> PriceClient<C> {
     }
 }
 
-impl<C: Default> PriceClient<C> {
+impl PriceClient<reqwest::Client> {
+    /// A client for this source using our HTTP client, which goes through the proxy if one is set.
     pub fn default_from_source(source: PriceSource) -> Self {
-        Self::new(C::default(), source)
+        Self::new(http::client(), source)
     }
 }
 
@@ -1,6 +1,35 @@
+use std::sync::RwLock;
+
 use async_trait::async_trait;
+use lianad::config::ProxyConfig;
 use reqwest::Response;
 
+/// The SOCKS5 proxy all our HTTP requests go through, if any. It is set from the global settings
+/// at startup.
+static PROXY: RwLock<Option<ProxyConfig>> = RwLock::new(None);
+
+pub fn set_proxy(proxy: Option<ProxyConfig>) {
+    *PROXY.write().expect("Must not be poisoned") = proxy;
+}
+
+pub fn proxy() -> Option<ProxyConfig> {
+    PROXY.read().expect("Must not be poisoned").clone()
+}
+
+/// A new HTTP client, which goes through the SOCKS5 proxy if one is set. Host names are
+/// resolved by the proxy so that DNS requests don't leak and onion addresses can be reached.
+pub fn client() -> reqwest::Client {
+    let mut builder = reqwest::Client::builder();
+    if let Some(proxy) = proxy() {
+        let proxy = reqwest::Proxy::all(format!("socks5h://{}", proxy.addr))
+            .expect("A socket address is a valid proxy URL")
+            // Connections to the local machine can't go through the proxy.
+            .no_proxy(reqwest::NoProxy::from_string("localhost,127.0.0.1,::1"));
+        builder = builder.proxy(proxy);
+    }
+    builder.build().expect("Valid client configuration")
+}
+
 /// Information about an unsuccessful response.
 #[derive(Debug, Clone)]
 pub struct NotSuccessResponseInfo {
@@ -777,6 +777,10 @@ pub fn btn_verify_compact<'a, T: Clone + 'a>(msg: T) -> Button<'a, T> {
     )
 }
 
+pub fn btn_sign_message_compact<'a, T: Clone + 'a>(msg: T) -> Button<'a, T> {
+    button_compact("Sign message", theme::button::tertiary, Some(msg))
+}
+
 pub fn btn_show_qr_compact<'a, T: Clone + 'a>(msg: T) -> Button<'a, T> {
     button_compact("Show QR Code", theme::button::tertiary, Some(msg))
 }
@@ -1001,6 +1005,10 @@ pub fn btn_backup_descriptor<'a, T: Clone + 'a>(msg: Option<T>, primary: bool) -
     }
 }
 
+pub fn btn_export_bsms_record<'a, T: Clone + 'a>(msg: Option<T>) -> Button<'a, T> {
+    btn_tertiary(None, "Export BSMS Record", BtnWidth::XL, msg)
+}
+
 pub fn btn_check_connection<'a, T: Clone + 'a>(msg: Option<T>, primary: bool) -> Button<'a, T> {
     let label = "Check connection";
     let width = BtnWidth::L;
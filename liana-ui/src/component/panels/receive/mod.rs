@@ -10,7 +10,7 @@ use iced::{
 use crate::{
     component::{
         address::copyable_address,
-        button::{btn_show_qr_compact, btn_verify_compact},
+        button::{btn_show_qr_compact, btn_sign_message_compact, btn_verify_compact},
         card, label,
         text::new,
     },
@@ -24,6 +24,7 @@ pub fn address_card<'a, M: Clone + 'static>(
     edit_label: M,
     clipboard: M,
     verify: M,
+    sign_message: M,
     show_qr: M,
 ) -> Element<'a, M> {
     let label = label::editable_label(label, edit_label);
@@ -32,9 +33,11 @@ pub fn address_card<'a, M: Clone + 'static>(
 
     let bottom = row![
         btn_verify_compact(verify),
+        btn_sign_message_compact(sign_message),
         Space::fill_width(),
         btn_show_qr_compact(show_qr)
-    ];
+    ]
+    .spacing(10);
 
     let content = column![top, bottom].spacing(16);
 
@@ -59,12 +59,14 @@ pub fn recipient_card<'a, M: Clone + 'static>(
     amount: &'a form::Value<String>,
     fiat: Option<RecipientFiat<'a, M>>,
     is_max_selected: bool,
+    is_subtract_fee_selected: bool,
     dust_warning: Option<&'a str>,
     max_estimated_amount: Option<Amount>,
     on_address_edit: impl Fn(String) -> M + 'static,
     on_label_edit: impl Fn(String) -> M + 'static,
     on_amount_edit: impl Fn(String) -> M + 'static + Clone,
     on_max: Option<M>,
+    on_subtract_fee: Option<M>,
     on_delete: Option<M>,
 ) -> Element<'a, M> {
     let btc_amt = if dust_warning.is_some() {
@@ -75,7 +77,11 @@ pub fn recipient_card<'a, M: Clone + 'static>(
 
     let address_form: Element<'a, M> = form::Form::new("Address", address, on_address_edit)
         .label("Address")
-        .warning("Invalid address (maybe it is for another network?)")
+        .warning(
+            address
+                .warning
+                .unwrap_or("Invalid address (maybe it is for another network?)"),
+        )
         .size(P1_SIZE)
         .padding(10)
         .into();
@@ -191,7 +197,21 @@ pub fn recipient_card<'a, M: Clone + 'static>(
         )
     });
 
-    let amount_row = row![btc_input, fiat_price, max]
+    // The fee cannot be deducted from a recipient that receives the max (on_subtract_fee is None).
+    let subtract_fee = on_subtract_fee.map(|msg| {
+        iced::widget::tooltip::Tooltip::new(
+            labelled_checkbox(
+                new::caption("DEDUCT FEE"),
+                is_subtract_fee_selected,
+                move |_| msg.clone(),
+            ),
+            // Add spaces at end so that text is padded at screen edge.
+            "Pay the fee out of this amount, shared with any other such recipient     ",
+            iced::widget::tooltip::Position::Bottom,
+        )
+    });
+
+    let amount_row = row![btc_input, fiat_price, max, subtract_fee]
         .align_y(Alignment::End)
         .spacing(10)
         .width(Length::Fill);
@@ -369,6 +389,84 @@ pub fn coin_selection<'a, M: 'a>(rows: Vec<Element<'a, M>>) -> Element<'a, M> {
     column![header, list].spacing(10).into()
 }
 
+/// Optional transaction settings, only displayed once expanded by the user.
+#[allow(clippy::too_many_arguments)]
+pub fn advanced_options<'a, M: Clone + 'static>(
+    expanded: bool,
+    op_return: &'a form::Value<String>,
+    locktime: &'a form::Value<String>,
+    signal_rbf: bool,
+    truc: bool,
+    on_toggle: M,
+    on_op_return_edit: impl Fn(String) -> M + 'static,
+    on_locktime_edit: impl Fn(String) -> M + 'static,
+    on_rbf: M,
+    on_truc: M,
+) -> Element<'a, M> {
+    let header = row![
+        section("Advanced options"),
+        button::btn_tertiary(
+            None,
+            if expanded { "Hide" } else { "Show" },
+            button::BtnWidth::Auto,
+            Some(on_toggle),
+        ),
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center);
+
+    if !expanded {
+        return header.into();
+    }
+
+    let op_return_form = form::Form::new(
+        "Text to embed in the transaction",
+        op_return,
+        on_op_return_edit,
+    )
+    .label("Data (OP_RETURN)")
+    .warning("Data is limited to 80 bytes")
+    .size(P1_SIZE)
+    .padding(10);
+
+    let locktime_form =
+        form::Form::new_trimmed("Default: current block height", locktime, on_locktime_edit)
+            .label("Locktime (block height)")
+            .warning("Invalid block height")
+            .size(P1_SIZE)
+            .padding(10);
+
+    let rbf = iced::widget::tooltip::Tooltip::new(
+        labelled_checkbox(new::caption("REPLACEABLE (RBF)"), signal_rbf, move |_| {
+            on_rbf.clone()
+        }),
+        // Add spaces at end so that text is padded at screen edge.
+        "Allow the transaction to be replaced by one paying a higher fee     ",
+        iced::widget::tooltip::Position::Bottom,
+    );
+
+    let truc = iced::widget::tooltip::Tooltip::new(
+        labelled_checkbox(new::caption("TRUC (V3)"), truc, move |_| on_truc.clone()),
+        // Add spaces at end so that text is padded at screen edge.
+        "Create a version 3 transaction, which is always replaceable and easier to fee bump     ",
+        iced::widget::tooltip::Position::Bottom,
+    );
+
+    let content = card::flat(
+        column![
+            op_return_form,
+            row![locktime_form, rbf, truc]
+                .spacing(20)
+                .align_y(Alignment::End)
+        ]
+        .spacing(10),
+        [12, 42],
+    )
+    .width(Length::Fill);
+
+    column![header, content].spacing(10).into()
+}
+
 const COMPACT_PILL_WIDTH: f32 = 1400.0;
 const DASHBOARD_PADDING: f32 = 380.0;
 const MIN_LABEL_LEN: usize = 20;
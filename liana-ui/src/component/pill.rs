@@ -235,6 +235,7 @@ pills! {
     unsigned,       "Unsigned",     "This transaction is missing signature(s)",                       M, soft_warning;
     signed,         "To broadcast", "This transaction is signed & ready to broadcast",                M, soft_warning;
     unconfirmed,    "Unconfirmed",  "Do not treat this as a payment until it is confirmed",           M, simple_fill;
+    quarantined,    "Quarantined",  "Likely dust attack: this coin is never selected automatically",  M, warning;
     confirmed,      "Confirmed",    "This transaction has been included in a block",                  M, success;
     key_internal,   "Internal",     "Key held by your organization",                                  M, internal;
     // Business installer only
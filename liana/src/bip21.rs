@@ -0,0 +1,357 @@
+//! BIP21 module
+//!
+//! Parsing and building of `bitcoin:` payment URIs (BIP21). Besides the address to pay to, a URI
+//! may carry the amount to pay, a label for the recipient and a message describing the payment.
+//!
+//! Payjoin (BIP78) is not supported: a `pj` endpoint is parsed and kept around, but it is up to
+//! the caller to fall back to a regular payment.
+
+use std::{error, fmt, str::FromStr};
+
+use miniscript::bitcoin::{address::NetworkUnchecked, Address, Amount, Denomination};
+
+/// The scheme of a payment URI, without the colon. It is case-insensitive.
+const SCHEME: &str = "bitcoin";
+
+/// Parameters of which the name starts with this prefix must be understood by the payer.
+const REQUIRED_PREFIX: &str = "req-";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bip21Error {
+    InvalidScheme,
+    InvalidAddress(String),
+    InvalidAmount(String),
+    InvalidEncoding(String),
+    DuplicateParameter(String),
+    UnsupportedRequiredParameter(String),
+}
+
+impl fmt::Display for Bip21Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidScheme => write!(f, "Payment URI must start with '{SCHEME}:'."),
+            Self::InvalidAddress(e) => write!(f, "Invalid address in payment URI: {e}"),
+            Self::InvalidAmount(e) => write!(f, "Invalid amount in payment URI: {e}"),
+            Self::InvalidEncoding(s) => write!(f, "Invalid percent-encoding in payment URI: '{s}'"),
+            Self::DuplicateParameter(p) => {
+                write!(f, "Parameter '{p}' is set more than once in payment URI.")
+            }
+            Self::UnsupportedRequiredParameter(p) => {
+                write!(f, "Payment URI requires unsupported parameter '{p}'.")
+            }
+        }
+    }
+}
+
+impl error::Error for Bip21Error {}
+
+/// A BIP21 payment URI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentUri {
+    pub address: Address<NetworkUnchecked>,
+    pub amount: Option<Amount>,
+    /// Label for the recipient, for instance its name.
+    pub label: Option<String>,
+    /// Message describing the payment.
+    pub message: Option<String>,
+    /// The Payjoin endpoint of the recipient, if any.
+    pub payjoin_endpoint: Option<String>,
+    /// Any other (optional) parameter, in the order they appear in the URI.
+    pub other_params: Vec<(String, String)>,
+}
+
+impl PaymentUri {
+    pub fn new(address: Address<NetworkUnchecked>) -> Self {
+        Self {
+            address,
+            amount: None,
+            label: None,
+            message: None,
+            payjoin_endpoint: None,
+            other_params: Vec::new(),
+        }
+    }
+
+    pub fn with_amount(mut self, amount: Amount) -> Self {
+        self.amount = Some(amount);
+        self
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Add a parameter not otherwise supported by this type.
+    pub fn with_param(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.other_params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Whether this string is meant to be a payment URI, as opposed to a plain address.
+    pub fn is_uri(s: &str) -> bool {
+        s.split_once(':')
+            .is_some_and(|(scheme, _)| scheme.eq_ignore_ascii_case(SCHEME))
+    }
+
+    /// A description of this payment: the message if there is one, the label otherwise.
+    pub fn description(&self) -> Option<&str> {
+        self.message.as_deref().or(self.label.as_deref())
+    }
+}
+
+/// Format an amount in BTC without trailing zeros, as BIP21 expects.
+fn format_amount(amount: Amount) -> String {
+    let sats = amount.to_sat();
+    let btc = sats / Amount::ONE_BTC.to_sat();
+    let frac = sats % Amount::ONE_BTC.to_sat();
+    if frac == 0 {
+        btc.to_string()
+    } else {
+        format!("{btc}.{frac:08}").trim_end_matches('0').to_string()
+    }
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte.into());
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+fn percent_decode(s: &str) -> Result<String, Bip21Error> {
+    let invalid = || Bip21Error::InvalidEncoding(s.to_string());
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .ok_or_else(invalid)?;
+            let hex = std::str::from_utf8(hex).expect("Checked above, only hex digits.");
+            decoded.push(u8::from_str_radix(hex, 16).expect("Checked above, two hex digits."));
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| invalid())
+}
+
+impl fmt::Display for PaymentUri {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", SCHEME, self.address.assume_checked_ref())?;
+        let params = self
+            .amount
+            .map(|amount| ("amount", format_amount(amount)))
+            .into_iter()
+            .chain(self.label.as_deref().map(|l| ("label", percent_encode(l))))
+            .chain(
+                self.message
+                    .as_deref()
+                    .map(|m| ("message", percent_encode(m))),
+            )
+            .chain(
+                self.payjoin_endpoint
+                    .as_deref()
+                    .map(|e| ("pj", percent_encode(e))),
+            )
+            .chain(
+                self.other_params
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), percent_encode(v))),
+            );
+        for (i, (key, value)) in params.enumerate() {
+            let sep = if i == 0 { '?' } else { '&' };
+            write!(f, "{sep}{key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PaymentUri {
+    type Err = Bip21Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s.split_once(':').ok_or(Bip21Error::InvalidScheme)?;
+        if !scheme.eq_ignore_ascii_case(SCHEME) {
+            return Err(Bip21Error::InvalidScheme);
+        }
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let address =
+            Address::from_str(address).map_err(|e| Bip21Error::InvalidAddress(e.to_string()))?;
+        let mut uri = PaymentUri::new(address);
+
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let value = percent_decode(value)?;
+            let duplicate = || Bip21Error::DuplicateParameter(key.to_string());
+            match key {
+                "amount" => {
+                    if uri.amount.is_some() {
+                        return Err(duplicate());
+                    }
+                    let amount = Amount::from_str_in(&value, Denomination::Bitcoin)
+                        .map_err(|e| Bip21Error::InvalidAmount(e.to_string()))?;
+                    uri.amount = Some(amount);
+                }
+                "label" | "message" | "pj" => {
+                    let field = match key {
+                        "label" => &mut uri.label,
+                        "message" => &mut uri.message,
+                        _ => &mut uri.payjoin_endpoint,
+                    };
+                    if field.is_some() {
+                        return Err(duplicate());
+                    }
+                    *field = Some(value);
+                }
+                k if k.starts_with(REQUIRED_PREFIX) => {
+                    return Err(Bip21Error::UnsupportedRequiredParameter(k.to_string()));
+                }
+                _ => uri.other_params.push((key.to_string(), value)),
+            }
+        }
+
+        Ok(uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "175tWpb8K1S7NmH4Zx6rewF9WQrcZv245W";
+
+    #[test]
+    fn bip21_parse() {
+        let address = Address::from_str(ADDRESS).unwrap();
+
+        // The examples from BIP21.
+        let uri = PaymentUri::from_str(&format!("bitcoin:{ADDRESS}")).unwrap();
+        assert_eq!(uri, PaymentUri::new(address.clone()));
+        let uri = PaymentUri::from_str(&format!("bitcoin:{ADDRESS}?label=Luke-Jr")).unwrap();
+        assert_eq!(uri, PaymentUri::new(address.clone()).with_label("Luke-Jr"));
+        let uri =
+            PaymentUri::from_str(&format!("bitcoin:{ADDRESS}?amount=20.3&label=Luke-Jr")).unwrap();
+        assert_eq!(uri.amount, Some(Amount::from_sat(2_030_000_000)));
+        assert_eq!(uri.label.as_deref(), Some("Luke-Jr"));
+        let uri = PaymentUri::from_str(&format!(
+            "bitcoin:{ADDRESS}?amount=50&label=Luke-Jr&message=Donation%20for%20project%20xyz"
+        ))
+        .unwrap();
+        assert_eq!(uri.amount, Some(Amount::from_int_btc(50)));
+        assert_eq!(uri.message.as_deref(), Some("Donation for project xyz"));
+        assert_eq!(uri.description(), Some("Donation for project xyz"));
+        assert_eq!(
+            PaymentUri::from_str(&format!(
+                "bitcoin:{ADDRESS}?req-somethingyoudontunderstand=50&req-somethingelseyoudontget=999"
+            )),
+            Err(Bip21Error::UnsupportedRequiredParameter(
+                "req-somethingyoudontunderstand".to_string()
+            ))
+        );
+        let uri = PaymentUri::from_str(&format!(
+            "bitcoin:{ADDRESS}?somethingyoudontunderstand=50&somethingelseyoudontget=999"
+        ))
+        .unwrap();
+        assert_eq!(
+            uri.other_params,
+            vec![
+                ("somethingyoudontunderstand".to_string(), "50".to_string()),
+                ("somethingelseyoudontget".to_string(), "999".to_string()),
+            ]
+        );
+
+        // The scheme is case-insensitive and a Payjoin endpoint is kept.
+        let uri = PaymentUri::from_str(&format!(
+            "BITCOIN:{ADDRESS}?amount=0.00001&pj=https://example.com/pj%3Fv%3D1"
+        ))
+        .unwrap();
+        assert_eq!(uri.amount, Some(Amount::from_sat(1_000)));
+        assert_eq!(
+            uri.payjoin_endpoint.as_deref(),
+            Some("https://example.com/pj?v=1")
+        );
+        assert!(PaymentUri::is_uri("Bitcoin:whatever"));
+        assert!(!PaymentUri::is_uri(ADDRESS));
+
+        // Invalid URIs.
+        assert_eq!(
+            PaymentUri::from_str(ADDRESS),
+            Err(Bip21Error::InvalidScheme)
+        );
+        assert_eq!(
+            PaymentUri::from_str(&format!("litecoin:{ADDRESS}")),
+            Err(Bip21Error::InvalidScheme)
+        );
+        assert!(matches!(
+            PaymentUri::from_str("bitcoin:notanaddress"),
+            Err(Bip21Error::InvalidAddress(..))
+        ));
+        for amount in ["-1", "1,5", "1e-3", "0.000000001", ""] {
+            assert!(matches!(
+                PaymentUri::from_str(&format!("bitcoin:{ADDRESS}?amount={amount}")),
+                Err(Bip21Error::InvalidAmount(..))
+            ));
+        }
+        assert_eq!(
+            PaymentUri::from_str(&format!("bitcoin:{ADDRESS}?amount=1&amount=2")),
+            Err(Bip21Error::DuplicateParameter("amount".to_string()))
+        );
+        assert_eq!(
+            PaymentUri::from_str(&format!("bitcoin:{ADDRESS}?label=a&label=b")),
+            Err(Bip21Error::DuplicateParameter("label".to_string()))
+        );
+        for label in ["%", "%4", "%zz", "%+1", "%ff"] {
+            assert!(matches!(
+                PaymentUri::from_str(&format!("bitcoin:{ADDRESS}?label={label}")),
+                Err(Bip21Error::InvalidEncoding(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn bip21_build() {
+        let address = Address::from_str(ADDRESS).unwrap();
+
+        let uri = PaymentUri::new(address.clone());
+        assert_eq!(uri.to_string(), format!("bitcoin:{ADDRESS}"));
+
+        let uri = PaymentUri::new(address.clone())
+            .with_amount(Amount::from_sat(2_030_000_000))
+            .with_label("Luke-Jr")
+            .with_message("Donation for project xyz & more");
+        assert_eq!(
+            uri.to_string(),
+            format!(
+                "bitcoin:{ADDRESS}?amount=20.3&label=Luke-Jr&message=Donation%20for%20project%20xyz%20%26%20more"
+            )
+        );
+        assert_eq!(PaymentUri::from_str(&uri.to_string()).unwrap(), uri);
+
+        let uri = PaymentUri::new(address)
+            .with_amount(Amount::from_sat(1))
+            .with_label("Café")
+            .with_param("index", "12");
+        assert_eq!(
+            uri.to_string(),
+            format!("bitcoin:{ADDRESS}?amount=0.00000001&label=Caf%C3%A9&index=12")
+        );
+        assert_eq!(PaymentUri::from_str(&uri.to_string()).unwrap(), uri);
+        assert_eq!(format_amount(Amount::from_int_btc(21)), "21");
+    }
+}
@@ -0,0 +1,295 @@
+//! BIP322 module
+//!
+//! Generic signed messages (BIP322). A message is signed for an address by spending a virtual
+//! transaction ("to_spend") paying to this address with another virtual transaction ("to_sign").
+//! The signature is the witness of the "to_sign" transaction, so any script a wallet is able to
+//! spend from can sign a message. Only the "simple" signature format is supported.
+//!
+//! The "to_sign" transaction is created as a PSBT so it can be signed by the same signers as any
+//! other transaction.
+
+use std::{error, fmt, str::FromStr};
+
+use crate::descriptors::DerivedSinglePathLianaDesc;
+
+use miniscript::{
+    bitcoin::{
+        self,
+        base64::{engine::general_purpose::STANDARD as BASE64, Engine},
+        consensus::encode,
+        hashes::{sha256, Hash, HashEngine},
+        opcodes,
+        psbt::{Input as PsbtIn, Psbt},
+        script::Builder,
+        secp256k1, sighash,
+        transaction::Version,
+        Address, Amount, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
+        Witness,
+    },
+    interpreter::Interpreter,
+    psbt::PsbtExt,
+};
+
+/// The tag of the hash of the message committed to in the "to_spend" transaction.
+const MESSAGE_TAG: &[u8] = b"BIP0322-signed-message";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bip322Error {
+    InvalidEncoding(String),
+    /// The PSBT is not the one signing this message for this address.
+    PsbtMismatch,
+    Finalization(String),
+}
+
+impl fmt::Display for Bip322Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidEncoding(e) => write!(f, "Invalid message signature encoding: {e}"),
+            Self::PsbtMismatch => {
+                write!(f, "The PSBT does not sign this message for this address.")
+            }
+            Self::Finalization(e) => write!(f, "Failed to finalize message signature: {e}"),
+        }
+    }
+}
+
+impl error::Error for Bip322Error {}
+
+/// The tagged hash of a message, as committed to by the "to_spend" transaction.
+pub fn message_hash(message: &[u8]) -> sha256::Hash {
+    let tag_hash = sha256::Hash::hash(MESSAGE_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    engine.input(message);
+    sha256::Hash::from_engine(engine)
+}
+
+/// The virtual transaction committing to the message, with a single output paying to the address
+/// for which the message is signed.
+pub fn to_spend_tx(script_pubkey: &Script, message: &[u8]) -> Transaction {
+    let script_sig = Builder::new()
+        .push_opcode(opcodes::OP_0)
+        .push_slice(message_hash(message).to_byte_array())
+        .into_script();
+    Transaction {
+        version: Version(0),
+        lock_time: bitcoin::absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                txid: Txid::all_zeros(),
+                vout: 0xFF_FF_FF_FF,
+            },
+            script_sig,
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script_pubkey.to_owned(),
+        }],
+    }
+}
+
+/// The virtual transaction spending the output of the "to_spend" transaction. Its witness is the
+/// signature of the message.
+pub fn to_sign_tx(to_spend: &Transaction) -> Transaction {
+    Transaction {
+        version: Version(0),
+        lock_time: bitcoin::absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                txid: to_spend.compute_txid(),
+                vout: 0,
+            },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: Builder::new()
+                .push_opcode(opcodes::all::OP_RETURN)
+                .into_script(),
+        }],
+    }
+}
+
+/// Create the PSBT to be signed in order to sign this message for the address of this derived
+/// descriptor. Only the primary path can be used, as the input of the "to_sign" transaction may
+/// not have a relative timelock.
+pub fn message_psbt(
+    der_desc: &DerivedSinglePathLianaDesc,
+    is_taproot: bool,
+    message: &[u8],
+) -> Psbt {
+    let to_spend = to_spend_tx(&der_desc.script_pubkey(), message);
+    let to_sign = to_sign_tx(&to_spend);
+
+    let mut psbt_in = PsbtIn::default();
+    der_desc.update_psbt_in(&mut psbt_in);
+    psbt_in.witness_utxo = Some(to_spend.output[0].clone());
+    if !is_taproot {
+        psbt_in.non_witness_utxo = Some(to_spend);
+    }
+
+    let mut psbt = Psbt::from_unsigned_tx(to_sign).expect("No script sig nor witness");
+    psbt.inputs[0] = psbt_in;
+    psbt
+}
+
+/// Whether this PSBT is the one signing this message for this address.
+pub fn is_message_psbt(psbt: &Psbt, script_pubkey: &Script, message: &[u8]) -> bool {
+    psbt.unsigned_tx == to_sign_tx(&to_spend_tx(script_pubkey, message))
+}
+
+/// A BIP322 signature in the "simple" format: the witness of the "to_sign" transaction, encoded
+/// in base64.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSignature(pub Witness);
+
+impl MessageSignature {
+    /// Get the signature of the message from its signed PSBT.
+    pub fn from_psbt(
+        secp: &secp256k1::Secp256k1<impl secp256k1::Verification>,
+        psbt: &Psbt,
+        script_pubkey: &Script,
+        message: &[u8],
+    ) -> Result<Self, Bip322Error> {
+        if !is_message_psbt(psbt, script_pubkey, message) || psbt.inputs.len() != 1 {
+            return Err(Bip322Error::PsbtMismatch);
+        }
+        let mut psbt = psbt.clone();
+        psbt.finalize_mut(secp).map_err(|e| {
+            Bip322Error::Finalization(
+                e.into_iter()
+                    .next()
+                    .map(|e| e.to_string())
+                    .unwrap_or_default(),
+            )
+        })?;
+        psbt.inputs[0]
+            .final_script_witness
+            .take()
+            .map(Self)
+            .ok_or_else(|| Bip322Error::Finalization("not a Segwit input".to_string()))
+    }
+
+    /// Whether this is a valid signature of the message for this address.
+    pub fn verify(
+        &self,
+        secp: &secp256k1::Secp256k1<impl secp256k1::Verification>,
+        address: &Address,
+        message: &[u8],
+    ) -> bool {
+        let to_spend = to_spend_tx(&address.script_pubkey(), message);
+        let mut to_sign = to_sign_tx(&to_spend);
+        to_sign.input[0].witness = self.0.clone();
+        let txin = &to_sign.input[0];
+
+        let interpreter = match Interpreter::from_txdata(
+            &to_spend.output[0].script_pubkey,
+            &txin.script_sig,
+            &txin.witness,
+            txin.sequence,
+            to_sign.lock_time,
+        ) {
+            Ok(interpreter) => interpreter,
+            Err(_) => return false,
+        };
+        let prevouts = sighash::Prevouts::All(&to_spend.output);
+        // The iterator errors if any constraint is not satisfied, or if the script doesn't
+        // succeed once they all were.
+        interpreter
+            .iter(secp, &to_sign, 0, &prevouts)
+            .all(|res| res.is_ok())
+    }
+}
+
+impl fmt::Display for MessageSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", BASE64.encode(encode::serialize(&self.0)))
+    }
+}
+
+impl FromStr for MessageSignature {
+    type Err = Bip322Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = BASE64
+            .decode(s)
+            .map_err(|e| Bip322Error::InvalidEncoding(e.to_string()))?;
+        encode::deserialize(&bytes)
+            .map(Self)
+            .map_err(|e| Bip322Error::InvalidEncoding(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from BIP322.
+    const ADDRESS: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+
+    fn address() -> Address {
+        Address::from_str(ADDRESS).unwrap().assume_checked()
+    }
+
+    #[test]
+    fn bip322_message_hash() {
+        assert_eq!(
+            message_hash(b"").to_string(),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            message_hash(b"Hello World").to_string(),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn bip322_transactions() {
+        let spk = address().script_pubkey();
+
+        let to_spend = to_spend_tx(&spk, b"");
+        assert_eq!(
+            to_spend.compute_txid().to_string(),
+            "c5680aa69bb8d860bf82d4e9cd3504b55dde018de765a91bb566283c545a99a7"
+        );
+        assert_eq!(
+            to_sign_tx(&to_spend).compute_txid().to_string(),
+            "1e9654e951a5ba44c8604c4de6c67fd78a27e81dcadcfe1edf638ba3aaebaed6"
+        );
+
+        let to_spend = to_spend_tx(&spk, b"Hello World");
+        assert_eq!(
+            to_spend.compute_txid().to_string(),
+            "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b"
+        );
+        assert_eq!(
+            to_sign_tx(&to_spend).compute_txid().to_string(),
+            "88737ae86f2077145f93cc4b153ae9a1cb8d56afa511988c149c5c8c9d93bddf"
+        );
+    }
+
+    #[test]
+    fn bip322_verify() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let sig_str = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        let sig = MessageSignature::from_str(sig_str).unwrap();
+        assert_eq!(sig.to_string(), sig_str);
+        assert!(sig.verify(&secp, &address(), b"Hello World"));
+        assert!(!sig.verify(&secp, &address(), b"Hello World!"));
+        let other_addr = Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv")
+            .unwrap()
+            .assume_checked();
+        assert!(!sig.verify(&secp, &other_addr, b"Hello World"));
+        assert!(!MessageSignature(Witness::new()).verify(&secp, &address(), b"Hello World"));
+
+        assert!(matches!(
+            MessageSignature::from_str("not base64!"),
+            Err(Bip322Error::InvalidEncoding(..))
+        ));
+    }
+}
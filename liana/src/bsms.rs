@@ -0,0 +1,592 @@
+//! BSMS module
+//!
+//! Helpers for setting up a multisig wallet using the Bitcoin Secure Multisig Setup (BIP129)
+//! protocol. Liana acts as the Coordinator: it generates the session token, collects the signers'
+//! key records and emits the descriptor record for the signers to verify. All records are plain
+//! text so they can be exchanged through files, without any network connection.
+//!
+//! Records are never encrypted: they must be transmitted over a channel the participants trust
+//! (for instance a SD card they handle themselves). The session token only ties the key records to
+//! a setup session.
+
+use crate::{descriptors::LianaDescriptor, random};
+
+use std::{error, fmt, str::FromStr};
+
+use miniscript::{
+    bitcoin::{
+        self,
+        bip32::{self, ChildNumber, DerivationPath, Fingerprint},
+        hashes::Hash,
+        hex::{DisplayHex, FromHex},
+        secp256k1,
+        sign_message::{signed_msg_hash, MessageSignature},
+    },
+    descriptor::{DerivPaths, DescriptorMultiXKey, DescriptorPublicKey, DescriptorXKey, Wildcard},
+};
+
+/// The only version of the BSMS protocol we support.
+pub const BSMS_VERSION: &str = "BSMS 1.0";
+
+/// The maximum length of the description of a key record, in characters.
+const MAX_DESCRIPTION_LEN: usize = 80;
+
+#[derive(Debug)]
+pub enum BsmsError {
+    Randomness(random::RandomnessError),
+    UnsupportedVersion(String),
+    InvalidToken(String),
+    InvalidKey(String),
+    DescriptionTooLong(usize),
+    InvalidSignature(String),
+    TokenMismatch { expected: Token, got: Token },
+    Malformed(String),
+}
+
+impl fmt::Display for BsmsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Randomness(e) => write!(f, "Error generating the session token: {e}"),
+            Self::UnsupportedVersion(v) => write!(
+                f,
+                "Unsupported BSMS version '{v}'. Only '{BSMS_VERSION}' is supported."
+            ),
+            Self::InvalidToken(t) => write!(
+                f,
+                "Invalid session token '{t}'. It must be '00', or 16 or 32 hexadecimal characters."
+            ),
+            Self::InvalidKey(s) => write!(f, "Invalid key in key record: {s}"),
+            Self::DescriptionTooLong(len) => write!(
+                f,
+                "Key description is {len} characters long, maximum is {MAX_DESCRIPTION_LEN}."
+            ),
+            Self::InvalidSignature(s) => write!(f, "Invalid key record signature: {s}"),
+            Self::TokenMismatch { expected, got } => write!(
+                f,
+                "Key record was created for session '{got}' but the current session is '{expected}'."
+            ),
+            Self::Malformed(s) => write!(f, "Malformed BSMS record: {s}"),
+        }
+    }
+}
+
+impl error::Error for BsmsError {}
+
+/// A BSMS session token, shared by the Coordinator with all the signers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    /// Records are not encrypted.
+    NoEncryption,
+    /// A 64 bits token.
+    Standard([u8; 8]),
+    /// A 128 bits token.
+    Extended([u8; 16]),
+}
+
+impl Token {
+    /// Generate a new random 64 bits session token.
+    pub fn generate() -> Result<Self, BsmsError> {
+        let rand = random::random_bytes().map_err(BsmsError::Randomness)?;
+        let mut token = [0; 8];
+        token.copy_from_slice(&rand[..8]);
+        Ok(Self::Standard(token))
+    }
+
+    /// Whether records exchanged within this session are encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        !matches!(self, Self::NoEncryption)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoEncryption => write!(f, "00"),
+            Self::Standard(t) => write!(f, "{}", t.to_upper_hex_string()),
+            Self::Extended(t) => write!(f, "{}", t.to_upper_hex_string()),
+        }
+    }
+}
+
+impl FromStr for Token {
+    type Err = BsmsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BsmsError::InvalidToken(s.to_string());
+        if s == "00" {
+            return Ok(Self::NoEncryption);
+        }
+        match s.len() {
+            16 => <[u8; 8]>::from_hex(s)
+                .map(Self::Standard)
+                .map_err(|_| invalid()),
+            32 => <[u8; 16]>::from_hex(s)
+                .map(Self::Extended)
+                .map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// The record a signer sends to the Coordinator to provide its key for the multisig. The key is
+/// signed to prove the signer controls it.
+///
+/// ```text
+/// BSMS 1.0
+/// <token>
+/// [<fingerprint>/<derivation path>]<xpub>
+/// <description>
+/// <signature>
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyRecord {
+    pub token: Token,
+    pub fingerprint: Fingerprint,
+    pub derivation_path: DerivationPath,
+    pub xpub: bip32::Xpub,
+    pub description: String,
+    pub signature: MessageSignature,
+    // The signed part of the record, exactly as it was created or parsed. The signature is checked
+    // against it rather than against a serialization of the fields above, which may differ.
+    signed_message: String,
+}
+
+impl KeyRecord {
+    /// Create a key record for the extended key at the given path from this master key.
+    pub fn new_signed(
+        secp: &secp256k1::Secp256k1<impl secp256k1::Signing>,
+        token: Token,
+        master_xpriv: &bip32::Xpriv,
+        derivation_path: DerivationPath,
+        description: String,
+    ) -> Result<Self, BsmsError> {
+        if description.chars().count() > MAX_DESCRIPTION_LEN {
+            return Err(BsmsError::DescriptionTooLong(description.chars().count()));
+        }
+        let xpriv = master_xpriv
+            .derive_priv(secp, &derivation_path)
+            .map_err(|e| BsmsError::InvalidKey(e.to_string()))?;
+        let xpub = bip32::Xpub::from_priv(secp, &xpriv);
+        let fingerprint = master_xpriv.fingerprint(secp);
+        let signed_message =
+            Self::message(&token, fingerprint, &derivation_path, &xpub, &description);
+        let msg_hash = signed_msg_hash(&signed_message);
+        let msg = secp256k1::Message::from_digest(msg_hash.to_byte_array());
+        let sig = secp.sign_ecdsa_recoverable(&msg, &xpriv.private_key);
+        Ok(Self {
+            token,
+            fingerprint,
+            derivation_path,
+            xpub,
+            description,
+            signature: MessageSignature::new(sig, true),
+            signed_message,
+        })
+    }
+
+    // The signed part of the record.
+    fn message(
+        token: &Token,
+        fingerprint: Fingerprint,
+        derivation_path: &DerivationPath,
+        xpub: &bip32::Xpub,
+        description: &str,
+    ) -> String {
+        format!(
+            "{BSMS_VERSION}\n{token}\n{}\n{description}",
+            Self::key_str(fingerprint, derivation_path, xpub)
+        )
+    }
+
+    fn key_str(
+        fingerprint: Fingerprint,
+        derivation_path: &DerivationPath,
+        xpub: &bip32::Xpub,
+    ) -> String {
+        let path: String = derivation_path
+            .into_iter()
+            .map(|child| format!("/{child}"))
+            .collect();
+        format!("[{fingerprint}{path}]{xpub}")
+    }
+
+    // Parse the signed part of a key record: the version, token, key and description lines.
+    fn parse_message(
+        message: &str,
+    ) -> Result<(Token, Fingerprint, DerivationPath, bip32::Xpub, String), BsmsError> {
+        let mut lines = message.split('\n');
+        let mut next_line = |name: &str| {
+            lines
+                .next()
+                .ok_or_else(|| BsmsError::Malformed(format!("missing {name}")))
+        };
+        let version = next_line("version")?;
+        if version != BSMS_VERSION {
+            return Err(BsmsError::UnsupportedVersion(version.to_string()));
+        }
+        let token = Token::from_str(next_line("token")?.trim())?;
+        let key = next_line("key")?;
+        let (fingerprint, derivation_path, xpub) = match DescriptorPublicKey::from_str(key.trim()) {
+            Ok(DescriptorPublicKey::XPub(DescriptorXKey {
+                origin: Some((fingerprint, derivation_path)),
+                xkey,
+                derivation_path: suffix,
+                wildcard: Wildcard::None,
+            })) if suffix.is_empty() => (fingerprint, derivation_path, xkey),
+            Ok(_) => {
+                return Err(BsmsError::InvalidKey(
+                    "must be an extended public key with its origin and no derivation step"
+                        .to_string(),
+                ))
+            }
+            Err(e) => return Err(BsmsError::InvalidKey(e.to_string())),
+        };
+        let description = next_line("description")?.to_string();
+        if description.chars().count() > MAX_DESCRIPTION_LEN {
+            return Err(BsmsError::DescriptionTooLong(description.chars().count()));
+        }
+        Ok((token, fingerprint, derivation_path, xpub, description))
+    }
+
+    /// Check this record was signed by the key it contains. This doesn't check the session token,
+    /// see [`KeyRecord::verify`].
+    pub fn verify_signature(
+        &self,
+        secp: &secp256k1::Secp256k1<impl secp256k1::Verification>,
+    ) -> Result<(), BsmsError> {
+        let signed_fields = Self::parse_message(&self.signed_message)?;
+        if signed_fields
+            != (
+                self.token,
+                self.fingerprint,
+                self.derivation_path.clone(),
+                self.xpub,
+                self.description.clone(),
+            )
+        {
+            return Err(BsmsError::InvalidSignature(
+                "the record doesn't match its signed content".to_string(),
+            ));
+        }
+        let msg_hash = signed_msg_hash(&self.signed_message);
+        let pubkey = self
+            .signature
+            .recover_pubkey(secp, msg_hash)
+            .map_err(|e| BsmsError::InvalidSignature(e.to_string()))?;
+        if pubkey.inner != self.xpub.public_key {
+            return Err(BsmsError::InvalidSignature(
+                "not signed by the key in the record".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check this record was signed by the key it contains and was created for this session.
+    pub fn verify(
+        &self,
+        secp: &secp256k1::Secp256k1<impl secp256k1::Verification>,
+        session_token: &Token,
+    ) -> Result<(), BsmsError> {
+        if &self.token != session_token {
+            return Err(BsmsError::TokenMismatch {
+                expected: *session_token,
+                got: self.token,
+            });
+        }
+        self.verify_signature(secp)
+    }
+
+    /// The key to use in a Liana descriptor, for both the receive and change keychains.
+    pub fn multipath_key(&self) -> DescriptorPublicKey {
+        DescriptorPublicKey::MultiXPub(DescriptorMultiXKey {
+            origin: Some((self.fingerprint, self.derivation_path.clone())),
+            xkey: self.xpub,
+            derivation_paths: DerivPaths::new(vec![
+                DerivationPath::from(vec![ChildNumber::from_normal_idx(0).expect("Normal")]),
+                DerivationPath::from(vec![ChildNumber::from_normal_idx(1).expect("Normal")]),
+            ])
+            .expect("Two derivation paths"),
+            wildcard: Wildcard::Unhardened,
+        })
+    }
+}
+
+impl fmt::Display for KeyRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.signed_message)?;
+        write!(f, "{}", self.signature.to_base64())
+    }
+}
+
+impl FromStr for KeyRecord {
+    type Err = BsmsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().map(|l| l.trim_end_matches('\r'));
+        let signed_message = lines.by_ref().take(4).collect::<Vec<_>>().join("\n");
+        let (token, fingerprint, derivation_path, xpub, description) =
+            Self::parse_message(&signed_message)?;
+        let signature = lines
+            .next()
+            .ok_or_else(|| BsmsError::Malformed("missing signature".to_string()))?;
+        let signature = MessageSignature::from_base64(signature.trim())
+            .map_err(|e| BsmsError::InvalidSignature(e.to_string()))?;
+        Ok(Self {
+            token,
+            fingerprint,
+            derivation_path,
+            xpub,
+            description,
+            signature,
+            signed_message,
+        })
+    }
+}
+
+/// The record the Coordinator sends to all signers, for them to check their key is part of the
+/// wallet and register it.
+///
+/// ```text
+/// BSMS 1.0
+/// <descriptor template>
+/// /0/*,/1/*
+/// <first receive address>
+/// ```
+///
+/// The keys for the receive and change keychains (`/<0;1>/*`) use the BIP129 `/**` notation.
+/// Keys using other derivation steps, as the ones reused across spending paths (for instance
+/// `/<2;3>/*`), can't be expressed with this notation and are kept as multipath keys. If no key
+/// uses `/<0;1>/*`, the record has no path restriction.
+pub fn descriptor_record(
+    desc: &LianaDescriptor,
+    network: bitcoin::Network,
+    secp: &secp256k1::Secp256k1<impl secp256k1::Verification>,
+) -> String {
+    let desc_str = desc.to_string();
+    let template = desc_str
+        .split('#')
+        .next()
+        .expect("Always at least one element")
+        .replace("/<0;1>/*", "/**");
+    let restrictions = if template.contains("/**") {
+        "/0/*,/1/*"
+    } else {
+        "No path restrictions"
+    };
+    let first_address = desc
+        .receive_descriptor()
+        .derive(0.into(), secp)
+        .address(network);
+    format!("{BSMS_VERSION}\n{template}\n{restrictions}\n{first_address}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptors::{import_descriptor, LianaPolicy, PathInfo};
+    use std::collections::BTreeMap;
+
+    fn master_xpriv(seed: u8) -> bip32::Xpriv {
+        bip32::Xpriv::new_master(bitcoin::Network::Testnet, &[seed; 32]).unwrap()
+    }
+
+    #[test]
+    fn bsms_token() {
+        assert_eq!(Token::from_str("00").unwrap(), Token::NoEncryption);
+        let token = Token::from_str("1D2B3C4D5E6F7A8B").unwrap();
+        assert!(token.is_encrypted());
+        assert_eq!(token.to_string(), "1D2B3C4D5E6F7A8B");
+        let token = Token::from_str("1d2b3c4d5e6f7a8b1d2b3c4d5e6f7a8b").unwrap();
+        assert!(matches!(token, Token::Extended(_)));
+        Token::from_str("0").unwrap_err();
+        Token::from_str("1d2b3c4d5e6f7a").unwrap_err();
+        Token::from_str("zz2b3c4d5e6f7a8b").unwrap_err();
+
+        let token = Token::generate().unwrap();
+        assert_eq!(Token::from_str(&token.to_string()).unwrap(), token);
+    }
+
+    #[test]
+    fn bsms_key_record() {
+        let secp = secp256k1::Secp256k1::new();
+        let path = DerivationPath::from_str("m/48'/1'/0'/2'").unwrap();
+        let record = KeyRecord::new_signed(
+            &secp,
+            Token::NoEncryption,
+            &master_xpriv(1),
+            path.clone(),
+            "Alice's signer".to_string(),
+        )
+        .unwrap();
+        record.verify(&secp, &Token::NoEncryption).unwrap();
+
+        // Round trip through the text format.
+        let record_str = record.to_string();
+        assert_eq!(record_str.lines().count(), 5);
+        assert!(record_str.starts_with("BSMS 1.0\n00\n["));
+        assert!(record_str.contains("/48'/1'/0'/2']tpub"));
+        let parsed = KeyRecord::from_str(&record_str).unwrap();
+        assert_eq!(parsed, record);
+        parsed.verify(&secp, &Token::NoEncryption).unwrap();
+        assert_eq!(
+            parsed.multipath_key().to_string(),
+            format!(
+                "[{}/48'/1'/0'/2']{}/<0;1>/*",
+                record.fingerprint, record.xpub
+            )
+        );
+
+        // Tampering with the description or the key invalidates the signature.
+        let tampered = record_str.replace("Alice's signer", "Mallory's signer");
+        let tampered = KeyRecord::from_str(&tampered).unwrap();
+        assert!(matches!(
+            tampered.verify(&secp, &Token::NoEncryption),
+            Err(BsmsError::InvalidSignature(_))
+        ));
+        let other = KeyRecord::new_signed(
+            &secp,
+            Token::NoEncryption,
+            &master_xpriv(2),
+            path.clone(),
+            "Alice's signer".to_string(),
+        )
+        .unwrap();
+        let mut tampered = record.clone();
+        tampered.xpub = other.xpub;
+        assert!(matches!(
+            tampered.verify(&secp, &Token::NoEncryption),
+            Err(BsmsError::InvalidSignature(_))
+        ));
+
+        // A record from another session is refused.
+        let session = Token::Standard([1; 8]);
+        assert!(matches!(
+            record.verify(&secp, &session),
+            Err(BsmsError::TokenMismatch { .. })
+        ));
+
+        // A record can be created for a generated session token, with an empty description.
+        let session = Token::generate().unwrap();
+        let session_record =
+            KeyRecord::new_signed(&secp, session, &master_xpriv(1), path.clone(), "".into())
+                .unwrap();
+        let parsed = KeyRecord::from_str(&session_record.to_string()).unwrap();
+        assert_eq!(parsed, session_record);
+        parsed.verify(&secp, &session).unwrap();
+        assert!(matches!(
+            parsed.verify(&secp, &Token::NoEncryption),
+            Err(BsmsError::TokenMismatch { .. })
+        ));
+
+        // The signature is checked against the record as it was written by the signer, which may
+        // be formatted differently than we would.
+        let master = master_xpriv(1);
+        let xpriv = master.derive_priv(&secp, &path).unwrap();
+        let message = format!(
+            "BSMS 1.0\r\n00\r\n[{}/48h/1h/0h/2h]{}\r\nAlice's signer ",
+            master.fingerprint(&secp),
+            bip32::Xpub::from_priv(&secp, &xpriv)
+        );
+        let msg_hash = signed_msg_hash(&message.replace('\r', ""));
+        let msg = secp256k1::Message::from_digest(msg_hash.to_byte_array());
+        let sig = secp.sign_ecdsa_recoverable(&msg, &xpriv.private_key);
+        let signer_record = format!(
+            "{message}\r\n{}\r\n",
+            MessageSignature::new(sig, true).to_base64()
+        );
+        let parsed = KeyRecord::from_str(&signer_record).unwrap();
+        assert_eq!(parsed.derivation_path, path);
+        assert_eq!(parsed.description, "Alice's signer ");
+        parsed.verify(&secp, &Token::NoEncryption).unwrap();
+        let mut tampered = parsed.clone();
+        tampered.description = "Mallory's signer".to_string();
+        assert!(matches!(
+            tampered.verify(&secp, &Token::NoEncryption),
+            Err(BsmsError::InvalidSignature(_))
+        ));
+
+        // Long descriptions and keys without origin are not supported.
+        assert!(matches!(
+            KeyRecord::new_signed(
+                &secp,
+                Token::NoEncryption,
+                &master_xpriv(1),
+                path,
+                "a".repeat(81)
+            ),
+            Err(BsmsError::DescriptionTooLong(81))
+        ));
+        let no_origin = format!(
+            "BSMS 1.0\n00\n{}\nA\n{}",
+            record.xpub,
+            record.signature.to_base64()
+        );
+        assert!(matches!(
+            KeyRecord::from_str(&no_origin),
+            Err(BsmsError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            KeyRecord::from_str(&record_str.replace("BSMS 1.0", "BSMS 2.0")),
+            Err(BsmsError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            KeyRecord::from_str("BSMS 1.0\n00\n"),
+            Err(BsmsError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn bsms_descriptor_record() {
+        let secp = secp256k1::Secp256k1::new();
+        let path = DerivationPath::from_str("m/48'/1'/0'/2'").unwrap();
+        let keys: Vec<DescriptorPublicKey> = (1..4)
+            .map(|seed| {
+                let record = KeyRecord::new_signed(
+                    &secp,
+                    Token::NoEncryption,
+                    &master_xpriv(seed),
+                    path.clone(),
+                    format!("Signer {seed}"),
+                )
+                .unwrap();
+                KeyRecord::from_str(&record.to_string())
+                    .unwrap()
+                    .multipath_key()
+            })
+            .collect();
+        let policy = LianaPolicy::new_legacy(
+            PathInfo::Multi(2, keys[..2].to_vec()),
+            BTreeMap::from([(52560, PathInfo::Single(keys[2].clone()))]),
+        )
+        .unwrap();
+        let desc = LianaDescriptor::new(policy);
+
+        let record = descriptor_record(&desc, bitcoin::Network::Testnet, &secp);
+        let lines: Vec<&str> = record.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "BSMS 1.0");
+        assert!(lines[1].starts_with("wsh(") && lines[1].contains("/**"));
+        assert!(!lines[1].contains('#') && !lines[1].contains("<0;1>"));
+        assert_eq!(lines[2], "/0/*,/1/*");
+        assert!(lines[3].starts_with("tb1q"));
+
+        // Signers (and Liana itself) can import it back.
+        assert_eq!(import_descriptor(&record).unwrap(), desc);
+
+        // Keys derived from other steps than /<0;1>/* are kept as multipath keys.
+        let recovery_key =
+            DescriptorPublicKey::from_str(&keys[2].to_string().replace("/<0;1>/*", "/<2;3>/*"))
+                .unwrap();
+        let policy = LianaPolicy::new_legacy(
+            PathInfo::Multi(2, keys[..2].to_vec()),
+            BTreeMap::from([(52560, PathInfo::Single(recovery_key))]),
+        )
+        .unwrap();
+        let desc = LianaDescriptor::new(policy);
+        let record = descriptor_record(&desc, bitcoin::Network::Testnet, &secp);
+        let lines: Vec<&str> = record.lines().collect();
+        assert!(lines[1].contains("/**") && lines[1].contains("/<2;3>/*"));
+        assert_eq!(lines[2], "/0/*,/1/*");
+        assert_eq!(import_descriptor(&record).unwrap(), desc);
+    }
+}
@@ -688,6 +688,26 @@ impl LianaPolicy {
         &self.recovery_paths
     }
 
+    /// Whether this policy is for use under a Taproot context.
+    pub fn is_taproot(&self) -> bool {
+        self.is_taproot
+    }
+
+    /// Get the same spending policy for use under a Taproot (if `is_taproot` is set) or P2WSH
+    /// context. This may fail as some policies are only valid under one context (for instance a
+    /// multisig with more than 20 keys under P2WSH).
+    pub fn with_taproot(self, is_taproot: bool) -> Result<LianaPolicy, LianaPolicyError> {
+        if self.is_taproot == is_taproot {
+            return Ok(self);
+        }
+        Self::_new(
+            self.primary_path,
+            self.recovery_paths,
+            is_taproot,
+            /* compile = */ true,
+        )
+    }
+
     fn into_policy(
         self,
     ) -> Result<miniscript::policy::Concrete<descriptor::DescriptorPublicKey>, LianaPolicyError>
@@ -0,0 +1,845 @@
+//! Import wallet configurations exported by other coordinators.
+//!
+//! Other coordinators (Sparrow, Specter, Nunchuk, Caravan, Coldcard, ..) export their wallets in
+//! various formats, which most of the time describe a descriptor that doesn't have the exact shape
+//! Liana expects. This module recognizes those formats and converts the equivalent structures to a
+//! Liana descriptor. If the wallet can't be expressed as a Liana descriptor, it tells why.
+
+use miniscript::{
+    bitcoin::{
+        self,
+        bip32::{self, ChildNumber, DerivationPath, Fingerprint},
+        secp256k1,
+    },
+    descriptor::{checksum, DerivPaths, DescriptorMultiXKey, DescriptorType, Wildcard},
+    policy::{Liftable, Semantic as SemanticPolicy},
+    translate_hash_clone, Descriptor, DescriptorPublicKey, TranslateErr, TranslatePk, Translator,
+};
+
+use std::{error, fmt, str::FromStr};
+
+use super::{LianaDescError, LianaDescriptor, LianaPolicyError};
+
+// The flag in a relative timelock's consensus encoding which indicates it is time-based.
+const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+
+#[derive(Debug)]
+pub enum DescImportError {
+    /// The format of the wallet configuration wasn't recognized.
+    UnknownFormat,
+    /// The wallet configuration was recognized but is malformed.
+    Malformed(String),
+    /// The wallet configuration contains more than one wallet.
+    Ambiguous,
+    Miniscript(miniscript::Error),
+    /// A key can't be converted to a multipath key as used by Liana.
+    UnsupportedKey(String),
+    /// The receive and change descriptors don't describe the same wallet.
+    MismatchingChangeDescriptor,
+    UnsupportedScriptType(&'static str),
+    /// The wallet doesn't have any timelocked recovery path.
+    NoRecoveryPath {
+        threshold: usize,
+        keys: Vec<DescriptorPublicKey>,
+    },
+    AbsoluteTimelock,
+    /// A relative timelock expressed in time rather than in blocks.
+    TimeBasedTimelock(u32),
+    HashLock,
+    Policy(LianaPolicyError),
+    /// The first address provided in the configuration doesn't match the one we derive.
+    FirstAddressMismatch {
+        expected: String,
+        derived: String,
+    },
+    /// The checksum of a descriptor doesn't match its content.
+    InvalidChecksum {
+        expected: String,
+        found: String,
+    },
+}
+
+impl std::fmt::Display for DescImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnknownFormat => write!(
+                f,
+                "Unrecognized wallet configuration. Supported formats are output descriptors \
+                (either a single multipath descriptor or a receive and a change descriptor), \
+                BSMS descriptor records, Coldcard multisig setup files and the JSON exports of \
+                Specter, Caravan or Bitcoin Core."
+            ),
+            Self::Malformed(e) => write!(f, "Malformed wallet configuration: {e}"),
+            Self::Ambiguous => write!(
+                f,
+                "The wallet configuration contains more than one wallet. Please only provide the \
+                receive and change descriptors of the wallet to import."
+            ),
+            Self::Miniscript(e) => write!(f, "Miniscript error: '{e}'."),
+            Self::UnsupportedKey(key) => write!(
+                f,
+                "Key '{key}' can't be used in Liana. Keys must be extended public keys with an \
+                origin (the fingerprint of the signing device), using derivation paths ending \
+                with /0/* for receiving and /1/* for change."
+            ),
+            Self::MismatchingChangeDescriptor => write!(
+                f,
+                "The receive and change descriptors don't describe the same wallet. They must only \
+                differ by their derivation steps: /0/* for receiving and /1/* for change."
+            ),
+            Self::UnsupportedScriptType(t) => write!(
+                f,
+                "Liana only supports P2WSH and Taproot wallets, this is a {t} wallet. You would \
+                need to create a new Liana wallet and move the funds to it."
+            ),
+            Self::NoRecoveryPath { threshold, keys } => write!(
+                f,
+                "This wallet is a {threshold}-of-{} without any timelocked recovery path, but \
+                Liana requires at least one. The existing addresses can't be used by a Liana \
+                wallet. You may create a new Liana wallet using the same keys for the primary \
+                path and add a recovery path, then move the funds to it.",
+                keys.len()
+            ),
+            Self::AbsoluteTimelock => write!(
+                f,
+                "The wallet uses an absolute timelock (after()). Liana only supports relative \
+                timelocks (older()), expressed in blocks."
+            ),
+            Self::TimeBasedTimelock(value) => write!(
+                f,
+                "The wallet uses a relative timelock expressed in time (older({value})). Liana only \
+                supports relative timelocks expressed in blocks."
+            ),
+            Self::HashLock => write!(
+                f,
+                "The wallet uses hash preimage conditions, which are not supported by Liana."
+            ),
+            Self::Policy(e) => write!(f, "The wallet's policy can't be expressed in Liana: {e}"),
+            Self::FirstAddressMismatch { expected, derived } => write!(
+                f,
+                "The first address of the wallet configuration ({expected}) doesn't match the \
+                first address of the imported descriptor ({derived}). Please check the wallet \
+                configuration wasn't tampered with."
+            ),
+            Self::InvalidChecksum { expected, found } => write!(
+                f,
+                "Invalid descriptor checksum '{found}', expected '{expected}'. The descriptor may \
+                have been corrupted."
+            ),
+        }
+    }
+}
+
+impl error::Error for DescImportError {}
+
+/// Import a wallet configuration exported by another coordinator and convert it to a Liana
+/// descriptor. The following formats are recognized:
+/// - One or more output descriptors, one per line. It can either be a single multipath descriptor,
+///   a single receive descriptor (using `/0/*`) or a receive and a change descriptor (using `/1/*`).
+///   Lines starting with `#` are ignored, and if one of the descriptors is multipath the others are
+///   ignored (as exported by Sparrow).
+/// - A BSMS descriptor record (BIP129), as exported by Nunchuk.
+/// - A Coldcard multisig setup file.
+/// - A Caravan wallet configuration (JSON).
+/// - A JSON object with a `descriptor` field (as exported by Specter) or a `descriptors` array
+///   (as exported by Bitcoin Core's `listdescriptors`).
+pub fn import_descriptor(config: &str) -> Result<LianaDescriptor, DescImportError> {
+    let config = config.trim();
+    if config.starts_with('{') {
+        import_json(config)
+    } else if config.starts_with("BSMS") {
+        import_bsms(config)
+    } else if config
+        .lines()
+        .any(|l| l.trim_start().to_lowercase().starts_with("policy:"))
+    {
+        import_coldcard(config)
+    } else {
+        let descs: Vec<(&str, Option<bool>)> = config
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| (l, None))
+            .collect();
+        import_descriptors(&descs)
+    }
+}
+
+// Import a wallet from a set of descriptors. Either a multipath descriptor, a receive descriptor
+// or a receive and a change descriptor, in any order. Each comes with its `internal` flag if the
+// coordinator exported it, which tells whether it is the change descriptor.
+fn import_descriptors(descs: &[(&str, Option<bool>)]) -> Result<LianaDescriptor, DescImportError> {
+    if descs.is_empty() {
+        return Err(DescImportError::UnknownFormat);
+    }
+    let descs = descs
+        .iter()
+        .map(|(s, internal)| {
+            // Some coordinators use the BIP129 notation for multipath keys. The checksum is
+            // computed on the original notation, so check it before converting.
+            let s = strip_checksum(s)?;
+            Descriptor::<DescriptorPublicKey>::from_str(&s.replace("/**", "/<0;1>/*"))
+                .map(|desc| (desc, *internal))
+                .map_err(DescImportError::Miniscript)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // If there is a multipath descriptor, it describes the whole wallet.
+    let mut multi_descs = descs
+        .iter()
+        .map(|(desc, _)| desc)
+        .filter(|d| d.is_multipath());
+    let desc = if let Some(desc) = multi_descs.next() {
+        if multi_descs.any(|d| d != desc) {
+            return Err(DescImportError::Ambiguous);
+        }
+        to_multipath(desc, 0)?
+    } else if descs.len() == 1 {
+        // Only a receive descriptor. Assume change is derived using /1/*.
+        to_multipath(&descs[0].0, 0)?
+    } else if descs.len() == 2 {
+        // Tell the change descriptor by its `internal` flag if there is one, or else by its keys
+        // being derived using /1/* instead of /0/*.
+        let is_change = |(desc, internal): &(Descriptor<DescriptorPublicKey>, Option<bool>)| {
+            internal
+                .unwrap_or_else(|| to_multipath(desc, 0).is_err() && to_multipath(desc, 1).is_ok())
+        };
+        let (receive, change) = if is_change(&descs[0]) && !is_change(&descs[1]) {
+            (&descs[1].0, &descs[0].0)
+        } else {
+            (&descs[0].0, &descs[1].0)
+        };
+        let receive_desc = to_multipath(receive, 0)?;
+        let change_desc =
+            to_multipath(change, 1).map_err(|_| DescImportError::MismatchingChangeDescriptor)?;
+        if receive_desc != change_desc {
+            return Err(DescImportError::MismatchingChangeDescriptor);
+        }
+        receive_desc
+    } else {
+        return Err(DescImportError::Ambiguous);
+    };
+
+    LianaDescriptor::from_str(&desc.to_string()).map_err(|e| diagnose(&desc, e))
+}
+
+// Check the checksum of this descriptor, if it has one, and return the descriptor without it.
+fn strip_checksum(desc: &str) -> Result<&str, DescImportError> {
+    let (desc, found) = match desc.rsplit_once('#') {
+        Some(parts) => parts,
+        None => return Ok(desc),
+    };
+    let mut engine = checksum::Engine::new();
+    engine
+        .input(desc)
+        .map_err(|e| DescImportError::Malformed(e.to_string()))?;
+    let expected = engine.checksum();
+    if found != expected {
+        return Err(DescImportError::InvalidChecksum {
+            expected,
+            found: found.to_string(),
+        });
+    }
+    Ok(desc)
+}
+
+// Convert all single path keys derived from `.../<index>/*` in this descriptor to multipath keys
+// of the form `.../<0;1>/*`.
+fn to_multipath(
+    desc: &Descriptor<DescriptorPublicKey>,
+    index: u32,
+) -> Result<Descriptor<DescriptorPublicKey>, DescImportError> {
+    struct Multipather(ChildNumber);
+    impl Translator<DescriptorPublicKey, DescriptorPublicKey, DescImportError> for Multipather {
+        fn pk(&mut self, pk: &DescriptorPublicKey) -> Result<DescriptorPublicKey, DescImportError> {
+            match pk {
+                DescriptorPublicKey::MultiXPub(..) => Ok(pk.clone()),
+                DescriptorPublicKey::XPub(xpub) if xpub.wildcard == Wildcard::Unhardened => {
+                    match xpub.derivation_path.as_ref().split_last() {
+                        Some((last, prefix)) if *last == self.0 => {
+                            let derivation_paths = [0, 1]
+                                .iter()
+                                .map(|i| {
+                                    prefix
+                                        .iter()
+                                        .copied()
+                                        .chain(std::iter::once(ChildNumber::from(*i)))
+                                        .collect::<DerivationPath>()
+                                })
+                                .collect();
+                            Ok(DescriptorPublicKey::MultiXPub(DescriptorMultiXKey {
+                                origin: xpub.origin.clone(),
+                                xkey: xpub.xkey,
+                                derivation_paths: DerivPaths::new(derivation_paths)
+                                    .expect("Two paths"),
+                                wildcard: Wildcard::Unhardened,
+                            }))
+                        }
+                        _ => Err(DescImportError::UnsupportedKey(pk.to_string())),
+                    }
+                }
+                _ => Err(DescImportError::UnsupportedKey(pk.to_string())),
+            }
+        }
+        translate_hash_clone!(DescriptorPublicKey, DescriptorPublicKey, DescImportError);
+    }
+
+    desc.translate_pk(&mut Multipather(ChildNumber::from(index)))
+        .map_err(|e| match e {
+            TranslateErr::TranslatorErr(e) => e,
+            TranslateErr::OuterError(e) => DescImportError::Miniscript(e),
+        })
+}
+
+// Find out why this (multipath) descriptor isn't a valid Liana descriptor, in order to give an
+// actionable error to the user.
+fn diagnose(desc: &Descriptor<DescriptorPublicKey>, e: LianaDescError) -> DescImportError {
+    let script_type = match desc.desc_type() {
+        DescriptorType::Wsh | DescriptorType::WshSortedMulti | DescriptorType::Tr => None,
+        DescriptorType::ShWsh | DescriptorType::ShWshSortedMulti => Some("P2SH-P2WSH"),
+        DescriptorType::Wpkh | DescriptorType::ShWpkh => Some("single signature"),
+        _ => Some("legacy (non-Segwit)"),
+    };
+    if let Some(script_type) = script_type {
+        return DescImportError::UnsupportedScriptType(script_type);
+    }
+
+    let policy = match desc.lift() {
+        Ok(policy) => policy,
+        Err(e) => return DescImportError::Miniscript(e),
+    };
+    if let Some(e) = unsupported_fragment(&policy) {
+        return e;
+    }
+    if !has_relative_timelock(&policy) {
+        match policy {
+            SemanticPolicy::Key(key) => {
+                return DescImportError::NoRecoveryPath {
+                    threshold: 1,
+                    keys: vec![key],
+                }
+            }
+            SemanticPolicy::Thresh(ref thresh) => {
+                let keys: Option<Vec<_>> = thresh
+                    .data()
+                    .iter()
+                    .map(|sub| match sub.as_ref() {
+                        SemanticPolicy::Key(key) => Some(key.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                if let Some(keys) = keys {
+                    return DescImportError::NoRecoveryPath {
+                        threshold: thresh.k(),
+                        keys,
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+
+    match e {
+        LianaDescError::Policy(e) => DescImportError::Policy(e),
+        LianaDescError::Miniscript(e) => DescImportError::Miniscript(e),
+        e => DescImportError::Malformed(e.to_string()),
+    }
+}
+
+// Get the first spending condition not supported by Liana in this policy, if any.
+fn unsupported_fragment(policy: &SemanticPolicy<DescriptorPublicKey>) -> Option<DescImportError> {
+    match policy {
+        SemanticPolicy::After(..) => Some(DescImportError::AbsoluteTimelock),
+        SemanticPolicy::Older(value) => {
+            let value = value.to_consensus_u32();
+            (value & SEQUENCE_LOCKTIME_TYPE_FLAG != 0)
+                .then_some(DescImportError::TimeBasedTimelock(value))
+        }
+        SemanticPolicy::Sha256(..)
+        | SemanticPolicy::Hash256(..)
+        | SemanticPolicy::Ripemd160(..)
+        | SemanticPolicy::Hash160(..) => Some(DescImportError::HashLock),
+        SemanticPolicy::Thresh(thresh) => thresh
+            .data()
+            .iter()
+            .find_map(|sub| unsupported_fragment(sub.as_ref())),
+        _ => None,
+    }
+}
+
+fn has_relative_timelock(policy: &SemanticPolicy<DescriptorPublicKey>) -> bool {
+    match policy {
+        SemanticPolicy::Older(..) => true,
+        SemanticPolicy::Thresh(thresh) => thresh
+            .data()
+            .iter()
+            .any(|sub| has_relative_timelock(sub.as_ref())),
+        _ => false,
+    }
+}
+
+// Import a BSMS (BIP129) descriptor record. It is of the form:
+// ```text
+// BSMS 1.0
+// <descriptor template>
+// <path restrictions>
+// <first address>
+// ```
+fn import_bsms(config: &str) -> Result<LianaDescriptor, DescImportError> {
+    let mut lines = config.lines().map(|l| l.trim()).filter(|l| !l.is_empty());
+    let version = lines.next().expect("Starts with BSMS");
+    if version != "BSMS 1.0" {
+        return Err(DescImportError::Malformed(format!(
+            "unsupported BSMS version '{version}'"
+        )));
+    }
+    let template = lines
+        .next()
+        .ok_or_else(|| DescImportError::Malformed("missing descriptor template".to_string()))?;
+    let restrictions = lines
+        .next()
+        .ok_or_else(|| DescImportError::Malformed("missing path restrictions".to_string()))?;
+    if restrictions != "/0/*,/1/*" && restrictions != "No path restrictions" {
+        return Err(DescImportError::Malformed(format!(
+            "unsupported path restrictions '{restrictions}', only '/0/*,/1/*' is supported"
+        )));
+    }
+    let first_address = lines
+        .next()
+        .ok_or_else(|| DescImportError::Malformed("missing first address".to_string()))?;
+    let first_address = bitcoin::Address::from_str(first_address)
+        .map_err(|e| DescImportError::Malformed(format!("invalid first address: {e}")))?;
+    let network = [
+        bitcoin::Network::Bitcoin,
+        bitcoin::Network::Testnet,
+        bitcoin::Network::Regtest,
+    ]
+    .iter()
+    .copied()
+    .find(|net| first_address.is_valid_for_network(*net))
+    .unwrap_or(bitcoin::Network::Bitcoin);
+    let first_address = first_address.assume_checked();
+
+    let desc = import_descriptors(&[(template, None)])?;
+    let secp = secp256k1::Secp256k1::verification_only();
+    let derived_address = desc
+        .receive_descriptor()
+        .derive(0.into(), &secp)
+        .address(network);
+    if derived_address.script_pubkey() != first_address.script_pubkey() {
+        return Err(DescImportError::FirstAddressMismatch {
+            expected: first_address.to_string(),
+            derived: derived_address.to_string(),
+        });
+    }
+
+    Ok(desc)
+}
+
+// Import a Coldcard multisig setup file. It is of the form:
+// ```text
+// # Coldcard Multisig setup file
+// Name: MyWallet
+// Policy: 2 of 3
+// Derivation: m/48'/0'/0'/2'
+// Format: P2WSH
+//
+// 0F056943: xpub...
+// 6BA6CFD0: xpub...
+// ```
+// The derivation may also be given before each key if they differ.
+fn import_coldcard(config: &str) -> Result<LianaDescriptor, DescImportError> {
+    let (mut threshold, mut format) = (None, None);
+    let mut derivation = None;
+    let mut keys = Vec::new();
+    for line in config.lines().map(|l| l.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once(':')
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .ok_or_else(|| DescImportError::Malformed(format!("invalid line '{line}'")))?;
+        match key.to_lowercase().as_str() {
+            "name" => {}
+            "policy" => {
+                threshold = value
+                    .split_once("of")
+                    .and_then(|(m, _)| m.trim().parse::<usize>().ok())
+                    .map(Some)
+                    .ok_or_else(|| {
+                        DescImportError::Malformed(format!("invalid policy '{value}'"))
+                    })?;
+            }
+            "derivation" => {
+                derivation = Some(DerivationPath::from_str(value).map_err(|e| {
+                    DescImportError::Malformed(format!("invalid derivation '{value}': {e}"))
+                })?);
+            }
+            "format" => format = Some(value.to_uppercase()),
+            fg => {
+                let fg = Fingerprint::from_str(fg)
+                    .map_err(|_| DescImportError::Malformed(format!("invalid line '{line}'")))?;
+                let der_path = derivation
+                    .as_ref()
+                    .ok_or_else(|| DescImportError::Malformed("missing derivation".to_string()))?;
+                keys.push(multipath_key(fg, der_path, &parse_xpub(value)?));
+            }
+        }
+    }
+
+    let threshold =
+        threshold.ok_or_else(|| DescImportError::Malformed("missing policy".to_string()))?;
+    let desc = match format.as_deref() {
+        None | Some("P2WSH") => format!("wsh(sortedmulti({threshold},{}))", keys.join(",")),
+        Some("P2SH-P2WSH") | Some("P2WSH-P2SH") => {
+            format!("sh(wsh(sortedmulti({threshold},{})))", keys.join(","))
+        }
+        Some("P2SH") => format!("sh(sortedmulti({threshold},{}))", keys.join(",")),
+        Some(f) => {
+            return Err(DescImportError::Malformed(format!(
+                "unknown address format '{f}'"
+            )))
+        }
+    };
+    import_descriptors(&[(desc.as_str(), None)])
+}
+
+// Import a wallet configuration in JSON. Either from Caravan, or an object containing the
+// descriptor(s).
+fn import_json(config: &str) -> Result<LianaDescriptor, DescImportError> {
+    let json: serde_json::Value = serde_json::from_str(config)
+        .map_err(|e| DescImportError::Malformed(format!("invalid JSON: {e}")))?;
+
+    // Caravan
+    if let Some(xpubs) = json.get("extendedPublicKeys") {
+        return import_caravan(&json, xpubs);
+    }
+
+    // Specter
+    if let Some(desc) = json.get("descriptor").and_then(|d| d.as_str()) {
+        return import_descriptors(&[(desc, None)]);
+    }
+
+    // Bitcoin Core's listdescriptors. The entries are objects with a 'desc' field and an
+    // 'internal' flag set for the change descriptor, but also accept plain strings.
+    if let Some(entries) = json.get("descriptors").and_then(|d| d.as_array()) {
+        let descs = entries
+            .iter()
+            .map(|entry| {
+                let internal = entry.get("internal").and_then(|i| i.as_bool());
+                entry
+                    .as_str()
+                    .or_else(|| entry.get("desc").and_then(|d| d.as_str()))
+                    .map(|desc| (desc, internal))
+                    .ok_or_else(|| DescImportError::Malformed("invalid descriptor".to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        return import_descriptors(&descs);
+    }
+
+    Err(DescImportError::UnknownFormat)
+}
+
+fn import_caravan(
+    json: &serde_json::Value,
+    xpubs: &serde_json::Value,
+) -> Result<LianaDescriptor, DescImportError> {
+    let threshold = json
+        .get("quorum")
+        .and_then(|q| q.get("requiredSigners"))
+        .and_then(|n| n.as_u64())
+        .ok_or_else(|| DescImportError::Malformed("missing quorum".to_string()))?;
+    let keys = xpubs
+        .as_array()
+        .ok_or_else(|| DescImportError::Malformed("invalid extended public keys".to_string()))?
+        .iter()
+        .map(|entry| {
+            let field = |name: &str| {
+                entry.get(name).and_then(|v| v.as_str()).ok_or_else(|| {
+                    DescImportError::Malformed(format!("missing '{name}' for a key"))
+                })
+            };
+            let xpub = parse_xpub(field("xpub")?)?;
+            // Caravan uses 'Unknown' paths and fingerprints when the signing device is not known.
+            let fg = Fingerprint::from_str(field("xfp")?)
+                .map_err(|_| DescImportError::UnsupportedKey(xpub.to_string()))?;
+            let der_path = DerivationPath::from_str(field("bip32Path")?)
+                .map_err(|_| DescImportError::UnsupportedKey(xpub.to_string()))?;
+            Ok(multipath_key(fg, &der_path, &xpub))
+        })
+        .collect::<Result<Vec<_>, DescImportError>>()?;
+
+    let desc = match json.get("addressType").and_then(|t| t.as_str()) {
+        None | Some("P2WSH") => format!("wsh(sortedmulti({threshold},{}))", keys.join(",")),
+        Some("P2SH-P2WSH") => format!("sh(wsh(sortedmulti({threshold},{})))", keys.join(",")),
+        Some("P2SH") => format!("sh(sortedmulti({threshold},{}))", keys.join(",")),
+        Some(t) => {
+            return Err(DescImportError::Malformed(format!(
+                "unknown address type '{t}'"
+            )))
+        }
+    };
+    import_descriptors(&[(desc.as_str(), None)])
+}
+
+// The string representation of a multipath key with the given origin.
+fn multipath_key(fg: Fingerprint, der_path: &DerivationPath, xpub: &bip32::Xpub) -> String {
+    let der_path: String = der_path
+        .into_iter()
+        .map(|step| format!("/{step}"))
+        .collect();
+    format!("[{fg}{der_path}]{xpub}/<0;1>/*")
+}
+
+// Parse an extended public key, also accepting the SLIP132 versions used by some coordinators
+// (ypub, zpub, Ypub, Zpub, ..).
+fn parse_xpub(s: &str) -> Result<bip32::Xpub, DescImportError> {
+    let invalid = || DescImportError::Malformed(format!("invalid extended public key '{s}'"));
+    let mut data = bitcoin::base58::decode_check(s).map_err(|_| invalid())?;
+    if data.len() != 78 {
+        return Err(invalid());
+    }
+    let version = match &data[..4] {
+        // ypub, zpub, Ypub, Zpub
+        [0x04, 0x9d, 0x7c, 0xb2]
+        | [0x04, 0xb2, 0x47, 0x46]
+        | [0x02, 0x95, 0xb4, 0x3f]
+        | [0x02, 0xaa, 0x7e, 0xd3] => Some([0x04, 0x88, 0xb2, 0x1e]),
+        // upub, vpub, Upub, Vpub
+        [0x04, 0x4a, 0x52, 0x62]
+        | [0x04, 0x5f, 0x1c, 0xf6]
+        | [0x02, 0x42, 0x89, 0xef]
+        | [0x02, 0x57, 0x54, 0x83] => Some([0x04, 0x35, 0x87, 0xcf]),
+        _ => None,
+    };
+    if let Some(version) = version {
+        data[..4].copy_from_slice(&version);
+    }
+    bip32::Xpub::decode(&data).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XPUB_A: &str = "xpub6FC8vmQGGfSuQGfKG5L73fZ7WjXit8TzfJYDKwTtHkhrbAhU5Kma41oenVq6aMnpgULJRXpQuxnVysyfdpRhVgD6vYe7XLbFDhmvYmDrAVq";
+    const ZPUB_A: &str = "Zpub75kkfLUY8z6EXSCvsTNLHv5vaTrsyj8aooEKozXCRYJ2KYtqM3UyPFyrdchk8cKZjDdto51eiVt6scpZNSQgEdgyVh9N6ZhDetB5urq3HUR";
+    const XPUB_B: &str = "xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW";
+    const XPUB_C: &str = "xpub661MyMwAqRbcExaDFtcC1pVGounzi9bmVb4nBxVr3reFEsFpCTn5VVwuDiUFeJkJtppEC7Gzk3cW8htEB9Q3DcXV28SAHioi2oJZv6oTobF";
+
+    // A Liana descriptor with the given derivation steps for the keys.
+    fn liana_desc(steps: &str) -> String {
+        format!("wsh(or_d(pk([aabbcc01/48'/0'/0'/2']{XPUB_A}/{steps}/*),and_v(v:pkh([aabbcc02/48'/0'/0'/2']{XPUB_B}/{steps}/*),older(52560))))")
+    }
+
+    #[test]
+    fn descriptors_import() {
+        let expected = LianaDescriptor::from_str(&liana_desc("<0;1>")).unwrap();
+
+        // A Liana descriptor is imported as is, with or without checksum.
+        assert_eq!(import_descriptor(&liana_desc("<0;1>")).unwrap(), expected);
+        assert_eq!(import_descriptor(&expected.to_string()).unwrap(), expected);
+
+        // A corrupted descriptor is detected through its checksum.
+        let (desc_str, checksum) = expected
+            .to_string()
+            .rsplit_once('#')
+            .map(|(d, c)| (d.to_string(), c.to_string()))
+            .unwrap();
+        let corrupted = desc_str.replace("older(52560)", "older(52561)");
+        match import_descriptor(&format!("{corrupted}#{checksum}")) {
+            Err(DescImportError::InvalidChecksum { found, .. }) => assert_eq!(found, checksum),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let bad_checksum =
+            if checksum.starts_with('q') { "p" } else { "q" }.to_string() + &checksum[1..];
+        assert!(matches!(
+            import_descriptor(&format!("{desc_str}#{bad_checksum}")),
+            Err(DescImportError::InvalidChecksum { .. })
+        ));
+        // A valid checksum on a single path descriptor is accepted.
+        let receive_desc = Descriptor::<DescriptorPublicKey>::from_str(&liana_desc("0")).unwrap();
+        assert_eq!(
+            import_descriptor(&receive_desc.to_string()).unwrap(),
+            expected
+        );
+
+        // The BIP129 multipath notation is accepted.
+        assert_eq!(
+            import_descriptor(&liana_desc("<0;1>").replace("/<0;1>/*", "/**")).unwrap(),
+            expected
+        );
+
+        // A receive descriptor alone, or along with a change descriptor.
+        assert_eq!(import_descriptor(&liana_desc("0")).unwrap(), expected);
+        assert_eq!(
+            import_descriptor(&format!("{}\n{}\n", liana_desc("0"), liana_desc("1"))).unwrap(),
+            expected
+        );
+
+        // Comments are ignored, and the multipath descriptor takes precedence (Sparrow export).
+        let sparrow = format!(
+            "# Receive and change descriptor (BIP389):\n{}\n\n# Receive descriptor (Bitcoin Core):\n{}\n\n# Change descriptor (Bitcoin Core):\n{}\n",
+            expected,
+            liana_desc("0"),
+            liana_desc("1")
+        );
+        assert_eq!(import_descriptor(&sparrow).unwrap(), expected);
+
+        // The change descriptor may come first.
+        assert_eq!(
+            import_descriptor(&format!("{}\n{}\n", liana_desc("1"), liana_desc("0"))).unwrap(),
+            expected
+        );
+
+        // The change descriptor must match the receive descriptor.
+        assert!(matches!(
+            import_descriptor(&format!("{}\n{}", liana_desc("0"), liana_desc("2"))),
+            Err(DescImportError::MismatchingChangeDescriptor)
+        ));
+        assert!(matches!(
+            import_descriptor(&format!(
+                "{}\n{}",
+                liana_desc("0"),
+                liana_desc("1").replace("older(52560)", "older(52561)")
+            )),
+            Err(DescImportError::MismatchingChangeDescriptor)
+        ));
+
+        // Unsupported keys and policies are reported.
+        assert!(matches!(
+            import_descriptor(&liana_desc("0").replace("[aabbcc01/48'/0'/0'/2']", "")),
+            Err(DescImportError::Policy(LianaPolicyError::InvalidKey(..)))
+        ));
+        assert!(matches!(
+            import_descriptor(&liana_desc("0").replace("older(52560)", "after(840000)")),
+            Err(DescImportError::AbsoluteTimelock)
+        ));
+        assert!(matches!(
+            import_descriptor(&liana_desc("0").replace("older(52560)", "older(4194305)")),
+            Err(DescImportError::TimeBasedTimelock(4194305))
+        ));
+        assert!(matches!(
+            import_descriptor(&format!("sh({})", liana_desc("0"))),
+            Err(DescImportError::UnsupportedScriptType("P2SH-P2WSH"))
+        ));
+        assert!(matches!(
+            import_descriptor("wsh(pk(invalid))"),
+            Err(DescImportError::Miniscript(..))
+        ));
+        assert!(matches!(
+            import_descriptor(""),
+            Err(DescImportError::UnknownFormat)
+        ));
+
+        // A multisig without timelock, as used by most coordinators.
+        match import_descriptor(&format!("wsh(sortedmulti(2,[aabbcc01/48'/0'/0'/2']{XPUB_A}/0/*,[aabbcc02/48'/0'/0'/2']{XPUB_B}/0/*,[aabbcc03/48'/0'/0'/2']{XPUB_C}/0/*))")) {
+            Err(DescImportError::NoRecoveryPath { threshold, keys }) => {
+                assert_eq!(threshold, 2);
+                assert_eq!(keys.len(), 3);
+                assert!(keys.iter().all(|k| k.is_multipath()));
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn bsms_import() {
+        let expected = LianaDescriptor::from_str(&liana_desc("<0;1>")).unwrap();
+        let secp = secp256k1::Secp256k1::verification_only();
+        let first_address = expected
+            .receive_descriptor()
+            .derive(0.into(), &secp)
+            .address(bitcoin::Network::Bitcoin);
+        let template = liana_desc("<0;1>").replace("/<0;1>/*", "/**");
+        let bsms = format!("BSMS 1.0\n{template}\n/0/*,/1/*\n{first_address}\n");
+        assert_eq!(import_descriptor(&bsms).unwrap(), expected);
+
+        // The first address must match.
+        let other_address = expected
+            .receive_descriptor()
+            .derive(1.into(), &secp)
+            .address(bitcoin::Network::Bitcoin);
+        let bsms = format!("BSMS 1.0\n{template}\n/0/*,/1/*\n{other_address}\n");
+        assert!(matches!(
+            import_descriptor(&bsms),
+            Err(DescImportError::FirstAddressMismatch { .. })
+        ));
+
+        // Only version 1.0 and the standard path restrictions are supported.
+        let bsms = format!("BSMS 1.1\n{template}\n/0/*,/1/*\n{first_address}\n");
+        assert!(matches!(
+            import_descriptor(&bsms),
+            Err(DescImportError::Malformed(..))
+        ));
+        let bsms = format!("BSMS 1.0\n{template}\n/0/*,/2/*\n{first_address}\n");
+        assert!(matches!(
+            import_descriptor(&bsms),
+            Err(DescImportError::Malformed(..))
+        ));
+    }
+
+    #[test]
+    fn coldcard_and_caravan_import() {
+        let coldcard = format!(
+            "# Coldcard Multisig setup file (created on AABBCC01)\n#\nName: CC-2-of-3\nPolicy: 2 of 3\nDerivation: m/48'/0'/0'/2'\nFormat: P2WSH\n\nAABBCC01: {ZPUB_A}\nAABBCC02: {XPUB_B}\nDerivation: m/48h/0h/1h/2h\naabbcc03: {XPUB_C}\n"
+        );
+        match import_descriptor(&coldcard) {
+            Err(DescImportError::NoRecoveryPath { threshold, keys }) => {
+                assert_eq!(threshold, 2);
+                assert_eq!(
+                    keys[0].to_string(),
+                    format!("[aabbcc01/48'/0'/0'/2']{XPUB_A}/<0;1>/*")
+                );
+                assert_eq!(
+                    keys[2].to_string(),
+                    format!("[aabbcc03/48'/0'/1'/2']{XPUB_C}/<0;1>/*")
+                );
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        assert!(matches!(
+            import_descriptor(&coldcard.replace("Format: P2WSH", "Format: P2SH-P2WSH")),
+            Err(DescImportError::UnsupportedScriptType("P2SH-P2WSH"))
+        ));
+
+        let caravan = format!(
+            r#"{{"name": "Test", "addressType": "P2WSH", "network": "mainnet", "quorum": {{"requiredSigners": 1, "totalSigners": 2}}, "extendedPublicKeys": [{{"name": "A", "bip32Path": "m/48'/0'/0'/2'", "xpub": "{XPUB_A}", "xfp": "aabbcc01"}}, {{"name": "B", "bip32Path": "m/48'/0'/0'/2'", "xpub": "{XPUB_B}", "xfp": "aabbcc02"}}], "startingAddressIndex": 0}}"#
+        );
+        assert!(matches!(
+            import_descriptor(&caravan),
+            Err(DescImportError::NoRecoveryPath { threshold: 1, .. })
+        ));
+        let caravan = caravan.replace("\"aabbcc02\"", "\"Unknown\"");
+        assert!(matches!(
+            import_descriptor(&caravan),
+            Err(DescImportError::UnsupportedKey(..))
+        ));
+
+        // Specter and Bitcoin Core JSON exports.
+        let expected = LianaDescriptor::from_str(&liana_desc("<0;1>")).unwrap();
+        let specter = format!(
+            r#"{{"label": "Test", "blockheight": 800000, "descriptor": "{}", "devices": []}}"#,
+            liana_desc("0")
+        );
+        assert_eq!(import_descriptor(&specter).unwrap(), expected);
+        let core = format!(
+            r#"{{"wallet_name": "test", "descriptors": [{{"desc": "{}", "internal": false}}, {{"desc": "{}", "internal": true}}]}}"#,
+            liana_desc("0"),
+            liana_desc("1")
+        );
+        assert_eq!(import_descriptor(&core).unwrap(), expected);
+        // The change descriptor is told by its internal flag rather than its position.
+        let core = format!(
+            r#"{{"wallet_name": "test", "descriptors": [{{"desc": "{}", "internal": true}}, {{"desc": "{}", "internal": false}}]}}"#,
+            liana_desc("1"),
+            liana_desc("0")
+        );
+        assert_eq!(import_descriptor(&core).unwrap(), expected);
+        assert!(matches!(
+            import_descriptor("{}"),
+            Err(DescImportError::UnknownFormat)
+        ));
+    }
+}
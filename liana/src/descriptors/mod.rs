@@ -28,6 +28,9 @@ pub use keys::*;
 pub mod analysis;
 pub use analysis::*;
 
+pub mod import;
+pub use import::*;
+
 #[derive(Debug)]
 pub enum LianaDescError {
     Miniscript(miniscript::Error),
@@ -324,55 +327,8 @@ impl LianaDescriptor {
     /// size of the witness stack length varint.
     pub fn max_sat_weight(&self, use_primary_path: bool) -> usize {
         if use_primary_path {
-            // Get the keys from the primary path, to get a satisfaction size estimation only
-            // considering those.
-            let keys = self
-                .policy()
-                .primary_path
-                .thresh_origins()
-                .1
-                .into_iter()
-                .fold(BTreeSet::new(), |mut keys, (fg, der_paths)| {
-                    for der_path in der_paths {
-                        keys.insert(((fg, der_path), CanSign::default()));
-                    }
-                    keys
-                });
-            let assets = Assets {
-                keys,
-                ..Default::default()
-            };
-
-            // Unfortunately rust-miniscript satisfaction size estimation is inconsistent. For
-            // Taproot it considers the whole witness (except the control block size + the
-            // script size), while under P2WSH it does not consider the witscript! Therefore we
-            // manually add the size of the witscript under P2WSH by means of the
-            // `explicit_script()` helper, which gives an error for Taproot, and for Taproot
-            // we add the sizes of the control block and script.
-            let der_desc = self
-                .receive_desc
-                .0
-                .at_derivation_index(0)
-                .expect("unhardened index");
-            let witscript_size = der_desc
-                .explicit_script()
-                .map(|s| varint_len(s.len()) + s.len());
-
-            // Finally, compute the satisfaction template for the primary path and get its size.
-            let plan = der_desc.plan(&assets).expect("Always satisfiable");
-            plan.witness_size()
-                + witscript_size.unwrap_or_else(|_| {
-                    plan.witness_template()
-                        .iter()
-                        .map(|elem| match elem {
-                            // We need to calculate the size manually before calculating the varint length.
-                            // See https://docs.rs/miniscript/11.0.0/src/miniscript/util.rs.html#35-36.
-                            Placeholder::TapScript(s) => varint_len(s.len()),
-                            Placeholder::TapControlBlock(cb) => varint_len(cb.serialize().len()),
-                            _ => 0,
-                        })
-                        .sum()
-                })
+            self.path_max_sat_weight(None)
+                .expect("The primary path always exists")
         } else {
             // We add one to account for the witness stack size, as the values above give the
             // difference in size for a satisfied input that was *already* in a transaction
@@ -391,6 +347,70 @@ impl LianaDescriptor {
         }
     }
 
+    /// Get the maximum size difference of a transaction input spending a Script derived from this
+    /// descriptor before and after satisfaction, when using a specific spending path. The
+    /// returned value is in weight units. Pass `None` for the primary path, or the timelock of
+    /// a recovery path. Returns `None` if there is no recovery path with this timelock.
+    /// Callers are expected to account for the Segwit marker (2 WU). This takes into account the
+    /// size of the witness stack length varint.
+    pub fn path_max_sat_weight(&self, timelock: Option<u16>) -> Option<usize> {
+        let policy = self.policy();
+        let path_info = match timelock {
+            None => &policy.primary_path,
+            Some(tl) => policy.recovery_paths.get(&tl)?,
+        };
+
+        // Get the keys from this path, to get a satisfaction size estimation only considering
+        // those.
+        let keys = path_info.thresh_origins().1.into_iter().fold(
+            BTreeSet::new(),
+            |mut keys, (fg, der_paths)| {
+                for der_path in der_paths {
+                    keys.insert(((fg, der_path), CanSign::default()));
+                }
+                keys
+            },
+        );
+        let assets = Assets {
+            keys,
+            relative_timelock: timelock.map(bitcoin::relative::LockTime::from_height),
+            ..Default::default()
+        };
+
+        // Unfortunately rust-miniscript satisfaction size estimation is inconsistent. For
+        // Taproot it considers the whole witness (except the control block size + the
+        // script size), while under P2WSH it does not consider the witscript! Therefore we
+        // manually add the size of the witscript under P2WSH by means of the
+        // `explicit_script()` helper, which gives an error for Taproot, and for Taproot
+        // we add the sizes of the control block and script.
+        let der_desc = self
+            .receive_desc
+            .0
+            .at_derivation_index(0)
+            .expect("unhardened index");
+        let witscript_size = der_desc
+            .explicit_script()
+            .map(|s| varint_len(s.len()) + s.len());
+
+        // Finally, compute the satisfaction template for this path and get its size.
+        let plan = der_desc.plan(&assets).expect("Always satisfiable");
+        Some(
+            plan.witness_size()
+                + witscript_size.unwrap_or_else(|_| {
+                    plan.witness_template()
+                        .iter()
+                        .map(|elem| match elem {
+                            // We need to calculate the size manually before calculating the varint length.
+                            // See https://docs.rs/miniscript/11.0.0/src/miniscript/util.rs.html#35-36.
+                            Placeholder::TapScript(s) => varint_len(s.len()),
+                            Placeholder::TapControlBlock(cb) => varint_len(cb.serialize().len()),
+                            _ => 0,
+                        })
+                        .sum()
+                }),
+        )
+    }
+
     /// Get the maximum size difference of a transaction input spending a Script derived from this
     /// descriptor before and after satisfaction. The returned value is in (rounded up) virtual
     /// bytes.
@@ -637,38 +657,75 @@ impl LianaDescriptor {
     /// after satisfaction, assuming all inputs of `tx` are from this
     /// descriptor.
     fn unsigned_tx_max_weight(&self, tx: &bitcoin::Transaction, use_primary_path: bool) -> u64 {
-        let num_inputs: u64 = tx.input.len().try_into().unwrap();
         let max_sat_weight: u64 = self.max_sat_weight(use_primary_path).try_into().unwrap();
-        // Add weights together before converting to vbytes to avoid rounding up multiple times.
-        tx.weight()
-            .to_wu()
-            .checked_add(max_sat_weight.checked_mul(num_inputs).unwrap())
-            .and_then(|weight| {
-                weight.checked_add(
-                    // Make sure the Segwit marker and flag are included:
-                    // https://docs.rs/bitcoin/0.31.0/src/bitcoin/blockdata/transaction.rs.html#752-753
-                    // https://docs.rs/bitcoin/0.31.0/src/bitcoin/blockdata/transaction.rs.html#968-979
-                    if num_inputs > 0 && tx.input.iter().all(|txin| txin.witness.is_empty()) {
-                        2
-                    } else {
-                        0
-                    },
-                )
-            })
-            .unwrap()
+        unsigned_tx_max_weight(tx, max_sat_weight)
     }
 
     /// Maximum possible size in vbytes of an unsigned transaction, `tx`,
     /// after satisfaction, assuming all inputs of `tx` are from this
     /// descriptor.
     pub fn unsigned_tx_max_vbytes(&self, tx: &bitcoin::Transaction, use_primary_path: bool) -> u64 {
-        let witness_factor: u64 = WITNESS_SCALE_FACTOR.try_into().unwrap();
-        self.unsigned_tx_max_weight(tx, use_primary_path)
-            .checked_add(witness_factor.checked_sub(1).unwrap())
-            .unwrap()
-            .checked_div(witness_factor)
-            .unwrap()
+        wu_to_vbytes(self.unsigned_tx_max_weight(tx, use_primary_path))
     }
+
+    /// Maximum possible size in vbytes of an unsigned transaction, `tx`,
+    /// after satisfaction, assuming all inputs of `tx` are from this
+    /// descriptor and spent using the given path. Pass `None` for the
+    /// primary path, or the timelock of a recovery path. Returns `None`
+    /// if there is no recovery path with this timelock.
+    pub fn unsigned_tx_max_vbytes_for_path(
+        &self,
+        tx: &bitcoin::Transaction,
+        timelock: Option<u16>,
+    ) -> Option<u64> {
+        let max_sat_weight: u64 = self.path_max_sat_weight(timelock)?.try_into().unwrap();
+        Some(wu_to_vbytes(unsigned_tx_max_weight(tx, max_sat_weight)))
+    }
+
+    /// Get the same descriptor compiled for use under a Taproot (if `is_taproot` is set) or P2WSH
+    /// context. If this descriptor is already of the requested type it is returned as is.
+    /// Note compiling a policy is not deterministic, see
+    /// [`LianaPolicy::compile_multipath_descriptor`].
+    pub fn with_taproot(&self, is_taproot: bool) -> Result<LianaDescriptor, LianaPolicyError> {
+        if self.is_taproot() == is_taproot {
+            return Ok(self.clone());
+        }
+        let policy = self.policy().with_taproot(is_taproot)?;
+        Ok(LianaDescriptor::new(policy))
+    }
+}
+
+// Maximum weight of the unsigned transaction `tx` after satisfaction of all its inputs, each of
+// which having a satisfaction weight of at most `max_sat_weight`.
+fn unsigned_tx_max_weight(tx: &bitcoin::Transaction, max_sat_weight: u64) -> u64 {
+    let num_inputs: u64 = tx.input.len().try_into().unwrap();
+    // Add weights together before converting to vbytes to avoid rounding up multiple times.
+    tx.weight()
+        .to_wu()
+        .checked_add(max_sat_weight.checked_mul(num_inputs).unwrap())
+        .and_then(|weight| {
+            weight.checked_add(
+                // Make sure the Segwit marker and flag are included:
+                // https://docs.rs/bitcoin/0.31.0/src/bitcoin/blockdata/transaction.rs.html#752-753
+                // https://docs.rs/bitcoin/0.31.0/src/bitcoin/blockdata/transaction.rs.html#968-979
+                if num_inputs > 0 && tx.input.iter().all(|txin| txin.witness.is_empty()) {
+                    2
+                } else {
+                    0
+                },
+            )
+        })
+        .unwrap()
+}
+
+// Convert a weight to (rounded up) virtual bytes.
+fn wu_to_vbytes(weight: u64) -> u64 {
+    let witness_factor: u64 = WITNESS_SCALE_FACTOR.try_into().unwrap();
+    weight
+        .checked_add(witness_factor.checked_sub(1).unwrap())
+        .unwrap()
+        .checked_div(witness_factor)
+        .unwrap()
 }
 
 impl SinglePathLianaDesc {
@@ -2219,6 +2276,63 @@ mod tests {
         assert_eq!(desc.unsigned_tx_max_vbytes(&psbt.unsigned_tx, false), 162); // 648/4 = 162
     }
 
+    #[test]
+    fn path_max_sat_weight() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc = LianaDescriptor::from_str("tr(tpubD6NzVbkrYhZ4WUdbVsXDYBCXS8EPSYG1cAN9g4uP6uLQHMHXRvHSFkQBXy7MBeAvV8PDVJJ4o3AwYMKJHp45ci2g69UCAKteVSAJ61CnGEV/<0;1>/*,{and_v(v:pk([9e1c1983/48'/1'/0'/2']tpubDEWCLCMncbStq4BLXkQUAPqzzrh2tQUgYeQPt4NrB5D7gRraMyGbRqzPTmQGvqfdaFsXDVGSQBRgfXuNjDyfU626pxSjpQZszFNY6CzogxK/<2;3>/*),older(65535)),multi_a(2,[9e1c1983/48'/1'/0'/2']tpubDEWCLCMncbStq4BLXkQUAPqzzrh2tQUgYeQPt4NrB5D7gRraMyGbRqzPTmQGvqfdaFsXDVGSQBRgfXuNjDyfU626pxSjpQZszFNY6CzogxK/<0;1>/*,[3b1913e1/48'/1'/0'/2']tpubDFeZ2ezf4VUuTnjdhxJ1DKhLa2t6vzXZNz8NnEgeT2PN4pPqTCTeWUcaxKHPJcf1C8WzkLA71zSjDwuo4zqu4kkiL91ZUmJydC8f1gx89wM/<0;1>/*)})#ee0r4tw5").unwrap();
+
+        // The primary path is the one used by `max_sat_weight`.
+        let primary_weight = desc.path_max_sat_weight(None).unwrap();
+        assert_eq!(primary_weight, desc.max_sat_weight(true));
+
+        // There is no recovery path with this timelock.
+        assert!(desc.path_max_sat_weight(Some(1)).is_none());
+
+        // The recovery path only needs a single signature, and both leaves are at the same depth.
+        let recovery_weight = desc.path_max_sat_weight(Some(65535)).unwrap();
+        assert!(recovery_weight < primary_weight);
+        assert!(recovery_weight <= desc.max_sat_weight(false));
+
+        // Same for the whole transaction.
+        let tx = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default(); 2],
+            output: vec![bitcoin::TxOut {
+                value: bitcoin::Amount::from_sat(10_000),
+                script_pubkey: desc
+                    .receive_descriptor()
+                    .derive(0.into(), &secp)
+                    .script_pubkey(),
+            }],
+        };
+        assert_eq!(
+            desc.unsigned_tx_max_vbytes_for_path(&tx, None),
+            Some(desc.unsigned_tx_max_vbytes(&tx, true))
+        );
+        assert!(
+            desc.unsigned_tx_max_vbytes_for_path(&tx, Some(65535))
+                .unwrap()
+                < desc.unsigned_tx_max_vbytes(&tx, true)
+        );
+        assert!(desc.unsigned_tx_max_vbytes_for_path(&tx, Some(1)).is_none());
+
+        // We can get the P2WSH variant of the same policy, and back.
+        assert!(desc.with_taproot(true).unwrap() == desc);
+        let wsh_desc = desc.with_taproot(false).unwrap();
+        assert!(!wsh_desc.is_taproot());
+        assert_eq!(
+            wsh_desc.policy().primary_path().thresh_origins(),
+            desc.policy().primary_path().thresh_origins()
+        );
+        assert_eq!(
+            wsh_desc.policy().recovery_paths()[&65535].thresh_origins(),
+            desc.policy().recovery_paths()[&65535].thresh_origins()
+        );
+        assert!(wsh_desc.path_max_sat_weight(Some(65535)).is_some());
+        assert!(wsh_desc.with_taproot(true).unwrap().is_taproot());
+    }
+
     fn run_change_detection(
         desc: LianaDescriptor,
         secp: &secp256k1::Secp256k1<impl secp256k1::Verification>,
@@ -1,6 +1,12 @@
+pub mod bip21;
+pub mod bip322;
+pub mod bsms;
 pub mod descriptors;
+pub mod privacy;
 pub mod random;
+pub mod reserves;
 pub mod signer;
+pub mod silent_payments;
 pub mod spend;
 
 pub use bip39;
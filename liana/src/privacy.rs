@@ -0,0 +1,169 @@
+//! Privacy module
+//!
+//! Heuristics chain observers commonly use to cluster the coins of a wallet, applied to our own
+//! transactions in order to point out the information they leak.
+
+use miniscript::bitcoin::{Amount, Script, Transaction};
+
+/// Amounts which are a multiple of this value are considered round. That is, likely chosen by a
+/// human rather than the leftover of a transaction.
+pub const ROUND_AMOUNT_SAT: u64 = 10_000;
+
+/// A reason why the change output of a transaction can be told apart from its payments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ChangeLeak {
+    /// All the payments have round amounts but the change doesn't.
+    RoundPayments,
+    /// The change uses a script type that none of the payments uses.
+    ScriptTypeMismatch,
+}
+
+impl std::fmt::Display for ChangeLeak {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::RoundPayments => write!(f, "the payments have round amounts"),
+            Self::ScriptTypeMismatch => write!(
+                f,
+                "the change uses a different address type than the payments"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScriptType {
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    Other,
+}
+
+impl ScriptType {
+    fn from_script(script: &Script) -> Self {
+        if script.is_p2pkh() {
+            Self::P2pkh
+        } else if script.is_p2sh() {
+            Self::P2sh
+        } else if script.is_p2wpkh() {
+            Self::P2wpkh
+        } else if script.is_p2wsh() {
+            Self::P2wsh
+        } else if script.is_p2tr() {
+            Self::P2tr
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// Whether this amount looks like it was chosen by a human.
+pub fn is_round_amount(amount: Amount) -> bool {
+    let sats = amount.to_sat();
+    sats > 0 && sats % ROUND_AMOUNT_SAT == 0
+}
+
+/// Whether this label is one of those (case-insensitive) designating a source which knows the
+/// identity of the owner of the wallet, such as an exchange.
+pub fn is_kyc_label(label: &str, kyc_labels: &[String]) -> bool {
+    let label = label.to_lowercase();
+    kyc_labels.iter().any(|l| l.to_lowercase() == label)
+}
+
+/// The reasons why the change outputs of this transaction, at the given indexes, can be told apart
+/// from its payments. Data outputs are not considered as payments. A transaction without change or
+/// without payment doesn't leak anything this way.
+pub fn change_leaks(tx: &Transaction, change_indexes: &[usize]) -> Vec<ChangeLeak> {
+    let (change, payments): (Vec<_>, Vec<_>) = tx
+        .output
+        .iter()
+        .enumerate()
+        .filter(|(_, txo)| !txo.script_pubkey.is_op_return())
+        .partition(|(i, _)| change_indexes.contains(i));
+    if change.is_empty() || payments.is_empty() {
+        return Vec::new();
+    }
+
+    let mut leaks = Vec::new();
+    if payments.iter().all(|(_, txo)| is_round_amount(txo.value))
+        && !change.iter().any(|(_, txo)| is_round_amount(txo.value))
+    {
+        leaks.push(ChangeLeak::RoundPayments);
+    }
+    let payment_types: Vec<_> = payments
+        .iter()
+        .map(|(_, txo)| ScriptType::from_script(&txo.script_pubkey))
+        .collect();
+    if change
+        .iter()
+        .all(|(_, txo)| !payment_types.contains(&ScriptType::from_script(&txo.script_pubkey)))
+    {
+        leaks.push(ChangeLeak::ScriptTypeMismatch);
+    }
+    leaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniscript::bitcoin::{
+        absolute, hashes::Hash, transaction::Version, PubkeyHash, ScriptBuf, TxOut, WScriptHash,
+    };
+
+    fn p2wsh(value: u64) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: ScriptBuf::new_p2wsh(&WScriptHash::all_zeros()),
+        }
+    }
+
+    fn p2pkh(value: u64) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: ScriptBuf::new_p2pkh(&PubkeyHash::all_zeros()),
+        }
+    }
+
+    fn tx(output: Vec<TxOut>) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: Vec::new(),
+            output,
+        }
+    }
+
+    #[test]
+    fn privacy_heuristics() {
+        assert!(is_round_amount(Amount::from_sat(1_500_000)));
+        assert!(!is_round_amount(Amount::from_sat(1_512_345)));
+        assert!(!is_round_amount(Amount::ZERO));
+
+        let kyc_labels = vec!["Exchange withdrawal".to_string(), "ATM".to_string()];
+        assert!(is_kyc_label("exchange Withdrawal", &kyc_labels));
+        assert!(is_kyc_label("ATM", &kyc_labels));
+        assert!(!is_kyc_label("Salary", &kyc_labels));
+        assert!(!is_kyc_label("ATM fees", &kyc_labels));
+        assert!(!is_kyc_label("ATM", &[]));
+
+        // The change is indistinguishable from the payment.
+        let tx_a = tx(vec![p2wsh(123_456), p2wsh(654_321)]);
+        assert!(change_leaks(&tx_a, &[1]).is_empty());
+
+        // A round payment gives the change away, and so does a different script type.
+        let tx_b = tx(vec![p2pkh(100_000), p2wsh(654_321)]);
+        assert_eq!(
+            change_leaks(&tx_b, &[1]),
+            vec![ChangeLeak::RoundPayments, ChangeLeak::ScriptTypeMismatch]
+        );
+
+        // Unless the change is round too, or some payment uses the same script type.
+        let tx_c = tx(vec![p2pkh(100_000), p2wsh(50_000), p2wsh(654_321)]);
+        assert!(change_leaks(&tx_c, &[1]).is_empty());
+
+        // Without change or without payment, there is nothing to tell apart.
+        assert!(change_leaks(&tx_b, &[]).is_empty());
+        assert!(change_leaks(&tx_b, &[0, 1]).is_empty());
+    }
+}
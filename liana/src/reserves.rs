@@ -0,0 +1,433 @@
+//! Proof of reserves module
+//!
+//! Proofs of reserves (BIP127). A proof is a transaction spending the coins whose ownership is
+//! proven, along with a "commitment" input spending a non-existent output which commits to a
+//! challenge message. This first input makes the transaction invalid, so it can be signed by the
+//! usual signers without putting the coins at risk.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    error, fmt,
+};
+
+use crate::{
+    descriptors,
+    spend::{coin_psbt_in, CandidateCoin, TxGetter},
+};
+
+use miniscript::{
+    bitcoin::{
+        self,
+        hashes::{sha256, Hash},
+        opcodes,
+        psbt::{Input as PsbtIn, Output as PsbtOut, Psbt},
+        script::Builder,
+        secp256k1, sighash,
+        transaction::Version,
+        Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
+    },
+    interpreter::Interpreter,
+    psbt::PsbtExt,
+};
+
+/// The prefix of the challenge message, committed to by the first input of a proof.
+const CHALLENGE_PREFIX: &[u8] = b"Proof-of-Reserves: ";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReservesProofError {
+    NoCoins,
+    /// The proof is not a well-formed proof of reserves for this message.
+    InvalidProof(String),
+    /// The coin spent by this input of the proof is not signed for.
+    InvalidSignature(OutPoint),
+    Finalization(String),
+}
+
+impl fmt::Display for ReservesProofError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoCoins => write!(f, "No coin to prove ownership of."),
+            Self::InvalidProof(e) => write!(f, "Invalid proof of reserves: {e}"),
+            Self::InvalidSignature(op) => {
+                write!(
+                    f,
+                    "The proof does not contain a valid signature for coin '{op}'."
+                )
+            }
+            Self::Finalization(e) => write!(f, "Failed to finalize proof of reserves: {e}"),
+        }
+    }
+}
+
+impl error::Error for ReservesProofError {}
+
+/// The txid of the non-existent transaction spent by the commitment input of a proof for this
+/// message.
+pub fn challenge_txid(message: &str) -> Txid {
+    let mut data = CHALLENGE_PREFIX.to_vec();
+    data.extend_from_slice(message.as_bytes());
+    Txid::from_byte_array(sha256::Hash::hash(&data).to_byte_array())
+}
+
+/// The output assumed to be spent by the commitment input. Only necessary for signers which commit
+/// to all the spent outputs (Taproot).
+pub fn challenge_txo() -> TxOut {
+    TxOut {
+        value: Amount::ZERO,
+        script_pubkey: op_true(),
+    }
+}
+
+fn op_true() -> ScriptBuf {
+    Builder::new().push_opcode(opcodes::OP_TRUE).into_script()
+}
+
+/// Create the PSBT of a proof of reserves for these coins, committing to this challenge message.
+/// It spends all the coins to a single "anyone can spend" output of the same total value, and is
+/// made invalid by its first input. The coins are spent using the primary path.
+pub fn create_proof_psbt(
+    main_descriptor: &descriptors::LianaDescriptor,
+    secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    tx_getter: &mut impl TxGetter,
+    coins: &[CandidateCoin],
+    message: &str,
+) -> Result<Psbt, ReservesProofError> {
+    if coins.is_empty() {
+        return Err(ReservesProofError::NoCoins);
+    }
+
+    let mut tx = Transaction {
+        version: Version::TWO,
+        lock_time: bitcoin::absolute::LockTime::ZERO,
+        input: Vec::with_capacity(coins.len() + 1),
+        output: Vec::with_capacity(1),
+    };
+    let mut psbt_ins = Vec::with_capacity(coins.len() + 1);
+    tx.input.push(TxIn {
+        previous_output: OutPoint {
+            txid: challenge_txid(message),
+            vout: 0,
+        },
+        sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+        ..TxIn::default()
+    });
+    psbt_ins.push(PsbtIn {
+        witness_utxo: Some(challenge_txo()),
+        ..PsbtIn::default()
+    });
+    for coin in coins {
+        tx.input.push(TxIn {
+            previous_output: coin.outpoint,
+            sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+            ..TxIn::default()
+        });
+        psbt_ins.push(coin_psbt_in(secp, main_descriptor, tx_getter, coin));
+    }
+    tx.output.push(TxOut {
+        value: coins.iter().map(|coin| coin.amount).sum(),
+        script_pubkey: op_true(),
+    });
+
+    Ok(Psbt {
+        unsigned_tx: tx,
+        version: 0,
+        xpub: BTreeMap::new(),
+        proprietary: BTreeMap::new(),
+        unknown: BTreeMap::new(),
+        inputs: psbt_ins,
+        outputs: vec![PsbtOut::default()],
+    })
+}
+
+// Check the structure of a proof for this message, which must spend the given previous outputs.
+fn check_proof(
+    tx: &Transaction,
+    message: &str,
+    prevouts_count: usize,
+) -> Result<(), ReservesProofError> {
+    let commitment = tx
+        .input
+        .first()
+        .ok_or_else(|| ReservesProofError::InvalidProof("no input".to_string()))?;
+    if commitment.previous_output.txid != challenge_txid(message)
+        || commitment.previous_output.vout != 0
+    {
+        return Err(ReservesProofError::InvalidProof(
+            "the first input does not commit to this message".to_string(),
+        ));
+    }
+    if tx.input.len() < 2 {
+        return Err(ReservesProofError::NoCoins);
+    }
+    if tx.input.len() != prevouts_count + 1 {
+        return Err(ReservesProofError::InvalidProof(
+            "the spent coins don't match the inputs".to_string(),
+        ));
+    }
+    let mut spent = HashSet::with_capacity(tx.input.len());
+    if !tx
+        .input
+        .iter()
+        .all(|txin| spent.insert(txin.previous_output))
+    {
+        return Err(ReservesProofError::InvalidProof(
+            "a coin is spent more than once".to_string(),
+        ));
+    }
+    if tx.output.len() != 1 || tx.output[0].script_pubkey != op_true() {
+        return Err(ReservesProofError::InvalidProof(
+            "a proof must have a single OP_TRUE output".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Get some information about the signatures present in the PSBT of a proof of reserves. See
+/// [`descriptors::LianaDescriptor::partial_spend_info`], the commitment input is ignored.
+pub fn proof_spend_info(
+    main_descriptor: &descriptors::LianaDescriptor,
+    psbt: &Psbt,
+) -> Result<descriptors::PartialSpendInfo, descriptors::LianaDescError> {
+    let mut coins_psbt = psbt.clone();
+    if coins_psbt.inputs.len() != coins_psbt.unsigned_tx.input.len() {
+        return Err(descriptors::LianaDescError::InsanePsbt);
+    }
+    if !coins_psbt.inputs.is_empty() {
+        coins_psbt.inputs.remove(0);
+        coins_psbt.unsigned_tx.input.remove(0);
+    }
+    main_descriptor.partial_spend_info(&coins_psbt)
+}
+
+/// Finalize the signed PSBT of a proof of reserves for this message. The commitment input is left
+/// unsigned.
+pub fn finalize_proof(
+    secp: &secp256k1::Secp256k1<impl secp256k1::Verification>,
+    mut psbt: Psbt,
+    message: &str,
+) -> Result<Transaction, ReservesProofError> {
+    check_proof(
+        &psbt.unsigned_tx,
+        message,
+        psbt.inputs.len().saturating_sub(1),
+    )?;
+    for i in 1..psbt.inputs.len() {
+        psbt.finalize_inp_mut(secp, i)
+            .map_err(|e| ReservesProofError::Finalization(e.to_string()))?;
+    }
+    Ok(psbt.extract_tx_unchecked_fee_rate())
+}
+
+/// Verify a proof of reserves for this message, given the outputs spent by each of its inputs but
+/// the commitment one. Returns the total value of the coins whose ownership is proven.
+pub fn verify_proof(
+    secp: &secp256k1::Secp256k1<impl secp256k1::Verification>,
+    tx: &Transaction,
+    message: &str,
+    prevouts: &[TxOut],
+) -> Result<Amount, ReservesProofError> {
+    check_proof(tx, message, prevouts.len())?;
+    let total: Amount = prevouts.iter().map(|txo| txo.value).sum();
+    if tx.output[0].value != total {
+        return Err(ReservesProofError::InvalidProof(
+            "the output value doesn't match the spent coins".to_string(),
+        ));
+    }
+
+    let all_prevouts: Vec<TxOut> = std::iter::once(challenge_txo())
+        .chain(prevouts.iter().cloned())
+        .collect();
+    let sighash_prevouts = sighash::Prevouts::All(&all_prevouts);
+    for (i, txin) in tx.input.iter().enumerate().skip(1) {
+        let interpreter = Interpreter::from_txdata(
+            &all_prevouts[i].script_pubkey,
+            &txin.script_sig,
+            &txin.witness,
+            txin.sequence,
+            tx.lock_time,
+        )
+        .map_err(|_| ReservesProofError::InvalidSignature(txin.previous_output))?;
+        // The iterator errors if any constraint is not satisfied, or if the script doesn't
+        // succeed once they all were.
+        if !interpreter
+            .iter(secp, tx, i, &sighash_prevouts)
+            .all(|res| res.is_ok())
+        {
+            return Err(ReservesProofError::InvalidSignature(txin.previous_output));
+        }
+    }
+
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::HotSigner;
+
+    use std::str::FromStr;
+
+    use miniscript::{
+        bitcoin::bip32,
+        descriptor::{DerivPaths, DescriptorMultiXKey, DescriptorPublicKey, Wildcard},
+    };
+
+    struct DummyTxGetter;
+
+    impl TxGetter for DummyTxGetter {
+        fn get_tx(&mut self, _: &bitcoin::Txid) -> Option<bitcoin::Transaction> {
+            None
+        }
+    }
+
+    fn signer_key(
+        signer: &HotSigner,
+        secp: &secp256k1::Secp256k1<secp256k1::All>,
+        account: &str,
+    ) -> DescriptorPublicKey {
+        let origin_der = bip32::DerivationPath::from_str(account).unwrap();
+        DescriptorPublicKey::MultiXPub(DescriptorMultiXKey {
+            origin: Some((signer.fingerprint(secp), origin_der.clone())),
+            xkey: signer.xpub_at(&origin_der, secp),
+            derivation_paths: DerivPaths::new(vec![
+                bip32::DerivationPath::from_str("m/0").unwrap(),
+                bip32::DerivationPath::from_str("m/1").unwrap(),
+            ])
+            .unwrap(),
+            wildcard: Wildcard::Unhardened,
+        })
+    }
+
+    #[test]
+    fn reserves_proof() {
+        let secp = secp256k1::Secp256k1::new();
+        let verif_secp = secp256k1::Secp256k1::verification_only();
+        let network = bitcoin::Network::Bitcoin;
+        let (prim_signer, recov_signer) = (
+            HotSigner::generate(network).unwrap(),
+            HotSigner::generate(network).unwrap(),
+        );
+        let policy = descriptors::LianaPolicy::new_legacy(
+            descriptors::PathInfo::Single(signer_key(&prim_signer, &secp, "m/48'/0'/0'/2'")),
+            [(
+                144,
+                descriptors::PathInfo::Single(signer_key(&recov_signer, &secp, "m/48'/0'/1'/2'")),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .unwrap();
+        let desc = descriptors::LianaDescriptor::new(policy);
+
+        let coins: Vec<CandidateCoin> = [(0, false, 100_000), (3, true, 42_000)]
+            .iter()
+            .enumerate()
+            .map(|(i, (index, is_change, value))| CandidateCoin {
+                outpoint: OutPoint {
+                    txid: Txid::from_str(
+                        "4613e078e4cdbb0fce1bc6e44b028f0e11621a134a1605efdc456c32d155c922",
+                    )
+                    .unwrap(),
+                    vout: i as u32,
+                },
+                amount: Amount::from_sat(*value),
+                deriv_index: bip32::ChildNumber::from(*index),
+                is_change: *is_change,
+                must_select: true,
+                sequence: None,
+                ancestor_info: None,
+            })
+            .collect();
+        let prevouts: Vec<TxOut> = coins
+            .iter()
+            .map(|coin| {
+                let desc = if coin.is_change {
+                    desc.change_descriptor()
+                } else {
+                    desc.receive_descriptor()
+                };
+                TxOut {
+                    value: coin.amount,
+                    script_pubkey: desc.derive(coin.deriv_index, &secp).script_pubkey(),
+                }
+            })
+            .collect();
+        let message = "Liana vault audit 2024-Q2";
+
+        // A proof must spend at least one coin.
+        assert_eq!(
+            create_proof_psbt(&desc, &verif_secp, &mut DummyTxGetter, &[], message),
+            Err(ReservesProofError::NoCoins)
+        );
+
+        let psbt =
+            create_proof_psbt(&desc, &verif_secp, &mut DummyTxGetter, &coins, message).unwrap();
+        assert_eq!(psbt.unsigned_tx.input.len(), 3);
+        assert_eq!(
+            psbt.unsigned_tx.input[0].previous_output,
+            OutPoint {
+                txid: challenge_txid(message),
+                vout: 0
+            }
+        );
+        assert_eq!(psbt.unsigned_tx.output.len(), 1);
+        assert_eq!(psbt.unsigned_tx.output[0].value, Amount::from_sat(142_000));
+
+        // It can't be finalized before being signed, and only by the primary path signer.
+        assert!(matches!(
+            finalize_proof(&secp, psbt.clone(), message),
+            Err(ReservesProofError::Finalization(..))
+        ));
+        let info = proof_spend_info(&desc, &psbt).unwrap();
+        assert_eq!(info.primary_path().sigs_count, 0);
+        assert_eq!(info.primary_path().threshold, 1);
+        let recov_signed = recov_signer.sign_psbt(psbt.clone(), &secp).unwrap();
+        assert!(matches!(
+            finalize_proof(&secp, recov_signed, message),
+            Err(ReservesProofError::Finalization(..))
+        ));
+        let signed = prim_signer.sign_psbt(psbt, &secp).unwrap();
+        let info = proof_spend_info(&desc, &signed).unwrap();
+        assert_eq!(info.primary_path().sigs_count, 1);
+        assert!(matches!(
+            finalize_proof(&secp, signed.clone(), "another message"),
+            Err(ReservesProofError::InvalidProof(..))
+        ));
+        let proof = finalize_proof(&secp, signed, message).unwrap();
+        assert!(proof.input[0].witness.is_empty());
+
+        // The proof is valid for these coins and this message only.
+        assert_eq!(
+            verify_proof(&verif_secp, &proof, message, &prevouts),
+            Ok(Amount::from_sat(142_000))
+        );
+        assert!(matches!(
+            verify_proof(&verif_secp, &proof, "another message", &prevouts),
+            Err(ReservesProofError::InvalidProof(..))
+        ));
+        assert!(matches!(
+            verify_proof(&verif_secp, &proof, message, &prevouts[..1]),
+            Err(ReservesProofError::InvalidProof(..))
+        ));
+        let mut wrong_prevouts = prevouts.clone();
+        wrong_prevouts.swap(0, 1);
+        assert!(verify_proof(&verif_secp, &proof, message, &wrong_prevouts).is_err());
+
+        // The output must have the value of the spent coins.
+        let mut inflated = proof.clone();
+        inflated.output[0].value = Amount::from_sat(150_000);
+        assert!(matches!(
+            verify_proof(&verif_secp, &inflated, message, &prevouts),
+            Err(ReservesProofError::InvalidProof(..))
+        ));
+
+        // All coins must be signed for.
+        let mut unsigned = proof;
+        unsigned.input[2].witness = bitcoin::Witness::new();
+        assert_eq!(
+            verify_proof(&verif_secp, &unsigned, message, &prevouts),
+            Err(ReservesProofError::InvalidSignature(coins[1].outpoint))
+        );
+    }
+}
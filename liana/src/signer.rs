@@ -3,7 +3,10 @@
 //! Some helpers to facilitate the usage of a signer in client of the Liana daemon. For now
 //! only contains a hot signer.
 
-use crate::random;
+use crate::{
+    bsms, random,
+    silent_payments::{self, SilentPaymentError},
+};
 
 use std::{
     convert::TryInto,
@@ -32,6 +35,7 @@ pub enum SignerError {
     MnemonicStorage(io::Error),
     InsanePsbt,
     IncompletePsbt,
+    SilentPayment(SilentPaymentError),
 }
 
 impl fmt::Display for SignerError {
@@ -46,6 +50,7 @@ impl fmt::Display for SignerError {
                 f,
                 "The PSBT is missing some information necessary for signing."
             ),
+            Self::SilentPayment(e) => write!(f, "{e}"),
         }
     }
 }
@@ -233,6 +238,39 @@ impl HotSigner {
         bip32::Xpub::from_priv(secp, &xpriv)
     }
 
+    /// Get a BSMS key record for the extended key at the given derivation path, for this signer
+    /// to take part in a multisig setup.
+    pub fn bsms_key_record(
+        &self,
+        secp: &secp256k1::Secp256k1<impl secp256k1::Signing>,
+        token: bsms::Token,
+        der_path: bip32::DerivationPath,
+        description: String,
+    ) -> Result<bsms::KeyRecord, bsms::BsmsError> {
+        bsms::KeyRecord::new_signed(secp, token, &self.master_xpriv, der_path, description)
+    }
+
+    // The private key of the Taproot output spent by this input, if its internal key is ours.
+    fn taproot_output_key(
+        &self,
+        secp: &secp256k1::Secp256k1<secp256k1::All>,
+        master_fingerprint: bip32::Fingerprint,
+        psbt_in: &PsbtIn,
+    ) -> Option<secp256k1::SecretKey> {
+        let int_key = psbt_in.tap_internal_key.as_ref()?;
+        let (_, (fg, der_path)) = psbt_in.tap_key_origins.get(int_key)?;
+        if *fg != master_fingerprint {
+            return None;
+        }
+        let privkey = self.xpriv_at(der_path, secp).to_priv();
+        let keypair = secp256k1::Keypair::from_secret_key(secp, &privkey.inner);
+        if keypair.x_only_public_key().0 != *int_key {
+            return None;
+        }
+        let keypair = keypair.tap_tweak(secp, psbt_in.tap_merkle_root).to_inner();
+        Some(keypair.secret_key())
+    }
+
     // Provide an ECDSA signature for this transaction input from the PSBT input information.
     fn sign_p2wsh(
         &self,
@@ -357,6 +395,9 @@ impl HotSigner {
 
     /// Sign all inputs of the given PSBT.
     ///
+    /// The outputs paying to a silent payment address are derived beforehand if they weren't yet,
+    /// which requires the internal keys of all the inputs to be ours.
+    ///
     /// **This does not perform any check. It will blindly sign anything that's passed.**
     pub fn sign_psbt(
         &self,
@@ -364,6 +405,21 @@ impl HotSigner {
         secp: &secp256k1::Secp256k1<secp256k1::All>,
     ) -> Result<Psbt, SignerError> {
         let master_fingerprint = self.fingerprint(secp);
+
+        // The signatures commit to the outputs, so they must be derived first.
+        if silent_payments::has_underived_outputs(&psbt) {
+            let input_keys = psbt
+                .inputs
+                .iter()
+                .map(|psbt_in| self.taproot_output_key(secp, master_fingerprint, psbt_in))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(SignerError::SilentPayment(
+                    SilentPaymentError::MissingInputKeys,
+                ))?;
+            silent_payments::derive_outputs(secp, &mut psbt, &input_keys)
+                .map_err(SignerError::SilentPayment)?;
+        }
+
         let mut sighash_cache = sighash::SighashCache::new(&psbt.unsigned_tx);
 
         let prevouts: Vec<_> = psbt
@@ -1154,6 +1210,58 @@ mod tests {
             .inputs
             .iter()
             .all(|psbt_in| psbt_in.partial_sigs.is_empty()));
+
+        // Pay to a silent payment address. The output can only be derived by the signer of the
+        // primary key, which is the internal key of the coins, before it signs.
+        for psbt_in in dummy_psbt.inputs.iter_mut() {
+            psbt_in.witness_utxo = Some(bitcoin::TxOut {
+                value: Amount::from_sat(19_000),
+                script_pubkey: spent_coin_desc.script_pubkey(),
+            });
+        }
+        let scan_key = secp256k1::PublicKey::from_secret_key(
+            &secp,
+            &secp256k1::SecretKey::from_slice(&[1; 32]).unwrap(),
+        );
+        let spend_key = secp256k1::PublicKey::from_secret_key(
+            &secp,
+            &secp256k1::SecretKey::from_slice(&[2; 32]).unwrap(),
+        );
+        let sp_addr = silent_payments::SilentPaymentAddress::new(
+            bitcoin::NetworkKind::Main,
+            scan_key,
+            spend_key,
+        );
+        dummy_psbt.unsigned_tx.output[1] = bitcoin::TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: sp_addr.placeholder_script(),
+        };
+        dummy_psbt.outputs = vec![Default::default(); 2];
+        silent_payments::set_psbt_output_info(&mut dummy_psbt.outputs[1], &sp_addr);
+        let psbt = dummy_psbt.clone();
+        assert!(recov_signer
+            .sign_psbt(psbt.clone(), &secp)
+            .unwrap_err()
+            .to_string()
+            .contains("the private keys of all the inputs of the transaction are needed"));
+        let psbt = prim_signer_a.sign_psbt(psbt, &secp).unwrap();
+        assert!(!silent_payments::has_underived_outputs(&psbt));
+        assert_ne!(
+            psbt.unsigned_tx.output[1].script_pubkey,
+            sp_addr.placeholder_script()
+        );
+        assert_eq!(psbt.unsigned_tx.output[0], dummy_psbt.unsigned_tx.output[0]);
+        assert!(psbt
+            .inputs
+            .iter()
+            .all(|psbt_in| psbt_in.tap_key_sig.is_some()));
+        // Once derived, other signers can sign the transaction.
+        let signed_psbt = recov_signer.sign_psbt(psbt.clone(), &secp).unwrap();
+        assert_eq!(signed_psbt.unsigned_tx, psbt.unsigned_tx);
+        assert!(signed_psbt
+            .inputs
+            .iter()
+            .all(|psbt_in| psbt_in.tap_script_sigs.len() == 1));
     }
 
     #[test]
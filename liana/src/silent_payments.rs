@@ -0,0 +1,646 @@
+//! Silent payments module
+//!
+//! Parsing of silent payment addresses (BIP352) and derivation of the outputs paying to them.
+//! Unlike a regular address, a silent payment address does not encode the script to pay to: the
+//! output key is derived from the scan and spend keys of the recipient along with the private keys
+//! of the inputs of the transaction. It can only be done for inputs spent using a single key, such
+//! as Taproot inputs spent through their key path.
+//!
+//! Liana inputs are spent using script paths, except for Taproot descriptors with a single key in
+//! the primary path. For those, a transaction is created with a placeholder output and the
+//! silent payment address is recorded in the PSBT output (BIP375). The output is then derived by
+//! the signer holding the primary key, right before signing the transaction.
+
+use crate::descriptors::{LianaDescriptor, PathInfo};
+
+use std::{collections::HashMap, convert::TryFrom, error, fmt, str::FromStr};
+
+use miniscript::bitcoin::{
+    bech32::{
+        primitives::{checksum::Checksum, decode::CheckedHrpstring},
+        ByteIterExt, Fe32, Fe32IterExt, Hrp,
+    },
+    consensus,
+    hashes::{sha256, Hash, HashEngine},
+    key::TweakedPublicKey,
+    psbt::{raw, Output as PsbtOut, Psbt},
+    secp256k1, NetworkKind, ScriptBuf, Transaction,
+};
+
+/// Human readable part of silent payment addresses on mainnet.
+const HRP_MAINNET: &str = "sp";
+/// Human readable part of silent payment addresses on test networks (including signet and
+/// regtest).
+const HRP_TESTNET: &str = "tsp";
+
+/// Type of the PSBT output field holding the scan and spend keys of the silent payment address the
+/// output pays to (BIP375).
+const PSBT_OUT_SP_V0_INFO: u8 = 0x09;
+
+/// Bech32m with the length limit lifted, as silent payment addresses exceed the 90 characters
+/// allowed by BIP173.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum SilentPaymentBech32m {}
+
+impl Checksum for SilentPaymentBech32m {
+    type MidstateRepr = u32;
+    const CODE_LENGTH: usize = 1023;
+    const CHECKSUM_LENGTH: usize = 6;
+    const GENERATOR_SH: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    const TARGET_RESIDUE: u32 = 0x2bc830a3;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SilentPaymentError {
+    InvalidEncoding(String),
+    InvalidHrp(String),
+    UnsupportedVersion(u8),
+    InvalidLength(usize),
+    InvalidKey(secp256k1::Error),
+    InvalidNetwork,
+    /// The inputs of this wallet are spent using a script path (or are P2WSH).
+    IneligibleInputs,
+    /// The private keys of some inputs are not available to derive the output key.
+    MissingInputKeys,
+    /// This signing device can't take part in the derivation of the output key.
+    UnsupportedSigning,
+    /// Two silent payment addresses would use the same placeholder output.
+    DuplicateSpendKey,
+    /// The outputs of the transaction were derived from its inputs, which may change in a
+    /// replacement.
+    Replacement,
+    /// Invalid silent payment information in a PSBT output.
+    InvalidPsbtOutput,
+    /// Some outputs paying to a silent payment address were not derived yet.
+    UnderivedOutputs,
+}
+
+impl fmt::Display for SilentPaymentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidEncoding(e) => write!(f, "Invalid silent payment address encoding: {e}"),
+            Self::InvalidHrp(hrp) => write!(
+                f,
+                "Invalid silent payment address prefix '{hrp}', expected '{HRP_MAINNET}' or '{HRP_TESTNET}'."
+            ),
+            Self::UnsupportedVersion(v) => {
+                write!(f, "Unsupported silent payment address version {v}.")
+            }
+            Self::InvalidLength(l) => write!(
+                f,
+                "Invalid silent payment address payload length: {l} bytes instead of 66."
+            ),
+            Self::InvalidKey(e) => write!(f, "Invalid key in silent payment address: {e}"),
+            Self::InvalidNetwork => write!(f, "Silent payment address is for another network."),
+            Self::IneligibleInputs => write!(
+                f,
+                "Cannot pay to a silent payment address: the output would need to be derived from \
+                 the keys of the inputs, but the coins of this wallet are spent using a script path."
+            ),
+            Self::MissingInputKeys => write!(
+                f,
+                "Cannot derive the output paying to a silent payment address: the private keys of \
+                 all the inputs of the transaction are needed."
+            ),
+            Self::UnsupportedSigning => write!(
+                f,
+                "Cannot derive the output paying to a silent payment address: this signing device \
+                 doesn't support it."
+            ),
+            Self::DuplicateSpendKey => write!(
+                f,
+                "Cannot pay to several silent payment addresses with the same spend key in a \
+                 single transaction."
+            ),
+            Self::Replacement => write!(
+                f,
+                "Cannot replace a transaction paying to a silent payment address: its outputs were \
+                 derived from its inputs."
+            ),
+            Self::InvalidPsbtOutput => write!(
+                f,
+                "Invalid silent payment address information in a PSBT output."
+            ),
+            Self::UnderivedOutputs => write!(
+                f,
+                "The outputs paying to a silent payment address must be derived before the \
+                 transaction is signed or broadcast."
+            ),
+        }
+    }
+}
+
+impl error::Error for SilentPaymentError {}
+
+/// A silent payment address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SilentPaymentAddress {
+    pub network: NetworkKind,
+    pub version: u8,
+    pub scan_key: secp256k1::PublicKey,
+    pub spend_key: secp256k1::PublicKey,
+}
+
+impl SilentPaymentAddress {
+    pub fn new(
+        network: NetworkKind,
+        scan_key: secp256k1::PublicKey,
+        spend_key: secp256k1::PublicKey,
+    ) -> Self {
+        Self {
+            network,
+            version: 0,
+            scan_key,
+            spend_key,
+        }
+    }
+
+    /// Whether this string looks like a silent payment address, as opposed to a regular address.
+    pub fn is_silent_payment(s: &str) -> bool {
+        let s = s.to_lowercase();
+        s.starts_with(&format!("{HRP_MAINNET}1")) || s.starts_with(&format!("{HRP_TESTNET}1"))
+    }
+
+    /// Whether this address may be used on this network. Signet and regtest use the same
+    /// addresses as testnet.
+    pub fn is_valid_for_network(&self, network: impl Into<NetworkKind>) -> bool {
+        self.network == network.into()
+    }
+
+    /// The script of the output paying to this address until it is derived at signing time. It
+    /// pays to the spend key itself, and has the size of the derived output for fee estimation.
+    pub fn placeholder_script(&self) -> ScriptBuf {
+        p2tr_script(&self.spend_key)
+    }
+}
+
+impl FromStr for SilentPaymentAddress {
+    type Err = SilentPaymentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut checked = CheckedHrpstring::new::<SilentPaymentBech32m>(s)
+            .map_err(|e| SilentPaymentError::InvalidEncoding(e.to_string()))?;
+        let hrp = checked.hrp().to_lowercase();
+        let network = match hrp.as_str() {
+            HRP_MAINNET => NetworkKind::Main,
+            HRP_TESTNET => NetworkKind::Test,
+            _ => return Err(SilentPaymentError::InvalidHrp(hrp)),
+        };
+        let version = checked
+            .remove_witness_version()
+            .ok_or(SilentPaymentError::InvalidLength(0))?
+            .to_u8();
+        let payload: Vec<u8> = checked.byte_iter().collect();
+        // Version 31 is reserved for a backward incompatible change. Other versions must be
+        // parsed as version 0, ignoring any additional data.
+        if version == 31 {
+            return Err(SilentPaymentError::UnsupportedVersion(version));
+        }
+        if payload.len() < 66 || (version == 0 && payload.len() != 66) {
+            return Err(SilentPaymentError::InvalidLength(payload.len()));
+        }
+        let scan_key = secp256k1::PublicKey::from_slice(&payload[..33])
+            .map_err(SilentPaymentError::InvalidKey)?;
+        let spend_key = secp256k1::PublicKey::from_slice(&payload[33..66])
+            .map_err(SilentPaymentError::InvalidKey)?;
+        Ok(Self {
+            network,
+            version,
+            scan_key,
+            spend_key,
+        })
+    }
+}
+
+impl fmt::Display for SilentPaymentAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hrp = match self.network {
+            NetworkKind::Main => HRP_MAINNET,
+            NetworkKind::Test => HRP_TESTNET,
+        };
+        let hrp = Hrp::parse(hrp).expect("Valid human readable part.");
+        let version = Fe32::try_from(self.version).map_err(|_| fmt::Error)?;
+        let payload = self
+            .scan_key
+            .serialize()
+            .into_iter()
+            .chain(self.spend_key.serialize());
+        for c in payload
+            .bytes_to_fes()
+            .with_checksum::<SilentPaymentBech32m>(&hrp)
+            .with_witness_version(version)
+            .chars()
+        {
+            write!(f, "{c}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check whether the coins of this wallet can be used to pay to a silent payment address.
+pub fn check_descriptor(desc: &LianaDescriptor) -> Result<(), SilentPaymentError> {
+    // P2WSH inputs are never eligible. Taproot inputs are only if the internal key isn't the
+    // unspendable key used when the primary path is a multisig.
+    if !desc.is_taproot() {
+        return Err(SilentPaymentError::IneligibleInputs);
+    }
+    match desc.policy().primary_path() {
+        PathInfo::Single(_) => Ok(()),
+        PathInfo::Multi(..) => Err(SilentPaymentError::IneligibleInputs),
+    }
+}
+
+// A P2TR script paying to this key, without tweaking it.
+fn p2tr_script(key: &secp256k1::PublicKey) -> ScriptBuf {
+    let (key, _) = key.x_only_public_key();
+    ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(key))
+}
+
+// A BIP340 tagged hash of the concatenation of this data.
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_byte_array());
+    engine.input(tag.as_byte_array());
+    for d in data {
+        engine.input(d);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+fn scalar(bytes: [u8; 32]) -> Result<secp256k1::Scalar, SilentPaymentError> {
+    secp256k1::Scalar::from_be_bytes(bytes)
+        .map_err(|_| SilentPaymentError::InvalidKey(secp256k1::Error::InvalidTweak))
+}
+
+/// Record in this PSBT output that it pays to this silent payment address.
+pub fn set_psbt_output_info(psbt_out: &mut PsbtOut, addr: &SilentPaymentAddress) {
+    let key = raw::Key {
+        type_value: PSBT_OUT_SP_V0_INFO,
+        key: Vec::new(),
+    };
+    let value = addr
+        .scan_key
+        .serialize()
+        .into_iter()
+        .chain(addr.spend_key.serialize())
+        .collect();
+    psbt_out.unknown.insert(key, value);
+}
+
+/// The scan and spend keys of the silent payment address this PSBT output pays to, if any.
+pub fn psbt_output_info(
+    psbt_out: &PsbtOut,
+) -> Result<Option<(secp256k1::PublicKey, secp256k1::PublicKey)>, SilentPaymentError> {
+    let key = raw::Key {
+        type_value: PSBT_OUT_SP_V0_INFO,
+        key: Vec::new(),
+    };
+    let value = match psbt_out.unknown.get(&key) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    if value.len() != 66 {
+        return Err(SilentPaymentError::InvalidPsbtOutput);
+    }
+    let scan_key = secp256k1::PublicKey::from_slice(&value[..33])
+        .map_err(|_| SilentPaymentError::InvalidPsbtOutput)?;
+    let spend_key = secp256k1::PublicKey::from_slice(&value[33..])
+        .map_err(|_| SilentPaymentError::InvalidPsbtOutput)?;
+    Ok(Some((scan_key, spend_key)))
+}
+
+/// Whether some outputs of this PSBT pay to a silent payment address.
+pub fn pays_silent_payment(psbt: &Psbt) -> bool {
+    psbt.outputs
+        .iter()
+        .any(|psbt_out| !matches!(psbt_output_info(psbt_out), Ok(None)))
+}
+
+/// Whether some outputs of this PSBT pay to a silent payment address and were not derived yet.
+pub fn has_underived_outputs(psbt: &Psbt) -> bool {
+    psbt.outputs
+        .iter()
+        .zip(psbt.unsigned_tx.output.iter())
+        .any(|(psbt_out, txo)| match psbt_output_info(psbt_out) {
+            Ok(Some((_, spend_key))) => txo.script_pubkey == p2tr_script(&spend_key),
+            Ok(None) => false,
+            Err(_) => true,
+        })
+}
+
+/// Whether this transaction is the one of this PSBT once its outputs paying to a silent payment
+/// address were derived.
+pub fn is_derived_from(tx: &Transaction, psbt: &Psbt) -> bool {
+    let placeholder = &psbt.unsigned_tx;
+    has_underived_outputs(psbt)
+        && tx != placeholder
+        && tx.version == placeholder.version
+        && tx.lock_time == placeholder.lock_time
+        && tx.input == placeholder.input
+        && tx.output.len() == placeholder.output.len()
+        && psbt
+            .outputs
+            .iter()
+            .zip(placeholder.output.iter().zip(tx.output.iter()))
+            .all(|(psbt_out, (placeholder_txo, txo))| {
+                placeholder_txo.value == txo.value
+                    && (placeholder_txo.script_pubkey == txo.script_pubkey
+                        || matches!(psbt_output_info(psbt_out), Ok(Some(_))))
+            })
+}
+
+/// Derive the outputs of this PSBT paying to a silent payment address, replacing their script.
+///
+/// The private keys of all the inputs must be given, in order. The inputs must all be P2TR: a key
+/// is the one of the Taproot output, that is the internal key tweaked with the Merkle root. The
+/// signatures commit to the outputs, so this must be done before signing.
+pub fn derive_outputs(
+    secp: &secp256k1::Secp256k1<secp256k1::All>,
+    psbt: &mut Psbt,
+    input_keys: &[secp256k1::SecretKey],
+) -> Result<(), SilentPaymentError> {
+    if input_keys.is_empty() || input_keys.len() != psbt.unsigned_tx.input.len() {
+        return Err(SilentPaymentError::MissingInputKeys);
+    }
+
+    // Sum the private keys of the inputs. Taproot output keys are x-only, so the private keys
+    // whose public key has an odd Y coordinate are negated.
+    let mut input_key: Option<secp256k1::SecretKey> = None;
+    for (i, key) in input_keys.iter().enumerate() {
+        let pubkey = secp256k1::PublicKey::from_secret_key(secp, key);
+        let spent_script = psbt.inputs[i]
+            .witness_utxo
+            .as_ref()
+            .map(|txo| &txo.script_pubkey);
+        if spent_script != Some(&p2tr_script(&pubkey)) {
+            return Err(SilentPaymentError::IneligibleInputs);
+        }
+        let key = match pubkey.x_only_public_key().1 {
+            secp256k1::Parity::Even => *key,
+            secp256k1::Parity::Odd => key.negate(),
+        };
+        input_key = Some(match input_key {
+            None => key,
+            Some(sum) => sum
+                .add_tweak(&secp256k1::Scalar::from(key))
+                .map_err(SilentPaymentError::InvalidKey)?,
+        });
+    }
+    let input_key = input_key.expect("There is at least one input.");
+    let input_pubkey = secp256k1::PublicKey::from_secret_key(secp, &input_key);
+
+    // Commit to the inputs through the smallest outpoint, as serialized in the transaction.
+    let smallest_outpoint = psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .map(|txin| consensus::serialize(&txin.previous_output))
+        .min()
+        .expect("There is at least one input.");
+    let input_hash = scalar(tagged_hash(
+        "BIP0352/Inputs",
+        &[&smallest_outpoint[..], &input_pubkey.serialize()[..]],
+    ))?;
+    let ecdh_key = input_key
+        .mul_tweak(&input_hash)
+        .map_err(SilentPaymentError::InvalidKey)?;
+
+    // The outputs paying to the same scan key are each derived with an increasing counter.
+    let mut counters: HashMap<secp256k1::PublicKey, u32> = HashMap::new();
+    for (psbt_out, txo) in psbt.outputs.iter().zip(psbt.unsigned_tx.output.iter_mut()) {
+        let (scan_key, spend_key) = match psbt_output_info(psbt_out)? {
+            Some(keys) => keys,
+            None => continue,
+        };
+        let shared_secret = scan_key
+            .mul_tweak(secp, &secp256k1::Scalar::from(ecdh_key))
+            .map_err(SilentPaymentError::InvalidKey)?;
+        let k = counters.entry(scan_key).or_insert(0);
+        let tweak = scalar(tagged_hash(
+            "BIP0352/SharedSecret",
+            &[&shared_secret.serialize()[..], &k.to_be_bytes()[..]],
+        ))?;
+        *k += 1;
+        let output_key = spend_key
+            .add_exp_tweak(secp, &tweak)
+            .map_err(SilentPaymentError::InvalidKey)?;
+        txo.script_pubkey = p2tr_script(&output_key);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> (secp256k1::PublicKey, secp256k1::PublicKey) {
+        let secp = secp256k1::Secp256k1::signing_only();
+        let scan = secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let spend = secp256k1::SecretKey::from_slice(&[2; 32]).unwrap();
+        (
+            secp256k1::PublicKey::from_secret_key(&secp, &scan),
+            secp256k1::PublicKey::from_secret_key(&secp, &spend),
+        )
+    }
+
+    #[test]
+    fn silent_payment_address_roundtrip() {
+        let (scan_key, spend_key) = keys();
+        for network in [NetworkKind::Main, NetworkKind::Test] {
+            let addr = SilentPaymentAddress::new(network, scan_key, spend_key);
+            let addr_str = addr.to_string();
+            assert!(SilentPaymentAddress::is_silent_payment(&addr_str));
+            assert!(addr_str.len() > 90);
+            assert_eq!(SilentPaymentAddress::from_str(&addr_str).unwrap(), addr);
+            assert_eq!(
+                SilentPaymentAddress::from_str(&addr_str.to_uppercase()).unwrap(),
+                addr
+            );
+        }
+        let addr = SilentPaymentAddress::new(NetworkKind::Main, scan_key, spend_key);
+        assert!(addr.to_string().starts_with("sp1q"));
+        assert!(addr.is_valid_for_network(miniscript::bitcoin::Network::Bitcoin));
+        assert!(!addr.is_valid_for_network(miniscript::bitcoin::Network::Signet));
+
+        // A single character change invalidates the checksum.
+        let mut addr_str = addr.to_string();
+        let last = if addr_str.ends_with('q') { "p" } else { "q" };
+        addr_str.replace_range(addr_str.len() - 1.., last);
+        assert!(matches!(
+            SilentPaymentAddress::from_str(&addr_str),
+            Err(SilentPaymentError::InvalidEncoding(_))
+        ));
+
+        // Regular addresses aren't silent payment addresses.
+        let segwit = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+        assert!(!SilentPaymentAddress::is_silent_payment(segwit));
+        assert!(SilentPaymentAddress::from_str(segwit).is_err());
+    }
+
+    #[test]
+    fn silent_payment_address_parse() {
+        // The generator and its double as scan and spend keys.
+        let addr = SilentPaymentAddress::from_str("sp1qqfumuen7l8wthtz45p3ftn58pvrs9xlumvkuu2xet8egzkcklqtesqkxq3legs0d04knq32qd62uqlxct3mcujuvau7202avpxu4cuy7u50zxff0").unwrap();
+        assert_eq!(addr.network, NetworkKind::Main);
+        assert_eq!(addr.version, 0);
+        assert_eq!(
+            addr.scan_key.to_string(),
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+        );
+        assert_eq!(
+            addr.spend_key.to_string(),
+            "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5"
+        );
+        let addr = SilentPaymentAddress::from_str("tsp1qqfumuen7l8wthtz45p3ftn58pvrs9xlumvkuu2xet8egzkcklqtesqkxq3legs0d04knq32qd62uqlxct3mcujuvau7202avpxu4cuy7u5mdxur5").unwrap();
+        assert_eq!(addr.network, NetworkKind::Test);
+        assert!(addr.is_valid_for_network(miniscript::bitcoin::Network::Regtest));
+    }
+
+    #[test]
+    fn silent_payment_eligibility() {
+        // P2WSH coins are never eligible, whether the primary path is a single key or a multisig.
+        let single_key = LianaDescriptor::from_str("wsh(or_d(pk([92162c45]tpubD6NzVbkrYhZ4WzTf9SsD6h7AH7oQEippXK2KP8qvhMMqFoNeN5YFVi7vRyeRSDGtgd2bPyMxUNmHui8t5yCgszxPPxMafu1VVzDpg9aruYW/<0;1>/*),and_v(v:pkh([abcdef01]tpubD6NzVbkrYhZ4Wdgu2yfdmrce5g4fiH1ZLmKhewsnNKupbi4sxjH1ZVAorkBLWSkhsjhg8kiq8C4BrBjMy3SjAKDyDdbuvUa1ToAHbiR98js/<0;1>/*),older(2))))#ravw7jw5").unwrap();
+        let multisig = LianaDescriptor::from_str("wsh(or_d(multi(3,[aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/0/<0;1>/*,[aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/0/<0;1>/*,[aabb0013/48'/0'/0'/2']xpub67zuTXF9Ln4731avKTBSawoVVNRuMfmRvkL7kLUaLBRqma9ZqdHBJg9qx8cPUm3oNQMiXT4TmGovXNoQPuwg17RFcVJ8YrnbcooN7pxVJqC/0/<0;1>/*),and_v(v:thresh(2,pkh([aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/1/<0;1>/*),a:pkh([aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/1/<0;1>/*),a:pkh([aabb0013/48'/0'/0'/2']xpub67zuTXF9Ln4731avKTBSawoVVNRuMfmRvkL7kLUaLBRqma9ZqdHBJg9qx8cPUm3oNQMiXT4TmGovXNoQPuwg17RFcVJ8YrnbcooN7pxVJqC/1/<0;1>/*)),older(26352))))").unwrap();
+        for desc in [&single_key, &multisig] {
+            assert_eq!(
+                check_descriptor(desc),
+                Err(SilentPaymentError::IneligibleInputs)
+            );
+        }
+
+        // Taproot coins with a multisig primary path are spent through a script path. With a
+        // single key primary path they are spent through the key path.
+        assert_eq!(
+            check_descriptor(&multisig.with_taproot(true).unwrap()),
+            Err(SilentPaymentError::IneligibleInputs)
+        );
+        assert_eq!(
+            check_descriptor(&single_key.with_taproot(true).unwrap()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn silent_payment_derivation() {
+        use miniscript::bitcoin::{
+            absolute, transaction::Version, Amount, OutPoint, Sequence, Transaction, TxIn, TxOut,
+            Txid, Witness,
+        };
+
+        let secp = secp256k1::Secp256k1::new();
+        let (scan_key, spend_key) = keys();
+        let addr = SilentPaymentAddress::new(NetworkKind::Main, scan_key, spend_key);
+
+        // A transaction spending two P2TR coins, paying twice to the silent payment address and
+        // to another output.
+        let input_keys = [
+            secp256k1::SecretKey::from_slice(&[3; 32]).unwrap(),
+            secp256k1::SecretKey::from_slice(&[4; 32]).unwrap(),
+        ];
+        let outpoints = [
+            OutPoint::new(Txid::from_slice(&[0xaa; 32]).unwrap(), 1),
+            OutPoint::new(Txid::from_slice(&[0x11; 32]).unwrap(), 7),
+        ];
+        let other_script = p2tr_script(&secp256k1::PublicKey::from_secret_key(
+            &secp,
+            &secp256k1::SecretKey::from_slice(&[5; 32]).unwrap(),
+        ));
+        let txo = |script_pubkey: ScriptBuf| TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey,
+        };
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: outpoints
+                .iter()
+                .map(|op| TxIn {
+                    previous_output: *op,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![
+                txo(addr.placeholder_script()),
+                txo(other_script.clone()),
+                txo(addr.placeholder_script()),
+            ],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        for (psbt_in, key) in psbt.inputs.iter_mut().zip(input_keys.iter()) {
+            let pubkey = secp256k1::PublicKey::from_secret_key(&secp, key);
+            psbt_in.witness_utxo = Some(txo(p2tr_script(&pubkey)));
+        }
+        set_psbt_output_info(&mut psbt.outputs[0], &addr);
+        set_psbt_output_info(&mut psbt.outputs[2], &addr);
+        assert!(pays_silent_payment(&psbt));
+        assert!(has_underived_outputs(&psbt));
+
+        // The keys of all the inputs are needed, and must be those of the coins.
+        assert_eq!(
+            derive_outputs(&secp, &mut psbt.clone(), &input_keys[..1]),
+            Err(SilentPaymentError::MissingInputKeys)
+        );
+        assert_eq!(
+            derive_outputs(&secp, &mut psbt.clone(), &[input_keys[1], input_keys[0]]),
+            Err(SilentPaymentError::IneligibleInputs)
+        );
+
+        let placeholder = psbt.clone();
+        derive_outputs(&secp, &mut psbt, &input_keys).unwrap();
+        assert!(pays_silent_payment(&psbt));
+        assert!(!has_underived_outputs(&psbt));
+        // The derived transaction can be told to be the one of the PSBT with placeholders.
+        assert!(is_derived_from(&psbt.unsigned_tx, &placeholder));
+        assert!(!is_derived_from(&placeholder.unsigned_tx, &placeholder));
+        let mut other_tx = psbt.unsigned_tx.clone();
+        other_tx.output[1].script_pubkey = addr.placeholder_script();
+        assert!(!is_derived_from(&other_tx, &placeholder));
+        let outputs = &psbt.unsigned_tx.output;
+        assert_eq!(outputs[1].script_pubkey, other_script);
+        assert_ne!(outputs[0].script_pubkey, addr.placeholder_script());
+        assert_ne!(outputs[0].script_pubkey, outputs[2].script_pubkey);
+
+        // The recipient finds both outputs using the public keys of the inputs and its private
+        // scan key.
+        let input_pubkeys: Vec<_> = input_keys
+            .iter()
+            .map(|key| {
+                let (xonly, _) =
+                    secp256k1::PublicKey::from_secret_key(&secp, key).x_only_public_key();
+                secp256k1::PublicKey::from_x_only_public_key(xonly, secp256k1::Parity::Even)
+            })
+            .collect();
+        let input_pubkey = input_pubkeys[0].combine(&input_pubkeys[1]).unwrap();
+        let smallest_outpoint = consensus::serialize(&outpoints[1]);
+        let input_hash = scalar(tagged_hash(
+            "BIP0352/Inputs",
+            &[&smallest_outpoint[..], &input_pubkey.serialize()[..]],
+        ))
+        .unwrap();
+        let scan_privkey = secp256k1::SecretKey::from_slice(&[1; 32])
+            .unwrap()
+            .mul_tweak(&input_hash)
+            .unwrap();
+        let shared_secret = input_pubkey
+            .mul_tweak(&secp, &secp256k1::Scalar::from(scan_privkey))
+            .unwrap();
+        for (k, i) in [(0u32, 0), (1, 2)] {
+            let tweak = scalar(tagged_hash(
+                "BIP0352/SharedSecret",
+                &[&shared_secret.serialize()[..], &k.to_be_bytes()[..]],
+            ))
+            .unwrap();
+            let output_key = spend_key.add_exp_tweak(&secp, &tweak).unwrap();
+            assert_eq!(outputs[i].script_pubkey, p2tr_script(&output_key));
+        }
+
+        // Deriving them again gives the same outputs.
+        let derived = psbt.clone();
+        derive_outputs(&secp, &mut psbt, &input_keys).unwrap();
+        assert_eq!(psbt, derived);
+    }
+}
@@ -3,8 +3,8 @@
 use crate::descriptors;
 
 use std::{
-    collections::{BTreeMap, HashMap},
-    convert::TryInto,
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::{TryFrom, TryInto},
     fmt,
     time::Duration,
 };
@@ -40,6 +40,17 @@ pub const MAX_FEE: bitcoin::Amount = bitcoin::Amount::ONE_BTC;
 /// Assume that paying more than 1000sat/vb in feerate is a bug.
 pub const MAX_FEERATE: u64 = 1_000;
 
+/// The maximum size of the data committed to in an OP_RETURN output, in bytes. This is the
+/// largest which is standard for all versions of Bitcoin Core.
+pub const MAX_OP_RETURN_DATA_SIZE: usize = 80;
+
+/// The maximum size of a TRUC (v3) transaction, in virtual bytes (BIP431).
+pub const TRUC_MAX_VSIZE: u64 = 10_000;
+
+/// The maximum size of a TRUC (v3) transaction spending an unconfirmed output, in virtual bytes
+/// (BIP431).
+pub const TRUC_CHILD_MAX_VSIZE: u64 = 1_000;
+
 /// Do not set locktime if tip age in seconds is older than this.
 // See also https://github.com/bitcoin/bitcoin/blob/ecd23656db174adef61d3bd753d02698c3528192/src/wallet/spend.cpp#L906.
 pub const MAX_ANTI_FEE_SNIPING_TIP_AGE_SECS: u64 = 8 * 60 * 60; // 8 hours
@@ -52,6 +63,21 @@ pub enum InsaneFeeInfo {
     TooHighFeerate(u64),
 }
 
+/// A violation of the topology restrictions on TRUC (v3) transactions (BIP431).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrucViolation {
+    /// The TRUC transaction would be larger than [`TRUC_MAX_VSIZE`] vbytes.
+    TooLarge(u64),
+    /// The TRUC transaction spends an unconfirmed output and would be larger than
+    /// [`TRUC_CHILD_MAX_VSIZE`] vbytes.
+    ChildTooLarge(u64),
+    /// The TRUC transaction spends outputs of more than one unconfirmed transaction.
+    MultipleUnconfirmedParents,
+    /// The transaction spends an unconfirmed output of this transaction, which is TRUC while the
+    /// spending one isn't or the other way around.
+    MixedVersions(bitcoin::Txid),
+}
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SpendCreationError {
@@ -61,6 +87,11 @@ pub enum SpendCreationError {
     SanityCheckFailure(Psbt),
     FetchingTransaction(bitcoin::OutPoint),
     CoinSelection(InsufficientFunds),
+    InvalidDestinations,
+    OpReturnDataTooLarge(usize),
+    Truc(TrucViolation),
+    /// A transaction with this many inputs and outputs would exceed the standard size limit.
+    TooLargeTransaction(/* inputs */ usize, /* outputs */ usize),
 }
 
 impl fmt::Display for SpendCreationError {
@@ -85,6 +116,41 @@ impl fmt::Display for SpendCreationError {
                 write!(f, "Could not fetch transaction for coin {op}")
             }
             Self::CoinSelection(e) => write!(f, "Coin selection error: '{e}'"),
+            Self::InvalidDestinations => write!(
+                f,
+                "At most one destination may receive the maximum amount, in which case no other \
+                destination may have the fee subtracted from its amount."
+            ),
+            Self::OpReturnDataTooLarge(size) => write!(
+                f,
+                "OP_RETURN data is {size} bytes long, maximum is {MAX_OP_RETURN_DATA_SIZE}."
+            ),
+            Self::Truc(violation) => match violation {
+                TrucViolation::TooLarge(vb) => write!(
+                    f,
+                    "A TRUC (v3) transaction may be at most {TRUC_MAX_VSIZE} vbytes, the created \
+                    transaction would be {vb} vbytes."
+                ),
+                TrucViolation::ChildTooLarge(vb) => write!(
+                    f,
+                    "A TRUC (v3) transaction spending an unconfirmed coin may be at most \
+                    {TRUC_CHILD_MAX_VSIZE} vbytes, the created transaction would be {vb} vbytes."
+                ),
+                TrucViolation::MultipleUnconfirmedParents => write!(
+                    f,
+                    "A TRUC (v3) transaction may only spend unconfirmed coins from a single \
+                    transaction."
+                ),
+                TrucViolation::MixedVersions(txid) => write!(
+                    f,
+                    "Unconfirmed coins of transaction '{txid}' can't be spent: TRUC (v3) and \
+                    non-TRUC transactions may not spend each other's unconfirmed outputs."
+                ),
+            },
+            Self::TooLargeTransaction(ins, outs) => write!(
+                f,
+                "A transaction with {ins} inputs and {outs} outputs exceeds the maximum standard size."
+            ),
             Self::SanityCheckFailure(psbt) => write!(
                 f,
                 "BUG! Please report this. Failed sanity checks for PSBT '{psbt}'.",
@@ -160,12 +226,82 @@ fn sanity_check_psbt(
         ));
     }
 
-    // Check for dust outputs
+    // Check for dust outputs. A single data output is allowed, which must not carry any value nor
+    // too much data.
+    let mut data_outputs = 0;
     for txo in psbt.unsigned_tx.output.iter() {
-        if txo.value < txo.script_pubkey.minimal_non_dust() {
+        if txo.script_pubkey.is_op_return() {
+            data_outputs += 1;
+            if data_outputs > 1
+                || txo.value != bitcoin::Amount::ZERO
+                || txo.script_pubkey.len() > MAX_OP_RETURN_DATA_SIZE + 3
+            {
+                return Err(SpendCreationError::SanityCheckFailure(psbt.clone()));
+            }
+        } else if txo.value < txo.script_pubkey.minimal_non_dust() {
             return Err(SpendCreationError::SanityCheckFailure(psbt.clone()));
         }
     }
+    // A transaction with only a data output would burn all its inputs.
+    if data_outputs == tx.output.len() {
+        return Err(SpendCreationError::SanityCheckFailure(psbt.clone()));
+    }
+
+    // The locktime must be enforced. For this no input may have its nSequence final.
+    if tx
+        .input
+        .iter()
+        .any(|txin| txin.sequence == bitcoin::Sequence::MAX)
+    {
+        return Err(SpendCreationError::SanityCheckFailure(psbt.clone()));
+    }
+
+    Ok(())
+}
+
+// Check the mempool policy restrictions on the topology of TRUC (v3) transactions: a TRUC
+// transaction may have a single unconfirmed parent, in which case its size is further restricted,
+// and TRUC and non-TRUC transactions may not spend each other's unconfirmed outputs. Coins with
+// ancestor info are assumed to be unconfirmed.
+fn check_truc_topology(
+    desc: &descriptors::LianaDescriptor,
+    tx_getter: &mut impl TxGetter,
+    tx: &bitcoin::Transaction,
+    selected: &[CandidateCoin],
+    use_primary_path: bool,
+) -> Result<(), SpendCreationError> {
+    let is_truc = tx.version == bitcoin::transaction::Version(3);
+    let mut unconfirmed_parents = HashSet::new();
+    for cand in selected.iter().filter(|cand| cand.ancestor_info.is_some()) {
+        let txid = cand.outpoint.txid;
+        if !unconfirmed_parents.insert(txid) {
+            continue;
+        }
+        let parent = tx_getter
+            .get_tx(&txid)
+            .ok_or(SpendCreationError::FetchingTransaction(cand.outpoint))?;
+        if is_truc != (parent.version == bitcoin::transaction::Version(3)) {
+            return Err(SpendCreationError::Truc(TrucViolation::MixedVersions(txid)));
+        }
+    }
+    if !is_truc {
+        return Ok(());
+    }
+
+    if unconfirmed_parents.len() > 1 {
+        return Err(SpendCreationError::Truc(
+            TrucViolation::MultipleUnconfirmedParents,
+        ));
+    }
+    let tx_vb = desc.unsigned_tx_max_vbytes(tx, use_primary_path);
+    if tx_vb > TRUC_MAX_VSIZE {
+        return Err(SpendCreationError::Truc(TrucViolation::TooLarge(tx_vb)));
+    }
+    if !unconfirmed_parents.is_empty() && tx_vb > TRUC_CHILD_MAX_VSIZE {
+        return Err(SpendCreationError::Truc(TrucViolation::ChildTooLarge(
+            tx_vb,
+        )));
+    }
 
     Ok(())
 }
@@ -268,6 +404,11 @@ impl bdk_coin_select::BnbMetric for LowestFeeChangeCondition {
 ///
 /// `must_have_change` indicates whether the transaction must have a change output.
 /// If `true`, the returned change amount will be positive.
+///
+/// `fee_from_outputs` indicates whether the fee will be deducted from the outputs of `base_tx`
+/// rather than paid by the selected coins. If `true`, the selected coins only need to cover the
+/// value of the outputs and the change amount doesn't account for the fee.
+#[allow(clippy::too_many_arguments)]
 fn select_coins_for_spend(
     candidate_coins: &[CandidateCoin],
     base_tx: bitcoin::Transaction,
@@ -276,6 +417,7 @@ fn select_coins_for_spend(
     replaced_fee: Option<u64>,
     max_sat_weight: u64,
     must_have_change: bool,
+    fee_from_outputs: bool,
 ) -> Result<CoinSelectionRes, InsufficientFunds> {
     let out_value_nochange = base_tx.output.iter().map(|o| o.value.to_sat()).sum();
     let out_weight_nochange = {
@@ -365,10 +507,16 @@ fn select_coins_for_spend(
 
     // Finally, run the coin selection algorithm. We use an opportunistic BnB and if it couldn't
     // find any solution we fall back to selecting coins by descending value.
-    let replace = replaced_fee.map(Replace::new);
-    let target_fee = TargetFee {
-        rate: feerate,
-        replace,
+    let target_fee = if fee_from_outputs {
+        TargetFee {
+            rate: FeeRate::from_sat_per_vb(0.0),
+            replace: None,
+        }
+    } else {
+        TargetFee {
+            rate: feerate,
+            replace: replaced_fee.map(Replace::new),
+        }
     };
     let target_outputs = TargetOutputs {
         value_sum: out_value_nochange,
@@ -482,6 +630,26 @@ fn derived_desc(
     desc.derive(coin.deriv_index, secp)
 }
 
+/// Create a PSBT input spending this coin, populated with the information needed by signers.
+pub(crate) fn coin_psbt_in(
+    secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    desc: &descriptors::LianaDescriptor,
+    tx_getter: &mut impl TxGetter,
+    coin: &CandidateCoin,
+) -> PsbtIn {
+    let mut psbt_in = PsbtIn::default();
+    let coin_desc = derived_desc(secp, desc, coin);
+    coin_desc.update_psbt_in(&mut psbt_in);
+    psbt_in.witness_utxo = Some(bitcoin::TxOut {
+        value: coin.amount,
+        script_pubkey: coin_desc.script_pubkey(),
+    });
+    if !desc.is_taproot() {
+        psbt_in.non_witness_utxo = tx_getter.get_tx(&coin.outpoint.txid);
+    }
+    psbt_in
+}
+
 /// Get value to use for transaction nLockTime in order to
 /// discourage fee sniping.
 ///
@@ -516,6 +684,117 @@ pub fn anti_fee_sniping_locktime(
         .unwrap_or(LockTime::Blocks(Height::ZERO))
 }
 
+/// The estimated cost of a transaction spending coins through a given spending path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathSpendCost {
+    /// Maximum size in virtual bytes of a single input spending through this path.
+    pub input_vbytes: u64,
+    /// Maximum size in virtual bytes of the whole transaction.
+    pub tx_vbytes: u64,
+    /// The fees paid by the transaction for each simulated feerate, as (sats/vb, fee) pairs.
+    pub fees: Vec<(u64, bitcoin::Amount)>,
+}
+
+/// The estimated cost of spending coins through each spending path of a descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpendCostSimulation {
+    pub primary_path: PathSpendCost,
+    /// The cost for each recovery path, by timelock.
+    pub recovery_paths: BTreeMap<u16, PathSpendCost>,
+}
+
+/// Simulate the cost of a transaction with `num_inputs` inputs and `num_outputs` outputs, spending
+/// coins from this descriptor through each of its spending paths, at each of the given feerates
+/// (in sats/vb).
+///
+/// All inputs are assumed to be spent using the same spending path and all outputs are assumed to
+/// be of the same type as the descriptor (P2WSH or P2TR). The returned sizes are upper bounds.
+pub fn simulate_spend_cost(
+    desc: &descriptors::LianaDescriptor,
+    secp: &secp256k1::Secp256k1<impl secp256k1::Verification>,
+    num_inputs: usize,
+    num_outputs: usize,
+    feerates: &[u64],
+) -> Result<SpendCostSimulation, SpendCreationError> {
+    if let Some(feerate) = feerates
+        .iter()
+        .find(|f| **f < 1 || **f > MAX_FEERATE)
+        .copied()
+    {
+        return Err(SpendCreationError::InvalidFeerate(feerate));
+    }
+    // An input is at least 41 vbytes and an output 43 vbytes. Don't simulate transactions which
+    // could never be relayed, this also bounds the size of the dummy transaction we create.
+    let max_vbytes = (bitcoin::policy::MAX_STANDARD_TX_WEIGHT / 4) as usize;
+    let min_vbytes = num_inputs
+        .checked_mul(41)
+        .zip(num_outputs.checked_mul(43))
+        .and_then(|(ins, outs)| ins.checked_add(outs));
+    if min_vbytes.is_none_or(|vb| vb > max_vbytes) {
+        return Err(SpendCreationError::TooLargeTransaction(
+            num_inputs,
+            num_outputs,
+        ));
+    }
+
+    // A dummy transaction with the requested number of inputs and outputs. The outputs pay to our
+    // own Script, which has the same size under both P2WSH and P2TR.
+    let script_pubkey = desc
+        .receive_descriptor()
+        .derive(0.into(), secp)
+        .script_pubkey();
+    let tx = bitcoin::Transaction {
+        version: bitcoin::transaction::Version::TWO,
+        lock_time: LockTime::Blocks(Height::ZERO),
+        input: vec![bitcoin::TxIn::default(); num_inputs],
+        output: vec![
+            bitcoin::TxOut {
+                value: bitcoin::Amount::ZERO,
+                script_pubkey,
+            };
+            num_outputs
+        ],
+    };
+
+    let path_cost = |timelock: Option<u16>| -> PathSpendCost {
+        let sat_weight: u64 = desc
+            .path_max_sat_weight(timelock)
+            .expect("Path exists")
+            .try_into()
+            .expect("Sat weight must fit in u64.");
+        let witness_factor: u64 = WITNESS_SCALE_FACTOR.try_into().unwrap();
+        // txid + vout + nSequence + empty scriptSig + witness
+        let input_vbytes = 32 + 4 + 4 + 1 + (sat_weight + witness_factor - 1) / witness_factor;
+        let tx_vbytes = desc
+            .unsigned_tx_max_vbytes_for_path(&tx, timelock)
+            .expect("Path exists");
+        let fees = feerates
+            .iter()
+            .map(|feerate| {
+                (
+                    *feerate,
+                    bitcoin::Amount::from_sat(tx_vbytes.checked_mul(*feerate).unwrap()),
+                )
+            })
+            .collect();
+        PathSpendCost {
+            input_vbytes,
+            tx_vbytes,
+            fees,
+        }
+    };
+
+    Ok(SpendCostSimulation {
+        primary_path: path_cost(None),
+        recovery_paths: desc
+            .policy()
+            .recovery_paths()
+            .keys()
+            .map(|tl| (*tl, path_cost(Some(*tl))))
+            .collect(),
+    })
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct AddrInfo {
     pub index: bip32::ChildNumber,
@@ -528,6 +807,44 @@ pub struct SpendOutputAddress {
     pub info: Option<AddrInfo>,
 }
 
+/// The amount to send to a destination of a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DestinationAmount {
+    /// Send exactly this amount.
+    Fixed(bitcoin::Amount),
+    /// Send this amount minus a share of the transaction fee. The fee is split between all the
+    /// destinations set so, proportionally to their amount.
+    SubtractFee(bitcoin::Amount),
+    /// Send the value of all the candidate coins left after paying the other destinations and the
+    /// fee.
+    Max,
+}
+
+/// Advanced options for the creation of a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpendOptions {
+    /// Data to commit to in an additional, zero-value, OP_RETURN output. At most
+    /// [`MAX_OP_RETURN_DATA_SIZE`] bytes.
+    pub op_return_data: Option<Vec<u8>>,
+    /// Whether to signal replaceability (BIP125) through the nSequence of the inputs. Inputs spent
+    /// using a recovery path always signal it because of their relative timelock.
+    pub signal_rbf: bool,
+    /// Whether to create a TRUC (v3) transaction (BIP431). Such a transaction is always
+    /// replaceable, and is subject to restrictions on its size and on its unconfirmed ancestors
+    /// and descendants which make fee bumping it more reliable.
+    pub truc: bool,
+}
+
+impl Default for SpendOptions {
+    fn default() -> Self {
+        Self {
+            op_return_data: None,
+            signal_rbf: true,
+            truc: false,
+        }
+    }
+}
+
 /// A trait for getting a wallet transaction by its txid.
 pub trait TxGetter {
     /// Get a wallet transaction. Allows for a cache by making the access mutable.
@@ -599,7 +916,9 @@ pub struct CreateSpendRes {
 /// * `destinations`: a list of addresses and amounts, one per recipient i.e. per output in the
 ///   transaction created. If empty all the `candidate_coins` get spent and a single change output
 ///   is created to the provided `change_addr`. Can be used to sweep all, or some, coins from the
-///   wallet.
+///   wallet. The fee may be subtracted from the amount of some destinations. At most one
+///   destination may receive the maximum amount, in which case all the `candidate_coins` get spent,
+///   no change output is created and the fee may not be subtracted from other destinations.
 /// * `candidate_coins`: a list of coins to consider including as input of the transaction. If
 ///   `destinations` is empty, they will all be included as inputs of the transaction. Otherwise, a
 ///   coin selection algorithm will be run to spend the most efficient subset of them to meet the
@@ -607,18 +926,21 @@ pub struct CreateSpendRes {
 /// * `fees`: the target feerate (in sats/vb) and, if necessary, minimum absolute fee for this tx.
 /// * `change_addr`: the address to use for a change output if we need to create one. Can be set to
 ///   an external address (if combined with an empty list of `destinations` it's useful to sweep some
-///   or all coins of a wallet to an external address).
+///   or all coins of a wallet to an external address). Unused if a destination receives the
+///   maximum amount.
 /// * `locktime`: the locktime to use for the transaction.
+/// * `options`: additional outputs and signaling for the transaction.
 #[allow(clippy::too_many_arguments)]
 pub fn create_spend(
     main_descriptor: &descriptors::LianaDescriptor,
     secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
     tx_getter: &mut impl TxGetter,
-    destinations: &[(SpendOutputAddress, bitcoin::Amount)],
+    destinations: &[(SpendOutputAddress, DestinationAmount)],
     candidate_coins: &[CandidateCoin],
     fees: SpendTxFees,
     change_addr: SpendOutputAddress,
     locktime: LockTime,
+    options: &SpendOptions,
 ) -> Result<CreateSpendRes, SpendCreationError> {
     // This method does quite a few things. In addition, we support different modes (coin control
     // vs automated coin selection, self-spend, sweep, etc..) which make the logic a bit more
@@ -644,21 +966,52 @@ pub fn create_spend(
         return Err(SpendCreationError::InvalidFeerate(feerate_vb));
     }
 
+    // A destination receiving the maximum amount is treated as the change output, paying the whole
+    // fee. All the candidates are spent.
+    let mut max_destinations = destinations
+        .iter()
+        .filter(|(_, amount)| *amount == DestinationAmount::Max);
+    let max_addr = max_destinations.next().map(|(address, _)| address.clone());
+    let fee_from_outputs = destinations
+        .iter()
+        .any(|(_, amount)| matches!(amount, DestinationAmount::SubtractFee(_)));
+    if max_destinations.next().is_some() || (max_addr.is_some() && fee_from_outputs) {
+        return Err(SpendCreationError::InvalidDestinations);
+    }
+    let sends_max = max_addr.is_some();
+    let change_addr = max_addr.unwrap_or(change_addr);
+    let candidate_coins: Vec<CandidateCoin> = candidate_coins
+        .iter()
+        .map(|cand| CandidateCoin {
+            must_select: cand.must_select || sends_max,
+            ..*cand
+        })
+        .collect();
+    let fixed_outputs_count = destinations.len() - usize::from(sends_max);
+
     // Create transaction with no inputs and no outputs.
     let mut tx = bitcoin::Transaction {
-        version: bitcoin::transaction::Version::TWO,
+        version: if options.truc {
+            bitcoin::transaction::Version(3)
+        } else {
+            bitcoin::transaction::Version::TWO
+        },
         lock_time: locktime,
         input: Vec::with_capacity(candidate_coins.iter().filter(|c| c.must_select).count()),
         output: Vec::with_capacity(destinations.len()),
     };
     // Add the destinations outputs to the transaction and PSBT. At the same time
-    // sanity check each output's value.
+    // sanity check each output's value. The fee is subtracted later on.
     let mut psbt_outs = Vec::with_capacity(destinations.len());
     for (address, amount) in destinations {
-        check_output_value(*amount)?;
+        let amount = match amount {
+            DestinationAmount::Fixed(amount) | DestinationAmount::SubtractFee(amount) => *amount,
+            DestinationAmount::Max => continue,
+        };
+        check_output_value(amount)?;
 
         tx.output.push(bitcoin::TxOut {
-            value: *amount,
+            value: amount,
             script_pubkey: address.addr.script_pubkey(),
         });
         // If it's an address of ours, signal it as change to signing devices by adding the
@@ -675,7 +1028,21 @@ pub fn create_spend(
         }
         psbt_outs.push(psbt_out);
     }
-    assert_eq!(tx.output.is_empty(), is_self_send);
+    assert_eq!(tx.output.len(), fixed_outputs_count);
+    // The data output, if any, comes after the destinations. It is accounted for in coin selection
+    // like any other output.
+    if let Some(data) = &options.op_return_data {
+        let data = bitcoin::script::PushBytesBuf::try_from(data.clone())
+            .ok()
+            .filter(|data| data.len() <= MAX_OP_RETURN_DATA_SIZE)
+            .ok_or(SpendCreationError::OpReturnDataTooLarge(data.len()))?;
+        tx.output.push(bitcoin::TxOut {
+            value: bitcoin::Amount::ZERO,
+            script_pubkey: bitcoin::ScriptBuf::new_op_return(data),
+        });
+        psbt_outs.push(PsbtOut::default());
+    }
+    let base_outputs_count = tx.output.len();
 
     // Now compute whether we'll need a change output while automatically selecting coins to be
     // used as input if necessary.
@@ -703,7 +1070,7 @@ pub fn create_spend(
         // At this point the transaction still has no input and no change output, as expected
         // by the coins selection helper function.
         assert!(tx.input.is_empty());
-        assert_eq!(tx.output.len(), destinations.len());
+        assert_eq!(tx.output.len(), base_outputs_count);
         // TODO: Introduce general conversion error type.
         let feerate_vb: f32 = {
             let fr: u16 = feerate_vb.try_into().map_err(|_| {
@@ -717,19 +1084,21 @@ pub fn create_spend(
             .try_into()
             .expect("Weight must fit in a u64");
         select_coins_for_spend(
-            candidate_coins,
+            &candidate_coins,
             tx.clone(),
             change_txo.clone(),
             feerate_vb,
             replaced_fee,
             max_sat_wu,
-            is_self_send,
+            is_self_send || sends_max,
+            fee_from_outputs,
         )
         .map_err(SpendCreationError::CoinSelection)?
     };
     // If necessary, add a change output.
-    // For a self-send, coin selection will only find solutions with change and will otherwise
-    // return an error. In any case, the PSBT sanity check will catch a transaction with no outputs.
+    // For a self-send, or when sending the max, coin selection will only find solutions with change
+    // and will otherwise return an error. In any case, the PSBT sanity check will catch a
+    // transaction with no outputs.
     let has_change = change_amount.to_sat() > 0;
     if has_change {
         check_output_value(change_amount)?;
@@ -766,9 +1135,11 @@ pub fn create_spend(
     // Iterate through selected coins and add necessary information to the PSBT inputs.
     let mut psbt_ins = Vec::with_capacity(selected.len());
     for cand in &selected {
-        let sequence = cand
-            .sequence
-            .unwrap_or(bitcoin::Sequence::ENABLE_RBF_NO_LOCKTIME);
+        let sequence = cand.sequence.unwrap_or(if options.signal_rbf {
+            bitcoin::Sequence::ENABLE_RBF_NO_LOCKTIME
+        } else {
+            bitcoin::Sequence::ENABLE_LOCKTIME_NO_RBF
+        });
         tx.input.push(bitcoin::TxIn {
             previous_output: cand.outpoint,
             sequence,
@@ -776,18 +1147,57 @@ pub fn create_spend(
             ..bitcoin::TxIn::default()
         });
 
-        // Populate the PSBT input with the information needed by signers.
-        let mut psbt_in = PsbtIn::default();
-        let coin_desc = derived_desc(secp, main_descriptor, cand);
-        coin_desc.update_psbt_in(&mut psbt_in);
-        psbt_in.witness_utxo = Some(bitcoin::TxOut {
-            value: cand.amount,
-            script_pubkey: coin_desc.script_pubkey(),
-        });
-        if !main_descriptor.is_taproot() {
-            psbt_in.non_witness_utxo = tx_getter.get_tx(&cand.outpoint.txid);
+        psbt_ins.push(coin_psbt_in(secp, main_descriptor, tx_getter, cand));
+    }
+
+    // Now we know the size of the transaction, subtract the fee from the destinations set so. It
+    // is split proportionally to their amount. Note those are the first outputs, in the same order,
+    // since no destination receives the max.
+    if fee_from_outputs {
+        let tx_vb = main_descriptor.unsigned_tx_max_vbytes(&tx, use_primary_path);
+        let mut fee = tx_vb
+            .checked_mul(feerate_vb)
+            .and_then(|fee| fee.checked_add(fee_for_ancestors.to_sat()))
+            .ok_or(SpendCreationError::InsaneFees(
+                InsaneFeeInfo::InvalidFeerate,
+            ))?;
+        if let Some(replaced_fee) = replaced_fee {
+            // The replacement must also pay for its own relay, at the incremental relay feerate.
+            fee = fee.max(replaced_fee.saturating_add(tx_vb));
+        }
+        // The value left over by the coin selection, if any, already goes to the fee.
+        let value_in: u64 = selected.iter().map(|cand| cand.amount.to_sat()).sum();
+        let value_out: u64 = tx.output.iter().map(|txo| txo.value.to_sat()).sum();
+        let to_subtract: u128 = fee
+            .saturating_sub(value_in.saturating_sub(value_out))
+            .into();
+
+        let subtract_from: Vec<(usize, u128)> = destinations
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (_, amount))| match amount {
+                DestinationAmount::SubtractFee(amount) => Some((i, amount.to_sat().into())),
+                _ => None,
+            })
+            .collect();
+        let total: u128 = subtract_from.iter().map(|(_, amount)| amount).sum();
+        let shares: Vec<u128> = subtract_from
+            .iter()
+            .map(|(_, amount)| to_subtract * amount / total)
+            .collect();
+        // Each share is rounded down, the remainder is split between the first destinations.
+        let remainder = to_subtract - shares.iter().sum::<u128>();
+        for (j, ((i, amount), share)) in subtract_from.into_iter().zip(shares).enumerate() {
+            let share = share + u128::from((j as u128) < remainder);
+            let value = bitcoin::Amount::from_sat(
+                amount
+                    .saturating_sub(share)
+                    .try_into()
+                    .expect("Must be lower than the original amount."),
+            );
+            check_output_value(value)?;
+            tx.output[i].value = value;
         }
-        psbt_ins.push(psbt_in);
     }
 
     // Finally, create the PSBT with all inputs and outputs, sanity check it and return it.
@@ -801,11 +1211,18 @@ pub fn create_spend(
         outputs: psbt_outs,
     };
     sanity_check_psbt(main_descriptor, &psbt, use_primary_path)?;
+    check_truc_topology(
+        main_descriptor,
+        tx_getter,
+        &psbt.unsigned_tx,
+        &selected,
+        use_primary_path,
+    )?;
     // TODO: maybe check for common standardness rules (max size, ..)?
 
     Ok(CreateSpendRes {
         psbt,
-        has_change,
+        has_change: has_change && !sends_max,
         warnings,
     })
 }
@@ -814,7 +1231,7 @@ pub fn create_spend(
 mod tests {
     use super::*;
 
-    use std::time::Duration;
+    use std::{str::FromStr, time::Duration};
 
     use miniscript::bitcoin::absolute::{Height, LockTime};
 
@@ -927,4 +1344,209 @@ mod tests {
             LockTime::from_height(1).unwrap() // subtract 90
         );
     }
+
+    #[test]
+    fn test_simulate_spend_cost() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc = descriptors::LianaDescriptor::from_str("tr(tpubD6NzVbkrYhZ4WUdbVsXDYBCXS8EPSYG1cAN9g4uP6uLQHMHXRvHSFkQBXy7MBeAvV8PDVJJ4o3AwYMKJHp45ci2g69UCAKteVSAJ61CnGEV/<0;1>/*,{and_v(v:pk([9e1c1983/48'/1'/0'/2']tpubDEWCLCMncbStq4BLXkQUAPqzzrh2tQUgYeQPt4NrB5D7gRraMyGbRqzPTmQGvqfdaFsXDVGSQBRgfXuNjDyfU626pxSjpQZszFNY6CzogxK/<2;3>/*),older(65535)),multi_a(2,[9e1c1983/48'/1'/0'/2']tpubDEWCLCMncbStq4BLXkQUAPqzzrh2tQUgYeQPt4NrB5D7gRraMyGbRqzPTmQGvqfdaFsXDVGSQBRgfXuNjDyfU626pxSjpQZszFNY6CzogxK/<0;1>/*,[3b1913e1/48'/1'/0'/2']tpubDFeZ2ezf4VUuTnjdhxJ1DKhLa2t6vzXZNz8NnEgeT2PN4pPqTCTeWUcaxKHPJcf1C8WzkLA71zSjDwuo4zqu4kkiL91ZUmJydC8f1gx89wM/<0;1>/*)})#ee0r4tw5").unwrap();
+
+        // Feerates must be sane.
+        assert_eq!(
+            simulate_spend_cost(&desc, &secp, 1, 1, &[0]),
+            Err(SpendCreationError::InvalidFeerate(0))
+        );
+        assert_eq!(
+            simulate_spend_cost(&desc, &secp, 1, 1, &[1, MAX_FEERATE + 1]),
+            Err(SpendCreationError::InvalidFeerate(MAX_FEERATE + 1))
+        );
+
+        // Transactions which could never be relayed aren't simulated.
+        assert_eq!(
+            simulate_spend_cost(&desc, &secp, usize::MAX, 1, &[1]),
+            Err(SpendCreationError::TooLargeTransaction(usize::MAX, 1))
+        );
+        assert_eq!(
+            simulate_spend_cost(&desc, &secp, 1, 2_500, &[1]),
+            Err(SpendCreationError::TooLargeTransaction(1, 2_500))
+        );
+
+        let sim = simulate_spend_cost(&desc, &secp, 2, 1, &[1, 10, 100]).unwrap();
+        assert_eq!(
+            sim.primary_path.input_vbytes,
+            desc.spender_input_size(true) as u64
+        );
+        // The primary path satisfaction weighs 268 WU (see the descriptor sat weight tests), so an
+        // input is 41 + 67 vbytes. The unsigned transaction weighs (4 + 1 + 2 * 41 + 1 + 43 + 4) * 4
+        // = 540 WU, plus 2 WU for the Segwit marker and flag: (540 + 2 + 2 * 268) / 4 = 269.5 vbytes.
+        assert_eq!(sim.primary_path.input_vbytes, 108);
+        assert_eq!(sim.primary_path.tx_vbytes, 270);
+        assert_eq!(
+            sim.primary_path.fees,
+            vec![
+                (1, bitcoin::Amount::from_sat(270)),
+                (10, bitcoin::Amount::from_sat(2_700)),
+                (100, bitcoin::Amount::from_sat(27_000)),
+            ]
+        );
+        assert_eq!(sim.recovery_paths.len(), 1);
+        let recov = &sim.recovery_paths[&65535];
+        assert!(recov.input_vbytes < sim.primary_path.input_vbytes);
+        assert!(recov.tx_vbytes < sim.primary_path.tx_vbytes);
+        for cost in [&sim.primary_path, recov] {
+            assert_eq!(
+                cost.fees,
+                vec![
+                    (1, bitcoin::Amount::from_sat(cost.tx_vbytes)),
+                    (10, bitcoin::Amount::from_sat(cost.tx_vbytes * 10)),
+                    (100, bitcoin::Amount::from_sat(cost.tx_vbytes * 100)),
+                ]
+            );
+        }
+
+        // Adding an input costs at least the size of an input, adding an output exactly 43 vbytes.
+        let sim_3_1 = simulate_spend_cost(&desc, &secp, 3, 1, &[1]).unwrap();
+        assert!(
+            sim_3_1.primary_path.tx_vbytes - sim.primary_path.tx_vbytes
+                >= sim.primary_path.input_vbytes - 1
+        );
+        let sim_2_2 = simulate_spend_cost(&desc, &secp, 2, 2, &[1]).unwrap();
+        assert_eq!(
+            sim_2_2.primary_path.tx_vbytes - sim.primary_path.tx_vbytes,
+            43
+        );
+    }
+
+    #[test]
+    fn test_check_truc_topology() {
+        struct MapTxGetter(HashMap<bitcoin::Txid, bitcoin::Transaction>);
+        impl TxGetter for MapTxGetter {
+            fn get_tx(&mut self, txid: &bitcoin::Txid) -> Option<bitcoin::Transaction> {
+                self.0.get(txid).cloned()
+            }
+        }
+
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc = descriptors::LianaDescriptor::from_str("tr(tpubD6NzVbkrYhZ4WUdbVsXDYBCXS8EPSYG1cAN9g4uP6uLQHMHXRvHSFkQBXy7MBeAvV8PDVJJ4o3AwYMKJHp45ci2g69UCAKteVSAJ61CnGEV/<0;1>/*,{and_v(v:pk([9e1c1983/48'/1'/0'/2']tpubDEWCLCMncbStq4BLXkQUAPqzzrh2tQUgYeQPt4NrB5D7gRraMyGbRqzPTmQGvqfdaFsXDVGSQBRgfXuNjDyfU626pxSjpQZszFNY6CzogxK/<2;3>/*),older(65535)),multi_a(2,[9e1c1983/48'/1'/0'/2']tpubDEWCLCMncbStq4BLXkQUAPqzzrh2tQUgYeQPt4NrB5D7gRraMyGbRqzPTmQGvqfdaFsXDVGSQBRgfXuNjDyfU626pxSjpQZszFNY6CzogxK/<0;1>/*,[3b1913e1/48'/1'/0'/2']tpubDFeZ2ezf4VUuTnjdhxJ1DKhLa2t6vzXZNz8NnEgeT2PN4pPqTCTeWUcaxKHPJcf1C8WzkLA71zSjDwuo4zqu4kkiL91ZUmJydC8f1gx89wM/<0;1>/*)})#ee0r4tw5").unwrap();
+        let script_pubkey = desc
+            .receive_descriptor()
+            .derive(0.into(), &secp)
+            .script_pubkey();
+
+        // Two unconfirmed TRUC parents and an unconfirmed non-TRUC one.
+        let parent = |version: i32, value: u64| bitcoin::Transaction {
+            version: bitcoin::transaction::Version(version),
+            lock_time: LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default()],
+            output: vec![
+                bitcoin::TxOut {
+                    value: bitcoin::Amount::from_sat(value),
+                    script_pubkey: script_pubkey.clone(),
+                };
+                50
+            ],
+        };
+        let (truc_a, truc_b, non_truc) = (parent(3, 10_000), parent(3, 20_000), parent(2, 30_000));
+        let mut tx_getter = MapTxGetter(
+            [&truc_a, &truc_b, &non_truc]
+                .iter()
+                .map(|tx| (tx.compute_txid(), (*tx).clone()))
+                .collect(),
+        );
+
+        let cand = |txid: bitcoin::Txid, vout: u32, unconfirmed: bool| CandidateCoin {
+            outpoint: bitcoin::OutPoint::new(txid, vout),
+            amount: bitcoin::Amount::from_sat(10_000),
+            deriv_index: 0.into(),
+            is_change: false,
+            must_select: true,
+            sequence: None,
+            ancestor_info: unconfirmed.then_some(AncestorInfo {
+                vsize: 100,
+                fee: 100,
+            }),
+        };
+        let check = |tx_getter: &mut MapTxGetter, version: i32, cands: &[CandidateCoin]| {
+            let tx = bitcoin::Transaction {
+                version: bitcoin::transaction::Version(version),
+                lock_time: LockTime::ZERO,
+                input: cands
+                    .iter()
+                    .map(|cand| bitcoin::TxIn {
+                        previous_output: cand.outpoint,
+                        ..bitcoin::TxIn::default()
+                    })
+                    .collect(),
+                output: vec![bitcoin::TxOut {
+                    value: bitcoin::Amount::from_sat(5_000),
+                    script_pubkey: script_pubkey.clone(),
+                }],
+            };
+            check_truc_topology(&desc, tx_getter, &tx, cands, true)
+        };
+        let (a, b, c) = (
+            truc_a.compute_txid(),
+            truc_b.compute_txid(),
+            non_truc.compute_txid(),
+        );
+
+        // Confirmed coins can be spent by any transaction, whatever the version of their parent.
+        for version in [2, 3] {
+            check(
+                &mut tx_getter,
+                version,
+                &[cand(a, 0, false), cand(c, 0, false)],
+            )
+            .unwrap();
+        }
+
+        // TRUC and non-TRUC transactions can only spend unconfirmed coins of their own kind.
+        check(&mut tx_getter, 3, &[cand(a, 0, true), cand(a, 1, true)]).unwrap();
+        check(&mut tx_getter, 2, &[cand(c, 0, true)]).unwrap();
+        assert_eq!(
+            check(&mut tx_getter, 3, &[cand(c, 0, true)]),
+            Err(SpendCreationError::Truc(TrucViolation::MixedVersions(c)))
+        );
+        assert_eq!(
+            check(&mut tx_getter, 2, &[cand(c, 0, true), cand(a, 0, true)]),
+            Err(SpendCreationError::Truc(TrucViolation::MixedVersions(a)))
+        );
+
+        // A TRUC transaction may only have a single unconfirmed parent.
+        assert_eq!(
+            check(&mut tx_getter, 3, &[cand(a, 0, true), cand(b, 0, true)]),
+            Err(SpendCreationError::Truc(
+                TrucViolation::MultipleUnconfirmedParents
+            ))
+        );
+
+        // The parent must be available to check its version.
+        let unknown = bitcoin::Txid::from_str(
+            "f7f4df8ea6ee7eb6a5f4ec1a6ff42d1a85d0b46bcb3c5a1c6c0ed9a97a4a8a3c",
+        )
+        .unwrap();
+        assert_eq!(
+            check(&mut tx_getter, 3, &[cand(unknown, 0, true)]),
+            Err(SpendCreationError::FetchingTransaction(
+                bitcoin::OutPoint::new(unknown, 0)
+            ))
+        );
+
+        // The size of a TRUC transaction is limited, further so if it has an unconfirmed parent.
+        let input_vb = desc.spender_input_size(true) as u32;
+        let child_inputs = TRUC_CHILD_MAX_VSIZE as u32 / input_vb + 1;
+        let cands: Vec<_> = (0..child_inputs).map(|i| cand(a, i, true)).collect();
+        assert!(matches!(
+            check(&mut tx_getter, 3, &cands),
+            Err(SpendCreationError::Truc(TrucViolation::ChildTooLarge(_)))
+        ));
+        let cands: Vec<_> = (0..child_inputs).map(|i| cand(a, i, false)).collect();
+        check(&mut tx_getter, 3, &cands).unwrap();
+        let large_inputs = TRUC_MAX_VSIZE as u32 / input_vb + 1;
+        let cands: Vec<_> = (0..large_inputs).map(|i| cand(a, i, false)).collect();
+        assert!(matches!(
+            check(&mut tx_getter, 3, &cands),
+            Err(SpendCreationError::Truc(TrucViolation::TooLarge(_)))
+        ));
+        check(&mut tx_getter, 2, &cands).unwrap();
+    }
 }
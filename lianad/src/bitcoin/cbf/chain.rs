@@ -0,0 +1,715 @@
+//! The chain of block headers of the compact block filters backend.
+//!
+//! Headers are checked to connect to each other, to follow the difficulty adjustment rules and to
+//! have a valid proof of work. The chain with the most work is selected. Headers are stored on
+//! disk so we don't have to download the whole chain again at every startup.
+//!
+//! The filter headers of the blocks of the best chain are stored along with it. Each batch of
+//! filter headers we get from our peer must connect to the ones we already have, all the way
+//! down to the genesis block, so the filters of blocks we already know about can't be changed.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use miniscript::bitcoin::{
+    block, consensus, constants, hashes::Hash, params::Params, BlockHash, CompactTarget,
+    FilterHash, FilterHeader, Network, Target, Work,
+};
+
+use crate::bitcoin::BlockChainTip;
+
+// The size of a serialized block header.
+const HEADER_SIZE: usize = 80;
+
+// The size of a stored filter header: the hash of its block followed by the filter header.
+const FILTER_HEADER_SIZE: usize = 64;
+
+// The maximum difference between the timestamp of a block and the time at which it was mined,
+// same as Bitcoin Core's TIMESTAMP_WINDOW.
+const TIMESTAMP_WINDOW: u32 = 2 * 60 * 60;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The headers don't connect to any block in our chain.
+    NotConnected(BlockHash),
+    /// The headers don't form a chain.
+    NotContinuous(BlockHash),
+    /// The proof of work of this header is invalid.
+    InvalidPow(BlockHash),
+    /// The target of this header doesn't follow the difficulty adjustment rules.
+    UnexpectedDifficulty(BlockHash),
+    /// The filter headers up to this block don't connect to the ones we have.
+    InvalidFilterHeaders(BlockHash),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error with the headers file: '{e}'."),
+            Self::NotConnected(h) => {
+                write!(f, "Header '{h}' does not connect to our chain.")
+            }
+            Self::NotContinuous(h) => write!(f, "Header '{h}' does not follow the previous one."),
+            Self::InvalidPow(h) => write!(f, "Header '{h}' has an invalid proof of work."),
+            Self::UnexpectedDifficulty(h) => {
+                write!(f, "Header '{h}' has an unexpected difficulty target.")
+            }
+            Self::InvalidFilterHeaders(h) => write!(
+                f,
+                "Filter headers up to block '{h}' do not connect to our filter headers chain."
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+// The total work of a non-empty list of headers.
+fn chain_work(headers: &[block::Header]) -> Work {
+    headers
+        .iter()
+        .map(|h| h.work())
+        .reduce(|acc, work| acc + work)
+        .expect("Must not be empty")
+}
+
+/// The headers of the best chain we know about, indexed by height.
+pub struct HeaderChain {
+    params: Params,
+    headers: Vec<block::Header>,
+    hashes: Vec<BlockHash>,
+    /// The filter headers of the blocks of our best chain, from the genesis block up to the last
+    /// one we got.
+    filter_headers: Vec<FilterHeader>,
+    path: PathBuf,
+    filter_headers_path: PathBuf,
+}
+
+impl HeaderChain {
+    /// Load the headers and filter headers stored at these paths. Start from the genesis block of
+    /// this network if there is none.
+    pub fn load(
+        network: Network,
+        path: PathBuf,
+        filter_headers_path: PathBuf,
+    ) -> Result<Self, Error> {
+        let genesis = constants::genesis_block(network).header;
+        let mut chain = HeaderChain {
+            params: Params::new(network),
+            headers: vec![genesis],
+            hashes: vec![genesis.block_hash()],
+            filter_headers: Vec::new(),
+            path,
+            filter_headers_path,
+        };
+
+        // Rewrite the headers file from scratch unless we could load valid headers from it.
+        let mut persist_from = 0;
+        if chain.path.exists() {
+            let data = fs::read(&chain.path)?;
+            let mut stored = data
+                .chunks_exact(HEADER_SIZE)
+                .filter_map(|chunk| consensus::deserialize::<block::Header>(chunk).ok());
+            if stored.next() == Some(genesis) {
+                for header in stored {
+                    // Stop at the first header that isn't valid, it will be downloaded again.
+                    let height = chain.headers.len();
+                    if chain
+                        .check_header(
+                            &header,
+                            *chain.hashes.last().expect("never empty"),
+                            height,
+                            |h| &chain.headers[h],
+                        )
+                        .is_err()
+                    {
+                        log::warn!("Invalid header in headers file, truncating it.");
+                        break;
+                    }
+                    chain.hashes.push(header.block_hash());
+                    chain.headers.push(header);
+                }
+                persist_from = chain.headers.len();
+            } else {
+                log::warn!("Headers file is for a different network, ignoring it.");
+            }
+        }
+        chain.persist(persist_from)?;
+        log::info!("Loaded {} block headers from disk.", chain.headers.len());
+
+        // Only keep the filter headers of the blocks which are still part of our best chain.
+        if chain.filter_headers_path.exists() {
+            let data = fs::read(&chain.filter_headers_path)?;
+            for (chunk, block_hash) in data.chunks_exact(FILTER_HEADER_SIZE).zip(&chain.hashes) {
+                match (
+                    BlockHash::from_slice(&chunk[..32]),
+                    FilterHeader::from_slice(&chunk[32..]),
+                ) {
+                    (Ok(hash), Ok(filter_header)) if hash == *block_hash => {
+                        chain.filter_headers.push(filter_header)
+                    }
+                    _ => break,
+                }
+            }
+        }
+        chain.persist_filter_headers(chain.filter_headers.len())?;
+        log::info!(
+            "Loaded {} filter headers from disk.",
+            chain.filter_headers.len()
+        );
+
+        Ok(chain)
+    }
+
+    /// The height of the best chain.
+    pub fn height(&self) -> u32 {
+        (self.headers.len() - 1) as u32
+    }
+
+    /// The tip of the best chain.
+    pub fn tip(&self) -> BlockChainTip {
+        self.block_at(self.height())
+            .expect("There is always a block at the tip")
+    }
+
+    /// The block at this height in our best chain, if any.
+    pub fn block_at(&self, height: u32) -> Option<BlockChainTip> {
+        self.hashes.get(height as usize).map(|hash| BlockChainTip {
+            height: height as i32,
+            hash: *hash,
+        })
+    }
+
+    /// The header at this height in our best chain, if any.
+    pub fn header_at(&self, height: u32) -> Option<&block::Header> {
+        self.headers.get(height as usize)
+    }
+
+    /// The height of the last block of our best chain whose filter header we have, if any.
+    pub fn filter_height(&self) -> Option<u32> {
+        (self.filter_headers.len() as u32).checked_sub(1)
+    }
+
+    /// The filter header of the block at this height in our best chain, if we have it.
+    pub fn filter_header_at(&self, height: u32) -> Option<FilterHeader> {
+        self.filter_headers.get(height as usize).copied()
+    }
+
+    /// The block locator for our best chain, as used in `getheaders` messages.
+    pub fn locator(&self) -> Vec<BlockHash> {
+        let mut locator = Vec::new();
+        let mut height = self.height() as i64;
+        let mut step = 1;
+        while height > 0 {
+            locator.push(self.hashes[height as usize]);
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height -= step;
+        }
+        locator.push(self.hashes[0]);
+        locator
+    }
+
+    /// Find the height of the last block before any block which may have been mined after this
+    /// timestamp.
+    pub fn height_before_date(&self, timestamp: u32) -> u32 {
+        // Like Bitcoin Core, account for the timestamps of blocks not being ordered by using the
+        // maximum timestamp of any previous block.
+        let mut max_time = 0;
+        for (height, header) in self.headers.iter().enumerate() {
+            max_time = std::cmp::max(max_time, header.time);
+            if max_time >= timestamp.saturating_sub(TIMESTAMP_WINDOW) {
+                return height.saturating_sub(1) as u32;
+            }
+        }
+        self.height()
+    }
+
+    // The target a header at this height must have according to the difficulty adjustment rules
+    // (see `GetNextWorkRequired` in Bitcoin Core). `ancestor` returns the header at a lower height
+    // in the chain of this header.
+    fn required_bits<'a>(
+        &self,
+        header: &block::Header,
+        height: usize,
+        ancestor: impl Fn(usize) -> &'a block::Header,
+    ) -> CompactTarget {
+        let params = &self.params;
+        let pow_limit = params.max_attainable_target.to_compact_lossy();
+        let interval = params.difficulty_adjustment_interval() as usize;
+        let prev = ancestor(height - 1);
+
+        if height % interval != 0 {
+            if params.allow_min_difficulty_blocks {
+                // On test networks a block mined more than 20 minutes after the previous one may
+                // use the minimum difficulty. Otherwise it must use the difficulty of the last
+                // block which didn't.
+                if u64::from(header.time) > u64::from(prev.time) + 2 * params.pow_target_spacing {
+                    return pow_limit;
+                }
+                let mut height = height - 1;
+                while height > 0 && height % interval != 0 && ancestor(height).bits == pow_limit {
+                    height -= 1;
+                }
+                return ancestor(height).bits;
+            }
+            return prev.bits;
+        }
+
+        if params.no_pow_retargeting {
+            return prev.bits;
+        }
+        let first = ancestor(height - interval);
+        // On testnet4 the new target is based on the one of the first block of the period (BIP94).
+        let last_bits = if params.network == Network::Testnet4 {
+            first.bits
+        } else {
+            prev.bits
+        };
+        let timespan = u64::from(prev.time.saturating_sub(first.time));
+        let bits = CompactTarget::from_next_work_required(last_bits, timespan, params);
+        if Target::from_compact(bits) > params.max_attainable_target {
+            pow_limit
+        } else {
+            bits
+        }
+    }
+
+    // Check this header at this height follows the block `prev_hash` and has a valid proof of
+    // work. `ancestor` returns the header at a lower height in the chain of this header.
+    fn check_header<'a>(
+        &self,
+        header: &block::Header,
+        prev_hash: BlockHash,
+        height: usize,
+        ancestor: impl Fn(usize) -> &'a block::Header,
+    ) -> Result<(), Error> {
+        let hash = header.block_hash();
+        if header.prev_blockhash != prev_hash {
+            return Err(Error::NotContinuous(hash));
+        }
+        if header.bits != self.required_bits(header, height, ancestor) {
+            return Err(Error::UnexpectedDifficulty(hash));
+        }
+        let target = header.target();
+        if target > self.params.max_attainable_target || header.validate_pow(target).is_err() {
+            return Err(Error::InvalidPow(hash));
+        }
+        Ok(())
+    }
+
+    /// Connect these headers to our chain. If they are part of a chain with more work than our
+    /// current best chain, it becomes our new best chain.
+    ///
+    /// Returns the height of the last block in common between the former and the new best chain if
+    /// our best chain changed.
+    pub fn connect(&mut self, headers: Vec<block::Header>) -> Result<Option<u32>, Error> {
+        let first = match headers.first() {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        // Most of the time the headers will extend our tip, so start looking from there.
+        let fork_height = self
+            .hashes
+            .iter()
+            .rposition(|h| *h == first.prev_blockhash)
+            .ok_or(Error::NotConnected(first.block_hash()))?;
+
+        let mut prev_hash = self.hashes[fork_height];
+        for (i, header) in headers.iter().enumerate() {
+            let height = fork_height + 1 + i;
+            // The ancestors of the new headers are in our chain up to the fork point.
+            let ancestor = |h: usize| {
+                if h <= fork_height {
+                    &self.headers[h]
+                } else {
+                    &headers[h - fork_height - 1]
+                }
+            };
+            self.check_header(header, prev_hash, height, ancestor)?;
+            prev_hash = header.block_hash();
+        }
+
+        // Ignore the headers we already have.
+        let new_height = fork_height + headers.len();
+        if new_height < self.headers.len() && self.hashes[new_height] == prev_hash {
+            return Ok(None);
+        }
+
+        // If this is a fork, only switch to it if it has more work than our current best chain.
+        if fork_height + 1 < self.headers.len() {
+            if chain_work(&headers) <= chain_work(&self.headers[fork_height + 1..]) {
+                log::debug!("Ignoring fork with less work at height {}.", fork_height);
+                return Ok(None);
+            }
+            log::info!("Block chain reorganization at height {}.", fork_height);
+            self.headers.truncate(fork_height + 1);
+            self.hashes.truncate(fork_height + 1);
+            if self.filter_headers.len() > fork_height + 1 {
+                self.filter_headers.truncate(fork_height + 1);
+                self.persist_filter_headers(fork_height + 1)?;
+            }
+        }
+        for header in headers {
+            self.hashes.push(header.block_hash());
+            self.headers.push(header);
+        }
+        self.persist(fork_height + 1)?;
+
+        Ok(Some(fork_height as u32))
+    }
+
+    /// Connect the filter headers of the blocks from `start_height` to the block `stop_hash`, as
+    /// given by the hashes of their filters and the filter header of the block before
+    /// `start_height`, to our filter headers chain.
+    ///
+    /// If our best chain changed since these were requested, they are ignored.
+    pub fn connect_filter_headers(
+        &mut self,
+        start_height: u32,
+        stop_hash: BlockHash,
+        previous_filter_header: FilterHeader,
+        filter_hashes: &[FilterHash],
+    ) -> Result<(), Error> {
+        // Another wallet may have synced them, or our best chain changed, in the meantime.
+        let start = start_height as usize;
+        if start != self.filter_headers.len() {
+            return Ok(());
+        }
+        let stop = match self.hashes.iter().skip(start).position(|h| *h == stop_hash) {
+            Some(offset) => start + offset,
+            None => return Ok(()),
+        };
+
+        let prev = match start.checked_sub(1) {
+            Some(height) => self.filter_headers[height],
+            // The filter header before the genesis block is all zeros (see BIP157).
+            None => FilterHeader::all_zeros(),
+        };
+        if prev != previous_filter_header || filter_hashes.len() != stop - start + 1 {
+            return Err(Error::InvalidFilterHeaders(stop_hash));
+        }
+
+        let mut filter_header = prev;
+        for filter_hash in filter_hashes {
+            filter_header = filter_hash.filter_header(&filter_header);
+            self.filter_headers.push(filter_header);
+        }
+        self.persist_filter_headers(start)?;
+        Ok(())
+    }
+
+    // Write the headers from this height on to disk.
+    fn persist(&self, from_height: usize) -> Result<(), Error> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&self.path)?;
+        file.set_len((from_height * HEADER_SIZE) as u64)?;
+        let mut writer = io::BufWriter::new(file);
+        io::Seek::seek(
+            &mut writer,
+            io::SeekFrom::Start((from_height * HEADER_SIZE) as u64),
+        )?;
+        for header in &self.headers[from_height..] {
+            writer.write_all(&consensus::serialize(header))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    // Write the filter headers from this height on to disk, along with the hash of their block.
+    fn persist_filter_headers(&self, from_height: usize) -> Result<(), Error> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&self.filter_headers_path)?;
+        file.set_len((from_height * FILTER_HEADER_SIZE) as u64)?;
+        let mut writer = io::BufWriter::new(file);
+        io::Seek::seek(
+            &mut writer,
+            io::SeekFrom::Start((from_height * FILTER_HEADER_SIZE) as u64),
+        )?;
+        for (hash, filter_header) in self.hashes[from_height..]
+            .iter()
+            .zip(&self.filter_headers[from_height..])
+        {
+            writer.write_all(hash.as_byte_array())?;
+            writer.write_all(filter_header.as_byte_array())?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniscript::bitcoin::{hashes::Hash, CompactTarget, TxMerkleNode};
+
+    fn tmp_path(name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!(
+            "lianad-cbf-headers-{}-{}-{}",
+            name,
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        path
+    }
+
+    // Mine a regtest header on top of this one.
+    fn mine(prev: &block::Header, time: u32, nonce_seed: u32) -> block::Header {
+        let mut header = block::Header {
+            version: block::Version::TWO,
+            prev_blockhash: prev.block_hash(),
+            merkle_root: TxMerkleNode::from_byte_array([nonce_seed as u8; 32]),
+            time,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        };
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    #[test]
+    fn header_chain() {
+        let path = tmp_path("chain");
+        let filter_path = tmp_path("chain-filters");
+        let mut chain =
+            HeaderChain::load(Network::Regtest, path.clone(), filter_path.clone()).unwrap();
+        assert_eq!(chain.height(), 0);
+        let genesis = *chain.header_at(0).unwrap();
+        assert_eq!(chain.locator(), vec![genesis.block_hash()]);
+
+        // Extend the chain by 3 blocks.
+        let mut headers = Vec::new();
+        let mut prev = genesis;
+        for i in 0..3 {
+            let header = mine(&prev, genesis.time + 600 * (i + 1), 0);
+            headers.push(header);
+            prev = header;
+        }
+        assert_eq!(chain.connect(headers.clone()).unwrap(), Some(0));
+        assert_eq!(chain.height(), 3);
+        assert_eq!(chain.tip().hash, headers[2].block_hash());
+        // Connecting them again is a no-op.
+        assert_eq!(chain.connect(headers.clone()).unwrap(), None);
+        assert_eq!(chain.connect(headers[1..].to_vec()).unwrap(), None);
+
+        // Headers which don't connect or don't follow each other are refused.
+        let orphan = mine(&headers[2], genesis.time + 2400, 1);
+        let orphan_child = mine(&orphan, genesis.time + 3000, 1);
+        assert!(matches!(
+            chain.connect(vec![orphan_child]),
+            Err(Error::NotConnected(_))
+        ));
+        assert!(matches!(
+            chain.connect(vec![orphan, headers[0]]),
+            Err(Error::NotContinuous(_))
+        ));
+        let mut invalid = mine(&headers[2], genesis.time + 2400, 2);
+        while invalid.validate_pow(invalid.target()).is_ok() {
+            invalid.nonce += 1;
+        }
+        assert!(matches!(
+            chain.connect(vec![invalid]),
+            Err(Error::InvalidPow(_))
+        ));
+        // On regtest all blocks must have the minimum difficulty, even if they have more work.
+        let mut harder = mine(&headers[2], genesis.time + 2400, 4);
+        harder.bits = CompactTarget::from_consensus(0x2000ffff);
+        while harder.validate_pow(harder.target()).is_err() {
+            harder.nonce += 1;
+        }
+        assert!(matches!(
+            chain.connect(vec![harder]),
+            Err(Error::UnexpectedDifficulty(_))
+        ));
+
+        // A fork with the same amount of work is ignored. One with more work is selected.
+        let fork_a = mine(&headers[0], genesis.time + 1200, 3);
+        let fork_b = mine(&fork_a, genesis.time + 1800, 3);
+        assert_eq!(chain.connect(vec![fork_a, fork_b]).unwrap(), None);
+        assert_eq!(chain.tip().hash, headers[2].block_hash());
+        let fork_c = mine(&fork_b, genesis.time + 2400, 3);
+        assert_eq!(
+            chain.connect(vec![fork_a, fork_b, fork_c]).unwrap(),
+            Some(1)
+        );
+        assert_eq!(chain.height(), 4);
+        assert_eq!(chain.tip().hash, fork_c.block_hash());
+        assert_eq!(chain.block_at(2).unwrap().hash, fork_a.block_hash());
+
+        // The block before a date accounts for the timestamp window.
+        assert_eq!(chain.height_before_date(genesis.time), 0);
+        assert_eq!(
+            chain.height_before_date(genesis.time + TIMESTAMP_WINDOW + 1800),
+            2
+        );
+        assert_eq!(chain.height_before_date(u32::MAX), 4);
+
+        // The headers are persisted across restarts.
+        let chain = HeaderChain::load(Network::Regtest, path.clone(), filter_path.clone()).unwrap();
+        assert_eq!(chain.height(), 4);
+        assert_eq!(chain.tip().hash, fork_c.block_hash());
+
+        // Headers from another network are ignored.
+        let chain = HeaderChain::load(Network::Signet, path.clone(), filter_path.clone()).unwrap();
+        assert_eq!(chain.height(), 0);
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(filter_path).unwrap();
+    }
+
+    #[test]
+    fn filter_headers_chain() {
+        let path = tmp_path("filters");
+        let filter_path = tmp_path("filters-filters");
+        let mut chain =
+            HeaderChain::load(Network::Regtest, path.clone(), filter_path.clone()).unwrap();
+        let genesis = *chain.header_at(0).unwrap();
+        let mut headers = Vec::new();
+        let mut prev = genesis;
+        for i in 0..3 {
+            let header = mine(&prev, genesis.time + 600 * (i + 1), 0);
+            headers.push(header);
+            prev = header;
+        }
+        chain.connect(headers.clone()).unwrap();
+        assert_eq!(chain.filter_height(), None);
+
+        let filter_hashes: Vec<FilterHash> = (0..4u8)
+            .map(|i| FilterHash::from_byte_array([i; 32]))
+            .collect();
+        let mut filter_headers = Vec::new();
+        let mut prev_filter_header = FilterHeader::all_zeros();
+        for filter_hash in &filter_hashes {
+            prev_filter_header = filter_hash.filter_header(&prev_filter_header);
+            filter_headers.push(prev_filter_header);
+        }
+
+        // The first filter headers must connect to the genesis block.
+        let block_1 = chain.block_at(1).unwrap().hash;
+        assert!(matches!(
+            chain.connect_filter_headers(0, block_1, filter_headers[0], &filter_hashes[..2]),
+            Err(Error::InvalidFilterHeaders(_))
+        ));
+        chain
+            .connect_filter_headers(0, block_1, FilterHeader::all_zeros(), &filter_hashes[..2])
+            .unwrap();
+        assert_eq!(chain.filter_height(), Some(1));
+        assert_eq!(chain.filter_header_at(1), Some(filter_headers[1]));
+
+        // The next ones must connect to the last one we have and be as many as the blocks.
+        let block_3 = chain.block_at(3).unwrap().hash;
+        assert!(matches!(
+            chain.connect_filter_headers(2, block_3, filter_headers[0], &filter_hashes[2..]),
+            Err(Error::InvalidFilterHeaders(_))
+        ));
+        assert!(matches!(
+            chain.connect_filter_headers(2, block_3, filter_headers[1], &filter_hashes[2..3]),
+            Err(Error::InvalidFilterHeaders(_))
+        ));
+        // Those for a block which isn't in our best chain anymore are ignored.
+        let stale = mine(&headers[2], genesis.time + 2400, 1).block_hash();
+        chain
+            .connect_filter_headers(2, stale, filter_headers[1], &filter_hashes[2..])
+            .unwrap();
+        assert_eq!(chain.filter_height(), Some(1));
+        chain
+            .connect_filter_headers(2, block_3, filter_headers[1], &filter_hashes[2..])
+            .unwrap();
+        assert_eq!(chain.filter_height(), Some(3));
+        assert_eq!(chain.filter_header_at(3), Some(filter_headers[3]));
+
+        // They are persisted across restarts.
+        let mut chain =
+            HeaderChain::load(Network::Regtest, path.clone(), filter_path.clone()).unwrap();
+        assert_eq!(chain.filter_height(), Some(3));
+        assert_eq!(chain.filter_header_at(3), Some(filter_headers[3]));
+
+        // Those of the blocks which were reorganized out are dropped.
+        let fork_a = mine(&headers[0], genesis.time + 1200, 3);
+        let fork_b = mine(&fork_a, genesis.time + 1800, 3);
+        let fork_c = mine(&fork_b, genesis.time + 2400, 3);
+        assert_eq!(
+            chain.connect(vec![fork_a, fork_b, fork_c]).unwrap(),
+            Some(1)
+        );
+        assert_eq!(chain.filter_height(), Some(1));
+        let chain = HeaderChain::load(Network::Regtest, path.clone(), filter_path.clone()).unwrap();
+        assert_eq!(chain.filter_height(), Some(1));
+        assert_eq!(chain.filter_header_at(1), Some(filter_headers[1]));
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(filter_path).unwrap();
+    }
+
+    #[test]
+    fn difficulty_adjustment() {
+        let path = tmp_path("difficulty");
+        let filter_path = tmp_path("difficulty-filters");
+        let chain = HeaderChain::load(Network::Bitcoin, path.clone(), filter_path.clone()).unwrap();
+        let genesis = *chain.header_at(0).unwrap();
+        let interval = 2016;
+
+        // A period of headers with the minimum difficulty, mined at this interval in seconds.
+        let period = |spacing: u32| -> Vec<block::Header> {
+            (0..interval)
+                .map(|i| block::Header {
+                    time: genesis.time + spacing * i as u32,
+                    ..genesis
+                })
+                .collect()
+        };
+        let next = |headers: &[block::Header], time: u32| block::Header {
+            time,
+            ..headers[headers.len() - 1]
+        };
+
+        // Within a period the difficulty must not change.
+        let headers = period(600);
+        let header = next(&headers[..10], headers[9].time + 600);
+        assert_eq!(
+            chain.required_bits(&header, 10, |h| &headers[h]),
+            genesis.bits
+        );
+
+        // If blocks were mined twice as fast as expected, the target is about halved at the next
+        // period. Like in Bitcoin Core the timespan of the period is measured over 2015 blocks.
+        let headers = period(300);
+        let header = next(&headers, headers[interval - 1].time + 300);
+        assert_eq!(
+            chain.required_bits(&header, interval, |h| &headers[h]),
+            CompactTarget::from_consensus(0x1c7fef3f)
+        );
+
+        // If they were mined slower, the target can't go above the maximum.
+        let headers = period(1200);
+        let header = next(&headers, headers[interval - 1].time + 1200);
+        assert_eq!(
+            chain.required_bits(&header, interval, |h| &headers[h]),
+            genesis.bits
+        );
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(filter_path).unwrap();
+    }
+}
@@ -0,0 +1,500 @@
+use std::{
+    cmp,
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::TryInto,
+    net::SocketAddr,
+    sync, time,
+};
+
+use bdk_electrum::bdk_chain::{
+    bitcoin::{
+        self, bip158, bip32::ChildNumber, hashes::Hash, FilterHash, FilterHeader, OutPoint,
+        ScriptBuf,
+    },
+    local_chain::CheckPoint,
+    tx_graph::TxGraph,
+    BlockId, ConfirmationTimeHeightAnchor,
+};
+
+pub mod chain;
+pub mod peer;
+use crate::{
+    bitcoin::{
+        electrum::{
+            utils::tip_from_block_id,
+            wallet::{self, KeychainType},
+        },
+        Block, BlockChainTip, Coin,
+    },
+    database::DatabaseInterface,
+};
+
+// The maximum number of headers a peer sends us in response to a `getheaders` message.
+const MAX_HEADERS_BATCH: usize = 2_000;
+
+// For how long we refuse to connect to a peer which sent us invalid data.
+const BAN_DURATION: time::Duration = time::Duration::from_secs(24 * 60 * 60);
+
+/// The connection to our peer, if there is one.
+#[derive(Default)]
+pub struct PeerState {
+    peer: Option<peer::Peer>,
+    /// Set if our peer sent us invalid data. We won't connect to it again until then.
+    banned_until: Option<time::Instant>,
+}
+
+/// The connection to our peer, shared by the interfaces of all our wallets.
+pub type SharedPeer = sync::Arc<sync::Mutex<PeerState>>;
+
+/// The header chain of the best chain, shared by the interfaces of all our wallets.
+pub type SharedHeaders = sync::Arc<sync::Mutex<chain::HeaderChain>>;
+
+/// An error in the compact block filters interface.
+#[derive(Debug)]
+pub enum CbfError {
+    Peer(peer::Error),
+    Headers(chain::Error),
+    /// Our peer sent us a filter for another block than the one we asked.
+    UnexpectedFilter(bitcoin::BlockHash),
+    /// The filter our peer sent us for this block doesn't match its filter headers.
+    InvalidFilter(bitcoin::BlockHash),
+    Filter(bip158::Error),
+    /// Our peer sent us invalid data before, we don't connect to it anymore.
+    PeerBanned(SocketAddr),
+}
+
+impl CbfError {
+    /// Whether this error is caused by our peer sending us invalid data.
+    pub fn is_misbehaviour(&self) -> bool {
+        match self {
+            Self::Peer(e) => matches!(e, peer::Error::InvalidBlock(_)),
+            Self::Headers(e) => !matches!(e, chain::Error::Io(_)),
+            Self::UnexpectedFilter(_) | Self::InvalidFilter(_) => true,
+            Self::Filter(_) | Self::PeerBanned(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for CbfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Peer(e) => write!(f, "Peer error: '{e}'"),
+            Self::Headers(e) => write!(f, "Block headers error: '{e}'"),
+            Self::UnexpectedFilter(h) => {
+                write!(f, "Received unexpected filter for block '{h}'.")
+            }
+            Self::InvalidFilter(h) => write!(
+                f,
+                "Filter for block '{h}' does not match the filter headers chain."
+            ),
+            Self::Filter(e) => write!(f, "Error matching block filter: '{e}'."),
+            Self::PeerBanned(addr) => write!(
+                f,
+                "Peer '{addr}' sent us invalid data and is banned. Restart to connect to it again."
+            ),
+        }
+    }
+}
+
+/// Interface for a light client backend using compact block filters (BIP157/158).
+///
+/// We download the headers of the best chain and the filters of the blocks from a peer, match the
+/// filters against the Script Pubkeys of our addresses and only download the blocks which match.
+/// Like for Electrum, the wallet's transactions are tracked in a BDK-based wallet whose local
+/// chain only advances once the filters of the new blocks have been scanned.
+///
+/// Unconfirmed transactions are not tracked: they are only seen once they are mined.
+pub struct Cbf {
+    addr: SocketAddr,
+    /// A SOCKS5 proxy to connect to our peer through, if any.
+    proxy: Option<SocketAddr>,
+    network: bitcoin::Network,
+    /// The connection to our peer, if there is one. It is established on demand.
+    peer: SharedPeer,
+    headers: SharedHeaders,
+    bdk_wallet: wallet::BdkWallet,
+    /// Used to get the Script Pubkeys to match the filters against.
+    db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+    /// Blocks mined before the wallet was created can't contain any of its transactions.
+    birth_timestamp: u32,
+    /// If set, scan the filters again from the block before this timestamp at the next poll.
+    rescan_timestamp: Option<u32>,
+    /// Used for setting the `last_seen` of unconfirmed transactions in a strictly
+    /// increasing manner.
+    sync_count: u64,
+}
+
+impl Cbf {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        addr: SocketAddr,
+        proxy: Option<SocketAddr>,
+        network: bitcoin::Network,
+        peer: SharedPeer,
+        headers: SharedHeaders,
+        bdk_wallet: wallet::BdkWallet,
+        db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+        birth_timestamp: u32,
+        rescan_timestamp: Option<u32>,
+    ) -> Self {
+        Self {
+            addr,
+            proxy,
+            network,
+            peer,
+            headers,
+            bdk_wallet,
+            db,
+            birth_timestamp,
+            rescan_timestamp,
+            sync_count: 0,
+        }
+    }
+
+    /// Make sure we can connect to our peer and it serves compact block filters.
+    pub fn sanity_checks(&self) -> Result<(), CbfError> {
+        self.with_peer(|_| Ok(()))
+    }
+
+    // Run `f` with a connection to our peer, connecting first if necessary. In case of error the
+    // connection is dropped and will be established again at the next request.
+    fn with_peer<T>(
+        &self,
+        f: impl FnOnce(&mut peer::Peer) -> Result<T, peer::Error>,
+    ) -> Result<T, CbfError> {
+        let mut state = self.peer.lock().expect("Must not be poisoned");
+        if state
+            .banned_until
+            .map(|until| time::Instant::now() < until)
+            .unwrap_or(false)
+        {
+            return Err(CbfError::PeerBanned(self.addr));
+        }
+        if state.peer.is_none() {
+            state.peer = Some(
+                peer::Peer::connect(self.addr, self.proxy, self.network).map_err(CbfError::Peer)?,
+            );
+        }
+        let res = f(state.peer.as_mut().expect("Just set"));
+        if res.is_err() {
+            state.peer = None;
+        }
+        res.map_err(CbfError::Peer)
+    }
+
+    // Disconnect from our peer and refuse to connect to it again for a while.
+    fn ban_peer(&self) {
+        let mut state = self.peer.lock().expect("Must not be poisoned");
+        state.peer = None;
+        state.banned_until = Some(time::Instant::now() + BAN_DURATION);
+    }
+
+    /// The header chain of the best chain we know about, along with its filter headers.
+    pub fn headers(&self) -> sync::MutexGuard<'_, chain::HeaderChain> {
+        self.headers.lock().expect("Must not be poisoned")
+    }
+
+    /// The connection to our peer and the header chain, to be shared with the interfaces of
+    /// other wallets.
+    pub fn connection(&self) -> (SharedPeer, SharedHeaders) {
+        (self.peer.clone(), self.headers.clone())
+    }
+
+    /// The height of the best chain, as far as we know.
+    pub fn best_height(&self) -> u32 {
+        let peer_height = self
+            .peer
+            .lock()
+            .expect("Must not be poisoned")
+            .peer
+            .as_ref()
+            .map(|p| p.start_height())
+            .unwrap_or(0);
+        cmp::max(self.headers().height(), peer_height)
+    }
+
+    /// Get all coins stored in the wallet.
+    pub fn wallet_coins(&self, outpoints: Option<&[OutPoint]>) -> HashMap<OutPoint, Coin> {
+        self.bdk_wallet.coins(outpoints, Some(self.sync_count))
+    }
+
+    /// Get the tip of the wallet's local chain.
+    pub fn wallet_tip(&self) -> BlockChainTip {
+        tip_from_block_id(self.bdk_wallet.local_chain().tip().block_id())
+    }
+
+    /// Whether `tip` exists in the wallet's `local_chain`.
+    ///
+    /// Returns `None` if no block at that height exists in `local_chain`.
+    pub fn is_in_wallet_chain(&self, tip: BlockChainTip) -> Option<bool> {
+        self.bdk_wallet.is_in_chain(tip)
+    }
+
+    /// Whether we'll scan the filters again from a past block at the next poll.
+    pub fn is_rescanning(&self) -> bool {
+        self.rescan_timestamp.is_some()
+    }
+
+    /// Scan the filters again from the block before this timestamp at the next poll.
+    pub fn trigger_rescan(&mut self, timestamp: u32) {
+        self.rescan_timestamp = Some(timestamp);
+    }
+
+    // Get the headers of the best chain from our peer.
+    fn sync_headers(&mut self) -> Result<(), CbfError> {
+        loop {
+            let locator = self.headers().locator();
+            let headers = self.with_peer(|p| p.get_headers(locator))?;
+            let count = headers.len();
+            let changed = self
+                .headers()
+                .connect(headers)
+                .map_err(CbfError::Headers)?
+                .is_some();
+            if !changed || count < MAX_HEADERS_BATCH {
+                break;
+            }
+            log::info!("Synced headers up to height {}.", self.headers().height());
+        }
+        Ok(())
+    }
+
+    // Get the filter headers of the best chain from our peer. They must connect to the ones we
+    // already have, so our peer can't change the filters of the blocks we already know about.
+    fn sync_filter_headers(&mut self) -> Result<(), CbfError> {
+        loop {
+            let (start_height, stop_hash) = {
+                let headers = self.headers();
+                let start_height = headers.filter_height().map(|h| h + 1).unwrap_or(0);
+                if start_height > headers.height() {
+                    break;
+                }
+                let stop_height = cmp::min(
+                    start_height + peer::MAX_CFHEADERS_BATCH - 1,
+                    headers.height(),
+                );
+                let stop_hash = headers.block_at(stop_height).expect("Below tip").hash;
+                (start_height, stop_hash)
+            };
+            let cfheaders = self.with_peer(|p| p.get_cfheaders(start_height, stop_hash))?;
+            self.headers()
+                .connect_filter_headers(
+                    start_height,
+                    stop_hash,
+                    cfheaders.previous_filter_header,
+                    &cfheaders.filter_hashes,
+                )
+                .map_err(CbfError::Headers)?;
+            log::debug!("Synced filter headers up to block '{}'.", stop_hash);
+        }
+        Ok(())
+    }
+
+    /// Sync the wallet with the best chain. If there was any reorg since the last poll, this
+    /// returns the first common ancestor between the previous and the new chain.
+    ///
+    /// If our peer sends us invalid headers, filters or blocks it gets banned.
+    pub fn sync_wallet(
+        &mut self,
+        receive_index: ChildNumber,
+        change_index: ChildNumber,
+    ) -> Result<Option<BlockChainTip>, CbfError> {
+        let res = self.sync_wallet_inner(receive_index, change_index);
+        if let Err(e) = &res {
+            if e.is_misbehaviour() {
+                log::error!(
+                    "Banning peer '{}' for misbehaviour: {}. Restart lianad to connect to it again.",
+                    self.addr,
+                    e
+                );
+                self.ban_peer();
+            }
+        }
+        res
+    }
+
+    fn sync_wallet_inner(
+        &mut self,
+        receive_index: ChildNumber,
+        change_index: ChildNumber,
+    ) -> Result<Option<BlockChainTip>, CbfError> {
+        self.bdk_wallet.reveal_spks(receive_index, change_index);
+        let local_chain_tip = self.bdk_wallet.local_chain().tip();
+        self.sync_headers()?;
+        self.sync_filter_headers()?;
+
+        // Another wallet may sync the headers in the meantime. Take what we need for the scan from
+        // our best chain as it is now instead of keeping it locked during the whole scan.
+        let (tip, mut blocks, scan_base, scan_blocks) = {
+            let headers = self.headers();
+            // We can only scan the blocks whose filter headers we have.
+            let tip_height = headers
+                .filter_height()
+                .expect("Filter headers were just synced, the genesis one is never removed.");
+            let tip = headers.block_at(tip_height).expect("Below tip");
+
+            // The update to the wallet's local chain. It re-states the blocks of the local chain
+            // as they are in the best chain, which lets BDK detect reorgs.
+            let mut blocks = BTreeMap::new();
+            let mut agreement_height = 0;
+            for cp in self.bdk_wallet.local_chain().iter_checkpoints() {
+                if cp.height() > tip_height {
+                    continue;
+                }
+                if let Some(block) = headers.block_at(cp.height()) {
+                    if block.hash == cp.hash() {
+                        agreement_height = cmp::max(agreement_height, cp.height());
+                    }
+                    blocks.insert(cp.height(), block.hash);
+                }
+            }
+
+            // Scan the filters from the last block in common with the best chain. For a new
+            // wallet there is no need to scan the blocks mined before its creation. In case of a
+            // rescan, start from the block before its timestamp.
+            let mut scan_base = agreement_height;
+            if agreement_height == 0 {
+                scan_base = headers.height_before_date(self.birth_timestamp);
+            }
+            if let Some(timestamp) = self.rescan_timestamp {
+                scan_base = cmp::min(scan_base, headers.height_before_date(timestamp));
+            }
+            scan_base = cmp::min(scan_base, tip_height);
+
+            // The hash and filter header of each block from the scan base to the tip.
+            let scan_blocks: Vec<(bitcoin::BlockHash, FilterHeader)> = (scan_base..=tip_height)
+                .map(|height| {
+                    (
+                        headers.block_at(height).expect("Below tip").hash,
+                        headers.filter_header_at(height).expect("Below filter tip"),
+                    )
+                })
+                .collect();
+            (tip, blocks, scan_base, scan_blocks)
+        };
+        let tip_height = tip.height as u32;
+        // Make sure the block we'll be told to roll back to after a rescan is in the local chain.
+        blocks.insert(scan_base, scan_blocks[0].0);
+        blocks.insert(tip_height, tip.hash);
+
+        let spks: HashMap<ScriptBuf, (ChildNumber, bool)> = self
+            .db
+            .connection()
+            .script_pubkeys()
+            .into_iter()
+            .map(|(spk, index, is_change)| (spk, (index, is_change)))
+            .collect();
+        let mut our_outpoints: HashSet<OutPoint> =
+            self.bdk_wallet.coins(None, None).into_keys().collect();
+        let mut graph_update = TxGraph::<ConfirmationTimeHeightAnchor>::default();
+        let mut keychain_update = BTreeMap::new();
+
+        log::debug!(
+            "Scanning filters from height {} to {}.",
+            scan_base + 1,
+            tip_height
+        );
+        // The filters are checked against the filter headers of our best chain.
+        let mut start_height = scan_base + 1;
+        while start_height <= tip_height {
+            let stop_height = cmp::min(start_height + peer::MAX_CFILTERS_BATCH - 1, tip_height);
+            let stop_hash = scan_blocks[(stop_height - scan_base) as usize].0;
+            let filters =
+                self.with_peer(|p| p.get_cfilters(start_height, stop_height, stop_hash))?;
+
+            for (height, filter) in (start_height..=stop_height).zip(filters) {
+                let (block_hash, filter_header) = scan_blocks[(height - scan_base) as usize];
+                let prev_filter_header = scan_blocks[(height - scan_base - 1) as usize].1;
+                if filter.block_hash != block_hash {
+                    return Err(CbfError::UnexpectedFilter(filter.block_hash));
+                }
+                if FilterHash::hash(&filter.filter).filter_header(&prev_filter_header)
+                    != filter_header
+                {
+                    return Err(CbfError::InvalidFilter(block_hash));
+                }
+                let is_match = !spks.is_empty()
+                    && bip158::BlockFilter::new(&filter.filter)
+                        .match_any(block_hash, spks.keys().map(|spk| spk.as_bytes()))
+                        .map_err(CbfError::Filter)?;
+                if !is_match {
+                    continue;
+                }
+
+                log::debug!("Filter of block '{block_hash}' at height {height} matches.");
+                let block = self.with_peer(|p| p.get_block(block_hash))?;
+                let anchor = ConfirmationTimeHeightAnchor {
+                    anchor_block: BlockId {
+                        height,
+                        hash: block_hash,
+                    },
+                    confirmation_height: height,
+                    confirmation_time: block.header.time.into(),
+                };
+                let mut is_relevant = false;
+                for tx in block.txdata {
+                    let txid = tx.compute_txid();
+                    let mut is_ours = tx
+                        .input
+                        .iter()
+                        .any(|txin| our_outpoints.contains(&txin.previous_output));
+                    for (vout, txout) in tx.output.iter().enumerate() {
+                        if let Some((index, is_change)) = spks.get(&txout.script_pubkey) {
+                            is_ours = true;
+                            our_outpoints.insert(OutPoint::new(
+                                txid,
+                                vout.try_into().expect("Number of outputs must fit in u32"),
+                            ));
+                            let keychain = if *is_change {
+                                KeychainType::Change
+                            } else {
+                                KeychainType::Receive
+                            };
+                            let last_index = keychain_update.entry(keychain).or_insert(0);
+                            *last_index = cmp::max(*last_index, u32::from(*index));
+                        }
+                    }
+                    if is_ours {
+                        let _ = graph_update.insert_tx(tx);
+                        let _ = graph_update.insert_anchor(txid, anchor);
+                        is_relevant = true;
+                    }
+                }
+                // The anchor block of our transactions must be part of the local chain.
+                if is_relevant {
+                    blocks.insert(height, block_hash);
+                }
+            }
+            start_height = stop_height + 1;
+        }
+        log::debug!("Filters scanned up to height {}.", tip_height);
+        self.rescan_timestamp = None;
+
+        let chain_update = CheckPoint::from_block_ids(
+            blocks
+                .into_iter()
+                .map(|(height, hash)| BlockId { height, hash }),
+        )
+        .expect("Blocks are ordered and there is at least one");
+        self.sync_count = self.sync_count.checked_add(1).expect("must fit");
+        Ok(self.bdk_wallet.apply_sync_update(
+            &local_chain_tip,
+            chain_update,
+            graph_update,
+            (!keychain_update.is_empty()).then_some(keychain_update),
+            self.sync_count,
+        ))
+    }
+
+    /// Send this transaction to our peer.
+    pub fn broadcast_tx(&self, tx: &bitcoin::Transaction) -> Result<(), CbfError> {
+        self.with_peer(|p| p.broadcast_tx(tx))
+    }
+
+    pub fn wallet_transaction(
+        &self,
+        txid: &bitcoin::Txid,
+    ) -> Option<(bitcoin::Transaction, Option<Block>)> {
+        self.bdk_wallet.get_transaction(txid)
+    }
+}
@@ -0,0 +1,377 @@
+//! A minimal client for the Bitcoin P2P protocol. It only implements what we need in order to
+//! fetch headers, compact block filters (BIP157) and blocks from a single peer, and to broadcast
+//! our transactions.
+
+use std::{
+    collections::hash_map::RandomState,
+    convert::TryInto,
+    hash::{BuildHasher, Hasher},
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use miniscript::bitcoin::{
+    self, block, consensus,
+    hashes::Hash,
+    p2p::{
+        self,
+        message::{NetworkMessage, RawNetworkMessage},
+        message_blockdata::{GetHeadersMessage, Inventory},
+        message_filter::{CFHeaders, CFilter, GetCFHeaders, GetCFilters},
+        message_network::VersionMessage,
+        Magic, ServiceFlags,
+    },
+    BlockHash, Network,
+};
+
+// The protocol version we advertise. It's the one introducing compact block filters messages.
+const PROTOCOL_VERSION: u32 = 70016;
+
+// How long to wait for establishing a connection with our peer.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// How long to wait for a message from our peer before giving up.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+// The maximum size of a message we accept from our peer, same as Bitcoin Core's.
+const MAX_MESSAGE_SIZE: usize = 4_000_000;
+
+// The size of the header of a P2P message: magic, command, length and checksum.
+const MESSAGE_HEADER_SIZE: usize = 24;
+
+/// The maximum number of filters which may be requested at once (see BIP157).
+pub const MAX_CFILTERS_BATCH: u32 = 1_000;
+
+/// The maximum number of filter headers which may be requested at once (see BIP157).
+pub const MAX_CFHEADERS_BATCH: u32 = 2_000;
+
+/// The type of the basic filters defined in BIP158.
+pub const BASIC_FILTER_TYPE: u8 = 0;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Decode(consensus::encode::Error),
+    /// Our peer does not serve compact block filters.
+    NoCompactFilters,
+    /// Our peer sent us an unexpected or invalid message.
+    Protocol(String),
+    /// The transactions of the block our peer sent us don't match its header.
+    InvalidBlock(BlockHash),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error when communicating with peer: '{e}'."),
+            Self::Decode(e) => write!(f, "Error decoding message from peer: '{e}'."),
+            Self::NoCompactFilters => write!(
+                f,
+                "Peer does not serve compact block filters. Make sure it runs with \
+                 '-blockfilterindex=1' and '-peerblockfilters=1'."
+            ),
+            Self::Protocol(s) => write!(f, "Protocol error: '{s}'."),
+            Self::InvalidBlock(h) => write!(
+                f,
+                "The transactions of block '{h}' don't match its merkle root or witness commitment."
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<consensus::encode::Error> for Error {
+    fn from(e: consensus::encode::Error) -> Self {
+        Self::Decode(e)
+    }
+}
+
+// Establish a connection to `target` through the SOCKS5 proxy at `proxy` (see RFC 1928). We only
+// support proxies which don't require authentication, such as Tor's.
+fn socks5_connect(proxy: SocketAddr, target: SocketAddr) -> Result<TcpStream, Error> {
+    let mut stream = TcpStream::connect_timeout(&proxy, CONNECT_TIMEOUT)?;
+    // Going through Tor may take a while.
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(READ_TIMEOUT))?;
+
+    // Version 5, one authentication method: none.
+    stream.write_all(&[0x05, 0x01, 0x00])?;
+    let mut resp = [0; 2];
+    stream.read_exact(&mut resp)?;
+    if resp != [0x05, 0x00] {
+        return Err(Error::Protocol(
+            "SOCKS5 proxy requires an unsupported authentication method".to_string(),
+        ));
+    }
+
+    // Version 5, command CONNECT, reserved byte, then the address of the target.
+    let mut req = vec![0x05, 0x01, 0x00];
+    match target.ip() {
+        IpAddr::V4(ip) => {
+            req.push(0x01);
+            req.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            req.push(0x04);
+            req.extend_from_slice(&ip.octets());
+        }
+    }
+    req.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&req)?;
+
+    // The reply is the version, the status, a reserved byte and the address bound by the proxy.
+    let mut resp = [0; 4];
+    stream.read_exact(&mut resp)?;
+    if resp[0] != 0x05 || resp[1] != 0x00 {
+        return Err(Error::Protocol(format!(
+            "SOCKS5 proxy failed to connect to peer, status {}",
+            resp[1]
+        )));
+    }
+    let addr_len = match resp[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => {
+            let mut len = [0; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        t => {
+            return Err(Error::Protocol(format!(
+                "SOCKS5 proxy replied with unknown address type {t}"
+            )))
+        }
+    };
+    // Skip the bound address and port.
+    let mut bound = vec![0; addr_len + 2];
+    stream.read_exact(&mut bound)?;
+
+    Ok(stream)
+}
+
+// A random 64-bits integer, used as nonce in the version and ping messages.
+fn random_nonce() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// A connection to a peer serving compact block filters.
+pub struct Peer {
+    stream: TcpStream,
+    addr: SocketAddr,
+    magic: Magic,
+    /// The height of our peer's chain as advertised when we connected to it.
+    start_height: u32,
+}
+
+impl Peer {
+    /// Connect to the peer at this address, through the given SOCKS5 proxy if any, and perform
+    /// the handshake.
+    pub fn connect(
+        addr: SocketAddr,
+        proxy: Option<SocketAddr>,
+        network: Network,
+    ) -> Result<Self, Error> {
+        let stream = match proxy {
+            Some(proxy) => socks5_connect(proxy, addr)?,
+            None => TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?,
+        };
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        stream.set_write_timeout(Some(READ_TIMEOUT))?;
+        let mut peer = Peer {
+            stream,
+            addr,
+            magic: network.magic(),
+            start_height: 0,
+        };
+        peer.handshake()?;
+        log::info!(
+            "Connected to peer at '{}' with chain height {}.",
+            addr,
+            peer.start_height
+        );
+        Ok(peer)
+    }
+
+    /// The height of our peer's chain when we connected to it.
+    pub fn start_height(&self) -> u32 {
+        self.start_height
+    }
+
+    fn handshake(&mut self) -> Result<(), Error> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let local_addr = self.stream.local_addr()?;
+        self.send(NetworkMessage::Version(VersionMessage {
+            version: PROTOCOL_VERSION,
+            services: ServiceFlags::NONE,
+            timestamp,
+            receiver: p2p::Address::new(&self.addr, ServiceFlags::NONE),
+            sender: p2p::Address::new(&local_addr, ServiceFlags::NONE),
+            nonce: random_nonce(),
+            user_agent: format!("/lianad:{}/", env!("CARGO_PKG_VERSION")),
+            start_height: 0,
+            // We don't care about unconfirmed transactions.
+            relay: false,
+        }))?;
+
+        let (mut got_version, mut got_verack) = (false, false);
+        while !(got_version && got_verack) {
+            match self.recv()? {
+                NetworkMessage::Version(version) => {
+                    if !version.services.has(ServiceFlags::COMPACT_FILTERS) {
+                        return Err(Error::NoCompactFilters);
+                    }
+                    self.start_height = version.start_height.try_into().unwrap_or(0);
+                    self.send(NetworkMessage::Verack)?;
+                    got_version = true;
+                }
+                NetworkMessage::Verack => got_verack = true,
+                msg => log::trace!("Ignoring message during handshake: {:?}", msg.cmd()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send(&mut self, msg: NetworkMessage) -> Result<(), Error> {
+        let raw_msg = RawNetworkMessage::new(self.magic, msg);
+        self.stream.write_all(&consensus::serialize(&raw_msg))?;
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Result<NetworkMessage, Error> {
+        let mut data = vec![0; MESSAGE_HEADER_SIZE];
+        self.stream.read_exact(&mut data)?;
+        if data[..4] != self.magic.to_bytes() {
+            return Err(Error::Protocol("Invalid network magic".to_string()));
+        }
+        let payload_len = u32::from_le_bytes(data[16..20].try_into().expect("4 bytes")) as usize;
+        if payload_len > MAX_MESSAGE_SIZE {
+            return Err(Error::Protocol(format!(
+                "Message too large: {payload_len} bytes"
+            )));
+        }
+        data.resize(MESSAGE_HEADER_SIZE + payload_len, 0);
+        self.stream.read_exact(&mut data[MESSAGE_HEADER_SIZE..])?;
+        let raw_msg: RawNetworkMessage = consensus::deserialize(&data)?;
+        Ok(raw_msg.into_payload())
+    }
+
+    // Wait for the first message for which `f` returns a value. Any other message is ignored,
+    // except pings which we answer to.
+    fn recv_until<T>(
+        &mut self,
+        mut f: impl FnMut(NetworkMessage) -> Result<Option<T>, Error>,
+    ) -> Result<T, Error> {
+        loop {
+            match self.recv()? {
+                NetworkMessage::Ping(nonce) => self.send(NetworkMessage::Pong(nonce))?,
+                msg => {
+                    if let Some(res) = f(msg)? {
+                        return Ok(res);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get the headers following the first hash of the `locator` our peer has in its best chain.
+    pub fn get_headers(&mut self, locator: Vec<BlockHash>) -> Result<Vec<block::Header>, Error> {
+        self.send(NetworkMessage::GetHeaders(GetHeadersMessage::new(
+            locator,
+            BlockHash::all_zeros(),
+        )))?;
+        self.recv_until(|msg| match msg {
+            NetworkMessage::Headers(headers) => Ok(Some(headers)),
+            _ => Ok(None),
+        })
+    }
+
+    /// Get the basic filters for the blocks from `start_height` to the block `stop_hash` at
+    /// `stop_height`, both included. The filters are returned in order.
+    pub fn get_cfilters(
+        &mut self,
+        start_height: u32,
+        stop_height: u32,
+        stop_hash: BlockHash,
+    ) -> Result<Vec<CFilter>, Error> {
+        assert!(stop_height >= start_height);
+        let count = (stop_height - start_height + 1) as usize;
+        assert!(count as u32 <= MAX_CFILTERS_BATCH);
+        self.send(NetworkMessage::GetCFilters(GetCFilters {
+            filter_type: BASIC_FILTER_TYPE,
+            start_height,
+            stop_hash,
+        }))?;
+
+        let mut filters = Vec::with_capacity(count);
+        self.recv_until(|msg| match msg {
+            NetworkMessage::CFilter(filter) if filter.filter_type == BASIC_FILTER_TYPE => {
+                filters.push(filter);
+                Ok((filters.len() == count).then_some(()))
+            }
+            _ => Ok(None),
+        })?;
+        Ok(filters)
+    }
+
+    /// Get the filter headers for the blocks from `start_height` to the block `stop_hash`, both
+    /// included. See [`Self::get_cfilters`].
+    pub fn get_cfheaders(
+        &mut self,
+        start_height: u32,
+        stop_hash: BlockHash,
+    ) -> Result<CFHeaders, Error> {
+        self.send(NetworkMessage::GetCFHeaders(GetCFHeaders {
+            filter_type: BASIC_FILTER_TYPE,
+            start_height,
+            stop_hash,
+        }))?;
+        self.recv_until(|msg| match msg {
+            NetworkMessage::CFHeaders(cfheaders)
+                if cfheaders.filter_type == BASIC_FILTER_TYPE
+                    && cfheaders.stop_hash == stop_hash =>
+            {
+                Ok(Some(cfheaders))
+            }
+            _ => Ok(None),
+        })
+    }
+
+    /// Get the block with this hash, including its witnesses. The transactions of the block are
+    /// checked against its header.
+    pub fn get_block(&mut self, hash: BlockHash) -> Result<bitcoin::Block, Error> {
+        self.send(NetworkMessage::GetData(vec![Inventory::WitnessBlock(hash)]))?;
+        let block = self.recv_until(|msg| match msg {
+            NetworkMessage::Block(block) if block.block_hash() == hash => Ok(Some(block)),
+            NetworkMessage::NotFound(_) => Err(Error::Protocol(format!(
+                "Peer does not have block '{hash}'"
+            ))),
+            _ => Ok(None),
+        })?;
+        if !block.check_merkle_root() || !block.check_witness_commitment() {
+            return Err(Error::InvalidBlock(hash));
+        }
+        Ok(block)
+    }
+
+    /// Send this transaction to our peer.
+    pub fn broadcast_tx(&mut self, tx: &bitcoin::Transaction) -> Result<(), Error> {
+        self.send(NetworkMessage::Tx(tx.clone()))?;
+        // Make sure the peer processed our transaction before returning.
+        let nonce = random_nonce();
+        self.send(NetworkMessage::Ping(nonce))?;
+        self.recv_until(|msg| match msg {
+            NetworkMessage::Pong(n) if n == nonce => Ok(Some(())),
+            _ => Ok(None),
+        })
+    }
+}
@@ -3,6 +3,7 @@
 //! We use the RPC interface and a watchonly descriptor wallet.
 
 mod utils;
+pub mod zmq;
 use crate::{
     bitcoin::{Block, BlockChainTip},
     config,
@@ -16,7 +17,7 @@ use std::{
     convert::TryInto,
     fs, io,
     str::FromStr,
-    thread,
+    sync, thread,
     time::Duration,
 };
 
@@ -62,6 +63,8 @@ pub enum BitcoindError {
     NetworkMismatch(String /*config*/, String /*bitcoind*/),
     StartRescan,
     RescanPastPruneHeight,
+    /// The package submitted through `submitpackage` was rejected, with this reason.
+    PackageRejected(String),
 }
 
 impl BitcoindError {
@@ -153,6 +156,9 @@ impl std::fmt::Display for BitcoindError {
                     "Trying to rescan the block chain past the prune block height."
                 )
             }
+            BitcoindError::PackageRejected(reason) => {
+                write!(f, "Package rejected by bitcoind: {reason}")
+            }
         }
     }
 }
@@ -207,8 +213,9 @@ impl std::fmt::Display for WalletError {
 }
 
 pub struct BitcoinD {
-    /// Client for generalistic calls.
-    node_client: Client,
+    /// Client for generalistic calls. It may be shared with the interfaces of other watchonly
+    /// wallets.
+    node_client: sync::Arc<Client>,
     /// A client that will disregard responses to the queries it makes.
     sendonly_client: Client,
     /// A client for calls related to the wallet.
@@ -218,6 +225,22 @@ pub struct BitcoinD {
     retries: usize,
 }
 
+// The builder for the transport of our clients, using the authentication from the config.
+fn transport_builder(
+    config: &config::BitcoindConfig,
+) -> Result<minreq_http::Builder, BitcoindError> {
+    Ok(match &config.rpc_auth {
+        config::BitcoindRpcAuth::CookieFile(cookie_path) => {
+            let cookie_string =
+                fs::read_to_string(cookie_path).map_err(BitcoindError::CookieFile)?;
+            MinreqHttpTransport::builder().cookie_auth(cookie_string)
+        }
+        config::BitcoindRpcAuth::UserPass(user, pass) => {
+            MinreqHttpTransport::builder().basic_auth(user.clone(), Some(pass.clone()))
+        }
+    })
+}
+
 macro_rules! params {
     ($($param:expr),* $(,)?) => {
         // FIXME: is there a way to avoid the allocation of an unnecessary Box?
@@ -238,17 +261,7 @@ impl BitcoinD {
     ) -> Result<BitcoinD, BitcoindError> {
         let node_url = format!("http://{}", config.addr);
         let watchonly_url = format!("http://{}/wallet/{}", config.addr, watchonly_wallet_path);
-
-        let builder = match &config.rpc_auth {
-            config::BitcoindRpcAuth::CookieFile(cookie_path) => {
-                let cookie_string =
-                    fs::read_to_string(cookie_path).map_err(BitcoindError::CookieFile)?;
-                MinreqHttpTransport::builder().cookie_auth(cookie_string)
-            }
-            config::BitcoindRpcAuth::UserPass(user, pass) => {
-                MinreqHttpTransport::builder().basic_auth(user.clone(), Some(pass.clone()))
-            }
-        };
+        let builder = transport_builder(config)?;
 
         // Create a dummy bitcoind with clients using a low timeout to sanity check the connection.
         let dummy_node_client = Client::with_transport(
@@ -276,7 +289,7 @@ impl BitcoinD {
                 .build(),
         );
         let dummy_bitcoind = BitcoinD {
-            node_client: dummy_node_client,
+            node_client: sync::Arc::new(dummy_node_client),
             sendonly_client,
             watchonly_client: dummy_wo_client,
             watchonly_wallet_path: watchonly_wallet_path.clone(),
@@ -310,6 +323,40 @@ impl BitcoinD {
                 .timeout(Duration::from_secs(RPC_SOCKET_TIMEOUT))
                 .build(),
         );
+        Ok(BitcoinD {
+            node_client: sync::Arc::new(node_client),
+            sendonly_client,
+            watchonly_client,
+            watchonly_wallet_path,
+            retries: BITCOIND_RETRY_LIMIT,
+        })
+    }
+
+    /// Create a bitcoind interface for another watchonly wallet, sharing the client for
+    /// generalistic calls of an existing interface. The connection to bitcoind is assumed to have
+    /// been checked already.
+    pub fn with_node_client(
+        config: &config::BitcoindConfig,
+        node_client: sync::Arc<Client>,
+        watchonly_wallet_path: String,
+    ) -> Result<BitcoinD, BitcoindError> {
+        let watchonly_url = format!("http://{}/wallet/{}", config.addr, watchonly_wallet_path);
+        let builder = transport_builder(config)?;
+        let sendonly_client = Client::with_transport(
+            builder
+                .clone()
+                .url(&watchonly_url)
+                .map_err(BitcoindError::from)?
+                .timeout(Duration::from_secs(1))
+                .build(),
+        );
+        let watchonly_client = Client::with_transport(
+            builder
+                .url(&watchonly_url)
+                .map_err(BitcoindError::from)?
+                .timeout(Duration::from_secs(RPC_SOCKET_TIMEOUT))
+                .build(),
+        );
         Ok(BitcoinD {
             node_client,
             sendonly_client,
@@ -319,6 +366,12 @@ impl BitcoinD {
         })
     }
 
+    /// The client for generalistic calls, to be shared with the interfaces of other watchonly
+    /// wallets.
+    pub fn node_client(&self) -> sync::Arc<Client> {
+        self.node_client.clone()
+    }
+
     fn check_client(&self, client: &Client) -> Result<(), BitcoindError> {
         if let Err(e) = self.make_request(client, "echo", None) {
             if e.is_warming_up() {
@@ -877,6 +930,51 @@ impl BitcoinD {
         .is_none()
     }
 
+    /// Get an output of the UTXO set along with the height of the block it was confirmed in.
+    /// Returns `None` if it is unconfirmed or spent, including by a mempool transaction.
+    pub fn utxo(&self, op: &bitcoin::OutPoint) -> Option<(bitcoin::TxOut, i32)> {
+        // The result of gettxout is empty if the outpoint is spent, and when including the
+        // mempool, unconfirmed outputs are returned with no confirmation.
+        let res = self.make_node_request(
+            "gettxout",
+            params!(
+                Json::String(op.txid.to_string()),
+                Json::Number(op.vout.into()),
+                Json::Bool(true)
+            ),
+        );
+        let confirmations = res
+            .get("confirmations")
+            .and_then(Json::as_i64)
+            .filter(|c| *c > 0)? as i32;
+        let best_block = res
+            .get("bestblock")
+            .and_then(Json::as_str)
+            .and_then(|s| bitcoin::BlockHash::from_str(s).ok())
+            .expect("Invalid bestblock in `gettxout` response");
+        let value = res
+            .get("value")
+            .and_then(Json::as_f64)
+            .and_then(|v| bitcoin::Amount::from_btc(v).ok())
+            .expect("Invalid value in `gettxout` response");
+        let script_pubkey = res
+            .get("scriptPubKey")
+            .and_then(|spk| spk.get("hex"))
+            .and_then(Json::as_str)
+            .and_then(|s| Vec::from_hex(s).ok())
+            .map(bitcoin::ScriptBuf::from_bytes)
+            .expect("Invalid scriptPubKey in `gettxout` response");
+        // The best block may have changed since, so get the height of this one.
+        let best_height = self.get_block_stats(best_block)?.height;
+        Some((
+            bitcoin::TxOut {
+                value,
+                script_pubkey,
+            },
+            best_height - confirmations + 1,
+        ))
+    }
+
     /// So, bitcoind has no API for getting the transaction spending a wallet UTXO. Instead we are
     /// therefore using a rather convoluted way to get it the other way around, since the spending
     /// transaction is actually *part of the wallet transactions*.
@@ -1052,6 +1150,45 @@ impl BitcoinD {
         Ok(())
     }
 
+    /// Submit a package of transactions to bitcoind's mempool and broadcast them. The package must
+    /// be a child along with its unconfirmed parents, topologically sorted (parents first).
+    pub fn broadcast_package(&self, txs: &[bitcoin::Transaction]) -> Result<(), BitcoindError> {
+        let txs_hex = txs
+            .iter()
+            .map(|tx| Json::String(bitcoin::consensus::encode::serialize_hex(tx)))
+            .collect();
+        let res =
+            self.make_fallible_node_request("submitpackage", params!(Json::Array(txs_hex)))?;
+        let package_msg = res
+            .get("package_msg")
+            .and_then(Json::as_str)
+            .expect("Missing or invalid 'package_msg' in 'submitpackage' result.");
+        if package_msg == "success" {
+            return Ok(());
+        }
+        // Report the errors of the individual transactions, if any, as they are more helpful than
+        // the generic package message.
+        let tx_errors: Vec<String> = res
+            .get("tx-results")
+            .and_then(Json::as_object)
+            .map(|results| {
+                results
+                    .values()
+                    .filter_map(|res| {
+                        let txid = res.get("txid").and_then(Json::as_str)?;
+                        let error = res.get("error").and_then(Json::as_str)?;
+                        Some(format!("{txid}: {error}"))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Err(BitcoindError::PackageRejected(if tx_errors.is_empty() {
+            package_msg.to_string()
+        } else {
+            format!("{package_msg} ({})", tx_errors.join(", "))
+        }))
+    }
+
     // For the given descriptor strings check if they are imported at this timestamp in the
     // watchonly wallet.
     fn check_descs_timestamp(
@@ -1228,6 +1365,25 @@ impl BitcoinD {
             .collect()
     }
 
+    /// Estimate the feerate in sats/vb for a transaction to confirm within this many blocks.
+    /// Returns `None` if bitcoind doesn't have enough data to estimate it.
+    pub fn estimate_feerate(&self, conf_target: u16) -> Option<u64> {
+        let res = match self.make_fallible_node_request(
+            "estimatesmartfee",
+            params!(Json::Number(conf_target.into())),
+        ) {
+            Ok(res) => res,
+            Err(e) => {
+                log::warn!("Error when estimating the feerate: {}", e);
+                return None;
+            }
+        };
+        // The feerate is given in BTC/kvb.
+        res.get("feerate")
+            .and_then(Json::as_f64)
+            .map(|btc_kvb| (btc_kvb * 100_000.0).ceil() as u64)
+    }
+
     /// Stop bitcoind.
     pub fn stop(&self) {
         self.make_node_request("stop", None);
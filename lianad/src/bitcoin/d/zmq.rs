@@ -0,0 +1,415 @@
+//! Listen to bitcoind's ZMQ notifications in order to update our state as soon as a new block is
+//! connected or a transaction touching our wallet enters the mempool.
+//!
+//! We only ever need to subscribe to a couple of topics on bitcoind's PUB sockets, so instead of
+//! depending on libzmq we implement the small subset of ZMTP 3.0 this requires (NULL security
+//! mechanism, SUB socket). See https://rfc.zeromq.org/spec/23/.
+
+use crate::{
+    bitcoin::poller::EventNotifier,
+    config,
+    database::{CoinStatus, DatabaseInterface},
+};
+
+use std::{
+    collections::HashSet,
+    convert::TryFrom,
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync, thread, time,
+};
+
+use miniscript::bitcoin::{self, consensus};
+
+/// Topic of the notifications for new blocks.
+pub const RAWBLOCK_TOPIC: &str = "rawblock";
+/// Topic of the notifications for new transactions, in the mempool or in a block.
+pub const RAWTX_TOPIC: &str = "rawtx";
+
+// How long to wait before checking whether we were told to stop while no message is coming.
+const STOP_CHECK_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+// Once the beginning of a message was received, how long to wait for the rest of it.
+const MESSAGE_TIMEOUT: time::Duration = time::Duration::from_secs(30);
+
+// How long to wait before trying to connect again after an error.
+const RECONNECT_DELAY: time::Duration = time::Duration::from_secs(10);
+
+// How often at most to refresh the set of our scripts and coins against which to match the
+// transactions we are notified of.
+const CACHE_REFRESH_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+// Larger than any block bitcoind would notify us of.
+const MAX_FRAME_SIZE: u64 = 32 * 1024 * 1024;
+
+// Frame flags.
+const FLAG_MORE: u8 = 0x01;
+const FLAG_LONG: u8 = 0x02;
+const FLAG_COMMAND: u8 = 0x04;
+
+/// Get the address to connect to from a ZMQ endpoint as set in bitcoind's configuration (for
+/// instance "tcp://127.0.0.1:28332"). Only TCP endpoints are supported.
+pub fn endpoint_address(endpoint: &str) -> Option<&str> {
+    endpoint
+        .strip_prefix("tcp://")
+        .filter(|addr| !addr.is_empty())
+}
+
+/// The topics to subscribe to on each of the ZMQ endpoints set in this configuration.
+pub fn subscriptions(bitcoind_config: &config::BitcoindConfig) -> Vec<(String, Vec<&'static str>)> {
+    let mut subs: Vec<(String, Vec<&'static str>)> = Vec::new();
+    for (endpoint, topic) in [
+        (&bitcoind_config.zmqpubrawblock, RAWBLOCK_TOPIC),
+        (&bitcoind_config.zmqpubrawtx, RAWTX_TOPIC),
+    ] {
+        let endpoint = match endpoint {
+            Some(endpoint) => endpoint,
+            None => continue,
+        };
+        match subs.iter_mut().find(|(e, _)| e == endpoint) {
+            Some((_, topics)) => topics.push(topic),
+            None => subs.push((endpoint.clone(), vec![topic])),
+        }
+    }
+    subs
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+// A connection to a ZMQ PUB socket.
+struct Subscriber {
+    stream: TcpStream,
+}
+
+impl Subscriber {
+    /// Connect to the PUB socket at this address and subscribe to these topics.
+    fn connect(addr: &str, topics: &[&str]) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(MESSAGE_TIMEOUT))?;
+        let mut sub = Subscriber { stream };
+        sub.handshake()?;
+        for topic in topics {
+            // In ZMTP 3.0 a subscription is a message starting with 0x01 followed by the topic.
+            let mut body = Vec::with_capacity(topic.len() + 1);
+            body.push(0x01);
+            body.extend_from_slice(topic.as_bytes());
+            sub.write_frame(0, &body)?;
+        }
+        Ok(sub)
+    }
+
+    fn handshake(&mut self) -> io::Result<()> {
+        // Signature, version 3.0, NULL mechanism, as-server false and filler.
+        let mut greeting = [0u8; 64];
+        greeting[0] = 0xFF;
+        greeting[9] = 0x7F;
+        greeting[10] = 3;
+        greeting[11] = 0;
+        greeting[12..16].copy_from_slice(b"NULL");
+        self.stream.write_all(&greeting)?;
+
+        let mut their_greeting = [0u8; 64];
+        self.stream.read_exact(&mut their_greeting)?;
+        if their_greeting[0] != 0xFF || their_greeting[9] != 0x7F {
+            return Err(invalid_data("Not a ZMTP peer."));
+        }
+        if their_greeting[10] < 3 {
+            return Err(invalid_data(format!(
+                "Unsupported ZMTP version {}.",
+                their_greeting[10]
+            )));
+        }
+        if &their_greeting[12..16] != b"NULL" || their_greeting[16..32].iter().any(|b| *b != 0) {
+            return Err(invalid_data("Unsupported ZMTP security mechanism."));
+        }
+
+        // The NULL mechanism handshake is a READY command exchange carrying our socket type.
+        let mut ready = Vec::new();
+        ready.push(5);
+        ready.extend_from_slice(b"READY");
+        ready.push(11);
+        ready.extend_from_slice(b"Socket-Type");
+        ready.extend_from_slice(&3u32.to_be_bytes());
+        ready.extend_from_slice(b"SUB");
+        self.write_frame(FLAG_COMMAND, &ready)?;
+
+        let (flags, body) = self.read_frame()?;
+        if flags & FLAG_COMMAND == 0 {
+            return Err(invalid_data("Expected a command from the peer."));
+        }
+        if body.first() == Some(&5) && body.get(1..6) == Some(b"READY") {
+            Ok(())
+        } else if body.first() == Some(&5) && body.get(1..6) == Some(b"ERROR") {
+            Err(invalid_data(format!(
+                "Peer refused the handshake: {}.",
+                String::from_utf8_lossy(body.get(7..).unwrap_or_default())
+            )))
+        } else {
+            Err(invalid_data("Expected a READY command from the peer."))
+        }
+    }
+
+    fn write_frame(&mut self, flags: u8, body: &[u8]) -> io::Result<()> {
+        let mut frame = Vec::with_capacity(body.len() + 9);
+        if let Ok(size) = u8::try_from(body.len()) {
+            frame.push(flags);
+            frame.push(size);
+        } else {
+            frame.push(flags | FLAG_LONG);
+            frame.extend_from_slice(&(body.len() as u64).to_be_bytes());
+        }
+        frame.extend_from_slice(body);
+        self.stream.write_all(&frame)
+    }
+
+    fn read_frame(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let mut flags = [0u8; 1];
+        self.stream.read_exact(&mut flags)?;
+        let flags = flags[0];
+        let size = if flags & FLAG_LONG != 0 {
+            let mut size = [0u8; 8];
+            self.stream.read_exact(&mut size)?;
+            u64::from_be_bytes(size)
+        } else {
+            let mut size = [0u8; 1];
+            self.stream.read_exact(&mut size)?;
+            size[0] as u64
+        };
+        if size > MAX_FRAME_SIZE {
+            return Err(invalid_data(format!("Frame too large: {} bytes.", size)));
+        }
+        let mut body = vec![0u8; size as usize];
+        self.stream.read_exact(&mut body)?;
+        Ok((flags, body))
+    }
+
+    /// Wait for the next message, for at most [`STOP_CHECK_INTERVAL`]. Returns `None` if no
+    /// message was received in the meantime.
+    fn recv(&mut self) -> io::Result<Option<Vec<Vec<u8>>>> {
+        // Only wait for a short time before the start of a message so we can regularly check
+        // whether to stop, but don't time out in the middle of one.
+        self.stream.set_read_timeout(Some(STOP_CHECK_INTERVAL))?;
+        let peeked = self.stream.peek(&mut [0u8; 1]);
+        self.stream.set_read_timeout(Some(MESSAGE_TIMEOUT))?;
+        match peeked {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) => {}
+            Err(e) if is_timeout(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let mut parts = Vec::new();
+        loop {
+            let (flags, body) = self.read_frame()?;
+            // We don't need any of the commands the peer may send once the handshake is done.
+            if flags & FLAG_COMMAND != 0 {
+                if parts.is_empty() {
+                    return Ok(None);
+                }
+                continue;
+            }
+            parts.push(body);
+            if flags & FLAG_MORE == 0 {
+                return Ok(Some(parts));
+            }
+        }
+    }
+}
+
+// The scripts and coins of our wallet, to tell whether a transaction concerns us.
+struct WalletCache {
+    spks: HashSet<bitcoin::ScriptBuf>,
+    outpoints: HashSet<bitcoin::OutPoint>,
+    last_refresh: Option<time::Instant>,
+}
+
+impl WalletCache {
+    fn new() -> Self {
+        WalletCache {
+            spks: HashSet::new(),
+            outpoints: HashSet::new(),
+            last_refresh: None,
+        }
+    }
+
+    fn refresh(&mut self, db: &sync::Arc<sync::Mutex<dyn DatabaseInterface>>) {
+        if self
+            .last_refresh
+            .is_some_and(|t| t.elapsed() < CACHE_REFRESH_INTERVAL)
+        {
+            return;
+        }
+        let mut db_conn = db.connection();
+        self.spks = db_conn
+            .script_pubkeys()
+            .into_iter()
+            .map(|(spk, ..)| spk)
+            .collect();
+        self.outpoints = db_conn
+            .coins(&[CoinStatus::Unconfirmed, CoinStatus::Confirmed], &[])
+            .into_keys()
+            .collect();
+        self.last_refresh = Some(time::Instant::now());
+    }
+
+    fn concerns_us(&self, tx: &bitcoin::Transaction) -> bool {
+        tx.output
+            .iter()
+            .any(|txo| self.spks.contains(&txo.script_pubkey))
+            || tx
+                .input
+                .iter()
+                .any(|txin| self.outpoints.contains(&txin.previous_output))
+    }
+}
+
+/// Listen to the notifications published by bitcoind on this endpoint for these topics, until
+/// told to stop.
+pub fn listen(
+    endpoint: String,
+    topics: Vec<&'static str>,
+    db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+    notifier: EventNotifier,
+) {
+    let addr = match endpoint_address(&endpoint) {
+        Some(addr) => addr,
+        None => {
+            log::error!(
+                "Unsupported ZMQ endpoint '{}', only TCP endpoints are supported.",
+                endpoint
+            );
+            return;
+        }
+    };
+    log::info!(
+        "Listening to bitcoind's ZMQ notifications ({}) on '{}'.",
+        topics.join(", "),
+        endpoint
+    );
+    let mut cache = WalletCache::new();
+    while !notifier.should_stop() {
+        if let Err(e) = listen_endpoint(addr, &topics, &db, &mut cache, &notifier) {
+            log::warn!(
+                "Error while listening to bitcoind's ZMQ notifications on '{}': {}. Reconnecting in {}s.",
+                endpoint,
+                e,
+                RECONNECT_DELAY.as_secs()
+            );
+            let start = time::Instant::now();
+            while !notifier.should_stop() && start.elapsed() < RECONNECT_DELAY {
+                thread::sleep(STOP_CHECK_INTERVAL);
+            }
+        }
+    }
+    log::info!(
+        "Stopped listening to bitcoind's ZMQ notifications on '{}'.",
+        endpoint
+    );
+}
+
+fn listen_endpoint(
+    addr: &str,
+    topics: &[&str],
+    db: &sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+    cache: &mut WalletCache,
+    notifier: &EventNotifier,
+) -> io::Result<()> {
+    let mut sub = Subscriber::connect(addr, topics)?;
+    while !notifier.should_stop() {
+        let parts = match sub.recv()? {
+            Some(parts) => parts,
+            None => continue,
+        };
+        // bitcoind's messages are made of the topic, the body and a sequence number.
+        let (topic, body) = match (parts.first(), parts.get(1)) {
+            (Some(topic), Some(body)) => (topic.as_slice(), body),
+            _ => continue,
+        };
+        if topic == RAWBLOCK_TOPIC.as_bytes() {
+            log::debug!("Got notified of a new block by bitcoind.");
+            notifier.notify();
+        } else if topic == RAWTX_TOPIC.as_bytes() {
+            let tx: bitcoin::Transaction = match consensus::deserialize(body) {
+                Ok(tx) => tx,
+                Err(e) => {
+                    log::error!("Invalid transaction notified by bitcoind: {}", e);
+                    continue;
+                }
+            };
+            cache.refresh(db);
+            if cache.concerns_us(&tx) {
+                log::debug!(
+                    "Got notified of transaction '{}' by bitcoind.",
+                    tx.compute_txid()
+                );
+                notifier.notify();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zmq_endpoint_address() {
+        assert_eq!(
+            endpoint_address("tcp://127.0.0.1:28332"),
+            Some("127.0.0.1:28332")
+        );
+        assert_eq!(
+            endpoint_address("tcp://localhost:28333"),
+            Some("localhost:28333")
+        );
+        assert_eq!(endpoint_address("tcp://"), None);
+        assert_eq!(endpoint_address("ipc:///tmp/bitcoind.zmq"), None);
+        assert_eq!(endpoint_address("127.0.0.1:28332"), None);
+    }
+
+    #[test]
+    fn zmq_subscriptions() {
+        let mut bitcoind_config = config::BitcoindConfig {
+            addr: "127.0.0.1:8332".parse().unwrap(),
+            rpc_auth: config::BitcoindRpcAuth::CookieFile("/dummy/cookie".into()),
+            zmqpubrawblock: None,
+            zmqpubrawtx: None,
+        };
+        assert!(subscriptions(&bitcoind_config).is_empty());
+
+        bitcoind_config.zmqpubrawblock = Some("tcp://127.0.0.1:28332".to_string());
+        assert_eq!(
+            subscriptions(&bitcoind_config),
+            vec![("tcp://127.0.0.1:28332".to_string(), vec![RAWBLOCK_TOPIC])]
+        );
+
+        bitcoind_config.zmqpubrawtx = Some("tcp://127.0.0.1:28332".to_string());
+        assert_eq!(
+            subscriptions(&bitcoind_config),
+            vec![(
+                "tcp://127.0.0.1:28332".to_string(),
+                vec![RAWBLOCK_TOPIC, RAWTX_TOPIC]
+            )]
+        );
+
+        bitcoind_config.zmqpubrawtx = Some("tcp://127.0.0.1:28333".to_string());
+        assert_eq!(
+            subscriptions(&bitcoind_config),
+            vec![
+                ("tcp://127.0.0.1:28332".to_string(), vec![RAWBLOCK_TOPIC]),
+                ("tcp://127.0.0.1:28333".to_string(), vec![RAWTX_TOPIC])
+            ]
+        );
+    }
+}
@@ -25,6 +25,12 @@ const DEFAULT_BATCH_SIZE: usize = 200;
 // If Electrum takes more than 3 minutes to answer one of our queries, fail.
 const RPC_SOCKET_TIMEOUT: u8 = 180;
 
+// How long to wait for the server to answer our first ping.
+const PING_TIMEOUT: u8 = 3;
+
+// Same when connecting through a proxy, which is much slower to establish a connection (Tor).
+const PROXY_PING_TIMEOUT: u8 = 30;
+
 // Number of retries while communicating with the Electrum server.
 // A retry happens with exponential back-off (base 2) so this makes us give up after (1+2+4+8+16+32=) 63 seconds.
 const RETRY_LIMIT: u8 = 6;
@@ -53,13 +59,29 @@ impl std::fmt::Display for Error {
 pub struct Client(BdkElectrumClient<electrum_client::Client>);
 
 impl Client {
-    /// Create a new client and perform sanity checks.
-    pub fn new(electrum_config: &config::ElectrumConfig) -> Result<Self, Error> {
+    /// Create a new client and perform sanity checks. If a proxy is given, the connection to a
+    /// remote server goes through it.
+    pub fn new(
+        electrum_config: &config::ElectrumConfig,
+        proxy: Option<&config::ProxyConfig>,
+    ) -> Result<Self, Error> {
+        // The proxy resolves the host name itself, which is what allows to use an onion address.
+        let socks5 = proxy
+            .filter(|proxy| proxy.applies_to_url(&electrum_config.addr))
+            .map(|proxy| electrum_client::Socks5Config::new(proxy.addr));
+        let ping_timeout = if socks5.is_some() {
+            log::info!("Connecting to the Electrum server through the proxy.");
+            PROXY_PING_TIMEOUT
+        } else {
+            PING_TIMEOUT
+        };
+
         // First use a dummy config to check connectivity (no retries, short timeout).
         let dummy_config = Config::builder()
             .retry(0)
             .validate_domain(electrum_config.validate_domain)
-            .timeout(Some(3))
+            .timeout(Some(ping_timeout))
+            .socks5(socks5.clone())
             .build();
         // Try to ping the server.
         electrum_client::Client::from_config(&electrum_config.addr, dummy_config)
@@ -71,6 +93,7 @@ impl Client {
             .retry(RETRY_LIMIT)
             .timeout(Some(RPC_SOCKET_TIMEOUT))
             .validate_domain(electrum_config.validate_domain)
+            .socks5(socks5)
             .build();
 
         let inner = electrum_client::Client::from_config(&electrum_config.addr, config)
@@ -118,6 +141,122 @@ impl Client {
             .map(|bh| bh.time)
     }
 
+    pub fn tip_median_time_past(&self) -> Result<u32, Error> {
+        let tip_height: u32 = self
+            .chain_tip()?
+            .height
+            .try_into()
+            .expect("Height must not be negative");
+        let start = crate::bitcoin::median_time_span_start(tip_height);
+        let times = self
+            .0
+            .inner
+            .block_headers(start as usize, (tip_height - start + 1) as usize)
+            .map_err(Error::Server)?
+            .headers
+            .iter()
+            .map(|header| header.time)
+            .collect();
+        crate::bitcoin::median_time(times).ok_or_else(|| {
+            Error::Server(electrum_client::Error::Message(
+                "No block headers returned".to_string(),
+            ))
+        })
+    }
+
+    /// Get an output of the UTXO set along with the height of the block it was confirmed in.
+    /// Returns `None` if it is unconfirmed or spent, including by a mempool transaction.
+    pub fn utxo(
+        &self,
+        outpoint: &bitcoin::OutPoint,
+    ) -> Result<Option<(bitcoin::TxOut, i32)>, Error> {
+        let tx = self
+            .0
+            .inner
+            .transaction_get(&outpoint.txid)
+            .map_err(Error::Server)?;
+        let txout = match tx.output.get(outpoint.vout as usize) {
+            Some(txout) => txout.clone(),
+            None => return Ok(None),
+        };
+        // The unspent outputs listed by the server exclude those spent in its mempool, and have a
+        // height of 0 if unconfirmed.
+        let height = self
+            .0
+            .inner
+            .script_list_unspent(&txout.script_pubkey)
+            .map_err(Error::Server)?
+            .into_iter()
+            .find(|utxo| utxo.tx_hash == outpoint.txid && utxo.tx_pos == outpoint.vout as usize)
+            .map(|utxo| height_i32_from_usize(utxo.height))
+            .filter(|h| *h > 0);
+        Ok(height.map(|h| (txout, h)))
+    }
+
+    /// Estimate the feerate in sats/vb for a transaction to confirm within this many blocks.
+    /// Returns `None` if the server doesn't have enough data to estimate it.
+    pub fn estimate_feerate(&self, conf_target: u16) -> Result<Option<u64>, Error> {
+        // The feerate is given in BTC/kvb, and is negative if it can't be estimated.
+        let btc_kvb = self
+            .0
+            .inner
+            .estimate_fee(conf_target.into())
+            .map_err(Error::Server)?;
+        Ok((btc_kvb > 0.0).then(|| (btc_kvb * 100_000.0).ceil() as u64))
+    }
+
+    /// Subscribe to notifications of new blocks.
+    pub fn subscribe_headers(&self) -> Result<(), Error> {
+        self.0
+            .inner
+            .block_headers_subscribe()
+            .map(|_| ())
+            .map_err(Error::Server)
+    }
+
+    /// Subscribe to notifications of changes to the history of these scripts.
+    pub fn subscribe_scripts(&self, spks: &[bitcoin::ScriptBuf]) -> Result<(), Error> {
+        for chunk in spks.chunks(DEFAULT_BATCH_SIZE) {
+            self.0
+                .inner
+                .batch_script_subscribe(chunk.iter().map(|spk| spk.as_script()))
+                .map_err(Error::Server)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the server notified us of a new block or of a change to the history of one of
+    /// these (subscribed) scripts since the last call.
+    pub fn pop_notifications<'a>(
+        &self,
+        spks: impl IntoIterator<Item = &'a bitcoin::ScriptBuf>,
+    ) -> Result<bool, Error> {
+        // Notifications are only read from the socket along with responses to our requests.
+        self.0.inner.ping().map_err(Error::Server)?;
+        let mut notified = false;
+        while self
+            .0
+            .inner
+            .block_headers_pop()
+            .map_err(Error::Server)?
+            .is_some()
+        {
+            notified = true;
+        }
+        for spk in spks {
+            while self
+                .0
+                .inner
+                .script_pop(spk.as_script())
+                .map_err(Error::Server)?
+                .is_some()
+            {
+                notified = true;
+            }
+        }
+        Ok(notified)
+    }
+
     /// Returns a reference to the wrapped `BdkElectrumClient`.
     pub fn bdk_electrum_client(&self) -> &BdkElectrumClient<electrum_client::Client> {
         &self.0
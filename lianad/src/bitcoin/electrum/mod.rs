@@ -1,16 +1,25 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync, time};
 
 use bdk_electrum::bdk_chain::{
     bitcoin::{self, bip32::ChildNumber, BlockHash, OutPoint},
     local_chain::LocalChain,
     spk_client::{FullScanRequest, SyncRequest},
-    ChainPosition,
 };
 
 pub mod client;
-mod utils;
+pub mod notifier;
+pub mod utils;
 pub mod wallet;
-use crate::bitcoin::{Block, BlockChainTip, Coin};
+use crate::{
+    bitcoin::{Block, BlockChainTip, Coin},
+    config,
+};
+
+use serde::{Deserialize, Serialize};
+
+// How many blocks the chain tip of the server we use may be behind the one of the server we
+// cross-check it against. Servers may not learn about a new block at the same time.
+const MAX_TIP_LAG: i32 = 2;
 
 /// An error in the Electrum interface.
 #[derive(Debug)]
@@ -38,9 +47,100 @@ impl std::fmt::Display for ElectrumError {
     }
 }
 
+/// The health of one of the Electrum servers from our configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerStatus {
+    /// The address of the server, as set in the configuration.
+    pub addr: String,
+    /// Whether this is the server we are syncing with.
+    pub active: bool,
+    /// The number of consecutive failures to communicate with this server.
+    pub failures: u32,
+    /// The error we got the last time we failed to communicate with this server, if it's still
+    /// failing.
+    pub last_error: Option<String>,
+    /// Timestamp of the last time we successfully communicated with this server, if any.
+    pub last_success: Option<u32>,
+}
+
+impl ServerStatus {
+    fn new(addr: String) -> Self {
+        Self {
+            addr,
+            active: false,
+            failures: 0,
+            last_error: None,
+            last_success: None,
+        }
+    }
+
+    fn record_success(&mut self) {
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or(0);
+        self.failures = 0;
+        self.last_error = None;
+        self.last_success = Some(now);
+    }
+
+    fn record_failure(&mut self, error: String) {
+        self.failures = self.failures.saturating_add(1);
+        self.last_error = Some(error);
+    }
+}
+
+/// Connect to the first server from the configuration we can reach. Returns its index in
+/// [`config::ElectrumConfig::addrs`] along with the client.
+pub fn connect_first(
+    electrum_config: &config::ElectrumConfig,
+    proxy: Option<&config::ProxyConfig>,
+) -> Result<(usize, client::Client), client::Error> {
+    let mut first_error = None;
+    for (i, addr) in electrum_config.addrs().enumerate() {
+        match connect_server(electrum_config, addr, proxy) {
+            Ok(client) => return Ok((i, client)),
+            Err(e) => {
+                log::error!("Failed to connect to Electrum server '{addr}': {e}");
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(first_error.expect("There is always at least one server"))
+}
+
+// Connect to the server at this address using the settings from the configuration.
+fn connect_server(
+    electrum_config: &config::ElectrumConfig,
+    addr: &str,
+    proxy: Option<&config::ProxyConfig>,
+) -> Result<client::Client, client::Error> {
+    let server_config = config::ElectrumConfig {
+        addr: addr.to_string(),
+        ..electrum_config.clone()
+    };
+    client::Client::new(&server_config, proxy)
+}
+
 /// Interface for Electrum backend.
+///
+/// If more than one server is configured, we fail over to the next one we can connect to when the
+/// server we use can't be reached. We don't switch back to a previous server unless the current one
+/// fails.
 pub struct Electrum {
-    client: client::Client,
+    /// The connection to the server we use. It may be shared with the interfaces of other
+    /// wallets, until one of them fails over to another server.
+    client: sync::Arc<client::Client>,
+    /// Used to connect to the other servers from the configuration.
+    config: config::ElectrumConfig,
+    proxy: Option<config::ProxyConfig>,
+    /// The health of every server from the configuration, in the same order.
+    servers: Vec<ServerStatus>,
+    /// The index in `servers` of the server `client` is connected to.
+    active: usize,
+    /// If cross-checks are enabled, a connection to a second server along with its index in
+    /// `servers`. It is established on demand.
+    witness: Option<(usize, client::Client)>,
     bdk_wallet: wallet::BdkWallet,
     /// Used for setting the `last_seen` of unconfirmed transactions in a strictly
     /// increasing manner.
@@ -52,12 +152,29 @@ pub struct Electrum {
 
 impl Electrum {
     pub fn new(
-        client: client::Client,
+        client: sync::Arc<client::Client>,
+        active: usize,
+        config: config::ElectrumConfig,
+        proxy: Option<config::ProxyConfig>,
         bdk_wallet: wallet::BdkWallet,
         full_scan: bool,
     ) -> Result<Self, ElectrumError> {
+        let mut servers: Vec<_> = config.addrs().cloned().map(ServerStatus::new).collect();
+        servers[active].active = true;
+        servers[active].record_success();
+        if config.cross_check && servers.len() < 2 {
+            log::warn!(
+                "Electrum cross-checks are enabled but there is no fallback server to check \
+                 against."
+            );
+        }
         Ok(Self {
             client,
+            config,
+            proxy,
+            servers,
+            active,
+            witness: None,
             bdk_wallet,
             sync_count: 0,
             full_scan,
@@ -85,6 +202,161 @@ impl Electrum {
         &self.client
     }
 
+    /// The connection to the server we use along with its index in the configuration, to be
+    /// shared with the interfaces of other wallets.
+    pub fn connection(&self) -> (usize, sync::Arc<client::Client>) {
+        (self.active, self.client.clone())
+    }
+
+    /// The health of every server from the configuration.
+    pub fn servers(&self) -> Vec<ServerStatus> {
+        self.servers.clone()
+    }
+
+    // Connect to the server at this index in `servers` and make sure it's on our network.
+    fn connect_to(&self, index: usize) -> Result<client::Client, ElectrumError> {
+        let client = connect_server(&self.config, &self.servers[index].addr, self.proxy.as_ref())
+            .map_err(ElectrumError::Client)?;
+        let expected_hash = self.local_chain().genesis_hash();
+        let server_hash = client.genesis_block().map_err(ElectrumError::Client)?.hash;
+        if server_hash != expected_hash {
+            return Err(ElectrumError::GenesisHashMismatch(
+                expected_hash,
+                server_hash,
+                expected_hash,
+            ));
+        }
+        Ok(client)
+    }
+
+    // Switch to the next server we can connect to, if any. Returns whether we did.
+    fn fail_over(&mut self) -> bool {
+        let count = self.servers.len();
+        for index in (1..count).map(|offset| (self.active + offset) % count) {
+            match self.connect_to(index) {
+                Ok(client) => {
+                    log::warn!(
+                        "Failing over from Electrum server '{}' to '{}'.",
+                        self.servers[self.active].addr,
+                        self.servers[index].addr
+                    );
+                    self.servers[self.active].active = false;
+                    self.servers[index].active = true;
+                    self.active = index;
+                    self.client = sync::Arc::new(client);
+                    if matches!(self.witness, Some((i, _)) if i == index) {
+                        self.witness = None;
+                    }
+                    return true;
+                }
+                Err(e) => {
+                    log::error!(
+                        "Failed to connect to Electrum server '{}': {}",
+                        self.servers[index].addr,
+                        e
+                    );
+                    self.servers[index].record_failure(e.to_string());
+                }
+            }
+        }
+        false
+    }
+
+    // Connect to a second server to cross-check the one we use against, if we aren't already.
+    fn connect_witness(&mut self) {
+        if self.witness.is_some() {
+            return;
+        }
+        let count = self.servers.len();
+        for index in (1..count).map(|offset| (self.active + offset) % count) {
+            match self.connect_to(index) {
+                Ok(client) => {
+                    log::info!(
+                        "Cross-checking Electrum server '{}' against '{}'.",
+                        self.servers[self.active].addr,
+                        self.servers[index].addr
+                    );
+                    self.servers[index].record_success();
+                    self.witness = Some((index, client));
+                    return;
+                }
+                Err(e) => self.servers[index].record_failure(e.to_string()),
+            }
+        }
+        log::warn!("No Electrum server available to cross-check against.");
+    }
+
+    // Check the chain tip of the server we use against a second server. If it's behind, it may be
+    // withholding blocks (and therefore transactions) from us. If it has another block at the same
+    // height, it is either on a fork or lying to us. In both cases stop using it.
+    fn cross_check_tip(&mut self) {
+        self.connect_witness();
+        let (index, witness) = match self.witness.as_ref() {
+            Some((index, witness)) => (*index, witness),
+            None => return,
+        };
+        let witness_tip = match witness.chain_tip() {
+            Ok(tip) => tip,
+            Err(e) => {
+                log::error!(
+                    "Error getting the chain tip from Electrum server '{}': {}",
+                    self.servers[index].addr,
+                    e
+                );
+                self.servers[index].record_failure(e.to_string());
+                self.witness = None;
+                return;
+            }
+        };
+        self.servers[index].record_success();
+
+        let tip = self.wallet_tip();
+        if witness_tip.height > tip.height + MAX_TIP_LAG {
+            let error = format!(
+                "Chain tip {} is behind the chain tip {} of server '{}'.",
+                tip, witness_tip, self.servers[index].addr
+            );
+            log::error!(
+                "Electrum server '{}' may be withholding blocks: {}",
+                self.servers[self.active].addr,
+                error
+            );
+            self.servers[self.active].record_failure(error);
+            self.fail_over();
+        } else if witness_tip.height == tip.height && witness_tip.hash != tip.hash {
+            let error = format!(
+                "Chain tip {} conflicts with the chain tip {} of server '{}'.",
+                tip, witness_tip, self.servers[index].addr
+            );
+            log::error!(
+                "Electrum server '{}' is on a fork or lying to us: {}",
+                self.servers[self.active].addr,
+                error
+            );
+            self.servers[self.active].record_failure(error);
+            self.fail_over();
+        }
+    }
+
+    // Connect to a server other than the one we use, for a one-off request. Returns its index in
+    // `servers` along with the connection.
+    fn connect_other(&self) -> Option<(usize, client::Client)> {
+        let count = self.servers.len();
+        (1..count)
+            .map(|offset| (self.active + offset) % count)
+            .find_map(|index| match self.connect_to(index) {
+                Ok(client) => Some((index, client)),
+                Err(e) => {
+                    log::error!(
+                        "Failed to connect to Electrum server '{}': {}",
+                        self.servers[index].addr,
+                        e
+                    );
+                    None
+                }
+            })
+    }
+
     fn local_chain(&self) -> &LocalChain {
         self.bdk_wallet.local_chain()
     }
@@ -119,10 +391,36 @@ impl Electrum {
 
     /// Sync the wallet with the Electrum server. If there was any reorg since the last poll, this
     /// returns the first common ancestor between the previous and the new chain.
+    ///
+    /// If the server can't be reached, we fail over to the next one and retry once.
     pub fn sync_wallet(
         &mut self,
         receive_index: ChildNumber,
         change_index: ChildNumber,
+    ) -> Result<Option<BlockChainTip>, ElectrumError> {
+        let mut res = self.sync_wallet_with_server(receive_index, change_index);
+        if let Err(e) = &res {
+            self.servers[self.active].record_failure(e.to_string());
+            if self.fail_over() {
+                res = self.sync_wallet_with_server(receive_index, change_index);
+                if let Err(e) = &res {
+                    self.servers[self.active].record_failure(e.to_string());
+                }
+            }
+        }
+        if res.is_ok() {
+            self.servers[self.active].record_success();
+            if self.config.cross_check {
+                self.cross_check_tip();
+            }
+        }
+        res
+    }
+
+    fn sync_wallet_with_server(
+        &mut self,
+        receive_index: ChildNumber,
+        change_index: ChildNumber,
     ) -> Result<Option<BlockChainTip>, ElectrumError> {
         self.bdk_wallet.reveal_spks(receive_index, change_index);
         let local_chain_tip = self.local_chain().tip();
@@ -140,7 +438,7 @@ impl Electrum {
         self.client
             .bdk_electrum_client()
             .populate_tx_cache(self.bdk_wallet.graph());
-        let (chain_update, mut graph_update, keychain_update) = if !self.is_rescanning() {
+        let (chain_update, graph_update, keychain_update) = if !self.is_rescanning() {
             log::debug!("Performing sync.");
             let mut request = SyncRequest::from_chain_tip(local_chain_tip.clone());
 
@@ -192,59 +490,49 @@ impl Electrum {
             chain_update.height()
         );
 
-        log::debug!("Full local chain: {:?}", self.local_chain());
-        log::debug!("Full chain update: {:?}", chain_update);
-
         // Increment the sync count and apply changes.
         self.sync_count = self.sync_count.checked_add(1).expect("must fit");
-        if let Some(keychain_update) = keychain_update {
-            self.bdk_wallet.apply_keychain_update(keychain_update);
-        }
-        let changeset = self.bdk_wallet.apply_connected_chain_update(chain_update);
-
-        let mut changes_iter = changeset.into_iter();
-        let reorg_common_ancestor = if let Some((height, _)) = changes_iter.next() {
-            // Either a new block has been added at this height or an existing block in our local
-            // chain has been invalidated.
-            // Since we iterate in ascending height order, we'll see the lowest block height first.
-            // If the lowest height is higher than our height before syncing, we're good.
-            // Else if it's adding/invalidating a block at height before syncing or lower,
-            // it's a reorg.
-            if height > local_chain_tip.height() {
-                None
-            } else {
-                log::info!("Block chain reorganization detected.");
-                // We can assume height is positive as genesis block will not have changed.
-                Some(
-                    self.bdk_wallet
-                        .find_block_before_height(height)
-                        .expect("height of first change is greater than 0"),
-                )
-            }
+        Ok(self.bdk_wallet.apply_sync_update(
+            &local_chain_tip,
+            chain_update,
+            graph_update,
+            keychain_update,
+            self.sync_count,
+        ))
+    }
+
+    /// Broadcast this transaction through the server we use. If cross-checks are enabled, also
+    /// broadcast it through the second server so a single server can't prevent it from being
+    /// relayed. If we aren't connected to a second server yet, we connect to one for this.
+    pub fn broadcast_tx(&self, tx: &bitcoin::Transaction) -> Result<(), ElectrumError> {
+        let res = self
+            .client
+            .broadcast_tx(tx)
+            .map(|_| ())
+            .map_err(ElectrumError::Client);
+        let connected = if self.config.cross_check && self.witness.is_none() {
+            self.connect_other()
         } else {
             None
         };
-
-        // Unconfirmed transactions have their last seen as 0, so we override to the `sync_count`
-        // so that conflicts can be properly handled. We use `sync_count` instead of current time
-        // in seconds to ensure strictly increasing values between poller iterations.
-        for tx in &graph_update.initial_changeset().txs {
+        if let Some((index, witness)) = self.witness.as_ref().or(connected.as_ref()) {
+            let (addr, witness_addr) =
+                (&self.servers[self.active].addr, &self.servers[*index].addr);
             let txid = tx.compute_txid();
-            if let Some(ChainPosition::Unconfirmed(_)) = graph_update.get_chain_position(
-                self.local_chain(),
-                self.local_chain().tip().block_id(),
-                txid,
-            ) {
-                log::debug!(
-                    "changing last seen for txid '{}' to {}",
-                    txid,
-                    self.sync_count
-                );
-                let _ = graph_update.insert_seen_at(txid, self.sync_count);
+            match (&res, witness.broadcast_tx(tx)) {
+                (Ok(()), Err(e)) => log::warn!(
+                    "Transaction '{txid}' was accepted by Electrum server '{addr}' but rejected by '{witness_addr}': {e}"
+                ),
+                (Err(e), Ok(_)) => {
+                    log::warn!(
+                        "Transaction '{txid}' was rejected by Electrum server '{addr}' but accepted by '{witness_addr}': {e}"
+                    );
+                    return Ok(());
+                }
+                _ => {}
             }
         }
-        self.bdk_wallet.apply_graph_update(graph_update);
-        Ok(reorg_common_ancestor)
+        res
     }
 
     pub fn wallet_transaction(
@@ -0,0 +1,85 @@
+//! Listen to notifications from the Electrum server in order to update our state as soon as a new
+//! block is connected or the history of one of our addresses changes.
+//!
+//! This uses a connection dedicated to subscriptions. Notifications may be missed if the connection
+//! gets silently re-established, so the poller keeps polling at its usual interval regardless.
+
+use super::{client, connect_first};
+use crate::{bitcoin::poller::EventNotifier, config, database::DatabaseInterface};
+
+use std::{collections::HashSet, sync, thread, time};
+
+use bdk_electrum::bdk_chain::bitcoin::ScriptBuf;
+
+// How often to check for notifications from the server.
+const CHECK_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+// How often to check for new addresses to subscribe to.
+const SUBSCRIBE_INTERVAL: time::Duration = time::Duration::from_secs(30);
+
+// How long to wait before trying to connect again after an error.
+const RECONNECT_DELAY: time::Duration = time::Duration::from_secs(10);
+
+/// Listen to notifications from the first Electrum server we can reach among those configured,
+/// until told to stop.
+pub fn listen(
+    electrum_config: config::ElectrumConfig,
+    proxy: Option<config::ProxyConfig>,
+    db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+    notifier: EventNotifier,
+) {
+    log::info!("Listening to Electrum server notifications.");
+    while !notifier.should_stop() {
+        if let Err(e) = listen_server(&electrum_config, proxy.as_ref(), &db, &notifier) {
+            log::warn!(
+                "Error while listening to Electrum server notifications: {}. Reconnecting in {}s.",
+                e,
+                RECONNECT_DELAY.as_secs()
+            );
+            let start = time::Instant::now();
+            while !notifier.should_stop() && start.elapsed() < RECONNECT_DELAY {
+                thread::sleep(CHECK_INTERVAL);
+            }
+        }
+    }
+    log::info!("Stopped listening to Electrum server notifications.");
+}
+
+fn listen_server(
+    electrum_config: &config::ElectrumConfig,
+    proxy: Option<&config::ProxyConfig>,
+    db: &sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+    notifier: &EventNotifier,
+) -> Result<(), client::Error> {
+    let (_, client) = connect_first(electrum_config, proxy)?;
+    client.subscribe_headers()?;
+
+    let mut subscribed: HashSet<ScriptBuf> = HashSet::new();
+    let mut last_subscription: Option<time::Instant> = None;
+    while !notifier.should_stop() {
+        // We may have derived new addresses since we last subscribed.
+        if last_subscription.is_none_or(|t| t.elapsed() >= SUBSCRIBE_INTERVAL) {
+            let new_spks: Vec<ScriptBuf> = db
+                .connection()
+                .script_pubkeys()
+                .into_iter()
+                .map(|(spk, ..)| spk)
+                .filter(|spk| !subscribed.contains(spk))
+                .collect();
+            if !new_spks.is_empty() {
+                log::debug!("Subscribing to {} new script(s).", new_spks.len());
+                client.subscribe_scripts(&new_spks)?;
+                subscribed.extend(new_spks);
+            }
+            last_subscription = Some(time::Instant::now());
+        }
+
+        if client.pop_notifications(&subscribed)? {
+            log::debug!("Got notified by the Electrum server.");
+            notifier.notify();
+        }
+        thread::sleep(CHECK_INTERVAL);
+    }
+
+    Ok(())
+}
@@ -325,6 +325,67 @@ impl BdkWallet {
         let _ = self.graph.apply_update(graph_update);
     }
 
+    /// Apply the result of a sync with the Bitcoin backend, which was performed from our
+    /// `local_chain_tip`. If there was any reorg since the last sync, this returns the first common
+    /// ancestor between the previous and the new chain.
+    ///
+    /// The last seen of the unconfirmed transactions in the update is set to `sync_count`.
+    pub fn apply_sync_update(
+        &mut self,
+        local_chain_tip: &CheckPoint,
+        chain_update: CheckPoint,
+        mut graph_update: TxGraph<ConfirmationTimeHeightAnchor>,
+        keychain_update: Option<BTreeMap<KeychainType, u32>>,
+        sync_count: u64,
+    ) -> Option<BlockChainTip> {
+        log::debug!("Full local chain: {:?}", self.local_chain());
+        log::debug!("Full chain update: {:?}", chain_update);
+
+        if let Some(keychain_update) = keychain_update {
+            self.apply_keychain_update(keychain_update);
+        }
+        let changeset = self.apply_connected_chain_update(chain_update);
+
+        let mut changes_iter = changeset.into_iter();
+        let reorg_common_ancestor = if let Some((height, _)) = changes_iter.next() {
+            // Either a new block has been added at this height or an existing block in our local
+            // chain has been invalidated.
+            // Since we iterate in ascending height order, we'll see the lowest block height first.
+            // If the lowest height is higher than our height before syncing, we're good.
+            // Else if it's adding/invalidating a block at height before syncing or lower,
+            // it's a reorg.
+            if height > local_chain_tip.height() {
+                None
+            } else {
+                log::info!("Block chain reorganization detected.");
+                // We can assume height is positive as genesis block will not have changed.
+                Some(
+                    self.find_block_before_height(height)
+                        .expect("height of first change is greater than 0"),
+                )
+            }
+        } else {
+            None
+        };
+
+        // Unconfirmed transactions have their last seen as 0, so we override to the `sync_count`
+        // so that conflicts can be properly handled. We use `sync_count` instead of current time
+        // in seconds to ensure strictly increasing values between poller iterations.
+        for tx in &graph_update.initial_changeset().txs {
+            let txid = tx.compute_txid();
+            if let Some(ChainPosition::Unconfirmed(_)) = graph_update.get_chain_position(
+                self.local_chain(),
+                self.local_chain().tip().block_id(),
+                txid,
+            ) {
+                log::debug!("changing last seen for txid '{}' to {}", txid, sync_count);
+                let _ = graph_update.insert_seen_at(txid, sync_count);
+            }
+        }
+        self.apply_graph_update(graph_update);
+        reorg_common_ancestor
+    }
+
     /// Apply a keychain update.
     pub fn apply_keychain_update(&mut self, keychain_update: BTreeMap<KeychainType, u32>) {
         let _ = self.graph.index.reveal_to_target_multi(&keychain_update);
@@ -0,0 +1,705 @@
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryInto,
+    future::Future,
+    thread,
+};
+
+use bdk_esplora::{
+    bdk_chain::{
+        bitcoin,
+        spk_client::{FullScanRequest, FullScanResult, SyncRequest, SyncResult},
+    },
+    esplora_client::{self, AsyncClient, BlockingClient, Builder},
+    EsploraAsyncExt, EsploraExt,
+};
+
+use crate::{
+    bitcoin::{BlockChainTip, MempoolEntry, MempoolEntryFees},
+    config,
+};
+
+// Number of requests to the Esplora server we allow to run in parallel when syncing.
+const PARALLEL_REQUESTS: usize = 5;
+
+// If the Esplora server takes more than 3 minutes to answer one of our queries, fail.
+const HTTP_TIMEOUT: u64 = 180;
+
+/// An error in the Esplora client.
+#[derive(Debug)]
+pub enum Error {
+    Server(esplora_client::Error),
+    /// A transaction we need wasn't found on the server.
+    MissingTx(bitcoin::Txid),
+    /// The server returned a chain height which can't be valid.
+    InvalidHeight(u32),
+    /// The amounts of this transaction, or of the transactions related to it, as returned by the
+    /// server, overflow or create money.
+    InvalidAmounts(bitcoin::Txid),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Server(e) => write!(f, "Esplora error: '{e}'."),
+            Error::MissingTx(txid) => {
+                write!(f, "Esplora error: transaction '{txid}' not found.")
+            }
+            Error::InvalidHeight(height) => {
+                write!(f, "Esplora error: invalid chain height '{height}'.")
+            }
+            Error::InvalidAmounts(txid) => {
+                write!(
+                    f,
+                    "Esplora error: invalid amounts for transaction '{txid}'."
+                )
+            }
+        }
+    }
+}
+
+impl From<esplora_client::Error> for Error {
+    fn from(e: esplora_client::Error) -> Self {
+        Error::Server(e)
+    }
+}
+
+// The errors of the BDK extension are boxed.
+impl From<Box<esplora_client::Error>> for Error {
+    fn from(e: Box<esplora_client::Error>) -> Self {
+        Error::Server(*e)
+    }
+}
+
+// The blocking client doesn't support SOCKS5 proxies. When going through a proxy, we use the
+// async client (based on reqwest) and drive it from our own runtime.
+enum Inner {
+    Blocking(BlockingClient),
+    Proxied {
+        client: AsyncClient,
+        runtime: tokio::runtime::Runtime,
+    },
+}
+
+// Call this method with these arguments on the inner client, whichever it is.
+macro_rules! call {
+    ($self:ident, $method:ident($($arg:expr),*)) => {
+        match &$self.0 {
+            Inner::Blocking(client) => client.$method($($arg),*),
+            Inner::Proxied { client, runtime } => block_on(runtime, client.$method($($arg),*)),
+        }
+    };
+}
+
+// Run this future to completion on our runtime. The runtime can't be blocked on from within
+// another runtime (such as the GUI's, when the daemon is embedded), so it is driven from a
+// dedicated thread.
+fn block_on<F>(runtime: &tokio::runtime::Runtime, fut: F) -> F::Output
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    thread::scope(|s| {
+        s.spawn(|| runtime.block_on(fut))
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    })
+}
+
+pub struct Client(Inner);
+
+impl Client {
+    /// Create a new client and check the server is reachable. If a proxy is given and applies to
+    /// the server, all the requests go through it.
+    pub fn new(
+        esplora_config: &config::EsploraConfig,
+        proxy: Option<&config::ProxyConfig>,
+    ) -> Result<Self, Error> {
+        // The paths of the API are appended to the base URL.
+        let url = esplora_config.addr.trim_end_matches('/');
+        let builder = Builder::new(url).timeout(HTTP_TIMEOUT);
+        let inner = match proxy.filter(|p| p.applies_to_url(url)) {
+            Some(proxy) => {
+                // Resolve the hostname through the proxy, as needed for onion addresses.
+                let client = builder
+                    .proxy(&format!("socks5h://{}", proxy.addr))
+                    .build_async()?;
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .expect("Creating a runtime must not fail");
+                Inner::Proxied { client, runtime }
+            }
+            None => Inner::Blocking(builder.build_blocking()),
+        };
+        let client = Client(inner);
+        client.chain_tip()?;
+        Ok(client)
+    }
+
+    pub fn chain_tip(&self) -> Result<BlockChainTip, Error> {
+        let height = call!(self, get_height())?;
+        let hash = call!(self, get_block_hash(height))?;
+        Ok(BlockChainTip {
+            height: height
+                .try_into()
+                .map_err(|_| Error::InvalidHeight(height))?,
+            hash,
+        })
+    }
+
+    fn block_time(&self, hash: &bitcoin::BlockHash) -> Result<u32, Error> {
+        Ok(call!(self, get_header_by_hash(hash))?.time)
+    }
+
+    pub fn genesis_block(&self) -> Result<BlockChainTip, Error> {
+        Ok(BlockChainTip {
+            hash: call!(self, get_block_hash(0))?,
+            height: 0,
+        })
+    }
+
+    pub fn genesis_block_timestamp(&self) -> Result<u32, Error> {
+        self.block_time(&self.genesis_block()?.hash)
+    }
+
+    pub fn tip_time(&self) -> Result<u32, Error> {
+        self.block_time(&call!(self, get_tip_hash())?)
+    }
+
+    pub fn tip_median_time_past(&self) -> Result<u32, Error> {
+        let tip_height = self.chain_tip()?.height as u32;
+        let mut times = Vec::new();
+        for height in crate::bitcoin::median_time_span_start(tip_height)..=tip_height {
+            let hash = call!(self, get_block_hash(height))?;
+            times.push(self.block_time(&hash)?);
+        }
+        Ok(crate::bitcoin::median_time(times).expect("There is at least one block"))
+    }
+
+    pub fn broadcast_tx(&self, tx: &bitcoin::Transaction) -> Result<(), Error> {
+        Ok(call!(self, broadcast(tx))?)
+    }
+
+    /// Estimate the feerate in sats/vb for a transaction to confirm within this many blocks.
+    /// Returns `None` if the server doesn't have enough data to estimate it.
+    pub fn estimate_feerate(&self, conf_target: u16) -> Result<Option<u64>, Error> {
+        // Estimates are only given for some confirmation targets. Use the one for the largest
+        // target no larger than ours, or the smallest target available.
+        let estimates: Vec<(u16, f64)> = call!(self, get_fee_estimates())?
+            .into_iter()
+            .filter_map(|(target, sat_vb)| Some((target.parse().ok()?, sat_vb)))
+            .collect();
+        let feerate = estimates
+            .iter()
+            .filter(|(target, _)| *target <= conf_target)
+            .max_by_key(|(target, _)| *target)
+            .or_else(|| estimates.iter().min_by_key(|(target, _)| *target))
+            .map(|(_, sat_vb)| sat_vb.ceil() as u64);
+        Ok(feerate)
+    }
+
+    /// Perform the given `SyncRequest`.
+    pub fn sync(&self, request: SyncRequest) -> Result<SyncResult, Error> {
+        Ok(call!(self, sync(request, PARALLEL_REQUESTS))?)
+    }
+
+    /// Perform the given `FullScanRequest`.
+    pub fn full_scan<K: Ord + Clone + Send>(
+        &self,
+        request: FullScanRequest<K>,
+        stop_gap: usize,
+    ) -> Result<FullScanResult<K>, Error> {
+        Ok(call!(
+            self,
+            full_scan(request, stop_gap, PARALLEL_REQUESTS)
+        )?)
+    }
+
+    // Get a transaction from the server, going through the cache first.
+    fn cached_tx(
+        &self,
+        cache: &mut HashMap<bitcoin::Txid, bitcoin::Transaction>,
+        txid: &bitcoin::Txid,
+    ) -> Result<bitcoin::Transaction, Error> {
+        if let Some(tx) = cache.get(txid) {
+            return Ok(tx.clone());
+        }
+        let tx = call!(self, get_tx(txid))?.ok_or(Error::MissingTx(*txid))?;
+        cache.insert(*txid, tx.clone());
+        Ok(tx)
+    }
+
+    // The fee paid by this transaction.
+    fn tx_fee(
+        &self,
+        cache: &mut HashMap<bitcoin::Txid, bitcoin::Transaction>,
+        tx: &bitcoin::Transaction,
+    ) -> Result<bitcoin::Amount, Error> {
+        let txid = tx.compute_txid();
+        let mut in_value = bitcoin::Amount::ZERO;
+        for txin in &tx.input {
+            let prev_tx = self.cached_tx(cache, &txin.previous_output.txid)?;
+            let prev_vout: usize = txin
+                .previous_output
+                .vout
+                .try_into()
+                .expect("vout must fit in usize");
+            let prev_value = prev_tx
+                .output
+                .get(prev_vout)
+                .ok_or(Error::MissingTx(txin.previous_output.txid))?
+                .value;
+            in_value = in_value
+                .checked_add(prev_value)
+                .ok_or(Error::InvalidAmounts(txid))?;
+        }
+        let out_value = tx
+            .output
+            .iter()
+            .try_fold(bitcoin::Amount::ZERO, |acc, txo| acc.checked_add(txo.value))
+            .ok_or(Error::InvalidAmounts(txid))?;
+        in_value
+            .checked_sub(out_value)
+            .ok_or(Error::InvalidAmounts(txid))
+    }
+
+    /// Get mempool data for the given transaction. Returns `None` if the transaction is unknown
+    /// to the server or confirmed.
+    ///
+    /// Esplora doesn't expose the ancestors and descendants of mempool transactions so they are
+    /// found by walking the unconfirmed parents and spenders of the transaction.
+    pub fn mempool_entry(&self, txid: &bitcoin::Txid) -> Result<Option<MempoolEntry>, Error> {
+        log::debug!("Getting mempool entry for txid '{}'.", txid);
+        let mut cache = HashMap::new();
+        let tx = match call!(self, get_tx(txid))? {
+            Some(tx) => tx,
+            None => return Ok(None),
+        };
+        if call!(self, get_tx_status(txid))?.confirmed {
+            return Ok(None);
+        }
+        cache.insert(*txid, tx.clone());
+        let base_fee = self.tx_fee(&mut cache, &tx)?;
+        let base_size: u64 = tx.vsize().try_into().expect("tx size must fit into u64");
+
+        // Ancestor fees and size include those of `txid`.
+        let (mut anc_fees, mut anc_size) = (base_fee, base_size);
+        let mut visited = HashSet::new();
+        let mut anc_txids: Vec<_> = tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output.txid)
+            .collect();
+        while let Some(anc_txid) = anc_txids.pop() {
+            if !visited.insert(anc_txid) || call!(self, get_tx_status(&anc_txid))?.confirmed {
+                continue;
+            }
+            log::debug!("Getting fee and size for anc txid '{}'.", anc_txid);
+            let anc_tx = self.cached_tx(&mut cache, &anc_txid)?;
+            anc_fees = anc_fees
+                .checked_add(self.tx_fee(&mut cache, &anc_tx)?)
+                .ok_or(Error::InvalidAmounts(*txid))?;
+            anc_size += TryInto::<u64>::try_into(anc_tx.vsize()).expect("must fit into u64");
+            anc_txids.extend(anc_tx.input.iter().map(|txin| txin.previous_output.txid));
+        }
+
+        // Descendant fees include those of `txid`. As they are descendants of an unconfirmed
+        // transaction, we can assume they are all unconfirmed.
+        let mut desc_fees = base_fee;
+        let mut visited = HashSet::new();
+        let mut desc_txs = vec![tx.clone()];
+        while let Some(desc_tx) = desc_txs.pop() {
+            let desc_txid = desc_tx.compute_txid();
+            for vout in 0..desc_tx.output.len() {
+                let spender = call!(
+                    self,
+                    get_output_status(&desc_txid, vout.try_into().expect("must fit in u64"))
+                )?
+                .and_then(|status| status.txid);
+                if let Some(spender) = spender.filter(|txid| visited.insert(*txid)) {
+                    log::debug!("Getting fee for desc txid '{}'.", spender);
+                    let spender_tx = self.cached_tx(&mut cache, &spender)?;
+                    desc_fees = desc_fees
+                        .checked_add(self.tx_fee(&mut cache, &spender_tx)?)
+                        .ok_or(Error::InvalidAmounts(*txid))?;
+                    desc_txs.push(spender_tx);
+                }
+            }
+        }
+
+        Ok(Some(MempoolEntry {
+            vsize: base_size,
+            ancestor_vsize: anc_size,
+            fees: MempoolEntryFees {
+                base: base_fee,
+                ancestor: anc_fees,
+                descendant: desc_fees,
+            },
+        }))
+    }
+
+    /// Get an output of the UTXO set along with the height of the block it was confirmed in.
+    /// Returns `None` if it is unconfirmed or spent, including by a mempool transaction.
+    pub fn utxo(
+        &self,
+        outpoint: &bitcoin::OutPoint,
+    ) -> Result<Option<(bitcoin::TxOut, i32)>, Error> {
+        let txout = match call!(self, get_tx(&outpoint.txid))? {
+            Some(tx) => match tx.output.get(outpoint.vout as usize) {
+                Some(txout) => txout.clone(),
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        let status = call!(self, get_tx_status(&outpoint.txid))?;
+        let height = match status.block_height.filter(|_| status.confirmed) {
+            Some(height) => height,
+            None => return Ok(None),
+        };
+        let spent = call!(
+            self,
+            get_output_status(&outpoint.txid, outpoint.vout.into())
+        )?
+        .is_some_and(|status| status.spent);
+        if spent {
+            return Ok(None);
+        }
+        let height = height
+            .try_into()
+            .map_err(|_| Error::InvalidHeight(height))?;
+        Ok(Some((txout, height)))
+    }
+
+    /// Get mempool spenders of the given outpoints.
+    pub fn mempool_spenders(
+        &self,
+        outpoints: &[bitcoin::OutPoint],
+    ) -> Result<Vec<MempoolEntry>, Error> {
+        log::debug!("Getting mempool spenders for outpoints: {:?}.", outpoints);
+        let mut txids = HashSet::new();
+        for op in outpoints {
+            let status = call!(self, get_output_status(&op.txid, op.vout.into()))?;
+            if let Some(status) = status.filter(|s| s.spent) {
+                let confirmed = status.status.map(|s| s.confirmed).unwrap_or(false);
+                if let Some(txid) = status.txid.filter(|_| !confirmed) {
+                    txids.insert(txid);
+                }
+            }
+        }
+        let mut entries = Vec::with_capacity(txids.len());
+        for txid in txids {
+            if let Some(entry) = self.mempool_entry(&txid)? {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net, sync, thread,
+    };
+
+    use bitcoin::{
+        absolute, consensus, hashes::Hash, transaction, Amount, BlockHash, OutPoint, ScriptBuf,
+        Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+    };
+
+    // A minimal HTTP server standing in for an Esplora server. It answers requests from a fixed
+    // set of routes and records the body of the POST requests it receives.
+    struct EsploraStub {
+        url: String,
+        posted: sync::Arc<sync::Mutex<Vec<String>>>,
+    }
+
+    impl EsploraStub {
+        fn start(routes: HashMap<String, Vec<u8>>) -> Self {
+            let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let posted = sync::Arc::new(sync::Mutex::new(Vec::new()));
+            thread::spawn({
+                let posted = posted.clone();
+                move || {
+                    for stream in listener.incoming() {
+                        let mut stream = stream.unwrap();
+                        let mut reader = BufReader::new(stream.try_clone().unwrap());
+                        let mut request_line = String::new();
+                        reader.read_line(&mut request_line).unwrap();
+                        let mut content_length = 0;
+                        loop {
+                            let mut header = String::new();
+                            reader.read_line(&mut header).unwrap();
+                            if header.trim().is_empty() {
+                                break;
+                            }
+                            if let Some((name, value)) = header.split_once(':') {
+                                if name.eq_ignore_ascii_case("content-length") {
+                                    content_length = value.trim().parse().unwrap();
+                                }
+                            }
+                        }
+                        let mut body = vec![0; content_length];
+                        reader.read_exact(&mut body).unwrap();
+
+                        let mut parts = request_line.split_whitespace();
+                        let (method, path) = (parts.next().unwrap(), parts.next().unwrap());
+                        if method == "POST" {
+                            posted
+                                .lock()
+                                .unwrap()
+                                .push(String::from_utf8(body).unwrap());
+                        }
+                        let (status, body) = match routes.get(&format!("{method} {path}")) {
+                            Some(body) => ("200 OK", body.clone()),
+                            None => ("404 Not Found", b"Not found".to_vec()),
+                        };
+                        let header = format!(
+                            "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        );
+                        stream.write_all(header.as_bytes()).unwrap();
+                        stream.write_all(&body).unwrap();
+                    }
+                }
+            });
+            EsploraStub { url, posted }
+        }
+
+        fn client(&self) -> Client {
+            Client::new(
+                &config::EsploraConfig {
+                    addr: format!("{}/", self.url),
+                },
+                None,
+            )
+            .unwrap()
+        }
+    }
+
+    fn dummy_tx(prevout: OutPoint, value: u64) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: prevout,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    fn tx_routes(routes: &mut HashMap<String, Vec<u8>>, tx: &Transaction, status: &str) {
+        let txid = tx.compute_txid();
+        routes.insert(format!("GET /tx/{txid}/raw"), consensus::serialize(tx));
+        routes.insert(format!("GET /tx/{txid}/status"), status.as_bytes().to_vec());
+    }
+
+    #[test]
+    fn esplora_client_chain() {
+        let genesis_hash = BlockHash::from_byte_array([1; 32]);
+        let tip_hash = BlockHash::from_byte_array([2; 32]);
+        let mut routes = HashMap::new();
+        routes.insert("GET /blocks/tip/height".to_string(), b"100".to_vec());
+        routes.insert(
+            "GET /blocks/tip/hash".to_string(),
+            tip_hash.to_string().into(),
+        );
+        routes.insert(
+            "GET /block-height/0".to_string(),
+            genesis_hash.to_string().into(),
+        );
+        routes.insert(
+            "GET /block-height/100".to_string(),
+            tip_hash.to_string().into(),
+        );
+        routes.insert("POST /tx".to_string(), b"".to_vec());
+        let stub = EsploraStub::start(routes);
+        let client = stub.client();
+
+        assert_eq!(
+            client.chain_tip().unwrap(),
+            BlockChainTip {
+                height: 100,
+                hash: tip_hash
+            }
+        );
+        assert_eq!(
+            client.genesis_block().unwrap(),
+            BlockChainTip {
+                height: 0,
+                hash: genesis_hash
+            }
+        );
+
+        // Transactions are broadcast as hex.
+        let tx = dummy_tx(OutPoint::new(Txid::all_zeros(), 0), 1_000);
+        client.broadcast_tx(&tx).unwrap();
+        assert_eq!(
+            *stub.posted.lock().unwrap(),
+            vec![consensus::encode::serialize_hex(&tx)]
+        );
+
+        // We can't create a client for an unreachable server.
+        assert!(Client::new(
+            &config::EsploraConfig {
+                addr: "http://127.0.0.1:1".to_string(),
+            },
+            None
+        )
+        .is_err());
+
+        // A proxy is not used to connect to the local machine.
+        let proxy = config::ProxyConfig {
+            addr: "127.0.0.1:1".parse().unwrap(),
+        };
+        let esplora_config = config::EsploraConfig {
+            addr: stub.url.clone(),
+        };
+        assert!(Client::new(&esplora_config, Some(&proxy)).is_ok());
+        // But it is for remote servers, and we can't connect to this one through an unreachable
+        // proxy.
+        let esplora_config = config::EsploraConfig {
+            addr: "http://esplora.example.onion".to_string(),
+        };
+        assert!(Client::new(&esplora_config, Some(&proxy)).is_err());
+    }
+
+    #[test]
+    fn esplora_client_mempool() {
+        // A confirmed parent, spent by an unconfirmed child paying 1_000 sats of fees, itself
+        // spent by an unconfirmed grandchild paying 500 sats of fees.
+        let parent = dummy_tx(OutPoint::new(Txid::all_zeros(), 0), 100_000);
+        let parent_op = OutPoint::new(parent.compute_txid(), 0);
+        let child = dummy_tx(parent_op, 99_000);
+        let child_op = OutPoint::new(child.compute_txid(), 0);
+        let grandchild = dummy_tx(child_op, 98_500);
+        let grandchild_op = OutPoint::new(grandchild.compute_txid(), 0);
+
+        let mut routes = HashMap::new();
+        routes.insert("GET /blocks/tip/height".to_string(), b"100".to_vec());
+        routes.insert(
+            "GET /block-height/100".to_string(),
+            BlockHash::all_zeros().to_string().into(),
+        );
+        tx_routes(
+            &mut routes,
+            &parent,
+            r#"{"confirmed":true,"block_height":90,"block_time":1700000000}"#,
+        );
+        tx_routes(&mut routes, &child, r#"{"confirmed":false}"#);
+        tx_routes(&mut routes, &grandchild, r#"{"confirmed":false}"#);
+        // An unrelated confirmed transaction whose output is unspent.
+        let other = dummy_tx(OutPoint::new(Txid::all_zeros(), 1), 50_000);
+        let other_op = OutPoint::new(other.compute_txid(), 0);
+        tx_routes(
+            &mut routes,
+            &other,
+            r#"{"confirmed":true,"block_height":95,"block_time":1700000000}"#,
+        );
+        for (op, spender) in [
+            (parent_op, Some(child.compute_txid())),
+            (child_op, Some(grandchild.compute_txid())),
+            (grandchild_op, None),
+            (other_op, None),
+        ] {
+            let status = match spender {
+                Some(txid) => format!(
+                    r#"{{"spent":true,"txid":"{txid}","vin":0,"status":{{"confirmed":false}}}}"#
+                ),
+                None => r#"{"spent":false}"#.to_string(),
+            };
+            routes.insert(
+                format!("GET /tx/{}/outspend/{}", op.txid, op.vout),
+                status.into(),
+            );
+        }
+        let stub = EsploraStub::start(routes);
+        let client = stub.client();
+
+        // Confirmed and unknown transactions aren't in the mempool.
+        assert!(client
+            .mempool_entry(&parent.compute_txid())
+            .unwrap()
+            .is_none());
+        assert!(client.mempool_entry(&Txid::all_zeros()).unwrap().is_none());
+
+        // The parent being confirmed, the child has no ancestor but has a descendant.
+        let child_vsize = child.vsize() as u64;
+        let entry = client
+            .mempool_entry(&child.compute_txid())
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.vsize, child_vsize);
+        assert_eq!(entry.ancestor_vsize, child_vsize);
+        assert_eq!(entry.fees.base, Amount::from_sat(1_000));
+        assert_eq!(entry.fees.ancestor, Amount::from_sat(1_000));
+        assert_eq!(entry.fees.descendant, Amount::from_sat(1_500));
+
+        // The grandchild has the child as ancestor.
+        let entry = client
+            .mempool_entry(&grandchild.compute_txid())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            entry.ancestor_vsize,
+            child_vsize + grandchild.vsize() as u64
+        );
+        assert_eq!(entry.fees.base, Amount::from_sat(500));
+        assert_eq!(entry.fees.ancestor, Amount::from_sat(1_500));
+        assert_eq!(entry.fees.descendant, Amount::from_sat(500));
+
+        // The child is the only mempool spender of the parent's output.
+        let spenders = client
+            .mempool_spenders(&[parent_op, grandchild_op])
+            .unwrap();
+        assert_eq!(spenders.len(), 1);
+        assert_eq!(spenders[0].fees.base, Amount::from_sat(1_000));
+
+        // Only the confirmed output which isn't spent, even by a mempool transaction, is part of
+        // the UTXO set.
+        assert_eq!(
+            client.utxo(&other_op).unwrap(),
+            Some((other.output[0].clone(), 95))
+        );
+        assert!(client.utxo(&parent_op).unwrap().is_none());
+        assert!(client.utxo(&grandchild_op).unwrap().is_none());
+        assert!(client
+            .utxo(&OutPoint::new(other.compute_txid(), 1))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn esplora_client_invalid_amounts() {
+        // An unconfirmed transaction which, according to the server, spends more than its input.
+        let parent = dummy_tx(OutPoint::new(Txid::all_zeros(), 0), 1_000);
+        let child = dummy_tx(OutPoint::new(parent.compute_txid(), 0), 2_000);
+        let mut routes = HashMap::new();
+        routes.insert("GET /blocks/tip/height".to_string(), b"100".to_vec());
+        routes.insert(
+            "GET /block-height/100".to_string(),
+            BlockHash::all_zeros().to_string().into(),
+        );
+        tx_routes(&mut routes, &parent, r#"{"confirmed":false}"#);
+        tx_routes(&mut routes, &child, r#"{"confirmed":false}"#);
+        let stub = EsploraStub::start(routes);
+        let client = stub.client();
+
+        let child_txid = child.compute_txid();
+        assert!(matches!(
+            client.mempool_entry(&child_txid),
+            Err(Error::InvalidAmounts(txid)) if txid == child_txid
+        ));
+    }
+}
@@ -0,0 +1,205 @@
+use std::{collections::HashMap, sync};
+
+use bdk_esplora::bdk_chain::{
+    bitcoin::{self, bip32::ChildNumber, BlockHash, OutPoint},
+    local_chain::LocalChain,
+    spk_client::{FullScanRequest, SyncRequest},
+};
+
+pub mod client;
+use crate::bitcoin::{
+    electrum::{utils::tip_from_block_id, wallet},
+    Block, BlockChainTip, Coin,
+};
+
+/// An error in the Esplora interface.
+#[derive(Debug)]
+pub enum EsploraError {
+    Client(client::Error),
+    GenesisHashMismatch(
+        BlockHash, /*expected hash*/
+        BlockHash, /*server hash*/
+        BlockHash, /*wallet hash*/
+    ),
+}
+
+impl std::fmt::Display for EsploraError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EsploraError::Client(e) => write!(f, "Esplora client error: '{e}'."),
+            EsploraError::GenesisHashMismatch(expected, server, wallet) => {
+                write!(
+                    f,
+                    "Genesis hash mismatch. The genesis hash is expected to be '{expected}'. \
+                    The server has hash '{server}' and the wallet has hash '{wallet}'.",
+                )
+            }
+        }
+    }
+}
+
+/// Interface for an Esplora backend.
+///
+/// Like for Electrum, the wallet's transactions are tracked in a BDK-based wallet which is synced
+/// against the server at every poll.
+pub struct Esplora {
+    /// May be shared with the interfaces of other wallets.
+    client: sync::Arc<client::Client>,
+    bdk_wallet: wallet::BdkWallet,
+    /// Used for setting the `last_seen` of unconfirmed transactions in a strictly
+    /// increasing manner.
+    sync_count: u64,
+    /// Set to `true` to force a full scan from the genesis block regardless of
+    /// the wallet's local chain height.
+    full_scan: bool,
+}
+
+impl Esplora {
+    pub fn new(
+        client: sync::Arc<client::Client>,
+        bdk_wallet: wallet::BdkWallet,
+        full_scan: bool,
+    ) -> Self {
+        Self {
+            client,
+            bdk_wallet,
+            sync_count: 0,
+            full_scan,
+        }
+    }
+
+    pub fn sanity_checks(&self, expected_hash: &bitcoin::BlockHash) -> Result<(), EsploraError> {
+        let server_hash = self
+            .client
+            .genesis_block()
+            .map_err(EsploraError::Client)?
+            .hash;
+        let wallet_hash = self.bdk_wallet.local_chain().genesis_hash();
+        if server_hash != *expected_hash || wallet_hash != *expected_hash {
+            return Err(EsploraError::GenesisHashMismatch(
+                *expected_hash,
+                server_hash,
+                wallet_hash,
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn client(&self) -> &client::Client {
+        &self.client
+    }
+
+    /// The connection to the server, to be shared with the interfaces of other wallets.
+    pub fn connection(&self) -> sync::Arc<client::Client> {
+        self.client.clone()
+    }
+
+    fn local_chain(&self) -> &LocalChain {
+        self.bdk_wallet.local_chain()
+    }
+
+    /// Get all coins stored in the wallet, taking into consideration only those unconfirmed
+    /// transactions that were seen in the last wallet sync.
+    pub fn wallet_coins(&self, outpoints: Option<&[OutPoint]>) -> HashMap<OutPoint, Coin> {
+        self.bdk_wallet.coins(outpoints, Some(self.sync_count))
+    }
+
+    /// Get the tip of the wallet's local chain.
+    pub fn wallet_tip(&self) -> BlockChainTip {
+        tip_from_block_id(self.local_chain().tip().block_id())
+    }
+
+    /// Whether `tip` exists in the wallet's `local_chain`.
+    ///
+    /// Returns `None` if no block at that height exists in `local_chain`.
+    pub fn is_in_wallet_chain(&self, tip: BlockChainTip) -> Option<bool> {
+        self.bdk_wallet.is_in_chain(tip)
+    }
+
+    /// Whether we'll perform a full scan at the next poll.
+    pub fn is_rescanning(&self) -> bool {
+        self.full_scan || self.local_chain().tip().height() == 0
+    }
+
+    /// Make the poller perform a full scan on the next iteration.
+    pub fn trigger_rescan(&mut self) {
+        self.full_scan = true;
+    }
+
+    /// Sync the wallet with the Esplora server. If there was any reorg since the last poll, this
+    /// returns the first common ancestor between the previous and the new chain.
+    pub fn sync_wallet(
+        &mut self,
+        receive_index: ChildNumber,
+        change_index: ChildNumber,
+    ) -> Result<Option<BlockChainTip>, EsploraError> {
+        self.bdk_wallet.reveal_spks(receive_index, change_index);
+        let local_chain_tip = self.local_chain().tip();
+        log::debug!(
+            "local chain tip height before sync with esplora: {}",
+            local_chain_tip.block_id().height
+        );
+
+        // Each SPK is a separate request, so use the same stop gap as for Electrum.
+        const STOP_GAP: usize = 200;
+
+        let (chain_update, graph_update, keychain_update) = if !self.is_rescanning() {
+            log::debug!("Performing sync.");
+            let all_spks: Vec<_> = self
+                .bdk_wallet
+                .index()
+                .inner() // we include lookahead SPKs
+                .all_spks()
+                .values()
+                .cloned()
+                .collect();
+            let request = SyncRequest::from_chain_tip(local_chain_tip.clone()).chain_spks(all_spks);
+            log::debug!("num SPKs for sync: {}", request.spks.len());
+
+            let sync_result = self.client.sync(request).map_err(EsploraError::Client)?;
+            log::debug!("Sync complete.");
+            (sync_result.chain_update, sync_result.graph_update, None)
+        } else {
+            log::info!("Performing full scan.");
+            // Either local_chain has height 0 or we want to trigger a full scan.
+            let mut request = FullScanRequest::from_chain_tip(local_chain_tip.clone());
+            for (k, spks) in self.bdk_wallet.index().all_unbounded_spk_iters() {
+                request = request.set_spks_for_keychain(k, spks);
+            }
+            let scan_result = self
+                .client
+                .full_scan(request, STOP_GAP)
+                .map_err(EsploraError::Client)?;
+            // A full scan only makes sense to do once, in most cases. Don't do it again unless
+            // explicitly asked to by a user.
+            self.full_scan = false;
+            log::info!("Full scan complete.");
+            (
+                scan_result.chain_update,
+                scan_result.graph_update,
+                Some(scan_result.last_active_indices),
+            )
+        };
+        log::debug!(
+            "chain update height after sync with esplora: {}",
+            chain_update.height()
+        );
+
+        // Increment the sync count and apply changes.
+        self.sync_count = self.sync_count.checked_add(1).expect("must fit");
+        Ok(self.bdk_wallet.apply_sync_update(
+            &local_chain_tip,
+            chain_update,
+            graph_update,
+            keychain_update,
+            self.sync_count,
+        ))
+    }
+
+    pub fn wallet_transaction(
+        &self,
+        txid: &bitcoin::Txid,
+    ) -> Option<(bitcoin::Transaction, Option<Block>)> {
+        self.bdk_wallet.get_transaction(txid)
+    }
+}
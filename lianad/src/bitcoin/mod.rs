@@ -2,15 +2,17 @@
 //!
 //! Broadcast transactions, poll for new unspent coins, gather fee estimates.
 
+pub mod cbf;
 pub mod d;
 pub mod electrum;
+pub mod esplora;
 pub mod poller;
 
 use crate::bitcoin::d::{BitcoindError, CachedTxGetter, LSBlockEntry};
 pub use d::{MempoolEntry, MempoolEntryFees, SyncProgress};
 use liana::descriptors;
 
-use std::{fmt, sync};
+use std::{collections::HashMap, fmt, sync};
 
 use miniscript::bitcoin::{self, address, bip32::ChildNumber};
 
@@ -19,6 +21,20 @@ type SpentCoin = (bitcoin::OutPoint, bitcoin::Txid, i32, u32);
 
 const COINBASE_MATURITY: i32 = 100;
 
+// The number of blocks whose timestamps the median time past is computed over (BIP113).
+const MEDIAN_TIME_SPAN: u32 = 11;
+
+// The height of the first block whose timestamp is part of the median time past of this tip.
+fn median_time_span_start(tip_height: u32) -> u32 {
+    tip_height.saturating_sub(MEDIAN_TIME_SPAN - 1)
+}
+
+// The median of these block timestamps, as used for the median time past.
+fn median_time(mut times: Vec<u32>) -> Option<u32> {
+    times.sort_unstable();
+    times.get(times.len() / 2).copied()
+}
+
 /// Information about a block
 #[derive(Debug, Clone, Eq, PartialEq, Copy)]
 pub struct Block {
@@ -40,6 +56,17 @@ impl fmt::Display for BlockChainTip {
     }
 }
 
+/// The connection to a Bitcoin backend, which can be shared by the interfaces of several wallets.
+#[derive(Clone)]
+pub enum BackendConnection {
+    /// The client for generalistic calls to bitcoind.
+    Bitcoind(sync::Arc<jsonrpc::client::Client>),
+    /// The client connected to the Electrum server at this index in the configuration.
+    Electrum(usize, sync::Arc<electrum::client::Client>),
+    Esplora(sync::Arc<esplora::client::Client>),
+    Cbf(cbf::SharedPeer, cbf::SharedHeaders),
+}
+
 /// Our Bitcoin backend.
 pub trait BitcoinInterface: Send {
     fn genesis_block_timestamp(&self) -> u32;
@@ -57,6 +84,10 @@ pub trait BitcoinInterface: Send {
     /// Get the timestamp set in the best block's header.
     fn tip_time(&self) -> Option<u32>;
 
+    /// Get the median time past of the best block, the median of the timestamps of the last 11
+    /// blocks. Time-based locktimes are checked against it, not the tip's timestamp (BIP113).
+    fn tip_median_time_past(&self) -> Option<u32>;
+
     /// Check whether this former tip is part of the current best chain.
     fn is_in_chain(&self, tip: &BlockChainTip) -> bool;
 
@@ -105,6 +136,13 @@ pub trait BitcoinInterface: Send {
     /// Broadcast this transaction to the Bitcoin P2P network
     fn broadcast_tx(&self, tx: &bitcoin::Transaction) -> Result<(), String>;
 
+    /// Broadcast these transactions together, as a package, to the Bitcoin P2P network. The
+    /// package must be a child along with its unconfirmed parents, parents first. This allows a
+    /// parent whose feerate is too low to be relayed on its own to be paid for by its child.
+    fn broadcast_package(&self, _txs: &[bitcoin::Transaction]) -> Result<(), String> {
+        Err("Package broadcast is not supported by this Bitcoin backend.".to_string())
+    }
+
     /// Trigger a rescan of the block chain for transactions related to this descriptor since
     /// the given date.
     fn start_rescan(
@@ -133,6 +171,34 @@ pub trait BitcoinInterface: Send {
     ///
     /// Returns `None` if the transaction is not in the mempool.
     fn mempool_entry(&self, txid: &bitcoin::Txid) -> Option<MempoolEntry>;
+
+    /// Get an output of the UTXO set at the current tip, along with the height of the block it
+    /// was confirmed in.
+    ///
+    /// Returns `None` if the output is unconfirmed or spent, including by an unconfirmed
+    /// transaction. Errors if the backend can't look up the UTXO set.
+    fn utxo(&self, _outpoint: &bitcoin::OutPoint) -> Result<Option<(bitcoin::TxOut, i32)>, String> {
+        Err("The backend doesn't give access to the UTXO set.".to_string())
+    }
+
+    /// Get the health of the Electrum servers we may use, if the backend is Electrum.
+    fn electrum_servers(&self) -> Vec<electrum::ServerStatus> {
+        Vec::new()
+    }
+
+    /// Estimate the feerate, in sats/vb, for a transaction to confirm within this many blocks.
+    ///
+    /// Returns `None` if the backend can't estimate it.
+    fn estimate_feerate(&self, _conf_target: u16) -> Option<u64> {
+        None
+    }
+
+    /// Get the connection to the backend, for the interfaces of other wallets to share it.
+    ///
+    /// Returns `None` if it can't be shared.
+    fn connection(&self) -> Option<BackendConnection> {
+        None
+    }
 }
 
 impl BitcoinInterface for d::BitcoinD {
@@ -362,6 +428,19 @@ impl BitcoinInterface for d::BitcoinD {
         }
     }
 
+    fn broadcast_package(&self, txs: &[bitcoin::Transaction]) -> Result<(), String> {
+        match self.broadcast_package(txs) {
+            Ok(()) => Ok(()),
+            Err(e @ BitcoindError::PackageRejected(_)) => Err(e.to_string()),
+            Err(BitcoindError::Server(e)) => Err(e.to_string()),
+            // We assume the Bitcoin backend doesn't fail, so it must be a JSONRPC error.
+            Err(e) => panic!(
+                "Unexpected Bitcoin error when broadcasting package: '{}'.",
+                e
+            ),
+        }
+    }
+
     fn start_rescan(
         &mut self,
         desc: &descriptors::LianaDescriptor,
@@ -385,6 +464,11 @@ impl BitcoinInterface for d::BitcoinD {
         Some(self.get_block_stats(tip.hash)?.time)
     }
 
+    fn tip_median_time_past(&self) -> Option<u32> {
+        let tip = self.chain_tip();
+        Some(self.get_block_stats(tip.hash)?.median_time_past)
+    }
+
     fn wallet_transaction(
         &self,
         txid: &bitcoin::Txid,
@@ -402,6 +486,18 @@ impl BitcoinInterface for d::BitcoinD {
     fn mempool_entry(&self, txid: &bitcoin::Txid) -> Option<MempoolEntry> {
         self.mempool_entry(txid)
     }
+
+    fn utxo(&self, outpoint: &bitcoin::OutPoint) -> Result<Option<(bitcoin::TxOut, i32)>, String> {
+        Ok(self.utxo(outpoint))
+    }
+
+    fn estimate_feerate(&self, conf_target: u16) -> Option<u64> {
+        self.estimate_feerate(conf_target)
+    }
+
+    fn connection(&self) -> Option<BackendConnection> {
+        Some(BackendConnection::Bitcoind(self.node_client()))
+    }
 }
 
 impl BitcoinInterface for electrum::Electrum {
@@ -419,70 +515,21 @@ impl BitcoinInterface for electrum::Electrum {
         tip: &BlockChainTip,
         _descs: &[descriptors::SinglePathLianaDesc],
     ) -> Vec<UTxO> {
-        // Get those wallet coins that are either unconfirmed or have a confirmation height
-        // after tip. The poller will then discard any that had already been received.
-        self.wallet_coins(None)
-            .values()
-            .filter_map(|c| {
-                let height = c.block_info.map(|info| info.height);
-                if height.filter(|h| *h <= tip.height).is_some() {
-                    None
-                } else {
-                    Some(UTxO {
-                        outpoint: c.outpoint,
-                        block_height: height,
-                        amount: c.amount,
-                        address: UTxOAddress::DerivIndex(c.derivation_index, c.is_change),
-                        is_immature: c.is_immature,
-                    })
-                }
-            })
-            .collect()
+        bdk_received_coins(&self.wallet_coins(None), tip)
     }
 
     fn confirmed_coins(
         &self,
         outpoints: &[bitcoin::OutPoint],
     ) -> (Vec<(bitcoin::OutPoint, i32, u32)>, Vec<bitcoin::OutPoint>) {
-        let wallet_coins = &self.wallet_coins(Some(outpoints));
-        let mut confirmed = Vec::new();
-        let mut expired = Vec::new();
-        for op in outpoints {
-            if let Some(w_c) = wallet_coins.get(op) {
-                if let Some(block) = w_c.block_info {
-                    if w_c.is_immature {
-                        log::debug!(
-                            "Coin at '{}' comes from an immature coinbase transaction at \
-                            block height {}. Not marking it as confirmed for now.",
-                            op,
-                            block.height
-                        );
-                        continue;
-                    }
-                    confirmed.push((w_c.outpoint, block.height, block.time));
-                }
-            } else {
-                expired.push(*op);
-            }
-        }
-        (confirmed, expired)
+        bdk_confirmed_coins(&self.wallet_coins(Some(outpoints)), outpoints)
     }
 
     fn spending_coins(
         &self,
         outpoints: &[bitcoin::OutPoint],
     ) -> Vec<(bitcoin::OutPoint, bitcoin::Txid)> {
-        let wallet_coins = &self.wallet_coins(Some(outpoints));
-        outpoints
-            .iter()
-            .filter_map(|op| {
-                if let Some(w_c) = wallet_coins.get(op) {
-                    w_c.spend_txid.map(|txid| (w_c.outpoint, txid))
-                } else {
-                    None
-                }
-            })
-            .collect()
+        bdk_spending_coins(&self.wallet_coins(Some(outpoints)), outpoints)
     }
 
     fn spent_coins(
@@ -490,21 +537,7 @@ impl BitcoinInterface for electrum::Electrum {
         outpoints: &[(bitcoin::OutPoint, bitcoin::Txid)],
     ) -> (Vec<SpentCoin>, Vec<bitcoin::OutPoint>) {
         let ops: Vec<_> = outpoints.iter().map(|(op, _)| op).copied().collect();
-        let wallet_coins = &self.wallet_coins(Some(&ops));
-        let mut spent = Vec::new();
-        let mut expired_spending = Vec::new();
-
-        for (op, spend_txid) in outpoints {
-            if let Some(w_c) = wallet_coins.get(op) {
-                if w_c.spend_txid != Some(*spend_txid) {
-                    expired_spending.push(*op);
-                }
-                if let Some(block) = w_c.spend_block {
-                    spent.push((*op, *spend_txid, block.height, block.time));
-                }
-            }
-        }
-        (spent, expired_spending)
+        bdk_spent_coins(&self.wallet_coins(Some(&ops)), outpoints)
     }
 
     fn genesis_block_timestamp(&self) -> u32 {
@@ -537,10 +570,7 @@ impl BitcoinInterface for electrum::Electrum {
     }
 
     fn broadcast_tx(&self, tx: &bitcoin::Transaction) -> Result<(), String> {
-        match self.client().broadcast_tx(tx) {
-            Ok(_txid) => Ok(()),
-            Err(e) => Err(e.to_string()),
-        }
+        self.broadcast_tx(tx).map_err(|e| e.to_string())
     }
 
     fn wallet_transaction(
@@ -560,6 +590,10 @@ impl BitcoinInterface for electrum::Electrum {
             .unwrap_or_default()
     }
 
+    fn utxo(&self, outpoint: &bitcoin::OutPoint) -> Result<Option<(bitcoin::TxOut, i32)>, String> {
+        self.client().utxo(outpoint).map_err(|e| e.to_string())
+    }
+
     fn sync_progress(&self) -> SyncProgress {
         // Always return 100% for now since the API is bitcoind-specific to mean "blocks/headers".
         // But in the future it would be nice to inform the user about the progress of the sync
@@ -589,6 +623,296 @@ impl BitcoinInterface for electrum::Electrum {
     fn tip_time(&self) -> Option<u32> {
         self.client().tip_time().ok()
     }
+
+    fn tip_median_time_past(&self) -> Option<u32> {
+        self.client().tip_median_time_past().ok()
+    }
+
+    fn electrum_servers(&self) -> Vec<electrum::ServerStatus> {
+        self.servers()
+    }
+
+    fn estimate_feerate(&self, conf_target: u16) -> Option<u64> {
+        self.client().estimate_feerate(conf_target).ok()?
+    }
+
+    fn connection(&self) -> Option<BackendConnection> {
+        let (active, client) = self.connection();
+        Some(BackendConnection::Electrum(active, client))
+    }
+}
+
+impl BitcoinInterface for esplora::Esplora {
+    fn sync_wallet(
+        &mut self,
+        receive_index: ChildNumber,
+        change_index: ChildNumber,
+    ) -> Result<Option<BlockChainTip>, String> {
+        self.sync_wallet(receive_index, change_index)
+            .map_err(|e| e.to_string())
+    }
+
+    fn received_coins(
+        &self,
+        tip: &BlockChainTip,
+        _descs: &[descriptors::SinglePathLianaDesc],
+    ) -> Vec<UTxO> {
+        bdk_received_coins(&self.wallet_coins(None), tip)
+    }
+
+    fn confirmed_coins(
+        &self,
+        outpoints: &[bitcoin::OutPoint],
+    ) -> (Vec<(bitcoin::OutPoint, i32, u32)>, Vec<bitcoin::OutPoint>) {
+        bdk_confirmed_coins(&self.wallet_coins(Some(outpoints)), outpoints)
+    }
+
+    fn spending_coins(
+        &self,
+        outpoints: &[bitcoin::OutPoint],
+    ) -> Vec<(bitcoin::OutPoint, bitcoin::Txid)> {
+        bdk_spending_coins(&self.wallet_coins(Some(outpoints)), outpoints)
+    }
+
+    fn spent_coins(
+        &self,
+        outpoints: &[(bitcoin::OutPoint, bitcoin::Txid)],
+    ) -> (Vec<SpentCoin>, Vec<bitcoin::OutPoint>) {
+        let ops: Vec<_> = outpoints.iter().map(|(op, _)| op).copied().collect();
+        bdk_spent_coins(&self.wallet_coins(Some(&ops)), outpoints)
+    }
+
+    fn genesis_block_timestamp(&self) -> u32 {
+        self.client()
+            .genesis_block_timestamp()
+            .expect("Genesis block timestamp must always be there")
+    }
+
+    fn genesis_block(&self) -> BlockChainTip {
+        self.client()
+            .genesis_block()
+            .expect("Genesis block must always be there")
+    }
+
+    fn chain_tip(&self) -> BlockChainTip {
+        // We want the wallet's local chain tip after syncing.
+        self.wallet_tip()
+    }
+
+    fn is_in_chain(&self, tip: &BlockChainTip) -> bool {
+        // Return `false` if no block at same height as `tip`
+        // is in wallet's local chain.
+        self.is_in_wallet_chain(*tip).unwrap_or_default()
+    }
+
+    fn common_ancestor(&self, _tip: &BlockChainTip) -> Option<BlockChainTip> {
+        unreachable!("The common ancestor is returned in `sync_wallet()`. If no reorg was detected then, this method will never be called on an Esplora backend.")
+    }
+
+    fn broadcast_tx(&self, tx: &bitcoin::Transaction) -> Result<(), String> {
+        self.client().broadcast_tx(tx).map_err(|e| e.to_string())
+    }
+
+    fn wallet_transaction(
+        &self,
+        txid: &bitcoin::Txid,
+    ) -> Option<(bitcoin::Transaction, Option<Block>)> {
+        self.wallet_transaction(txid)
+    }
+
+    fn mempool_entry(&self, txid: &bitcoin::Txid) -> Option<MempoolEntry> {
+        self.client().mempool_entry(txid).ok()?
+    }
+
+    fn mempool_spenders(&self, outpoints: &[bitcoin::OutPoint]) -> Vec<MempoolEntry> {
+        self.client()
+            .mempool_spenders(outpoints)
+            .unwrap_or_default()
+    }
+
+    fn utxo(&self, outpoint: &bitcoin::OutPoint) -> Result<Option<(bitcoin::TxOut, i32)>, String> {
+        self.client().utxo(outpoint).map_err(|e| e.to_string())
+    }
+
+    fn sync_progress(&self) -> SyncProgress {
+        // As for Electrum, the server is always synced from our point of view.
+        let blocks = self.chain_tip().height as u64;
+        SyncProgress::new(1.0, blocks, blocks)
+    }
+
+    fn estimate_feerate(&self, conf_target: u16) -> Option<u64> {
+        self.client().estimate_feerate(conf_target).ok()?
+    }
+
+    fn start_rescan(
+        &mut self,
+        _desc: &descriptors::LianaDescriptor,
+        _timestamp: u32,
+    ) -> Result<(), String> {
+        self.trigger_rescan();
+        Ok(())
+    }
+
+    fn rescan_progress(&self) -> Option<f64> {
+        // Until we sync we're at 0%. After the sync, we're at 100%.
+        self.is_rescanning().then_some(0.0)
+    }
+
+    fn block_before_date(&self, _timestamp: u32) -> Option<BlockChainTip> {
+        // A rescan is a full scan of the wallet's history, so we need to go through all of it
+        // again regardless of the date.
+        Some(self.genesis_block())
+    }
+
+    fn tip_time(&self) -> Option<u32> {
+        self.client().tip_time().ok()
+    }
+
+    fn tip_median_time_past(&self) -> Option<u32> {
+        self.client().tip_median_time_past().ok()
+    }
+
+    fn connection(&self) -> Option<BackendConnection> {
+        Some(BackendConnection::Esplora(self.connection()))
+    }
+}
+
+impl BitcoinInterface for cbf::Cbf {
+    fn sync_wallet(
+        &mut self,
+        receive_index: ChildNumber,
+        change_index: ChildNumber,
+    ) -> Result<Option<BlockChainTip>, String> {
+        self.sync_wallet(receive_index, change_index)
+            .map_err(|e| e.to_string())
+    }
+
+    fn received_coins(
+        &self,
+        tip: &BlockChainTip,
+        _descs: &[descriptors::SinglePathLianaDesc],
+    ) -> Vec<UTxO> {
+        bdk_received_coins(&self.wallet_coins(None), tip)
+    }
+
+    fn confirmed_coins(
+        &self,
+        outpoints: &[bitcoin::OutPoint],
+    ) -> (Vec<(bitcoin::OutPoint, i32, u32)>, Vec<bitcoin::OutPoint>) {
+        bdk_confirmed_coins(&self.wallet_coins(Some(outpoints)), outpoints)
+    }
+
+    fn spending_coins(
+        &self,
+        outpoints: &[bitcoin::OutPoint],
+    ) -> Vec<(bitcoin::OutPoint, bitcoin::Txid)> {
+        bdk_spending_coins(&self.wallet_coins(Some(outpoints)), outpoints)
+    }
+
+    fn spent_coins(
+        &self,
+        outpoints: &[(bitcoin::OutPoint, bitcoin::Txid)],
+    ) -> (Vec<SpentCoin>, Vec<bitcoin::OutPoint>) {
+        let ops: Vec<_> = outpoints.iter().map(|(op, _)| op).copied().collect();
+        bdk_spent_coins(&self.wallet_coins(Some(&ops)), outpoints)
+    }
+
+    fn genesis_block_timestamp(&self) -> u32 {
+        self.headers()
+            .header_at(0)
+            .expect("Genesis block must always be there")
+            .time
+    }
+
+    fn genesis_block(&self) -> BlockChainTip {
+        self.headers()
+            .block_at(0)
+            .expect("Genesis block must always be there")
+    }
+
+    fn chain_tip(&self) -> BlockChainTip {
+        // The wallet's local chain only advances once the filters were scanned.
+        self.wallet_tip()
+    }
+
+    fn is_in_chain(&self, tip: &BlockChainTip) -> bool {
+        // Return `false` if no block at same height as `tip`
+        // is in wallet's local chain.
+        self.is_in_wallet_chain(*tip).unwrap_or_default()
+    }
+
+    fn common_ancestor(&self, _tip: &BlockChainTip) -> Option<BlockChainTip> {
+        unreachable!("The common ancestor is returned in `sync_wallet()`. If no reorg was detected then, this method will never be called on a compact block filters backend.")
+    }
+
+    fn broadcast_tx(&self, tx: &bitcoin::Transaction) -> Result<(), String> {
+        self.broadcast_tx(tx).map_err(|e| e.to_string())
+    }
+
+    fn wallet_transaction(
+        &self,
+        txid: &bitcoin::Txid,
+    ) -> Option<(bitcoin::Transaction, Option<Block>)> {
+        self.wallet_transaction(txid)
+    }
+
+    fn mempool_entry(&self, _txid: &bitcoin::Txid) -> Option<MempoolEntry> {
+        // We don't have access to our peer's mempool.
+        None
+    }
+
+    fn mempool_spenders(&self, _outpoints: &[bitcoin::OutPoint]) -> Vec<MempoolEntry> {
+        Vec::new()
+    }
+
+    fn sync_progress(&self) -> SyncProgress {
+        let blocks = self.chain_tip().height as u64;
+        let headers = std::cmp::max(self.best_height() as u64, blocks);
+        let progress = if headers == 0 {
+            1.0
+        } else {
+            blocks as f64 / headers as f64
+        };
+        SyncProgress::new(progress, headers, blocks)
+    }
+
+    fn start_rescan(
+        &mut self,
+        _desc: &descriptors::LianaDescriptor,
+        timestamp: u32,
+    ) -> Result<(), String> {
+        self.trigger_rescan(timestamp);
+        Ok(())
+    }
+
+    fn rescan_progress(&self) -> Option<f64> {
+        // The filters are scanned at once during the next sync.
+        self.is_rescanning().then_some(0.0)
+    }
+
+    fn block_before_date(&self, timestamp: u32) -> Option<BlockChainTip> {
+        let headers = self.headers();
+        headers.block_at(headers.height_before_date(timestamp))
+    }
+
+    fn tip_time(&self) -> Option<u32> {
+        let headers = self.headers();
+        headers.header_at(headers.height()).map(|h| h.time)
+    }
+
+    fn tip_median_time_past(&self) -> Option<u32> {
+        let headers = self.headers();
+        let tip_height = headers.height();
+        let times = (median_time_span_start(tip_height)..=tip_height)
+            .map(|h| headers.header_at(h).map(|header| header.time))
+            .collect::<Option<Vec<_>>>()?;
+        median_time(times)
+    }
+
+    fn connection(&self) -> Option<BackendConnection> {
+        let (peer, headers) = self.connection();
+        Some(BackendConnection::Cbf(peer, headers))
+    }
 }
 
 // FIXME: do we need to repeat the entire trait implementation? Isn't there a nicer way?
@@ -660,6 +984,10 @@ impl BitcoinInterface for sync::Arc<sync::Mutex<dyn BitcoinInterface + 'static>>
         self.lock().unwrap().broadcast_tx(tx)
     }
 
+    fn broadcast_package(&self, txs: &[bitcoin::Transaction]) -> Result<(), String> {
+        self.lock().unwrap().broadcast_package(txs)
+    }
+
     fn start_rescan(
         &mut self,
         desc: &descriptors::LianaDescriptor,
@@ -680,6 +1008,10 @@ impl BitcoinInterface for sync::Arc<sync::Mutex<dyn BitcoinInterface + 'static>>
         self.lock().unwrap().tip_time()
     }
 
+    fn tip_median_time_past(&self) -> Option<u32> {
+        self.lock().unwrap().tip_median_time_past()
+    }
+
     fn wallet_transaction(
         &self,
         txid: &bitcoin::Txid,
@@ -694,6 +1026,113 @@ impl BitcoinInterface for sync::Arc<sync::Mutex<dyn BitcoinInterface + 'static>>
     fn mempool_entry(&self, txid: &bitcoin::Txid) -> Option<MempoolEntry> {
         self.lock().unwrap().mempool_entry(txid)
     }
+
+    fn electrum_servers(&self) -> Vec<electrum::ServerStatus> {
+        self.lock().unwrap().electrum_servers()
+    }
+
+    fn utxo(&self, outpoint: &bitcoin::OutPoint) -> Result<Option<(bitcoin::TxOut, i32)>, String> {
+        self.lock().unwrap().utxo(outpoint)
+    }
+
+    fn estimate_feerate(&self, conf_target: u16) -> Option<u64> {
+        self.lock().unwrap().estimate_feerate(conf_target)
+    }
+
+    fn connection(&self) -> Option<BackendConnection> {
+        self.lock().unwrap().connection()
+    }
+}
+
+// The following functions implement the coins tracking of the backends which store the wallet's
+// transactions in a BDK-based wallet, from the coins of this wallet.
+
+fn bdk_received_coins(
+    wallet_coins: &HashMap<bitcoin::OutPoint, Coin>,
+    tip: &BlockChainTip,
+) -> Vec<UTxO> {
+    // Get those wallet coins that are either unconfirmed or have a confirmation height
+    // after tip. The poller will then discard any that had already been received.
+    wallet_coins
+        .values()
+        .filter_map(|c| {
+            let height = c.block_info.map(|info| info.height);
+            if height.filter(|h| *h <= tip.height).is_some() {
+                None
+            } else {
+                Some(UTxO {
+                    outpoint: c.outpoint,
+                    block_height: height,
+                    amount: c.amount,
+                    address: UTxOAddress::DerivIndex(c.derivation_index, c.is_change),
+                    is_immature: c.is_immature,
+                })
+            }
+        })
+        .collect()
+}
+
+fn bdk_confirmed_coins(
+    wallet_coins: &HashMap<bitcoin::OutPoint, Coin>,
+    outpoints: &[bitcoin::OutPoint],
+) -> (Vec<(bitcoin::OutPoint, i32, u32)>, Vec<bitcoin::OutPoint>) {
+    let mut confirmed = Vec::new();
+    let mut expired = Vec::new();
+    for op in outpoints {
+        if let Some(w_c) = wallet_coins.get(op) {
+            if let Some(block) = w_c.block_info {
+                if w_c.is_immature {
+                    log::debug!(
+                        "Coin at '{}' comes from an immature coinbase transaction at \
+                        block height {}. Not marking it as confirmed for now.",
+                        op,
+                        block.height
+                    );
+                    continue;
+                }
+                confirmed.push((w_c.outpoint, block.height, block.time));
+            }
+        } else {
+            expired.push(*op);
+        }
+    }
+    (confirmed, expired)
+}
+
+fn bdk_spending_coins(
+    wallet_coins: &HashMap<bitcoin::OutPoint, Coin>,
+    outpoints: &[bitcoin::OutPoint],
+) -> Vec<(bitcoin::OutPoint, bitcoin::Txid)> {
+    outpoints
+        .iter()
+        .filter_map(|op| {
+            if let Some(w_c) = wallet_coins.get(op) {
+                w_c.spend_txid.map(|txid| (w_c.outpoint, txid))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn bdk_spent_coins(
+    wallet_coins: &HashMap<bitcoin::OutPoint, Coin>,
+    outpoints: &[(bitcoin::OutPoint, bitcoin::Txid)],
+) -> (Vec<SpentCoin>, Vec<bitcoin::OutPoint>) {
+    let mut spent = Vec::new();
+    let mut expired_spending = Vec::new();
+
+    for (op, spend_txid) in outpoints {
+        if let Some(w_c) = wallet_coins.get(op) {
+            if w_c.spend_txid != Some(*spend_txid) {
+                expired_spending.push(*op);
+            }
+            if let Some(block) = w_c.spend_block {
+                spent.push((*op, *spend_txid, block.height, block.time));
+            }
+        }
+    }
+    (spent, expired_spending)
 }
 
 // FIXME: We could avoid this type (and all the conversions entailing allocations) if bitcoind
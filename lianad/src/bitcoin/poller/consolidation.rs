@@ -0,0 +1,495 @@
+//! Automatic consolidation of the small coins of the wallet while fees are low.
+//!
+//! Once the feerate estimated by the Bitcoin backend drops to the configured maximum, a
+//! transaction spending the small coins of the wallet to a single change output is created. It is
+//! stored as a Spend PSBT for the signers to sign, and the configured command is run to notify
+//! them.
+
+use crate::{
+    bitcoin::BitcoinInterface,
+    config::ConsolidationConfig,
+    database::{Coin, CoinStatus, DatabaseConnection, DatabaseInterface, LabelItem},
+};
+
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryInto,
+    process, sync, thread, time,
+};
+
+use liana::{
+    descriptors,
+    spend::{
+        anti_fee_sniping_locktime, create_spend, AddrInfo, CandidateCoin, SpendOptions,
+        SpendOutputAddress, SpendTxFees, TxGetter,
+    },
+};
+use miniscript::bitcoin::{self, absolute::LockTime, secp256k1};
+
+/// The label set on the consolidation transactions we create. No other consolidation is created
+/// while a Spend PSBT with this label is pending: stored, spending only unspent coins and not
+/// broadcast.
+pub const CONSOLIDATION_LABEL: &str = "Automatic consolidation";
+
+// Get the previous transactions of the consolidated coins from the database.
+struct DbTxGetter<'a>(&'a mut Box<dyn DatabaseConnection>);
+
+impl TxGetter for DbTxGetter<'_> {
+    fn get_tx(&mut self, txid: &bitcoin::Txid) -> Option<bitcoin::Transaction> {
+        self.0
+            .list_wallet_transactions(&[*txid])
+            .pop()
+            .map(|(tx, _, _)| tx)
+    }
+}
+
+// Whether this coin carries one of the given labels, on itself, its deposit transaction or its
+// address.
+fn has_label(
+    coin: &Coin,
+    address: &bitcoin::Address,
+    labels: &HashMap<String, String>,
+    excluded: &[String],
+) -> bool {
+    [
+        coin.outpoint.to_string(),
+        coin.outpoint.txid.to_string(),
+        address.to_string(),
+    ]
+    .iter()
+    .filter_map(|item| labels.get(item))
+    .any(|label| {
+        excluded
+            .iter()
+            .any(|ex| ex.to_lowercase() == label.to_lowercase())
+    })
+}
+
+/// Create and store a consolidation PSBT if the conditions set in the configuration are met at
+/// the given feerate. Returns its txid, if one was created.
+pub fn maybe_consolidate(
+    db_conn: &mut Box<dyn DatabaseConnection>,
+    secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    desc: &descriptors::LianaDescriptor,
+    config: &ConsolidationConfig,
+    feerate_vb: u64,
+    locktime: LockTime,
+) -> Option<bitcoin::Txid> {
+    if feerate_vb > config.max_feerate {
+        log::debug!(
+            "Not consolidating at {} sat/vb, above the maximum of {} sat/vb.",
+            feerate_vb,
+            config.max_feerate
+        );
+        return None;
+    }
+    let feerate_vb = feerate_vb.max(1);
+
+    // Don't pile up consolidations while the signers haven't dealt with the previous one. It is
+    // dealt with once broadcast, or once a coin it spends was spent by another transaction. Also
+    // leave alone the coins already spent by a stored PSBT.
+    let spend_psbts = db_conn.list_spend();
+    let spend_items: HashSet<_> = spend_psbts
+        .iter()
+        .map(|(psbt, _)| LabelItem::Txid(psbt.unsigned_tx.compute_txid()))
+        .collect();
+    let labels = db_conn.labels(&spend_items);
+    let pending = spend_psbts.iter().any(|(psbt, _)| {
+        let txid = psbt.unsigned_tx.compute_txid();
+        if labels.get(&txid.to_string()).map(String::as_str) != Some(CONSOLIDATION_LABEL) {
+            return false;
+        }
+        let outpoints: Vec<_> = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect();
+        let coins = db_conn.coins(&[], &outpoints);
+        db_conn.list_wallet_transactions(&[txid]).is_empty()
+            && outpoints
+                .iter()
+                .all(|op| coins.get(op).is_some_and(|c| !c.is_spent()))
+    });
+    if pending {
+        log::debug!("Not consolidating: a previous consolidation is still pending.");
+        return None;
+    }
+    let reserved: HashSet<_> = spend_psbts
+        .iter()
+        .flat_map(|(psbt, _)| {
+            psbt.unsigned_tx
+                .input
+                .iter()
+                .map(|txin| txin.previous_output)
+        })
+        .collect();
+
+    let network = db_conn.network();
+    let address = |c: &Coin| {
+        let desc = if c.is_change {
+            desc.change_descriptor()
+        } else {
+            desc.receive_descriptor()
+        };
+        desc.derive(c.derivation_index, secp).address(network)
+    };
+    let mut coins: Vec<_> = db_conn
+        .coins(&[CoinStatus::Confirmed], &[])
+        .into_values()
+        .filter(|c| {
+            !c.is_immature
+                && !c.is_quarantined
+                && !reserved.contains(&c.outpoint)
+                && config
+                    .max_coin_amount_sat
+                    .is_none_or(|max| c.amount.to_sat() <= max)
+        })
+        .collect();
+    if !config.exclude_labels.is_empty() && coins.len() >= config.min_coins {
+        let items: HashSet<_> = coins
+            .iter()
+            .flat_map(|c| {
+                [
+                    LabelItem::OutPoint(c.outpoint),
+                    LabelItem::Txid(c.outpoint.txid),
+                    LabelItem::Address(address(c)),
+                ]
+            })
+            .collect();
+        let labels = db_conn.labels(&items);
+        coins.retain(|c| !has_label(c, &address(c), &labels, &config.exclude_labels));
+    }
+    if coins.len() < config.min_coins {
+        log::debug!(
+            "Not consolidating: only {} coins eligible out of {} required.",
+            coins.len(),
+            config.min_coins
+        );
+        return None;
+    }
+    coins.sort_by_key(|c| (c.amount, c.outpoint));
+    coins.truncate(config.max_coins);
+
+    let change_index = db_conn
+        .change_index()
+        .increment()
+        .expect("Must not get into hardened territory");
+    let change_addr = SpendOutputAddress {
+        addr: desc
+            .change_descriptor()
+            .derive(change_index, secp)
+            .address(network),
+        info: Some(AddrInfo {
+            index: change_index,
+            is_change: true,
+        }),
+    };
+
+    // The smallest coins cost the most to spend relative to their value. Leave them out until the
+    // fee is within the allowed share of the consolidated value.
+    while coins.len() >= config.min_coins {
+        let candidates: Vec<_> = coins
+            .iter()
+            .map(|c| CandidateCoin {
+                outpoint: c.outpoint,
+                amount: c.amount,
+                deriv_index: c.derivation_index,
+                is_change: c.is_change,
+                must_select: true,
+                sequence: None,
+                ancestor_info: None,
+            })
+            .collect();
+        let value: bitcoin::Amount = coins.iter().map(|c| c.amount).sum();
+        match create_spend(
+            desc,
+            secp,
+            &mut DbTxGetter(db_conn),
+            &[],
+            &candidates,
+            SpendTxFees::Regular(feerate_vb),
+            change_addr.clone(),
+            locktime,
+            &SpendOptions::default(),
+        ) {
+            // Without change, the whole value of the coins would go to fees.
+            Ok(res) if !res.has_change => {
+                log::debug!(
+                    "Not consolidating {} coins: the transaction would have no output.",
+                    coins.len()
+                );
+            }
+            Ok(res) => {
+                let out_value: bitcoin::Amount = res
+                    .psbt
+                    .unsigned_tx
+                    .output
+                    .iter()
+                    .map(|txo| txo.value)
+                    .sum();
+                let fee = value - out_value;
+                if fee.to_sat() as f64 <= value.to_sat() as f64 * config.max_fee_share {
+                    let txid = res.psbt.unsigned_tx.compute_txid();
+                    db_conn.store_spend(&res.psbt);
+                    db_conn.set_change_index(change_index, secp);
+                    db_conn.update_labels(&HashMap::from([(
+                        LabelItem::Txid(txid),
+                        Some(CONSOLIDATION_LABEL.to_string()),
+                    )]));
+                    log::info!(
+                        "Created consolidation transaction '{}' of {} coins worth {} (fee: {}, {} sat/vb).",
+                        txid,
+                        coins.len(),
+                        value,
+                        fee,
+                        feerate_vb
+                    );
+                    return Some(txid);
+                }
+            }
+            Err(e) => {
+                log::debug!(
+                    "Error creating a consolidation of {} coins: {}",
+                    coins.len(),
+                    e
+                );
+            }
+        }
+        coins.remove(0);
+    }
+
+    log::debug!(
+        "Not consolidating at {} sat/vb: the fee would exceed {}% of the value of the coins.",
+        feerate_vb,
+        config.max_fee_share * 100.0
+    );
+    None
+}
+
+// Run the notification command for this consolidation transaction, without waiting for it.
+fn run_notify_command(command: &str, txid: &bitcoin::Txid) {
+    let command = command.replace("%s", &txid.to_string());
+    #[cfg(not(windows))]
+    let mut cmd = {
+        let mut cmd = process::Command::new("sh");
+        cmd.arg("-c").arg(&command);
+        cmd
+    };
+    #[cfg(windows)]
+    let mut cmd = {
+        let mut cmd = process::Command::new("cmd");
+        cmd.arg("/C").arg(&command);
+        cmd
+    };
+    let res = thread::Builder::new()
+        .name("Consolidation notification".to_string())
+        .spawn(move || match cmd.status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => log::error!(
+                "Consolidation notification command '{}' failed: {}",
+                command,
+                status
+            ),
+            Err(e) => log::error!(
+                "Error running consolidation notification command '{}': {}",
+                command,
+                e
+            ),
+        });
+    if let Err(e) = res {
+        log::error!(
+            "Error spawning the consolidation notification thread: {}",
+            e
+        );
+    }
+}
+
+/// Consolidate the small coins of the wallet if the feerate estimated by the Bitcoin backend is
+/// low enough, and notify the signers.
+pub fn poll(
+    bit: &impl BitcoinInterface,
+    db: &sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+    secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    desc: &descriptors::LianaDescriptor,
+    config: &ConsolidationConfig,
+) {
+    let feerate_vb = match bit.estimate_feerate(config.conf_target) {
+        Some(feerate) => feerate,
+        None => {
+            log::debug!("No feerate estimate available, not consolidating.");
+            return;
+        }
+    };
+    let mut db_conn = db.connection();
+    let tip_height = db_conn
+        .chain_tip()
+        .expect("Always set at first startup")
+        .height;
+    let now = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .expect("current system time must be later than epoch");
+    let locktime = anti_fee_sniping_locktime(
+        now,
+        tip_height.try_into().expect("height must fit into u32"),
+        bit.tip_time(),
+    );
+    if let Some(txid) = maybe_consolidate(&mut db_conn, secp, desc, config, feerate_vb, locktime) {
+        if let Some(command) = &config.notify_command {
+            run_notify_command(command, &txid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{database::BlockInfo, testutils::DummyDatabase};
+    use std::str::FromStr;
+
+    fn coin(vout: u32, amount_sat: u64) -> Coin {
+        Coin {
+            outpoint: bitcoin::OutPoint::new(
+                bitcoin::Txid::from_str(
+                    "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810",
+                )
+                .unwrap(),
+                vout,
+            ),
+            is_immature: false,
+            block_info: Some(BlockInfo {
+                height: 100,
+                time: 1_700_000_000,
+            }),
+            amount: bitcoin::Amount::from_sat(amount_sat),
+            derivation_index: vout.into(),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            is_from_self: false,
+            is_quarantined: false,
+        }
+    }
+
+    #[test]
+    fn consolidation() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc = descriptors::LianaDescriptor::from_str("wsh(andor(pk([aabbccdd]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk([aabbccdd]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#dw4ulnrs").unwrap();
+        let mut config = ConsolidationConfig {
+            max_feerate: 2,
+            conf_target: 144,
+            min_coins: 4,
+            max_coins: 5,
+            max_coin_amount_sat: Some(100_000),
+            max_fee_share: 0.05,
+            exclude_labels: vec!["cold storage".to_string()],
+            notify_command: None,
+        };
+        let locktime = LockTime::ZERO;
+        let mut db = DummyDatabase::new();
+        let mut db_conn = db.connection();
+
+        // Six small coins, along with a large one, a quarantined one and one excluded by its label.
+        let mut coins: Vec<_> = (0..6).map(|i| coin(i, 20_000 + u64::from(i))).collect();
+        coins.push(coin(6, 1_000_000));
+        coins.push(Coin {
+            is_quarantined: true,
+            ..coin(7, 20_000)
+        });
+        coins.push(coin(8, 20_000));
+        db.insert_coins(coins.clone());
+        db_conn.update_labels(&HashMap::from([(
+            LabelItem::OutPoint(coins[8].outpoint),
+            Some("Cold Storage".to_string()),
+        )]));
+
+        // Nothing happens while fees are too high, or if there aren't enough coins to consolidate.
+        assert!(maybe_consolidate(&mut db_conn, &secp, &desc, &config, 3, locktime).is_none());
+        config.min_coins = 7;
+        assert!(maybe_consolidate(&mut db_conn, &secp, &desc, &config, 2, locktime).is_none());
+        assert!(db_conn.list_spend().is_empty());
+
+        // Nor if the fee would be too large a share of the value of the coins.
+        config.min_coins = 4;
+        config.max_fee_share = 0.001;
+        assert!(maybe_consolidate(&mut db_conn, &secp, &desc, &config, 2, locktime).is_none());
+        assert!(db_conn.list_spend().is_empty());
+
+        // Otherwise the smallest eligible coins are consolidated to a new change address.
+        config.max_fee_share = 0.05;
+        let txid = maybe_consolidate(&mut db_conn, &secp, &desc, &config, 2, locktime).unwrap();
+        let psbt = db_conn.spend_tx(&txid).unwrap();
+        let mut spent: Vec<_> = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect();
+        spent.sort();
+        assert_eq!(
+            spent,
+            coins[..5].iter().map(|c| c.outpoint).collect::<Vec<_>>()
+        );
+        assert_eq!(psbt.unsigned_tx.output.len(), 1);
+        assert_eq!(db_conn.change_index(), 1.into());
+        assert_eq!(
+            db_conn
+                .labels(&HashSet::from([LabelItem::Txid(txid)]))
+                .get(&txid.to_string())
+                .map(String::as_str),
+            Some(CONSOLIDATION_LABEL)
+        );
+
+        // No other consolidation is created until this one is dealt with.
+        config.min_coins = 1;
+        assert!(maybe_consolidate(&mut db_conn, &secp, &desc, &config, 1, locktime).is_none());
+        db_conn.delete_spend(&txid);
+        let txid = maybe_consolidate(&mut db_conn, &secp, &desc, &config, 1, locktime).unwrap();
+        let tx = db_conn.spend_tx(&txid).unwrap().unsigned_tx;
+        assert_eq!(tx.input.len(), 5);
+
+        // It is dealt with once broadcast, even though its PSBT is still stored. The remaining
+        // coin can then be consolidated, and this new consolidation is pending in turn.
+        db_conn.new_txs(&[tx.clone()]);
+        db_conn.spend_coins(
+            &tx.input
+                .iter()
+                .map(|txin| (txin.previous_output, txid))
+                .collect::<Vec<_>>(),
+        );
+        assert!(db_conn.spend_tx(&txid).is_some());
+        let next_txid =
+            maybe_consolidate(&mut db_conn, &secp, &desc, &config, 1, locktime).unwrap();
+        let next_tx = db_conn.spend_tx(&next_txid).unwrap().unsigned_tx;
+        assert_eq!(next_tx.input.len(), 1);
+        assert_eq!(next_tx.input[0].previous_output, coins[5].outpoint);
+        assert!(maybe_consolidate(&mut db_conn, &secp, &desc, &config, 1, locktime).is_none());
+    }
+
+    #[test]
+    fn consolidation_without_change() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc = descriptors::LianaDescriptor::from_str("wsh(andor(pk([aabbccdd]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk([aabbccdd]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#dw4ulnrs").unwrap();
+        let config = ConsolidationConfig {
+            max_feerate: 2,
+            conf_target: 144,
+            min_coins: 2,
+            max_coins: 5,
+            max_coin_amount_sat: None,
+            max_fee_share: 1.0,
+            exclude_labels: Vec::new(),
+            notify_command: None,
+        };
+        let mut db = DummyDatabase::new();
+        let mut db_conn = db.connection();
+
+        // Even if the whole value may go to fees, coins too small to pay for a change output are
+        // never consolidated.
+        db.insert_coins((0..4).map(|i| coin(i, 300)).collect());
+        assert!(
+            maybe_consolidate(&mut db_conn, &secp, &desc, &config, 2, LockTime::ZERO).is_none()
+        );
+        assert!(db_conn.list_spend().is_empty());
+        assert_eq!(db_conn.change_index(), 0.into());
+    }
+}
@@ -1,12 +1,17 @@
 use crate::{
     bitcoin::{BitcoinInterface, BlockChainTip, UTxO, UTxOAddress},
-    database::{Coin, DatabaseConnection, DatabaseInterface},
+    config::DustProtectionConfig,
+    database::{Coin, DatabaseConnection, DatabaseInterface, ScheduledBroadcastState},
 };
 
-use std::{collections::HashSet, convert::TryInto, sync, thread, time};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryInto,
+    sync, thread, time,
+};
 
 use liana::descriptors;
-use miniscript::bitcoin::{self, secp256k1};
+use miniscript::bitcoin::{self, bip32, secp256k1};
 
 #[derive(Debug, Clone)]
 struct UpdatedCoins {
@@ -18,6 +23,82 @@ struct UpdatedCoins {
     pub spent: Vec<(bitcoin::OutPoint, bitcoin::Txid, i32, u32)>,
 }
 
+// The reasons for which a newly received coin looks like part of a dust attack, according to the
+// given heuristics. `deposit_tx` is the transaction creating the coin, if we could fetch it, and
+// `used_addresses` the derivation indexes of the addresses which already received coins.
+fn dust_reasons(
+    coin: &Coin,
+    deposit_tx: Option<&bitcoin::Transaction>,
+    used_addresses: &HashSet<(bip32::ChildNumber, bool)>,
+    config: &DustProtectionConfig,
+) -> Vec<&'static str> {
+    let max_amount = bitcoin::Amount::from_sat(config.max_amount_sat);
+    let mut reasons = Vec::new();
+    if coin.amount <= max_amount {
+        reasons.push("tiny amount");
+    }
+    if config.reused_address && used_addresses.contains(&(coin.derivation_index, coin.is_change)) {
+        reasons.push("sent to a reused address");
+    }
+    // Data carrier outputs are not payments, don't count them as tiny outputs. The dust amount
+    // threshold applies, so this heuristic is disabled along with the amount one.
+    if let Some(tx) = deposit_tx.filter(|_| config.max_amount_sat > 0) {
+        let tiny_outputs = tx
+            .output
+            .iter()
+            .filter(|txo| txo.value <= max_amount && !txo.script_pubkey.is_op_return())
+            .count();
+        if config.min_tiny_outputs > 0 && tiny_outputs >= config.min_tiny_outputs {
+            reasons.push("created by a transaction with many tiny outputs");
+        }
+    }
+    reasons
+}
+
+// Quarantine the newly received coins which look like part of a dust attack. Coins created by a
+// transaction spending our own coins are never flagged.
+fn quarantine_dust(
+    bit: &impl BitcoinInterface,
+    curr_coins: &HashMap<bitcoin::OutPoint, Coin>,
+    received: &mut [Coin],
+    config: &DustProtectionConfig,
+) {
+    let used_addresses: HashSet<_> = curr_coins
+        .values()
+        .map(|coin| (coin.derivation_index, coin.is_change))
+        .collect();
+    let received_outpoints: HashSet<_> = received.iter().map(|coin| coin.outpoint).collect();
+    let mut deposit_txs: HashMap<bitcoin::Txid, Option<bitcoin::Transaction>> = HashMap::new();
+
+    for coin in received.iter_mut() {
+        let deposit_tx = deposit_txs
+            .entry(coin.outpoint.txid)
+            .or_insert_with(|| {
+                bit.wallet_transaction(&coin.outpoint.txid)
+                    .map(|(tx, _)| tx)
+            })
+            .as_ref();
+        let is_from_self = deposit_tx.is_some_and(|tx| {
+            tx.input.iter().any(|txin| {
+                curr_coins.contains_key(&txin.previous_output)
+                    || received_outpoints.contains(&txin.previous_output)
+            })
+        });
+        if is_from_self {
+            continue;
+        }
+        let reasons = dust_reasons(coin, deposit_tx, &used_addresses, config);
+        if !reasons.is_empty() {
+            log::warn!(
+                "Quarantining coin '{}' as a likely dust attack: {}.",
+                coin.outpoint,
+                reasons.join(", ")
+            );
+            coin.is_quarantined = true;
+        }
+    }
+}
+
 // Update the state of our coins. There may be new unspent, and existing ones may become confirmed
 // or spent.
 // NOTE: A coin may be updated multiple times at once. That is, a coin may be received, confirmed,
@@ -29,6 +110,7 @@ fn update_coins(
     db_conn: &mut Box<dyn DatabaseConnection>,
     previous_tip: &BlockChainTip,
     descs: &[descriptors::SinglePathLianaDesc],
+    dust_protection: Option<&DustProtectionConfig>,
     secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
 ) -> UpdatedCoins {
     let network = db_conn.network();
@@ -91,10 +173,14 @@ fn update_coins(
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             received.push(coin);
         }
     }
+    if let Some(config) = dust_protection {
+        quarantine_dust(bit, &curr_coins, &mut received, config);
+    }
     log::debug!("Newly received coins: {:?}", received);
 
     // We need to take the newly received ones into account as well, as they may have been
@@ -242,6 +328,7 @@ fn updates(
     db_conn: &mut Box<dyn DatabaseConnection>,
     bit: &mut impl BitcoinInterface,
     descs: &[descriptors::SinglePathLianaDesc],
+    dust_protection: Option<&DustProtectionConfig>,
     secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
 ) {
     // Check if there was a new block before we update our state.
@@ -264,7 +351,7 @@ fn updates(
                     // between our former chain and the new one, then restart fresh.
                     db_conn.rollback_tip(&new_tip);
                     log::info!("Tip was rolled back to '{}'.", new_tip);
-                    return updates(db_conn, bit, descs, secp);
+                    return updates(db_conn, bit, descs, dust_protection, secp);
                 }
             }
         }
@@ -285,23 +372,23 @@ fn updates(
                     &reorg_common_ancestor
                 );
             }
-            return updates(db_conn, bit, descs, secp);
+            return updates(db_conn, bit, descs, dust_protection, secp);
         }
         Err(e) => {
             log::error!("Error syncing wallet: '{}'.", e);
             thread::sleep(time::Duration::from_secs(2));
-            return updates(db_conn, bit, descs, secp);
+            return updates(db_conn, bit, descs, dust_protection, secp);
         }
     };
 
     // Then check the state of our coins. Do it even if the tip did not change since last poll, as
     // we may have unconfirmed transactions.
-    let updated_coins = update_coins(bit, db_conn, &current_tip, descs, secp);
+    let updated_coins = update_coins(bit, db_conn, &current_tip, descs, dust_protection, secp);
 
     // If the tip changed while we were polling our Bitcoin interface, start over.
     if bit.chain_tip() != latest_tip {
         log::info!("Chain tip changed while we were updating our state. Starting over.");
-        return updates(db_conn, bit, descs, secp);
+        return updates(db_conn, bit, descs, dust_protection, secp);
     }
 
     // Transactions must be added to the DB before coins due to foreign key constraints.
@@ -331,6 +418,7 @@ fn rescan_check(
     db_conn: &mut Box<dyn DatabaseConnection>,
     bit: &mut impl BitcoinInterface,
     descs: &[descriptors::SinglePathLianaDesc],
+    dust_protection: Option<&DustProtectionConfig>,
     secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
 ) {
     log::debug!("Checking the state of an ongoing rescan if there is any");
@@ -368,12 +456,76 @@ fn rescan_check(
             "Rolling back our internal tip to '{}' to update our internal state with past transactions.",
             rescan_tip
         );
-        updates(db_conn, bit, descs, secp)
+        updates(db_conn, bit, descs, dust_protection, secp)
     } else {
         log::debug!("No ongoing rescan.");
     }
 }
 
+// Broadcast the scheduled transactions which became valid at our current tip. Those which were
+// broadcast by someone else are marked as broadcast too.
+fn broadcast_scheduled(
+    db_conn: &mut Box<dyn DatabaseConnection>,
+    bit: &mut impl BitcoinInterface,
+    now: u32,
+) {
+    let tip = db_conn.chain_tip().expect("Always set at first startup");
+    let tip_mtp = bit.tip_median_time_past();
+    for sched in db_conn.scheduled_broadcasts() {
+        let txid = sched.txid();
+        let outpoints: Vec<_> = sched
+            .tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect();
+        let coins = db_conn.coins_by_outpoints(&outpoints);
+        match sched.state(&coins, tip.height, tip_mtp) {
+            ScheduledBroadcastState::Broadcast => {
+                let broadcast_at = match sched.broadcast_at {
+                    Some(broadcast_at) => broadcast_at,
+                    None => {
+                        log::info!("Scheduled transaction '{}' was broadcast.", txid);
+                        now
+                    }
+                };
+                // It may have been dropped from the mempool, broadcast it again until it confirms.
+                match bit.broadcast_tx(&sched.tx) {
+                    Ok(()) => {
+                        if sched.broadcast_at.is_none() || sched.last_error.is_some() {
+                            db_conn.update_scheduled_broadcast(&txid, Some(broadcast_at), None);
+                        }
+                    }
+                    Err(e) => {
+                        log::debug!(
+                            "Error rebroadcasting scheduled transaction '{}': {}",
+                            txid,
+                            e
+                        );
+                        if sched.broadcast_at.is_none() {
+                            db_conn.update_scheduled_broadcast(&txid, Some(broadcast_at), None);
+                        }
+                    }
+                }
+            }
+            ScheduledBroadcastState::Ready => match bit.broadcast_tx(&sched.tx) {
+                Ok(()) => {
+                    log::info!("Broadcast scheduled transaction '{}'.", txid);
+                    db_conn.update_scheduled_broadcast(&txid, Some(now), None);
+                }
+                Err(e) => {
+                    log::warn!("Error broadcasting scheduled transaction '{}': {}", txid, e);
+                    db_conn.update_scheduled_broadcast(&txid, None, Some(e));
+                }
+            },
+            ScheduledBroadcastState::WaitingConfirmation
+            | ScheduledBroadcastState::Pending { .. }
+            | ScheduledBroadcastState::Confirmed
+            | ScheduledBroadcastState::Conflicted => {}
+        }
+    }
+}
+
 /// If the database chain tip is NULL (first startup), initialize it.
 pub fn maybe_initialize_tip(bit: &impl BitcoinInterface, db: &impl DatabaseInterface) {
     let mut db_conn = db.connection();
@@ -400,15 +552,130 @@ pub fn poll(
     db: &sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
     secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
     descs: &[descriptors::SinglePathLianaDesc],
+    dust_protection: Option<&DustProtectionConfig>,
 ) {
     let mut db_conn = db.connection();
-    updates(&mut db_conn, bit, descs, secp);
-    rescan_check(&mut db_conn, bit, descs, secp);
+    updates(&mut db_conn, bit, descs, dust_protection, secp);
+    rescan_check(&mut db_conn, bit, descs, dust_protection, secp);
     let now: u32 = time::SystemTime::now()
         .duration_since(time::UNIX_EPOCH)
         .expect("current system time must be later than epoch")
         .as_secs()
         .try_into()
         .expect("system clock year is earlier than 2106");
+    broadcast_scheduled(&mut db_conn, bit, now);
     db_conn.set_last_poll(now);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn coin(amount_sat: u64, derivation_index: u32) -> Coin {
+        Coin {
+            outpoint: bitcoin::OutPoint::from_str(
+                "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+            )
+            .unwrap(),
+            is_immature: false,
+            block_info: None,
+            amount: bitcoin::Amount::from_sat(amount_sat),
+            derivation_index: bip32::ChildNumber::from(derivation_index),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            is_from_self: false,
+            is_quarantined: false,
+        }
+    }
+
+    fn tx_with_outputs(values_sat: &[u64]) -> bitcoin::Transaction {
+        bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: values_sat
+                .iter()
+                .map(|value| bitcoin::TxOut {
+                    value: bitcoin::Amount::from_sat(*value),
+                    script_pubkey: bitcoin::ScriptBuf::new(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn dust_heuristics() {
+        let mut config = DustProtectionConfig {
+            max_amount_sat: 1_000,
+            reused_address: false,
+            min_tiny_outputs: 3,
+        };
+        let used_addresses: HashSet<_> = [(bip32::ChildNumber::from(0), false)]
+            .iter()
+            .copied()
+            .collect();
+        let regular_tx = tx_with_outputs(&[50_000, 20_000]);
+
+        // A regular payment isn't flagged, even to a reused address by default.
+        assert!(dust_reasons(
+            &coin(50_000, 0),
+            Some(&regular_tx),
+            &used_addresses,
+            &config
+        )
+        .is_empty());
+        // A tiny amount is flagged, whether or not we know the deposit transaction.
+        assert_eq!(
+            dust_reasons(&coin(546, 1), None, &used_addresses, &config),
+            vec!["tiny amount"]
+        );
+        // Any output of a transaction with many tiny outputs is flagged.
+        let spray_tx = tx_with_outputs(&[600, 600, 600, 50_000]);
+        assert_eq!(
+            dust_reasons(&coin(50_000, 1), Some(&spray_tx), &used_addresses, &config),
+            vec!["created by a transaction with many tiny outputs"]
+        );
+        // Outputs above the dust amount threshold and data carrier outputs aren't counted.
+        let batch_tx = tx_with_outputs(&[600, 600, 1_001, 1_001, 50_000]);
+        assert!(
+            dust_reasons(&coin(50_000, 1), Some(&batch_tx), &used_addresses, &config).is_empty()
+        );
+        let mut data_tx = tx_with_outputs(&[0, 0, 600, 50_000]);
+        for txo in data_tx.output.iter_mut().take(2) {
+            txo.script_pubkey = bitcoin::ScriptBuf::from_bytes(vec![0x6a]);
+        }
+        assert!(
+            dust_reasons(&coin(50_000, 1), Some(&data_tx), &used_addresses, &config).is_empty()
+        );
+        // Unless this heuristic is disabled, directly or along with the dust amount threshold.
+        config.max_amount_sat = 0;
+        assert!(
+            dust_reasons(&coin(50_000, 1), Some(&spray_tx), &used_addresses, &config).is_empty()
+        );
+        config.max_amount_sat = 1_000;
+        config.min_tiny_outputs = 0;
+        assert!(
+            dust_reasons(&coin(50_000, 1), Some(&spray_tx), &used_addresses, &config).is_empty()
+        );
+        // Address reuse is flagged once enabled.
+        config.reused_address = true;
+        assert_eq!(
+            dust_reasons(
+                &coin(50_000, 0),
+                Some(&regular_tx),
+                &used_addresses,
+                &config
+            ),
+            vec!["sent to a reused address"]
+        );
+        assert!(dust_reasons(
+            &coin(50_000, 1),
+            Some(&regular_tx),
+            &used_addresses,
+            &config
+        )
+        .is_empty());
+    }
+}
@@ -1,11 +1,19 @@
+mod consolidation;
 mod looper;
 
-use crate::{bitcoin::BitcoinInterface, database::DatabaseInterface};
+use crate::{
+    bitcoin::BitcoinInterface,
+    config::{ConsolidationConfig, DustProtectionConfig},
+    database::DatabaseInterface,
+    metrics::Metrics,
+};
 use liana::descriptors;
 
 use std::{
-    sync::{self, mpsc},
-    time,
+    collections::BTreeMap,
+    fmt,
+    sync::{self, atomic, mpsc},
+    thread, time,
 };
 
 use miniscript::bitcoin::secp256k1;
@@ -16,6 +24,77 @@ pub enum PollerMessage {
     /// Ask the Bitcoin poller to poll immediately, get notified through the passed channel once
     /// it's done.
     PollNow(mpsc::SyncSender<()>),
+    /// Start keeping this additional wallet in sync, identified by the given id.
+    AddWallet(String, PolledWallet),
+    /// Stop keeping the additional wallet with this id in sync.
+    RemoveWallet(String),
+    /// The Bitcoin backend notified us of a new block or of a transaction which may concern us.
+    /// Poll immediately rather than waiting for the next poll.
+    BackendEvent,
+}
+
+/// Used by a thread listening to events from the Bitcoin backend to get the poller to update our
+/// state as soon as something happens. The poller stops the thread when it shuts down.
+#[derive(Clone)]
+pub struct EventNotifier {
+    sender: mpsc::SyncSender<PollerMessage>,
+    stop: sync::Arc<atomic::AtomicBool>,
+}
+
+impl EventNotifier {
+    /// Tell the poller something happened.
+    pub fn notify(&self) {
+        match self.sender.try_send(PollerMessage::BackendEvent) {
+            // If the channel is full the poller is about to wake up anyways.
+            Ok(()) | Err(mpsc::TrySendError::Full(_)) => {}
+            Err(mpsc::TrySendError::Disconnected(_)) => {
+                self.stop.store(true, atomic::Ordering::Relaxed)
+            }
+        }
+    }
+
+    /// Whether the listening thread should stop.
+    pub fn should_stop(&self) -> bool {
+        self.stop.load(atomic::Ordering::Relaxed)
+    }
+}
+
+// A thread listening to events from the Bitcoin backend.
+struct EventListener {
+    stop: sync::Arc<atomic::AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+/// An additional wallet kept in sync by the poller, alongside the main one.
+#[derive(Clone)]
+pub struct PolledWallet {
+    bit: sync::Arc<sync::Mutex<dyn BitcoinInterface>>,
+    db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+    // The receive and change descriptors (in this order).
+    descs: [descriptors::SinglePathLianaDesc; 2],
+}
+
+impl PolledWallet {
+    pub fn new(
+        bit: sync::Arc<sync::Mutex<dyn BitcoinInterface>>,
+        db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+        desc: &descriptors::LianaDescriptor,
+    ) -> PolledWallet {
+        let descs = [
+            desc.receive_descriptor().clone(),
+            desc.change_descriptor().clone(),
+        ];
+        looper::maybe_initialize_tip(&bit, &db);
+        PolledWallet { bit, db, descs }
+    }
+}
+
+impl fmt::Debug for PolledWallet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PolledWallet")
+            .field("descs", &self.descs)
+            .finish_non_exhaustive()
+    }
 }
 
 /// The Bitcoin poller handler.
@@ -23,8 +102,19 @@ pub struct Poller {
     bit: sync::Arc<sync::Mutex<dyn BitcoinInterface>>,
     db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
     secp: secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    desc: descriptors::LianaDescriptor,
     // The receive and change descriptors (in this order).
     descs: [descriptors::SinglePathLianaDesc; 2],
+    // The additional wallets loaded on this daemon, by id. They share the same poller loop as the
+    // main wallet.
+    wallets: BTreeMap<String, PolledWallet>,
+    // The threads notifying us of events from the Bitcoin backend, if any.
+    listeners: Vec<EventListener>,
+    metrics: sync::Arc<Metrics>,
+    // The heuristics to detect dust attacks on the coins we receive, if enabled.
+    dust_protection: Option<DustProtectionConfig>,
+    // When to consolidate the small coins of the main wallet, if enabled.
+    consolidation: Option<ConsolidationConfig>,
 }
 
 impl Poller {
@@ -32,6 +122,9 @@ impl Poller {
         bit: sync::Arc<sync::Mutex<dyn BitcoinInterface>>,
         db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
         desc: descriptors::LianaDescriptor,
+        metrics: sync::Arc<Metrics>,
+        dust_protection: Option<DustProtectionConfig>,
+        consolidation: Option<ConsolidationConfig>,
     ) -> Poller {
         let secp = secp256k1::Secp256k1::verification_only();
         let descs = [
@@ -46,10 +139,74 @@ impl Poller {
             bit,
             db,
             secp,
+            desc,
             descs,
+            wallets: BTreeMap::new(),
+            listeners: Vec::new(),
+            metrics,
+            dust_protection,
+            consolidation,
+        }
+    }
+
+    /// Start a thread listening to events from the Bitcoin backend. It must notify the poller of
+    /// these through the given [`EventNotifier`] and return once told to stop.
+    pub fn spawn_listener(
+        &mut self,
+        name: &str,
+        sender: mpsc::SyncSender<PollerMessage>,
+        listen: impl FnOnce(EventNotifier) + Send + 'static,
+    ) {
+        let stop = sync::Arc::new(atomic::AtomicBool::new(false));
+        let notifier = EventNotifier {
+            sender,
+            stop: stop.clone(),
+        };
+        let handle = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || listen(notifier))
+            .expect("Spawning the listener thread must never fail.");
+        self.listeners.push(EventListener { stop, handle });
+    }
+
+    fn stop_listeners(&mut self) {
+        for listener in &self.listeners {
+            listener.stop.store(true, atomic::Ordering::Relaxed);
+        }
+        for listener in self.listeners.drain(..) {
+            if listener.handle.join().is_err() {
+                log::error!("Bitcoin backend events listener thread panicked.");
+            }
         }
     }
 
+    // Update the state of the main wallet and of all the additional wallets.
+    fn poll(&mut self) {
+        let poll_start = time::Instant::now();
+        let dust_protection = self.dust_protection.as_ref();
+        looper::poll(
+            &mut self.bit,
+            &self.db,
+            &self.secp,
+            &self.descs,
+            dust_protection,
+        );
+        if let Some(consolidation) = &self.consolidation {
+            consolidation::poll(&self.bit, &self.db, &self.secp, &self.desc, consolidation);
+        }
+        for (id, wallet) in self.wallets.iter_mut() {
+            log::debug!("Polling wallet '{}'.", id);
+            looper::poll(
+                &mut wallet.bit,
+                &wallet.db,
+                &self.secp,
+                &wallet.descs,
+                dust_protection,
+            );
+        }
+        self.metrics.record_poll(poll_start.elapsed());
+    }
+
     /// Continuously update our state from the Bitcoin backend.
     /// - `poll_interval`: how frequently to perform an update.
     /// - `shutdown`: set to true to stop continuously updating and make this function return.
@@ -86,6 +243,7 @@ impl Poller {
             match receiver.recv_timeout(time_before_poll) {
                 Ok(PollerMessage::Shutdown) => {
                     log::info!("Bitcoin poller was told to shut down.");
+                    self.stop_listeners();
                     return;
                 }
                 Ok(PollerMessage::PollNow(sender)) => {
@@ -108,7 +266,7 @@ impl Poller {
                     // poll too soon.
                     last_poll = Some(time::Instant::now());
                     if synced {
-                        looper::poll(&mut self.bit, &self.db, &self.secp, &self.descs);
+                        self.poll();
                     } else {
                         log::warn!("Skipped poll as block chain is still synchronizing.");
                     }
@@ -117,11 +275,31 @@ impl Poller {
                     }
                     continue;
                 }
+                Ok(PollerMessage::AddWallet(id, wallet)) => {
+                    log::info!("Bitcoin poller now keeping wallet '{}' in sync.", id);
+                    self.wallets.insert(id, wallet);
+                    continue;
+                }
+                Ok(PollerMessage::RemoveWallet(id)) => {
+                    if self.wallets.remove(&id).is_some() {
+                        log::info!("Bitcoin poller stopped keeping wallet '{}' in sync.", id);
+                    }
+                    continue;
+                }
+                Ok(PollerMessage::BackendEvent) => {
+                    // Something happened, don't wait for the next poll. Still, until we are synced
+                    // we don't poll more often than usual.
+                    if !synced {
+                        continue;
+                    }
+                    log::debug!("Polling upon Bitcoin backend notification.");
+                }
                 Err(mpsc::RecvTimeoutError::Timeout) => {
                     // It's been long enough since the last poll.
                 }
                 Err(mpsc::RecvTimeoutError::Disconnected) => {
                     log::error!("Bitcoin poller communication channel got disconnected. Exiting.");
+                    self.stop_listeners();
                     return;
                 }
             }
@@ -142,7 +320,7 @@ impl Poller {
                 }
             }
 
-            looper::poll(&mut self.bit, &self.db, &self.secp, &self.descs);
+            self.poll();
         }
     }
 }
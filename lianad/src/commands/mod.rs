@@ -7,31 +7,38 @@ mod utils;
 
 use crate::{
     bitcoin::BitcoinInterface,
-    database::{Coin, DatabaseConnection, DatabaseInterface},
+    database::{Coin, DatabaseConnection, DatabaseInterface, ScheduledBroadcastState},
+    datadir::DataDirectory,
     miniscript::bitcoin::absolute::LockTime,
-    poller::PollerMessage,
-    DaemonControl, VERSION,
+    poller::{PolledWallet, PollerMessage},
+    setup_wallet, wallet_descriptor, DaemonControl, StartupError, VERSION,
 };
 
+pub use crate::bitcoin::electrum::ServerStatus as ElectrumServerStatus;
 pub use crate::database::{CoinStatus, LabelItem};
 
 use liana::{
-    descriptors,
+    bip322::{self, MessageSignature},
+    descriptors, privacy,
+    reserves::{self, ReservesProofError},
+    silent_payments::{self, SilentPaymentAddress, SilentPaymentError},
     spend::{
         self, create_spend, AddrInfo, AncestorInfo, CandidateCoin, CreateSpendRes,
-        SpendCreationError, SpendOutputAddress, SpendTxFees, TxGetter,
+        DestinationAmount, SpendCreationError, SpendOptions, SpendOutputAddress, SpendTxFees,
+        TxGetter,
     },
 };
 
 use utils::{
-    deser_addr_assume_checked, deser_amount_from_sats, deser_fromstr, deser_hex, ser_amount,
-    ser_hex, ser_to_string,
+    deser_addr_assume_checked, deser_amount_from_sats, deser_fromstr, deser_hex,
+    deser_opt_amount_from_sats, deser_vec_fromstr, ser_amount, ser_hex, ser_opt_amount,
+    ser_to_string, ser_vec_to_string,
 };
 
 use std::{
-    collections::{hash_map, HashMap, HashSet},
+    collections::{btree_map, hash_map, BTreeMap, BTreeSet, HashMap, HashSet},
     convert::TryInto,
-    fmt,
+    fmt, fs, iter, path,
     sync::{self, mpsc},
     time::SystemTime,
 };
@@ -40,7 +47,7 @@ use miniscript::{
     bitcoin::{
         self, address,
         bip32::{self, ChildNumber},
-        psbt::Psbt,
+        psbt::{Input as PsbtIn, Psbt},
     },
     psbt::PsbtExt,
 };
@@ -54,6 +61,8 @@ pub enum CommandError {
     UnknownOutpoint(bitcoin::OutPoint),
     AlreadySpent(bitcoin::OutPoint),
     ImmatureCoinbase(bitcoin::OutPoint),
+    QuarantinedCoin(bitcoin::OutPoint),
+    QuarantinedCoins(Vec<bitcoin::OutPoint>),
     Address(bitcoin::address::ParseError),
     SpendCreation(SpendCreationError),
     InsufficientFunds(
@@ -76,6 +85,32 @@ pub enum CommandError {
     InvalidDerivationIndex,
     RbfError(RbfErrorInfo),
     EmptyFilterList,
+    InvalidWalletId(String),
+    UnknownWallet(String),
+    WalletAlreadyLoaded(String),
+    WalletAlreadyExists(String),
+    WalletNotFound(String),
+    /// An error when setting up the interfaces of an additional wallet.
+    WalletSetup(String),
+    InvalidMigrationDescriptor(String),
+    NoCoinToMigrate,
+    /// Unconfirmed or immature coins which would be left behind by a migration.
+    CoinsNotMigratable(Vec<bitcoin::OutPoint>),
+    AlreadyRetired,
+    InvalidPsbt(String),
+    InvalidFeeSubtraction(String),
+    UnknownAddress(String),
+    MessageSignature(String),
+    ReservesProof(ReservesProofError),
+    InvalidHeight(i32),
+    /// The coin was not part of the UTXO set at this block height.
+    NotUnspentAtHeight(bitcoin::OutPoint, i32),
+    /// The backend failed to look up an output in the UTXO set.
+    UtxoLookup(String),
+    /// An invalid silent payment address, or one this wallet can't pay to.
+    SilentPayment(SilentPaymentError),
+    /// The input spending this coin has no witness.
+    UnsignedInput(bitcoin::OutPoint),
 }
 
 impl fmt::Display for CommandError {
@@ -90,6 +125,18 @@ impl fmt::Display for CommandError {
                 f,
                 "Coin at '{op}' is from an immature coinbase transaction.",
             ),
+            Self::QuarantinedCoin(op) => write!(
+                f,
+                "Coin at '{op}' is quarantined as a likely dust attack. It must be released before being spent.",
+            ),
+            Self::QuarantinedCoins(ops) => write!(
+                f,
+                "Coins at {} are quarantined as a likely dust attack and would be left out. They must be released first, or the coins to spend must be specified.",
+                ops.iter()
+                    .map(|op| format!("'{op}'"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             Self::UnknownOutpoint(op) => write!(f, "Unknown outpoint '{op}'."),
             Self::Address(e) => write!(f, "Address error: {e}"),
             Self::SpendCreation(e) => write!(f, "Creating spend: {e}"),
@@ -129,6 +176,53 @@ impl fmt::Display for CommandError {
             }
             Self::RbfError(e) => write!(f, "RBF error: '{e}'."),
             Self::EmptyFilterList => write!(f, "Filter list is empty, should supply None instead."),
+            Self::InvalidWalletId(id) => write!(
+                f,
+                "Invalid wallet id '{id}'. It must be 1 to 64 alphanumeric characters, '-' or '_'."
+            ),
+            Self::UnknownWallet(id) => write!(f, "No wallet with id '{id}' is loaded."),
+            Self::WalletAlreadyLoaded(id) => write!(f, "Wallet '{id}' is already loaded."),
+            Self::WalletAlreadyExists(id) => {
+                write!(
+                    f,
+                    "A wallet with id '{id}' already exists. Use 'loadwallet' instead."
+                )
+            }
+            Self::WalletNotFound(id) => {
+                write!(f, "No wallet with id '{id}' in the data directory.")
+            }
+            Self::WalletSetup(e) => write!(f, "Error setting up wallet: '{e}'."),
+            Self::InvalidMigrationDescriptor(e) => {
+                write!(f, "Cannot migrate to this descriptor: {e}.")
+            }
+            Self::NoCoinToMigrate => write!(f, "No confirmed coin to migrate."),
+            Self::CoinsNotMigratable(ops) => write!(
+                f,
+                "Coins at {} are unconfirmed or immature and would be left out. They must be confirmed and mature before migrating.",
+                ops.iter()
+                    .map(|op| format!("'{op}'"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::AlreadyRetired => write!(f, "This wallet was already migrated and retired."),
+            Self::InvalidPsbt(e) => write!(f, "Invalid PSBT: {e}"),
+            Self::InvalidFeeSubtraction(addr) => write!(
+                f,
+                "Cannot subtract the fee from '{addr}', which is not one of the destinations."
+            ),
+            Self::UnknownAddress(addr) => write!(f, "Address '{addr}' is not from this wallet."),
+            Self::MessageSignature(e) => write!(f, "Message signature error: {e}"),
+            Self::ReservesProof(e) => write!(f, "{e}"),
+            Self::InvalidHeight(h) => write!(f, "Invalid block height: {h}."),
+            Self::UtxoLookup(e) => write!(f, "Error looking up the UTXO set: {e}"),
+            Self::NotUnspentAtHeight(op, h) => {
+                write!(
+                    f,
+                    "Coin '{op}' is not in the UTXO set or was not confirmed at height {h}."
+                )
+            }
+            Self::SilentPayment(e) => write!(f, "{e}"),
+            Self::UnsignedInput(op) => write!(f, "Input spending coin '{op}' is not signed."),
         }
     }
 }
@@ -141,6 +235,18 @@ impl From<SpendCreationError> for CommandError {
     }
 }
 
+impl From<ReservesProofError> for CommandError {
+    fn from(e: ReservesProofError) -> Self {
+        CommandError::ReservesProof(e)
+    }
+}
+
+impl From<SilentPaymentError> for CommandError {
+    fn from(e: SilentPaymentError) -> Self {
+        CommandError::SilentPayment(e)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RbfErrorInfo {
     MissingFeerate,
@@ -198,6 +304,29 @@ impl TxGetter for DbTxGetter<'_> {
     }
 }
 
+// Signature progress for a spending path of a PSBT, along with the signers yet to sign.
+fn path_analysis(
+    path_info: &descriptors::PathInfo,
+    spend_info: &descriptors::PathSpendInfo,
+    timelock: Option<u16>,
+    timelock_ready: bool,
+) -> PsbtPathAnalysis {
+    let (_, origins) = path_info.thresh_origins();
+    let mut missing_signers: Vec<bip32::Fingerprint> = origins
+        .into_keys()
+        .filter(|fg| !spend_info.signed_pubkeys.contains_key(fg))
+        .collect();
+    missing_signers.sort();
+    PsbtPathAnalysis {
+        timelock,
+        threshold: spend_info.threshold,
+        sigs_count: spend_info.sigs_count,
+        signed_pubkeys: spend_info.signed_pubkeys.clone(),
+        missing_signers,
+        timelock_ready,
+    }
+}
+
 fn coin_to_candidate(
     coin: &Coin,
     must_select: bool,
@@ -215,6 +344,19 @@ fn coin_to_candidate(
     }
 }
 
+/// The coins spent by the Spend transactions stored in database. They are accounted for as
+/// spending in the balance.
+pub(crate) fn draft_spends_inputs(
+    db_conn: &mut Box<dyn DatabaseConnection>,
+) -> HashSet<bitcoin::OutPoint> {
+    db_conn
+        .list_spend()
+        .into_iter()
+        .flat_map(|(psbt, _)| psbt.unsigned_tx.input.into_iter())
+        .map(|txin| txin.previous_output)
+        .collect()
+}
+
 impl DaemonControl {
     // Get the derived descriptor for this coin
     fn derived_desc(&self, coin: &Coin) -> descriptors::DerivedSinglePathLianaDesc {
@@ -226,6 +368,39 @@ impl DaemonControl {
         desc.derive(coin.derivation_index, &self.secp)
     }
 
+    // Whether this PSBT input spends a coin from our descriptor, according to the derivation
+    // index of its keys and the spent output it provides.
+    fn psbt_in_is_ours(&self, psbt_in: &PsbtIn, txin: &bitcoin::TxIn) -> bool {
+        let spent_txo = psbt_in.witness_utxo.as_ref().or_else(|| {
+            psbt_in
+                .non_witness_utxo
+                .as_ref()
+                .filter(|prev_tx| prev_tx.compute_txid() == txin.previous_output.txid)
+                .and_then(|prev_tx| prev_tx.output.get(txin.previous_output.vout as usize))
+        });
+        let der_index = psbt_in
+            .bip32_derivation
+            .values()
+            .map(|(_, der_path)| der_path)
+            .chain(
+                psbt_in
+                    .tap_key_origins
+                    .values()
+                    .map(|(_, (_, der_path))| der_path),
+            )
+            .next()
+            .and_then(|der_path| der_path.into_iter().last().copied());
+        match (spent_txo, der_index) {
+            (Some(txo), Some(index)) if index.is_normal() => [
+                self.config.main_descriptor.receive_descriptor(),
+                self.config.main_descriptor.change_descriptor(),
+            ]
+            .iter()
+            .any(|desc| desc.derive(index, &self.secp).script_pubkey() == txo.script_pubkey),
+            _ => false,
+        }
+    }
+
     // Check whether this address is valid for the network we are operating on.
     fn validate_address(
         &self,
@@ -237,6 +412,20 @@ impl DaemonControl {
             .map_err(CommandError::Address)
     }
 
+    // Check a silent payment address is for our network and our coins can be used to pay to it.
+    // The output can only be derived from the keys of the inputs, which is only possible for
+    // coins spent through the Taproot key path.
+    fn validate_silent_payment_address(
+        &self,
+        addr: &SilentPaymentAddress,
+    ) -> Result<(), CommandError> {
+        if !addr.is_valid_for_network(self.config.bitcoin_config.network) {
+            return Err(SilentPaymentError::InvalidNetwork.into());
+        }
+        silent_payments::check_descriptor(&self.config.main_descriptor)?;
+        Ok(())
+    }
+
     // Get details about this address, if we know about it.
     fn addr_info(
         &self,
@@ -338,6 +527,8 @@ impl DaemonControl {
             last_poll_timestamp: wallet.last_poll_timestamp,
             receive_index,
             change_index,
+            retired_timestamp: wallet.retired_timestamp,
+            electrum_servers: self.bitcoin.electrum_servers(),
         }
     }
 
@@ -588,6 +779,7 @@ impl DaemonControl {
                     is_immature,
                     is_change,
                     is_from_self,
+                    is_quarantined,
                     derivation_index,
                     ..
                 } = coin;
@@ -609,20 +801,142 @@ impl DaemonControl {
                     is_immature,
                     is_change,
                     is_from_self,
+                    is_quarantined,
                 }
             })
             .collect();
         ListCoinsResult { coins }
     }
 
+    /// Release coins from quarantine, making them available to automatic coin selection again.
+    pub fn release_coins(&self, outpoints: &[bitcoin::OutPoint]) -> Result<(), CommandError> {
+        let mut db_conn = self.db.connection();
+        let coins = db_conn.coins(&[], outpoints);
+        if let Some(op) = outpoints.iter().find(|op| !coins.contains_key(op)) {
+            return Err(CommandError::UnknownOutpoint(*op));
+        }
+        db_conn.release_coins(outpoints);
+        Ok(())
+    }
+
+    /// Get the balance of the wallet, by status of the coins. Coins used as inputs of a stored
+    /// Spend transaction, or of an unconfirmed one, are accounted as "spending".
+    ///
+    /// For each recovery path this also gives the value of the coins which could be swept through
+    /// it at the next block (as with [`DaemonControl::create_recovery`]), and of those which
+    /// become so within the next `window` blocks.
+    pub fn get_balance(&self, window: u32) -> GetBalanceResult {
+        let mut db_conn = self.db.connection();
+        let coins = db_conn.coins(
+            &[
+                CoinStatus::Unconfirmed,
+                CoinStatus::Confirmed,
+                CoinStatus::Spending,
+            ],
+            &[],
+        );
+        let drafts_inputs = draft_spends_inputs(&mut db_conn);
+        let next_height = self.bitcoin.chain_tip().height + 1;
+        let window_blocks: i32 = window.try_into().unwrap_or(i32::MAX);
+
+        let mut res = GetBalanceResult {
+            confirmed: bitcoin::Amount::ZERO,
+            unconfirmed: bitcoin::Amount::ZERO,
+            immature: bitcoin::Amount::ZERO,
+            spending: bitcoin::Amount::ZERO,
+            window,
+            recovery_paths: self
+                .config
+                .main_descriptor
+                .policy()
+                .recovery_paths()
+                .keys()
+                .map(|timelock| RecoveryPathBalance {
+                    timelock: *timelock,
+                    spendable: bitcoin::Amount::ZERO,
+                    spendable_within_window: bitcoin::Amount::ZERO,
+                })
+                .collect(),
+        };
+        for coin in coins.values() {
+            // Like for `createrecovery`, a stored Spend transaction doesn't prevent sweeping a
+            // confirmed coin through a recovery path. Immature coins can't be swept yet.
+            if let (Some(block), false, false) =
+                (coin.block_info, coin.is_spent(), coin.is_immature)
+            {
+                for path in res.recovery_paths.iter_mut() {
+                    let available_at = block.height.saturating_add(path.timelock.into());
+                    if next_height >= available_at {
+                        path.spendable += coin.amount;
+                    } else if next_height.saturating_add(window_blocks) >= available_at {
+                        path.spendable_within_window += coin.amount;
+                    }
+                }
+            }
+
+            if coin.is_spent() || drafts_inputs.contains(&coin.outpoint) {
+                res.spending += coin.amount;
+            } else if coin.is_immature {
+                res.immature += coin.amount;
+            } else if coin.is_confirmed() {
+                res.confirmed += coin.amount;
+            } else {
+                res.unconfirmed += coin.amount;
+            }
+        }
+
+        res
+    }
+
+    /// Create a transaction spending some of our coins.
+    ///
+    /// `send_max_to` is an optional destination receiving all the value of the coins left after
+    /// paying the other destinations and the fee. If set and no `coins_outpoints` are given, all
+    /// the coins which would be candidates for coin selection are spent. `change_address` is
+    /// unused in this case.
+    ///
+    /// The fee is subtracted from the amount sent to the destinations in `subtract_fee_from`,
+    /// proportionally to their amount, instead of being paid on top of it.
     pub fn create_spend(
         &self,
         destinations: &HashMap<bitcoin::Address<bitcoin::address::NetworkUnchecked>, u64>,
         coins_outpoints: &[bitcoin::OutPoint],
         feerate_vb: u64,
         change_address: Option<bitcoin::Address<bitcoin::address::NetworkUnchecked>>,
+        send_max_to: Option<bitcoin::Address<bitcoin::address::NetworkUnchecked>>,
+        subtract_fee_from: &[bitcoin::Address<bitcoin::address::NetworkUnchecked>],
+    ) -> Result<CreateSpendResult, CommandError> {
+        self.create_spend_with_options(
+            destinations,
+            coins_outpoints,
+            feerate_vb,
+            change_address,
+            send_max_to,
+            subtract_fee_from,
+            &CreateSpendOptions::default(),
+        )
+    }
+
+    /// Same as [`DaemonControl::create_spend`], with advanced options for the transaction.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_spend_with_options(
+        &self,
+        destinations: &HashMap<bitcoin::Address<bitcoin::address::NetworkUnchecked>, u64>,
+        coins_outpoints: &[bitcoin::OutPoint],
+        feerate_vb: u64,
+        change_address: Option<bitcoin::Address<bitcoin::address::NetworkUnchecked>>,
+        send_max_to: Option<bitcoin::Address<bitcoin::address::NetworkUnchecked>>,
+        subtract_fee_from: &[bitcoin::Address<bitcoin::address::NetworkUnchecked>],
+        options: &CreateSpendOptions,
     ) -> Result<CreateSpendResult, CommandError> {
-        let is_self_send = destinations.is_empty();
+        // The output paying to a silent payment address can only be derived from the keys of the
+        // inputs, which isn't possible for the coins of all wallets. Check it before anything
+        // else to return a clear error.
+        for addr in options.silent_payments.keys() {
+            self.validate_silent_payment_address(addr)?;
+        }
+        let is_self_send =
+            destinations.is_empty() && send_max_to.is_none() && options.silent_payments.is_empty();
         // For self-send, the coins must be specified.
         if is_self_send && coins_outpoints.is_empty() {
             return Err(CommandError::NoOutpointForSelfSend);
@@ -634,13 +948,48 @@ impl DaemonControl {
         let mut tx_getter = DbTxGetter::new(&self.db);
 
         // Prepare the destination addresses.
-        let mut destinations_checked = Vec::with_capacity(destinations.len());
+        if let Some(addr) = subtract_fee_from
+            .iter()
+            .find(|addr| !destinations.contains_key(addr))
+        {
+            return Err(CommandError::InvalidFeeSubtraction(
+                addr.assume_checked_ref().to_string(),
+            ));
+        }
+        let mut destinations_checked = Vec::with_capacity(destinations.len() + 1);
         for (address, value_sat) in destinations {
-            let address = self.validate_address(address.clone())?;
             let amount = bitcoin::Amount::from_sat(*value_sat);
+            let amount = if subtract_fee_from.contains(address) {
+                DestinationAmount::SubtractFee(amount)
+            } else {
+                DestinationAmount::Fixed(amount)
+            };
+            let address = self.validate_address(address.clone())?;
             let address = self.spend_addr(&mut db_conn, address);
             destinations_checked.push((address, amount));
         }
+        if let Some(address) = send_max_to {
+            let address = self.validate_address(address)?;
+            let address = self.spend_addr(&mut db_conn, address);
+            destinations_checked.push((address, DestinationAmount::Max));
+        }
+        // The outputs paying to silent payment addresses are created with a placeholder script,
+        // until they are derived by the signer.
+        for (sp_addr, value_sat) in &options.silent_payments {
+            let addr = bitcoin::Address::from_script(
+                &sp_addr.placeholder_script(),
+                self.config.bitcoin_config.network,
+            )
+            .expect("A P2TR script is a valid address.");
+            if destinations_checked
+                .iter()
+                .any(|(dest, _)| dest.addr == addr)
+            {
+                return Err(SilentPaymentError::DuplicateSpendKey.into());
+            }
+            let amount = DestinationAmount::Fixed(bitcoin::Amount::from_sat(*value_sat));
+            destinations_checked.push((SpendOutputAddress { addr, info: None }, amount));
+        }
 
         // The change address to be used if a change output needs to be created. It may be
         // specified by the caller (for instance for the purpose of a sweep, or to avoid us
@@ -664,7 +1013,9 @@ impl DaemonControl {
                 .coins(&[CoinStatus::Unconfirmed, CoinStatus::Confirmed], &[])
                 .into_iter()
                 .filter_map(|(op, c)| {
-                    if c.block_info.is_some() {
+                    if c.is_quarantined {
+                        None
+                    } else if c.block_info.is_some() {
                         Some((c, None)) // confirmed coins have no ancestor info
                     } else if c.is_from_self {
                         // In case the mempool_entry is None, the coin will be included without
@@ -707,6 +1058,9 @@ impl DaemonControl {
                 if coin.is_immature {
                     return Err(CommandError::ImmatureCoinbase(*op));
                 }
+                if coin.is_quarantined {
+                    return Err(CommandError::QuarantinedCoin(*op));
+                }
             }
             coins
                 .into_iter()
@@ -743,9 +1097,11 @@ impl DaemonControl {
         // derivation index in case any address in the transaction outputs was ours and from the
         // future.
         let change_info = change_address.info;
-        let locktime = self.anti_fee_sniping_locktime();
+        let locktime = options
+            .locktime
+            .unwrap_or_else(|| self.anti_fee_sniping_locktime());
         let CreateSpendRes {
-            psbt,
+            mut psbt,
             has_change,
             warnings,
         } = match create_spend(
@@ -757,6 +1113,7 @@ impl DaemonControl {
             SpendTxFees::Regular(feerate_vb),
             change_address,
             locktime,
+            &options.spend,
         ) {
             Ok(res) => res,
             Err(SpendCreationError::CoinSelection(e)) => {
@@ -772,6 +1129,17 @@ impl DaemonControl {
         if has_change {
             self.maybe_increase_last_deriv_index(&mut db_conn, &change_info);
         }
+        for sp_addr in options.silent_payments.keys() {
+            let script = sp_addr.placeholder_script();
+            if let Some(i) = psbt
+                .unsigned_tx
+                .output
+                .iter()
+                .position(|txo| txo.script_pubkey == script)
+            {
+                silent_payments::set_psbt_output_info(&mut psbt.outputs[i], sp_addr);
+            }
+        }
 
         Ok(CreateSpendResult::Success {
             psbt,
@@ -780,8 +1148,20 @@ impl DaemonControl {
     }
 
     pub fn update_spend(&self, mut psbt: Psbt) -> Result<(), CommandError> {
+        // The signatures would commit to the placeholder outputs of the silent payments, which
+        // must be derived first.
+        let is_signed = psbt.inputs.iter().any(|psbtin| {
+            !psbtin.partial_sigs.is_empty()
+                || !psbtin.tap_script_sigs.is_empty()
+                || psbtin.tap_key_sig.is_some()
+        });
+        if is_signed && silent_payments::has_underived_outputs(&psbt) {
+            return Err(SilentPaymentError::UnderivedOutputs.into());
+        }
+
         let mut db_conn = self.db.connection();
         let tx = &psbt.unsigned_tx;
+        let mut replaced_spend = None;
 
         // If the transaction already exists in DB, merge the signatures for each input on a best
         // effort basis.
@@ -829,11 +1209,43 @@ impl DaemonControl {
                     }
                 }
             }
+            // It may be a stored Spend once its outputs paying to silent payment addresses were
+            // derived, in which case it replaces it.
+            replaced_spend = db_conn
+                .list_spend()
+                .into_iter()
+                .map(|(db_psbt, _)| db_psbt)
+                .find(|db_psbt| silent_payments::is_derived_from(tx, db_psbt));
         }
 
         // Finally, insert (or update) the PSBT in database.
         db_conn.store_spend(&psbt);
 
+        // Carry over the labels of the replaced Spend and of its outputs, which are the same.
+        if let Some(replaced_psbt) = replaced_spend {
+            let replaced_txid = replaced_psbt.unsigned_tx.compute_txid();
+            let items: Vec<(LabelItem, LabelItem)> =
+                iter::once((LabelItem::Txid(replaced_txid), LabelItem::Txid(txid)))
+                    .chain((0..replaced_psbt.unsigned_tx.output.len()).map(|vout| {
+                        let vout = vout.try_into().expect("Number of outputs must fit in u32");
+                        (
+                            LabelItem::OutPoint(bitcoin::OutPoint::new(replaced_txid, vout)),
+                            LabelItem::OutPoint(bitcoin::OutPoint::new(txid, vout)),
+                        )
+                    }))
+                    .collect();
+            let labels = db_conn.labels(&items.iter().map(|(item, _)| item.clone()).collect());
+            let mut updated_labels = HashMap::new();
+            for (replaced_item, item) in items {
+                if let Some(label) = labels.get(&replaced_item.to_string()) {
+                    updated_labels.insert(item, Some(label.clone()));
+                    updated_labels.insert(replaced_item, None);
+                }
+            }
+            db_conn.update_labels(&updated_labels);
+            db_conn.delete_spend(&replaced_txid);
+        }
+
         Ok(())
     }
 
@@ -856,6 +1268,251 @@ impl DaemonControl {
         }
     }
 
+    /// Analyze the coins and transactions of the wallet for privacy leaks: addresses which
+    /// received more than once, transactions which spent together coins carrying different
+    /// labels, change outputs which can be told apart from the payments and coins which can be
+    /// traced back to a KYC source, as designated by the labels set in the configuration. Each
+    /// finding comes with a suggested remediation.
+    pub fn privacy_report(&self) -> PrivacyReportResult {
+        let network = self.config.bitcoin_config.network;
+        let mut db_conn = self.db.connection();
+        let coins = db_conn.coins(&[], &[]);
+        let addresses: HashMap<bitcoin::OutPoint, bitcoin::Address> = coins
+            .values()
+            .map(|c| (c.outpoint, self.derived_desc(c).address(network)))
+            .collect();
+        let label_items: HashSet<LabelItem> = coins
+            .values()
+            .flat_map(|c| {
+                [
+                    LabelItem::OutPoint(c.outpoint),
+                    LabelItem::Txid(c.outpoint.txid),
+                    LabelItem::Address(addresses[&c.outpoint].clone()),
+                ]
+            })
+            .collect();
+        let labels = db_conn.labels(&label_items);
+        // The labels of a coin, by order of precedence: its own, the one of its deposit
+        // transaction and the one of its address.
+        let coin_labels = |c: &Coin| -> Vec<String> {
+            [
+                c.outpoint.to_string(),
+                c.outpoint.txid.to_string(),
+                addresses[&c.outpoint].to_string(),
+            ]
+            .iter()
+            .filter_map(|item| labels.get(item))
+            .filter(|label| !label.is_empty())
+            .cloned()
+            .collect()
+        };
+        // Our coins created by each transaction, ordered by outpoint.
+        let mut coins_by_txid: HashMap<bitcoin::Txid, Vec<&Coin>> = HashMap::new();
+        for c in coins.values() {
+            coins_by_txid.entry(c.outpoint.txid).or_default().push(c);
+        }
+        for created in coins_by_txid.values_mut() {
+            created.sort_by_key(|c| c.outpoint);
+        }
+        let mut findings = Vec::new();
+
+        // Addresses which received more than once.
+        let mut coins_by_address: BTreeMap<(bool, ChildNumber), Vec<bitcoin::OutPoint>> =
+            BTreeMap::new();
+        for c in coins.values() {
+            coins_by_address
+                .entry((c.is_change, c.derivation_index))
+                .or_default()
+                .push(c.outpoint);
+        }
+        for mut outpoints in coins_by_address.into_values().filter(|ops| ops.len() > 1) {
+            outpoints.sort();
+            let address = addresses[&outpoints[0]].clone();
+            findings.push(PrivacyFinding {
+                kind: PrivacyFindingKind::AddressReuse,
+                description: format!("Address {} received {} coins.", address, outpoints.len()),
+                txid: None,
+                labels: labels
+                    .get(&address.to_string())
+                    .cloned()
+                    .into_iter()
+                    .collect(),
+                address: Some(address.as_unchecked().clone()),
+                outpoints,
+                remediation: "Stop sharing this address and use a new one for every payment. \
+                              The coins it received are publicly linked to each other."
+                    .to_string(),
+            });
+        }
+
+        // The coins spent by each of our transactions.
+        let mut coins_by_spend: BTreeMap<bitcoin::Txid, Vec<&Coin>> = BTreeMap::new();
+        for c in coins.values() {
+            if let Some(txid) = c.spend_txid {
+                coins_by_spend.entry(txid).or_default().push(c);
+            }
+        }
+
+        // Transactions which merged coins from different sources.
+        for (txid, spent) in &coins_by_spend {
+            let sources: BTreeSet<String> = spent
+                .iter()
+                .filter_map(|c| coin_labels(c).into_iter().next())
+                .collect();
+            if sources.len() > 1 {
+                let mut outpoints: Vec<_> = spent.iter().map(|c| c.outpoint).collect();
+                outpoints.sort();
+                findings.push(PrivacyFinding {
+                    kind: PrivacyFindingKind::MergedLabels,
+                    description: format!(
+                        "Transaction {} spent together coins labelled differently: {}.",
+                        txid,
+                        sources
+                            .iter()
+                            .map(|l| format!("'{l}'"))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    address: None,
+                    txid: Some(*txid),
+                    outpoints,
+                    labels: sources.into_iter().collect(),
+                    remediation: "Spending coins from different sources together reveals they \
+                                  belong to the same person. Use coin control to spend coins \
+                                  from a single source at a time."
+                        .to_string(),
+                });
+            }
+        }
+
+        // Transactions whose change can be told apart from the payments.
+        let spend_txids: Vec<_> = coins_by_spend.keys().copied().collect();
+        let mut spend_txs: Vec<_> = db_conn
+            .list_wallet_transactions(&spend_txids)
+            .into_iter()
+            .map(|(tx, _, _)| (tx.compute_txid(), tx))
+            .collect();
+        spend_txs.sort_by_key(|(txid, _)| *txid);
+        for (txid, tx) in spend_txs {
+            let change = coins_by_txid
+                .get(&txid)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let change_indexes: Vec<usize> =
+                change.iter().map(|c| c.outpoint.vout as usize).collect();
+            let leaks = privacy::change_leaks(&tx, &change_indexes);
+            if leaks.is_empty() {
+                continue;
+            }
+            let remediation = leaks
+                .iter()
+                .map(|leak| match leak {
+                    privacy::ChangeLeak::RoundPayments => {
+                        "Avoid paying round amounts, or select coins matching the amount to pay \
+                         so no change is needed."
+                    }
+                    privacy::ChangeLeak::ScriptTypeMismatch => {
+                        "When possible, ask payees for an address of the same type as those of \
+                         this wallet."
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            findings.push(PrivacyFinding {
+                kind: PrivacyFindingKind::IdentifiableChange,
+                description: format!(
+                    "The change of transaction {} can be told apart from the payments: {}.",
+                    txid,
+                    leaks
+                        .iter()
+                        .map(|leak| leak.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" and ")
+                ),
+                address: None,
+                txid: Some(txid),
+                outpoints: change.iter().map(|c| c.outpoint).collect(),
+                labels: Vec::new(),
+                remediation,
+            });
+        }
+
+        // Coins which can be traced back to a KYC source, either directly through their labels or
+        // because they were created by a transaction spending such a coin.
+        let kyc_labels = self
+            .config
+            .privacy
+            .as_ref()
+            .map(|privacy| privacy.kyc_labels.as_slice())
+            .unwrap_or_default();
+        let mut kyc_sources: HashMap<bitcoin::OutPoint, BTreeSet<String>> = coins
+            .values()
+            .filter_map(|c| {
+                let sources: BTreeSet<_> = coin_labels(c)
+                    .into_iter()
+                    .filter(|l| privacy::is_kyc_label(l, kyc_labels))
+                    .collect();
+                (!sources.is_empty()).then_some((c.outpoint, sources))
+            })
+            .collect();
+        loop {
+            let mut linked = Vec::new();
+            for (txid, spent) in &coins_by_spend {
+                let sources: BTreeSet<String> = spent
+                    .iter()
+                    .filter_map(|c| kyc_sources.get(&c.outpoint))
+                    .flatten()
+                    .cloned()
+                    .collect();
+                if sources.is_empty() {
+                    continue;
+                }
+                for c in coins_by_txid.get(txid).into_iter().flatten() {
+                    if !kyc_sources
+                        .get(&c.outpoint)
+                        .is_some_and(|s| s.is_superset(&sources))
+                    {
+                        linked.push((c.outpoint, sources.clone()));
+                    }
+                }
+            }
+            if linked.is_empty() {
+                break;
+            }
+            for (outpoint, sources) in linked {
+                kyc_sources.entry(outpoint).or_default().extend(sources);
+            }
+        }
+        let mut kyc_coins: Vec<_> = kyc_sources
+            .into_iter()
+            .filter(|(op, _)| coins[op].spend_txid.is_none())
+            .collect();
+        kyc_coins.sort_by_key(|(op, _)| *op);
+        for (outpoint, sources) in kyc_coins {
+            findings.push(PrivacyFinding {
+                kind: PrivacyFindingKind::KycLinkedCoin,
+                description: format!(
+                    "Coin {} can be traced back to a KYC source: {}.",
+                    outpoint,
+                    sources
+                        .iter()
+                        .map(|l| format!("'{l}'"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                address: None,
+                txid: None,
+                outpoints: vec![outpoint],
+                labels: sources.into_iter().collect(),
+                remediation: "This coin is linked to your identity. Don't spend it together with \
+                              coins you want to keep private."
+                    .to_string(),
+            });
+        }
+
+        PrivacyReportResult { findings }
+    }
+
     pub fn list_spend(
         &self,
         txids: Option<Vec<bitcoin::Txid>>,
@@ -889,6 +1546,161 @@ impl DaemonControl {
         db_conn.delete_spend(txid);
     }
 
+    /// Analyze a PSBT spending coins from our descriptor. The PSBT need not be one of our stored
+    /// Spend transactions and nothing is stored.
+    pub fn analyze_psbt(&self, psbt: &Psbt) -> Result<AnalyzePsbtResult, CommandError> {
+        let desc = &self.config.main_descriptor;
+        let spend_info = desc
+            .partial_spend_info(psbt)
+            .map_err(|e| CommandError::InvalidPsbt(e.to_string()))?;
+        let tx = &psbt.unsigned_tx;
+
+        // Don't derive any amount from a previous transaction which isn't the one spent by the
+        // input, or which contradicts the spent output provided along with it.
+        for (txin, psbt_in) in tx.input.iter().zip(psbt.inputs.iter()) {
+            let op = txin.previous_output;
+            if let Some(prev_tx) = psbt_in.non_witness_utxo.as_ref() {
+                if prev_tx.compute_txid() != op.txid {
+                    return Err(CommandError::InvalidPsbt(format!(
+                        "The non-witness UTXO of the input spending '{op}' is another transaction."
+                    )));
+                }
+                if let Some(txo) = psbt_in.witness_utxo.as_ref() {
+                    if prev_tx.output.get(op.vout as usize) != Some(txo) {
+                        return Err(CommandError::InvalidPsbt(format!(
+                            "The witness UTXO of the input spending '{op}' doesn't match its \
+                             non-witness UTXO."
+                        )));
+                    }
+                }
+            }
+        }
+
+        // Use the value from our database for our own coins, or otherwise that of the spent
+        // output provided in the PSBT input if any.
+        let outpoints: Vec<bitcoin::OutPoint> =
+            tx.input.iter().map(|txin| txin.previous_output).collect();
+        let coins = self.db.connection().coins_by_outpoints(&outpoints);
+        let inputs: Vec<AnalyzePsbtInput> = tx
+            .input
+            .iter()
+            .zip(psbt.inputs.iter())
+            .map(|(txin, psbt_in)| {
+                let op = txin.previous_output;
+                let coin = coins.get(&op);
+                let amount = coin.map(|c| c.amount).or_else(|| {
+                    psbt_in
+                        .witness_utxo
+                        .as_ref()
+                        .or_else(|| {
+                            psbt_in
+                                .non_witness_utxo
+                                .as_ref()
+                                .and_then(|prev_tx| prev_tx.output.get(op.vout as usize))
+                        })
+                        .map(|txo| txo.value)
+                });
+                AnalyzePsbtInput {
+                    outpoint: op,
+                    amount,
+                    is_ours: coin.is_some(),
+                }
+            })
+            .collect();
+
+        // The fee can only be computed if we know the value of all inputs.
+        let in_value = inputs
+            .iter()
+            .try_fold(bitcoin::Amount::ZERO, |total, input| {
+                input.amount.and_then(|a| total.checked_add(a))
+            });
+        let out_value = tx
+            .output
+            .iter()
+            .try_fold(bitcoin::Amount::ZERO, |total, txo| {
+                total.checked_add(txo.value)
+            });
+        let fee = in_value
+            .zip(out_value)
+            .and_then(|(in_value, out_value)| in_value.checked_sub(out_value));
+
+        // Finalize a copy of the PSBT to know whether it's ready, and to get the actual size of
+        // the transaction. Otherwise estimate its size for the path it's meant to be spent with.
+        // The sequence is set to the timelock of this path, so it's the last available one.
+        let mut final_psbt = psbt.clone();
+        let can_finalize = final_psbt.finalize_mut(&self.secp).is_ok();
+        let vbytes = if can_finalize {
+            final_psbt.extract_tx_unchecked_fee_rate().vsize() as u64
+        } else {
+            let timelock = spend_info.recovery_paths().keys().next_back().copied();
+            desc.unsigned_tx_max_vbytes_for_path(tx, timelock)
+                .expect("Recovery path is from this descriptor.")
+        };
+        let feerate_vb = fee.map(|fee| fee.to_sat() / vbytes);
+
+        // A recovery path is only ready once all the coins spent have been confirmed for long
+        // enough.
+        let tip = self.bitcoin.chain_tip();
+        let next_height = tip.height + 1;
+        let coins_heights: Option<Vec<i32>> = outpoints
+            .iter()
+            .map(|op| {
+                coins
+                    .get(op)
+                    .and_then(|c| c.block_info)
+                    .map(|block| block.height)
+            })
+            .collect();
+        let policy = desc.policy();
+        let mut paths = vec![path_analysis(
+            policy.primary_path(),
+            spend_info.primary_path(),
+            None,
+            true,
+        )];
+        for (timelock, path_spend_info) in spend_info.recovery_paths() {
+            let path_info = policy
+                .recovery_paths()
+                .get(timelock)
+                .expect("Spend info is for a path of this descriptor.");
+            let timelock_ready = coins_heights.as_ref().is_some_and(|heights| {
+                heights
+                    .iter()
+                    .all(|h| next_height >= h.saturating_add((*timelock).into()))
+            });
+            paths.push(path_analysis(
+                path_info,
+                path_spend_info,
+                Some(*timelock),
+                timelock_ready,
+            ));
+        }
+        // Like in Bitcoin Core, time-based locktimes are checked against the median time past
+        // (BIP113).
+        let locktime_ready = match tx.lock_time {
+            LockTime::Blocks(h) => i64::from(h.to_consensus_u32()) <= i64::from(tip.height),
+            LockTime::Seconds(t) => self
+                .bitcoin
+                .tip_median_time_past()
+                .is_some_and(|time| t.to_consensus_u32() <= time),
+        };
+
+        Ok(AnalyzePsbtResult {
+            txid: tx.compute_txid(),
+            inputs,
+            change_indexes: desc
+                .change_indexes(psbt, &self.secp)
+                .into_iter()
+                .map(|c| c.index())
+                .collect(),
+            fee,
+            feerate_vb,
+            paths,
+            locktime_ready,
+            can_finalize,
+        })
+    }
+
     /// Finalize and broadcast this stored Spend transaction.
     pub fn broadcast_spend(&self, txid: &bitcoin::Txid) -> Result<(), CommandError> {
         let mut db_conn = self.db.connection();
@@ -898,6 +1710,9 @@ impl DaemonControl {
         let mut spend_psbt = db_conn
             .spend_tx(txid)
             .ok_or(CommandError::UnknownSpend(*txid))?;
+        if silent_payments::has_underived_outputs(&spend_psbt) {
+            return Err(SilentPaymentError::UnderivedOutputs.into());
+        }
         spend_psbt.finalize_mut(&self.secp).map_err(|e| {
             CommandError::SpendFinalization(
                 e.into_iter()
@@ -911,58 +1726,387 @@ impl DaemonControl {
         // error at broadcast time).
         // These checks are already performed at Spend creation time. TODO: a belt-and-suspenders is still worth it though.
         let final_tx = spend_psbt.extract_tx_unchecked_fee_rate();
+        self.broadcast_tx(&final_tx)?;
+
+        Ok(())
+    }
+
+    /// Finalize a PSBT spending coins from our descriptor, which need not be stored, and return
+    /// the final transaction.
+    pub fn finalize_psbt(&self, mut psbt: Psbt) -> Result<FinalizePsbtResult, CommandError> {
+        if psbt.inputs.len() != psbt.unsigned_tx.input.len() {
+            return Err(CommandError::InvalidPsbt(
+                "inputs count doesn't match the transaction's".to_string(),
+            ));
+        }
+        for (i, (psbt_in, txin)) in psbt
+            .inputs
+            .iter()
+            .zip(psbt.unsigned_tx.input.iter())
+            .enumerate()
+        {
+            if !self.psbt_in_is_ours(psbt_in, txin) {
+                return Err(CommandError::InvalidPsbt(format!(
+                    "input {i} doesn't spend a coin from this wallet"
+                )));
+            }
+        }
+
+        psbt.finalize_mut(&self.secp).map_err(|e| {
+            CommandError::SpendFinalization(
+                e.into_iter()
+                    .next()
+                    .map(|e| e.to_string())
+                    .unwrap_or_default(),
+            )
+        })?;
+        let tx = psbt.extract_tx_unchecked_fee_rate();
+
+        Ok(FinalizePsbtResult {
+            txid: tx.compute_txid(),
+            tx,
+        })
+    }
+
+    /// Broadcast this transaction through our Bitcoin backend, and update our state with its
+    /// changes. The transaction need not spend any of our coins.
+    pub fn broadcast_tx(&self, tx: &bitcoin::Transaction) -> Result<bitcoin::Txid, CommandError> {
         self.bitcoin
-            .broadcast_tx(&final_tx)
+            .broadcast_tx(tx)
             .map_err(CommandError::TxBroadcast)?;
 
-        // Finally, update our state with the changes from this transaction.
-        let (tx, rx) = mpsc::sync_channel(0);
-        if let Err(e) = self.poller_sender.send(PollerMessage::PollNow(tx)) {
+        let (sender, receiver) = mpsc::sync_channel(0);
+        if let Err(e) = self.poller_sender.send(PollerMessage::PollNow(sender)) {
             log::error!("Error requesting update from poller: {}", e);
         }
-        if let Err(e) = rx.recv() {
+        if let Err(e) = receiver.recv() {
             log::error!("Error receiving completion signal from poller: {}", e);
         }
 
-        Ok(())
+        Ok(tx.compute_txid())
     }
 
-    /// Create PSBT to replace the given transaction using RBF.
-    ///
-    /// `txid` must either point to a PSBT in our database (not necessarily broadcast) or an
-    /// unconfirmed spend transaction (whether or not any associated PSBT is saved in our database).
-    ///
-    /// `is_cancel` indicates whether to "cancel" the transaction by including only a single (change)
-    /// output in the replacement or otherwise to keep the same (non-change) outputs and simply
-    /// bump the fee.
-    /// If `true`, the only output of the RBF transaction will be change and the inputs will include
-    /// at least one of the inputs from the previous transaction. If `false`, all inputs from the previous
-    /// transaction will be used in the replacement.
-    /// In both cases:
-    /// - if the previous transaction includes a change output to one of our own change addresses,
-    ///   this same address will be used for change in the RBF transaction, if required. If the previous
-    ///   transaction pays to more than one of our change addresses, then the one receiving the highest
-    ///   value will be used as a change address and the others will be treated as non-change outputs.
-    /// - the RBF transaction may include additional confirmed coins as inputs if required
-    ///   in order to pay the higher fee (this applies also when replacing a self-send).
-    ///
-    /// `feerate_vb` is the target feerate for the RBF transaction (in sat/vb). If `None`, it will be set
-    /// to 1 sat/vb larger than the feerate of the previous transaction, which is the minimum value allowed
-    /// when using RBF.
-    pub fn rbf_psbt(
+    /// Broadcast these transactions together as a package through our Bitcoin backend, and update
+    /// our state with their changes. The package must be a child along with its unconfirmed
+    /// parents, parents first.
+    pub fn broadcast_package(
         &self,
-        txid: &bitcoin::Txid,
-        is_cancel: bool,
-        feerate_vb: Option<u64>,
-    ) -> Result<CreateSpendResult, CommandError> {
-        let mut db_conn = self.db.connection();
-        let mut tx_getter = DbTxGetter::new(&self.db);
-
+        txs: &[bitcoin::Transaction],
+    ) -> Result<Vec<bitcoin::Txid>, CommandError> {
+        self.bitcoin
+            .broadcast_package(txs)
+            .map_err(CommandError::TxBroadcast)?;
+
+        let (sender, receiver) = mpsc::sync_channel(0);
+        if let Err(e) = self.poller_sender.send(PollerMessage::PollNow(sender)) {
+            log::error!("Error requesting update from poller: {}", e);
+        }
+        if let Err(e) = receiver.recv() {
+            log::error!("Error receiving completion signal from poller: {}", e);
+        }
+
+        Ok(txs.iter().map(|tx| tx.compute_txid()).collect())
+    }
+
+    /// Schedule this fully signed transaction to be broadcast as soon as it becomes valid, for
+    /// instance once its absolute locktime or the relative timelock of one of its inputs matured.
+    /// It must only spend coins from this wallet. Scheduling a transaction again resets its state.
+    pub fn schedule_broadcast(&self, tx: &bitcoin::Transaction) -> Result<(), CommandError> {
+        let mut db_conn = self.db.connection();
+        let txid = tx.compute_txid();
+        let outpoints: Vec<_> = tx.input.iter().map(|txin| txin.previous_output).collect();
+        let coins = db_conn.coins_by_outpoints(&outpoints);
+        for txin in &tx.input {
+            let op = txin.previous_output;
+            let coin = coins.get(&op).ok_or(CommandError::UnknownOutpoint(op))?;
+            if coin.spend_txid.is_some_and(|spend_txid| spend_txid != txid) {
+                return Err(CommandError::AlreadySpent(op));
+            }
+            if txin.witness.is_empty() {
+                return Err(CommandError::UnsignedInput(op));
+            }
+        }
+
+        let now: u32 = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs().try_into().expect("Timestamp must fit in u32"))
+            .expect("Time measured now cannot be before unix epoch");
+        db_conn.schedule_broadcast(tx, now);
+        Ok(())
+    }
+
+    /// List the transactions scheduled for broadcast, along with their state at the current tip.
+    pub fn list_scheduled_broadcasts(&self) -> ListScheduledBroadcastsResult {
+        let mut db_conn = self.db.connection();
+        let tip_height = db_conn.chain_tip().map(|tip| tip.height).unwrap_or(0);
+        let tip_mtp = self.bitcoin.tip_median_time_past();
+        let transactions = db_conn
+            .scheduled_broadcasts()
+            .into_iter()
+            .map(|sched| {
+                let outpoints: Vec<_> = sched
+                    .tx
+                    .input
+                    .iter()
+                    .map(|txin| txin.previous_output)
+                    .collect();
+                let coins = db_conn.coins_by_outpoints(&outpoints);
+                let (status, valid_at_height, valid_at_time) = match sched
+                    .state(&coins, tip_height, tip_mtp)
+                {
+                    ScheduledBroadcastState::WaitingConfirmation => {
+                        (ScheduledBroadcastStatus::WaitingConfirmation, None, None)
+                    }
+                    ScheduledBroadcastState::Pending { height, time } => {
+                        (ScheduledBroadcastStatus::Pending, height, time)
+                    }
+                    ScheduledBroadcastState::Ready => (ScheduledBroadcastStatus::Ready, None, None),
+                    ScheduledBroadcastState::Broadcast => {
+                        (ScheduledBroadcastStatus::Broadcast, None, None)
+                    }
+                    ScheduledBroadcastState::Confirmed => {
+                        (ScheduledBroadcastStatus::Confirmed, None, None)
+                    }
+                    ScheduledBroadcastState::Conflicted => {
+                        (ScheduledBroadcastStatus::Conflicted, None, None)
+                    }
+                };
+                ScheduledBroadcastEntry {
+                    txid: sched.txid(),
+                    tx: sched.tx,
+                    status,
+                    valid_at_height,
+                    valid_at_time,
+                    created_at: sched.created_at,
+                    broadcast_at: sched.broadcast_at,
+                    last_error: sched.last_error,
+                }
+            })
+            .collect();
+        ListScheduledBroadcastsResult { transactions }
+    }
+
+    /// Remove a transaction from the broadcast schedule.
+    pub fn delete_scheduled_broadcast(&self, txid: &bitcoin::Txid) {
+        let mut db_conn = self.db.connection();
+        db_conn.delete_scheduled_broadcast(txid);
+    }
+
+    /// Sign a message for one of our addresses (BIP322).
+    ///
+    /// Without a `psbt`, returns the PSBT to be signed by the wallet's signers. Once it's signed,
+    /// pass it back as `psbt` to get the signature of the message.
+    pub fn sign_message(
+        &self,
+        address: bitcoin::Address<address::NetworkUnchecked>,
+        message: &str,
+        psbt: Option<&Psbt>,
+    ) -> Result<SignMessageResult, CommandError> {
+        let address = self.validate_address(address)?;
+        let (index, is_change) = self
+            .db
+            .connection()
+            .derivation_index_by_address(&address)
+            .ok_or_else(|| CommandError::UnknownAddress(address.to_string()))?;
+
+        if let Some(psbt) = psbt {
+            let signature = MessageSignature::from_psbt(
+                &self.secp,
+                psbt,
+                &address.script_pubkey(),
+                message.as_bytes(),
+            )
+            .map_err(|e| CommandError::MessageSignature(e.to_string()))?;
+            return Ok(SignMessageResult::Signature { signature });
+        }
+
+        let desc = &self.config.main_descriptor;
+        let der_desc = if is_change {
+            desc.change_descriptor()
+        } else {
+            desc.receive_descriptor()
+        }
+        .derive(index, &self.secp);
+        let psbt = bip322::message_psbt(&der_desc, desc.is_taproot(), message.as_bytes());
+        Ok(SignMessageResult::Psbt { psbt })
+    }
+
+    /// Check a message signature (BIP322) for any address.
+    pub fn verify_message(
+        &self,
+        address: bitcoin::Address<address::NetworkUnchecked>,
+        message: &str,
+        signature: &MessageSignature,
+    ) -> Result<VerifyMessageResult, CommandError> {
+        let address = self.validate_address(address)?;
+        Ok(VerifyMessageResult {
+            valid: signature.verify(&self.secp, &address, message.as_bytes()),
+        })
+    }
+
+    /// Create the PSBT of a proof of reserves (BIP127) committing to this challenge message, for
+    /// the given coins or all our confirmed coins if none is given.
+    ///
+    /// Once signed, the PSBT is turned into the proof using
+    /// [`DaemonControl::finalize_reserves_proof`].
+    pub fn create_reserves_proof(
+        &self,
+        message: &str,
+        outpoints: &[bitcoin::OutPoint],
+    ) -> Result<CreateReservesProofResult, CommandError> {
+        let mut db_conn = self.db.connection();
+        let mut tx_getter = DbTxGetter::new(&self.db);
+
+        let mut coins: Vec<Coin> = if outpoints.is_empty() {
+            db_conn
+                .coins(&[CoinStatus::Confirmed], &[])
+                .into_values()
+                .filter(|c| !c.is_immature)
+                .collect()
+        } else {
+            let coins = db_conn.coins(&[], outpoints);
+            for op in outpoints {
+                let coin = coins.get(op).ok_or(CommandError::UnknownOutpoint(*op))?;
+                if coin.is_spent() {
+                    return Err(CommandError::AlreadySpent(*op));
+                }
+                if coin.is_immature {
+                    return Err(CommandError::ImmatureCoinbase(*op));
+                }
+            }
+            coins.into_values().collect()
+        };
+        coins.sort_by_key(|c| c.outpoint);
+        let candidates: Vec<CandidateCoin> = coins
+            .iter()
+            .map(|c| {
+                coin_to_candidate(c, /*must_select=*/ true, /*sequence=*/ None, None)
+            })
+            .collect();
+
+        let psbt = reserves::create_proof_psbt(
+            &self.config.main_descriptor,
+            &self.secp,
+            &mut tx_getter,
+            &candidates,
+            message,
+        )?;
+        Ok(CreateReservesProofResult { psbt })
+    }
+
+    /// Finalize the signed PSBT of a proof of reserves committing to this challenge message, and
+    /// return the proof.
+    pub fn finalize_reserves_proof(
+        &self,
+        psbt: Psbt,
+        message: &str,
+    ) -> Result<ReservesProofResult, CommandError> {
+        // All the coins must be ours, and signed for using the primary path.
+        for (i, (psbt_in, txin)) in psbt
+            .inputs
+            .iter()
+            .zip(psbt.unsigned_tx.input.iter())
+            .enumerate()
+            .skip(1)
+        {
+            if !self.psbt_in_is_ours(psbt_in, txin) {
+                return Err(CommandError::InvalidPsbt(format!(
+                    "input {i} doesn't spend a coin from this wallet"
+                )));
+            }
+        }
+        let spend_info = reserves::proof_spend_info(&self.config.main_descriptor, &psbt)
+            .map_err(|e| CommandError::InvalidPsbt(e.to_string()))?;
+        let primary_path = spend_info.primary_path();
+        if primary_path.sigs_count < primary_path.threshold {
+            return Err(ReservesProofError::Finalization(format!(
+                "{} out of {} signatures",
+                primary_path.sigs_count, primary_path.threshold
+            ))
+            .into());
+        }
+
+        let proof = reserves::finalize_proof(&self.secp, psbt, message)?;
+        Ok(ReservesProofResult {
+            message: message.to_string(),
+            amount: proof.output[0].value,
+            proof,
+        })
+    }
+
+    /// Verify a proof of reserves committing to this challenge message against the UTXO set of
+    /// our backend. All the coins spent by the proof must be unspent at the backend's tip, and
+    /// have been confirmed at the given block height, or at the tip if none is given.
+    ///
+    /// The coins don't need to be ours: the outputs they spend are those of the UTXO set.
+    pub fn verify_reserves_proof(
+        &self,
+        proof: &bitcoin::Transaction,
+        message: &str,
+        height: Option<i32>,
+    ) -> Result<VerifyReservesProofResult, CommandError> {
+        let tip_height = self.bitcoin.chain_tip().height;
+        let height = height.unwrap_or(tip_height);
+        if height < 0 || height > tip_height {
+            return Err(CommandError::InvalidHeight(height));
+        }
+
+        // Get the outputs spent by the proof from the UTXO set, ignoring the commitment input. A
+        // coin unspent at the tip and confirmed by this height was also unspent at this height.
+        let mut prevouts = Vec::with_capacity(proof.input.len().saturating_sub(1));
+        for txin in proof.input.iter().skip(1) {
+            let op = txin.previous_output;
+            match self.bitcoin.utxo(&op).map_err(CommandError::UtxoLookup)? {
+                Some((txo, conf_height)) if conf_height <= height => prevouts.push(txo),
+                _ => return Err(CommandError::NotUnspentAtHeight(op, height)),
+            }
+        }
+
+        let amount = reserves::verify_proof(&self.secp, proof, message, &prevouts)?;
+        Ok(VerifyReservesProofResult { amount, height })
+    }
+
+    /// Create PSBT to replace the given transaction using RBF.
+    ///
+    /// `txid` must either point to a PSBT in our database (not necessarily broadcast) or an
+    /// unconfirmed spend transaction (whether or not any associated PSBT is saved in our database).
+    ///
+    /// `is_cancel` indicates whether to "cancel" the transaction by including only a single (change)
+    /// output in the replacement or otherwise to keep the same (non-change) outputs and simply
+    /// bump the fee.
+    /// If `true`, the only output of the RBF transaction will be change and the inputs will include
+    /// at least one of the inputs from the previous transaction. If `false`, all inputs from the previous
+    /// transaction will be used in the replacement.
+    /// In both cases:
+    /// - if the previous transaction includes a change output to one of our own change addresses,
+    ///   this same address will be used for change in the RBF transaction, if required. If the previous
+    ///   transaction pays to more than one of our change addresses, then the one receiving the highest
+    ///   value will be used as a change address and the others will be treated as non-change outputs.
+    /// - the RBF transaction may include additional confirmed coins as inputs if required
+    ///   in order to pay the higher fee (this applies also when replacing a self-send).
+    ///
+    /// `feerate_vb` is the target feerate for the RBF transaction (in sat/vb). If `None`, it will be set
+    /// to 1 sat/vb larger than the feerate of the previous transaction, which is the minimum value allowed
+    /// when using RBF.
+    pub fn rbf_psbt(
+        &self,
+        txid: &bitcoin::Txid,
+        is_cancel: bool,
+        feerate_vb: Option<u64>,
+    ) -> Result<CreateSpendResult, CommandError> {
+        let mut db_conn = self.db.connection();
+        let mut tx_getter = DbTxGetter::new(&self.db);
+
         if is_cancel && feerate_vb.is_some() {
             return Err(CommandError::RbfError(RbfErrorInfo::SuperfluousFeerate));
         }
 
         let prev_tx = if let Some(psbt) = db_conn.spend_tx(txid) {
+            // The outputs paying to a silent payment address were derived from the inputs, which
+            // may change in the replacement. A cancellation only pays to ourselves.
+            if !is_cancel && silent_payments::pays_silent_payment(&psbt) {
+                return Err(SilentPaymentError::Replacement.into());
+            }
             psbt.unsigned_tx
         } else {
             db_conn
@@ -972,7 +2116,9 @@ impl DaemonControl {
                 .and_then(|_| tx_getter.get_tx(txid))
                 .ok_or(CommandError::UnknownSpend(*txid))?
         };
-        if !prev_tx.is_explicitly_rbf() {
+        // TRUC transactions are always replaceable.
+        let is_truc = prev_tx.version == bitcoin::transaction::Version(3);
+        if !is_truc && !prev_tx.is_explicitly_rbf() {
             return Err(CommandError::RbfError(RbfErrorInfo::NotSignaling));
         }
         let prev_outpoints: Vec<bitcoin::OutPoint> = prev_tx
@@ -1036,10 +2182,23 @@ impl DaemonControl {
                 min_feerate_vb,
             )));
         }
-        // Get info about prev outputs to determine replacement outputs.
+        // Get info about prev outputs to determine replacement outputs. The data output, if any,
+        // doesn't have an address. Its data is kept in the replacement, unless it is a cancel.
+        let op_return_data = prev_tx
+            .output
+            .iter()
+            .find(|txo| txo.script_pubkey.is_op_return())
+            .and_then(|txo| match txo.script_pubkey.instructions().nth(1) {
+                Some(Ok(bitcoin::script::Instruction::PushBytes(data))) => {
+                    Some(data.as_bytes().to_vec())
+                }
+                _ => None,
+            })
+            .filter(|_| !is_cancel);
         let prev_derivs: Vec<_> = prev_tx
             .output
             .iter()
+            .filter(|txo| !txo.script_pubkey.is_op_return())
             .map(|txo| {
                 let address = bitcoin::Address::from_script(
                     &txo.script_pubkey,
@@ -1075,7 +2234,10 @@ impl DaemonControl {
                 .into_iter()
                 .filter_map(|(addr, amt, _)| {
                     if prev_change_address.as_ref() != Some(&addr) {
-                        Some((self.spend_addr(&mut db_conn, addr), amt))
+                        Some((
+                            self.spend_addr(&mut db_conn, addr),
+                            DestinationAmount::Fixed(amt),
+                        ))
                     } else {
                         None
                     }
@@ -1114,7 +2276,7 @@ impl DaemonControl {
             .filter_map(|c| {
                 // Make sure we don't have duplicate candidates in case any of the coins are not
                 // currently set as spending in the DB (and are therefore still confirmed).
-                if !prev_coins.contains_key(&c.outpoint) {
+                if !prev_coins.contains_key(&c.outpoint) && !c.is_quarantined {
                     Some(coin_to_candidate(
                         &c, /*must_select=*/ false, /*sequence=*/ None,
                         /*ancestor_info=*/ None,
@@ -1143,6 +2305,11 @@ impl DaemonControl {
                 SpendTxFees::Rbf(feerate_vb, replaced_fee),
                 change_address.clone(),
                 locktime,
+                &SpendOptions {
+                    op_return_data: op_return_data.clone(),
+                    truc: is_truc,
+                    ..SpendOptions::default()
+                },
             ) {
                 Ok(CreateSpendRes {
                     psbt,
@@ -1246,8 +2413,9 @@ impl DaemonControl {
     /// we'll use the first recovery path available.
     ///
     /// If `coins_outpoints` is empty, all coins for which the given recovery path is currently
-    /// available will be used. Otherwise, only those specified will be considered. An error will
-    /// be returned if any coins specified by `coins_outpoints` are unknown, already spent or
+    /// available will be used. An error listing them is returned if some of them are in
+    /// quarantine. Otherwise, only those specified will be considered. An error will be returned
+    /// if any coins specified by `coins_outpoints` are unknown, already spent, in quarantine or
     /// otherwise not currently recoverable using the given recovery path.
     ///
     /// Note that not all coins may be spendable through a single recovery path at the same time.
@@ -1287,16 +2455,26 @@ impl DaemonControl {
                 if coin.is_spent() {
                     return Err(CommandError::AlreadySpent(*op));
                 }
+                if coin.is_quarantined {
+                    return Err(CommandError::QuarantinedCoin(*op));
+                }
             }
             coins_by_op
         };
         let mut sweepable_coins = Vec::with_capacity(coins.len());
+        let mut quarantined_coins = Vec::new();
         for (op, c) in coins {
             // We are interested in coins available at the *next* block
             if c.block_info
                 .map(|b| current_height + 1 >= b.height + height_delta)
                 .unwrap_or(false)
             {
+                // Quarantined coins are never selected automatically, but don't silently leave
+                // them behind either.
+                if c.is_quarantined {
+                    quarantined_coins.push(op);
+                    continue;
+                }
                 sweepable_coins.push(coin_to_candidate(
                     &c,
                     /*must_select=*/ true,
@@ -1307,6 +2485,10 @@ impl DaemonControl {
                 return Err(CommandError::OutpointNotRecoverable(op, timelock));
             }
         }
+        if !quarantined_coins.is_empty() {
+            quarantined_coins.sort();
+            return Err(CommandError::QuarantinedCoins(quarantined_coins));
+        }
         if sweepable_coins.is_empty() {
             return Err(CommandError::RecoveryNotAvailable);
         }
@@ -1324,6 +2506,7 @@ impl DaemonControl {
             SpendTxFees::Regular(feerate_vb),
             sweep_addr,
             locktime,
+            &SpendOptions::default(),
         )?;
         if has_change {
             self.maybe_increase_last_deriv_index(&mut db_conn, &sweep_addr_info);
@@ -1331,6 +2514,235 @@ impl DaemonControl {
 
         Ok(CreateRecoveryResult { psbt })
     }
+
+    /// Estimate the fees paid by a transaction with `num_inputs` inputs and `num_outputs` outputs
+    /// spending through each spending path of the given descriptor (our main descriptor if none is
+    /// given), for every feerate between `min_feerate_vb` and `max_feerate_vb` included.
+    ///
+    /// The estimation is done for both the Taproot and the P2WSH variants of the descriptor's
+    /// policy. A variant is not present in the result if the policy can't be used under this
+    /// context. Note the variant that was not given is compiled from the policy, it may therefore
+    /// differ from the descriptor a user would get by creating the same policy in another
+    /// context.
+    pub fn simulate_spend_cost(
+        &self,
+        descriptor: Option<&descriptors::LianaDescriptor>,
+        num_inputs: usize,
+        num_outputs: usize,
+        min_feerate_vb: u64,
+        max_feerate_vb: u64,
+    ) -> Result<SimulateSpendCostResult, CommandError> {
+        if min_feerate_vb < 1 {
+            return Err(CommandError::InvalidFeerate(min_feerate_vb));
+        }
+        if max_feerate_vb < min_feerate_vb || max_feerate_vb > spend::MAX_FEERATE {
+            return Err(CommandError::InvalidFeerate(max_feerate_vb));
+        }
+        let feerates: Vec<u64> = (min_feerate_vb..=max_feerate_vb).collect();
+        let descriptor = descriptor.unwrap_or(&self.config.main_descriptor);
+
+        let simulate = |is_taproot: bool| -> Result<Option<DescriptorSpendCost>, CommandError> {
+            let desc = match descriptor.with_taproot(is_taproot) {
+                Ok(desc) => desc,
+                Err(e) => {
+                    log::debug!(
+                        "Cannot use policy under {}: {}",
+                        if is_taproot { "Taproot" } else { "P2WSH" },
+                        e
+                    );
+                    return Ok(None);
+                }
+            };
+            let sim =
+                spend::simulate_spend_cost(&desc, &self.secp, num_inputs, num_outputs, &feerates)?;
+            Ok(Some(DescriptorSpendCost {
+                descriptor: desc,
+                primary_path: PathSpendCostEntry::new(None, sim.primary_path),
+                recovery_paths: sim
+                    .recovery_paths
+                    .into_iter()
+                    .map(|(tl, cost)| PathSpendCostEntry::new(Some(tl), cost))
+                    .collect(),
+            }))
+        };
+
+        Ok(SimulateSpendCostResult {
+            taproot: simulate(true)?,
+            p2wsh: simulate(false)?,
+        })
+    }
+
+    /// Move all the funds of this wallet to the given new descriptor and retire this wallet.
+    ///
+    /// All the confirmed coins are swept to receive addresses of the new descriptor through the
+    /// primary path. The sweep is split into as many transactions as necessary for each of them to
+    /// remain standard, optionally with at most `max_inputs` inputs each. The PSBTs are stored as
+    /// drafts in database, they still need to be signed and broadcast.
+    ///
+    /// A wallet for the new descriptor must be loaded. Our labels are copied over to it and the
+    /// addresses swept to, after those it already revealed, are marked as used. Note the wallet
+    /// is marked as retired regardless of whether the sweep transactions get broadcast. It can't
+    /// be migrated again once retired, nor while some of its coins are unconfirmed, immature or
+    /// quarantined as they would be left behind.
+    pub fn migrate_wallet(
+        &self,
+        new_descriptor: &descriptors::LianaDescriptor,
+        feerate_vb: u64,
+        max_inputs: Option<usize>,
+    ) -> Result<MigrateWalletResult, CommandError> {
+        if feerate_vb < 1 {
+            return Err(CommandError::InvalidFeerate(feerate_vb));
+        }
+        let network = self.config.bitcoin_config.network;
+        if new_descriptor == &self.config.main_descriptor {
+            return Err(CommandError::InvalidMigrationDescriptor(
+                "it is the descriptor of this wallet".to_string(),
+            ));
+        }
+        if !new_descriptor.all_xpubs_net_is(network) {
+            return Err(CommandError::InvalidMigrationDescriptor(format!(
+                "it is not for network '{network}'"
+            )));
+        }
+        let successor = self
+            .wallets()
+            .lock()
+            .expect("Wallets lock must not be poisoned")
+            .values()
+            .find(|control| &control.config.main_descriptor == new_descriptor)
+            .cloned()
+            .ok_or_else(|| {
+                CommandError::InvalidMigrationDescriptor(
+                    "no wallet is loaded for it, create or load one first".to_string(),
+                )
+            })?;
+
+        // Sweep all our confirmed coins. Unconfirmed and immature coins must confirm and mature
+        // first, and quarantined coins be released, as they would otherwise be left behind in the
+        // retired wallet. Sort them for the chunks to be deterministic.
+        let mut tx_getter = DbTxGetter::new(&self.db);
+        let mut db_conn = self.db.connection();
+        if db_conn.wallet().retired_timestamp.is_some() {
+            return Err(CommandError::AlreadyRetired);
+        }
+        let mut pending_coins: Vec<_> = db_conn
+            .coins(&[CoinStatus::Unconfirmed, CoinStatus::Confirmed], &[])
+            .into_values()
+            .filter_map(|c| (c.block_info.is_none() || c.is_immature).then_some(c.outpoint))
+            .collect();
+        if !pending_coins.is_empty() {
+            pending_coins.sort();
+            return Err(CommandError::CoinsNotMigratable(pending_coins));
+        }
+        let mut coins: Vec<Coin> = db_conn
+            .coins(&[CoinStatus::Confirmed], &[])
+            .into_values()
+            .collect();
+        let mut quarantined_coins: Vec<_> = coins
+            .iter()
+            .filter_map(|c| c.is_quarantined.then_some(c.outpoint))
+            .collect();
+        if !quarantined_coins.is_empty() {
+            quarantined_coins.sort();
+            return Err(CommandError::QuarantinedCoins(quarantined_coins));
+        }
+        if coins.is_empty() {
+            return Err(CommandError::NoCoinToMigrate);
+        }
+        coins.sort_by_key(|c| c.outpoint);
+
+        // Leave room for the transaction header and the output when sizing the chunks.
+        let max_tx_vbytes = (bitcoin::policy::MAX_STANDARD_TX_WEIGHT / 4) as usize - 1_000;
+        let chunk_size = (max_tx_vbytes / self.config.main_descriptor.spender_input_size(true))
+            .min(max_inputs.unwrap_or(usize::MAX))
+            .max(1);
+        // Sweep to the addresses following those the new wallet already revealed.
+        let mut successor_conn = successor.db.connection();
+        let first_index: u32 = successor_conn.receive_index().into();
+        let locktime = self.anti_fee_sniping_locktime();
+        let mut psbts = Vec::new();
+        let mut last_index = first_index;
+        for (i, chunk) in coins.chunks(chunk_size).enumerate() {
+            let candidates: Vec<_> = chunk
+                .iter()
+                .map(|c| {
+                    coin_to_candidate(
+                        c, /*must_select=*/ true, /*sequence=*/ None,
+                        /*ancestor_info=*/ None,
+                    )
+                })
+                .collect();
+            let offset: u32 = i.try_into().expect("Number of chunks must fit in u32");
+            last_index = first_index
+                .checked_add(offset + 1)
+                .filter(|index| *index < (1 << 31))
+                .expect("Can't get into hardened territory");
+            let sweep_addr = SpendOutputAddress {
+                addr: new_descriptor
+                    .receive_descriptor()
+                    .derive(last_index.into(), &self.secp)
+                    .address(network),
+                info: None,
+            };
+            let CreateSpendRes { psbt, .. } = create_spend(
+                &self.config.main_descriptor,
+                &self.secp,
+                &mut tx_getter,
+                &[], // No destination, everything goes to the new wallet's address.
+                &candidates,
+                SpendTxFees::Regular(feerate_vb),
+                sweep_addr,
+                locktime,
+                &SpendOptions::default(),
+            )?;
+            psbts.push(psbt);
+        }
+        for psbt in &psbts {
+            db_conn.store_spend(psbt);
+        }
+
+        // Make sure the new wallet doesn't hand out the addresses we sweep to again.
+        successor_conn.set_receive_index(last_index.into(), &self.secp);
+        self.carry_labels_over(&mut db_conn, &successor);
+        let now: u32 = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs().try_into().expect("Timestamp must fit in u32"))
+            .expect("Time measured now cannot be before unix epoch");
+        db_conn.retire(new_descriptor, now);
+        log::info!(
+            "Retired wallet in favour of descriptor '{}' with {} sweep transaction(s).",
+            new_descriptor,
+            psbts.len()
+        );
+
+        Ok(MigrateWalletResult { psbts })
+    }
+
+    // Copy all our labels to this wallet.
+    fn carry_labels_over(
+        &self,
+        db_conn: &mut Box<dyn DatabaseConnection>,
+        successor: &DaemonControl,
+    ) {
+        const LABELS_BATCH_SIZE: u32 = 100;
+        let network = self.config.bitcoin_config.network;
+        let mut offset = 0;
+        loop {
+            let labels = db_conn
+                .get_labels_bip329(offset, LABELS_BATCH_SIZE)
+                .into_vec();
+            let items: HashMap<LabelItem, Option<String>> = labels
+                .iter()
+                .filter_map(|l| LabelItem::from_bip329(l, network))
+                .map(|(item, label)| (item, Some(label)))
+                .collect();
+            successor.update_labels(&items);
+            if (labels.len() as u32) < LABELS_BATCH_SIZE {
+                break;
+            }
+            offset += LABELS_BATCH_SIZE;
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1356,6 +2768,13 @@ pub struct GetInfoResult {
     pub receive_index: u32,
     /// Last index used to generate a change address
     pub change_index: u32,
+    /// Timestamp at which the wallet was migrated to a new descriptor, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retired_timestamp: Option<u32>,
+    /// The Electrum servers from the configuration along with their health, if the Bitcoin
+    /// backend is Electrum.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub electrum_servers: Vec<ElectrumServerStatus>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1390,6 +2809,42 @@ pub struct GetLabelsBip329Result {
     pub labels: crate::bip329::Labels,
 }
 
+/// The kind of privacy leak found in the wallet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivacyFindingKind {
+    /// An address received more than once.
+    AddressReuse,
+    /// A transaction spent together coins carrying different labels.
+    MergedLabels,
+    /// The change of a transaction can be told apart from its payments.
+    IdentifiableChange,
+    /// An unspent coin can be traced back to a KYC source.
+    KycLinkedCoin,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrivacyFinding {
+    pub kind: PrivacyFindingKind,
+    /// A human readable description of the leak.
+    pub description: String,
+    /// The address concerned, if any.
+    pub address: Option<bitcoin::Address<address::NetworkUnchecked>>,
+    /// The transaction concerned, if any.
+    pub txid: Option<bitcoin::Txid>,
+    /// The coins concerned.
+    pub outpoints: Vec<bitcoin::OutPoint>,
+    /// The labels involved.
+    pub labels: Vec<String>,
+    /// What to do about it.
+    pub remediation: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrivacyReportResult {
+    pub findings: Vec<PrivacyFinding>,
+}
+
 #[derive(Debug, Clone, Serialize, PartialEq, Eq)]
 pub struct AddressInfo {
     index: u32,
@@ -1473,6 +2928,10 @@ pub struct ListCoinsEntry {
     /// this same wallet. If the coin is unconfirmed, it also means that all its
     /// unconfirmed ancestors, if any, are also from self.
     pub is_from_self: bool,
+    /// Whether the coin was quarantined as a likely dust attack. It is never selected
+    /// automatically until released.
+    #[serde(default)]
+    pub is_quarantined: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1480,25 +2939,87 @@ pub struct ListCoinsResult {
     pub coins: Vec<ListCoinsEntry>,
 }
 
-#[allow(clippy::large_enum_variant)]
+/// How much of the confirmed balance can be swept through a recovery path.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(untagged)]
-pub enum CreateSpendResult {
-    Success {
-        #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
-        psbt: Psbt,
-        warnings: Vec<String>,
-    },
-    InsufficientFunds {
-        missing: u64,
-    },
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ListSpendEntry {
-    #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
-    pub psbt: Psbt,
-    pub updated_at: Option<u32>,
+pub struct RecoveryPathBalance {
+    /// The timelock of this recovery path, in blocks.
+    pub timelock: u16,
+    /// Value of the coins which can be swept through this path at the next block.
+    #[serde(
+        serialize_with = "ser_amount",
+        deserialize_with = "deser_amount_from_sats"
+    )]
+    pub spendable: bitcoin::Amount,
+    /// Value of the coins which become so within the requested window.
+    #[serde(
+        serialize_with = "ser_amount",
+        deserialize_with = "deser_amount_from_sats"
+    )]
+    pub spendable_within_window: bitcoin::Amount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GetBalanceResult {
+    /// Value of the confirmed coins not being spent.
+    #[serde(
+        serialize_with = "ser_amount",
+        deserialize_with = "deser_amount_from_sats"
+    )]
+    pub confirmed: bitcoin::Amount,
+    /// Value of the unconfirmed coins not being spent.
+    #[serde(
+        serialize_with = "ser_amount",
+        deserialize_with = "deser_amount_from_sats"
+    )]
+    pub unconfirmed: bitcoin::Amount,
+    /// Value of the coinbase deposits which are not yet mature.
+    #[serde(
+        serialize_with = "ser_amount",
+        deserialize_with = "deser_amount_from_sats"
+    )]
+    pub immature: bitcoin::Amount,
+    /// Value of the coins used in a stored or unconfirmed Spend transaction.
+    #[serde(
+        serialize_with = "ser_amount",
+        deserialize_with = "deser_amount_from_sats"
+    )]
+    pub spending: bitcoin::Amount,
+    /// The window, in blocks, used for the recovery paths balances.
+    pub window: u32,
+    /// For each recovery path, in increasing order of timelock.
+    pub recovery_paths: Vec<RecoveryPathBalance>,
+}
+
+/// Advanced options for [`DaemonControl::create_spend_with_options`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CreateSpendOptions {
+    /// The nLockTime of the transaction. If not set, one discouraging fee sniping is used.
+    pub locktime: Option<LockTime>,
+    /// The data output and replaceability signaling of the transaction.
+    pub spend: SpendOptions,
+    /// Silent payment addresses to pay, along with the value in sats.
+    pub silent_payments: HashMap<SilentPaymentAddress, u64>,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum CreateSpendResult {
+    Success {
+        #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
+        psbt: Psbt,
+        warnings: Vec<String>,
+    },
+    InsufficientFunds {
+        missing: u64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListSpendEntry {
+    #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
+    pub psbt: Psbt,
+    pub updated_at: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1506,6 +3027,156 @@ pub struct ListSpendResult {
     pub spend_txs: Vec<ListSpendEntry>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AnalyzePsbtInput {
+    pub outpoint: bitcoin::OutPoint,
+    /// The value of the spent coin, if known.
+    #[serde(
+        serialize_with = "ser_opt_amount",
+        deserialize_with = "deser_opt_amount_from_sats"
+    )]
+    pub amount: Option<bitcoin::Amount>,
+    /// Whether the spent coin is one of our coins.
+    pub is_ours: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PsbtPathAnalysis {
+    /// The timelock of this recovery path, or `None` for the primary path.
+    pub timelock: Option<u16>,
+    pub threshold: usize,
+    pub sigs_count: usize,
+    /// The number of signatures provided for each signer.
+    pub signed_pubkeys: HashMap<bip32::Fingerprint, usize>,
+    /// The signers of this path which did not provide a signature yet.
+    pub missing_signers: Vec<bip32::Fingerprint>,
+    /// Whether all the coins spent are mature enough to be spent through this path.
+    pub timelock_ready: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AnalyzePsbtResult {
+    pub txid: bitcoin::Txid,
+    pub inputs: Vec<AnalyzePsbtInput>,
+    /// The indexes of the outputs paying to one of our addresses.
+    pub change_indexes: Vec<usize>,
+    /// The fee paid by the transaction, if the value of all the inputs is known.
+    #[serde(
+        serialize_with = "ser_opt_amount",
+        deserialize_with = "deser_opt_amount_from_sats"
+    )]
+    pub fee: Option<bitcoin::Amount>,
+    /// The feerate, estimated from the maximum size of the transaction if it can't be finalized
+    /// yet.
+    pub feerate_vb: Option<u64>,
+    /// The primary path, followed by the recovery paths available given the inputs' sequence.
+    pub paths: Vec<PsbtPathAnalysis>,
+    /// Whether the transaction's absolute locktime is satisfied at the current tip.
+    pub locktime_ready: bool,
+    /// Whether enough signatures are present to finalize the PSBT.
+    pub can_finalize: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FinalizePsbtResult {
+    pub txid: bitcoin::Txid,
+    #[serde(serialize_with = "ser_hex", deserialize_with = "deser_hex")]
+    pub tx: bitcoin::Transaction,
+}
+
+/// The state of a scheduled transaction at the current tip.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduledBroadcastStatus {
+    /// A coin it spends with a relative timelock isn't confirmed yet.
+    WaitingConfirmation,
+    /// Not valid yet.
+    Pending,
+    /// Valid, it will be broadcast on the next poll.
+    Ready,
+    /// Broadcast but not confirmed yet, it is broadcast again on every poll.
+    Broadcast,
+    /// All the coins it spends were spent by it in a block.
+    Confirmed,
+    /// A coin it spends is unknown or was spent by another transaction.
+    Conflicted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ScheduledBroadcastEntry {
+    pub txid: bitcoin::Txid,
+    #[serde(serialize_with = "ser_hex", deserialize_with = "deser_hex")]
+    pub tx: bitcoin::Transaction,
+    pub status: ScheduledBroadcastStatus,
+    /// For a pending transaction, the height of the first block which may include it.
+    pub valid_at_height: Option<i32>,
+    /// For a pending transaction, the time after which the median time past of the chain must be
+    /// for it to be included.
+    pub valid_at_time: Option<u32>,
+    pub created_at: u32,
+    pub broadcast_at: Option<u32>,
+    /// The error of the last failed attempt to broadcast it, if any.
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ListScheduledBroadcastsResult {
+    pub transactions: Vec<ScheduledBroadcastEntry>,
+}
+
+/// The PSBT to sign for signing a message, or the signature once it was signed.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum SignMessageResult {
+    Signature {
+        #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
+        signature: MessageSignature,
+    },
+    Psbt {
+        #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
+        psbt: Psbt,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VerifyMessageResult {
+    pub valid: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CreateReservesProofResult {
+    #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
+    pub psbt: Psbt,
+}
+
+/// A proof of reserves, as exported to be verified.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReservesProofResult {
+    /// The challenge message committed to by the proof.
+    pub message: String,
+    /// The total value of the coins spent by the proof.
+    #[serde(
+        serialize_with = "ser_amount",
+        deserialize_with = "deser_amount_from_sats"
+    )]
+    pub amount: bitcoin::Amount,
+    #[serde(serialize_with = "ser_hex", deserialize_with = "deser_hex")]
+    pub proof: bitcoin::Transaction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VerifyReservesProofResult {
+    /// The total value of the coins whose ownership is proven.
+    #[serde(
+        serialize_with = "ser_amount",
+        deserialize_with = "deser_amount_from_sats"
+    )]
+    pub amount: bitcoin::Amount,
+    /// The block height by which the coins were checked to be confirmed.
+    pub height: i32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ListTransactionsResult {
     pub transactions: Vec<TransactionInfo>,
@@ -1525,10 +3196,354 @@ pub struct CreateRecoveryResult {
     pub psbt: Psbt,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MigrateWalletResult {
+    #[serde(
+        serialize_with = "ser_vec_to_string",
+        deserialize_with = "deser_vec_fromstr"
+    )]
+    pub psbts: Vec<Psbt>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SpendCostFee {
+    /// Feerate in sats/vb.
+    pub feerate: u64,
+    #[serde(
+        serialize_with = "ser_amount",
+        deserialize_with = "deser_amount_from_sats"
+    )]
+    pub fee: bitcoin::Amount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PathSpendCostEntry {
+    /// The timelock of this recovery path, or `None` for the primary path.
+    pub timelock: Option<u16>,
+    /// Maximum size of a single input spent through this path.
+    pub input_vbytes: u64,
+    /// Maximum size of the whole transaction.
+    pub tx_vbytes: u64,
+    pub fees: Vec<SpendCostFee>,
+}
+
+impl PathSpendCostEntry {
+    fn new(timelock: Option<u16>, cost: spend::PathSpendCost) -> Self {
+        PathSpendCostEntry {
+            timelock,
+            input_vbytes: cost.input_vbytes,
+            tx_vbytes: cost.tx_vbytes,
+            fees: cost
+                .fees
+                .into_iter()
+                .map(|(feerate, fee)| SpendCostFee { feerate, fee })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DescriptorSpendCost {
+    #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
+    pub descriptor: descriptors::LianaDescriptor,
+    pub primary_path: PathSpendCostEntry,
+    pub recovery_paths: Vec<PathSpendCostEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SimulateSpendCostResult {
+    pub taproot: Option<DescriptorSpendCost>,
+    pub p2wsh: Option<DescriptorSpendCost>,
+}
+
+// Wallet ids are used as directory names, make sure they are sane.
+fn check_wallet_id(wallet_id: &str) -> Result<(), CommandError> {
+    if wallet_id.is_empty()
+        || wallet_id.len() > 64
+        || !wallet_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(CommandError::InvalidWalletId(wallet_id.to_string()));
+    }
+    Ok(())
+}
+
+// The ids of the wallets listed in this file, one per line. A missing file lists no wallet.
+fn read_loaded_wallets(path: &path::Path) -> Vec<String> {
+    match fs::read_to_string(path) {
+        Ok(content) => content
+            .lines()
+            .filter(|id| check_wallet_id(id).is_ok())
+            .map(|id| id.to_string())
+            .collect(),
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::error!("Error reading the list of loaded wallets: {}", e);
+            }
+            Vec::new()
+        }
+    }
+}
+
+/// Managing the additional wallets of this daemon. They share the connection to the Bitcoin
+/// backend and the poller of the main wallet, but each has its own database and data directory.
+/// The list of loaded wallets is persisted so they are loaded again at startup.
+impl DaemonControl {
+    // The data directory of the additional wallet with this id.
+    fn wallet_data_dir(&self, wallet_id: &str) -> Result<DataDirectory, CommandError> {
+        check_wallet_id(wallet_id)?;
+        self.config
+            .data_directory()
+            .map(|dir| dir.wallet_directory(wallet_id))
+            .ok_or_else(|| {
+                CommandError::WalletSetup(StartupError::DefaultDataDirNotFound.to_string())
+            })
+    }
+
+    // Set up the interfaces for this wallet, register it in these loaded wallets and start keeping
+    // it in sync.
+    fn start_wallet(
+        &self,
+        loaded_wallets: &mut BTreeMap<String, DaemonControl>,
+        wallet_id: String,
+        descriptor: descriptors::LianaDescriptor,
+        data_dir: &DataDirectory,
+        fresh_data_dir: bool,
+    ) -> Result<(), CommandError> {
+        let entry = match loaded_wallets.entry(wallet_id.clone()) {
+            btree_map::Entry::Vacant(entry) => entry,
+            btree_map::Entry::Occupied(_) => {
+                return Err(CommandError::WalletAlreadyLoaded(wallet_id))
+            }
+        };
+        let connection = self.bitcoin.connection().ok_or_else(|| {
+            CommandError::WalletSetup(StartupError::MissingBitcoinBackendConfig.to_string())
+        })?;
+        let config = self.config.for_wallet(descriptor, data_dir);
+        let (bitcoin, db) = setup_wallet(&config, connection, fresh_data_dir, &self.secp)
+            .map_err(|e| CommandError::WalletSetup(e.to_string()))?;
+        let polled_wallet = PolledWallet::new(bitcoin.clone(), db.clone(), &config.main_descriptor);
+        let control = DaemonControl {
+            config,
+            bitcoin,
+            poller_sender: self.poller_sender.clone(),
+            db,
+            secp: self.secp.clone(),
+            wallets: self.weak_wallets(),
+            metrics: self.metrics.clone(),
+        };
+        entry.insert(control);
+        self.poller_sender
+            .send(PollerMessage::AddWallet(wallet_id, polled_wallet))
+            .expect("Poller must be running");
+        Ok(())
+    }
+
+    // Write the ids of the loaded wallets to disk, to load them again at the next startup.
+    fn persist_loaded_wallets(&self) {
+        let path = match self.config.data_directory() {
+            Some(data_dir) => data_dir.loaded_wallets_file_path(),
+            None => return,
+        };
+        let content: String = self
+            .wallets()
+            .lock()
+            .expect("Wallets lock must not be poisoned")
+            .keys()
+            .map(|id| format!("{}\n", id))
+            .collect();
+        if let Err(e) = fs::write(&path, content) {
+            log::error!("Error writing the list of loaded wallets: {}", e);
+        }
+    }
+
+    /// Load the wallets which were loaded when the daemon last stopped. A wallet which fails to be
+    /// loaded is skipped.
+    pub fn load_persisted_wallets(&self) {
+        let path = match self.config.data_directory() {
+            Some(data_dir) => data_dir.loaded_wallets_file_path(),
+            None => return,
+        };
+        for wallet_id in read_loaded_wallets(&path) {
+            if let Err(e) = self.load_wallet_inner(&wallet_id) {
+                log::error!("Error loading wallet '{}': {}", wallet_id, e);
+            }
+        }
+    }
+
+    /// Get the control for the additional wallet with this id.
+    pub fn wallet(&self, wallet_id: &str) -> Result<DaemonControl, CommandError> {
+        self.wallets()
+            .lock()
+            .expect("Wallets lock must not be poisoned")
+            .get(wallet_id)
+            .cloned()
+            .ok_or_else(|| CommandError::UnknownWallet(wallet_id.to_string()))
+    }
+
+    /// Create a new wallet for this descriptor and load it.
+    pub fn create_wallet(
+        &self,
+        wallet_id: String,
+        descriptor: descriptors::LianaDescriptor,
+    ) -> Result<(), CommandError> {
+        let data_dir = self.wallet_data_dir(&wallet_id)?;
+        let network = self.config.bitcoin_config.network;
+        if !descriptor.all_xpubs_net_is(network) {
+            return Err(CommandError::WalletSetup(format!(
+                "descriptor is not for network '{network}'"
+            )));
+        }
+        {
+            // Keep the wallets locked until this one is registered, so it can't be created twice
+            // concurrently.
+            let wallets = self.wallets();
+            let mut loaded_wallets = wallets.lock().expect("Wallets lock must not be poisoned");
+            if data_dir.exists() {
+                return Err(CommandError::WalletAlreadyExists(wallet_id));
+            }
+            data_dir
+                .init()
+                .map_err(|e| CommandError::WalletSetup(e.to_string()))?;
+            if let Err(e) = self.start_wallet(
+                &mut loaded_wallets,
+                wallet_id.clone(),
+                descriptor,
+                &data_dir,
+                true,
+            ) {
+                // Don't leave a half-initialized wallet behind.
+                if let Err(e) = std::fs::remove_dir_all(data_dir.path()) {
+                    log::error!(
+                        "Error removing data directory of wallet '{}': {}",
+                        wallet_id,
+                        e
+                    );
+                }
+                return Err(e);
+            }
+        }
+        self.persist_loaded_wallets();
+        log::info!("Created wallet '{}'.", wallet_id);
+        Ok(())
+    }
+
+    // Load an existing wallet from the data directory, without persisting the list of loaded
+    // wallets.
+    fn load_wallet_inner(&self, wallet_id: &str) -> Result<(), CommandError> {
+        let data_dir = self.wallet_data_dir(wallet_id)?;
+        // Keep the wallets locked until this one is registered, so it can't be loaded twice
+        // concurrently.
+        let wallets = self.wallets();
+        let mut loaded_wallets = wallets.lock().expect("Wallets lock must not be poisoned");
+        if loaded_wallets.contains_key(wallet_id) {
+            return Err(CommandError::WalletAlreadyLoaded(wallet_id.to_string()));
+        }
+        if !data_dir.sqlite_db_file_path().exists() {
+            return Err(CommandError::WalletNotFound(wallet_id.to_string()));
+        }
+        let descriptor = wallet_descriptor(&data_dir, &self.secp)
+            .map_err(|e| CommandError::WalletSetup(e.to_string()))?;
+        self.start_wallet(
+            &mut loaded_wallets,
+            wallet_id.to_string(),
+            descriptor,
+            &data_dir,
+            false,
+        )?;
+        log::info!("Loaded wallet '{}'.", wallet_id);
+        Ok(())
+    }
+
+    /// Load an existing wallet from the data directory.
+    pub fn load_wallet(&self, wallet_id: String) -> Result<(), CommandError> {
+        self.load_wallet_inner(&wallet_id)?;
+        self.persist_loaded_wallets();
+        Ok(())
+    }
+
+    /// Stop managing this wallet. It can be loaded back later on.
+    pub fn unload_wallet(&self, wallet_id: String) -> Result<(), CommandError> {
+        self.wallets()
+            .lock()
+            .expect("Wallets lock must not be poisoned")
+            .remove(&wallet_id)
+            .ok_or_else(|| CommandError::UnknownWallet(wallet_id.clone()))?;
+        self.poller_sender
+            .send(PollerMessage::RemoveWallet(wallet_id.clone()))
+            .expect("Poller must be running");
+        self.persist_loaded_wallets();
+        log::info!("Unloaded wallet '{}'.", wallet_id);
+        Ok(())
+    }
+
+    /// List the additional wallets in the data directory, and whether they are loaded.
+    pub fn list_wallets(&self) -> ListWalletsResult {
+        let loaded_wallets = self.wallets();
+        let loaded = loaded_wallets
+            .lock()
+            .expect("Wallets lock must not be poisoned");
+        let mut wallets: Vec<ListWalletsEntry> = loaded
+            .iter()
+            .map(|(id, control)| ListWalletsEntry {
+                id: id.clone(),
+                descriptor: control.config.main_descriptor.clone(),
+                loaded: true,
+            })
+            .collect();
+        // Also list the wallets present in the data directory but not loaded.
+        let wallets_path = self.config.data_directory().map(|dir| dir.wallets_path());
+        if let Some(Ok(entries)) = wallets_path.map(std::fs::read_dir) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let id = entry.file_name().to_string_lossy().to_string();
+                if loaded.contains_key(&id) {
+                    continue;
+                }
+                // Ignore anything which isn't a wallet directory.
+                let data_dir = match self.wallet_data_dir(&id) {
+                    Ok(data_dir) => data_dir,
+                    Err(_) => continue,
+                };
+                match wallet_descriptor(&data_dir, &self.secp) {
+                    Ok(descriptor) => wallets.push(ListWalletsEntry {
+                        id,
+                        descriptor,
+                        loaded: false,
+                    }),
+                    Err(e) => log::warn!("Could not read wallet '{}': {}", id, e),
+                }
+            }
+        }
+        wallets.sort_by(|a, b| a.id.cmp(&b.id));
+        ListWalletsResult { wallets }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListWalletsEntry {
+    pub id: String,
+    #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
+    pub descriptor: descriptors::LianaDescriptor,
+    pub loaded: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListWalletsResult {
+    pub wallets: Vec<ListWalletsEntry>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{bitcoin::Block, database::BlockInfo, testutils::*};
+    use crate::{
+        bitcoin::Block,
+        database::{
+            sqlite::{FreshDbOptions, SqliteDb},
+            BlockInfo,
+        },
+        testutils::*,
+    };
     use liana::spend::InsaneFeeInfo;
 
     use bitcoin::{
@@ -1551,28 +3566,332 @@ mod tests {
     }
 
     #[test]
-    fn getnewaddress() {
+    fn simulate_spend_cost() {
         let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
-
         let control = &ms.control();
-        // We can get an address (it will have index 1)
-        let addr = control.get_new_address().address;
-        // $ bitcoin-cli deriveaddresses "wsh(or_d(pk([aabbccdd]xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/0/*),and_v(v:pkh([aabbccdd]xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/0/*),older(10000))))#wx6v3mks" 1
-        // [
-        // "bc1q9ksrc647hx8zp2cewl8p5f487dgux3777yees8rjcx46t4daqzzqt7yga8",
-        // "bc1qm06ceyghltr8v5cmeckh6cquhy9nks626pahapc04xd98kwf478qwmhqew"
-        // ]
+
+        // Feerates must be sane.
         assert_eq!(
-            addr,
-            bitcoin::Address::from_str(
-                "bc1qm06ceyghltr8v5cmeckh6cquhy9nks626pahapc04xd98kwf478qwmhqew"
-            )
-            .unwrap()
-            .assume_checked()
+            control.simulate_spend_cost(None, 1, 1, 0, 10),
+            Err(CommandError::InvalidFeerate(0))
         );
-        // We won't get the same twice.
-        let addr2 = control.get_new_address().address;
-        assert_ne!(addr, addr2);
+        assert_eq!(
+            control.simulate_spend_cost(None, 1, 1, 10, 9),
+            Err(CommandError::InvalidFeerate(9))
+        );
+        assert_eq!(
+            control.simulate_spend_cost(None, 1, 1, 1, 1_001),
+            Err(CommandError::InvalidFeerate(1_001))
+        );
+
+        // The transaction must fit within the standard size limit.
+        assert_eq!(
+            control.simulate_spend_cost(None, usize::MAX, 1, 1, 1),
+            Err(CommandError::SpendCreation(
+                SpendCreationError::TooLargeTransaction(usize::MAX, 1)
+            ))
+        );
+        assert_eq!(
+            control.simulate_spend_cost(None, 1, 2_500, 1, 1),
+            Err(CommandError::SpendCreation(
+                SpendCreationError::TooLargeTransaction(1, 2_500)
+            ))
+        );
+        assert!(control.simulate_spend_cost(None, 100, 100, 1, 1).is_ok());
+
+        // By default our own descriptor is used. We get both variants.
+        let res = control.simulate_spend_cost(None, 2, 2, 1, 5).unwrap();
+        let p2wsh = res.p2wsh.unwrap();
+        assert_eq!(p2wsh.descriptor, control.config.main_descriptor);
+        let taproot = res.taproot.unwrap();
+        assert!(taproot.descriptor.is_taproot());
+        for desc_cost in [&p2wsh, &taproot] {
+            assert_eq!(desc_cost.primary_path.timelock, None);
+            assert_eq!(desc_cost.recovery_paths.len(), 1);
+            assert_eq!(desc_cost.recovery_paths[0].timelock, Some(10_000));
+            for cost in
+                std::iter::once(&desc_cost.primary_path).chain(desc_cost.recovery_paths.iter())
+            {
+                assert_eq!(cost.fees.len(), 5);
+                for (i, fee) in cost.fees.iter().enumerate() {
+                    assert_eq!(fee.feerate, i as u64 + 1);
+                    assert_eq!(fee.fee.to_sat(), cost.tx_vbytes * fee.feerate);
+                }
+            }
+        }
+        // Single-key paths are cheaper to spend under Taproot.
+        assert!(taproot.primary_path.input_vbytes < p2wsh.primary_path.input_vbytes);
+
+        // We can pass another descriptor.
+        let other_desc = descriptors::LianaDescriptor::from_str("tr([f5acc2fd]tpubD6NzVbkrYhZ4YgUx2ZLNt2rLYAMTdYysCRzKoLu2BeSHKvzqPaBDvf17GeBPnExUVPkuBpx4kniP964e2MxyzzazcXLptxLXModSVCVEV1T/<0;1>/*,and_v(v:pkh([8a64f2a9]tpubD6NzVbkrYhZ4WmzFjvQrp7sDa4ECUxTi9oby8K4FZkd3XCBtEdKwUiQyYJaxiJo5y42gyDWEczrFpozEjeLxMPxjf2WtkfcbpUdfvNnozWF/<0;1>/*),older(10)))").unwrap();
+        let res = control
+            .simulate_spend_cost(Some(&other_desc), 1, 1, 2, 2)
+            .unwrap();
+        assert_eq!(res.taproot.unwrap().descriptor, other_desc);
+        assert_eq!(res.p2wsh.unwrap().recovery_paths[0].timelock, Some(10));
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn wallet_management() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.control();
+        let desc = control.config.main_descriptor.clone();
+        let data_dir = control.config.data_directory().unwrap();
+
+        // Wallet ids are used as directory names.
+        for id in ["", "../main", "a/b", &"a".repeat(65)] {
+            assert_eq!(
+                control.create_wallet(id.to_string(), desc.clone()),
+                Err(CommandError::InvalidWalletId(id.to_string()))
+            );
+        }
+        assert_eq!(
+            control.wallet("vault-1").err(),
+            Some(CommandError::UnknownWallet("vault-1".to_string()))
+        );
+        assert_eq!(
+            control.unload_wallet("vault-1".to_string()),
+            Err(CommandError::UnknownWallet("vault-1".to_string()))
+        );
+        assert_eq!(
+            control.load_wallet("vault-1".to_string()),
+            Err(CommandError::WalletNotFound("vault-1".to_string()))
+        );
+        assert!(control.list_wallets().wallets.is_empty());
+
+        // The descriptor must be for our network.
+        let testnet_desc = descriptors::LianaDescriptor::from_str("wsh(or_d(pk([f5acc2fd]tpubD6NzVbkrYhZ4YgUx2ZLNt2rLYAMTdYysCRzKoLu2BeSHKvzqPaBDvf17GeBPnExUVPkuBpx4kniP964e2MxyzzazcXLptxLXModSVCVEV1T/<0;1>/*),and_v(v:pkh([8a64f2a9]tpubD6NzVbkrYhZ4WmzFjvQrp7sDa4ECUxTi9oby8K4FZkd3XCBtEdKwUiQyYJaxiJo5y42gyDWEczrFpozEjeLxMPxjf2WtkfcbpUdfvNnozWF/<0;1>/*),older(10))))").unwrap();
+        assert!(matches!(
+            control.create_wallet("vault-1".to_string(), testnet_desc),
+            Err(CommandError::WalletSetup(_))
+        ));
+
+        // The dummy daemon has no Bitcoin backend configured to set up new wallets with. The data
+        // directory of a wallet which failed to be created isn't left behind.
+        assert!(matches!(
+            control.create_wallet("vault-1".to_string(), desc.clone()),
+            Err(CommandError::WalletSetup(_))
+        ));
+        assert!(!data_dir.wallet_directory("vault-1").exists());
+        assert!(control.list_wallets().wallets.is_empty());
+
+        // Wallets present in the data directory are listed, even if not loaded.
+        let wallet_dir = data_dir.wallet_directory("vault-2");
+        wallet_dir.init().unwrap();
+        SqliteDb::new(
+            wallet_dir.sqlite_db_file_path(),
+            Some(FreshDbOptions::new(bitcoin::Network::Bitcoin, desc.clone())),
+            &control.secp,
+        )
+        .unwrap();
+        let wallets = control.list_wallets().wallets;
+        assert_eq!(wallets.len(), 1);
+        assert_eq!(wallets[0].id, "vault-2");
+        assert_eq!(wallets[0].descriptor, desc);
+        assert!(!wallets[0].loaded);
+        assert_eq!(
+            control.create_wallet("vault-2".to_string(), desc.clone()),
+            Err(CommandError::WalletAlreadyExists("vault-2".to_string()))
+        );
+        // Failing to load an existing wallet must not remove it.
+        assert!(matches!(
+            control.load_wallet("vault-2".to_string()),
+            Err(CommandError::WalletSetup(_))
+        ));
+        assert!(wallet_dir.sqlite_db_file_path().exists());
+
+        // The list of wallets to load at startup ignores invalid ids. A wallet from the list
+        // which fails to be loaded is skipped.
+        let loaded_wallets_path = data_dir.loaded_wallets_file_path();
+        assert!(read_loaded_wallets(&loaded_wallets_path).is_empty());
+        std::fs::write(&loaded_wallets_path, "vault-2\n../main\n\nvault-3\n").unwrap();
+        assert_eq!(
+            read_loaded_wallets(&loaded_wallets_path),
+            vec!["vault-2".to_string(), "vault-3".to_string()]
+        );
+        control.load_persisted_wallets();
+        assert!(control.list_wallets().wallets.iter().all(|w| !w.loaded));
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn migrate_wallet() {
+        let dummy_tx = bitcoin::Transaction {
+            version: TxVersion::TWO,
+            lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
+            input: vec![],
+            output: vec![],
+        };
+        let dummy_txid = dummy_tx.compute_txid();
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.control();
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_txs(&[dummy_tx]);
+        let network = control.config.bitcoin_config.network;
+        // Same policy, but under Taproot.
+        let new_desc = control.config.main_descriptor.with_taproot(true).unwrap();
+
+        // Arguments sanity checking.
+        assert_eq!(
+            control.migrate_wallet(&new_desc, 0, None),
+            Err(CommandError::InvalidFeerate(0))
+        );
+        assert!(matches!(
+            control.migrate_wallet(&control.config.main_descriptor, 1, None),
+            Err(CommandError::InvalidMigrationDescriptor(_))
+        ));
+        let testnet_desc = descriptors::LianaDescriptor::from_str("wsh(or_d(pk([f5acc2fd]tpubD6NzVbkrYhZ4YgUx2ZLNt2rLYAMTdYysCRzKoLu2BeSHKvzqPaBDvf17GeBPnExUVPkuBpx4kniP964e2MxyzzazcXLptxLXModSVCVEV1T/<0;1>/*),and_v(v:pkh([8a64f2a9]tpubD6NzVbkrYhZ4WmzFjvQrp7sDa4ECUxTi9oby8K4FZkd3XCBtEdKwUiQyYJaxiJo5y42gyDWEczrFpozEjeLxMPxjf2WtkfcbpUdfvNnozWF/<0;1>/*),older(10))))").unwrap();
+        assert!(matches!(
+            control.migrate_wallet(&testnet_desc, 1, None),
+            Err(CommandError::InvalidMigrationDescriptor(_))
+        ));
+        // A wallet for the new descriptor must be loaded.
+        assert!(matches!(
+            control.migrate_wallet(&new_desc, 1, None),
+            Err(CommandError::InvalidMigrationDescriptor(_))
+        ));
+        let successor_db = sync::Arc::new(sync::Mutex::new(DummyDatabase::new()))
+            as sync::Arc<sync::Mutex<dyn DatabaseInterface>>;
+        let successor = DaemonControl {
+            config: control.config.for_wallet(
+                new_desc.clone(),
+                &control
+                    .config
+                    .data_directory()
+                    .unwrap()
+                    .wallet_directory("successor"),
+            ),
+            bitcoin: control.bitcoin.clone(),
+            poller_sender: control.poller_sender.clone(),
+            db: successor_db.clone(),
+            secp: control.secp.clone(),
+            wallets: control.weak_wallets(),
+            metrics: control.metrics.clone(),
+        };
+        control
+            .wallets()
+            .lock()
+            .unwrap()
+            .insert("successor".to_string(), successor);
+        assert_eq!(
+            control.migrate_wallet(&new_desc, 1, None),
+            Err(CommandError::NoCoinToMigrate)
+        );
+
+        // Add three confirmed coins and an unconfirmed one.
+        let coins: Vec<Coin> = (0..4)
+            .map(|vout| Coin {
+                outpoint: bitcoin::OutPoint::new(dummy_txid, vout),
+                is_immature: false,
+                block_info: None,
+                amount: bitcoin::Amount::from_sat(100_000),
+                derivation_index: bip32::ChildNumber::from(vout),
+                is_change: false,
+                spend_txid: None,
+                spend_block: None,
+                is_from_self: false,
+                is_quarantined: vout == 2,
+            })
+            .collect();
+        db_conn.new_unspent_coins(&coins);
+        db_conn.confirm_coins(
+            &coins[..3]
+                .iter()
+                .map(|c| (c.outpoint, 100, 1_000_000))
+                .collect::<Vec<_>>(),
+        );
+
+        // An unconfirmed coin must confirm first, it's not left behind in the retired wallet.
+        assert_eq!(
+            control.migrate_wallet(&new_desc, 1, Some(2)),
+            Err(CommandError::CoinsNotMigratable(vec![coins[3].outpoint]))
+        );
+        db_conn.confirm_coins(&[(coins[3].outpoint, 101, 1_000_000)]);
+
+        // A quarantined coin must be released first, it's not left behind in the retired wallet.
+        assert_eq!(
+            control.migrate_wallet(&new_desc, 1, Some(2)),
+            Err(CommandError::QuarantinedCoins(vec![coins[2].outpoint]))
+        );
+        control.release_coins(&[coins[2].outpoint]).unwrap();
+
+        let labels: HashMap<LabelItem, Option<String>> =
+            vec![(LabelItem::Txid(dummy_txid), Some("deposits".to_string()))]
+                .into_iter()
+                .collect();
+        control.update_labels(&labels);
+
+        // The new wallet already revealed some addresses.
+        let mut successor_conn = successor_db.connection();
+        successor_conn.set_receive_index(3.into(), &control.secp);
+
+        // Ask for at most three inputs per transaction. The four coins are swept in two
+        // transactions to the two addresses following those the new wallet revealed.
+        let res = control.migrate_wallet(&new_desc, 1, Some(3)).unwrap();
+        assert_eq!(res.psbts.len(), 2);
+        assert_eq!(res.psbts[0].unsigned_tx.input.len(), 3);
+        assert_eq!(res.psbts[1].unsigned_tx.input.len(), 1);
+        for (i, psbt) in res.psbts.iter().enumerate() {
+            assert_eq!(psbt.unsigned_tx.output.len(), 1);
+            let addr = new_desc
+                .receive_descriptor()
+                .derive((i as u32 + 4).into(), &control.secp)
+                .address(network);
+            assert_eq!(
+                psbt.unsigned_tx.output[0].script_pubkey,
+                addr.script_pubkey()
+            );
+        }
+        // The PSBTs were stored and the wallet marked as retired.
+        assert_eq!(control.list_spend(None).unwrap().spend_txs.len(), 2);
+        let info = control.get_info();
+        assert!(info.retired_timestamp.is_some());
+        assert_eq!(db_conn.wallet().successor_descriptor, Some(new_desc));
+        // The labels were copied to the new wallet, and the addresses swept to are marked as used.
+        assert_eq!(
+            successor_conn.labels(&vec![LabelItem::Txid(dummy_txid)].into_iter().collect()),
+            vec![(dummy_txid.to_string(), "deposits".to_string())]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(successor_conn.receive_index(), ChildNumber::from(5));
+
+        // It can't be migrated again.
+        assert_eq!(
+            control.migrate_wallet(&new_desc, 1, None),
+            Err(CommandError::AlreadyRetired)
+        );
+        assert_eq!(control.list_spend(None).unwrap().spend_txs.len(), 2);
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn getnewaddress() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+
+        let control = &ms.control();
+        // We can get an address (it will have index 1)
+        let addr = control.get_new_address().address;
+        // $ bitcoin-cli deriveaddresses "wsh(or_d(pk([aabbccdd]xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/0/*),and_v(v:pkh([aabbccdd]xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/0/*),older(10000))))#wx6v3mks" 1
+        // [
+        // "bc1q9ksrc647hx8zp2cewl8p5f487dgux3777yees8rjcx46t4daqzzqt7yga8",
+        // "bc1qm06ceyghltr8v5cmeckh6cquhy9nks626pahapc04xd98kwf478qwmhqew"
+        // ]
+        assert_eq!(
+            addr,
+            bitcoin::Address::from_str(
+                "bc1qm06ceyghltr8v5cmeckh6cquhy9nks626pahapc04xd98kwf478qwmhqew"
+            )
+            .unwrap()
+            .assume_checked()
+        );
+        // We won't get the same twice.
+        let addr2 = control.get_new_address().address;
+        assert_ne!(addr, addr2);
 
         ms.shutdown();
     }
@@ -1841,6 +4160,7 @@ mod tests {
             spend_txid: None,
             spend_block: None,
             is_from_self: true,
+            is_quarantined: false,
         }]);
 
         // If we don't exclude used, results will be same as before, except index 5 is marked as used:
@@ -1938,6 +4258,7 @@ mod tests {
             spend_txid: None,
             spend_block: None,
             is_from_self: true,
+            is_quarantined: false,
         }]);
 
         let list = control
@@ -2021,7 +4342,7 @@ mod tests {
         let dummy_value = 10_000;
         let mut destinations = <HashMap<bitcoin::Address<address::NetworkUnchecked>, u64>>::new();
         assert_eq!(
-            control.create_spend(&destinations, &[], 1, None),
+            control.create_spend(&destinations, &[], 1, None, None, &[]),
             Err(CommandError::NoOutpointForSelfSend)
         );
         destinations = [(dummy_addr.clone(), dummy_value)]
@@ -2030,18 +4351,18 @@ mod tests {
             .collect();
         // Insufficient funds for coin selection.
         assert!(matches!(
-            control.create_spend(&destinations, &[], 1, None),
+            control.create_spend(&destinations, &[], 1, None, None, &[]),
             Ok(CreateSpendResult::InsufficientFunds { .. }),
         ));
         assert_eq!(
-            control.create_spend(&destinations, &[dummy_op], 0, None),
+            control.create_spend(&destinations, &[dummy_op], 0, None, None, &[]),
             Err(CommandError::InvalidFeerate(0))
         );
 
         // The coin doesn't exist. If we create a new unspent one at this outpoint with a much
         // higher value, we'll get a Spend transaction with a change output.
         assert_eq!(
-            control.create_spend(&destinations, &[dummy_op], 1, None),
+            control.create_spend(&destinations, &[dummy_op], 1, None, None, &[]),
             Err(CommandError::UnknownOutpoint(dummy_op))
         );
         db_conn.new_unspent_coins(&[Coin {
@@ -2054,15 +4375,16 @@ mod tests {
             spend_txid: None,
             spend_block: None,
             is_from_self: false,
+            is_quarantined: false,
         }]);
         // If we try to use coin selection, the unconfirmed not-from-self coin will not be used
         // as a candidate and so we get a coin selection error due to insufficient funds.
         assert!(matches!(
-            control.create_spend(&destinations, &[], 1, None),
+            control.create_spend(&destinations, &[], 1, None, None, &[]),
             Ok(CreateSpendResult::InsufficientFunds { .. }),
         ));
         let (psbt, warnings) = if let CreateSpendResult::Success { psbt, warnings } = control
-            .create_spend(&destinations, &[dummy_op], 1, None)
+            .create_spend(&destinations, &[dummy_op], 1, None, None, &[])
             .unwrap()
         {
             (psbt, warnings)
@@ -2092,7 +4414,7 @@ mod tests {
         // At 2sats/vb, it's twice that.
         assert_eq!(tx.output[1].value.to_sat(), 89_839);
         let psbt = if let CreateSpendResult::Success { psbt, .. } = control
-            .create_spend(&destinations, &[dummy_op], 2, None)
+            .create_spend(&destinations, &[dummy_op], 2, None, None, &[])
             .unwrap()
         {
             psbt
@@ -2105,22 +4427,22 @@ mod tests {
         // A feerate of 555 won't trigger the sanity checks (they were previously not taking the
         // satisfaction size into account and overestimating the feerate).
         control
-            .create_spend(&destinations, &[dummy_op], 555, None)
+            .create_spend(&destinations, &[dummy_op], 555, None, None, &[])
             .unwrap();
 
         // If we ask for a too high feerate, or a too large/too small output, it'll fail.
         assert!(matches!(
-            control.create_spend(&destinations, &[dummy_op], 10_000, None),
+            control.create_spend(&destinations, &[dummy_op], 10_000, None, None, &[]),
             Ok(CreateSpendResult::InsufficientFunds { .. }),
         ));
         *destinations.get_mut(&dummy_addr).unwrap() = 100_001;
         assert!(matches!(
-            control.create_spend(&destinations, &[dummy_op], 1, None),
+            control.create_spend(&destinations, &[dummy_op], 1, None, None, &[]),
             Ok(CreateSpendResult::InsufficientFunds { .. }),
         ));
         *destinations.get_mut(&dummy_addr).unwrap() = DUST - 1;
         assert_eq!(
-            control.create_spend(&destinations, &[dummy_op], 1, None),
+            control.create_spend(&destinations, &[dummy_op], 1, None, None, &[]),
             Err(CommandError::SpendCreation(
                 SpendCreationError::InvalidOutputValue(bitcoin::Amount::from_sat(DUST - 1))
             ))
@@ -2132,7 +4454,7 @@ mod tests {
         let invalid_destinations: HashMap<bitcoin::Address<address::NetworkUnchecked>, u64> =
             [(invalid_addr, dummy_value)].iter().cloned().collect();
         assert!(matches!(
-            control.create_spend(&invalid_destinations, &[dummy_op], 1, None),
+            control.create_spend(&invalid_destinations, &[dummy_op], 1, None, None, &[]),
             Err(CommandError::Address(
                 address::error::ParseError::NetworkValidation { .. }
             ))
@@ -2142,7 +4464,7 @@ mod tests {
         // won't create an output lower than 500 sats.
         *destinations.get_mut(&dummy_addr).unwrap() = COIN_VALUE - DUST;
         let (psbt, warnings) = if let CreateSpendResult::Success { psbt, warnings } = control
-            .create_spend(&destinations, &[dummy_op], 1, None)
+            .create_spend(&destinations, &[dummy_op], 1, None, None, &[])
             .unwrap()
         {
             (psbt, warnings)
@@ -2171,7 +4493,7 @@ mod tests {
         // Increase the target value by the change amount and the warning will disappear.
         *destinations.get_mut(&dummy_addr).unwrap() = (COIN_VALUE - DUST) + 339;
         let (psbt, warnings) = if let CreateSpendResult::Success { psbt, warnings } = control
-            .create_spend(&destinations, &[dummy_op], 1, None)
+            .create_spend(&destinations, &[dummy_op], 1, None, None, &[])
             .unwrap()
         {
             (psbt, warnings)
@@ -2186,7 +4508,7 @@ mod tests {
         *destinations.get_mut(&dummy_addr).unwrap() =
             (COIN_VALUE - DUST) + 330 + /* fee for change output */ 43;
         let (psbt, warnings) = if let CreateSpendResult::Success { psbt, warnings } = control
-            .create_spend(&destinations, &[dummy_op], 1, None)
+            .create_spend(&destinations, &[dummy_op], 1, None, None, &[])
             .unwrap()
         {
             (psbt, warnings)
@@ -2201,7 +4523,7 @@ mod tests {
         *destinations.get_mut(&dummy_addr).unwrap() =
             (COIN_VALUE - DUST) + 339 + /* fee for change output */ 43 + 1;
         assert_eq!(
-            control.create_spend(&destinations, &[dummy_op], 1, None),
+            control.create_spend(&destinations, &[dummy_op], 1, None, None, &[]),
             Ok(CreateSpendResult::InsufficientFunds { missing: 1 }),
         );
 
@@ -2209,7 +4531,7 @@ mod tests {
         *destinations.get_mut(&dummy_addr).unwrap() =
             COIN_VALUE - /* fee without change */ 118 - /* extra fee for change output */ 43 - 1;
         let warnings = if let CreateSpendResult::Success { warnings, .. } = control
-            .create_spend(&destinations, &[dummy_op], 1, None)
+            .create_spend(&destinations, &[dummy_op], 1, None, None, &[])
             .unwrap()
         {
             warnings
@@ -2230,7 +4552,7 @@ mod tests {
         *destinations.get_mut(&dummy_addr).unwrap() = (COIN_VALUE - DUST) - /* fee without change */ 118 - /* extra fee for change output */ 43;
 
         let (psbt, warnings) = if let CreateSpendResult::Success { psbt, warnings } = control
-            .create_spend(&destinations, &[dummy_op], 1, None)
+            .create_spend(&destinations, &[dummy_op], 1, None, None, &[])
             .unwrap()
         {
             (psbt, warnings)
@@ -2246,7 +4568,7 @@ mod tests {
         *destinations.get_mut(&dummy_addr).unwrap() = (COIN_VALUE - DUST) - /* fee without change */ 118 - /* extra fee for change output */ 43
             + 1;
         let warnings = if let CreateSpendResult::Success { warnings, .. } = control
-            .create_spend(&destinations, &[dummy_op], 1, None)
+            .create_spend(&destinations, &[dummy_op], 1, None, None, &[])
             .unwrap()
         {
             warnings
@@ -2272,13 +4594,13 @@ mod tests {
             .unwrap(),
         )]);
         assert_eq!(
-            control.create_spend(&destinations, &[dummy_op], 1, None),
+            control.create_spend(&destinations, &[dummy_op], 1, None, None, &[]),
             Err(CommandError::AlreadySpent(dummy_op))
         );
         // If we try to use coin selection, the spent coin will not be used as a candidate
         // and so we get a coin selection error due to insufficient funds.
         assert!(matches!(
-            control.create_spend(&destinations, &[], 1, None),
+            control.create_spend(&destinations, &[], 1, None, None, &[]),
             Ok(CreateSpendResult::InsufficientFunds { .. }),
         ));
 
@@ -2297,9 +4619,10 @@ mod tests {
             spend_txid: None,
             spend_block: None,
             is_from_self: false,
+            is_quarantined: false,
         }]);
         assert_eq!(
-            control.create_spend(&destinations, &[dummy_op_dup], 1_001, None),
+            control.create_spend(&destinations, &[dummy_op_dup], 1_001, None, None, &[]),
             Err(CommandError::SpendCreation(SpendCreationError::InsaneFees(
                 InsaneFeeInfo::TooHighFeerate(1_001)
             )))
@@ -2320,18 +4643,19 @@ mod tests {
             spend_txid: None,
             spend_block: None,
             is_from_self: true,
+            is_quarantined: false,
         };
         db_conn.new_unspent_coins(&[unconfirmed_coin]);
         // Coin selection error due to insufficient funds.
         assert!(matches!(
-            control.create_spend(&destinations, &[], 1, None),
+            control.create_spend(&destinations, &[], 1, None, None, &[]),
             Ok(CreateSpendResult::InsufficientFunds { .. }),
         ));
         // Set destination amount equal to value of confirmed coins.
         *destinations.get_mut(&dummy_addr).unwrap() = 80_000;
         // Coin selection error occurs due to insufficient funds to pay fee.
         assert!(matches!(
-            control.create_spend(&destinations, &[], 1, None),
+            control.create_spend(&destinations, &[], 1, None, None, &[]),
             Ok(CreateSpendResult::InsufficientFunds { .. }),
         ));
         let confirmed_op_2 = bitcoin::OutPoint {
@@ -2352,10 +4676,12 @@ mod tests {
             spend_txid: None,
             spend_block: None,
             is_from_self: false,
+            is_quarantined: false,
         }]);
         // First, create a transaction using auto coin selection.
-        let psbt = if let CreateSpendResult::Success { psbt, .. } =
-            control.create_spend(&destinations, &[], 1, None).unwrap()
+        let psbt = if let CreateSpendResult::Success { psbt, .. } = control
+            .create_spend(&destinations, &[], 1, None, None, &[])
+            .unwrap()
         {
             psbt
         } else {
@@ -2380,7 +4706,14 @@ mod tests {
 
         // Create a second transaction using manual coin selection.
         let psbt = if let CreateSpendResult::Success { psbt, .. } = control
-            .create_spend(&destinations, &[confirmed_op_1, confirmed_op_2], 1, None)
+            .create_spend(
+                &destinations,
+                &[confirmed_op_1, confirmed_op_2],
+                1,
+                None,
+                None,
+                &[],
+            )
             .unwrap()
         {
             psbt
@@ -2413,7 +4746,7 @@ mod tests {
         unconfirmed_coin_2.is_change = false;
         db_conn.new_unspent_coins(&[unconfirmed_coin_2]);
         assert!(matches!(
-            control.create_spend(&destinations, &[], 1, None),
+            control.create_spend(&destinations, &[], 1, None, None, &[]),
             Ok(CreateSpendResult::InsufficientFunds { .. }),
         ));
         // 2. not from self and change
@@ -2422,7 +4755,7 @@ mod tests {
         unconfirmed_coin_2.is_change = true;
         db_conn.new_unspent_coins(&[unconfirmed_coin_2]);
         assert!(matches!(
-            control.create_spend(&destinations, &[], 1, None),
+            control.create_spend(&destinations, &[], 1, None, None, &[]),
             Ok(CreateSpendResult::InsufficientFunds { .. }),
         ));
 
@@ -2443,6 +4776,8 @@ mod tests {
                 &[confirmed_op_1, confirmed_op_2],
                 1,
                 Some(change_address.as_unchecked().clone()),
+                None,
+                &[],
             )
             .unwrap()
         {
@@ -2479,15 +4814,16 @@ mod tests {
             spend_txid: None,
             spend_block: None,
             is_from_self: false,
+            is_quarantined: false,
         }]);
         let empty_dest = &HashMap::<bitcoin::Address<address::NetworkUnchecked>, u64>::new();
         assert_eq!(
-            control.create_spend(empty_dest, &[confirmed_op_3], 5, None),
+            control.create_spend(empty_dest, &[confirmed_op_3], 5, None, None, &[]),
             Ok(CreateSpendResult::InsufficientFunds { missing: 150 },)
         );
         // If we use a lower fee, the self-send will succeed.
         let psbt = if let CreateSpendResult::Success { psbt, .. } = control
-            .create_spend(empty_dest, &[confirmed_op_3], 1, None)
+            .create_spend(empty_dest, &[confirmed_op_3], 1, None, None, &[])
             .unwrap()
         {
             psbt
@@ -2519,9 +4855,10 @@ mod tests {
             spend_txid: None,
             spend_block: None,
             is_from_self: false,
+            is_quarantined: false,
         }]);
         assert_eq!(
-            control.create_spend(&destinations, &[imma_op], 1_001, None),
+            control.create_spend(&destinations, &[imma_op], 1_001, None, None, &[]),
             Err(CommandError::ImmatureCoinbase(imma_op))
         );
 
@@ -2565,6 +4902,7 @@ mod tests {
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             },
             Coin {
                 outpoint: dummy_op_b,
@@ -2576,6 +4914,7 @@ mod tests {
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             },
         ]);
 
@@ -2602,7 +4941,7 @@ mod tests {
                 .cloned()
                 .collect();
         let mut psbt_a = if let CreateSpendResult::Success { psbt, .. } = control
-            .create_spend(&destinations_a, &[dummy_op_a], 1, None)
+            .create_spend(&destinations_a, &[dummy_op_a], 1, None, None, &[])
             .unwrap()
         {
             psbt
@@ -2611,7 +4950,7 @@ mod tests {
         };
         let txid_a = psbt_a.unsigned_tx.compute_txid();
         let psbt_b = if let CreateSpendResult::Success { psbt, .. } = control
-            .create_spend(&destinations_b, &[dummy_op_b], 10, None)
+            .create_spend(&destinations_b, &[dummy_op_b], 10, None, None, &[])
             .unwrap()
         {
             psbt
@@ -2620,7 +4959,14 @@ mod tests {
         };
         let txid_b = psbt_b.unsigned_tx.compute_txid();
         let psbt_c = if let CreateSpendResult::Success { psbt, .. } = control
-            .create_spend(&destinations_c, &[dummy_op_a, dummy_op_b], 100, None)
+            .create_spend(
+                &destinations_c,
+                &[dummy_op_a, dummy_op_b],
+                100,
+                None,
+                None,
+                &[],
+            )
             .unwrap()
         {
             psbt
@@ -2724,6 +5070,7 @@ mod tests {
                 time: 184500,
             }),
             is_from_self: false,
+            is_quarantined: false,
         }]);
         // The coin is spent so we cannot RBF.
         assert_eq!(
@@ -2836,6 +5183,7 @@ mod tests {
                 amount: bitcoin::Amount::from_sat(100_000_000),
                 spend_txid: Some(spend_tx.compute_txid()),
                 is_from_self: false,
+                is_quarantined: false,
             },
             // Deposit 2
             Coin {
@@ -2851,6 +5199,7 @@ mod tests {
                 amount: bitcoin::Amount::from_sat(2000),
                 spend_txid: None,
                 is_from_self: false,
+                is_quarantined: false,
             },
             // This coin is a change output.
             Coin {
@@ -2863,6 +5212,7 @@ mod tests {
                 amount: bitcoin::Amount::from_sat(100_000_000 - 4000 - 1000),
                 spend_txid: None,
                 is_from_self: false,
+                is_quarantined: false,
             },
             // Deposit 3
             Coin {
@@ -2878,6 +5228,7 @@ mod tests {
                 amount: bitcoin::Amount::from_sat(3000),
                 spend_txid: None,
                 is_from_self: false,
+                is_quarantined: false,
             },
         ]);
 
@@ -3099,6 +5450,7 @@ mod tests {
                     spend_txid: None,
                     spend_block: None,
                     is_from_self: false,
+                    is_quarantined: false,
                 }]);
             }
         }
@@ -3126,6 +5478,260 @@ mod tests {
         ms.shutdown();
     }
 
+    #[test]
+    fn get_balance() {
+        let ms = DummyLiana::new_timelock(DummyBitcoind::new(), DummyDatabase::new(), 10);
+        let control = &ms.control();
+        let mut db_conn = control.db().lock().unwrap().connection();
+
+        // No coin, no balance.
+        let empty = control.get_balance(5);
+        assert_eq!(empty.confirmed, Amount::ZERO);
+        assert_eq!(empty.window, 5);
+        assert_eq!(
+            empty.recovery_paths,
+            vec![RecoveryPathBalance {
+                timelock: 10,
+                spendable: Amount::ZERO,
+                spendable_within_window: Amount::ZERO,
+            }]
+        );
+
+        // The tip is at 100. Coins confirmed at 85 can be recovered at the next block, those
+        // confirmed at 95 at block 105 and those confirmed at 99 at block 109.
+        let coin = |vout: u32, height: Option<i32>, amount: u64| Coin {
+            outpoint: bitcoin::OutPoint::new(
+                Txid::from_str("84f09bddfe0f036d0390edf655636ad6092c3ab8f09b2bb1503caa393463f241")
+                    .unwrap(),
+                vout,
+            ),
+            is_immature: false,
+            block_info: height.map(|height| BlockInfo { height, time: 0 }),
+            amount: Amount::from_sat(amount),
+            derivation_index: bip32::ChildNumber::from(vout),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            is_from_self: false,
+            is_quarantined: false,
+        };
+        // Immature coins are not recoverable, even if confirmed long enough ago.
+        let immature = Coin {
+            is_immature: true,
+            ..coin(4, Some(80), 2_000)
+        };
+        let spending = Coin {
+            spend_txid: Some(
+                Txid::from_str("7d0ea4aaa98a4ea45e6a0c8b2e58df36f4a6fcd3eb7b0e9fdb2f8b7d85d6a7b3")
+                    .unwrap(),
+            ),
+            ..coin(5, Some(80), 10_000)
+        };
+        let in_draft = coin(6, Some(80), 20_000);
+        db_conn.new_unspent_coins(&[
+            coin(0, Some(85), 100_000),
+            coin(1, Some(95), 200_000),
+            coin(2, Some(99), 400_000),
+            coin(3, None, 1_000),
+            immature,
+            spending,
+            in_draft,
+        ]);
+        let draft_tx = bitcoin::Transaction {
+            version: TxVersion::TWO,
+            lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
+            input: vec![bitcoin::TxIn {
+                previous_output: in_draft.outpoint,
+                ..bitcoin::TxIn::default()
+            }],
+            output: vec![],
+        };
+        db_conn.store_spend(&Psbt::from_unsigned_tx(draft_tx).unwrap());
+
+        let balance = control.get_balance(5);
+        assert_eq!(balance.confirmed, Amount::from_sat(700_000));
+        assert_eq!(balance.unconfirmed, Amount::from_sat(1_000));
+        assert_eq!(balance.immature, Amount::from_sat(2_000));
+        assert_eq!(balance.spending, Amount::from_sat(30_000));
+        assert_eq!(
+            balance.recovery_paths,
+            vec![RecoveryPathBalance {
+                timelock: 10,
+                spendable: Amount::from_sat(120_000),
+                spendable_within_window: Amount::from_sat(200_000),
+            }]
+        );
+
+        // With a larger window, the last confirmed coin becomes recoverable within it too.
+        let balance = control.get_balance(10);
+        assert_eq!(
+            balance.recovery_paths[0].spendable_within_window,
+            Amount::from_sat(600_000)
+        );
+    }
+
+    #[test]
+    fn analyze_psbt() {
+        let ms = DummyLiana::new_timelock(DummyBitcoind::new(), DummyDatabase::new(), 10);
+        let control = &ms.control();
+        let mut db_conn = control.db().lock().unwrap().connection();
+
+        // An empty PSBT can't be analyzed.
+        let empty_tx = bitcoin::Transaction {
+            version: TxVersion::TWO,
+            lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
+            input: vec![],
+            output: vec![],
+        };
+        assert!(matches!(
+            control.analyze_psbt(&Psbt::from_unsigned_tx(empty_tx).unwrap()),
+            Err(CommandError::InvalidPsbt(..))
+        ));
+
+        // Confirm a coin such that the recovery path is available at the next block (101).
+        let op = bitcoin::OutPoint::new(
+            Txid::from_str("84f09bddfe0f036d0390edf655636ad6092c3ab8f09b2bb1503caa393463f241")
+                .unwrap(),
+            0,
+        );
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: op,
+            is_immature: false,
+            block_info: None,
+            amount: Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            is_from_self: false,
+            is_quarantined: false,
+        }]);
+        db_conn.confirm_coins(&[(op, 91, 100_000)]);
+
+        // A Spend with a change output, not signed yet.
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations = HashMap::from([(dummy_addr.clone(), 10_000)]);
+        let psbt = match control.create_spend(&destinations, &[op], 1, None, None, &[]) {
+            Ok(CreateSpendResult::Success { psbt, .. }) => psbt,
+            res => panic!("Unexpected result: {:?}", res),
+        };
+        let analysis = control.analyze_psbt(&psbt).unwrap();
+        assert_eq!(analysis.txid, psbt.unsigned_tx.compute_txid());
+        assert_eq!(
+            analysis.inputs,
+            vec![AnalyzePsbtInput {
+                outpoint: op,
+                amount: Some(Amount::from_sat(100_000)),
+                is_ours: true,
+            }]
+        );
+        assert_eq!(analysis.change_indexes.len(), 1);
+        let out_value: Amount = psbt.unsigned_tx.output.iter().map(|txo| txo.value).sum();
+        assert_eq!(analysis.fee, Some(Amount::from_sat(100_000) - out_value));
+        assert_eq!(analysis.feerate_vb, Some(1));
+        assert!(analysis.locktime_ready);
+        assert!(!analysis.can_finalize);
+        // A PSBT whose non-witness UTXO isn't the transaction spent by the input is refused.
+        let mut bad_psbt = psbt.clone();
+        bad_psbt.inputs[0].non_witness_utxo = Some(Transaction {
+            version: TxVersion::TWO,
+            lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        });
+        assert!(matches!(
+            control.analyze_psbt(&bad_psbt),
+            Err(CommandError::InvalidPsbt(..))
+        ));
+        // The sequence doesn't enable the recovery path.
+        assert_eq!(
+            analysis.paths,
+            vec![PsbtPathAnalysis {
+                timelock: None,
+                threshold: 1,
+                sigs_count: 0,
+                signed_pubkeys: HashMap::new(),
+                missing_signers: vec![bip32::Fingerprint::from_str("aabbccdd").unwrap()],
+                timelock_ready: true,
+            }]
+        );
+
+        // The recovery path is available for a recovery transaction, and the coin is mature
+        // enough to use it.
+        let psbt = control
+            .create_recovery(dummy_addr, &[], 1, None)
+            .unwrap()
+            .psbt;
+        let analysis = control.analyze_psbt(&psbt).unwrap();
+        assert!(analysis.change_indexes.is_empty());
+        assert_eq!(analysis.paths.len(), 2);
+        assert_eq!(analysis.paths[1].timelock, Some(10));
+        assert!(analysis.paths[1].timelock_ready);
+
+        // Nothing was stored.
+        assert!(db_conn.list_spend().is_empty());
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn finalize_psbt() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.control();
+        let mut db_conn = control.db().lock().unwrap().connection();
+
+        // A PSBT spending a coin which isn't ours is refused.
+        let op = bitcoin::OutPoint::new(
+            Txid::from_str("84f09bddfe0f036d0390edf655636ad6092c3ab8f09b2bb1503caa393463f241")
+                .unwrap(),
+            0,
+        );
+        let foreign_tx = bitcoin::Transaction {
+            version: TxVersion::TWO,
+            lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
+            input: vec![bitcoin::TxIn {
+                previous_output: op,
+                ..bitcoin::TxIn::default()
+            }],
+            output: vec![],
+        };
+        assert!(matches!(
+            control.finalize_psbt(Psbt::from_unsigned_tx(foreign_tx).unwrap()),
+            Err(CommandError::InvalidPsbt(..))
+        ));
+
+        // A PSBT spending one of our coins can't be finalized until it's signed.
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: op,
+            is_immature: false,
+            block_info: None,
+            amount: Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            is_from_self: false,
+            is_quarantined: false,
+        }]);
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations = HashMap::from([(dummy_addr, 10_000)]);
+        let psbt = match control.create_spend(&destinations, &[op], 1, None, None, &[]) {
+            Ok(CreateSpendResult::Success { psbt, .. }) => psbt,
+            res => panic!("Unexpected result: {:?}", res),
+        };
+        assert!(matches!(
+            control.finalize_psbt(psbt),
+            Err(CommandError::SpendFinalization(..))
+        ));
+
+        ms.shutdown();
+    }
+
     #[test]
     fn create_recovery() {
         let dummy_tx = bitcoin::Transaction {
@@ -3181,6 +5787,7 @@ mod tests {
             spend_txid: None,
             spend_block: None,
             is_from_self: false,
+            is_quarantined: false,
         };
         db_conn.new_unspent_coins(&[dummy_coin]);
         // Recovery not available for unconfirmed coins.
@@ -3289,6 +5896,7 @@ mod tests {
             spend_txid: None,
             spend_block: None,
             is_from_self: false,
+            is_quarantined: false,
         };
         db_conn.new_unspent_coins(&[dummy_coin_2]);
         db_conn.confirm_coins(&[(dummy_op_2, 92, 200_000)]);
@@ -3359,4 +5967,916 @@ mod tests {
 
         ms.shutdown();
     }
+
+    #[test]
+    fn create_spend_subtract_fee_and_max() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.control();
+        let mut db_conn = control.db().lock().unwrap().connection();
+
+        // Two confirmed coins of 100k sats each.
+        let ops: Vec<bitcoin::OutPoint> = (0..2)
+            .map(|vout| {
+                bitcoin::OutPoint::new(
+                    Txid::from_str(
+                        "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810",
+                    )
+                    .unwrap(),
+                    vout,
+                )
+            })
+            .collect();
+        db_conn.new_unspent_coins(
+            &ops.iter()
+                .enumerate()
+                .map(|(i, op)| Coin {
+                    outpoint: *op,
+                    is_immature: false,
+                    block_info: None,
+                    amount: Amount::from_sat(100_000),
+                    derivation_index: bip32::ChildNumber::from(i as u32),
+                    is_change: false,
+                    spend_txid: None,
+                    spend_block: None,
+                    is_from_self: false,
+                    is_quarantined: false,
+                })
+                .collect::<Vec<_>>(),
+        );
+        db_conn.confirm_coins(&ops.iter().map(|op| (*op, 50, 100_000)).collect::<Vec<_>>());
+
+        let addr_a =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let addr_b =
+            bitcoin::Address::from_str("bc1q39srgatmkp6k2ne3l52yhkjprdvunvspqydmkx").unwrap();
+        let spk_a = addr_a.assume_checked_ref().script_pubkey();
+        let spk_b = addr_b.assume_checked_ref().script_pubkey();
+        let destinations: HashMap<bitcoin::Address<address::NetworkUnchecked>, u64> =
+            HashMap::from([(addr_a.clone(), 40_000), (addr_b.clone(), 20_000)]);
+        let output_value = |psbt: &Psbt, spk: &bitcoin::ScriptBuf| {
+            psbt.unsigned_tx
+                .output
+                .iter()
+                .find(|txo| &txo.script_pubkey == spk)
+                .map(|txo| txo.value.to_sat())
+        };
+
+        // The fee can only be subtracted from one of the destinations.
+        let change_addr = bitcoin::Address::from_str(
+            "bc1qd5m23jemr8mfj8x4q482zpspnxehg0jg0pyzrhxfg8xrrtyqewvqjrq3x6",
+        )
+        .unwrap();
+        assert_eq!(
+            control.create_spend(
+                &destinations,
+                &[ops[0]],
+                1,
+                None,
+                None,
+                &[change_addr.clone()]
+            ),
+            Err(CommandError::InvalidFeeSubtraction(
+                change_addr.assume_checked_ref().to_string()
+            ))
+        );
+
+        // Subtract the fee from both destinations. It is split proportionally to their amount and
+        // the change output gets exactly the value left by the destinations.
+        let psbt = match control.create_spend(
+            &destinations,
+            &[ops[0]],
+            2,
+            None,
+            None,
+            &[addr_a.clone(), addr_b.clone()],
+        ) {
+            Ok(CreateSpendResult::Success { psbt, .. }) => psbt,
+            res => panic!("Unexpected result: {:?}", res),
+        };
+        assert_eq!(psbt.unsigned_tx.output.len(), 3);
+        let fee = psbt.fee().unwrap().to_sat();
+        let (value_a, value_b) = (
+            output_value(&psbt, &spk_a).unwrap(),
+            output_value(&psbt, &spk_b).unwrap(),
+        );
+        assert_eq!(value_a + value_b, 60_000 - fee);
+        // Shares are rounded down and the remainder is added to either of them.
+        let (share_a, share_b) = ((40_000 - value_a) as i64, (20_000 - value_b) as i64);
+        assert!((share_a - 2 * share_b).abs() <= 2);
+        let change_value: u64 = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .filter(|txo| txo.script_pubkey != spk_a && txo.script_pubkey != spk_b)
+            .map(|txo| txo.value.to_sat())
+            .sum();
+        assert_eq!(change_value, 100_000 - 60_000);
+
+        // Subtract the fee from a single destination.
+        let psbt = match control.create_spend(
+            &destinations,
+            &[ops[0]],
+            2,
+            None,
+            None,
+            &[addr_b.clone()],
+        ) {
+            Ok(CreateSpendResult::Success { psbt, .. }) => psbt,
+            res => panic!("Unexpected result: {:?}", res),
+        };
+        let fee = psbt.fee().unwrap().to_sat();
+        assert_eq!(output_value(&psbt, &spk_a), Some(40_000));
+        assert_eq!(output_value(&psbt, &spk_b), Some(20_000 - fee));
+
+        // If the fee doesn't leave enough for this destination, it errors.
+        let small_destinations: HashMap<bitcoin::Address<address::NetworkUnchecked>, u64> =
+            HashMap::from([(addr_a.clone(), 40_000), (addr_b.clone(), DUST + 10)]);
+        assert!(matches!(
+            control.create_spend(
+                &small_destinations,
+                &[ops[0]],
+                2,
+                None,
+                None,
+                &[addr_b.clone()]
+            ),
+            Err(CommandError::SpendCreation(
+                SpendCreationError::InvalidOutputValue(..)
+            ))
+        ));
+
+        // Send the max to the second destination. All the coins are spent and there is no
+        // change output.
+        let destinations_a: HashMap<bitcoin::Address<address::NetworkUnchecked>, u64> =
+            HashMap::from([(addr_a.clone(), 40_000)]);
+        let psbt =
+            match control.create_spend(&destinations_a, &[], 2, None, Some(addr_b.clone()), &[]) {
+                Ok(CreateSpendResult::Success { psbt, .. }) => psbt,
+                res => panic!("Unexpected result: {:?}", res),
+            };
+        assert_eq!(psbt.unsigned_tx.input.len(), 2);
+        assert_eq!(psbt.unsigned_tx.output.len(), 2);
+        let fee = psbt.fee().unwrap().to_sat();
+        assert_eq!(output_value(&psbt, &spk_a), Some(40_000));
+        assert_eq!(output_value(&psbt, &spk_b), Some(200_000 - 40_000 - fee));
+
+        // It can't be combined with subtracting the fee from another destination.
+        assert_eq!(
+            control.create_spend(&destinations_a, &[], 2, None, Some(addr_b), &[addr_a]),
+            Err(CommandError::SpendCreation(
+                SpendCreationError::InvalidDestinations
+            ))
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn sign_verify_message() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.control();
+
+        // Messages can only be signed for addresses of the wallet.
+        let addr =
+            bitcoin::Address::from_str("bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l").unwrap();
+        assert_eq!(
+            control.sign_message(addr.clone(), "Hello World", None),
+            Err(CommandError::UnknownAddress(
+                addr.clone().assume_checked().to_string()
+            ))
+        );
+
+        // But can be verified for any address (test vector from BIP322).
+        let sig = MessageSignature::from_str("AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=").unwrap();
+        assert_eq!(
+            control.verify_message(addr.clone(), "Hello World", &sig),
+            Ok(VerifyMessageResult { valid: true })
+        );
+        assert_eq!(
+            control.verify_message(addr, "Hello", &sig),
+            Ok(VerifyMessageResult { valid: false })
+        );
+        // The address must be for our network.
+        let testnet_addr =
+            bitcoin::Address::from_str("tb1qfufcrdyarcg5eph608c6l8vktrc9re6agu4se2").unwrap();
+        assert!(matches!(
+            control.verify_message(testnet_addr, "Hello World", &sig),
+            Err(CommandError::Address(..))
+        ));
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn reserves_proof() {
+        // The UTXO set of the backend only contains the first coin, confirmed at height 50.
+        let op_a = OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let mut bitcoind = DummyBitcoind::new();
+        bitcoind.utxos.insert(
+            op_a,
+            (
+                TxOut {
+                    value: Amount::from_sat(100_000),
+                    script_pubkey: ScriptBuf::new(),
+                },
+                50,
+            ),
+        );
+        let ms = DummyLiana::new(bitcoind, DummyDatabase::new());
+        let control = &ms.control();
+        let mut db_conn = control.db().lock().unwrap().connection();
+        let message = "Vault audit";
+
+        // Without any coin, there is nothing to prove.
+        assert_eq!(
+            control.create_reserves_proof(message, &[]),
+            Err(CommandError::ReservesProof(ReservesProofError::NoCoins))
+        );
+
+        let op_b = OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:1",
+        )
+        .unwrap();
+        let coin = |outpoint, derivation_index: u32| Coin {
+            outpoint,
+            is_immature: false,
+            block_info: None,
+            amount: Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(derivation_index),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            is_from_self: false,
+            is_quarantined: false,
+        };
+        db_conn.new_unspent_coins(&[coin(op_a, 0), coin(op_b, 1)]);
+        db_conn.confirm_coins(&[(op_a, 50, 100_000)]);
+        db_conn.update_tip(&crate::bitcoin::BlockChainTip {
+            height: 60,
+            hash: bitcoin::BlockHash::from_str(
+                "000000007bc154e0fa7ea32218a72fe2c1bb9f86cf8c9ebf9a715ed27fdb229a",
+            )
+            .unwrap(),
+        });
+
+        // By default, all the confirmed coins are spent by the proof, after the commitment input.
+        let psbt = control.create_reserves_proof(message, &[]).unwrap().psbt;
+        assert_eq!(psbt.unsigned_tx.input.len(), 2);
+        assert_eq!(
+            psbt.unsigned_tx.input[0].previous_output.txid,
+            reserves::challenge_txid(message)
+        );
+        assert_eq!(psbt.unsigned_tx.input[1].previous_output, op_a);
+        assert_eq!(psbt.unsigned_tx.output[0].value, Amount::from_sat(100_000));
+        // But coins can be selected.
+        let psbt_b = control
+            .create_reserves_proof(message, &[op_b])
+            .unwrap()
+            .psbt;
+        assert_eq!(psbt_b.unsigned_tx.input[1].previous_output, op_b);
+        let unknown_op = OutPoint::from_str(
+            "4613e078e4cdbb0fce1bc6e44b028f0e11621a134a1605efdc456c32d155c922:19",
+        )
+        .unwrap();
+        assert_eq!(
+            control.create_reserves_proof(message, &[unknown_op]),
+            Err(CommandError::UnknownOutpoint(unknown_op))
+        );
+
+        // It can't be finalized without the signatures.
+        assert!(matches!(
+            control.finalize_reserves_proof(psbt.clone(), message),
+            Err(CommandError::ReservesProof(
+                ReservesProofError::Finalization(..)
+            ))
+        ));
+
+        // The coins must be in the UTXO set of the backend, and have been confirmed at the height
+        // of the verification, which can't be beyond the backend's tip.
+        let tx = psbt.unsigned_tx;
+        assert_eq!(
+            control.verify_reserves_proof(&tx, message, Some(101)),
+            Err(CommandError::InvalidHeight(101))
+        );
+        assert_eq!(
+            control.verify_reserves_proof(&tx, message, Some(49)),
+            Err(CommandError::NotUnspentAtHeight(op_a, 49))
+        );
+        assert_eq!(
+            control.verify_reserves_proof(&psbt_b.unsigned_tx, message, None),
+            Err(CommandError::NotUnspentAtHeight(op_b, 100))
+        );
+        // Our own view of the coins doesn't matter. Once found in the UTXO set, the proof is
+        // checked for signatures.
+        db_conn.spend_coins(&[(op_a, tx.compute_txid())]);
+        assert_eq!(
+            control.verify_reserves_proof(&tx, message, None),
+            Err(CommandError::ReservesProof(
+                ReservesProofError::InvalidSignature(op_a)
+            ))
+        );
+        assert_eq!(
+            control.verify_reserves_proof(&tx, message, Some(50)),
+            Err(CommandError::ReservesProof(
+                ReservesProofError::InvalidSignature(op_a)
+            ))
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn create_spend_with_options() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.control();
+        let mut db_conn = control.db().lock().unwrap().connection();
+
+        let op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: op,
+            is_immature: false,
+            block_info: None,
+            amount: Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(0),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            is_from_self: false,
+            is_quarantined: false,
+        }]);
+        db_conn.confirm_coins(&[(op, 50, 100_000)]);
+
+        let dest_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address<address::NetworkUnchecked>, u64> =
+            HashMap::from([(dest_addr, 40_000)]);
+
+        // By default, there is no data output and the inputs signal for RBF.
+        let psbt = match control.create_spend(&destinations, &[op], 2, None, None, &[]) {
+            Ok(CreateSpendResult::Success { psbt, .. }) => psbt,
+            res => panic!("Unexpected result: {:?}", res),
+        };
+        assert!(!psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .any(|txo| txo.script_pubkey.is_op_return()));
+        assert!(psbt.unsigned_tx.is_explicitly_rbf());
+
+        // Commit to some data, set the locktime and opt out of RBF.
+        let locktime = absolute::LockTime::from_height(42).unwrap();
+        let options = CreateSpendOptions {
+            locktime: Some(locktime),
+            spend: SpendOptions {
+                op_return_data: Some(b"liana".to_vec()),
+                signal_rbf: false,
+                truc: false,
+            },
+            ..Default::default()
+        };
+        let psbt = match control.create_spend_with_options(
+            &destinations,
+            &[op],
+            2,
+            None,
+            None,
+            &[],
+            &options,
+        ) {
+            Ok(CreateSpendResult::Success { psbt, .. }) => psbt,
+            res => panic!("Unexpected result: {:?}", res),
+        };
+        let data_outputs: Vec<_> = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .filter(|txo| txo.script_pubkey.is_op_return())
+            .collect();
+        assert_eq!(data_outputs.len(), 1);
+        assert_eq!(data_outputs[0].value, Amount::ZERO);
+        assert!(data_outputs[0].script_pubkey.as_bytes().ends_with(b"liana"));
+        assert_eq!(psbt.unsigned_tx.output.len(), psbt.outputs.len());
+        assert_eq!(psbt.unsigned_tx.lock_time, locktime);
+        assert!(!psbt.unsigned_tx.is_explicitly_rbf());
+        assert!(psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .all(|txin| txin.sequence == Sequence::ENABLE_LOCKTIME_NO_RBF));
+
+        // A TRUC transaction can be created instead.
+        let options = CreateSpendOptions {
+            spend: SpendOptions {
+                truc: true,
+                ..SpendOptions::default()
+            },
+            ..Default::default()
+        };
+        let psbt = match control.create_spend_with_options(
+            &destinations,
+            &[op],
+            2,
+            None,
+            None,
+            &[],
+            &options,
+        ) {
+            Ok(CreateSpendResult::Success { psbt, .. }) => psbt,
+            res => panic!("Unexpected result: {:?}", res),
+        };
+        assert_eq!(psbt.unsigned_tx.version, bitcoin::transaction::Version(3));
+
+        // The data must fit in a standard OP_RETURN output.
+        let options = CreateSpendOptions {
+            locktime: None,
+            spend: SpendOptions {
+                op_return_data: Some(vec![0; spend::MAX_OP_RETURN_DATA_SIZE + 1]),
+                ..SpendOptions::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            control.create_spend_with_options(&destinations, &[op], 2, None, None, &[], &options),
+            Err(CommandError::SpendCreation(
+                SpendCreationError::OpReturnDataTooLarge(spend::MAX_OP_RETURN_DATA_SIZE + 1)
+            ))
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn schedule_broadcast() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.control();
+        let mut db_conn = control.db().lock().unwrap().connection();
+
+        let op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: op,
+            is_immature: false,
+            block_info: None,
+            amount: Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(0),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            is_from_self: false,
+            is_quarantined: false,
+        }]);
+        db_conn.confirm_coins(&[(op, 50, 100_000)]);
+
+        // A transaction with a locktime far in the future.
+        let mut tx = Transaction {
+            version: TxVersion::TWO,
+            lock_time: absolute::LockTime::from_height(1_000_000).unwrap(),
+            input: vec![TxIn {
+                previous_output: op,
+                sequence: Sequence::ENABLE_LOCKTIME_NO_RBF,
+                ..TxIn::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(90_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+
+        // It must be signed and only spend our coins.
+        assert_eq!(
+            control.schedule_broadcast(&tx),
+            Err(CommandError::UnsignedInput(op))
+        );
+        tx.input[0].witness = Witness::from_slice(&[vec![0; 64]]);
+        let mut foreign_tx = tx.clone();
+        let foreign_op = OutPoint::new(op.txid, 1);
+        foreign_tx.input[0].previous_output = foreign_op;
+        assert_eq!(
+            control.schedule_broadcast(&foreign_tx),
+            Err(CommandError::UnknownOutpoint(foreign_op))
+        );
+
+        // Once scheduled, it's pending until the block after its locktime.
+        control.schedule_broadcast(&tx).unwrap();
+        let res = control.list_scheduled_broadcasts();
+        assert_eq!(res.transactions.len(), 1);
+        let entry = &res.transactions[0];
+        assert_eq!(entry.txid, tx.compute_txid());
+        assert_eq!(entry.status, ScheduledBroadcastStatus::Pending);
+        assert_eq!(entry.valid_at_height, Some(1_000_001));
+        assert!(entry.broadcast_at.is_none() && entry.last_error.is_none());
+
+        // It can't be scheduled anymore once the coin was spent by another transaction, and the
+        // scheduled one is reported as conflicted.
+        let other_txid = foreign_tx.compute_txid();
+        db_conn.spend_coins(&[(op, other_txid)]);
+        assert_eq!(
+            control.schedule_broadcast(&tx),
+            Err(CommandError::AlreadySpent(op))
+        );
+        assert_eq!(
+            control.list_scheduled_broadcasts().transactions[0].status,
+            ScheduledBroadcastStatus::Conflicted
+        );
+
+        // Once spent by it, it's broadcast until the spend is confirmed.
+        let txid = tx.compute_txid();
+        db_conn.unspend_coins(&[op]);
+        db_conn.spend_coins(&[(op, txid)]);
+        assert_eq!(
+            control.list_scheduled_broadcasts().transactions[0].status,
+            ScheduledBroadcastStatus::Broadcast
+        );
+        db_conn.confirm_spend(&[(op, txid, 2, 1_700_000_000)]);
+        assert_eq!(
+            control.list_scheduled_broadcasts().transactions[0].status,
+            ScheduledBroadcastStatus::Confirmed
+        );
+
+        control.delete_scheduled_broadcast(&txid);
+        assert!(control.list_scheduled_broadcasts().transactions.is_empty());
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn quarantined_coins() {
+        let ms = DummyLiana::new_timelock(DummyBitcoind::new(), DummyDatabase::new(), 10);
+        let control = &ms.control();
+        let mut db_conn = control.db().lock().unwrap().connection();
+
+        let op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: op,
+            is_immature: false,
+            block_info: None,
+            amount: Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(0),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            is_from_self: false,
+            is_quarantined: true,
+        }]);
+        db_conn.confirm_coins(&[(op, 50, 100_000)]);
+        assert!(control.list_coins(&[], &[op]).coins[0].is_quarantined);
+
+        let dest_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address<address::NetworkUnchecked>, u64> =
+            HashMap::from([(dest_addr.clone(), 40_000)]);
+
+        // A quarantined coin is never selected automatically, nor can it be selected manually.
+        assert!(matches!(
+            control.create_spend(&destinations, &[], 2, None, None, &[]),
+            Ok(CreateSpendResult::InsufficientFunds { .. })
+        ));
+        assert_eq!(
+            control.create_spend(&destinations, &[op], 2, None, None, &[]),
+            Err(CommandError::QuarantinedCoin(op))
+        );
+        // Nor is it silently left out when sweeping the coins through a recovery path.
+        assert!(matches!(
+            control.create_recovery(dest_addr.clone(), &[], 2, None),
+            Err(CommandError::QuarantinedCoins(ops)) if ops == vec![op]
+        ));
+        assert!(matches!(
+            control.create_recovery(dest_addr.clone(), &[op], 2, None),
+            Err(CommandError::QuarantinedCoin(o)) if o == op
+        ));
+
+        // Only known coins can be released.
+        let unknown_op = OutPoint::new(op.txid, 1);
+        assert_eq!(
+            control.release_coins(&[unknown_op]),
+            Err(CommandError::UnknownOutpoint(unknown_op))
+        );
+
+        // Once released, it is spendable as any other coin.
+        control.release_coins(&[op]).unwrap();
+        assert!(!control.list_coins(&[], &[op]).coins[0].is_quarantined);
+        assert!(matches!(
+            control.create_spend(&destinations, &[], 2, None, None, &[]),
+            Ok(CreateSpendResult::Success { .. })
+        ));
+        assert!(control.create_recovery(dest_addr, &[], 2, None).is_ok());
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn privacy_report() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        // Coins are only linked to a KYC source through the labels set in the configuration.
+        let unconfigured = ms.control();
+        let mut config = unconfigured.config.clone();
+        config.privacy = Some(crate::config::PrivacyConfig {
+            kyc_labels: vec!["bought on KYC exchange".to_string()],
+        });
+        let control = &DaemonControl {
+            config,
+            bitcoin: unconfigured.bitcoin.clone(),
+            poller_sender: unconfigured.poller_sender.clone(),
+            db: unconfigured.db.clone(),
+            secp: unconfigured.secp.clone(),
+            wallets: unconfigured.wallets.clone(),
+            metrics: unconfigured.metrics.clone(),
+        };
+        let mut db_conn = control.db().lock().unwrap().connection();
+        let coin = |outpoint, amount, is_change| Coin {
+            outpoint,
+            is_immature: false,
+            block_info: None,
+            amount: Amount::from_sat(amount),
+            derivation_index: bip32::ChildNumber::from(0),
+            is_change,
+            spend_txid: None,
+            spend_block: None,
+            is_from_self: false,
+            is_quarantined: false,
+        };
+
+        // Nothing to report for an empty wallet.
+        assert!(control.privacy_report().findings.is_empty());
+
+        // Two deposits to the same address, one of them from a KYC exchange.
+        let salary_op = OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let kyc_op = OutPoint::from_str(
+            "6f0dc85a369f44d18e31a5d6d4dd8e2c8a4b7a0dba2c6e4f7ce4a3d67a7bd6b2:1",
+        )
+        .unwrap();
+        db_conn.new_unspent_coins(&[coin(salary_op, 80_000, false), coin(kyc_op, 150_000, false)]);
+        db_conn.update_labels(&HashMap::from([
+            (LabelItem::OutPoint(salary_op), Some("Salary".to_string())),
+            (
+                LabelItem::OutPoint(kyc_op),
+                Some("Bought on KYC exchange".to_string()),
+            ),
+        ]));
+        let findings = control.privacy_report().findings;
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].kind, PrivacyFindingKind::AddressReuse);
+        assert_eq!(findings[0].outpoints, vec![salary_op, kyc_op]);
+        assert_eq!(findings[1].kind, PrivacyFindingKind::KycLinkedCoin);
+        assert_eq!(findings[1].outpoints, vec![kyc_op]);
+        let findings = unconfigured.privacy_report().findings;
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].kind, PrivacyFindingKind::AddressReuse);
+
+        // Both are spent together to a P2WPKH address, paying a round amount. The P2WSH change
+        // can be told apart from the payment and inherits the link to the KYC source.
+        let dest_addr = bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv")
+            .unwrap()
+            .assume_checked();
+        let change_addr = control
+            .config
+            .main_descriptor
+            .change_descriptor()
+            .derive(0.into(), &control.secp)
+            .address(bitcoin::Network::Bitcoin);
+        let spend_tx = Transaction {
+            version: TxVersion::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: [salary_op, kyc_op]
+                .iter()
+                .map(|previous_output| TxIn {
+                    previous_output: *previous_output,
+                    ..TxIn::default()
+                })
+                .collect(),
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(100_000),
+                    script_pubkey: dest_addr.script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(123_456),
+                    script_pubkey: change_addr.script_pubkey(),
+                },
+            ],
+        };
+        let spend_txid = spend_tx.compute_txid();
+        let change_op = OutPoint::new(spend_txid, 1);
+        db_conn.new_txs(&[spend_tx]);
+        db_conn.spend_coins(&[(salary_op, spend_txid), (kyc_op, spend_txid)]);
+        db_conn.new_unspent_coins(&[coin(change_op, 123_456, true)]);
+
+        let findings = control.privacy_report().findings;
+        assert_eq!(findings.len(), 4);
+        assert_eq!(findings[0].kind, PrivacyFindingKind::AddressReuse);
+        assert_eq!(findings[1].kind, PrivacyFindingKind::MergedLabels);
+        assert_eq!(findings[1].txid, Some(spend_txid));
+        assert_eq!(
+            findings[1].labels,
+            vec!["Bought on KYC exchange".to_string(), "Salary".to_string()]
+        );
+        assert_eq!(findings[2].kind, PrivacyFindingKind::IdentifiableChange);
+        assert_eq!(findings[2].txid, Some(spend_txid));
+        assert_eq!(findings[2].outpoints, vec![change_op]);
+        assert_eq!(findings[3].kind, PrivacyFindingKind::KycLinkedCoin);
+        assert_eq!(findings[3].outpoints, vec![change_op]);
+        assert_eq!(
+            findings[3].labels,
+            vec!["Bought on KYC exchange".to_string()]
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn create_spend_silent_payment() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.control();
+
+        let secp = bitcoin::secp256k1::Secp256k1::signing_only();
+        let scan_key = bitcoin::secp256k1::PublicKey::from_secret_key(
+            &secp,
+            &bitcoin::secp256k1::SecretKey::from_slice(&[1; 32]).unwrap(),
+        );
+        let spend_key = bitcoin::secp256k1::PublicKey::from_secret_key(
+            &secp,
+            &bitcoin::secp256k1::SecretKey::from_slice(&[2; 32]).unwrap(),
+        );
+        let op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+
+        // A silent payment address for another network is rejected.
+        let addr = SilentPaymentAddress::new(bitcoin::NetworkKind::Test, scan_key, spend_key);
+        let options = CreateSpendOptions {
+            silent_payments: HashMap::from([(addr, 10_000)]),
+            ..Default::default()
+        };
+        assert_eq!(
+            control.create_spend_with_options(&HashMap::new(), &[op], 2, None, None, &[], &options),
+            Err(CommandError::SilentPayment(
+                SilentPaymentError::InvalidNetwork
+            ))
+        );
+
+        // The coins of this P2WSH wallet can't be used to pay to a silent payment address.
+        let addr = SilentPaymentAddress::new(bitcoin::NetworkKind::Main, scan_key, spend_key);
+        let options = CreateSpendOptions {
+            silent_payments: HashMap::from([(addr, 10_000)]),
+            ..Default::default()
+        };
+        assert_eq!(
+            control.create_spend_with_options(&HashMap::new(), &[op], 2, None, None, &[], &options),
+            Err(CommandError::SilentPayment(
+                SilentPaymentError::IneligibleInputs
+            ))
+        );
+
+        ms.shutdown();
+
+        // The coins of a Taproot wallet with a single key primary path are spent through the key
+        // path. The output is created with a placeholder and derived by the signer, so the PSBT
+        // output records the silent payment address.
+        let ms = DummyLiana::new_taproot(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.control();
+        let dummy_tx = bitcoin::Transaction {
+            version: TxVersion::TWO,
+            lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
+            input: vec![],
+            output: vec![],
+        };
+        let op = bitcoin::OutPoint::new(dummy_tx.compute_txid(), 0);
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_txs(&[dummy_tx]);
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: op,
+            is_immature: false,
+            block_info: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            is_from_self: false,
+            is_quarantined: false,
+        }]);
+        let psbt = match control
+            .create_spend_with_options(&HashMap::new(), &[op], 2, None, None, &[], &options)
+            .unwrap()
+        {
+            CreateSpendResult::Success { psbt, .. } => psbt,
+            CreateSpendResult::InsufficientFunds { .. } => {
+                panic!("expect successful spend creation")
+            }
+        };
+        let i = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .position(|txo| txo.script_pubkey == addr.placeholder_script())
+            .unwrap();
+        assert_eq!(psbt.unsigned_tx.output[i].value.to_sat(), 10_000);
+        assert_eq!(
+            silent_payments::psbt_output_info(&psbt.outputs[i]),
+            Ok(Some((scan_key, spend_key)))
+        );
+        assert!(silent_payments::has_underived_outputs(&psbt));
+
+        // The transaction can't be replaced, as its inputs may change, but it can be cancelled.
+        let txid = psbt.unsigned_tx.compute_txid();
+        control.update_spend(psbt.clone()).unwrap();
+        assert_eq!(
+            control.rbf_psbt(&txid, false, Some(10)),
+            Err(CommandError::SilentPayment(SilentPaymentError::Replacement))
+        );
+        assert!(!matches!(
+            control.rbf_psbt(&txid, true, None),
+            Err(CommandError::SilentPayment(_))
+        ));
+
+        // It can't be signed nor broadcast before the output is derived.
+        let mut signed_psbt = psbt.clone();
+        signed_psbt.inputs[0].tap_key_sig = Some(bitcoin::taproot::Signature {
+            signature: bitcoin::secp256k1::schnorr::Signature::from_slice(&[1; 64]).unwrap(),
+            sighash_type: bitcoin::TapSighashType::Default,
+        });
+        assert_eq!(
+            control.update_spend(signed_psbt),
+            Err(CommandError::SilentPayment(
+                SilentPaymentError::UnderivedOutputs
+            ))
+        );
+        assert_eq!(
+            control.broadcast_spend(&txid),
+            Err(CommandError::SilentPayment(
+                SilentPaymentError::UnderivedOutputs
+            ))
+        );
+
+        // Once derived, the transaction replaces the stored one along with its labels.
+        let prev_outpoint = bitcoin::OutPoint::new(txid, i as u32);
+        control.update_labels(&HashMap::from([
+            (LabelItem::Txid(txid), Some("to sp".to_string())),
+            (
+                LabelItem::OutPoint(prev_outpoint),
+                Some("sp output".to_string()),
+            ),
+        ]));
+        let mut derived_psbt = psbt.clone();
+        derived_psbt.unsigned_tx.output[i].script_pubkey =
+            bitcoin::ScriptBuf::from_bytes([&[0x51, 0x20][..], &[7; 32][..]].concat());
+        assert!(!silent_payments::has_underived_outputs(&derived_psbt));
+        let derived_txid = derived_psbt.unsigned_tx.compute_txid();
+        control.update_spend(derived_psbt).unwrap();
+        let spend_txids: Vec<_> = control
+            .list_spend(None)
+            .unwrap()
+            .spend_txs
+            .into_iter()
+            .map(|entry| entry.psbt.unsigned_tx.compute_txid())
+            .collect();
+        assert_eq!(spend_txids, vec![derived_txid]);
+        let derived_outpoint = bitcoin::OutPoint::new(derived_txid, i as u32);
+        let labels = control
+            .get_labels(&HashSet::from([
+                LabelItem::Txid(txid),
+                LabelItem::OutPoint(prev_outpoint),
+                LabelItem::Txid(derived_txid),
+                LabelItem::OutPoint(derived_outpoint),
+            ]))
+            .labels;
+        assert_eq!(
+            labels,
+            HashMap::from([
+                (derived_txid.to_string(), "to sp".to_string()),
+                (derived_outpoint.to_string(), "sp output".to_string()),
+            ])
+        );
+
+        // Two addresses with the same spend key would have the same placeholder output.
+        let other_addr =
+            SilentPaymentAddress::new(bitcoin::NetworkKind::Main, spend_key, spend_key);
+        let options = CreateSpendOptions {
+            silent_payments: HashMap::from([(addr, 10_000), (other_addr, 10_000)]),
+            ..Default::default()
+        };
+        assert_eq!(
+            control.create_spend_with_options(&HashMap::new(), &[op], 2, None, None, &[], &options),
+            Err(CommandError::SilentPayment(
+                SilentPaymentError::DuplicateSpendKey
+            ))
+        );
+
+        ms.shutdown();
+    }
 }
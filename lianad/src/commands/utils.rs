@@ -45,6 +45,28 @@ where
     Ok(bitcoin::Amount::from_sat(a))
 }
 
+/// Serialize an optional amount as sats
+pub fn ser_opt_amount<S: Serializer>(
+    amount: &Option<bitcoin::Amount>,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    match amount {
+        Some(amount) => s.serialize_some(&amount.to_sat()),
+        None => s.serialize_none(),
+    }
+}
+
+/// Deserialize an optional amount from sats
+pub fn deser_opt_amount_from_sats<'de, D>(
+    deserializer: D,
+) -> Result<Option<bitcoin::Amount>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let a = Option::<u64>::deserialize(deserializer)?;
+    Ok(a.map(bitcoin::Amount::from_sat))
+}
+
 pub fn ser_hex<S, T>(t: T, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -62,3 +84,25 @@ where
     let s = Vec::from_hex(&s).map_err(de::Error::custom)?;
     consensus::deserialize(&s).map_err(de::Error::custom)
 }
+
+/// Serialize a list of items as a list of strings.
+pub fn ser_vec_to_string<T: std::fmt::Display, S: Serializer>(
+    items: &[T],
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    s.collect_seq(items.iter().map(|i| i.to_string()))
+}
+
+/// Deserialize a list of items from a list of strings.
+pub fn deser_vec_fromstr<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    let strings = Vec::<String>::deserialize(deserializer)?;
+    strings
+        .iter()
+        .map(|s| T::from_str(s).map_err(de::Error::custom))
+        .collect()
+}
@@ -89,6 +89,12 @@ pub enum BitcoinBackend {
     /// Settings specific to Electrum as the Bitcoin interface.
     #[serde(rename = "electrum_config")]
     Electrum(ElectrumConfig),
+    /// Settings specific to Esplora as the Bitcoin interface.
+    #[serde(rename = "esplora_config")]
+    Esplora(EsploraConfig),
+    /// Settings specific to using compact block filters as the Bitcoin interface.
+    #[serde(rename = "cbf_config")]
+    Cbf(CbfConfig),
 }
 
 /// RPC authentication options.
@@ -119,6 +125,14 @@ pub struct BitcoindConfig {
     pub rpc_auth: BitcoindRpcAuth,
     /// The IP:port bitcoind's RPC is listening on
     pub addr: SocketAddr,
+    /// The endpoint bitcoind publishes new blocks to through ZMQ, if any. For instance
+    /// "tcp://127.0.0.1:28332". Same as bitcoind's `-zmqpubrawblock`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zmqpubrawblock: Option<String>,
+    /// The endpoint bitcoind publishes new transactions to through ZMQ, if any. Same as
+    /// bitcoind's `-zmqpubrawtx`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zmqpubrawtx: Option<String>,
 }
 
 /// Everything we need to know for talking to Electrum serenely.
@@ -132,12 +146,181 @@ pub struct ElectrumConfig {
     /// (useful to allow usage of self signed certificates on local network)
     #[serde(default = "default_validate_domain")]
     pub validate_domain: bool,
+    /// Other servers to fail over to, in this order, when the one we use can't be reached.
+    /// Same format as `addr`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_addrs: Vec<String>,
+    /// If set, check the chain tip of the server we use against a second server after each
+    /// sync and broadcast our transactions through both. Requires at least one fallback server.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub cross_check: bool,
 }
 
 fn default_validate_domain() -> bool {
     true
 }
 
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+impl ElectrumConfig {
+    /// The addresses of all the servers, starting with the main one.
+    pub fn addrs(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.addr).chain(self.fallback_addrs.iter())
+    }
+}
+
+/// Everything we need to know for talking to an Esplora server.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct EsploraConfig {
+    /// The base URL of the Esplora REST API, for instance "https://blockstream.info/api".
+    pub addr: String,
+}
+
+/// Everything we need to know for getting compact block filters (BIP157/158) from a peer.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CbfConfig {
+    /// The IP:port of a peer serving compact block filters. For instance a bitcoind started with
+    /// `-blockfilterindex=1` and `-peerblockfilters=1`.
+    pub addr: SocketAddr,
+}
+
+/// A SOCKS5 proxy to route our outbound connections through, for instance a Tor daemon.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ProxyConfig {
+    /// The IP:port the SOCKS5 proxy is listening on.
+    pub addr: SocketAddr,
+}
+
+impl ProxyConfig {
+    /// Whether a connection to this host should go through the proxy. Connections to the local
+    /// machine never do, as the proxy would not be able to reach them.
+    pub fn applies_to(&self, host: &str) -> bool {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.eq_ignore_ascii_case("localhost") {
+            return false;
+        }
+        host.parse::<std::net::IpAddr>()
+            .map(|ip| !ip.is_loopback())
+            .unwrap_or(true)
+    }
+
+    /// Same as [`ProxyConfig::applies_to`] for an address of the form
+    /// "[scheme://]host[:port][/path]".
+    pub fn applies_to_url(&self, url: &str) -> bool {
+        let authority = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+        let authority = authority.split('/').next().unwrap_or(authority);
+        let host = match authority.rsplit_once(':') {
+            // Don't mistake the last group of an IPv6 address without port for a port.
+            Some((host, _)) if !host.contains(':') || host.ends_with(']') => host,
+            _ => authority,
+        };
+        self.applies_to(host)
+    }
+}
+
+fn default_metrics_addr() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 9766))
+}
+
+/// Settings for the optional listener exposing metrics about the daemon.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MetricsConfig {
+    /// The IP:port to serve the metrics on, under "/metrics" in the Prometheus text format. They
+    /// are only served to the local machine by default.
+    #[serde(default = "default_metrics_addr")]
+    pub addr: SocketAddr,
+    /// Confirmed coins whose first recovery path becomes available within this many blocks are
+    /// reported as expiring. Defaults to 10% of the timelock of the first recovery path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry_window_blocks: Option<u32>,
+}
+
+fn default_dust_max_amount_sat() -> u64 {
+    1_000
+}
+
+fn default_dust_min_tiny_outputs() -> usize {
+    10
+}
+
+/// Heuristics used to detect likely dust attacks among the coins we receive. A coin flagged by
+/// any of them is quarantined: it is never selected automatically to be spent until it is
+/// released.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DustProtectionConfig {
+    /// Coins of this value or less are flagged. Set to 0 to disable.
+    #[serde(default = "default_dust_max_amount_sat")]
+    pub max_amount_sat: u64,
+    /// Whether to flag coins sent to an address which already received coins.
+    #[serde(default)]
+    pub reused_address: bool,
+    /// Flag coins created by a transaction which has at least this many outputs, other than data
+    /// carriers, below the maximum amount, whatever their own value. Set to 0 to disable. It is
+    /// also disabled if the maximum amount is 0.
+    #[serde(default = "default_dust_min_tiny_outputs")]
+    pub min_tiny_outputs: usize,
+}
+
+fn default_consolidation_conf_target() -> u16 {
+    144
+}
+
+fn default_consolidation_min_coins() -> usize {
+    5
+}
+
+fn default_consolidation_max_coins() -> usize {
+    50
+}
+
+fn default_consolidation_max_fee_share() -> f64 {
+    0.05
+}
+
+/// When to automatically create a transaction merging small coins into one, in order to spend
+/// them while fees are low. The transaction is stored as a Spend PSBT for the signers to sign.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ConsolidationConfig {
+    /// Consolidate once the estimated feerate, in sats/vb, drops to this value or below.
+    pub max_feerate: u64,
+    /// The confirmation target, in blocks, of the feerate estimate.
+    #[serde(default = "default_consolidation_conf_target")]
+    pub conf_target: u16,
+    /// Don't consolidate fewer coins than this.
+    #[serde(default = "default_consolidation_min_coins")]
+    pub min_coins: usize,
+    /// Don't consolidate more coins than this at once. The smallest coins are consolidated first.
+    #[serde(default = "default_consolidation_max_coins")]
+    pub max_coins: usize,
+    /// Only consider coins of this value or less, if set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_coin_amount_sat: Option<u64>,
+    /// The maximum share of the value of the consolidated coins that may be spent on fees,
+    /// between 0 and 1.
+    #[serde(default = "default_consolidation_max_fee_share")]
+    pub max_fee_share: f64,
+    /// Never consolidate coins with one of these labels (case-insensitive), whether the label is
+    /// set on the coin, its deposit transaction or its address.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_labels: Vec<String>,
+    /// A command to run once a consolidation transaction was created, for instance to notify the
+    /// signers. Any "%s" in the command is replaced by the txid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notify_command: Option<String>,
+}
+
+/// Settings of the privacy report of the wallet.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PrivacyConfig {
+    /// Coins with one of these labels (case-insensitive), whether the label is set on the coin,
+    /// its deposit transaction or its address, come from a source which knows the identity of the
+    /// owner of the wallet, such as an exchange.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kyc_labels: Vec<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BitcoinConfig {
     /// The network we are operating on, one of "bitcoin", "testnet", "testnet4", "regtest", "signet"
@@ -173,6 +356,21 @@ pub struct Config {
     pub main_descriptor: LianaDescriptor,
     /// Settings for the Bitcoin interface
     pub bitcoin_config: BitcoinConfig,
+    /// An optional SOCKS5 proxy for all our connections to remote hosts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<ProxyConfig>,
+    /// An optional listener exposing metrics about the daemon.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<MetricsConfig>,
+    /// Optional detection of dust attacks on incoming coins.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dust_protection: Option<DustProtectionConfig>,
+    /// Optional automatic consolidation of small coins when fees are low.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consolidation: Option<ConsolidationConfig>,
+    /// Optional settings of the privacy report.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub privacy: Option<PrivacyConfig>,
     /// Settings specific to the Bitcoin backend.
     #[serde(flatten)]
     pub bitcoin_backend: Option<BitcoinBackend>,
@@ -193,6 +391,22 @@ impl Config {
             main_descriptor,
             data_directory: Some(data_directory.path().to_path_buf()),
             data_dir: None,
+            proxy: None,
+            metrics: None,
+            dust_protection: None,
+            consolidation: None,
+            privacy: None,
+        }
+    }
+
+    /// The configuration of an additional wallet managed by this daemon. It shares the Bitcoin
+    /// backend settings of this configuration but has its own descriptor and data directory.
+    pub fn for_wallet(&self, descriptor: LianaDescriptor, data_directory: &DataDirectory) -> Self {
+        Self {
+            main_descriptor: descriptor,
+            data_directory: Some(data_directory.path().to_path_buf()),
+            data_dir: None,
+            ..self.clone()
         }
     }
 
@@ -312,6 +526,30 @@ impl Config {
             )));
         }
 
+        if let Some(consolidation) = &self.consolidation {
+            if consolidation.min_coins < 2 || consolidation.min_coins > consolidation.max_coins {
+                return Err(ConfigError::Unexpected(format!(
+                    "Invalid consolidation coin counts: minimum {}, maximum {}. The minimum must \
+                     be at least 2 and no larger than the maximum.",
+                    consolidation.min_coins, consolidation.max_coins
+                )));
+            }
+            if !(consolidation.max_fee_share > 0.0 && consolidation.max_fee_share <= 1.0) {
+                return Err(ConfigError::Unexpected(format!(
+                    "Invalid consolidation maximum fee share: {}. It must be above 0 and at most \
+                     1.",
+                    consolidation.max_fee_share
+                )));
+            }
+            if let Some(BitcoinBackend::Cbf(_)) = self.bitcoin_backend {
+                return Err(ConfigError::Unexpected(
+                    "Automatic consolidation requires feerate estimates, which are not available \
+                     when using compact block filters."
+                        .to_string(),
+                ));
+            }
+        }
+
         // TODO: check the semantics of the main descriptor
 
         Ok(())
@@ -474,6 +712,25 @@ mod tests {
             parsed.rpc_auth,
             BitcoindRpcAuth::UserPass("my_user".to_string(), "my_password".to_string())
         );
+        assert!(parsed.zmqpubrawblock.is_none() && parsed.zmqpubrawtx.is_none());
+
+        // A valid config with ZMQ endpoints
+        let toml_str = r#"
+            cookie_path = '/home/user/.bitcoin/.cookie'
+            addr = '127.0.0.1:8332'
+            zmqpubrawblock = 'tcp://127.0.0.1:28332'
+            zmqpubrawtx = 'tcp://127.0.0.1:28333'
+            "#
+        .trim_start()
+        .replace("            ", "");
+        let parsed = toml::from_str::<BitcoindConfig>(&toml_str).expect("Deserializing toml_str");
+        let serialized = toml::to_string_pretty(&parsed).expect("Serializing to toml");
+        assert_eq!(toml_str, serialized);
+        assert_eq!(
+            parsed.zmqpubrawblock.as_deref(),
+            Some("tcp://127.0.0.1:28332")
+        );
+        assert_eq!(parsed.zmqpubrawtx.as_deref(), Some("tcp://127.0.0.1:28333"));
 
         // Must not set both cookie_file and auth
         let toml_str = r#"
@@ -532,6 +789,8 @@ mod tests {
         let expected = ElectrumConfig {
             addr: "ssl://electrum.blockstream.info:60002".into(),
             validate_domain: false,
+            fallback_addrs: Vec::new(),
+            cross_check: false,
         };
         assert_eq!(parsed, expected,);
 
@@ -546,8 +805,361 @@ mod tests {
             addr: "ssl://electrum.blockstream.info:60002".into(),
             // `validate_domain` must default to true
             validate_domain: true,
+            fallback_addrs: Vec::new(),
+            cross_check: false,
         };
         assert_eq!(parsed, expected,);
+
+        // A valid config with fallback servers and cross-checks
+        let toml_str = r#"
+            addr = 'ssl://electrum.blockstream.info:60002'
+            validate_domain = true
+            fallback_addrs = [
+                'tcp://127.0.0.1:60001',
+                'ssl://mempool.space:60602',
+            ]
+            cross_check = true
+            "#
+        .trim_start()
+        .replace("            ", "");
+        let parsed = toml::from_str::<ElectrumConfig>(&toml_str).expect("Deserializing toml_str");
+        let serialized = toml::to_string_pretty(&parsed).expect("Serializing to toml");
+        assert_eq!(toml_str, serialized);
+        assert_eq!(
+            parsed.addrs().collect::<Vec<_>>(),
+            vec![
+                "ssl://electrum.blockstream.info:60002",
+                "tcp://127.0.0.1:60001",
+                "ssl://mempool.space:60602"
+            ]
+        );
+        assert!(parsed.cross_check);
+    }
+
+    // Test the format of the `esplora_config` section
+    #[test]
+    fn toml_esplora_config() {
+        let toml_str = r#"
+            addr = 'https://blockstream.info/api'
+            "#
+        .trim_start()
+        .replace("            ", "");
+        let parsed = toml::from_str::<EsploraConfig>(&toml_str).expect("Deserializing toml_str");
+        let serialized = toml::to_string_pretty(&parsed).expect("Serializing to toml");
+        assert_eq!(toml_str, serialized);
+        let expected = EsploraConfig {
+            addr: "https://blockstream.info/api".into(),
+        };
+        assert_eq!(parsed, expected);
+
+        // It must be picked up as the Bitcoin backend of the configuration.
+        let toml_str = r#"
+            data_dir = '/home/wizardsardine/custom/folder/'
+            log_level = 'DEBUG'
+            main_descriptor = 'wsh(andor(pk([aabbccdd]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk([aabbccdd]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#dw4ulnrs'
+
+            [bitcoin_config]
+            network = 'bitcoin'
+            poll_interval_secs = 18
+
+            [esplora_config]
+            addr = 'https://blockstream.info/api'
+            "#
+        .trim_start()
+        .replace("            ", "");
+        let config = toml::from_str::<Config>(&toml_str).expect("Deserializing toml_str");
+        match config.bitcoin_backend {
+            Some(BitcoinBackend::Esplora(EsploraConfig { addr })) => {
+                assert_eq!(addr, "https://blockstream.info/api")
+            }
+            _ => panic!("Expected an Esplora backend"),
+        }
+    }
+
+    // Test the format of the `cbf_config` section
+    #[test]
+    fn toml_cbf_config() {
+        let toml_str = r#"
+            addr = '127.0.0.1:8333'
+            "#
+        .trim_start()
+        .replace("            ", "");
+        let parsed = toml::from_str::<CbfConfig>(&toml_str).expect("Deserializing toml_str");
+        let serialized = toml::to_string_pretty(&parsed).expect("Serializing to toml");
+        assert_eq!(toml_str, serialized);
+        let expected = CbfConfig {
+            addr: "127.0.0.1:8333".parse().unwrap(),
+        };
+        assert_eq!(parsed, expected);
+
+        // The address must be an IP:port.
+        let toml_str = r#"
+            addr = 'mynode.local'
+            "#
+        .trim_start()
+        .replace("            ", "");
+        toml::from_str::<CbfConfig>(&toml_str).expect_err("Not a socket address");
+    }
+
+    // Test the format of the `proxy` section
+    #[test]
+    fn toml_proxy_config() {
+        let toml_str = r#"
+            data_dir = '/home/wizardsardine/custom/folder/'
+            log_level = 'DEBUG'
+            main_descriptor = 'wsh(andor(pk([aabbccdd]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk([aabbccdd]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#dw4ulnrs'
+
+            [bitcoin_config]
+            network = 'bitcoin'
+            poll_interval_secs = 18
+
+            [proxy]
+            addr = '127.0.0.1:9050'
+
+            [electrum_config]
+            addr = 'ssl://electrumexampleonionaddressaaaaaaaaaaaaaaaaaaaaaaaaaaaa.onion:50002'
+            validate_domain = true
+            "#.trim_start().replace("            ", "");
+        let parsed = toml::from_str::<Config>(&toml_str).expect("Deserializing toml_str");
+        let serialized = toml::to_string_pretty(&parsed).expect("Serializing to toml");
+        assert_eq!(toml_str, serialized);
+        let proxy = parsed.proxy.expect("Proxy is set");
+        assert_eq!(proxy.addr, "127.0.0.1:9050".parse().unwrap());
+
+        // Connections to the local machine are never proxied.
+        assert!(!proxy.applies_to("127.0.0.1"));
+        assert!(!proxy.applies_to("[::1]"));
+        assert!(!proxy.applies_to("localhost"));
+        assert!(proxy.applies_to("192.168.1.10"));
+        assert!(proxy.applies_to("blockstream.info"));
+        assert!(proxy.applies_to("electrumexampleonion.onion"));
+        assert!(!proxy.applies_to_url("127.0.0.1:50001"));
+        assert!(!proxy.applies_to_url("tcp://localhost:50001"));
+        assert!(!proxy.applies_to_url("http://[::1]:3000/api"));
+        assert!(proxy.applies_to_url("ssl://electrum.blockstream.info:50002"));
+        assert!(proxy.applies_to_url("https://blockstream.info/api"));
+        assert!(proxy.applies_to_url("tcp://electrumexampleonion.onion:50001"));
+
+        // The proxy is optional.
+        let toml_str = toml_str.replace("[proxy]\naddr = '127.0.0.1:9050'\n\n", "");
+        let parsed = toml::from_str::<Config>(&toml_str).expect("Deserializing toml_str");
+        assert!(parsed.proxy.is_none());
+        let serialized = toml::to_string_pretty(&parsed).expect("Serializing to toml");
+        assert_eq!(toml_str, serialized);
+    }
+
+    // Test the format of the optional `metrics` section
+    #[test]
+    fn toml_metrics_config() {
+        let toml_str = r#"
+            data_dir = '/home/wizardsardine/custom/folder/'
+            log_level = 'DEBUG'
+            main_descriptor = 'wsh(andor(pk([aabbccdd]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk([aabbccdd]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#dw4ulnrs'
+
+            [bitcoin_config]
+            network = 'bitcoin'
+            poll_interval_secs = 18
+
+            [metrics]
+            addr = '127.0.0.1:9766'
+            expiry_window_blocks = 144
+
+            [bitcoind_config]
+            cookie_path = '/home/user/.bitcoin/.cookie'
+            addr = '127.0.0.1:8332'
+            "#.trim_start().replace("            ", "");
+        let parsed = toml::from_str::<Config>(&toml_str).expect("Deserializing toml_str");
+        let serialized = toml::to_string_pretty(&parsed).expect("Serializing to toml");
+        assert_eq!(toml_str, serialized);
+        let metrics = parsed.metrics.expect("Metrics are set");
+        assert_eq!(metrics.addr, "127.0.0.1:9766".parse().unwrap());
+        assert_eq!(metrics.expiry_window_blocks, Some(144));
+
+        // The expiry window is optional.
+        let toml_str = toml_str.replace("expiry_window_blocks = 144\n", "");
+        let parsed = toml::from_str::<Config>(&toml_str).expect("Deserializing toml_str");
+        assert_eq!(parsed.metrics.unwrap().expiry_window_blocks, None);
+
+        // So is the address, which defaults to the local machine.
+        let toml_str = toml_str.replace("addr = '127.0.0.1:9766'\n", "");
+        let parsed = toml::from_str::<Config>(&toml_str).expect("Deserializing toml_str");
+        assert_eq!(
+            parsed.metrics.unwrap().addr,
+            "127.0.0.1:9766".parse().unwrap()
+        );
+    }
+
+    // Test the format of the optional `dust_protection` section
+    #[test]
+    fn toml_dust_protection_config() {
+        let toml_str = r#"
+            data_dir = '/home/wizardsardine/custom/folder/'
+            log_level = 'DEBUG'
+            main_descriptor = 'wsh(andor(pk([aabbccdd]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk([aabbccdd]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#dw4ulnrs'
+
+            [bitcoin_config]
+            network = 'bitcoin'
+            poll_interval_secs = 18
+
+            [dust_protection]
+            max_amount_sat = 546
+            reused_address = true
+            min_tiny_outputs = 20
+
+            [bitcoind_config]
+            cookie_path = '/home/user/.bitcoin/.cookie'
+            addr = '127.0.0.1:8332'
+            "#.trim_start().replace("            ", "");
+        let parsed = toml::from_str::<Config>(&toml_str).expect("Deserializing toml_str");
+        let serialized = toml::to_string_pretty(&parsed).expect("Serializing to toml");
+        assert_eq!(toml_str, serialized);
+        let dust = parsed.dust_protection.expect("Dust protection is set");
+        assert_eq!(dust.max_amount_sat, 546);
+        assert!(dust.reused_address);
+        assert_eq!(dust.min_tiny_outputs, 20);
+
+        // All the fields have defaults.
+        let toml_str = toml_str
+            .replace("max_amount_sat = 546\n", "")
+            .replace("reused_address = true\n", "")
+            .replace("min_tiny_outputs = 20\n", "");
+        let parsed = toml::from_str::<Config>(&toml_str).expect("Deserializing toml_str");
+        let dust = parsed.dust_protection.expect("Dust protection is set");
+        assert_eq!(dust.max_amount_sat, 1_000);
+        assert!(!dust.reused_address);
+        assert_eq!(dust.min_tiny_outputs, 10);
+    }
+
+    // Test the format of the optional `privacy` section
+    #[test]
+    fn toml_privacy_config() {
+        let toml_str = r#"
+            log_level = 'DEBUG'
+            main_descriptor = 'wsh(andor(pk([aabbccdd]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk([aabbccdd]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#dw4ulnrs'
+
+            [bitcoin_config]
+            network = 'bitcoin'
+            poll_interval_secs = 18
+
+            [privacy]
+            kyc_labels = ['Exchange withdrawal', 'ATM']
+
+            [bitcoind_config]
+            cookie_path = '/home/user/.bitcoin/.cookie'
+            addr = '127.0.0.1:8332'
+            "#.trim_start().replace("            ", "");
+        let parsed = toml::from_str::<Config>(&toml_str).expect("Deserializing toml_str");
+        let serialized = toml::to_string_pretty(&parsed).expect("Serializing to toml");
+        assert_eq!(toml_str, serialized);
+        assert_eq!(
+            parsed.privacy.expect("Privacy is set").kyc_labels,
+            vec!["Exchange withdrawal".to_string(), "ATM".to_string()]
+        );
+
+        // The labels default to none.
+        let toml_str = toml_str.replace("kyc_labels = ['Exchange withdrawal', 'ATM']\n", "");
+        let parsed = toml::from_str::<Config>(&toml_str).expect("Deserializing toml_str");
+        assert!(parsed
+            .privacy
+            .expect("Privacy is set")
+            .kyc_labels
+            .is_empty());
+    }
+
+    // Test the format and the sanity checks of the optional `consolidation` section
+    #[test]
+    fn toml_consolidation_config() {
+        let toml_str = r#"
+            log_level = 'DEBUG'
+            main_descriptor = 'wsh(andor(pk([aabbccdd]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk([aabbccdd]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#dw4ulnrs'
+
+            [bitcoin_config]
+            network = 'testnet'
+            poll_interval_secs = 18
+
+            [consolidation]
+            max_feerate = 3
+            conf_target = 72
+            min_coins = 10
+            max_coins = 20
+            max_coin_amount_sat = 100000
+            max_fee_share = 0.1
+            exclude_labels = ['Cold storage', 'Donations']
+            notify_command = 'notify-signers %s'
+
+            [bitcoind_config]
+            cookie_path = '/home/user/.bitcoin/.cookie'
+            addr = '127.0.0.1:8332'
+            "#.trim_start().replace("            ", "");
+        let parsed = toml::from_str::<Config>(&toml_str).expect("Deserializing toml_str");
+        parsed.check().expect("Valid config");
+        let consolidation = parsed.consolidation.expect("Consolidation is set");
+        assert_eq!(consolidation.max_feerate, 3);
+        assert_eq!(consolidation.conf_target, 72);
+        assert_eq!(consolidation.min_coins, 10);
+        assert_eq!(consolidation.max_coins, 20);
+        assert_eq!(consolidation.max_coin_amount_sat, Some(100_000));
+        assert_eq!(consolidation.max_fee_share, 0.1);
+        assert_eq!(
+            consolidation.exclude_labels,
+            vec!["Cold storage".to_string(), "Donations".to_string()]
+        );
+        assert_eq!(
+            consolidation.notify_command.as_deref(),
+            Some("notify-signers %s")
+        );
+
+        // Only the maximum feerate is mandatory.
+        let default_str = toml_str
+            .lines()
+            .filter(|l| {
+                ![
+                    "conf_target",
+                    "min_coins",
+                    "max_coin",
+                    "max_fee_share",
+                    "exclude",
+                    "notify",
+                ]
+                .iter()
+                .any(|field| l.starts_with(field))
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let parsed = toml::from_str::<Config>(&default_str).expect("Deserializing toml_str");
+        let consolidation = parsed.consolidation.expect("Consolidation is set");
+        assert_eq!(consolidation.conf_target, 144);
+        assert_eq!(consolidation.min_coins, 5);
+        assert_eq!(consolidation.max_coins, 50);
+        assert_eq!(consolidation.max_coin_amount_sat, None);
+        assert_eq!(consolidation.max_fee_share, 0.05);
+        assert!(consolidation.exclude_labels.is_empty());
+        assert_eq!(consolidation.notify_command, None);
+        assert!(toml::from_str::<Config>(&default_str.replace("max_feerate = 3\n", "")).is_err());
+
+        // Nonsensical coin counts and fee shares are refused.
+        for (from, to) in [
+            ("min_coins = 10", "min_coins = 1"),
+            ("min_coins = 10", "min_coins = 21"),
+            ("max_fee_share = 0.1", "max_fee_share = 0.0"),
+            ("max_fee_share = 0.1", "max_fee_share = 1.5"),
+        ] {
+            let parsed = toml::from_str::<Config>(&toml_str.replace(from, to))
+                .expect("Deserializing toml_str");
+            assert!(matches!(parsed.check(), Err(ConfigError::Unexpected(_))));
+        }
+
+        // It can't be used without feerate estimates.
+        let cbf_str = toml_str.replace(
+            "[bitcoind_config]\ncookie_path = '/home/user/.bitcoin/.cookie'\naddr = '127.0.0.1:8332'",
+            "[cbf_config]\naddr = '127.0.0.1:8333'",
+        );
+        let parsed = toml::from_str::<Config>(&cbf_str).expect("Deserializing cbf_str");
+        assert!(matches!(
+            parsed.bitcoin_backend,
+            Some(BitcoinBackend::Cbf(_))
+        ));
+        assert!(matches!(parsed.check(), Err(ConfigError::Unexpected(_))));
     }
 
     #[test]
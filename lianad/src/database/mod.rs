@@ -7,7 +7,7 @@ pub mod sqlite;
 use crate::{
     bitcoin::BlockChainTip,
     database::sqlite::{
-        schema::{DbBlockInfo, DbCoin, DbTip},
+        schema::{DbBlockInfo, DbCoin, DbScheduledBroadcast, DbTip},
         SqliteConn, SqliteDb,
     },
 };
@@ -21,7 +21,10 @@ use std::{
 };
 
 use bip329::Labels;
-use miniscript::bitcoin::{self, bip32, psbt::Psbt, secp256k1, Address, Network, OutPoint, Txid};
+use liana::descriptors::LianaDescriptor;
+use miniscript::bitcoin::{
+    self, absolute, bip32, psbt::Psbt, relative, secp256k1, Address, Network, OutPoint, Txid,
+};
 
 /// Information about the wallet.
 ///
@@ -38,6 +41,10 @@ pub struct Wallet {
     pub rescan_timestamp: Option<u32>,
     /// Timestamp at which the last poll of the blockchain completed, if any,
     pub last_poll_timestamp: Option<u32>,
+    /// Timestamp at which the wallet was retired in favour of a new descriptor, if it was.
+    pub retired_timestamp: Option<u32>,
+    /// The descriptor this wallet was migrated to, if it was retired.
+    pub successor_descriptor: Option<LianaDescriptor>,
 }
 
 pub trait DatabaseInterface: Send {
@@ -110,12 +117,19 @@ pub trait DatabaseConnection {
     /// where `timestamp` should be given as the number of seconds since the UNIX epoch.
     fn set_last_poll(&mut self, timestamp: u32);
 
+    /// Mark the wallet as retired in favour of the given successor descriptor.
+    fn retire(&mut self, successor: &LianaDescriptor, timestamp: u32);
+
     /// Get the derivation index for this address, as well as whether this address is change.
     fn derivation_index_by_address(
         &mut self,
         address: &bitcoin::Address,
     ) -> Option<(bip32::ChildNumber, bool)>;
 
+    /// Get the Script Pubkeys of all the addresses in our derivation index cache, along with their
+    /// derivation index and whether they are change.
+    fn script_pubkeys(&mut self) -> Vec<(bitcoin::ScriptBuf, bip32::ChildNumber, bool)>;
+
     /// Get all our coins, past or present, spent or not.
     fn coins(
         &mut self,
@@ -132,6 +146,9 @@ pub trait DatabaseConnection {
     /// Remove some UTxOs from the database.
     fn remove_coins(&mut self, coins: &[bitcoin::OutPoint]);
 
+    /// Release a set of coins from quarantine.
+    fn release_coins(&mut self, outpoints: &[bitcoin::OutPoint]);
+
     /// Mark a set of coins as being confirmed at a specified height and block time.
     /// NOTE: if the coin comes from an immature coinbase transaction, this will mark it as mature.
     /// Immature coinbase deposits must not be confirmed before they are 100 blocks deep in the
@@ -164,6 +181,25 @@ pub trait DatabaseConnection {
     /// Delete a Spend transaction from database.
     fn delete_spend(&mut self, txid: &bitcoin::Txid);
 
+    /// Insert a transaction to be broadcast once it becomes valid, or replace an existing one with
+    /// the same txid. A replaced transaction is considered as not broadcast yet.
+    fn schedule_broadcast(&mut self, tx: &bitcoin::Transaction, created_at: u32);
+
+    /// List all the transactions scheduled for broadcast, whether they were broadcast or not.
+    fn scheduled_broadcasts(&mut self) -> Vec<ScheduledBroadcast>;
+
+    /// Record the outcome of an attempt to broadcast a scheduled transaction: either the time at
+    /// which it was broadcast or the error returned by the Bitcoin backend.
+    fn update_scheduled_broadcast(
+        &mut self,
+        txid: &bitcoin::Txid,
+        broadcast_at: Option<u32>,
+        last_error: Option<String>,
+    );
+
+    /// Remove a transaction from the broadcast schedule.
+    fn delete_scheduled_broadcast(&mut self, txid: &bitcoin::Txid);
+
     /// Update, for a set of items (as key), their label (as value). A `None` value deletes the
     /// label.
     fn update_labels(&mut self, items: &HashMap<LabelItem, Option<String>>);
@@ -220,6 +256,8 @@ impl DatabaseConnection for SqliteConn {
             change_index: db_wallet.change_derivation_index,
             rescan_timestamp: db_wallet.rescan_timestamp,
             last_poll_timestamp: db_wallet.last_poll_timestamp,
+            retired_timestamp: db_wallet.retired_timestamp,
+            successor_descriptor: db_wallet.successor_descriptor,
         }
     }
 
@@ -276,6 +314,10 @@ impl DatabaseConnection for SqliteConn {
             .expect("database must be available")
     }
 
+    fn retire(&mut self, successor: &LianaDescriptor, timestamp: u32) {
+        self.retire_wallet(successor, timestamp)
+    }
+
     fn coins(
         &mut self,
         statuses: &[CoinStatus],
@@ -302,6 +344,10 @@ impl DatabaseConnection for SqliteConn {
         self.remove_coins(outpoints)
     }
 
+    fn release_coins(&mut self, outpoints: &[bitcoin::OutPoint]) {
+        self.release_coins(outpoints)
+    }
+
     fn confirm_coins<'a>(&mut self, outpoints: &[(bitcoin::OutPoint, i32, u32)]) {
         self.confirm_coins(outpoints)
     }
@@ -333,6 +379,26 @@ impl DatabaseConnection for SqliteConn {
         })
     }
 
+    fn script_pubkeys(&mut self) -> Vec<(bitcoin::ScriptBuf, bip32::ChildNumber, bool)> {
+        self.db_addresses()
+            .into_iter()
+            .flat_map(|db_addr| {
+                [
+                    (
+                        db_addr.receive_address.assume_checked().script_pubkey(),
+                        db_addr.derivation_index,
+                        false,
+                    ),
+                    (
+                        db_addr.change_address.assume_checked().script_pubkey(),
+                        db_addr.derivation_index,
+                        true,
+                    ),
+                ]
+            })
+            .collect()
+    }
+
     fn coins_by_outpoints(
         &mut self,
         outpoints: &[bitcoin::OutPoint],
@@ -362,6 +428,30 @@ impl DatabaseConnection for SqliteConn {
         self.delete_spend(txid)
     }
 
+    fn schedule_broadcast(&mut self, tx: &bitcoin::Transaction, created_at: u32) {
+        self.schedule_broadcast(tx, created_at)
+    }
+
+    fn scheduled_broadcasts(&mut self) -> Vec<ScheduledBroadcast> {
+        self.scheduled_broadcasts()
+            .into_iter()
+            .map(ScheduledBroadcast::from)
+            .collect()
+    }
+
+    fn update_scheduled_broadcast(
+        &mut self,
+        txid: &bitcoin::Txid,
+        broadcast_at: Option<u32>,
+        last_error: Option<String>,
+    ) {
+        self.update_scheduled_broadcast(txid, broadcast_at, last_error)
+    }
+
+    fn delete_scheduled_broadcast(&mut self, txid: &bitcoin::Txid) {
+        self.delete_scheduled_broadcast(txid)
+    }
+
     fn update_labels(&mut self, items: &HashMap<LabelItem, Option<String>>) {
         self.update_labels(items)
     }
@@ -444,6 +534,9 @@ pub struct Coin {
     pub spend_txid: Option<bitcoin::Txid>,
     pub spend_block: Option<BlockInfo>,
     pub is_from_self: bool,
+    /// Whether this coin was flagged as a likely dust attack upon reception. A quarantined coin
+    /// is never selected automatically to be spent.
+    pub is_quarantined: bool,
 }
 
 impl std::convert::From<DbCoin> for Coin {
@@ -458,6 +551,7 @@ impl std::convert::From<DbCoin> for Coin {
             spend_txid,
             spend_block,
             is_from_self,
+            is_quarantined,
             ..
         } = db_coin;
         Coin {
@@ -470,6 +564,7 @@ impl std::convert::From<DbCoin> for Coin {
             spend_txid,
             spend_block: spend_block.map(BlockInfo::from),
             is_from_self,
+            is_quarantined,
         }
     }
 }
@@ -520,6 +615,143 @@ impl CoinStatus {
     }
 }
 
+/// A fully signed transaction to be broadcast once it becomes valid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledBroadcast {
+    pub tx: bitcoin::Transaction,
+    /// Timestamp at which it was scheduled.
+    pub created_at: u32,
+    /// Timestamp at which it was broadcast, if it was.
+    pub broadcast_at: Option<u32>,
+    /// The error returned by the Bitcoin backend on the last attempt to broadcast it, if any.
+    pub last_error: Option<String>,
+}
+
+impl From<DbScheduledBroadcast> for ScheduledBroadcast {
+    fn from(db_sched: DbScheduledBroadcast) -> ScheduledBroadcast {
+        let DbScheduledBroadcast {
+            tx,
+            created_at,
+            broadcast_at,
+            last_error,
+            ..
+        } = db_sched;
+        ScheduledBroadcast {
+            tx,
+            created_at,
+            broadcast_at,
+            last_error,
+        }
+    }
+}
+
+/// The state of a scheduled transaction at a given chain tip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduledBroadcastState {
+    /// A coin it spends with a relative timelock isn't confirmed yet, so we can't tell from which
+    /// block it will be valid.
+    WaitingConfirmation,
+    /// Not valid yet. It may be included in the block at this height and once the median time
+    /// past of the chain is past this timestamp.
+    Pending {
+        height: Option<i32>,
+        time: Option<u32>,
+    },
+    /// Valid for inclusion in the next block.
+    Ready,
+    /// Broadcast, by us or by someone else, but not confirmed yet.
+    Broadcast,
+    /// All the coins it spends were spent by it in a block.
+    Confirmed,
+    /// A coin it spends is unknown or was spent by another transaction.
+    Conflicted,
+}
+
+impl ScheduledBroadcast {
+    pub fn txid(&self) -> bitcoin::Txid {
+        self.tx.compute_txid()
+    }
+
+    /// Get the state of this transaction given the coins it spends, the height of the current
+    /// chain tip and its median time past.
+    pub fn state(
+        &self,
+        coins: &HashMap<bitcoin::OutPoint, Coin>,
+        tip_height: i32,
+        tip_mtp: Option<u32>,
+    ) -> ScheduledBroadcastState {
+        let txid = self.txid();
+
+        // It is only done with once all its coins are spent by it in a block. Until then it may
+        // still be dropped from the mempool, so it's considered broadcast and not confirmed.
+        let (mut spent, mut confirmed) = (false, true);
+        for txin in &self.tx.input {
+            let coin = match coins.get(&txin.previous_output) {
+                Some(coin) => coin,
+                None => return ScheduledBroadcastState::Conflicted,
+            };
+            match coin.spend_txid {
+                Some(spend_txid) if spend_txid == txid => {
+                    spent = true;
+                    confirmed &= coin.spend_block.is_some();
+                }
+                Some(_) => return ScheduledBroadcastState::Conflicted,
+                None => confirmed = false,
+            }
+        }
+        if spent && confirmed {
+            return ScheduledBroadcastState::Confirmed;
+        }
+        if spent || self.broadcast_at.is_some() {
+            return ScheduledBroadcastState::Broadcast;
+        }
+
+        // The block including the transaction must be higher than its locktime, or its median
+        // time past later than its locktime.
+        let (mut height, time) = match self.tx.lock_time {
+            _ if !self.tx.is_lock_time_enabled() => (None, None),
+            absolute::LockTime::Blocks(h) => (Some(h.to_consensus_u32() as i32 + 1), None),
+            absolute::LockTime::Seconds(t) => (None, Some(t.to_consensus_u32())),
+        };
+
+        // The block including the transaction must be at least as many blocks after the one
+        // including a coin as the relative timelock of the input spending it. Time-based
+        // relative timelocks are never used in Liana descriptors, we don't account for them.
+        let mut waiting_conf = false;
+        for txin in &self.tx.input {
+            let coin = match coins.get(&txin.previous_output) {
+                Some(coin) => coin,
+                None => return ScheduledBroadcastState::Conflicted,
+            };
+            if self.tx.version.0 < 2 {
+                continue;
+            }
+            if let Some(relative::LockTime::Blocks(blocks)) = txin.sequence.to_relative_lock_time()
+            {
+                match coin.block_info {
+                    Some(block) => {
+                        let min_height = block.height + i32::from(blocks.value());
+                        height = Some(height.map_or(min_height, |h| h.max(min_height)));
+                    }
+                    None => waiting_conf = true,
+                }
+            }
+        }
+        if waiting_conf {
+            return ScheduledBroadcastState::WaitingConfirmation;
+        }
+
+        // The median time past of the next block is at least the one of the tip (BIP113).
+        let height_ready = height.is_none_or(|h| tip_height + 1 >= h);
+        let time_ready = time.is_none_or(|t| tip_mtp.is_some_and(|mtp| mtp > t));
+        if height_ready && time_ready {
+            ScheduledBroadcastState::Ready
+        } else {
+            ScheduledBroadcastState::Pending { height, time }
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum LabelItem {
     Address(bitcoin::Address),
@@ -637,4 +869,156 @@ mod tests {
             Some(CoinStatus::Spent)
         );
     }
+
+    #[test]
+    fn scheduled_broadcast_state() {
+        let op_a = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let op_b = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:1",
+        )
+        .unwrap();
+        let coin = |outpoint, height: Option<i32>| Coin {
+            outpoint,
+            is_immature: false,
+            block_info: height.map(|height| BlockInfo { height, time: 1 }),
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(0),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            is_from_self: false,
+            is_quarantined: false,
+        };
+        let mut coins = HashMap::from([(op_a, coin(op_a, Some(100))), (op_b, coin(op_b, None))]);
+        let txin = |previous_output, sequence| bitcoin::TxIn {
+            previous_output,
+            sequence,
+            ..bitcoin::TxIn::default()
+        };
+        let mut sched = ScheduledBroadcast {
+            tx: bitcoin::Transaction {
+                version: bitcoin::transaction::Version::TWO,
+                lock_time: absolute::LockTime::from_height(150).unwrap(),
+                input: vec![txin(op_a, bitcoin::Sequence::ENABLE_LOCKTIME_NO_RBF)],
+                output: vec![],
+            },
+            created_at: 1,
+            broadcast_at: None,
+            last_error: None,
+        };
+
+        // An absolute timelock may be included in the block after its value.
+        assert_eq!(
+            sched.state(&coins, 149, None),
+            ScheduledBroadcastState::Pending {
+                height: Some(151),
+                time: None
+            }
+        );
+        assert_eq!(
+            sched.state(&coins, 150, None),
+            ScheduledBroadcastState::Ready
+        );
+
+        // A relative timelock is counted from the confirmation of the coin.
+        sched.tx.input[0].sequence = bitcoin::Sequence::from_height(144);
+        assert_eq!(
+            sched.state(&coins, 200, None),
+            ScheduledBroadcastState::Pending {
+                height: Some(244),
+                time: None
+            }
+        );
+        assert_eq!(
+            sched.state(&coins, 243, None),
+            ScheduledBroadcastState::Ready
+        );
+        // It can't be known before the coin is confirmed.
+        sched
+            .tx
+            .input
+            .push(txin(op_b, bitcoin::Sequence::from_height(10)));
+        assert_eq!(
+            sched.state(&coins, 243, None),
+            ScheduledBroadcastState::WaitingConfirmation
+        );
+        coins.get_mut(&op_b).unwrap().block_info = Some(BlockInfo {
+            height: 240,
+            time: 1,
+        });
+        assert_eq!(
+            sched.state(&coins, 243, None),
+            ScheduledBroadcastState::Pending {
+                height: Some(250),
+                time: None
+            }
+        );
+
+        // A time-based locktime is compared to the median time past of the tip.
+        sched.tx.lock_time = absolute::LockTime::from_time(1_700_000_000).unwrap();
+        assert_eq!(
+            sched.state(&coins, 249, Some(1_700_000_000)),
+            ScheduledBroadcastState::Pending {
+                height: Some(250),
+                time: Some(1_700_000_000)
+            }
+        );
+        assert_eq!(
+            sched.state(&coins, 249, Some(1_700_000_001)),
+            ScheduledBroadcastState::Ready
+        );
+
+        // Once we broadcast it, it stays broadcast until all its coins are spent by it in a block.
+        sched.broadcast_at = Some(2);
+        assert_eq!(
+            sched.state(&coins, 249, None),
+            ScheduledBroadcastState::Broadcast
+        );
+        let txid = sched.txid();
+        coins.get_mut(&op_a).unwrap().spend_txid = Some(txid);
+        coins.get_mut(&op_a).unwrap().spend_block = Some(BlockInfo {
+            height: 250,
+            time: 2,
+        });
+        assert_eq!(
+            sched.state(&coins, 250, None),
+            ScheduledBroadcastState::Broadcast
+        );
+        coins.get_mut(&op_b).unwrap().spend_txid = Some(txid);
+        assert_eq!(
+            sched.state(&coins, 250, None),
+            ScheduledBroadcastState::Broadcast
+        );
+        // Someone else may have broadcast it too.
+        sched.broadcast_at = None;
+        assert_eq!(
+            sched.state(&coins, 250, None),
+            ScheduledBroadcastState::Broadcast
+        );
+        coins.get_mut(&op_b).unwrap().spend_block = Some(BlockInfo {
+            height: 250,
+            time: 2,
+        });
+        assert_eq!(
+            sched.state(&coins, 250, None),
+            ScheduledBroadcastState::Confirmed
+        );
+
+        // If one of its coins is spent by another transaction or unknown, it's conflicted even if
+        // we broadcast it.
+        sched.broadcast_at = Some(2);
+        coins.get_mut(&op_a).unwrap().spend_txid = Some(op_a.txid);
+        assert_eq!(
+            sched.state(&coins, 250, None),
+            ScheduledBroadcastState::Conflicted
+        );
+        coins.remove(&op_a);
+        assert_eq!(
+            sched.state(&coins, 250, None),
+            ScheduledBroadcastState::Conflicted
+        );
+    }
 }
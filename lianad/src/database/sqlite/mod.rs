@@ -15,8 +15,8 @@ use crate::{
     database::{
         sqlite::{
             schema::{
-                DbAddress, DbCoin, DbLabel, DbLabelledKind, DbSpendTransaction, DbTip, DbWallet,
-                DbWalletTransaction, SCHEMA,
+                DbAddress, DbCoin, DbLabel, DbLabelledKind, DbScheduledBroadcast,
+                DbSpendTransaction, DbTip, DbWallet, DbWalletTransaction, SCHEMA,
             },
             utils::{
                 create_fresh_db, curr_timestamp, db_exec, db_query, db_tx_query, db_version,
@@ -43,7 +43,7 @@ use miniscript::bitcoin::{
     secp256k1,
 };
 
-const DB_VERSION: i64 = 8;
+const DB_VERSION: i64 = 11;
 
 /// Last database version for which Bitcoin transactions were not stored in database. In practice
 /// this meant we relied on the bitcoind watchonly wallet to store them for us.
@@ -385,6 +385,19 @@ impl SqliteConn {
         .map_err(SqliteDbError::Rusqlite)
     }
 
+    /// Mark the wallet as retired in favour of the given successor descriptor.
+    pub fn retire_wallet(&mut self, successor: &LianaDescriptor, timestamp: u32) {
+        db_exec(&mut self.conn, |db_tx| {
+            db_tx
+                .execute(
+                    "UPDATE wallets SET retired_timestamp = (?1), successor_descriptor = (?2) WHERE id = (?3)",
+                    rusqlite::params![timestamp, successor.to_string(), WALLET_ID],
+                )
+                .map(|_| ())
+        })
+        .expect("Database must be available")
+    }
+
     /// Get all the coins from DB, optionally filtered by coin status and/or outpoint.
     pub fn coins(
         &mut self,
@@ -461,8 +474,8 @@ impl SqliteConn {
             for coin in coins {
                 let deriv_index: u32 = coin.derivation_index.into();
                 db_tx.execute(
-                    "INSERT INTO coins (wallet_id, txid, vout, amount_sat, derivation_index, is_change, is_immature) \
-                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    "INSERT INTO coins (wallet_id, txid, vout, amount_sat, derivation_index, is_change, is_immature, is_quarantined) \
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
                     rusqlite::params![
                         WALLET_ID,
                         coin.outpoint.txid[..].to_vec(),
@@ -471,6 +484,7 @@ impl SqliteConn {
                         deriv_index,
                         coin.is_change,
                         coin.is_immature,
+                        coin.is_quarantined,
                     ],
                 )?;
             }
@@ -494,6 +508,21 @@ impl SqliteConn {
         .expect("Database must be available")
     }
 
+    /// Release a set of coins from quarantine.
+    pub fn release_coins(&mut self, outpoints: &[bitcoin::OutPoint]) {
+        db_exec(&mut self.conn, |db_tx| {
+            for outpoint in outpoints {
+                db_tx.execute(
+                    "UPDATE coins SET is_quarantined = 0 WHERE txid = ?1 AND vout = ?2",
+                    rusqlite::params![outpoint.txid[..].to_vec(), outpoint.vout,],
+                )?;
+            }
+
+            Ok(())
+        })
+        .expect("Database must be available")
+    }
+
     /// Mark a set of coins as confirmed.
     ///
     /// NOTE: this will also mark the coin as mature if it originates from an immature coinbase
@@ -594,6 +623,17 @@ impl SqliteConn {
         .pop()
     }
 
+    /// Get all the entries of the address->deriv_index mapping.
+    pub fn db_addresses(&mut self) -> Vec<DbAddress> {
+        db_query(
+            &mut self.conn,
+            "SELECT * FROM addresses ORDER BY derivation_index",
+            rusqlite::params![],
+            |row| row.try_into(),
+        )
+        .expect("Db must not fail")
+    }
+
     pub fn db_coins(&mut self, outpoints: &[bitcoin::OutPoint]) -> Vec<DbCoin> {
         self.coins(&[], outpoints)
     }
@@ -924,6 +964,58 @@ impl SqliteConn {
         .expect("Db must not fail");
     }
 
+    pub fn schedule_broadcast(&mut self, tx: &bitcoin::Transaction, created_at: u32) {
+        let txid = &tx.compute_txid()[..].to_vec();
+
+        db_exec(&mut self.conn, |db_tx| {
+            db_tx.execute(
+                "INSERT INTO scheduled_broadcasts (txid, tx, created_at) VALUES (?1, ?2, ?3) \
+                 ON CONFLICT DO UPDATE SET tx=excluded.tx, created_at=excluded.created_at, \
+                 broadcast_at=NULL, last_error=NULL",
+                rusqlite::params![txid, bitcoin::consensus::serialize(tx), created_at],
+            )?;
+            Ok(())
+        })
+        .expect("Db must not fail");
+    }
+
+    pub fn scheduled_broadcasts(&mut self) -> Vec<DbScheduledBroadcast> {
+        db_query(
+            &mut self.conn,
+            "SELECT * FROM scheduled_broadcasts ORDER BY created_at, id",
+            rusqlite::params![],
+            |row| row.try_into(),
+        )
+        .expect("Db must not fail")
+    }
+
+    pub fn update_scheduled_broadcast(
+        &mut self,
+        txid: &bitcoin::Txid,
+        broadcast_at: Option<u32>,
+        last_error: Option<String>,
+    ) {
+        db_exec(&mut self.conn, |db_tx| {
+            db_tx.execute(
+                "UPDATE scheduled_broadcasts SET broadcast_at = ?1, last_error = ?2 WHERE txid = ?3",
+                rusqlite::params![broadcast_at, last_error, txid[..].to_vec()],
+            )?;
+            Ok(())
+        })
+        .expect("Db must not fail");
+    }
+
+    pub fn delete_scheduled_broadcast(&mut self, txid: &bitcoin::Txid) {
+        db_exec(&mut self.conn, |db_tx| {
+            db_tx.execute(
+                "DELETE FROM scheduled_broadcasts WHERE txid = ?1",
+                rusqlite::params![txid[..].to_vec()],
+            )?;
+            Ok(())
+        })
+        .expect("Db must not fail");
+    }
+
     // TODO: mark coinbase deposits that were mature and became immature as such.
     /// Unconfirm all data that was marked as being confirmed *after* the given chain
     /// tip, and set it as our new best block seen.
@@ -1453,6 +1545,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             conn.new_unspent_coins(&[coin_a]);
             // We can query by status and/or outpoint.
@@ -1500,6 +1593,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             conn.new_unspent_coins(&[coin_b]);
             // Both coins are unconfirmed.
@@ -1635,6 +1729,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             let outpoint_d = bitcoin::OutPoint::new(txs.get(4).unwrap().compute_txid(), 43);
             let coin_d = Coin {
@@ -1647,6 +1742,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             conn.new_unspent_coins(&[coin_c, coin_d]);
 
@@ -1762,6 +1858,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             conn.new_unspent_coins(&[coin_a]);
             assert_eq!(conn.coins(&[], &[])[0].outpoint, coin_a.outpoint);
@@ -1770,6 +1867,17 @@ CREATE TABLE labels (
             conn.remove_coins(&[coin_a.outpoint]);
             assert!(conn.coins(&[], &[]).is_empty());
 
+            // A coin may be stored in quarantine, until it's released.
+            let quarantined_coin = Coin {
+                is_quarantined: true,
+                ..coin_a
+            };
+            conn.new_unspent_coins(&[quarantined_coin]);
+            assert!(conn.db_coins(&[coin_a.outpoint])[0].is_quarantined);
+            conn.release_coins(&[coin_a.outpoint]);
+            assert!(!conn.db_coins(&[coin_a.outpoint])[0].is_quarantined);
+            conn.remove_coins(&[coin_a.outpoint]);
+
             // Add it back for the rest of the test.
             conn.new_unspent_coins(&[coin_a]);
 
@@ -1805,6 +1913,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             conn.new_unspent_coins(&[coin_b]);
             let outpoints: HashSet<bitcoin::OutPoint> = conn
@@ -1934,6 +2043,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             conn.new_unspent_coins(&[coin_imma]);
             let outpoints: HashSet<bitcoin::OutPoint> = conn
@@ -1996,12 +2106,17 @@ CREATE TABLE labels (
                 .derive(200.into(), &secp)
                 .address(options.bitcoind_network);
             assert!(conn.db_address(&addr).is_none());
+            let db_addresses = conn.db_addresses();
+            assert_eq!(db_addresses.len(), 200);
+            assert_eq!(db_addresses[199].derivation_index, 199.into());
 
             // But if we increment the deposit derivation index, the 200th one will be there.
             conn.set_derivation_index(1.into(), false, &secp);
             let db_addr = conn.db_address(&addr).unwrap();
             assert_eq!(db_addr.derivation_index, 200.into());
 
+            assert_eq!(conn.db_addresses().len(), 201);
+
             // It will also be there for the change descriptor.
             let addr = options
                 .main_descriptor
@@ -2106,6 +2221,7 @@ CREATE TABLE labels (
                     spend_txid: None,
                     spend_block: None,
                     is_from_self: false,
+                    is_quarantined: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::new(txs.get(1).unwrap().compute_txid(), 2),
@@ -2120,6 +2236,7 @@ CREATE TABLE labels (
                     spend_txid: None,
                     spend_block: None,
                     is_from_self: false,
+                    is_quarantined: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::new(txs.get(2).unwrap().compute_txid(), 3),
@@ -2137,6 +2254,7 @@ CREATE TABLE labels (
                         time: 1_231_678,
                     }),
                     is_from_self: false,
+                    is_quarantined: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::new(txs.get(4).unwrap().compute_txid(), 4),
@@ -2151,6 +2269,7 @@ CREATE TABLE labels (
                     spend_txid: None,
                     spend_block: None,
                     is_from_self: false,
+                    is_quarantined: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::new(txs.get(5).unwrap().compute_txid(), 5),
@@ -2168,6 +2287,7 @@ CREATE TABLE labels (
                         time: 1_201_678,
                     }),
                     is_from_self: false,
+                    is_quarantined: false,
                 },
             ];
             conn.new_unspent_coins(&coins);
@@ -2286,6 +2406,82 @@ CREATE TABLE labels (
         fs::remove_dir_all(tmp_dir).unwrap();
     }
 
+    #[test]
+    fn db_retire_wallet() {
+        let (tmp_dir, options, _, db) = dummy_db();
+
+        {
+            let mut conn = db.connection().unwrap();
+
+            // At first the wallet isn't retired.
+            let db_wallet = conn.db_wallet();
+            assert!(db_wallet.retired_timestamp.is_none());
+            assert!(db_wallet.successor_descriptor.is_none());
+
+            // Once migrated, the date and the new descriptor are recorded.
+            let successor = options.main_descriptor.with_taproot(true).unwrap();
+            conn.retire_wallet(&successor, 1_001);
+            let db_wallet = conn.db_wallet();
+            assert_eq!(db_wallet.retired_timestamp, Some(1_001));
+            assert_eq!(db_wallet.successor_descriptor, Some(successor));
+        }
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn db_scheduled_broadcasts() {
+        let (tmp_dir, _, _, db) = dummy_db();
+
+        {
+            let mut conn = db.connection().unwrap();
+            assert!(conn.scheduled_broadcasts().is_empty());
+
+            let txs: Vec<_> = (0..2)
+                .map(|i| bitcoin::Transaction {
+                    version: bitcoin::transaction::Version::TWO,
+                    lock_time: bitcoin::absolute::LockTime::from_height(i).unwrap(),
+                    input: vec![bitcoin::TxIn::default()],
+                    output: vec![bitcoin::TxOut::minimal_non_dust(ScriptBuf::default())],
+                })
+                .collect();
+            let (txid_a, txid_b) = (txs[0].compute_txid(), txs[1].compute_txid());
+            conn.schedule_broadcast(&txs[0], 1_000);
+            conn.schedule_broadcast(&txs[1], 1_001);
+            let scheduled = conn.scheduled_broadcasts();
+            assert_eq!(scheduled.len(), 2);
+            assert_eq!(
+                (scheduled[0].txid, scheduled[0].created_at),
+                (txid_a, 1_000)
+            );
+            assert_eq!(scheduled[0].tx, txs[0]);
+            assert!(scheduled[0].broadcast_at.is_none() && scheduled[0].last_error.is_none());
+
+            // Record a failed then a successful attempt.
+            conn.update_scheduled_broadcast(&txid_a, None, Some("non-final".to_string()));
+            let scheduled = conn.scheduled_broadcasts();
+            assert_eq!(scheduled[0].last_error.as_deref(), Some("non-final"));
+            conn.update_scheduled_broadcast(&txid_a, Some(1_010), None);
+            let scheduled = conn.scheduled_broadcasts();
+            assert_eq!(scheduled[0].broadcast_at, Some(1_010));
+            assert!(scheduled[0].last_error.is_none());
+
+            // Scheduling it again resets its state.
+            conn.schedule_broadcast(&txs[0], 1_020);
+            let scheduled = conn.scheduled_broadcasts();
+            assert_eq!(scheduled[1].txid, txid_a);
+            assert_eq!(scheduled[1].created_at, 1_020);
+            assert!(scheduled[1].broadcast_at.is_none());
+
+            conn.delete_scheduled_broadcast(&txid_a);
+            let scheduled = conn.scheduled_broadcasts();
+            assert_eq!(scheduled.len(), 1);
+            assert_eq!(scheduled[0].txid, txid_b);
+        }
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
     #[test]
     fn sqlite_list_txids() {
         let (tmp_dir, _, _, db) = dummy_db();
@@ -2314,6 +2510,7 @@ CREATE TABLE labels (
                     spend_txid: None,
                     spend_block: None,
                     is_from_self: false,
+                    is_quarantined: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::new(txs.get(1).unwrap().compute_txid(), 2),
@@ -2328,6 +2525,7 @@ CREATE TABLE labels (
                     spend_txid: None,
                     spend_block: None,
                     is_from_self: false,
+                    is_quarantined: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::new(txs.get(2).unwrap().compute_txid(), 3),
@@ -2345,6 +2543,7 @@ CREATE TABLE labels (
                         time: 1_123_000,
                     }),
                     is_from_self: false,
+                    is_quarantined: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::new(txs.get(4).unwrap().compute_txid(), 4),
@@ -2359,6 +2558,7 @@ CREATE TABLE labels (
                     spend_txid: None,
                     spend_block: None,
                     is_from_self: false,
+                    is_quarantined: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::new(txs.get(5).unwrap().compute_txid(), 5),
@@ -2376,6 +2576,7 @@ CREATE TABLE labels (
                         time: 1_126_000,
                     }),
                     is_from_self: false,
+                    is_quarantined: false,
                 },
             ];
             conn.new_unspent_coins(&coins);
@@ -2438,6 +2639,7 @@ CREATE TABLE labels (
                     spend_txid: None,
                     spend_block: None,
                     is_from_self: false,
+                    is_quarantined: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::new(txs.get(1).unwrap().compute_txid(), 2),
@@ -2452,6 +2654,7 @@ CREATE TABLE labels (
                     spend_txid: None,
                     spend_block: None,
                     is_from_self: false,
+                    is_quarantined: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::new(txs.get(2).unwrap().compute_txid(), 3),
@@ -2469,6 +2672,7 @@ CREATE TABLE labels (
                         time: 1_123_000,
                     }),
                     is_from_self: false,
+                    is_quarantined: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::new(txs.get(4).unwrap().compute_txid(), 4),
@@ -2483,6 +2687,7 @@ CREATE TABLE labels (
                     spend_txid: None,
                     spend_block: None,
                     is_from_self: false,
+                    is_quarantined: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::new(txs.get(5).unwrap().compute_txid(), 5),
@@ -2500,6 +2705,7 @@ CREATE TABLE labels (
                         time: 1_126_000,
                     }),
                     is_from_self: false,
+                    is_quarantined: false,
                 },
             ];
             conn.new_unspent_coins(&coins);
@@ -2622,6 +2828,7 @@ CREATE TABLE labels (
                         None
                     },
                     is_from_self: false,
+                    is_quarantined: false,
                 })
                 .collect();
 
@@ -2778,6 +2985,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             let coin_tx_b: Coin = Coin {
                 outpoint: bitcoin::OutPoint::new(tx_b.compute_txid(), 0),
@@ -2789,6 +2997,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             conn.new_txs(&[tx_a, tx_b]);
             conn.new_unspent_coins(&[coin_tx_a, coin_tx_b]);
@@ -2812,6 +3021,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             conn.new_txs(&[tx_c.clone()]);
             conn.spend_coins(&[(coin_tx_a.outpoint, tx_c.compute_txid())]);
@@ -2836,6 +3046,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             conn.new_txs(&[tx_d.clone()]);
             conn.spend_coins(&[(coin_tx_c.outpoint, tx_d.compute_txid())]);
@@ -2859,6 +3070,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             conn.new_txs(&[tx_e.clone()]);
             conn.spend_coins(&[
@@ -2884,6 +3096,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             };
             conn.new_txs(&[tx_f.clone()]);
             conn.spend_coins(&[(coin_tx_e.outpoint, tx_f.compute_txid())]);
@@ -3079,7 +3292,7 @@ CREATE TABLE labels (
     }
 
     #[test]
-    fn v0_to_v8_migration() {
+    fn v0_to_latest_migration() {
         let secp = secp256k1::Secp256k1::verification_only();
 
         // Create a database with version 0, using the old schema.
@@ -3185,7 +3398,7 @@ CREATE TABLE labels (
         {
             let mut conn = db.connection().unwrap();
             let version = conn.db_version();
-            assert_eq!(version, 8);
+            assert_eq!(version, 11);
         }
         // We should now be able to insert another PSBT, to query both, and the first PSBT must
         // have no associated timestamp.
@@ -3226,6 +3439,7 @@ CREATE TABLE labels (
                 spend_txid: None,
                 spend_block: None,
                 is_from_self: false,
+                is_quarantined: false,
             }]);
             let coins = conn.coins(&[], &[]);
             assert_eq!(coins.len(), 3);
@@ -3260,7 +3474,7 @@ CREATE TABLE labels (
     }
 
     #[test]
-    fn v3_to_v8_migration() {
+    fn v3_to_latest_migration() {
         let secp = secp256k1::Secp256k1::verification_only();
 
         // Create a database with version 3, using the old schema.
@@ -3412,10 +3626,10 @@ CREATE TABLE labels (
 
             // Migrate the DB.
             maybe_apply_migration(&db_path, &bitcoin_txs).unwrap();
-            assert_eq!(conn.db_version(), 8);
+            assert_eq!(conn.db_version(), 11);
             // Migrating twice will be a no-op. No need to pass `bitcoin_txs` second time.
             maybe_apply_migration(&db_path, &[]).unwrap();
-            assert!(conn.db_version() == 8);
+            assert!(conn.db_version() == 11);
 
             // Compare the `DbCoin`s with the expected values.
             let coins_post = conn.coins(&[], &[]);
@@ -29,13 +29,16 @@ CREATE TABLE tip (
     blockhash BLOB
 );
 
-/* This stores metadata about our wallet. We only support single wallet for
- * now (and the foreseeable future).
+/* This stores metadata about our wallet. A database only ever stores a single
+ * wallet: the additional wallets managed by a daemon each have their own database
+ * under their own data directory.
  *
  * The 'timestamp' field is the creation date of the wallet. We guarantee to have seen all
  * information related to our descriptor(s) that occurred after this date.
  * The optional 'rescan_timestamp' field is a the timestamp we need to rescan the chain
  * for events related to our descriptor(s) from.
+ * The optional 'retired_timestamp' and 'successor_descriptor' fields are set when the wallet
+ * was migrated to a new descriptor, and are the date of the migration and the new descriptor.
  */
 CREATE TABLE wallets (
     id INTEGER PRIMARY KEY NOT NULL,
@@ -44,7 +47,9 @@ CREATE TABLE wallets (
     deposit_derivation_index INTEGER NOT NULL,
     change_derivation_index INTEGER NOT NULL,
     rescan_timestamp INTEGER,
-    last_poll_timestamp INTEGER
+    last_poll_timestamp INTEGER,
+    retired_timestamp INTEGER,
+    successor_descriptor TEXT
 );
 
 /* Our (U)TxOs.
@@ -75,6 +80,7 @@ CREATE TABLE coins (
     spend_block_time INTEGER,
     is_immature BOOLEAN NOT NULL CHECK (is_immature IN (0,1)),
     is_from_self BOOLEAN NOT NULL DEFAULT 0 CHECK (is_from_self IN (0,1)),
+    is_quarantined BOOLEAN NOT NULL DEFAULT 0 CHECK (is_quarantined IN (0,1)),
     UNIQUE (txid, vout),
     FOREIGN KEY (wallet_id) REFERENCES wallets (id)
         ON UPDATE RESTRICT
@@ -122,6 +128,21 @@ CREATE TABLE labels (
     item TEXT UNIQUE NOT NULL,
     value TEXT NOT NULL
 );
+
+/* Fully signed transactions to be broadcast once they become valid.
+ *
+ * The 'broadcast_at' field is only present once the transaction was broadcast. The
+ * 'last_error' field is the error returned by the Bitcoin backend on the last attempt
+ * to broadcast it, if any.
+ */
+CREATE TABLE scheduled_broadcasts (
+    id INTEGER PRIMARY KEY NOT NULL,
+    txid BLOB UNIQUE NOT NULL,
+    tx BLOB NOT NULL,
+    created_at INTEGER NOT NULL,
+    broadcast_at INTEGER,
+    last_error TEXT
+);
 ";
 
 /// A row in the "tip" table.
@@ -164,6 +185,8 @@ pub struct DbWallet {
     pub change_derivation_index: bip32::ChildNumber,
     pub rescan_timestamp: Option<u32>,
     pub last_poll_timestamp: Option<u32>,
+    pub retired_timestamp: Option<u32>,
+    pub successor_descriptor: Option<LianaDescriptor>,
 }
 
 impl TryFrom<&rusqlite::Row<'_>> for DbWallet {
@@ -184,6 +207,11 @@ impl TryFrom<&rusqlite::Row<'_>> for DbWallet {
 
         let rescan_timestamp = row.get(5)?;
         let last_poll_timestamp = row.get(6)?;
+        let retired_timestamp = row.get(7)?;
+        let successor_descriptor = row.get::<_, Option<String>>(8)?.map(|desc_str| {
+            LianaDescriptor::from_str(&desc_str)
+                .expect("Insane database: can't parse successor descriptor")
+        });
 
         Ok(DbWallet {
             id,
@@ -193,6 +221,8 @@ impl TryFrom<&rusqlite::Row<'_>> for DbWallet {
             change_derivation_index,
             rescan_timestamp,
             last_poll_timestamp,
+            retired_timestamp,
+            successor_descriptor,
         })
     }
 }
@@ -222,6 +252,9 @@ pub struct DbCoin {
     /// be from self, as otherwise they will depend on an unconfirmed
     /// external transaction.
     pub is_from_self: bool,
+    /// Whether this coin was flagged as a likely dust attack upon reception, and wasn't released
+    /// since.
+    pub is_quarantined: bool,
 }
 
 impl TryFrom<&rusqlite::Row<'_>> for DbCoin {
@@ -262,6 +295,7 @@ impl TryFrom<&rusqlite::Row<'_>> for DbCoin {
 
         let is_immature: bool = row.get(12)?;
         let is_from_self: bool = row.get(13)?;
+        let is_quarantined: bool = row.get(14)?;
 
         Ok(DbCoin {
             id,
@@ -275,6 +309,7 @@ impl TryFrom<&rusqlite::Row<'_>> for DbCoin {
             spend_txid,
             spend_block,
             is_from_self,
+            is_quarantined,
         })
     }
 }
@@ -343,6 +378,45 @@ impl TryFrom<&rusqlite::Row<'_>> for DbSpendTransaction {
     }
 }
 
+/// A row in the "scheduled_broadcasts" table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DbScheduledBroadcast {
+    pub id: i64,
+    pub txid: bitcoin::Txid,
+    pub tx: bitcoin::Transaction,
+    pub created_at: u32,
+    pub broadcast_at: Option<u32>,
+    pub last_error: Option<String>,
+}
+
+impl TryFrom<&rusqlite::Row<'_>> for DbScheduledBroadcast {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &rusqlite::Row) -> Result<Self, Self::Error> {
+        let id: i64 = row.get(0)?;
+
+        let txid: Vec<u8> = row.get(1)?;
+        let txid: bitcoin::Txid = encode::deserialize(&txid).expect("We only store valid txids");
+        let tx: Vec<u8> = row.get(2)?;
+        let tx: bitcoin::Transaction =
+            encode::deserialize(&tx).expect("We only store valid transactions");
+        assert_eq!(txid, tx.compute_txid());
+
+        let created_at = row.get(3)?;
+        let broadcast_at = row.get(4)?;
+        let last_error = row.get(5)?;
+
+        Ok(DbScheduledBroadcast {
+            id,
+            txid,
+            tx,
+            created_at,
+            broadcast_at,
+            last_error,
+        })
+    }
+}
+
 /// A row in the "labels" table
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DbLabel {
@@ -481,6 +481,56 @@ fn migrate_v7_to_v8(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError
     Ok(())
 }
 
+fn migrate_v8_to_v9(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |db_tx| {
+        db_tx.execute_batch(
+            "
+            ALTER TABLE wallets ADD COLUMN retired_timestamp INTEGER;
+            ALTER TABLE wallets ADD COLUMN successor_descriptor TEXT;
+
+            UPDATE version SET version = 9;
+            ",
+        )?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+fn migrate_v9_to_v10(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |db_tx| {
+        db_tx.execute_batch(
+            "
+            CREATE TABLE scheduled_broadcasts (
+                id INTEGER PRIMARY KEY NOT NULL,
+                txid BLOB UNIQUE NOT NULL,
+                tx BLOB NOT NULL,
+                created_at INTEGER NOT NULL,
+                broadcast_at INTEGER,
+                last_error TEXT
+            );
+
+            UPDATE version SET version = 10;
+            ",
+        )?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+fn migrate_v10_to_v11(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |db_tx| {
+        db_tx.execute_batch(
+            "
+            ALTER TABLE coins ADD COLUMN is_quarantined BOOLEAN NOT NULL DEFAULT 0 CHECK (is_quarantined IN (0,1));
+
+            UPDATE version SET version = 11;
+            ",
+        )?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
 /// Check the database version and if necessary apply the migrations to upgrade it to the current
 /// one. The `bitcoin_txs` parameter is here for the migration from versions 4 and earlier, which
 /// did not store the Bitcoin transactions in database, to versions 5 and later, which do. For a
@@ -544,6 +594,21 @@ pub fn maybe_apply_migration(
                 migrate_v7_to_v8(&mut conn)?;
                 log::warn!("Migration from database version 7 to version 8 successful.");
             }
+            8 => {
+                log::warn!("Upgrading database from version 8 to version 9.");
+                migrate_v8_to_v9(&mut conn)?;
+                log::warn!("Migration from database version 8 to version 9 successful.");
+            }
+            9 => {
+                log::warn!("Upgrading database from version 9 to version 10.");
+                migrate_v9_to_v10(&mut conn)?;
+                log::warn!("Migration from database version 9 to version 10 successful.");
+            }
+            10 => {
+                log::warn!("Upgrading database from version 10 to version 11.");
+                migrate_v10_to_v11(&mut conn)?;
+                log::warn!("Migration from database version 10 to version 11 successful.");
+            }
             _ => return Err(SqliteDbError::UnsupportedVersion(version)),
         }
     }
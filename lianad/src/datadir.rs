@@ -39,6 +39,39 @@ impl DataDirectory {
         dir.push("lianad_watchonly_wallet");
         dir
     }
+    /// The directory containing the data directories of the additional wallets managed by this
+    /// daemon.
+    pub fn wallets_path(&self) -> PathBuf {
+        let mut dir = self.0.clone();
+        dir.push("wallets");
+        dir
+    }
+    /// The file listing the ids of the additional wallets to load at startup.
+    pub fn loaded_wallets_file_path(&self) -> PathBuf {
+        let mut dir = self.0.clone();
+        dir.push("loaded_wallets");
+        dir
+    }
+    /// The data directory of the additional wallet with the given id.
+    pub fn wallet_directory(&self, wallet_id: &str) -> DataDirectory {
+        let mut dir = self.wallets_path();
+        dir.push(wallet_id);
+        DataDirectory(dir)
+    }
+    /// The file in which the block headers are stored when using the compact block filters
+    /// backend.
+    pub fn cbf_headers_file_path(&self) -> PathBuf {
+        let mut dir = self.0.clone();
+        dir.push("cbf_headers");
+        dir
+    }
+    /// The file in which the filter headers are stored when using the compact block filters
+    /// backend.
+    pub fn cbf_filter_headers_file_path(&self) -> PathBuf {
+        let mut dir = self.0.clone();
+        dir.push("cbf_filter_headers");
+        dir
+    }
     pub fn lianad_rpc_socket_path(&self) -> PathBuf {
         let mut dir = self.0.clone();
         dir.push("lianad_rpc");
@@ -1,5 +1,5 @@
 use crate::{
-    commands::{CoinStatus, LabelItem},
+    commands::{CoinStatus, CreateSpendOptions, LabelItem},
     jsonrpc::rpc::{Error, Params, Request, Response},
     DaemonControl,
 };
@@ -10,23 +10,69 @@ use std::{
     str::FromStr,
 };
 
-use miniscript::bitcoin::{self, psbt::Psbt, Txid};
+use liana::{
+    bip21::PaymentUri, bip322::MessageSignature, descriptors,
+    silent_payments::SilentPaymentAddress, spend::SpendOptions,
+};
+use miniscript::bitcoin::{self, absolute, hex::FromHex, psbt::Psbt, Txid};
 
 fn create_spend(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
-    let destinations = params
+    // A destination is either an address or a BIP21 payment URI. A single destination may be set
+    // to receive the "max" amount instead of a value. The value of a payment URI may be left null
+    // to use the amount it specifies, but may not be set another amount. A destination may also be
+    // a silent payment address, which must be set a value.
+    let mut send_max_to = None;
+    let mut destinations = HashMap::new();
+    let mut silent_payments = HashMap::new();
+    let mut uri_labels = HashMap::new();
+    for (dest, value) in params
         .get(0, "destinations")
         .ok_or_else(|| Error::invalid_params("Missing 'destinations' parameter."))?
         .as_object()
-        .and_then(|obj| {
-            obj.into_iter()
-                .map(|(k, v)| {
-                    let addr = bitcoin::Address::from_str(k).ok()?;
-                    let amount: u64 = v.as_i64()?.try_into().ok()?;
-                    Some((addr, amount))
-                })
-                .collect::<Option<HashMap<bitcoin::Address<bitcoin::address::NetworkUnchecked>, u64>>>()
-        })
-        .ok_or_else(|| Error::invalid_params("Invalid 'destinations' parameter."))?;
+        .ok_or_else(|| Error::invalid_params("Invalid 'destinations' parameter."))?
+    {
+        if SilentPaymentAddress::is_silent_payment(dest) {
+            let addr = SilentPaymentAddress::from_str(dest).map_err(|e| {
+                Error::invalid_params(format!("Invalid 'destinations.{dest}' parameter: {e}"))
+            })?;
+            let amount = value
+                .as_u64()
+                .ok_or_else(|| Error::invalid_params("Invalid 'destinations' parameter."))?;
+            silent_payments.insert(addr, amount);
+            continue;
+        }
+        let (addr, uri_amount) = if PaymentUri::is_uri(dest) {
+            let uri = PaymentUri::from_str(dest).map_err(|e| {
+                Error::invalid_params(format!("Invalid 'destinations.{dest}' parameter: {e}"))
+            })?;
+            if let Some(desc) = uri.description() {
+                uri_labels.insert(uri.address.clone(), desc.to_string());
+            }
+            (uri.address, uri.amount)
+        } else {
+            let addr = bitcoin::Address::from_str(dest)
+                .map_err(|_| Error::invalid_params("Invalid 'destinations' parameter."))?;
+            (addr, None)
+        };
+        if let Some(uri_amount) = uri_amount {
+            if !value.is_null() && value.as_u64() != Some(uri_amount.to_sat()) {
+                return Err(Error::invalid_params(format!(
+                    "Invalid 'destinations.{dest}' parameter: value conflicts with the amount of \
+                     the payment URI."
+                )));
+            }
+        }
+        if value.as_str() == Some("max") && send_max_to.is_none() {
+            send_max_to = Some(addr);
+            continue;
+        }
+        let amount = value
+            .as_i64()
+            .and_then(|a| a.try_into().ok())
+            .or_else(|| uri_amount.filter(|_| value.is_null()).map(|a| a.to_sat()))
+            .ok_or_else(|| Error::invalid_params("Invalid 'destinations' parameter."))?;
+        destinations.insert(addr, amount);
+    }
     let outpoints = params
         .get(1, "outpoints")
         .ok_or_else(|| Error::invalid_params("Missing 'outpoints' parameter."))?
@@ -48,6 +94,7 @@ fn create_spend(control: &DaemonControl, params: Params) -> Result<serde_json::V
         .ok_or_else(|| Error::invalid_params("Invalid 'feerate' parameter."))?;
     let change_address: Option<bitcoin::Address<bitcoin::address::NetworkUnchecked>> = params
         .get(3, "change_address")
+        .filter(|addr| !addr.is_null())
         .map(|addr| {
             let addr_str = addr.as_str().ok_or_else(|| {
                 Error::invalid_params("Invalid 'change_address' parameter: must be a string.")
@@ -58,8 +105,98 @@ fn create_spend(control: &DaemonControl, params: Params) -> Result<serde_json::V
         })
         .transpose()?;
 
-    let res = control.create_spend(&destinations, &outpoints, feerate, change_address)?;
-    Ok(serde_json::json!(&res))
+    let subtract_fee_from = params
+        .get(4, "subtract_fee_from")
+        .map(|addrs| {
+            addrs
+                .as_array()
+                .and_then(|arr| {
+                    arr.iter()
+                        .map(|addr| {
+                            addr.as_str()
+                                .and_then(|a| bitcoin::Address::from_str(a).ok())
+                        })
+                        .collect::<Option<Vec<bitcoin::Address<bitcoin::address::NetworkUnchecked>>>>()
+                })
+                .ok_or_else(|| Error::invalid_params("Invalid 'subtract_fee_from' parameter."))
+        })
+        .transpose()?
+        .unwrap_or_default();
+    let op_return_data = params
+        .get(5, "op_return")
+        .filter(|data| !data.is_null())
+        .map(|data| {
+            data.as_str()
+                .and_then(|data| Vec::<u8>::from_hex(data).ok())
+                .ok_or_else(|| {
+                    Error::invalid_params("Invalid 'op_return' parameter: must be hex data.")
+                })
+        })
+        .transpose()?;
+    let locktime = params
+        .get(6, "locktime")
+        .filter(|lt| !lt.is_null())
+        .map(|lt| {
+            lt.as_u64()
+                .and_then(|lt| lt.try_into().ok())
+                .map(absolute::LockTime::from_consensus)
+                .ok_or_else(|| Error::invalid_params("Invalid 'locktime' parameter."))
+        })
+        .transpose()?;
+    let signal_rbf = params
+        .get(7, "rbf")
+        .map(|rbf| {
+            rbf.as_bool()
+                .ok_or_else(|| Error::invalid_params("Invalid 'rbf' parameter."))
+        })
+        .transpose()?
+        .unwrap_or(true);
+    let truc = params
+        .get(8, "truc")
+        .map(|truc| {
+            truc.as_bool()
+                .ok_or_else(|| Error::invalid_params("Invalid 'truc' parameter."))
+        })
+        .transpose()?
+        .unwrap_or(false);
+    let options = CreateSpendOptions {
+        locktime,
+        spend: SpendOptions {
+            op_return_data,
+            signal_rbf,
+            truc,
+        },
+        silent_payments,
+    };
+
+    let res = control.create_spend_with_options(
+        &destinations,
+        &outpoints,
+        feerate,
+        change_address,
+        send_max_to,
+        &subtract_fee_from,
+        &options,
+    )?;
+
+    // The description of the payment URIs, if any, is suggested as the label of their address. It
+    // is up to the caller to set it, for instance once the PSBT is stored.
+    let mut res = serde_json::json!(&res);
+    if let Some(obj) = res.as_object_mut().filter(|obj| obj.contains_key("psbt")) {
+        let suggested_labels: HashMap<String, String> = uri_labels
+            .into_iter()
+            .map(|(addr, label)| {
+                let addr = addr.assume_checked().to_string();
+                (addr, label.chars().take(100).collect())
+            })
+            .collect();
+        obj.insert(
+            "suggested_labels".to_string(),
+            serde_json::json!(suggested_labels),
+        );
+    }
+
+    Ok(res)
 }
 
 fn update_spend(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
@@ -74,6 +211,217 @@ fn update_spend(control: &DaemonControl, params: Params) -> Result<serde_json::V
     Ok(serde_json::json!({}))
 }
 
+fn analyze_psbt(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let psbt: Psbt = params
+        .get(0, "psbt")
+        .ok_or_else(|| Error::invalid_params("Missing 'psbt' parameter."))?
+        .as_str()
+        .and_then(|s| Psbt::from_str(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'psbt' parameter."))?;
+    let res = control.analyze_psbt(&psbt)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn finalize_psbt(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let psbt: Psbt = params
+        .get(0, "psbt")
+        .ok_or_else(|| Error::invalid_params("Missing 'psbt' parameter."))?
+        .as_str()
+        .and_then(|s| Psbt::from_str(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'psbt' parameter."))?;
+    let res = control.finalize_psbt(psbt)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn message_params(
+    params: &Params,
+) -> Result<(bitcoin::Address<bitcoin::address::NetworkUnchecked>, String), Error> {
+    let address = params
+        .get(0, "address")
+        .ok_or_else(|| Error::invalid_params("Missing 'address' parameter."))?
+        .as_str()
+        .and_then(|s| bitcoin::Address::from_str(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'address' parameter."))?;
+    let message = params
+        .get(1, "message")
+        .ok_or_else(|| Error::invalid_params("Missing 'message' parameter."))?
+        .as_str()
+        .ok_or_else(|| Error::invalid_params("Invalid 'message' parameter."))?
+        .to_string();
+    Ok((address, message))
+}
+
+fn sign_message(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let (address, message) = message_params(&params)?;
+    let psbt: Option<Psbt> = params
+        .get(2, "psbt")
+        .filter(|psbt| !psbt.is_null())
+        .map(|psbt| {
+            psbt.as_str()
+                .and_then(|s| Psbt::from_str(s).ok())
+                .ok_or_else(|| Error::invalid_params("Invalid 'psbt' parameter."))
+        })
+        .transpose()?;
+    let res = control.sign_message(address, &message, psbt.as_ref())?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn verify_message(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let (address, message) = message_params(&params)?;
+    let signature: MessageSignature = params
+        .get(2, "signature")
+        .ok_or_else(|| Error::invalid_params("Missing 'signature' parameter."))?
+        .as_str()
+        .ok_or_else(|| Error::invalid_params("Invalid 'signature' parameter."))?
+        .parse()
+        .map_err(|e| Error::invalid_params(format!("Invalid 'signature' parameter: {e}")))?;
+    let res = control.verify_message(address, &message, &signature)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn reserves_proof_message(params: &Params, index: usize) -> Result<String, Error> {
+    params
+        .get(index, "message")
+        .ok_or_else(|| Error::invalid_params("Missing 'message' parameter."))?
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| Error::invalid_params("Invalid 'message' parameter."))
+}
+
+fn create_reserves_proof(
+    control: &DaemonControl,
+    params: Params,
+) -> Result<serde_json::Value, Error> {
+    let message = reserves_proof_message(&params, 0)?;
+    let outpoints: Vec<bitcoin::OutPoint> = params
+        .get(1, "outpoints")
+        .filter(|outpoints| !outpoints.is_null())
+        .map(|outpoints| {
+            outpoints
+                .as_array()
+                .and_then(|arr| {
+                    arr.iter()
+                        .map(|entry| {
+                            entry
+                                .as_str()
+                                .and_then(|e| bitcoin::OutPoint::from_str(e).ok())
+                        })
+                        .collect::<Option<Vec<bitcoin::OutPoint>>>()
+                })
+                .ok_or_else(|| Error::invalid_params("Invalid 'outpoints' parameter."))
+        })
+        .transpose()?
+        .unwrap_or_default();
+    let res = control.create_reserves_proof(&message, &outpoints)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn finalize_reserves_proof(
+    control: &DaemonControl,
+    params: Params,
+) -> Result<serde_json::Value, Error> {
+    let psbt: Psbt = params
+        .get(0, "psbt")
+        .ok_or_else(|| Error::invalid_params("Missing 'psbt' parameter."))?
+        .as_str()
+        .and_then(|s| Psbt::from_str(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'psbt' parameter."))?;
+    let message = reserves_proof_message(&params, 1)?;
+    let res = control.finalize_reserves_proof(psbt, &message)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn verify_reserves_proof(
+    control: &DaemonControl,
+    params: Params,
+) -> Result<serde_json::Value, Error> {
+    let proof: bitcoin::Transaction = params
+        .get(0, "proof")
+        .ok_or_else(|| Error::invalid_params("Missing 'proof' parameter."))?
+        .as_str()
+        .and_then(|s| bitcoin::consensus::encode::deserialize_hex(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'proof' parameter."))?;
+    let message = reserves_proof_message(&params, 1)?;
+    let height: Option<i32> = params
+        .get(2, "height")
+        .filter(|height| !height.is_null())
+        .map(|height| {
+            height
+                .as_i64()
+                .and_then(|h| h.try_into().ok())
+                .ok_or_else(|| Error::invalid_params("Invalid 'height' parameter."))
+        })
+        .transpose()?;
+    let res = control.verify_reserves_proof(&proof, &message, height)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn broadcast_tx(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let tx: bitcoin::Transaction = params
+        .get(0, "tx")
+        .ok_or_else(|| Error::invalid_params("Missing 'tx' parameter."))?
+        .as_str()
+        .and_then(|s| bitcoin::consensus::encode::deserialize_hex(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'tx' parameter."))?;
+    let txid = control.broadcast_tx(&tx)?;
+
+    Ok(serde_json::json!({ "txid": txid }))
+}
+
+fn broadcast_package(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let txs: Vec<bitcoin::Transaction> = params
+        .get(0, "txs")
+        .ok_or_else(|| Error::invalid_params("Missing 'txs' parameter."))?
+        .as_array()
+        .and_then(|arr| {
+            arr.iter()
+                .map(|tx| {
+                    tx.as_str()
+                        .and_then(|s| bitcoin::consensus::encode::deserialize_hex(s).ok())
+                })
+                .collect::<Option<Vec<_>>>()
+        })
+        .filter(|txs| !txs.is_empty())
+        .ok_or_else(|| Error::invalid_params("Invalid 'txs' parameter."))?;
+    let txids = control.broadcast_package(&txs)?;
+
+    Ok(serde_json::json!({ "txids": txids }))
+}
+
+fn schedule_broadcast(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let tx: bitcoin::Transaction = params
+        .get(0, "tx")
+        .ok_or_else(|| Error::invalid_params("Missing 'tx' parameter."))?
+        .as_str()
+        .and_then(|s| bitcoin::consensus::encode::deserialize_hex(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'tx' parameter."))?;
+    control.schedule_broadcast(&tx)?;
+
+    Ok(serde_json::json!({}))
+}
+
+fn delete_scheduled_broadcast(
+    control: &DaemonControl,
+    params: Params,
+) -> Result<serde_json::Value, Error> {
+    let txid = params
+        .get(0, "txid")
+        .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?
+        .as_str()
+        .and_then(|s| bitcoin::Txid::from_str(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'txid' parameter."))?;
+    control.delete_scheduled_broadcast(&txid);
+
+    Ok(serde_json::json!({}))
+}
+
 fn delete_spend(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
     let txid = params
         .get(0, "txid")
@@ -123,6 +471,47 @@ fn rbf_psbt(control: &DaemonControl, params: Params) -> Result<serde_json::Value
     Ok(serde_json::json!(&res))
 }
 
+fn release_coins(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let outpoints: Vec<bitcoin::OutPoint> = params
+        .get(0, "outpoints")
+        .ok_or_else(|| Error::invalid_params("Missing 'outpoints' parameter."))?
+        .as_array()
+        .and_then(|arr| {
+            arr.iter()
+                .map(|entry| {
+                    entry
+                        .as_str()
+                        .and_then(|e| bitcoin::OutPoint::from_str(e).ok())
+                })
+                .collect::<Option<Vec<_>>>()
+        })
+        .ok_or_else(|| Error::invalid_params("Invalid 'outpoints' parameter."))?;
+    control.release_coins(&outpoints)?;
+
+    Ok(serde_json::json!({}))
+}
+
+// The default window for the recovery paths balances in `getbalance`, about a day.
+const DEFAULT_BALANCE_WINDOW: u32 = 144;
+
+fn get_balance(
+    control: &DaemonControl,
+    params: Option<Params>,
+) -> Result<serde_json::Value, Error> {
+    let window = params
+        .as_ref()
+        .and_then(|p| p.get(0, "window"))
+        .map(|window| {
+            window
+                .as_u64()
+                .and_then(|w| w.try_into().ok())
+                .ok_or_else(|| Error::invalid_params("Invalid 'window' parameter."))
+        })
+        .transpose()?
+        .unwrap_or(DEFAULT_BALANCE_WINDOW);
+    Ok(serde_json::json!(&control.get_balance(window)))
+}
+
 fn list_coins(control: &DaemonControl, params: Option<Params>) -> Result<serde_json::Value, Error> {
     let statuses_arg = params
         .as_ref()
@@ -409,6 +798,123 @@ fn create_recovery(control: &DaemonControl, params: Params) -> Result<serde_json
     Ok(serde_json::json!(&res))
 }
 
+fn simulate_spend_cost(
+    control: &DaemonControl,
+    params: Params,
+) -> Result<serde_json::Value, Error> {
+    let num_inputs: usize = params
+        .get(0, "num_inputs")
+        .ok_or_else(|| Error::invalid_params("Missing 'num_inputs' parameter."))?
+        .as_u64()
+        .filter(|n| *n > 0)
+        .and_then(|n| n.try_into().ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'num_inputs' parameter."))?;
+    let num_outputs: usize = params
+        .get(1, "num_outputs")
+        .ok_or_else(|| Error::invalid_params("Missing 'num_outputs' parameter."))?
+        .as_u64()
+        .filter(|n| *n > 0)
+        .and_then(|n| n.try_into().ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'num_outputs' parameter."))?;
+    let min_feerate: u64 = params
+        .get(2, "min_feerate")
+        .ok_or_else(|| Error::invalid_params("Missing 'min_feerate' parameter."))?
+        .as_u64()
+        .ok_or_else(|| Error::invalid_params("Invalid 'min_feerate' parameter."))?;
+    let max_feerate: u64 = params
+        .get(3, "max_feerate")
+        .ok_or_else(|| Error::invalid_params("Missing 'max_feerate' parameter."))?
+        .as_u64()
+        .ok_or_else(|| Error::invalid_params("Invalid 'max_feerate' parameter."))?;
+    let descriptor = params
+        .get(4, "descriptor")
+        .map(|desc| {
+            let desc_str = desc.as_str().ok_or_else(|| {
+                Error::invalid_params("Invalid 'descriptor' parameter: must be a string.")
+            })?;
+            descriptors::LianaDescriptor::from_str(desc_str)
+                .map_err(|e| Error::invalid_params(format!("Invalid 'descriptor' parameter: {e}.")))
+        })
+        .transpose()?;
+
+    let res = control.simulate_spend_cost(
+        descriptor.as_ref(),
+        num_inputs,
+        num_outputs,
+        min_feerate,
+        max_feerate,
+    )?;
+    Ok(serde_json::json!(&res))
+}
+
+fn migrate_wallet(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let desc_str = params
+        .get(0, "descriptor")
+        .ok_or_else(|| Error::invalid_params("Missing 'descriptor' parameter."))?
+        .as_str()
+        .ok_or_else(|| {
+            Error::invalid_params("Invalid 'descriptor' parameter: must be a string.")
+        })?;
+    let descriptor = descriptors::LianaDescriptor::from_str(desc_str)
+        .map_err(|e| Error::invalid_params(format!("Invalid 'descriptor' parameter: {e}.")))?;
+    let feerate: u64 = params
+        .get(1, "feerate")
+        .ok_or_else(|| Error::invalid_params("Missing 'feerate' parameter."))?
+        .as_u64()
+        .ok_or_else(|| Error::invalid_params("Invalid 'feerate' parameter."))?;
+    let max_inputs: Option<usize> = params
+        .get(2, "max_inputs")
+        .map(|n| {
+            n.as_u64()
+                .filter(|n| *n > 0)
+                .and_then(|n| n.try_into().ok())
+                .ok_or_else(|| Error::invalid_params("Invalid 'max_inputs' parameter."))
+        })
+        .transpose()?;
+
+    let res = control.migrate_wallet(&descriptor, feerate, max_inputs)?;
+    Ok(serde_json::json!(&res))
+}
+
+fn wallet_id_param(params: &Params) -> Result<String, Error> {
+    params
+        .get(0, "wallet_id")
+        .ok_or_else(|| Error::invalid_params("Missing 'wallet_id' parameter."))?
+        .as_str()
+        .map(|id| id.to_string())
+        .ok_or_else(|| Error::invalid_params("Invalid 'wallet_id' parameter."))
+}
+
+fn create_wallet(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let wallet_id = wallet_id_param(&params)?;
+    let desc_str = params
+        .get(1, "descriptor")
+        .ok_or_else(|| Error::invalid_params("Missing 'descriptor' parameter."))?
+        .as_str()
+        .ok_or_else(|| {
+            Error::invalid_params("Invalid 'descriptor' parameter: must be a string.")
+        })?;
+    let descriptor = descriptors::LianaDescriptor::from_str(desc_str)
+        .map_err(|e| Error::invalid_params(format!("Invalid 'descriptor' parameter: {e}.")))?;
+    control.create_wallet(wallet_id, descriptor)?;
+
+    Ok(serde_json::json!({}))
+}
+
+fn load_wallet(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let wallet_id = wallet_id_param(&params)?;
+    control.load_wallet(wallet_id)?;
+
+    Ok(serde_json::json!({}))
+}
+
+fn unload_wallet(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let wallet_id = wallet_id_param(&params)?;
+    control.unload_wallet(wallet_id)?;
+
+    Ok(serde_json::json!({}))
+}
+
 fn update_labels(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
     let mut items = HashMap::new();
     for (item, value) in params
@@ -484,19 +990,55 @@ fn get_labels_bip329(control: &DaemonControl, params: Params) -> Result<serde_js
 
 /// Handle an incoming JSONRPC2 request.
 pub fn handle_request(control: &mut DaemonControl, req: Request) -> Result<Response, Error> {
+    // Requests for an additional wallet are treated using the control of this wallet. The wallets
+    // themselves are always managed from the control of the main wallet.
+    let manages_wallets = matches!(
+        req.method.as_str(),
+        "createwallet" | "listwallets" | "loadwallet" | "unloadwallet"
+    );
+    let mut wallet_control = match req.wallet {
+        Some(ref wallet_id) if !manages_wallets => Some(control.wallet(wallet_id)?),
+        _ => None,
+    };
+    let control = wallet_control.as_mut().unwrap_or(control);
+
     let result = match req.method.as_str() {
+        "analyzepsbt" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'psbt' parameter."))?;
+            analyze_psbt(control, params)?
+        }
         "broadcastspend" => {
             let params = req
                 .params
                 .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?;
             broadcast_spend(control, params)?
         }
+        "broadcastpackage" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'txs' parameter."))?;
+            broadcast_package(control, params)?
+        }
+        "broadcasttx" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'tx' parameter."))?;
+            broadcast_tx(control, params)?
+        }
         "createrecovery" => {
             let params = req.params.ok_or_else(|| {
                 Error::invalid_params("Missing 'address' and 'feerate' parameters.")
             })?;
             create_recovery(control, params)?
         }
+        "createreservesproof" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'message' parameter."))?;
+            create_reserves_proof(control, params)?
+        }
         "createspend" => {
             let params = req.params.ok_or_else(|| {
                 Error::invalid_params(
@@ -505,19 +1047,51 @@ pub fn handle_request(control: &mut DaemonControl, req: Request) -> Result<Respo
             })?;
             create_spend(control, params)?
         }
+        "delscheduledbroadcast" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?;
+            delete_scheduled_broadcast(control, params)?
+        }
         "delspendtx" => {
             let params = req
                 .params
                 .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?;
             delete_spend(control, params)?
         }
+        "finalizepsbt" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'psbt' parameter."))?;
+            finalize_psbt(control, params)?
+        }
+        "finalizereservesproof" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'psbt' and 'message' parameters."))?;
+            finalize_reserves_proof(control, params)?
+        }
+        "privacyreport" => serde_json::json!(&control.privacy_report()),
         "rbfpsbt" => {
             let params = req.params.ok_or_else(|| {
                 Error::invalid_params("Missing 'txid', 'feerate' and 'is_cancel' parameters.")
             })?;
             rbf_psbt(control, params)?
         }
+        "releasecoins" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'outpoints' parameter."))?;
+            release_coins(control, params)?
+        }
+        "getbalance" => get_balance(control, req.params)?,
         "getinfo" => serde_json::json!(&control.get_info()),
+        "migratewallet" => {
+            let params = req.params.ok_or_else(|| {
+                Error::invalid_params("Missing 'descriptor' and 'feerate' parameters.")
+            })?;
+            migrate_wallet(control, params)?
+        }
         "getnewaddress" => serde_json::json!(&control.get_new_address()),
         "updatederivationindexes" => {
             let params = req.params.ok_or_else(|| {
@@ -549,6 +1123,7 @@ pub fn handle_request(control: &mut DaemonControl, req: Request) -> Result<Respo
             })?;
             list_confirmed(control, params)?
         }
+        "listscheduledbroadcasts" => serde_json::json!(&control.list_scheduled_broadcasts()),
         "listspendtxs" => list_spendtxs(control, req.params)?,
         "listtransactions" => {
             let params = req.params.ok_or_else(|| {
@@ -558,13 +1133,45 @@ pub fn handle_request(control: &mut DaemonControl, req: Request) -> Result<Respo
             })?;
             list_transactions(control, params)?
         }
+        "schedulebroadcast" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'tx' parameter."))?;
+            schedule_broadcast(control, params)?
+        }
+        "simulatespendcost" => {
+            let params = req.params.ok_or_else(|| {
+                Error::invalid_params(
+                    "Missing 'num_inputs', 'num_outputs', 'min_feerate' and 'max_feerate' parameters.",
+                )
+            })?;
+            simulate_spend_cost(control, params)?
+        }
         "startrescan" => {
             let params = req
                 .params
                 .ok_or_else(|| Error::invalid_params("Missing 'timestamp' parameter."))?;
             start_rescan(control, params)?
         }
+        "signmessage" => {
+            let params = req.params.ok_or_else(|| {
+                Error::invalid_params("Missing 'address' and 'message' parameters.")
+            })?;
+            sign_message(control, params)?
+        }
         "stop" => serde_json::json!({}),
+        "verifymessage" => {
+            let params = req.params.ok_or_else(|| {
+                Error::invalid_params("Missing 'address', 'message' and 'signature' parameters.")
+            })?;
+            verify_message(control, params)?
+        }
+        "verifyreservesproof" => {
+            let params = req.params.ok_or_else(|| {
+                Error::invalid_params("Missing 'proof' and 'message' parameters.")
+            })?;
+            verify_reserves_proof(control, params)?
+        }
         "updatespend" => {
             let params = req
                 .params
@@ -583,6 +1190,25 @@ pub fn handle_request(control: &mut DaemonControl, req: Request) -> Result<Respo
                 .ok_or_else(|| Error::invalid_params("Missing 'items' parameter."))?;
             get_labels(control, params)?
         }
+        "createwallet" => {
+            let params = req.params.ok_or_else(|| {
+                Error::invalid_params("Missing 'wallet_id' and 'descriptor' parameters.")
+            })?;
+            create_wallet(control, params)?
+        }
+        "loadwallet" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'wallet_id' parameter."))?;
+            load_wallet(control, params)?
+        }
+        "unloadwallet" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'wallet_id' parameter."))?;
+            unload_wallet(control, params)?
+        }
+        "listwallets" => serde_json::json!(&control.list_wallets()),
         "getlabelsbip329" => {
             let params = req
                 .params
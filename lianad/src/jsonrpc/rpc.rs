@@ -46,6 +46,9 @@ pub struct Request {
     pub params: Option<Params>,
     /// Request identifier.
     pub id: ReqId,
+    /// Id of the additional wallet this request is for. The main wallet if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wallet: Option<String>,
 }
 
 /// A failure to broadcast a transaction to the P2P network.
@@ -153,6 +156,8 @@ impl From<commands::CommandError> for Error {
             | commands::CommandError::InvalidFeerate(..)
             | commands::CommandError::AlreadySpent(..)
             | commands::CommandError::ImmatureCoinbase(..)
+            | commands::CommandError::QuarantinedCoin(..)
+            | commands::CommandError::QuarantinedCoins(..)
             | commands::CommandError::Address(..)
             | commands::CommandError::SpendCreation(..)
             | commands::CommandError::InsufficientFunds(..)
@@ -164,10 +169,30 @@ impl From<commands::CommandError> for Error {
             | commands::CommandError::RbfError(..)
             | commands::CommandError::EmptyFilterList
             | commands::CommandError::RecoveryNotAvailable
-            | commands::CommandError::OutpointNotRecoverable(..) => {
+            | commands::CommandError::OutpointNotRecoverable(..)
+            | commands::CommandError::InvalidWalletId(..)
+            | commands::CommandError::UnknownWallet(..)
+            | commands::CommandError::WalletAlreadyLoaded(..)
+            | commands::CommandError::WalletAlreadyExists(..)
+            | commands::CommandError::WalletNotFound(..)
+            | commands::CommandError::InvalidMigrationDescriptor(..)
+            | commands::CommandError::NoCoinToMigrate
+            | commands::CommandError::CoinsNotMigratable(..)
+            | commands::CommandError::AlreadyRetired
+            | commands::CommandError::InvalidPsbt(..)
+            | commands::CommandError::InvalidFeeSubtraction(..)
+            | commands::CommandError::UnknownAddress(..)
+            | commands::CommandError::ReservesProof(..)
+            | commands::CommandError::InvalidHeight(..)
+            | commands::CommandError::NotUnspentAtHeight(..)
+            | commands::CommandError::SilentPayment(..)
+            | commands::CommandError::UnsignedInput(..)
+            | commands::CommandError::MessageSignature(..) => {
                 Error::new(ErrorCode::InvalidParams, e.to_string())
             }
-            commands::CommandError::RescanTrigger(..) => {
+            commands::CommandError::RescanTrigger(..)
+            | commands::CommandError::WalletSetup(..)
+            | commands::CommandError::UtxoLookup(..) => {
                 Error::new(ErrorCode::InternalError, e.to_string())
             }
             commands::CommandError::TxBroadcast(_) => {
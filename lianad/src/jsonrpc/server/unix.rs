@@ -6,7 +6,7 @@
 use crate::{
     jsonrpc::{
         api,
-        rpc::{Request, Response},
+        rpc::{ErrorCode, Request, Response},
     },
     DaemonControl,
 };
@@ -108,8 +108,16 @@ fn connection_handler(
         }
 
         log::trace!("JSONRPC request: {:?}", serde_json::to_string(&req));
-        let response =
-            api::handle_request(&mut control, req).unwrap_or_else(|e| Response::error(req_id, e));
+        let method = req.method.clone();
+        let request_start = time::Instant::now();
+        let result = api::handle_request(&mut control, req);
+        // Don't let clients create arbitrary metrics by calling unknown methods.
+        if !matches!(&result, Err(e) if matches!(e.code, ErrorCode::MethodNotFound)) {
+            control
+                .metrics
+                .record_rpc_call(&method, request_start.elapsed(), result.is_err());
+        }
+        let response = result.unwrap_or_else(|e| Response::error(req_id, e));
         log::trace!("JSONRPC response: {:?}", serde_json::to_string(&response));
         if let Err(e) = serde_json::to_writer(&stream, &response) {
             log::error!("Error writing response: '{}'", e);
@@ -390,6 +398,7 @@ mod tests {
             method: "dummy".to_string(),
             params: Some(Params::Map(params)),
             id: ReqId::Num(0),
+            wallet: None,
         };
         write_messages(&socket_path, &[&serde_json::to_vec(&req).unwrap(), b"\n"]);
         let read_req = t.join().unwrap().unwrap();
@@ -421,6 +430,7 @@ mod tests {
             method: "stop".to_string(),
             params: None,
             id: ReqId::Num(0),
+            wallet: None,
         };
         write_messages(
             &socket_path,
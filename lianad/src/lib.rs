@@ -4,23 +4,27 @@ pub mod config;
 mod database;
 pub mod datadir;
 mod jsonrpc;
+pub mod metrics;
 #[cfg(test)]
 mod testutils;
 
 pub use bdk_electrum::electrum_client;
 pub use bip329;
-use bitcoin::electrum;
+use bitcoin::{cbf, d::zmq, electrum, esplora};
 use datadir::DataDirectory;
+use liana::descriptors;
 pub use miniscript;
 
 pub use crate::bitcoin::{
+    cbf::{Cbf, CbfError},
     d::{BitcoinD, BitcoindError, WalletError},
     electrum::{Electrum, ElectrumError},
+    esplora::{Esplora, EsploraError},
 };
 
 use crate::jsonrpc::server;
 use crate::{
-    bitcoin::{poller, BitcoinInterface},
+    bitcoin::{poller, BackendConnection, BitcoinInterface},
     config::Config,
     database::{
         sqlite::{FreshDbOptions, SqliteDb, SqliteDbError, MAX_DB_VERSION_NO_TX_DB},
@@ -29,7 +33,7 @@ use crate::{
 };
 
 use std::{
-    error, fmt, io, path,
+    collections, error, fmt, io, net, path,
     sync::{self, mpsc},
     thread,
 };
@@ -82,11 +86,18 @@ pub enum StartupError {
     DatadirCreation(path::PathBuf, io::Error),
     MissingBitcoindConfig,
     MissingElectrumConfig,
+    MissingEsploraConfig,
+    MissingCbfConfig,
     MissingBitcoinBackendConfig,
+    /// A proxy is configured but connections to this Bitcoin backend can't go through it.
+    ProxyNotSupported(&'static str),
     DbMigrateBitcoinTxs(&'static str),
     Database(SqliteDbError),
     Bitcoind(BitcoindError),
     Electrum(ElectrumError),
+    Esplora(EsploraError),
+    Cbf(CbfError),
+    Metrics(net::SocketAddr, io::Error),
     #[cfg(windows)]
     NoWatchonlyInDatadir,
 }
@@ -111,10 +122,24 @@ impl fmt::Display for StartupError {
                 f,
                 "Our Bitcoin interface is Electrum but we have no 'electrum_config' entry in the configuration."
             ),
+            Self::MissingEsploraConfig => write!(
+                f,
+                "Our Bitcoin interface is Esplora but we have no 'esplora_config' entry in the configuration."
+            ),
+            Self::MissingCbfConfig => write!(
+                f,
+                "Our Bitcoin interface is compact block filters but we have no 'cbf_config' entry in the configuration."
+            ),
             Self::MissingBitcoinBackendConfig => write!(
                 f,
                 "No Bitcoin backend entry in the configuration."
             ),
+            Self::ProxyNotSupported(backend) => write!(
+                f,
+                "A proxy is configured but the connection to {backend} can't go through a SOCKS5 proxy. \
+                 Refusing to connect to it directly. Use a backend running on this machine or remove \
+                 the 'proxy' entry from the configuration."
+            ),
             Self::DbMigrateBitcoinTxs(msg) => write!(
                 f,
                 "Error when migrating Bitcoin transaction from Bitcoin backend to database: {msg}.",
@@ -122,6 +147,12 @@ impl fmt::Display for StartupError {
             Self::Database(e) => write!(f, "Error initializing database: '{e}'."),
             Self::Bitcoind(e) => write!(f, "Error setting up bitcoind interface: '{e}'."),
             Self::Electrum(e) => write!(f, "Error setting up Electrum interface: '{e}'."),
+            Self::Esplora(e) => write!(f, "Error setting up Esplora interface: '{e}'."),
+            Self::Cbf(e) => write!(
+                f,
+                "Error setting up compact block filters interface: '{e}'."
+            ),
+            Self::Metrics(addr, e) => write!(f, "Could not listen for metrics on '{addr}': '{e}'."),
             #[cfg(windows)]
             Self::NoWatchonlyInDatadir => {
                 write!(
@@ -205,12 +236,13 @@ fn setup_sqlite(
     Ok(sqlite)
 }
 
-// Connect to bitcoind. Setup the watchonly wallet, and do some sanity checks.
-// If all went well, returns the interface to bitcoind.
+// Connect to bitcoind, unless a client for generalistic calls is given to share. Setup the
+// watchonly wallet, and do some sanity checks. If all went well, returns the interface to bitcoind.
 fn setup_bitcoind(
     config: &Config,
     data_dir: &DataDirectory,
     fresh_data_dir: bool,
+    node_client: Option<sync::Arc<jsonrpc::client::Client>>,
 ) -> Result<BitcoinD, StartupError> {
     let wo_path: path::PathBuf = data_dir.lianad_watchonly_wallet_path();
     let wo_path_str = wo_path.to_str().expect("Must be valid unicode").to_string();
@@ -230,7 +262,25 @@ fn setup_bitcoind(
         Some(config::BitcoinBackend::Bitcoind(bitcoind_config)) => bitcoind_config,
         _ => Err(StartupError::MissingBitcoindConfig)?,
     };
-    let bitcoind = BitcoinD::new(bitcoind_config, wo_path_str)?;
+    // Our RPC client doesn't support SOCKS5 proxies.
+    if let Some(proxy) = config.proxy.as_ref() {
+        if proxy.applies_to(&bitcoind_config.addr.ip().to_string()) {
+            return Err(StartupError::ProxyNotSupported("bitcoind's RPC"));
+        }
+        // Nor does our ZMQ subscriber.
+        if bitcoind_config
+            .zmqpubrawblock
+            .iter()
+            .chain(bitcoind_config.zmqpubrawtx.iter())
+            .any(|endpoint| proxy.applies_to_url(endpoint))
+        {
+            return Err(StartupError::ProxyNotSupported("bitcoind's ZMQ"));
+        }
+    }
+    let bitcoind = match node_client {
+        Some(node_client) => BitcoinD::with_node_client(bitcoind_config, node_client, wo_path_str)?,
+        None => BitcoinD::new(bitcoind_config, wo_path_str)?,
+    };
     bitcoind.node_sanity_checks(
         config.bitcoin_config.network,
         config.main_descriptor.is_taproot(),
@@ -253,20 +303,13 @@ fn setup_bitcoind(
     Ok(bitcoind)
 }
 
-// Create an Electrum interface from a client and BDK-based wallet, and do some sanity checks.
-// If all went well, returns the interface to Electrum.
-fn setup_electrum(
+// Create the BDK-based wallet used by the Electrum and Esplora interfaces and populate it with
+// the data from our database. Returns the wallet along with the genesis block hash of the network
+// and whether a full scan must be performed at the first sync.
+fn setup_bdk_wallet(
     config: &Config,
-    db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
-) -> Result<Electrum, StartupError> {
-    let electrum_config = match config.bitcoin_backend.as_ref() {
-        Some(config::BitcoinBackend::Electrum(electrum_config)) => electrum_config,
-        _ => Err(StartupError::MissingElectrumConfig)?,
-    };
-    // First create the client to communicate with the Electrum server.
-    let client = electrum::client::Client::new(electrum_config)
-        .map_err(|e| StartupError::Electrum(ElectrumError::Client(e)))?;
-    // Then create the BDK-based wallet and populate it with DB data.
+    db: &sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+) -> (electrum::wallet::BdkWallet, BlockHash, bool) {
     let mut db_conn = db.connection();
     let tip = db_conn.chain_tip();
     let coins: Vec<_> = db_conn
@@ -311,13 +354,200 @@ fn setup_electrum(
         change_index,
     );
     let full_scan = db_conn.rescan_timestamp().is_some();
-    let electrum = Electrum::new(client, bdk_wallet, full_scan).map_err(StartupError::Electrum)?;
+    (bdk_wallet, genesis_hash, full_scan)
+}
+
+// Create an Electrum interface from a client and BDK-based wallet, and do some sanity checks.
+// The client of another interface may be given to share its connection along with the index of
+// its server. If all went well, returns the interface to Electrum.
+fn setup_electrum(
+    config: &Config,
+    db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+    connection: Option<(usize, sync::Arc<electrum::client::Client>)>,
+) -> Result<Electrum, StartupError> {
+    let electrum_config = match config.bitcoin_backend.as_ref() {
+        Some(config::BitcoinBackend::Electrum(electrum_config)) => electrum_config,
+        _ => Err(StartupError::MissingElectrumConfig)?,
+    };
+    // First create the client to communicate with the first Electrum server we can reach.
+    let (active, client) = match connection {
+        Some(connection) => connection,
+        None => electrum::connect_first(electrum_config, config.proxy.as_ref())
+            .map(|(active, client)| (active, sync::Arc::new(client)))
+            .map_err(|e| StartupError::Electrum(ElectrumError::Client(e)))?,
+    };
+    // Then create the BDK-based wallet and populate it with DB data.
+    let (bdk_wallet, genesis_hash, full_scan) = setup_bdk_wallet(config, &db);
+    let electrum = Electrum::new(
+        client,
+        active,
+        electrum_config.clone(),
+        config.proxy.clone(),
+        bdk_wallet,
+        full_scan,
+    )
+    .map_err(StartupError::Electrum)?;
     electrum
         .sanity_checks(&genesis_hash)
         .map_err(StartupError::Electrum)?;
     Ok(electrum)
 }
 
+// Create an Esplora interface from a client and BDK-based wallet, and do some sanity checks.
+// The client of another interface may be given to share it. If all went well, returns the
+// interface to Esplora.
+fn setup_esplora(
+    config: &Config,
+    db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+    client: Option<sync::Arc<esplora::client::Client>>,
+) -> Result<Esplora, StartupError> {
+    let esplora_config = match config.bitcoin_backend.as_ref() {
+        Some(config::BitcoinBackend::Esplora(esplora_config)) => esplora_config,
+        _ => Err(StartupError::MissingEsploraConfig)?,
+    };
+    // First create the client to communicate with the Esplora server, through the proxy if
+    // there is one.
+    let client = match client {
+        Some(client) => client,
+        None => esplora::client::Client::new(esplora_config, config.proxy.as_ref())
+            .map(sync::Arc::new)
+            .map_err(|e| StartupError::Esplora(EsploraError::Client(e)))?,
+    };
+    // Then create the BDK-based wallet and populate it with DB data.
+    let (bdk_wallet, genesis_hash, full_scan) = setup_bdk_wallet(config, &db);
+    let esplora = Esplora::new(client, bdk_wallet, full_scan);
+    esplora
+        .sanity_checks(&genesis_hash)
+        .map_err(StartupError::Esplora)?;
+    Ok(esplora)
+}
+
+// Create a compact block filters interface from the headers stored on disk and a BDK-based
+// wallet, and make sure we can connect to our peer. The connection to the peer and the headers of
+// another interface may be given to share them. If all went well, returns the interface.
+fn setup_cbf(
+    config: &Config,
+    data_dir: &DataDirectory,
+    db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+    connection: Option<(cbf::SharedPeer, cbf::SharedHeaders)>,
+) -> Result<Cbf, StartupError> {
+    let cbf_config = match config.bitcoin_backend.as_ref() {
+        Some(config::BitcoinBackend::Cbf(cbf_config)) => cbf_config,
+        _ => Err(StartupError::MissingCbfConfig)?,
+    };
+    let (peer, headers) = match connection {
+        Some(connection) => connection,
+        None => {
+            let headers = cbf::chain::HeaderChain::load(
+                config.bitcoin_config.network,
+                data_dir.cbf_headers_file_path(),
+                data_dir.cbf_filter_headers_file_path(),
+            )
+            .map_err(|e| StartupError::Cbf(CbfError::Headers(e)))?;
+            (
+                sync::Arc::new(sync::Mutex::new(cbf::PeerState::default())),
+                sync::Arc::new(sync::Mutex::new(headers)),
+            )
+        }
+    };
+    let (bdk_wallet, _, _) = setup_bdk_wallet(config, &db);
+    let (birth_timestamp, rescan_timestamp) = {
+        let mut db_conn = db.connection();
+        (db_conn.timestamp(), db_conn.rescan_timestamp())
+    };
+    let proxy = config
+        .proxy
+        .as_ref()
+        .filter(|proxy| proxy.applies_to(&cbf_config.addr.ip().to_string()))
+        .map(|proxy| proxy.addr);
+    let cbf = Cbf::new(
+        cbf_config.addr,
+        proxy,
+        config.bitcoin_config.network,
+        peer,
+        headers,
+        bdk_wallet,
+        db,
+        birth_timestamp,
+        rescan_timestamp,
+    );
+    cbf.sanity_checks().map_err(StartupError::Cbf)?;
+    Ok(cbf)
+}
+
+// Set up the default database and Bitcoin backend interfaces for an additional wallet managed by
+// this daemon, sharing the connection to the backend of the main wallet. The wallet's data
+// directory must already exist.
+pub(crate) fn setup_wallet(
+    config: &Config,
+    connection: BackendConnection,
+    fresh_data_dir: bool,
+    secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+) -> Result<
+    (
+        sync::Arc<sync::Mutex<dyn BitcoinInterface>>,
+        sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+    ),
+    StartupError,
+> {
+    let data_dir = config
+        .data_directory()
+        .ok_or(StartupError::DefaultDataDirNotFound)?;
+    let bitcoind = if let BackendConnection::Bitcoind(node_client) = &connection {
+        Some(setup_bitcoind(
+            config,
+            &data_dir,
+            fresh_data_dir,
+            Some(node_client.clone()),
+        )?)
+    } else {
+        None
+    };
+    let db = sync::Arc::from(sync::Mutex::from(setup_sqlite(
+        config,
+        &data_dir,
+        fresh_data_dir,
+        secp,
+        &bitcoind,
+    )?)) as sync::Arc<sync::Mutex<dyn DatabaseInterface>>;
+    let bit = match (bitcoind, connection) {
+        (Some(bitcoind), _) => sync::Arc::from(sync::Mutex::from(bitcoind))
+            as sync::Arc<sync::Mutex<dyn BitcoinInterface>>,
+        (None, BackendConnection::Electrum(active, client)) => sync::Arc::from(sync::Mutex::from(
+            setup_electrum(config, db.clone(), Some((active, client)))?,
+        )),
+        (None, BackendConnection::Esplora(client)) => sync::Arc::from(sync::Mutex::from(
+            setup_esplora(config, db.clone(), Some(client))?,
+        )),
+        (None, BackendConnection::Cbf(peer, headers)) => sync::Arc::from(sync::Mutex::from(
+            setup_cbf(config, &data_dir, db.clone(), Some((peer, headers)))?,
+        )),
+        (None, BackendConnection::Bitcoind(_)) => unreachable!("bitcoind was set up above"),
+    };
+    Ok((bit, db))
+}
+
+// Get the descriptor of the wallet whose database is stored in this data directory.
+pub(crate) fn wallet_descriptor(
+    data_dir: &DataDirectory,
+    secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+) -> Result<descriptors::LianaDescriptor, StartupError> {
+    let sqlite = SqliteDb::new(data_dir.sqlite_db_file_path(), None, secp)?;
+    let mut conn = sqlite.connection()?;
+    Ok(conn.db_wallet().main_descriptor)
+}
+
+// The additional wallets loaded on this daemon, by id.
+type WalletsMap = sync::Mutex<collections::BTreeMap<String, DaemonControl>>;
+
+// The map of the additional wallets is owned by the control of the main wallet. The controls of
+// the additional wallets only keep a weak reference to it, since it holds them.
+#[derive(Clone)]
+enum SharedWallets {
+    Owned(sync::Arc<WalletsMap>),
+    Weak(sync::Weak<WalletsMap>),
+}
+
 #[derive(Clone)]
 pub struct DaemonControl {
     config: Config,
@@ -326,6 +556,9 @@ pub struct DaemonControl {
     // FIXME: Should we require Sync on DatabaseInterface rather than using a Mutex?
     db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
     secp: secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    // The additional wallets loaded on this daemon. Shared by the controls of all wallets.
+    wallets: SharedWallets,
+    metrics: sync::Arc<metrics::Metrics>,
 }
 
 impl DaemonControl {
@@ -335,6 +568,7 @@ impl DaemonControl {
         poller_sender: mpsc::SyncSender<poller::PollerMessage>,
         db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
         secp: secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+        metrics: sync::Arc<metrics::Metrics>,
     ) -> DaemonControl {
         DaemonControl {
             config,
@@ -342,9 +576,27 @@ impl DaemonControl {
             poller_sender,
             db,
             secp,
+            wallets: SharedWallets::Owned(sync::Arc::new(sync::Mutex::new(
+                collections::BTreeMap::new(),
+            ))),
+            metrics,
+        }
+    }
+
+    // The additional wallets loaded on this daemon. Empty if the main wallet's control, and
+    // therefore the daemon, is gone.
+    fn wallets(&self) -> sync::Arc<WalletsMap> {
+        match &self.wallets {
+            SharedWallets::Owned(wallets) => wallets.clone(),
+            SharedWallets::Weak(wallets) => wallets.upgrade().unwrap_or_default(),
         }
     }
 
+    // A reference to the additional wallets for the control of one of them.
+    fn weak_wallets(&self) -> SharedWallets {
+        SharedWallets::Weak(sync::Arc::downgrade(&self.wallets()))
+    }
+
     // Useful for unit test to directly mess up with the DB
     #[cfg(test)]
     pub fn db(&self) -> sync::Arc<sync::Mutex<dyn DatabaseInterface>> {
@@ -360,12 +612,14 @@ pub enum DaemonHandle {
         poller_sender: mpsc::SyncSender<poller::PollerMessage>,
         poller_handle: thread::JoinHandle<()>,
         control: DaemonControl,
+        metrics_server: Option<metrics::ServerHandle>,
     },
     Server {
         poller_sender: mpsc::SyncSender<poller::PollerMessage>,
         poller_handle: thread::JoinHandle<()>,
         rpcserver_shutdown: sync::Arc<sync::atomic::AtomicBool>,
         rpcserver_handle: thread::JoinHandle<Result<(), io::Error>>,
+        metrics_server: Option<metrics::ServerHandle>,
     },
 }
 
@@ -407,7 +661,7 @@ impl DaemonHandle {
         // migration when setting up SQLite below.
         let bitcoind = if bitcoin.is_none() {
             if let Some(config::BitcoinBackend::Bitcoind(_)) = &config.bitcoin_backend {
-                Some(setup_bitcoind(&config, &data_dir, fresh_data_dir)?)
+                Some(setup_bitcoind(&config, &data_dir, fresh_data_dir, None)?)
             } else {
                 None
             }
@@ -427,24 +681,84 @@ impl DaemonHandle {
             )?)) as sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
         };
 
-        // Finally set up the Bitcoin backend.
+        // Finally set up the Bitcoin backend. We only listen to notifications from the backends we
+        // set up ourselves.
+        let listen_to_backend = bitcoin.is_none();
         let bit = match (bitcoin, &config.bitcoin_backend) {
             (Some(bit), _) => sync::Arc::from(sync::Mutex::from(bit)),
             (None, Some(config::BitcoinBackend::Bitcoind(..))) => sync::Arc::from(
                 sync::Mutex::from(bitcoind.expect("bitcoind must have been set already")),
             )
                 as sync::Arc<sync::Mutex<dyn BitcoinInterface>>,
-            (None, Some(config::BitcoinBackend::Electrum(..))) => {
-                sync::Arc::from(sync::Mutex::from(setup_electrum(&config, db.clone())?))
+            (None, Some(config::BitcoinBackend::Electrum(..))) => sync::Arc::from(
+                sync::Mutex::from(setup_electrum(&config, db.clone(), None)?),
+            ),
+            (None, Some(config::BitcoinBackend::Esplora(..))) => {
+                sync::Arc::from(sync::Mutex::from(setup_esplora(&config, db.clone(), None)?))
             }
+            (None, Some(config::BitcoinBackend::Cbf(..))) => sync::Arc::from(sync::Mutex::from(
+                setup_cbf(&config, &data_dir, db.clone(), None)?,
+            )),
             (None, None) => Err(StartupError::MissingBitcoinBackendConfig)?,
         };
 
+        // Bind the metrics listener, if any, before starting any thread.
+        let metrics_listener = config
+            .metrics
+            .as_ref()
+            .map(|metrics_config| {
+                if !metrics_config.addr.ip().is_loopback() {
+                    log::warn!(
+                        "Metrics are served on '{}', they are reachable from other machines.",
+                        metrics_config.addr
+                    );
+                }
+                net::TcpListener::bind(metrics_config.addr)
+                    .map(|listener| (listener, metrics_config.clone()))
+                    .map_err(|e| StartupError::Metrics(metrics_config.addr, e))
+            })
+            .transpose()?;
+        let metrics = sync::Arc::new(metrics::Metrics::default());
+
         // Start the poller thread. Keep the thread handle to be able to check if it crashed. Store
         // an atomic to be able to stop it.
-        let mut bitcoin_poller =
-            poller::Poller::new(bit.clone(), db.clone(), config.main_descriptor.clone());
+        let mut bitcoin_poller = poller::Poller::new(
+            bit.clone(),
+            db.clone(),
+            config.main_descriptor.clone(),
+            metrics.clone(),
+            config.dust_protection.clone(),
+            config.consolidation.clone(),
+        );
         let (poller_sender, poller_receiver) = mpsc::sync_channel(1);
+        // Get notified by the Bitcoin backend, if it supports it, to poll as soon as something
+        // happens. The poller still polls at the configured interval.
+        if listen_to_backend {
+            match &config.bitcoin_backend {
+                Some(config::BitcoinBackend::Bitcoind(bitcoind_config)) => {
+                    for (endpoint, topics) in zmq::subscriptions(bitcoind_config) {
+                        let db = db.clone();
+                        bitcoin_poller.spawn_listener(
+                            "bitcoind ZMQ listener",
+                            poller_sender.clone(),
+                            move |notifier| zmq::listen(endpoint, topics, db, notifier),
+                        );
+                    }
+                }
+                Some(config::BitcoinBackend::Electrum(electrum_config)) => {
+                    let (electrum_config, proxy, db) =
+                        (electrum_config.clone(), config.proxy.clone(), db.clone());
+                    bitcoin_poller.spawn_listener(
+                        "Electrum notifications listener",
+                        poller_sender.clone(),
+                        move |notifier| {
+                            electrum::notifier::listen(electrum_config, proxy, db, notifier)
+                        },
+                    );
+                }
+                _ => {}
+            }
+        }
         let poller_handle = thread::Builder::new()
             .name("Bitcoin Network poller".to_string())
             .spawn({
@@ -459,7 +773,14 @@ impl DaemonHandle {
 
         // Create the API the external world will use to talk to us, either directly through the Rust
         // structure or through the JSONRPC server we may setup below.
-        let control = DaemonControl::new(config, bit, poller_sender.clone(), db, secp);
+        let control = DaemonControl::new(config, bit, poller_sender.clone(), db, secp, metrics);
+        // Load back the additional wallets which were loaded when we last stopped.
+        control.load_persisted_wallets();
+        let metrics_server = metrics_listener
+            .map(|(listener, metrics_config)| {
+                metrics::serve(listener, &metrics_config, control.clone())
+            })
+            .transpose()?;
 
         if with_rpc_server {
             let rpcserver_shutdown = sync::Arc::from(sync::atomic::AtomicBool::from(false));
@@ -479,6 +800,7 @@ impl DaemonHandle {
                 poller_handle,
                 rpcserver_shutdown,
                 rpcserver_handle,
+                metrics_server,
             });
         }
 
@@ -486,6 +808,7 @@ impl DaemonHandle {
             poller_sender,
             poller_handle,
             control,
+            metrics_server,
         })
     }
 
@@ -525,11 +848,15 @@ impl DaemonHandle {
             Self::Controller {
                 poller_sender,
                 poller_handle,
+                metrics_server,
                 ..
             } => {
                 poller_sender
                     .send(poller::PollerMessage::Shutdown)
                     .expect("The other end should never have hung up before this.");
+                if let Some(metrics_server) = metrics_server {
+                    metrics_server.stop()?;
+                }
                 poller_handle.join().expect("Poller thread must not panic");
                 Ok(())
             }
@@ -538,6 +865,7 @@ impl DaemonHandle {
                 poller_handle,
                 rpcserver_shutdown,
                 rpcserver_handle,
+                metrics_server,
             } => {
                 poller_sender
                     .send(poller::PollerMessage::Shutdown)
@@ -546,6 +874,9 @@ impl DaemonHandle {
                 rpcserver_handle
                     .join()
                     .expect("Poller thread must not panic")?;
+                if let Some(metrics_server) = metrics_server {
+                    metrics_server.stop()?;
+                }
                 poller_handle.join().expect("Poller thread must not panic");
                 Ok(())
             }
@@ -774,6 +1105,8 @@ mod tests {
         let bitcoind_config = BitcoindConfig {
             addr,
             rpc_auth: BitcoindRpcAuth::CookieFile(cookie),
+            zmqpubrawblock: None,
+            zmqpubrawtx: None,
         };
 
         // Create a dummy config with this bitcoind
@@ -0,0 +1,538 @@
+//! Metrics about the daemon.
+//!
+//! They are collected as the daemon runs and optionally exposed over HTTP, under "/metrics" in
+//! the Prometheus text format, for monitoring a production deployment. The metrics about the
+//! wallet only concern the main wallet.
+
+use crate::{
+    bitcoin::BitcoinInterface,
+    commands::draft_spends_inputs,
+    config,
+    database::{CoinStatus, DatabaseInterface},
+    DaemonControl,
+};
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write as _},
+    fs,
+    io::{self, Read, Write},
+    net, sync, thread, time,
+};
+
+// How long to wait for a client to send its request or to read our response.
+const CONNECTION_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+// We only ever expect a short GET request.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Summary {
+    count: u64,
+    sum: time::Duration,
+}
+
+impl Summary {
+    fn record(&mut self, duration: time::Duration) {
+        self.count = self.count.saturating_add(1);
+        self.sum = self.sum.saturating_add(duration);
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct RpcCallStats {
+    errors: u64,
+    durations: Summary,
+}
+
+#[derive(Debug, Default)]
+struct MetricsInner {
+    polls: Summary,
+    last_poll: Option<time::Duration>,
+    rpc_calls: BTreeMap<String, RpcCallStats>,
+}
+
+/// Measurements taken by the daemon as it runs. Shared between the poller, the JSONRPC server and
+/// the metrics listener.
+#[derive(Debug, Default)]
+pub struct Metrics(sync::Mutex<MetricsInner>);
+
+impl Metrics {
+    fn inner(&self) -> sync::MutexGuard<MetricsInner> {
+        self.0.lock().expect("Metrics lock must not be poisoned")
+    }
+
+    /// Record how long it took to poll the Bitcoin backend and update our state.
+    pub fn record_poll(&self, duration: time::Duration) {
+        let mut inner = self.inner();
+        inner.polls.record(duration);
+        inner.last_poll = Some(duration);
+    }
+
+    /// Record a call to this JSONRPC method, how long it took and whether it failed.
+    pub fn record_rpc_call(&self, method: &str, duration: time::Duration, is_error: bool) {
+        let mut inner = self.inner();
+        let stats = inner.rpc_calls.entry(method.to_string()).or_default();
+        stats.durations.record(duration);
+        if is_error {
+            stats.errors = stats.errors.saturating_add(1);
+        }
+    }
+}
+
+// Helper to write metrics in the Prometheus text format.
+#[derive(Default)]
+struct Exposition(String);
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.0, "# HELP {name} {help}");
+        let _ = writeln!(self.0, "# TYPE {name} {kind}");
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl fmt::Display) {
+        self.0.push_str(name);
+        if !labels.is_empty() {
+            self.0.push('{');
+            for (i, (label, label_value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.0.push(',');
+                }
+                let escaped = label_value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n");
+                let _ = write!(self.0, "{label}=\"{escaped}\"");
+            }
+            self.0.push('}');
+        }
+        let _ = writeln!(self.0, " {value}");
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, value: impl fmt::Display) {
+        self.family(name, "gauge", help);
+        self.sample(name, &[], value);
+    }
+}
+
+// Gather the current metrics and render them in the Prometheus text format.
+fn render(control: &DaemonControl, expiry_window_blocks: Option<u32>) -> String {
+    let mut out = Exposition::default();
+
+    // First what we can get from the database. Like in `getbalance`, the coins spent by a stored
+    // Spend transaction are accounted for as spending.
+    let (tip_height, coins, drafts_inputs, rescanning) = {
+        let mut db_conn = control.db.connection();
+        let tip_height = db_conn.chain_tip().map(|tip| tip.height).unwrap_or(0);
+        let coins = db_conn.coins(
+            &[
+                CoinStatus::Unconfirmed,
+                CoinStatus::Confirmed,
+                CoinStatus::Spending,
+            ],
+            &[],
+        );
+        let drafts_inputs = draft_spends_inputs(&mut db_conn);
+        (
+            tip_height,
+            coins,
+            drafts_inputs,
+            db_conn.rescan_timestamp().is_some(),
+        )
+    };
+    let timelock = control.config.main_descriptor.first_timelock_value() as u32;
+    let expiry_window = expiry_window_blocks.unwrap_or(timelock * 10 / 100);
+
+    let mut balances: BTreeMap<&str, (u64, u64)> =
+        ["unconfirmed", "confirmed", "immature", "spending"]
+            .iter()
+            .map(|status| (*status, (0, 0)))
+            .collect();
+    let (mut expiring, mut recoverable) = (0u64, 0u64);
+    for coin in coins.values() {
+        let status = if coin.spend_txid.is_some() || drafts_inputs.contains(&coin.outpoint) {
+            "spending"
+        } else if coin.is_immature {
+            "immature"
+        } else if coin.block_info.is_none() {
+            "unconfirmed"
+        } else {
+            "confirmed"
+        };
+        let (value, count) = balances.get_mut(status).expect("All statuses are present");
+        *value += coin.amount.to_sat();
+        *count += 1;
+
+        if let (Some(block), None) = (coin.block_info, coin.spend_txid) {
+            let available_at = block.height.saturating_add(timelock as i32);
+            let remaining = available_at.saturating_sub(tip_height).max(0) as u32;
+            if remaining <= expiry_window {
+                expiring += 1;
+            }
+            if remaining == 0 {
+                recoverable += 1;
+            }
+        }
+    }
+    out.family(
+        "liana_balance_sats",
+        "gauge",
+        "Value of our unspent coins in satoshis, by status. Coins spent by a stored Spend \
+         transaction are spending.",
+    );
+    for (status, (value, _)) in &balances {
+        out.sample("liana_balance_sats", &[("status", status)], value);
+    }
+    out.family(
+        "liana_coins",
+        "gauge",
+        "Number of our unspent coins, by status.",
+    );
+    for (status, (_, count)) in &balances {
+        out.sample("liana_coins", &[("status", status)], count);
+    }
+    out.gauge(
+        "liana_coins_expiring",
+        &format!(
+            "Number of confirmed coins whose first recovery path is available within {expiry_window} blocks."
+        ),
+        expiring,
+    );
+    out.gauge(
+        "liana_coins_recoverable",
+        "Number of confirmed coins whose first recovery path is available.",
+        recoverable,
+    );
+    out.gauge(
+        "liana_block_height",
+        "Height of the block our state is synced to.",
+        tip_height,
+    );
+
+    // Then what we can get from the Bitcoin backend.
+    let backend_tip = control.bitcoin.chain_tip();
+    let progress = control.bitcoin.sync_progress();
+    out.gauge(
+        "liana_backend_block_height",
+        "Height of the chain tip of the Bitcoin backend.",
+        backend_tip.height,
+    );
+    out.gauge(
+        "liana_backend_headers",
+        "Number of headers of the best chain known to the Bitcoin backend.",
+        progress.headers,
+    );
+    out.gauge(
+        "liana_backend_sync_progress",
+        "Progress of the Bitcoin backend's synchronization, between 0 and 1.",
+        progress.rounded_up_progress(),
+    );
+    let rescan_progress = control.bitcoin.rescan_progress();
+    out.gauge(
+        "liana_rescan_in_progress",
+        "Whether a rescan of the block chain is in progress.",
+        u8::from(rescanning || rescan_progress.is_some()),
+    );
+    out.gauge(
+        "liana_rescan_progress",
+        "Progress of the ongoing rescan, between 0 and 1. 1 if there is none.",
+        rescan_progress.unwrap_or(1.0),
+    );
+
+    // Then the measurements taken as the daemon runs.
+    let (polls, last_poll, rpc_calls) = {
+        let inner = control.metrics.inner();
+        (inner.polls, inner.last_poll, inner.rpc_calls.clone())
+    };
+    out.family(
+        "liana_poll_duration_seconds",
+        "summary",
+        "Time spent polling the Bitcoin backend and updating our state.",
+    );
+    out.sample(
+        "liana_poll_duration_seconds_sum",
+        &[],
+        polls.sum.as_secs_f64(),
+    );
+    out.sample("liana_poll_duration_seconds_count", &[], polls.count);
+    if let Some(last_poll) = last_poll {
+        out.gauge(
+            "liana_last_poll_duration_seconds",
+            "Time spent on the last poll.",
+            last_poll.as_secs_f64(),
+        );
+    }
+    out.family(
+        "liana_rpc_requests_total",
+        "counter",
+        "Number of JSONRPC requests, by method.",
+    );
+    for (method, stats) in &rpc_calls {
+        out.sample(
+            "liana_rpc_requests_total",
+            &[("method", method)],
+            stats.durations.count,
+        );
+    }
+    out.family(
+        "liana_rpc_errors_total",
+        "counter",
+        "Number of JSONRPC requests which returned an error, by method.",
+    );
+    for (method, stats) in &rpc_calls {
+        out.sample(
+            "liana_rpc_errors_total",
+            &[("method", method)],
+            stats.errors,
+        );
+    }
+    out.family(
+        "liana_rpc_request_duration_seconds",
+        "summary",
+        "Time spent treating JSONRPC requests, by method.",
+    );
+    for (method, stats) in &rpc_calls {
+        out.sample(
+            "liana_rpc_request_duration_seconds_sum",
+            &[("method", method)],
+            stats.durations.sum.as_secs_f64(),
+        );
+        out.sample(
+            "liana_rpc_request_duration_seconds_count",
+            &[("method", method)],
+            stats.durations.count,
+        );
+    }
+
+    // Finally the size of the database, if we are using SQLite.
+    if let Some(db_size) = control
+        .config
+        .data_directory()
+        .and_then(|datadir| fs::metadata(datadir.sqlite_db_file_path()).ok())
+        .map(|metadata| metadata.len())
+    {
+        out.gauge(
+            "liana_database_size_bytes",
+            "Size of the database file in bytes.",
+            db_size,
+        );
+    }
+
+    out.0
+}
+
+// Read the request and send the response.
+fn handle_connection(
+    mut stream: net::TcpStream,
+    control: &DaemonControl,
+    expiry_window_blocks: Option<u32>,
+) -> Result<(), io::Error> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+    stream.set_write_timeout(Some(CONNECTION_TIMEOUT))?;
+
+    // We only care about the request line, but read the whole head of the request.
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+        if request.len() > MAX_REQUEST_SIZE {
+            break;
+        }
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let (method, path) = (request_line.next(), request_line.next());
+    let path = path.map(|path| path.split('?').next().unwrap_or(path));
+
+    let (status, body) = match (method, path) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render(control, expiry_window_blocks)),
+        (Some("GET"), _) => ("404 Not Found", "Not found.\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "Method not allowed.\n".to_string(),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// The handle to a running metrics listener.
+pub struct ServerHandle {
+    shutdown: sync::Arc<sync::atomic::AtomicBool>,
+    handle: thread::JoinHandle<Result<(), io::Error>>,
+}
+
+impl ServerHandle {
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Stop the listener. Returns any error which may have occurred.
+    pub fn stop(self) -> Result<(), io::Error> {
+        self.shutdown.store(true, sync::atomic::Ordering::Relaxed);
+        self.handle
+            .join()
+            .expect("Metrics listener thread must not panic")
+    }
+}
+
+/// Start listening for requests of the metrics.
+pub fn serve(
+    listener: net::TcpListener,
+    metrics_config: &config::MetricsConfig,
+    control: DaemonControl,
+) -> Result<ServerHandle, io::Error> {
+    listener.set_nonblocking(true)?;
+    let expiry_window_blocks = metrics_config.expiry_window_blocks;
+    let shutdown = sync::Arc::new(sync::atomic::AtomicBool::new(false));
+    let handle = thread::Builder::new()
+        .name("Metrics listener".to_string())
+        .spawn({
+            let shutdown = shutdown.clone();
+            move || {
+                log::info!(
+                    "Serving metrics on 'http://{}/metrics'.",
+                    listener.local_addr()?
+                );
+                // Scrapes are infrequent, treat connections one at a time.
+                while !shutdown.load(sync::atomic::Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            if let Err(e) =
+                                handle_connection(stream, &control, expiry_window_blocks)
+                            {
+                                log::debug!("Error handling metrics request: '{}'", e);
+                            }
+                        }
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            thread::sleep(time::Duration::from_millis(100));
+                        }
+                        Err(e) => {
+                            log::error!("Error accepting metrics connection: '{}'", e);
+                            thread::sleep(time::Duration::from_millis(100));
+                        }
+                    }
+                }
+                log::info!("Metrics listener stopped.");
+                Ok(())
+            }
+        })?;
+    Ok(ServerHandle { shutdown, handle })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::{BlockInfo, Coin},
+        testutils::*,
+    };
+
+    use std::str::FromStr;
+
+    use miniscript::bitcoin::{
+        absolute, bip32, psbt::Psbt, transaction, Amount, OutPoint, Transaction, TxIn, Txid,
+    };
+
+    #[test]
+    fn exposition_format() {
+        let mut out = Exposition::default();
+        out.family("liana_test", "counter", "A test metric.");
+        out.sample("liana_test", &[("method", "getinfo")], 3);
+        out.sample("liana_test", &[("method", "we\"ird\\")], 1);
+        out.gauge("liana_other", "Another one.", 0.5);
+        assert_eq!(
+            out.0,
+            "# HELP liana_test A test metric.\n\
+             # TYPE liana_test counter\n\
+             liana_test{method=\"getinfo\"} 3\n\
+             liana_test{method=\"we\\\"ird\\\\\"} 1\n\
+             # HELP liana_other Another one.\n\
+             # TYPE liana_other gauge\n\
+             liana_other 0.5\n"
+        );
+    }
+
+    #[test]
+    fn render_balance() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = ms.control();
+        let mut db_conn = control.db.connection();
+        let coin = |vout: u32, amount: u64| Coin {
+            outpoint: OutPoint::new(
+                Txid::from_str("84f09bddfe0f036d0390edf655636ad6092c3ab8f09b2bb1503caa393463f241")
+                    .unwrap(),
+                vout,
+            ),
+            is_immature: false,
+            block_info: Some(BlockInfo {
+                height: 90,
+                time: 0,
+            }),
+            amount: Amount::from_sat(amount),
+            derivation_index: bip32::ChildNumber::from(vout),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            is_from_self: false,
+            is_quarantined: false,
+        };
+        db_conn.new_unspent_coins(&[coin(0, 10_000), coin(1, 20_000)]);
+
+        // A coin spent by a stored Spend transaction is spending, like in `getbalance`.
+        let draft_tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: coin(1, 20_000).outpoint,
+                ..TxIn::default()
+            }],
+            output: vec![],
+        };
+        db_conn.store_spend(&Psbt::from_unsigned_tx(draft_tx).unwrap());
+        let balance = control.get_balance(0);
+        let out = render(control, None);
+        assert!(out.contains(&format!(
+            "liana_balance_sats{{status=\"confirmed\"}} {}\n",
+            balance.confirmed.to_sat()
+        )));
+        assert!(out.contains(&format!(
+            "liana_balance_sats{{status=\"spending\"}} {}\n",
+            balance.spending.to_sat()
+        )));
+        assert_eq!(balance.spending, Amount::from_sat(20_000));
+        assert!(out.contains("liana_coins{status=\"spending\"} 1\n"));
+    }
+
+    #[test]
+    fn record_metrics() {
+        let metrics = Metrics::default();
+        metrics.record_poll(time::Duration::from_millis(200));
+        metrics.record_poll(time::Duration::from_millis(100));
+        metrics.record_rpc_call("getinfo", time::Duration::from_millis(10), false);
+        metrics.record_rpc_call("getinfo", time::Duration::from_millis(20), true);
+        let inner = metrics.inner();
+        assert_eq!(inner.polls.count, 2);
+        assert_eq!(inner.polls.sum, time::Duration::from_millis(300));
+        assert_eq!(inner.last_poll, Some(time::Duration::from_millis(100)));
+        let getinfo = inner.rpc_calls["getinfo"];
+        assert_eq!(getinfo.durations.count, 2);
+        assert_eq!(getinfo.durations.sum, time::Duration::from_millis(30));
+        assert_eq!(getinfo.errors, 1);
+    }
+}
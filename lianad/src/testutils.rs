@@ -2,7 +2,8 @@ use crate::{
     bitcoin::{BitcoinInterface, Block, BlockChainTip, MempoolEntry, SyncProgress, UTxO},
     config::{BitcoinConfig, Config},
     database::{
-        BlockInfo, Coin, CoinStatus, DatabaseConnection, DatabaseInterface, LabelItem, Wallet,
+        BlockInfo, Coin, CoinStatus, DatabaseConnection, DatabaseInterface, LabelItem,
+        ScheduledBroadcast, Wallet,
     },
     datadir::DataDirectory,
     DaemonControl, DaemonHandle,
@@ -25,6 +26,8 @@ use miniscript::{
 
 pub struct DummyBitcoind {
     pub txs: HashMap<Txid, (Transaction, Option<Block>)>,
+    /// The UTXO set, along with the height at which each output was confirmed.
+    pub utxos: HashMap<bitcoin::OutPoint, (bitcoin::TxOut, i32)>,
 }
 
 impl DummyBitcoind {}
@@ -33,6 +36,7 @@ impl DummyBitcoind {
     pub fn new() -> Self {
         Self {
             txs: HashMap::new(),
+            utxos: HashMap::new(),
         }
     }
 }
@@ -129,6 +133,10 @@ impl BitcoinInterface for DummyBitcoind {
         None
     }
 
+    fn tip_median_time_past(&self) -> Option<u32> {
+        None
+    }
+
     fn wallet_transaction(
         &self,
         txid: &bitcoin::Txid,
@@ -143,6 +151,10 @@ impl BitcoinInterface for DummyBitcoind {
     fn mempool_entry(&self, _: &bitcoin::Txid) -> Option<MempoolEntry> {
         None
     }
+
+    fn utxo(&self, outpoint: &bitcoin::OutPoint) -> Result<Option<(bitcoin::TxOut, i32)>, String> {
+        Ok(self.utxos.get(outpoint).cloned())
+    }
 }
 
 struct DummyDbState {
@@ -152,10 +164,13 @@ struct DummyDbState {
     coins: HashMap<bitcoin::OutPoint, Coin>,
     txs: HashMap<bitcoin::Txid, bitcoin::Transaction>,
     spend_txs: HashMap<bitcoin::Txid, (Psbt, Option<u32>)>,
+    scheduled_broadcasts: Vec<ScheduledBroadcast>,
     labels: HashMap<LabelItem, String>,
     timestamp: u32,
     rescan_timestamp: Option<u32>,
     last_poll_timestamp: Option<u32>,
+    retired_timestamp: Option<u32>,
+    successor_descriptor: Option<descriptors::LianaDescriptor>,
 }
 
 pub struct DummyDatabase {
@@ -187,10 +202,13 @@ impl DummyDatabase {
                 coins: HashMap::new(),
                 txs: HashMap::new(),
                 spend_txs: HashMap::new(),
+                scheduled_broadcasts: Vec::new(),
                 labels: HashMap::new(),
                 timestamp: now,
                 rescan_timestamp: None,
                 last_poll_timestamp: None,
+                retired_timestamp: None,
+                successor_descriptor: None,
             })),
         }
     }
@@ -219,6 +237,8 @@ impl DatabaseConnection for DummyDatabase {
             change_index: db_wallet.change_index,
             rescan_timestamp: db_wallet.rescan_timestamp,
             last_poll_timestamp: db_wallet.last_poll_timestamp,
+            retired_timestamp: db_wallet.retired_timestamp,
+            successor_descriptor: db_wallet.successor_descriptor.clone(),
         }
     }
 
@@ -315,6 +335,15 @@ impl DatabaseConnection for DummyDatabase {
         }
     }
 
+    fn release_coins(&mut self, outpoints: &[bitcoin::OutPoint]) {
+        let mut db = self.db.write().unwrap();
+        for op in outpoints {
+            if let Some(coin) = db.coins.get_mut(op) {
+                coin.is_quarantined = false;
+            }
+        }
+    }
+
     fn confirm_coins<'a>(&mut self, outpoints: &[(bitcoin::OutPoint, i32, u32)]) {
         for (op, height, time) in outpoints {
             let mut db = self.db.write().unwrap();
@@ -368,6 +397,10 @@ impl DatabaseConnection for DummyDatabase {
         None
     }
 
+    fn script_pubkeys(&mut self) -> Vec<(bitcoin::ScriptBuf, bip32::ChildNumber, bool)> {
+        Vec::new()
+    }
+
     fn coins_by_outpoints(
         &mut self,
         outpoints: &[bitcoin::OutPoint],
@@ -416,6 +449,47 @@ impl DatabaseConnection for DummyDatabase {
         self.db.write().unwrap().spend_txs.remove(txid);
     }
 
+    fn schedule_broadcast(&mut self, tx: &bitcoin::Transaction, created_at: u32) {
+        let txid = tx.compute_txid();
+        self.delete_scheduled_broadcast(&txid);
+        self.db
+            .write()
+            .unwrap()
+            .scheduled_broadcasts
+            .push(ScheduledBroadcast {
+                tx: tx.clone(),
+                created_at,
+                broadcast_at: None,
+                last_error: None,
+            });
+    }
+
+    fn scheduled_broadcasts(&mut self) -> Vec<ScheduledBroadcast> {
+        self.db.read().unwrap().scheduled_broadcasts.clone()
+    }
+
+    fn update_scheduled_broadcast(
+        &mut self,
+        txid: &bitcoin::Txid,
+        broadcast_at: Option<u32>,
+        last_error: Option<String>,
+    ) {
+        for sched in self.db.write().unwrap().scheduled_broadcasts.iter_mut() {
+            if sched.txid() == *txid {
+                sched.broadcast_at = broadcast_at;
+                sched.last_error = last_error.clone();
+            }
+        }
+    }
+
+    fn delete_scheduled_broadcast(&mut self, txid: &bitcoin::Txid) {
+        self.db
+            .write()
+            .unwrap()
+            .scheduled_broadcasts
+            .retain(|sched| sched.txid() != *txid);
+    }
+
     fn rollback_tip(&mut self, _: &BlockChainTip) {
         todo!()
     }
@@ -440,6 +514,12 @@ impl DatabaseConnection for DummyDatabase {
         self.db.write().unwrap().last_poll_timestamp = Some(timestamp);
     }
 
+    fn retire(&mut self, successor: &descriptors::LianaDescriptor, timestamp: u32) {
+        let mut db = self.db.write().unwrap();
+        db.retired_timestamp = Some(timestamp);
+        db.successor_descriptor = Some(successor.clone());
+    }
+
     fn update_labels(&mut self, items: &HashMap<LabelItem, Option<String>>) {
         for (lab_item, lab_val) in items {
             if let Some(val) = lab_val {
@@ -547,8 +627,35 @@ impl DatabaseConnection for DummyDatabase {
         wallet_txs
     }
 
-    fn get_labels_bip329(&mut self, _offset: u32, _limit: u32) -> bip329::Labels {
-        todo!()
+    fn get_labels_bip329(&mut self, offset: u32, limit: u32) -> bip329::Labels {
+        let db = self.db.read().unwrap();
+        let mut labels: Vec<_> = db.labels.iter().collect();
+        labels.sort_by_key(|(item, _)| item.to_string());
+        bip329::Labels::new(
+            labels
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .map(|(item, label)| match item {
+                    LabelItem::Address(addr) => bip329::Label::Address(bip329::AddressRecord {
+                        ref_: addr.as_unchecked().clone(),
+                        label: Some(label.clone()),
+                    }),
+                    LabelItem::Txid(txid) => {
+                        bip329::Label::Transaction(bip329::TransactionRecord {
+                            ref_: *txid,
+                            label: Some(label.clone()),
+                            origin: None,
+                        })
+                    }
+                    LabelItem::OutPoint(outpoint) => bip329::Label::Output(bip329::OutputRecord {
+                        ref_: *outpoint,
+                        label: Some(label.clone()),
+                        spendable: true,
+                    }),
+                })
+                .collect(),
+        )
     }
 }
 
@@ -583,6 +690,7 @@ impl DummyLiana {
         database: impl DatabaseInterface + 'static,
         rpc_server: bool,
         timelock: u16,
+        is_taproot: bool,
     ) -> DummyLiana {
         let tmp_dir = tmp_dir();
         fs::create_dir_all(&tmp_dir).unwrap();
@@ -606,7 +714,9 @@ impl DummyLiana {
             [(timelock, heir_key)].iter().cloned().collect(),
         )
         .unwrap();
-        let desc = descriptors::LianaDescriptor::new(policy);
+        let desc = descriptors::LianaDescriptor::new(policy)
+            .with_taproot(is_taproot)
+            .unwrap();
         let config = Config::new(
             bitcoin_config,
             None,
@@ -626,7 +736,7 @@ impl DummyLiana {
         bitcoin_interface: impl BitcoinInterface + 'static,
         database: impl DatabaseInterface + 'static,
     ) -> DummyLiana {
-        Self::_new(bitcoin_interface, database, false, 10_000)
+        Self::_new(bitcoin_interface, database, false, 10_000, false)
     }
 
     /// Creates a new DummyLiana interface with the specified recovery path timelock.
@@ -635,7 +745,7 @@ impl DummyLiana {
         database: impl DatabaseInterface + 'static,
         timelock: u16,
     ) -> DummyLiana {
-        Self::_new(bitcoin_interface, database, false, timelock)
+        Self::_new(bitcoin_interface, database, false, timelock, false)
     }
 
     /// Creates a new DummyLiana interface which also spins up an RPC server.
@@ -643,7 +753,15 @@ impl DummyLiana {
         bitcoin_interface: impl BitcoinInterface + 'static,
         database: impl DatabaseInterface + 'static,
     ) -> DummyLiana {
-        Self::_new(bitcoin_interface, database, true, 10_000)
+        Self::_new(bitcoin_interface, database, true, 10_000, false)
+    }
+
+    /// Creates a new DummyLiana interface for a Taproot wallet.
+    pub fn new_taproot(
+        bitcoin_interface: impl BitcoinInterface + 'static,
+        database: impl DatabaseInterface + 'static,
+    ) -> DummyLiana {
+        Self::_new(bitcoin_interface, database, false, 10_000, true)
     }
 
     pub fn control(&self) -> &DaemonControl {
@@ -3,6 +3,7 @@ from bip32.utils import _pubkey_to_fingerprint
 from bip380.descriptors import Descriptor
 from concurrent import futures
 from test_framework.bitcoind import Bitcoind
+from test_framework.cbf import Cbf
 from test_framework.electrs import Electrs
 from test_framework.lianad import Lianad
 from test_framework.signer import SingleSigner, MultiSigner
@@ -137,6 +138,14 @@ def bitcoin_backend(directory, bitcoind):
         electrs.startup()
         yield electrs
         electrs.cleanup()
+    elif BITCOIN_BACKEND_TYPE is BitcoinBackendType.Cbf:
+        cbf = Cbf(
+            bitcoind_p2pport=bitcoind.p2pport,
+            cbf_dir=os.path.join(directory, "cbf"),
+        )
+        cbf.startup()
+        yield cbf
+        cbf.cleanup()
     else:
         raise NotImplementedError
 
@@ -1,5 +1,7 @@
 import copy
 
+from ephemeral_port_reserve import reserve
+
 from fixtures import *
 from test_framework.utils import (
     wait_for,
@@ -560,3 +562,156 @@ def test_spend_replacement(lianad, bitcoind):
     assert (
         lianad.rpc.listcoins([], [first_outpoints[0]])["coins"][0]["spend_info"] is None
     )
+
+
+@pytest.mark.skipif(
+    BITCOIN_BACKEND_TYPE is not BitcoinBackendType.Cbf,
+    reason="Specific to the compact block filters backend.",
+)
+def test_cbf_backend(lianad, bitcoind):
+    """Test the compact block filters backend picks up our confirmed transactions, broadcasts
+    ours over P2P and follows reorgs."""
+    wait_for(
+        lambda: lianad.rpc.getinfo()["block_height"] == bitcoind.rpc.getblockcount()
+    )
+
+    # A deposit is picked up once it's mined.
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.5)
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    deposit_height = bitcoind.rpc.getblockcount()
+    wait_for(lambda: lianad.rpc.getinfo()["block_height"] == deposit_height)
+    coin = get_coin(lianad, txid)
+    assert coin["block_height"] == deposit_height
+
+    # Spend it. The transaction is relayed to our peer and picked up once mined.
+    res = lianad.rpc.createspend(
+        {bitcoind.rpc.getnewaddress(): 40_000_000}, [coin["outpoint"]], 2
+    )
+    spend_txid = sign_and_broadcast_psbt(lianad, PSBT.from_base64(res["psbt"]))
+    bitcoind.generate_block(1, wait_for_mempool=spend_txid)
+    spend_height = bitcoind.rpc.getblockcount()
+    wait_for(
+        lambda: (get_coin(lianad, txid)["spend_info"] or {}).get("height")
+        == spend_height
+    )
+    assert get_coin(lianad, txid)["spend_info"]["txid"] == spend_txid
+
+    # Re-mine the spend one block later. We detect the reorg and update the spend.
+    bitcoind.simple_reorg(spend_height, shift=1)
+    wait_for(
+        lambda: lianad.rpc.getinfo()["block_height"] == bitcoind.rpc.getblockcount()
+    )
+    wait_for(
+        lambda: (get_coin(lianad, txid)["spend_info"] or {}).get("height")
+        == spend_height + 1
+    )
+
+
+@pytest.mark.skipif(
+    BITCOIN_BACKEND_TYPE is not BitcoinBackendType.Electrs,
+    reason="Specific to the Electrum backend.",
+)
+def test_electrum_failover(lianad, bitcoind):
+    """Test we fail over to a fallback Electrum server when the main one can't be reached,
+    and report the health of the servers in getinfo."""
+    electrs_addr = f"127.0.0.1:{lianad.bitcoin_backend.rpcport}"
+    dead_addr = f"127.0.0.1:{reserve()}"
+
+    # Make the main server one which doesn't exist, and the actual one a fallback.
+    lianad.stop()
+    with open(lianad.conf_file, "r") as f:
+        conf = f.read()
+    conf = conf.replace(
+        f"addr = '{electrs_addr}'\n",
+        f"addr = '{dead_addr}'\nfallback_addrs = ['{electrs_addr}']\ncross_check = true\n",
+    )
+    with open(lianad.conf_file, "w") as f:
+        f.write(conf)
+    lianad.start()
+
+    # We keep syncing through the fallback server.
+    bitcoind.generate_block(1)
+    wait_for(
+        lambda: lianad.rpc.getinfo()["block_height"] == bitcoind.rpc.getblockcount()
+    )
+    servers = lianad.rpc.getinfo()["electrum_servers"]
+    assert [s["addr"] for s in servers] == [dead_addr, electrs_addr]
+    assert not servers[0]["active"] and servers[1]["active"]
+    assert servers[0]["last_success"] is None
+    assert servers[1]["failures"] == 0 and servers[1]["last_success"] is not None
+
+
+@pytest.mark.skipif(
+    BITCOIN_BACKEND_TYPE is not BitcoinBackendType.Bitcoind,
+    reason="Specific to the bitcoind backend.",
+)
+def test_bitcoind_zmq_notifications(lianad, bitcoind):
+    """Test we update our state upon bitcoind's ZMQ notifications without waiting for the next
+    poll."""
+    zmq_endpoint = f"tcp://127.0.0.1:{bitcoind.zmqport}"
+    lianad.stop()
+    with open(lianad.conf_file, "r") as f:
+        conf = f.read()
+    # Make sure we'd notice if we only updated our state upon polling.
+    conf = conf.replace(
+        f"poll_interval_secs = {lianad.poll_interval_secs}\n",
+        "poll_interval_secs = 3600\n",
+    )
+    conf += f"zmqpubrawblock = '{zmq_endpoint}'\nzmqpubrawtx = '{zmq_endpoint}'\n"
+    with open(lianad.conf_file, "w") as f:
+        f.write(conf)
+    lianad.start()
+    lianad.wait_for_log("Listening to bitcoind's ZMQ notifications")
+    # Make sure the first poll happened.
+    wait_for(
+        lambda: lianad.rpc.getinfo()["block_height"] == bitcoind.rpc.getblockcount()
+    )
+
+    # We pick up a new block right away.
+    bitcoind.generate_block(1)
+    lianad.wait_for_log("Got notified of a new block by bitcoind")
+    wait_for(
+        lambda: lianad.rpc.getinfo()["block_height"] == bitcoind.rpc.getblockcount()
+    )
+
+    # And a transaction paying to us as soon as it hits the mempool.
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.01)
+    lianad.wait_for_log(f"Got notified of transaction '{txid}' by bitcoind")
+    wait_for(lambda: get_coin(lianad, txid)["block_height"] is None)
+
+
+@pytest.mark.skipif(
+    BITCOIN_BACKEND_TYPE is not BitcoinBackendType.Electrs,
+    reason="Specific to the Electrum backend.",
+)
+def test_electrum_notifications(lianad, bitcoind):
+    """Test we update our state upon the Electrum server's notifications without waiting for
+    the next poll."""
+    lianad.stop()
+    with open(lianad.conf_file, "r") as f:
+        conf = f.read()
+    conf = conf.replace(
+        f"poll_interval_secs = {lianad.poll_interval_secs}\n",
+        "poll_interval_secs = 3600\n",
+    )
+    with open(lianad.conf_file, "w") as f:
+        f.write(conf)
+    lianad.start()
+    lianad.wait_for_log("Listening to Electrum server notifications")
+    wait_for(
+        lambda: lianad.rpc.getinfo()["block_height"] == bitcoind.rpc.getblockcount()
+    )
+
+    bitcoind.generate_block(1)
+    lianad.wait_for_log("Got notified by the Electrum server")
+    wait_for(
+        lambda: lianad.rpc.getinfo()["block_height"] == bitcoind.rpc.getblockcount()
+    )
+
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.01)
+    wait_for(
+        lambda: any(txid in c["outpoint"] for c in lianad.rpc.listcoins()["coins"])
+    )
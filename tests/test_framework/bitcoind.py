@@ -13,7 +13,9 @@ from test_framework.utils import (
     wait_for,
     TIMEOUT,
     BITCOIND_PATH,
+    BITCOIN_BACKEND_TYPE,
     COIN,
+    BitcoinBackendType,
 )
 
 
@@ -52,6 +54,7 @@ class Bitcoind(BitcoinBackend):
         self.bitcoin_dir = bitcoin_dir
         self.rpcport = rpcport
         self.p2pport = reserve()
+        self.zmqport = reserve()
         self.prefix = "bitcoind"
 
         regtestdir = os.path.join(bitcoin_dir, "regtest")
@@ -78,7 +81,13 @@ class Bitcoind(BitcoinBackend):
             # See https://github.com/bitcoin/bitcoin/blob/fa05ee0517d58b600f0ccad4c02c0734a23707d6/src/net.cpp#L1961.
             # h/t pythcoiner :)
             "peertimeout": 2 * 24 * 60 * 60,  # 2 days
+            "zmqpubrawblock": f"tcp://127.0.0.1:{self.zmqport}",
+            "zmqpubrawtx": f"tcp://127.0.0.1:{self.zmqport}",
         }
+        if BITCOIN_BACKEND_TYPE is BitcoinBackendType.Cbf:
+            # Serve compact block filters to lianad over P2P.
+            bitcoind_conf["blockfilterindex"] = 1
+            bitcoind_conf["peerblockfilters"] = 1
         self.conf_file = os.path.join(bitcoin_dir, "bitcoin.conf")
         with open(self.conf_file, "w") as f:
             f.write("chain=regtest\n")
@@ -0,0 +1,24 @@
+from test_framework.utils import BitcoinBackend, TailableProc
+
+
+class Cbf(BitcoinBackend):
+    """Use compact block filters served by bitcoind over P2P. There is no additional process to
+    run: bitcoind must be started with '-blockfilterindex' and '-peerblockfilters'."""
+
+    def __init__(self, bitcoind_p2pport, cbf_dir):
+        TailableProc.__init__(self, cbf_dir, verbose=False)
+        self.p2pport = bitcoind_p2pport
+
+    def startup(self):
+        pass
+
+    def stop(self):
+        pass
+
+    def cleanup(self):
+        pass
+
+    def append_to_lianad_conf(self, conf_file):
+        with open(conf_file, "a") as f:
+            f.write("[cbf_config]\n")
+            f.write(f"addr = '127.0.0.1:{self.p2pport}'\n")
@@ -2,7 +2,10 @@ import logging
 import pytest
 import shutil
 import time
+import urllib.error
+import urllib.request
 
+from ephemeral_port_reserve import reserve
 from fixtures import *
 from test_framework.authproxy import JSONRPCException
 from test_framework.serializations import PSBT
@@ -387,3 +390,47 @@ def test_retry_on_workqueue_exceeded(lianad, bitcoind, executor):
     # We should have retried the request to bitcoind, which should now succeed along with the call.
     # This just checks the response we get is sane, nothing particular with this field.
     assert "block_height" in f_liana.result(TIMEOUT)
+
+
+def test_metrics(lianad, bitcoind):
+    """Test the metrics are served when configured."""
+    metrics_port = reserve()
+    lianad.stop()
+    with open(lianad.conf_file, "a") as f:
+        f.write(f"[metrics]\naddr = '127.0.0.1:{metrics_port}'\n")
+    lianad.start()
+    lianad.wait_for_log("Serving metrics on")
+
+    def get_metrics():
+        url = f"http://127.0.0.1:{metrics_port}/metrics"
+        with urllib.request.urlopen(url, timeout=TIMEOUT) as res:
+            assert res.status == 200
+            lines = res.read().decode().splitlines()
+        return {
+            line.rsplit(" ", 1)[0]: float(line.rsplit(" ", 1)[1])
+            for line in lines
+            if not line.startswith("#")
+        }
+
+    # Receive a coin and check it's accounted for.
+    addr = lianad.rpc.getnewaddress()["address"]
+    bitcoind.rpc.sendtoaddress(addr, 0.5)
+    bitcoind.generate_block(1, wait_for_mempool=1)
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 1)
+    metrics = get_metrics()
+    assert metrics['liana_balance_sats{status="confirmed"}'] == 0.5 * COIN
+    assert metrics['liana_coins{status="confirmed"}'] == 1
+    assert metrics['liana_coins{status="unconfirmed"}'] == 0
+    assert metrics["liana_coins_recoverable"] == 0
+    assert metrics["liana_block_height"] == bitcoind.rpc.getblockcount()
+    assert metrics["liana_backend_block_height"] == bitcoind.rpc.getblockcount()
+    assert metrics["liana_poll_duration_seconds_count"] > 0
+    assert metrics['liana_rpc_requests_total{method="listcoins"}'] > 0
+    assert metrics["liana_database_size_bytes"] > 0
+
+    # Unknown paths and methods are not recorded.
+    with pytest.raises(urllib.error.HTTPError):
+        urllib.request.urlopen(f"http://127.0.0.1:{metrics_port}/", timeout=TIMEOUT)
+    with pytest.raises(RpcError):
+        lianad.rpc.call("notamethod", [])
+    assert not any("notamethod" in k for k in get_metrics())
@@ -17,11 +17,15 @@ from test_framework.utils import (
     spend_coins,
     sign_and_broadcast,
     sign_and_broadcast_psbt,
+    IS_NOT_BITCOIND_24,
     USE_TAPROOT,
 )
 
 MAX_DERIV = 2**31 - 1
 
+# The PSBT output field holding the keys of a silent payment address (BIP375).
+PSBT_OUT_SP_V0_INFO = 0x09
+
 
 def test_getinfo(lianad):
     res = lianad.rpc.getinfo()
@@ -712,6 +716,86 @@ def test_listcoins(lianad, bitcoind):
             lianad.rpc.listcoins(statuses, outpoints)
 
 
+def test_getbalance(lianad, bitcoind):
+    """Test the balance of the wallet by coin status and recovery path."""
+    res = lianad.rpc.getbalance()
+    assert res["confirmed"] == res["unconfirmed"] == res["spending"] == 0
+    assert res["window"] == 144
+    assert res["recovery_paths"] == [
+        {"timelock": 10, "spendable": 0, "spendable_within_window": 0}
+    ]
+
+    # An unconfirmed coin.
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 1)
+    wait_for(lambda: lianad.rpc.getbalance()["unconfirmed"] == 1 * COIN)
+
+    # Once confirmed it becomes recoverable after the timelock.
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: lianad.rpc.getbalance()["confirmed"] == 1 * COIN)
+    res = lianad.rpc.getbalance(5)
+    assert res["unconfirmed"] == 0
+    assert res["recovery_paths"][0]["spendable"] == 0
+    assert res["recovery_paths"][0]["spendable_within_window"] == 0
+    res = lianad.rpc.getbalance()
+    assert res["recovery_paths"][0]["spendable"] == 0
+    assert res["recovery_paths"][0]["spendable_within_window"] == 1 * COIN
+    bitcoind.generate_block(9)
+    wait_for(
+        lambda: lianad.rpc.getbalance()["recovery_paths"][0]["spendable"] == 1 * COIN
+    )
+
+    # Using the coin in a stored Spend transaction makes it "spending".
+    outpoint = lianad.rpc.listcoins()["coins"][0]["outpoint"]
+    destinations = {bitcoind.rpc.getnewaddress(): 100_000}
+    psbt = lianad.rpc.createspend(destinations, [outpoint], 2)["psbt"]
+    lianad.rpc.updatespend(psbt)
+    res = lianad.rpc.getbalance()
+    assert res["confirmed"] == 0
+    assert res["spending"] == 1 * COIN
+    assert res["recovery_paths"][0]["spendable"] == 1 * COIN
+
+    # Invalid window.
+    with pytest.raises(RpcError, match="Invalid 'window' parameter."):
+        lianad.rpc.getbalance(-1)
+
+
+def test_dust_quarantine(lianad, bitcoind):
+    """Test coins flagged as a likely dust attack are quarantined until released."""
+    lianad.stop()
+    with open(lianad.conf_file, "a") as f:
+        f.write("[dust_protection]\n")
+        f.write("max_amount_sat = 1000\n")
+    lianad.start()
+
+    addr = lianad.rpc.getnewaddress()["address"]
+    txids = [
+        bitcoind.rpc.sendtoaddress(addr, 0.01),
+        bitcoind.rpc.sendtoaddress(addr, 0.00000900),
+    ]
+    bitcoind.generate_block(1, wait_for_mempool=txids)
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 2)
+    coins = lianad.rpc.listcoins(["confirmed"])["coins"]
+    dust = next(c for c in coins if c["amount"] == 900)
+    regular = next(c for c in coins if c["amount"] != 900)
+    assert dust["is_quarantined"] and not regular["is_quarantined"]
+
+    # The quarantined coin can't be selected, neither automatically nor manually.
+    destinations = {bitcoind.rpc.getnewaddress(): 999_000}
+    res = lianad.rpc.createspend(destinations, [], 1)
+    assert "missing" in res
+    with pytest.raises(RpcError, match="is quarantined as a likely dust attack"):
+        lianad.rpc.createspend(destinations, [dust["outpoint"]], 1)
+
+    # Once released it can be spent as any other coin.
+    lianad.rpc.releasecoins([dust["outpoint"]])
+    assert not lianad.rpc.listcoins([], [dust["outpoint"]])["coins"][0][
+        "is_quarantined"
+    ]
+    res = lianad.rpc.createspend(destinations, [], 1)
+    assert "psbt" in res
+
+
 def test_jsonrpc_server(lianad, bitcoind):
     """Test passing parameters as a list or a mapping."""
     addr = lianad.rpc.getnewaddress()["address"]
@@ -795,6 +879,187 @@ def test_create_spend(lianad, bitcoind):
         lianad.rpc.createspend(destinations, [imma_coin["outpoint"]], 1)
 
 
+
+def test_create_spend_subtract_fee_and_max(lianad, bitcoind):
+    # Receive two coins.
+    for _ in range(2):
+        addr = lianad.rpc.getnewaddress()["address"]
+        txid = bitcoind.rpc.sendtoaddress(addr, 0.01)
+        bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 2)
+    outpoint = lianad.rpc.listcoins()["coins"][0]["outpoint"]
+
+    addr_a, addr_b = bitcoind.rpc.getnewaddress(), bitcoind.rpc.getnewaddress()
+    spk_a = bytes.fromhex(bitcoind.rpc.getaddressinfo(addr_a)["scriptPubKey"])
+    spk_b = bytes.fromhex(bitcoind.rpc.getaddressinfo(addr_b)["scriptPubKey"])
+
+    def output_value(psbt, spk):
+        return next(o.nValue for o in psbt.tx.vout if o.scriptPubKey == spk)
+
+    # The fee can only be subtracted from a destination.
+    destinations = {addr_a: 400_000, addr_b: 200_000}
+    with pytest.raises(RpcError, match=".*is not one of the destinations."):
+        lianad.rpc.createspend(
+            destinations, [outpoint], 2, None, [bitcoind.rpc.getnewaddress()]
+        )
+
+    # Subtract the fee from both destinations. The change gets exactly what's left from the coin.
+    res = lianad.rpc.createspend(destinations, [outpoint], 2, None, [addr_a, addr_b])
+    psbt = PSBT.from_base64(res["psbt"])
+    assert len(psbt.tx.vout) == 3
+    out_value = sum(o.nValue for o in psbt.tx.vout)
+    fee = 1_000_000 - out_value
+    assert output_value(psbt, spk_a) + output_value(psbt, spk_b) == 600_000 - fee
+    assert out_value - 600_000 + fee == 400_000
+    txid = sign_and_broadcast_psbt(lianad, psbt)
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 2)
+
+    # Now send the max to a destination. All the coins are spent, without change.
+    coins = lianad.rpc.listcoins(["confirmed"])["coins"]
+    total = sum(c["amount"] for c in coins)
+    res = lianad.rpc.createspend({addr_a: 100_000, addr_b: "max"}, [], 2)
+    psbt = PSBT.from_base64(res["psbt"])
+    assert len(psbt.tx.vin) == 2
+    assert len(psbt.tx.vout) == 2
+    fee = total - sum(o.nValue for o in psbt.tx.vout)
+    assert output_value(psbt, spk_a) == 100_000
+    assert output_value(psbt, spk_b) == total - 100_000 - fee
+    change_index = lianad.rpc.getinfo()["change_index"]
+    sign_and_broadcast_psbt(lianad, psbt)
+    assert lianad.rpc.getinfo()["change_index"] == change_index
+
+
+def test_create_spend_payment_uri(lianad, bitcoind):
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.01)
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 1)
+    outpoint = lianad.rpc.listcoins()["coins"][0]["outpoint"]
+
+    # The amount of the URI is used if no value is given, and its message is suggested as the
+    # label of the address without being stored.
+    dest_a, dest_b = bitcoind.rpc.getnewaddress(), bitcoind.rpc.getnewaddress()
+    uri = f"bitcoin:{dest_a}?amount=0.002&label=Alice&message=Invoice%20%2342"
+    res = lianad.rpc.createspend({uri: None, dest_b: 10_000}, [outpoint], 2)
+    psbt = PSBT.from_base64(res["psbt"])
+    assert sorted(o.nValue for o in psbt.tx.vout)[:2] == [10_000, 200_000]
+    assert res["suggested_labels"] == {dest_a: "Invoice #42"}
+    assert lianad.rpc.getlabels([dest_a])["labels"] == {}
+
+    # An explicit value must match the amount of the URI.
+    res = lianad.rpc.createspend({uri: 200_000}, [outpoint], 2)
+    psbt = PSBT.from_base64(res["psbt"])
+    assert 200_000 in [o.nValue for o in psbt.tx.vout]
+    with pytest.raises(RpcError, match=".*conflicts with the amount of the payment URI."):
+        lianad.rpc.createspend({uri: 300_000}, [outpoint], 2)
+    res = lianad.rpc.createspend({f"bitcoin:{dest_a}": 300_000}, [outpoint], 2)
+    assert res["suggested_labels"] == {}
+
+    # A value must be given if the URI has no amount.
+    with pytest.raises(RpcError, match="Invalid 'destinations' parameter."):
+        lianad.rpc.createspend({f"bitcoin:{dest_a}": None}, [outpoint], 2)
+    # Unknown required parameters are refused.
+    with pytest.raises(RpcError, match=".*requires unsupported parameter 'req-foo'."):
+        lianad.rpc.createspend({f"bitcoin:{dest_a}?req-foo=bar": 10_000}, [outpoint], 2)
+    with pytest.raises(RpcError, match=".*Invalid amount in payment URI.*"):
+        lianad.rpc.createspend({f"bitcoin:{dest_a}?amount=1e-3": None}, [outpoint], 2)
+
+
+def test_create_spend_silent_payment(lianad, bitcoind):
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.01)
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 1)
+    outpoint = lianad.rpc.listcoins()["coins"][0]["outpoint"]
+
+    sp_addr = "tsp1qqfumuen7l8wthtz45p3ftn58pvrs9xlumvkuu2xet8egzkcklqtesqkxq3legs0d04knq32qd62uqlxct3mcujuvau7202avpxu4cuy7u5mdxur5"
+    if USE_TAPROOT:
+        # The coins are spent through the Taproot key path. The output is created with a
+        # placeholder paying to the spend key, and the address recorded in the PSBT output
+        # (BIP375) for the signer to derive it.
+        res = lianad.rpc.createspend({sp_addr: 10_000}, [outpoint], 2)
+        psbt = PSBT.from_base64(res["psbt"])
+        assert len(psbt.tx.vout) == len(psbt.o) == 2
+        sp_outputs = [
+            (txo, psbt_out)
+            for txo, psbt_out in zip(psbt.tx.vout, psbt.o)
+            if PSBT_OUT_SP_V0_INFO in psbt_out.map
+        ]
+        assert len(sp_outputs) == 1
+        txo, psbt_out = sp_outputs[0]
+        assert txo.nValue == 10_000
+        scan_key = bytes.fromhex(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+        )
+        spend_key = bytes.fromhex(
+            "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5"
+        )
+        assert psbt_out.map[PSBT_OUT_SP_V0_INFO] == scan_key + spend_key
+        assert txo.scriptPubKey == bytes.fromhex("5120") + spend_key[1:]
+    else:
+        # P2WSH coins are spent through a script path, they can't be used to pay to them.
+        with pytest.raises(RpcError, match="Cannot pay to a silent payment address.*"):
+            lianad.rpc.createspend({sp_addr: 10_000}, [outpoint], 2)
+    # An invalid checksum or an address for another network are refused.
+    with pytest.raises(RpcError, match=".*Invalid silent payment address encoding.*"):
+        lianad.rpc.createspend({sp_addr[:-1] + "q": 10_000}, [outpoint], 2)
+    mainnet_addr = "sp1qqfumuen7l8wthtz45p3ftn58pvrs9xlumvkuu2xet8egzkcklqtesqkxq3legs0d04knq32qd62uqlxct3mcujuvau7202avpxu4cuy7u50zxff0"
+    with pytest.raises(RpcError, match="Silent payment address is for another network."):
+        lianad.rpc.createspend({mainnet_addr: 10_000}, [outpoint], 2)
+
+
+def test_create_spend_options(lianad, bitcoind):
+    """Test committing to data, setting the locktime and opting out of RBF in createspend."""
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.01)
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 1)
+    outpoint = lianad.rpc.listcoins()["coins"][0]["outpoint"]
+    destinations = {bitcoind.rpc.getnewaddress(): 200_000}
+    data_script = bytes([0x6A, 0x05]) + b"liana"
+
+    def data_outputs(psbt):
+        return [o for o in psbt.tx.vout if o.scriptPubKey[0] == 0x6A]
+
+    # The data must be hex encoded and fit in a standard OP_RETURN output.
+    with pytest.raises(RpcError, match="Invalid 'op_return' parameter"):
+        lianad.rpc.createspend(destinations, [outpoint], 2, None, [], "liana")
+    with pytest.raises(RpcError, match="OP_RETURN data is 81 bytes long"):
+        lianad.rpc.createspend(destinations, [outpoint], 2, None, [], "00" * 81)
+
+    # Commit to some data, set an explicit locktime and opt out of RBF.
+    locktime = bitcoind.rpc.getblockcount() - 10
+    res = lianad.rpc.createspend(
+        destinations, [outpoint], 2, None, [], b"liana".hex(), locktime, False
+    )
+    psbt = PSBT.from_base64(res["psbt"])
+    assert len(psbt.tx.vout) == len(psbt.o) == 3
+    outputs = data_outputs(psbt)
+    assert len(outputs) == 1
+    assert outputs[0].nValue == 0 and outputs[0].scriptPubKey == data_script
+    assert psbt.tx.nLockTime == locktime
+    assert all(txin.nSequence == 0xFFFFFFFE for txin in psbt.tx.vin)
+
+    # By default the spend signals for RBF, and a replacement keeps the data output.
+    res = lianad.rpc.createspend(destinations, [outpoint], 2, None, [], b"liana".hex())
+    psbt = PSBT.from_base64(res["psbt"])
+    assert all(txin.nSequence == 0xFFFFFFFD for txin in psbt.tx.vin)
+    txid = sign_and_broadcast_psbt(lianad, psbt)
+    wait_for(
+        lambda: lianad.rpc.listscheduledbroadcasts()["transactions"][0]["status"]
+        == "confirmed"
+    )
+    rbf_psbt = PSBT.from_base64(lianad.rpc.rbfpsbt(txid, False, 5)["psbt"])
+    outputs = data_outputs(rbf_psbt)
+    assert len(outputs) == 1 and outputs[0].scriptPubKey == data_script
+    # A cancel transaction drops it.
+    cancel_psbt = PSBT.from_base64(lianad.rpc.rbfpsbt(txid, True)["psbt"])
+    assert len(data_outputs(cancel_psbt)) == 0
+    rbf_txid = sign_and_broadcast_psbt(lianad, rbf_psbt)
+    bitcoind.generate_block(1, wait_for_mempool=rbf_txid)
+
+
 def test_list_spend(lianad, bitcoind):
     # Start by creating two conflicting Spend PSBTs. The first one will have a change
     # output but not the second one.
@@ -964,6 +1229,307 @@ def test_broadcast_spend(lianad, bitcoind):
     lianad.rpc.broadcastspend(txid)
 
 
+def test_analyzepsbt(lianad, bitcoind):
+    # Create a new coin and a spending tx for it.
+    addr = lianad.rpc.getnewaddress()["address"]
+    bitcoind.rpc.sendtoaddress(addr, 0.01)
+    wait_for(lambda: len(lianad.rpc.listcoins()["coins"]) > 0)
+    coin = lianad.rpc.listcoins()["coins"][0]
+    destinations = {
+        bitcoind.rpc.getnewaddress(): 200_000,
+    }
+    res = lianad.rpc.createspend(destinations, [coin["outpoint"]], 2)
+    psbt = PSBT.from_base64(res["psbt"])
+
+    # Garbage is refused.
+    with pytest.raises(RpcError, match="Invalid 'psbt' parameter."):
+        lianad.rpc.analyzepsbt("cHNidP8=")
+
+    # The unsigned PSBT is missing the signature of the primary key.
+    prim_fg = xpub_fingerprint(lianad.signer.primary_hd)
+    analysis = lianad.rpc.analyzepsbt(res["psbt"])
+    assert analysis["txid"] == psbt.tx.txid().hex()
+    assert analysis["inputs"] == [
+        {"outpoint": coin["outpoint"], "amount": coin["amount"], "is_ours": True}
+    ]
+    assert len(analysis["change_indexes"]) == 1
+    out_value = sum(o.nValue for o in psbt.tx.vout)
+    assert analysis["fee"] == coin["amount"] - out_value
+    assert analysis["feerate_vb"] >= 2
+    assert len(analysis["paths"]) == 1
+    assert analysis["paths"][0]["timelock"] is None
+    assert analysis["paths"][0]["sigs_count"] == 0
+    assert analysis["paths"][0]["missing_signers"] == [prim_fg]
+    assert not analysis["can_finalize"]
+
+    # Once signed, it can be finalized.
+    signed_psbt = lianad.signer.sign_psbt(psbt)
+    analysis = lianad.rpc.analyzepsbt(signed_psbt.to_base64())
+    assert analysis["paths"][0]["sigs_count"] == 1
+    assert analysis["paths"][0]["signed_pubkeys"] == {prim_fg: 1}
+    assert analysis["paths"][0]["missing_signers"] == []
+    assert analysis["locktime_ready"]
+    assert analysis["can_finalize"]
+
+    # Nothing was stored.
+    assert lianad.rpc.listspendtxs()["spend_txs"] == []
+
+
+def test_finalizepsbt_broadcasttx(lianad, bitcoind):
+    # Create a new coin and a spending tx for it, without storing it.
+    addr = lianad.rpc.getnewaddress()["address"]
+    bitcoind.rpc.sendtoaddress(addr, 0.01)
+    wait_for(lambda: len(lianad.rpc.listcoins()["coins"]) > 0)
+    outpoint = lianad.rpc.listcoins()["coins"][0]["outpoint"]
+    destinations = {
+        bitcoind.rpc.getnewaddress(): 200_000,
+    }
+    res = lianad.rpc.createspend(destinations, [outpoint], 2)
+    psbt = PSBT.from_base64(res["psbt"])
+
+    # It can't be finalized before being signed.
+    with pytest.raises(RpcError, match="Failed to finalize the spend transaction.*"):
+        lianad.rpc.finalizepsbt(res["psbt"])
+
+    # The backend refuses to relay the unsigned transaction.
+    unsigned_tx = psbt.tx.serialize_with_witness().hex()
+    with pytest.raises(RpcError, match="Failed to broadcast transaction.*"):
+        lianad.rpc.broadcasttx(unsigned_tx)
+    with pytest.raises(RpcError, match="Invalid 'tx' parameter."):
+        lianad.rpc.broadcasttx("00")
+
+    # Once signed, it can be finalized and broadcast.
+    signed_psbt = lianad.signer.sign_psbt(psbt)
+    final = lianad.rpc.finalizepsbt(signed_psbt.to_base64())
+    assert final["txid"] == psbt.tx.txid().hex()
+    assert lianad.rpc.broadcasttx(final["tx"])["txid"] == final["txid"]
+    bitcoind.generate_block(1, wait_for_mempool=final["txid"])
+    wait_for(
+        lambda: lianad.rpc.listcoins([], [outpoint])["coins"][0]["spend_info"]
+        is not None
+    )
+    assert lianad.rpc.listspendtxs()["spend_txs"] == []
+
+
+def test_schedule_broadcast(lianad, bitcoind):
+    """Test scheduling a signed transaction to be broadcast once its locktime is reached."""
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.01)
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 1)
+    outpoint = lianad.rpc.listcoins()["coins"][0]["outpoint"]
+
+    # Create a transaction which will only be valid in a few blocks.
+    locktime = bitcoind.rpc.getblockcount() + 5
+    destinations = {bitcoind.rpc.getnewaddress(): 200_000}
+    res = lianad.rpc.createspend(destinations, [outpoint], 2, None, [], None, locktime)
+    psbt = PSBT.from_base64(res["psbt"])
+
+    # It must be signed to be scheduled.
+    with pytest.raises(RpcError, match=".*is not signed."):
+        lianad.rpc.schedulebroadcast(psbt.tx.serialize_with_witness().hex())
+    signed_psbt = lianad.signer.sign_psbt(psbt)
+    final = lianad.rpc.finalizepsbt(signed_psbt.to_base64())
+    with pytest.raises(RpcError, match="Failed to broadcast transaction.*"):
+        lianad.rpc.broadcasttx(final["tx"])
+
+    # Once scheduled it is pending until the block after its locktime.
+    lianad.rpc.schedulebroadcast(final["tx"])
+    scheduled = lianad.rpc.listscheduledbroadcasts()["transactions"]
+    assert len(scheduled) == 1
+    assert scheduled[0]["txid"] == final["txid"]
+    assert scheduled[0]["status"] == "pending"
+    assert scheduled[0]["valid_at_height"] == locktime + 1
+    assert scheduled[0]["broadcast_at"] is None
+
+    # It is broadcast automatically once valid.
+    bitcoind.generate_block(4)
+    wait_for(
+        lambda: lianad.rpc.getinfo()["block_height"] == bitcoind.rpc.getblockcount()
+    )
+    assert (
+        lianad.rpc.listscheduledbroadcasts()["transactions"][0]["status"] == "pending"
+    )
+    bitcoind.generate_block(1)
+    wait_for(
+        lambda: lianad.rpc.listscheduledbroadcasts()["transactions"][0]["status"]
+        == "broadcast"
+    )
+    assert final["txid"] in bitcoind.rpc.getrawmempool()
+    assert (
+        lianad.rpc.listscheduledbroadcasts()["transactions"][0]["broadcast_at"]
+        is not None
+    )
+    bitcoind.generate_block(1, wait_for_mempool=final["txid"])
+    wait_for(
+        lambda: lianad.rpc.listcoins([], [outpoint])["coins"][0]["spend_info"]
+        is not None
+    )
+
+    # It can be removed from the schedule.
+    lianad.rpc.delscheduledbroadcast(final["txid"])
+    assert lianad.rpc.listscheduledbroadcasts()["transactions"] == []
+
+
+@pytest.mark.skipif(
+    BITCOIN_BACKEND_TYPE is not BitcoinBackendType.Bitcoind or not IS_NOT_BITCOIND_24,
+    reason="Need bitcoind's 'submitpackage' and TRUC transactions support",
+)
+def test_truc_package(lianad, bitcoind):
+    """Test creating a TRUC transaction and broadcasting it along with a child paying for it."""
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.01)
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 1)
+    outpoint = lianad.rpc.listcoins()["coins"][0]["outpoint"]
+
+    # Create a low-fee TRUC parent paying to an external address.
+    dest = bitcoind.rpc.getnewaddress()
+    res = lianad.rpc.createspend(
+        {dest: 500_000}, [outpoint], 1, None, [], None, None, True, True
+    )
+    psbt = PSBT.from_base64(res["psbt"])
+    assert psbt.tx.nVersion == 3
+    signed_psbt = lianad.signer.sign_psbt(psbt)
+    parent = lianad.rpc.finalizepsbt(signed_psbt.to_base64())
+
+    # Create a TRUC child spending the external output, paying a larger fee.
+    decoded = bitcoind.rpc.decoderawtransaction(parent["tx"])
+    vout = next(o for o in decoded["vout"] if o["scriptPubKey"].get("address") == dest)
+    child = bitcoind.rpc.createrawtransaction(
+        [{"txid": parent["txid"], "vout": vout["n"]}],
+        [{bitcoind.rpc.getnewaddress(): 0.004}],
+    )
+    child = "03" + child[2:]
+    prevtxs = [
+        {
+            "txid": parent["txid"],
+            "vout": vout["n"],
+            "scriptPubKey": vout["scriptPubKey"]["hex"],
+            "amount": vout["value"],
+        }
+    ]
+    child = bitcoind.rpc.signrawtransactionwithwallet(child, prevtxs)["hex"]
+    child_txid = bitcoind.rpc.decoderawtransaction(child)["txid"]
+
+    # The child can't be broadcast without its parent.
+    with pytest.raises(RpcError, match="Failed to broadcast transaction.*"):
+        lianad.rpc.broadcastpackage([child])
+    with pytest.raises(RpcError, match="Invalid 'txs' parameter."):
+        lianad.rpc.broadcastpackage([])
+
+    # Both are broadcast together as a package.
+    res = lianad.rpc.broadcastpackage([parent["tx"], child])
+    assert res["txids"] == [parent["txid"], child_txid]
+    mempool = bitcoind.rpc.getrawmempool()
+    assert parent["txid"] in mempool and child_txid in mempool
+    wait_for(
+        lambda: lianad.rpc.listcoins([], [outpoint])["coins"][0]["spend_info"]
+        is not None
+    )
+
+
+def test_sign_verify_message(lianad, bitcoind):
+    """Test signing a message for an address of the wallet, and verifying it (BIP322)."""
+    addr = lianad.rpc.getnewaddress()["address"]
+    message = "I own this address."
+
+    # Messages can only be signed for addresses of the wallet.
+    with pytest.raises(RpcError, match=".*is not from this wallet."):
+        lianad.rpc.signmessage(bitcoind.rpc.getnewaddress(), message)
+
+    # Get the PSBT of the virtual transaction to sign. It can't be used before being signed.
+    psbt_str = lianad.rpc.signmessage(addr, message)["psbt"]
+    psbt = PSBT.from_base64(psbt_str)
+    assert len(psbt.tx.vin) == len(psbt.tx.vout) == 1
+    assert psbt.tx.vout[0].nValue == 0
+    with pytest.raises(RpcError, match="Message signature error: Failed to finalize.*"):
+        lianad.rpc.signmessage(addr, message, psbt_str)
+
+    # Once signed, get the signature. It can't be obtained for another message.
+    signed_psbt = lianad.signer.sign_psbt(psbt).to_base64()
+    with pytest.raises(RpcError, match=".*does not sign this message for this address."):
+        lianad.rpc.signmessage(addr, "I don't own this address.", signed_psbt)
+    signature = lianad.rpc.signmessage(addr, message, signed_psbt)["signature"]
+
+    # The signature is only valid for this message and this address.
+    assert lianad.rpc.verifymessage(addr, message, signature)["valid"] is True
+    assert lianad.rpc.verifymessage(addr, message + " Really.", signature)["valid"] is False
+    other_addr = lianad.rpc.getnewaddress()["address"]
+    assert lianad.rpc.verifymessage(other_addr, message, signature)["valid"] is False
+    with pytest.raises(RpcError, match="Invalid 'signature' parameter.*"):
+        lianad.rpc.verifymessage(addr, message, "not base64!")
+
+    # Signing a message doesn't affect the wallet.
+    assert lianad.rpc.listspendtxs()["spend_txs"] == []
+    assert lianad.rpc.listcoins()["coins"] == []
+
+
+def test_reserves_proof(lianad, bitcoind):
+    """Test creating a proof of reserves for the coins of the wallet, and verifying it (BIP127)."""
+    message = "Proof of reserves for Q3 audit."
+    with pytest.raises(RpcError, match="No coin to prove ownership of."):
+        lianad.rpc.createreservesproof(message)
+
+    # Receive a couple of coins.
+    for amount in (0.01, 0.02):
+        addr = lianad.rpc.getnewaddress()["address"]
+        txid = bitcoind.rpc.sendtoaddress(addr, amount)
+        bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 2)
+    height = lianad.rpc.getinfo()["block_height"]
+
+    # By default the proof spends all our confirmed coins, after the commitment input. It can't
+    # be finalized before being signed.
+    res = lianad.rpc.createreservesproof(message)
+    psbt = PSBT.from_base64(res["psbt"])
+    assert len(psbt.tx.vin) == 3
+    assert len(psbt.tx.vout) == 1
+    assert psbt.tx.vout[0].nValue == 3_000_000
+    with pytest.raises(RpcError, match="Failed to finalize proof of reserves.*"):
+        lianad.rpc.finalizereservesproof(res["psbt"], message)
+
+    # Once signed, it can be finalized into a proof. It's only valid for this message.
+    signed_psbt = lianad.signer.sign_psbt(psbt).to_base64()
+    with pytest.raises(RpcError, match="Invalid proof of reserves.*"):
+        lianad.rpc.finalizereservesproof(signed_psbt, "Another message.")
+    proof = lianad.rpc.finalizereservesproof(signed_psbt, message)
+    assert proof["message"] == message
+    assert proof["amount"] == 3_000_000
+    res = lianad.rpc.verifyreservesproof(proof["proof"], message)
+    assert res == {"amount": 3_000_000, "height": height}
+    with pytest.raises(RpcError, match="Invalid proof of reserves.*"):
+        lianad.rpc.verifyreservesproof(proof["proof"], "Another message.")
+
+    # The proof is not a valid transaction.
+    with pytest.raises(RpcError, match="Failed to broadcast transaction.*"):
+        lianad.rpc.broadcasttx(proof["proof"])
+
+    # The coins must have been confirmed by the height of the verification, which can't be beyond
+    # the tip.
+    with pytest.raises(RpcError, match=".*was not confirmed at height.*"):
+        lianad.rpc.verifyreservesproof(proof["proof"], message, height - 1)
+    with pytest.raises(RpcError, match="Invalid block height.*"):
+        lianad.rpc.verifyreservesproof(proof["proof"], message, height + 10)
+
+    # The coins are looked up in the UTXO set: once one is spent, the proof is not valid anymore,
+    # whatever the height.
+    coin = lianad.rpc.listcoins(["confirmed"])["coins"][0]
+    spend_psbt = lianad.rpc.createspend(
+        {bitcoind.rpc.getnewaddress(): 500_000}, [coin["outpoint"]], 2
+    )["psbt"]
+    spend_txid = sign_and_broadcast_psbt(lianad, PSBT.from_base64(spend_psbt))
+    bitcoind.generate_block(1, wait_for_mempool=spend_txid)
+    wait_for(lambda: lianad.rpc.getinfo()["block_height"] == height + 1)
+    wait_for(
+        lambda: lianad.rpc.listcoins([], [coin["outpoint"]])["coins"][0]["spend_info"]
+        is not None
+    )
+    for h in (None, height):
+        with pytest.raises(RpcError, match=".*is not in the UTXO set.*"):
+            lianad.rpc.verifyreservesproof(proof["proof"], message, h)
+
+
 # Use a descriptor that includes hardened derivation paths so that we can check
 # there is no problem regarding the use of `h` and `'`.
 def test_start_rescan_does_not_error(lianad_with_deriv_paths, bitcoind):